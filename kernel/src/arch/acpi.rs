@@ -0,0 +1,562 @@
+// kernel/src/arch/acpi.rs
+//
+// 役割:
+// - BIOS 領域から RSDP を探し、RSDT / XSDT を辿って ACPI table を列挙する（checksum の合うものだけ）。
+// - MADT（"APIC"）と HPET（"HPET"）と FADT（"FACP"）を読んで、値だけの構造体（Platform）にまとめる。
+//   * MADT: LAPIC の物理アドレス / 使える CPU の APIC ID / IOAPIC / ISA IRQ の override（arch::smp、LAPIC timer 用）
+//   * HPET: register block の物理アドレスと capability（HPET の時刻源用）
+//   * FADT: PM1a / PM1b control block の I/O port と、DSDT の \_S5 の SLP_TYP（arch::power の ACPI poweroff 用）
+// - init()（arch::init、paging::init の後）で 1 回だけ読み、platform summary をログに出す。以後は platform() で写しを返す。
+//
+// 方針:
+// - table は physmap（physical_memory_offset）経由で読むだけ。書かない・map しない
+// - checksum（全 byte の和が 0）が合わない table は無いものとして扱う
+// - RSDP は EBDA の先頭 1KiB → 0xE0000..0xFFFFF の順に 16byte 境界で探す
+//   （bootloader 0.9 の BootInfo は RSDP を渡さないので走査だけ。UEFI 起動は対象外）
+// - revision >= 2 で XSDT があれば XSDT（64bit entry）、無ければ RSDT（32bit entry）
+// - 固定長の配列に収まらない entry は捨てて数だけ数える（*_ignored）
+//
+// やらないこと:
+// - AML の解釈（DSDT / SSDT）。\_S5 は DSDT の byte 列から "_S5_" の Package を探すだけ（method で返す形は読めない）
+// - FADT の他の power management（SCI / GPE / sleep の S1..S4、ACPI mode への切り替え）
+// - MADT の x2APIC / NMI source / LAPIC NMI entry の解釈
+// - HPET の設定（ここは table を読むだけ。register に触るのは時刻源の側）
+
+use spin::Mutex;
+
+use super::paging;
+
+const LOG: crate::logging::Subsystem = crate::logging::Subsystem::Arch;
+
+/// MADT で拾う CPU（Local APIC）の上限（arch::smp の MAX_CPUS と同じ）
+pub const MADT_MAX_CPUS: usize = 4;
+/// MADT で拾う IOAPIC の上限
+pub const MADT_MAX_IOAPICS: usize = 2;
+/// MADT で拾う interrupt source override の上限（ISA の 16 本で足りる）
+pub const MADT_MAX_OVERRIDES: usize = 16;
+/// summary に残す table の数の上限
+pub const ACPI_MAX_TABLES: usize = 32;
+
+const RSDP_SIGNATURE: &[u8; 8] = b"RSD PTR ";
+const EBDA_SEGMENT_PTR: u64 = 0x40E;
+const BIOS_AREA_START: u64 = 0xE0000;
+const BIOS_AREA_END: u64 = 0x100000;
+
+/// SDT header の長さ（signature .. creator_revision）
+const SDT_HEADER_LEN: u64 = 36;
+
+/// MADT の entry type
+const MADT_ENTRY_LOCAL_APIC: u8 = 0;
+const MADT_ENTRY_IOAPIC: u8 = 1;
+const MADT_ENTRY_INTERRUPT_OVERRIDE: u8 = 2;
+const MADT_ENTRY_LAPIC_ADDRESS_OVERRIDE: u8 = 5;
+/// Local APIC entry の flags: 使える（online capable だけの CPU は起こさない）
+const MADT_LAPIC_ENABLED: u32 = 1 << 0;
+/// MADT の flags: 8259 PIC も載っている
+const MADT_PCAT_COMPAT: u32 = 1 << 0;
+
+/// IOAPIC 1 つ
+#[derive(Clone, Copy)]
+pub struct IoApicInfo {
+    pub id: u8,
+    pub phys: u64,
+    /// この IOAPIC の最初の入力が受け持つ GSI
+    pub gsi_base: u32,
+}
+
+/// ISA IRQ → GSI の付け替え（MADT の interrupt source override）
+#[derive(Clone, Copy)]
+pub struct IrqOverride {
+    /// ISA の IRQ 番号
+    pub source: u8,
+    pub gsi: u32,
+    /// MPS INTI flags（polarity: bit0-1、trigger mode: bit2-3）
+    pub flags: u16,
+}
+
+/// MADT から読んだ割り込みまわりの構成
+#[derive(Clone, Copy)]
+pub struct MadtInfo {
+    /// Local APIC の物理アドレス（address override entry があればそちら）
+    pub lapic_phys: u64,
+    /// 8259 PIC も載っているか（PCAT_COMPAT）
+    pub legacy_pics: bool,
+    /// 使える CPU の APIC ID（MADT の順。cpu_count 個まで有効）
+    pub apic_ids: [u8; MADT_MAX_CPUS],
+    pub cpu_count: usize,
+    /// MADT_MAX_CPUS を超えて捨てた CPU の数
+    pub cpus_ignored: usize,
+    pub ioapics: [Option<IoApicInfo>; MADT_MAX_IOAPICS],
+    pub overrides: [Option<IrqOverride>; MADT_MAX_OVERRIDES],
+    /// 配列に入らなかった IOAPIC / override の数
+    pub entries_ignored: usize,
+}
+
+impl MadtInfo {
+    /// ISA IRQ の GSI（override が無ければ IRQ 番号のまま）
+    pub fn isa_irq_gsi(&self, irq: u8) -> u32 {
+        self.overrides
+            .iter()
+            .flatten()
+            .find(|o| o.source == irq)
+            .map_or(irq as u32, |o| o.gsi)
+    }
+}
+
+/// HPET table から読んだ register block の情報
+#[derive(Clone, Copy)]
+pub struct HpetInfo {
+    /// register block の物理アドレス（GAS が system memory のときだけ有効な table として扱う）
+    pub base_phys: u64,
+    pub hpet_number: u8,
+    /// comparator の数（event timer block id の bit8-12 + 1）
+    pub comparators: u8,
+    /// main counter が 64bit か
+    pub counter_64bit: bool,
+    /// legacy replacement（IRQ0 / IRQ8 の置き換え）ができるか
+    pub legacy_replacement: bool,
+    pub pci_vendor_id: u16,
+    /// periodic mode で壊れずに使える最小の tick（main counter の単位）
+    pub min_tick: u16,
+}
+
+/// FADT と DSDT の \_S5 から読んだ soft-off（S5）の手順
+#[derive(Clone, Copy)]
+pub struct S5Info {
+    /// PM1a / PM1b control block の I/O port（PM1b は無ければ 0）
+    pub pm1a_cnt: u16,
+    pub pm1b_cnt: u16,
+    /// \_S5 の SLP_TYPa / SLP_TYPb（3 bit）
+    pub slp_typ_a: u16,
+    pub slp_typ_b: u16,
+}
+
+/// 見つかった table 1 つ（summary 用）
+#[derive(Clone, Copy)]
+pub struct TableEntry {
+    pub signature: [u8; 4],
+    pub phys: u64,
+    pub length: u32,
+}
+
+/// ACPI から読んだ platform の記述（init で 1 回作る）
+#[derive(Clone, Copy)]
+pub struct Platform {
+    pub rsdp_phys: u64,
+    /// RSDP の revision（0 = ACPI 1.0 / RSDT、2 以上 = XSDT あり）
+    pub revision: u8,
+    pub oem_id: [u8; 6],
+    /// 辿った root table が XSDT か
+    pub uses_xsdt: bool,
+    pub tables: [Option<TableEntry>; ACPI_MAX_TABLES],
+    pub table_count: usize,
+    /// checksum が合わずに捨てた table の数
+    pub tables_bad_checksum: usize,
+    pub madt: Option<MadtInfo>,
+    pub hpet: Option<HpetInfo>,
+    pub s5: Option<S5Info>,
+}
+
+static PLATFORM: Mutex<Option<Platform>> = Mutex::new(None);
+
+#[inline(always)]
+fn phys_ptr(phys: u64) -> *const u8 {
+    (paging::physical_memory_offset() + phys) as *const u8
+}
+
+#[inline(always)]
+unsafe fn read_u8(phys: u64) -> u8 {
+    core::ptr::read_volatile(phys_ptr(phys))
+}
+
+#[inline(always)]
+unsafe fn read_u16(phys: u64) -> u16 {
+    core::ptr::read_unaligned(phys_ptr(phys) as *const u16)
+}
+
+#[inline(always)]
+unsafe fn read_u32(phys: u64) -> u32 {
+    core::ptr::read_unaligned(phys_ptr(phys) as *const u32)
+}
+
+#[inline(always)]
+unsafe fn read_u64(phys: u64) -> u64 {
+    core::ptr::read_unaligned(phys_ptr(phys) as *const u64)
+}
+
+/// [phys, phys + len) の byte の和が 0 か
+unsafe fn checksum_ok(phys: u64, len: usize) -> bool {
+    let mut sum: u8 = 0;
+    for i in 0..len as u64 {
+        sum = sum.wrapping_add(read_u8(phys + i));
+    }
+    sum == 0
+}
+
+unsafe fn signature_at(phys: u64, sig: &[u8]) -> bool {
+    sig.iter().enumerate().all(|(i, &b)| read_u8(phys + i as u64) == b)
+}
+
+/// [start, end) を 16byte 境界で走査して RSDP を探す（先頭 20byte の checksum まで確認）
+unsafe fn scan_rsdp(start: u64, end: u64) -> Option<u64> {
+    let mut p = start & !0xF;
+    while p + 20 <= end {
+        if signature_at(p, RSDP_SIGNATURE) && checksum_ok(p, 20) {
+            return Some(p);
+        }
+        p += 16;
+    }
+    None
+}
+
+/// RSDP の物理アドレス（paging::init の後に呼ぶこと）
+pub fn find_rsdp() -> Option<u64> {
+    if paging::physical_memory_offset() == 0 {
+        return None;
+    }
+    unsafe {
+        let ebda = (read_u16(EBDA_SEGMENT_PTR) as u64) << 4;
+        if ebda != 0 {
+            if let Some(p) = scan_rsdp(ebda, ebda + 1024) {
+                return Some(p);
+            }
+        }
+        scan_rsdp(BIOS_AREA_START, BIOS_AREA_END)
+    }
+}
+
+/// RSDP から root table（XSDT / RSDT）の物理アドレスと entry の幅を決める
+unsafe fn root_table(rsdp: u64) -> Option<(u64, u64)> {
+    let revision = read_u8(rsdp + 15);
+    let (root, entry_size) = if revision >= 2 && read_u64(rsdp + 24) != 0 && checksum_ok(rsdp, read_u32(rsdp + 20) as usize) {
+        (read_u64(rsdp + 24), 8u64)
+    } else {
+        (read_u32(rsdp + 16) as u64, 4u64)
+    };
+    if root == 0 || !checksum_ok(root, read_u32(root + 4) as usize) {
+        return None;
+    }
+    Some((root, entry_size))
+}
+
+/// root table の entry を順に f(table の物理アドレス) に渡す（checksum はまだ見ない）
+unsafe fn for_each_table(root: u64, entry_size: u64, mut f: impl FnMut(u64)) {
+    let entries = (read_u32(root + 4) as u64).saturating_sub(SDT_HEADER_LEN) / entry_size;
+    for i in 0..entries {
+        let at = root + SDT_HEADER_LEN + i * entry_size;
+        let table = if entry_size == 8 { read_u64(at) } else { read_u32(at) as u64 };
+        if table != 0 {
+            f(table);
+        }
+    }
+}
+
+/// MADT を読む
+unsafe fn parse_madt(madt: u64) -> MadtInfo {
+    let mut info = MadtInfo {
+        lapic_phys: read_u32(madt + SDT_HEADER_LEN) as u64,
+        legacy_pics: read_u32(madt + SDT_HEADER_LEN + 4) & MADT_PCAT_COMPAT != 0,
+        apic_ids: [0; MADT_MAX_CPUS],
+        cpu_count: 0,
+        cpus_ignored: 0,
+        ioapics: [None; MADT_MAX_IOAPICS],
+        overrides: [None; MADT_MAX_OVERRIDES],
+        entries_ignored: 0,
+    };
+    let mut ioapic_count = 0;
+    let mut override_count = 0;
+
+    // header + LAPIC address(4) + flags(4) の後ろが可変長 entry（type, length, ...）
+    let end = madt + read_u32(madt + 4) as u64;
+    let mut p = madt + SDT_HEADER_LEN + 8;
+    while p + 2 <= end {
+        let ty = read_u8(p);
+        let entry_len = read_u8(p + 1) as u64;
+        if entry_len < 2 || p + entry_len > end {
+            break;
+        }
+        match ty {
+            MADT_ENTRY_LOCAL_APIC if entry_len >= 8 => {
+                let apic_id = read_u8(p + 3);
+                if read_u32(p + 4) & MADT_LAPIC_ENABLED != 0 {
+                    if info.cpu_count < MADT_MAX_CPUS {
+                        info.apic_ids[info.cpu_count] = apic_id;
+                        info.cpu_count += 1;
+                    } else {
+                        info.cpus_ignored += 1;
+                    }
+                }
+            }
+            MADT_ENTRY_IOAPIC if entry_len >= 12 => {
+                if ioapic_count < MADT_MAX_IOAPICS {
+                    info.ioapics[ioapic_count] = Some(IoApicInfo {
+                        id: read_u8(p + 2),
+                        phys: read_u32(p + 4) as u64,
+                        gsi_base: read_u32(p + 8),
+                    });
+                    ioapic_count += 1;
+                } else {
+                    info.entries_ignored += 1;
+                }
+            }
+            MADT_ENTRY_INTERRUPT_OVERRIDE if entry_len >= 10 => {
+                if override_count < MADT_MAX_OVERRIDES {
+                    info.overrides[override_count] = Some(IrqOverride {
+                        source: read_u8(p + 3),
+                        gsi: read_u32(p + 4),
+                        flags: read_u16(p + 8),
+                    });
+                    override_count += 1;
+                } else {
+                    info.entries_ignored += 1;
+                }
+            }
+            MADT_ENTRY_LAPIC_ADDRESS_OVERRIDE if entry_len >= 12 => {
+                info.lapic_phys = read_u64(p + 4);
+            }
+            _ => {}
+        }
+        p += entry_len;
+    }
+
+    info
+}
+
+/// FADT と、そこから辿った DSDT の \_S5 を読む（PM1a_CNT か \_S5 が無ければ None）
+unsafe fn parse_fadt(fadt: u64) -> Option<S5Info> {
+    let length = read_u32(fadt + 4) as u64;
+    if length < 72 {
+        return None;
+    }
+    // +40: DSDT（32bit）。ACPI 2.0 以降は +140 の X_DSDT（64bit）があればそちら
+    let mut dsdt = read_u32(fadt + 40) as u64;
+    if length >= 148 && read_u64(fadt + 140) != 0 {
+        dsdt = read_u64(fadt + 140);
+    }
+    // +64 / +68: PM1a / PM1b control block（I/O port）
+    let pm1a_cnt = read_u32(fadt + 64);
+    let pm1b_cnt = read_u32(fadt + 68);
+    if pm1a_cnt == 0 || pm1a_cnt > u16::MAX as u32 || pm1b_cnt > u16::MAX as u32 || dsdt == 0 {
+        return None;
+    }
+    if !checksum_ok(dsdt, read_u32(dsdt + 4) as usize) {
+        return None;
+    }
+    let (slp_typ_a, slp_typ_b) = find_s5(dsdt)?;
+    Some(S5Info { pm1a_cnt: pm1a_cnt as u16, pm1b_cnt: pm1b_cnt as u16, slp_typ_a, slp_typ_b })
+}
+
+/// DSDT の AML から `Name(\_S5, Package() { a, b, .. })` を探して (SLP_TYPa, SLP_TYPb) を返す
+/// - NameOp（0x08、"\" の root prefix があってもよい）→ "_S5_" → PackageOp（0x12）の並びだけを見る
+/// - 要素は BytePrefix（0x0A）付きの byte か ZeroOp / OneOp（0x00 / 0x01）
+unsafe fn find_s5(dsdt: u64) -> Option<(u16, u16)> {
+    let end = dsdt + read_u32(dsdt + 4) as u64;
+    let mut p = dsdt + SDT_HEADER_LEN;
+    while p + 4 < end {
+        let found = signature_at(p, b"_S5_")
+            && (read_u8(p - 1) == 0x08 || (read_u8(p - 1) == b'\\' && read_u8(p - 2) == 0x08))
+            && read_u8(p + 4) == 0x12;
+        if !found {
+            p += 1;
+            continue;
+        }
+        // PkgLength: 先頭 byte の bit6-7 が後続の byte 数。その後に NumElements（1 byte）
+        let mut q = p + 5;
+        q += ((read_u8(q) >> 6) as u64) + 1;
+        q += 1;
+        let mut typ = [0u16; 2];
+        for t in typ.iter_mut() {
+            if q >= end {
+                return None;
+            }
+            if read_u8(q) == 0x0A {
+                q += 1;
+            }
+            *t = (read_u8(q) & 0x7) as u16;
+            q += 1;
+        }
+        return Some((typ[0], typ[1]));
+    }
+    None
+}
+
+/// HPET table を読む（register block が system memory に無ければ None）
+unsafe fn parse_hpet(hpet: u64) -> Option<HpetInfo> {
+    if read_u32(hpet + 4) < 56 {
+        return None;
+    }
+    let block_id = read_u32(hpet + SDT_HEADER_LEN);
+    // +40: Generic Address Structure（space id = 0 が system memory。address は +44）
+    if read_u8(hpet + 40) != 0 {
+        return None;
+    }
+    let base_phys = read_u64(hpet + 44);
+    if base_phys == 0 {
+        return None;
+    }
+    Some(HpetInfo {
+        base_phys,
+        hpet_number: read_u8(hpet + 52),
+        comparators: (((block_id >> 8) & 0x1F) + 1) as u8,
+        counter_64bit: block_id & (1 << 13) != 0,
+        legacy_replacement: block_id & (1 << 15) != 0,
+        pci_vendor_id: (block_id >> 16) as u16,
+        min_tick: read_u16(hpet + 53),
+    })
+}
+
+/// RSDP から全 table を辿って Platform を作る（RSDP / root table が無ければ None）
+fn discover() -> Option<Platform> {
+    let rsdp = find_rsdp()?;
+    unsafe {
+        let (root, entry_size) = root_table(rsdp)?;
+
+        let mut oem_id = [0u8; 6];
+        for (i, b) in oem_id.iter_mut().enumerate() {
+            *b = read_u8(rsdp + 9 + i as u64);
+        }
+        let mut platform = Platform {
+            rsdp_phys: rsdp,
+            revision: read_u8(rsdp + 15),
+            oem_id,
+            uses_xsdt: entry_size == 8,
+            tables: [None; ACPI_MAX_TABLES],
+            table_count: 0,
+            tables_bad_checksum: 0,
+            madt: None,
+            hpet: None,
+            s5: None,
+        };
+
+        for_each_table(root, entry_size, |table| {
+            let length = read_u32(table + 4);
+            if !checksum_ok(table, length as usize) {
+                platform.tables_bad_checksum += 1;
+                return;
+            }
+            let mut signature = [0u8; 4];
+            for (i, b) in signature.iter_mut().enumerate() {
+                *b = read_u8(table + i as u64);
+            }
+            if platform.table_count < ACPI_MAX_TABLES {
+                platform.tables[platform.table_count] = Some(TableEntry { signature, phys: table, length });
+                platform.table_count += 1;
+            }
+
+            match &signature {
+                b"APIC" if platform.madt.is_none() => platform.madt = Some(parse_madt(table)),
+                b"HPET" if platform.hpet.is_none() => platform.hpet = parse_hpet(table),
+                b"FACP" if platform.s5.is_none() => platform.s5 = parse_fadt(table),
+                _ => {}
+            }
+        });
+
+        Some(platform)
+    }
+}
+
+/// ACPI を読んで Platform を保存し、summary をログに出す（arch::init から 1 回。paging::init の後）
+pub fn init() {
+    let platform = discover();
+    match &platform {
+        Some(p) => log_summary(p),
+        None => LOG.info("acpi: RSDP not found (no ACPI tables)"),
+    }
+    *PLATFORM.lock() = platform;
+}
+
+/// init で読んだ Platform の写し（ACPI が無い / init 前は None）
+pub fn platform() -> Option<Platform> {
+    *PLATFORM.lock()
+}
+
+/// MADT の写し（arch::smp 用）
+#[cfg_attr(not(feature = "smp"), allow(dead_code))]
+pub fn madt() -> Option<MadtInfo> {
+    platform().and_then(|p| p.madt)
+}
+
+/// HPET table の写し（arch::clock 用）
+pub fn hpet() -> Option<HpetInfo> {
+    platform().and_then(|p| p.hpet)
+}
+
+/// FADT / \_S5 の写し（arch::power 用）
+pub fn s5() -> Option<S5Info> {
+    platform().and_then(|p| p.s5)
+}
+
+/// "<prefix><bytes>" の 1 行を出す（signature / OEM ID。表示できない byte は '?'）
+fn log_label(prefix: &str, bytes: &[u8]) {
+    let mut buf = [0u8; 48];
+    let mut n = 0;
+    for &b in prefix.as_bytes().iter().chain(bytes.iter()) {
+        if n < buf.len() {
+            buf[n] = if b.is_ascii_graphic() || b == b' ' { b } else { b'?' };
+            n += 1;
+        }
+    }
+    let s = unsafe { core::str::from_utf8_unchecked(&buf[..n]) };
+    LOG.info(s);
+}
+
+fn log_summary(p: &Platform) {
+    LOG.info("=== ACPI Platform ===");
+    LOG.info_hex("acpi_rsdp_phys", p.rsdp_phys);
+    LOG.info_u64("acpi_revision", p.revision as u64);
+    log_label("acpi: oem ", &p.oem_id);
+    LOG.info(if p.uses_xsdt { "acpi: root XSDT" } else { "acpi: root RSDT" });
+    for t in p.tables.iter().flatten() {
+        log_label("acpi: table ", &t.signature);
+        LOG.info_hex("acpi_table_phys", t.phys);
+        LOG.info_u64("acpi_table_len", t.length as u64);
+    }
+    LOG.info_u64("acpi_tables", p.table_count as u64);
+    LOG.info_u64("acpi_tables_bad_checksum", p.tables_bad_checksum as u64);
+
+    match &p.madt {
+        Some(m) => {
+            LOG.info_hex("madt_lapic_phys", m.lapic_phys);
+            LOG.info_u64("madt_cpus", m.cpu_count as u64);
+            LOG.info_u64("madt_cpus_ignored", m.cpus_ignored as u64);
+            LOG.info_u64("madt_legacy_pics", m.legacy_pics as u64);
+            for io in m.ioapics.iter().flatten() {
+                LOG.info_u64("madt_ioapic_id", io.id as u64);
+                LOG.info_hex("madt_ioapic_phys", io.phys);
+                LOG.info_u64("madt_ioapic_gsi_base", io.gsi_base as u64);
+            }
+            for o in m.overrides.iter().flatten() {
+                LOG.info_u64("madt_override_irq", o.source as u64);
+                LOG.info_u64("madt_override_gsi", o.gsi as u64);
+                LOG.info_hex("madt_override_flags", o.flags as u64);
+            }
+            LOG.info_u64("madt_entries_ignored", m.entries_ignored as u64);
+            LOG.info_u64("madt_irq0_gsi", m.isa_irq_gsi(0) as u64);
+        }
+        None => LOG.info("acpi: no MADT"),
+    }
+
+    match &p.hpet {
+        Some(h) => {
+            LOG.info_hex("hpet_base_phys", h.base_phys);
+            LOG.info_u64("hpet_number", h.hpet_number as u64);
+            LOG.info_hex("hpet_pci_vendor_id", h.pci_vendor_id as u64);
+            LOG.info_u64("hpet_comparators", h.comparators as u64);
+            LOG.info_u64("hpet_counter_64bit", h.counter_64bit as u64);
+            LOG.info_u64("hpet_legacy_replacement", h.legacy_replacement as u64);
+            LOG.info_u64("hpet_min_tick", h.min_tick as u64);
+        }
+        None => LOG.info("acpi: no HPET"),
+    }
+
+    match &p.s5 {
+        Some(s) => {
+            LOG.info_hex("acpi_pm1a_cnt", s.pm1a_cnt as u64);
+            LOG.info_hex("acpi_pm1b_cnt", s.pm1b_cnt as u64);
+            LOG.info_u64("acpi_s5_slp_typ_a", s.slp_typ_a as u64);
+            LOG.info_u64("acpi_s5_slp_typ_b", s.slp_typ_b as u64);
+        }
+        None => LOG.info("acpi: no FADT / \\_S5 (ACPI poweroff unavailable)"),
+    }
+    LOG.info("=== End of ACPI Platform ===");
+}
@@ -0,0 +1,233 @@
+// kernel/src/arch/clock.rs
+//
+// 役割:
+// - 単調増加の hardware clock を 1 つ選び、起動からの ns で読めるようにする（kernel::time の hardware 側）。
+//   * HPET: ACPI の HPET table（arch::acpi）の register block。main counter を有効にして読む
+//   * TSC: HPET が使えないとき。PIT channel 2（timer::pit_delay_us）で 10ms を測って周波数を calibrate する
+//   * どちらも使えない: None（now_ns は 0 のまま）
+//
+// 方針:
+// - init(phys_mem) は KernelState::new で 1 回（PMM ができてから。2 回目以降は何もしない）。選んだ source と周波数をログに出す
+// - HPET の register block は PMM に予約して MMIO window に uncached で map する（paging::map_mmio。できなければ HPET は使わない）
+// - 32bit の HPET counter は読むたびに上位を補う（14.3MHz で約 5 分で 1 周。それより短い間隔で読む前提）
+// - TSC は invariant（CPUID 8000_0007h:EDX[8]）でなくても使う（invariant かどうかはログに出すだけ）
+//
+// やらないこと:
+// - HPET の comparator（割り込み）/ TSC deadline timer（tick は PIT IRQ0 のまま）
+// - CPU 間の TSC のずれの補正（読むのは BSP だけ）
+
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+
+use super::{acpi, paging, timer};
+use crate::mm::PhysicalMemoryManager;
+
+const LOG: crate::logging::Subsystem = crate::logging::Subsystem::Arch;
+
+/// hardware clock の出どころ
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ClockSource {
+    None,
+    Hpet,
+    Tsc,
+}
+
+impl ClockSource {
+    pub fn name(self) -> &'static str {
+        match self {
+            ClockSource::None => "none",
+            ClockSource::Hpet => "hpet",
+            ClockSource::Tsc => "tsc",
+        }
+    }
+
+    fn from_u8(v: u8) -> ClockSource {
+        match v {
+            1 => ClockSource::Hpet,
+            2 => ClockSource::Tsc,
+            _ => ClockSource::None,
+        }
+    }
+
+    fn as_u8(self) -> u8 {
+        match self {
+            ClockSource::None => 0,
+            ClockSource::Hpet => 1,
+            ClockSource::Tsc => 2,
+        }
+    }
+}
+
+// HPET の register（base からの offset）
+const HPET_REG_CAPS: u64 = 0x00;
+const HPET_REG_CONFIG: u64 = 0x10;
+const HPET_REG_COUNTER: u64 = 0xF0;
+const HPET_CONFIG_ENABLE: u64 = 1 << 0;
+const HPET_CAPS_COUNTER_64: u64 = 1 << 13;
+/// HPET の counter 周期の上限（仕様上 100ns = 10^8 fs 以下）
+const HPET_MAX_PERIOD_FS: u64 = 100_000_000;
+/// HPET の register block の大きさ（map_mmio で予約・map する）
+const HPET_MMIO_SIZE: u64 = 0x400;
+const FS_PER_NS: u128 = 1_000_000;
+
+/// TSC の calibrate に使う時間
+const TSC_CALIBRATE_US: u64 = 10_000;
+const CPUID_EDX_TSC: u32 = 1 << 4;
+const CPUID_EXT_EDX_INVARIANT_TSC: u32 = 1 << 8;
+
+static SOURCE: AtomicU8 = AtomicU8::new(0);
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+static HPET_BASE_VIRT: AtomicU64 = AtomicU64::new(0);
+static HPET_PERIOD_FS: AtomicU64 = AtomicU64::new(0);
+static HPET_COUNTER_64: AtomicBool = AtomicBool::new(false);
+// init 時の counter（ここを 0 ns とする）と、32bit counter の上位を補った直近の値
+static HPET_START: AtomicU64 = AtomicU64::new(0);
+static HPET_LAST: AtomicU64 = AtomicU64::new(0);
+
+static TSC_HZ: AtomicU64 = AtomicU64::new(0);
+static TSC_START: AtomicU64 = AtomicU64::new(0);
+
+/// HPET → TSC の順に使える clock を選ぶ（KernelState::new から。2 回目以降は何もしない）
+pub fn init(phys_mem: &mut PhysicalMemoryManager) {
+    if INITIALIZED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    if init_hpet(phys_mem) || init_tsc() {
+        return;
+    }
+    LOG.info("clock: no hardware clock (kernel time only)");
+}
+
+/// 選ばれた source
+pub fn source() -> ClockSource {
+    ClockSource::from_u8(SOURCE.load(Ordering::SeqCst))
+}
+
+/// source の周波数（Hz。None なら 0）
+pub fn frequency_hz() -> u64 {
+    match source() {
+        ClockSource::Hpet => {
+            let period = HPET_PERIOD_FS.load(Ordering::SeqCst);
+            if period == 0 { 0 } else { 1_000_000_000_000_000 / period }
+        }
+        ClockSource::Tsc => TSC_HZ.load(Ordering::SeqCst),
+        ClockSource::None => 0,
+    }
+}
+
+/// init からの経過 ns（source が None なら 0）
+pub fn now_ns() -> u64 {
+    match source() {
+        ClockSource::Hpet => {
+            let elapsed = hpet_counter().wrapping_sub(HPET_START.load(Ordering::SeqCst));
+            (elapsed as u128 * HPET_PERIOD_FS.load(Ordering::SeqCst) as u128 / FS_PER_NS) as u64
+        }
+        ClockSource::Tsc => {
+            let hz = TSC_HZ.load(Ordering::SeqCst);
+            let elapsed = rdtsc().wrapping_sub(TSC_START.load(Ordering::SeqCst));
+            (elapsed as u128 * 1_000_000_000 / hz as u128) as u64
+        }
+        ClockSource::None => 0,
+    }
+}
+
+#[inline(always)]
+fn rdtsc() -> u64 {
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+#[inline(always)]
+unsafe fn hpet_read(reg: u64) -> u64 {
+    core::ptr::read_volatile((HPET_BASE_VIRT.load(Ordering::Relaxed) + reg) as *const u64)
+}
+
+#[inline(always)]
+unsafe fn hpet_write(reg: u64, v: u64) {
+    core::ptr::write_volatile((HPET_BASE_VIRT.load(Ordering::Relaxed) + reg) as *mut u64, v);
+}
+
+/// main counter（32bit なら上位を補って単調にする）
+fn hpet_counter() -> u64 {
+    let raw = unsafe { hpet_read(HPET_REG_COUNTER) };
+    if HPET_COUNTER_64.load(Ordering::Relaxed) {
+        return raw;
+    }
+    let last = HPET_LAST.load(Ordering::SeqCst);
+    let mut ext = (last & !0xFFFF_FFFF) | (raw & 0xFFFF_FFFF);
+    if ext < last {
+        ext += 1u64 << 32;
+    }
+    HPET_LAST.store(ext, Ordering::SeqCst);
+    ext
+}
+
+fn init_hpet(phys_mem: &mut PhysicalMemoryManager) -> bool {
+    let Some(h) = acpi::hpet() else {
+        return false;
+    };
+    match paging::map_mmio(h.base_phys, HPET_MMIO_SIZE, phys_mem) {
+        Ok(virt) => HPET_BASE_VIRT.store(virt, Ordering::SeqCst),
+        Err(e) => {
+            LOG.error("clock: map_mmio for HPET failed; skip");
+            LOG.info(e.name());
+            return false;
+        }
+    }
+
+    unsafe {
+        let caps = hpet_read(HPET_REG_CAPS);
+        let period = caps >> 32;
+        if period == 0 || period > HPET_MAX_PERIOD_FS {
+            LOG.error("clock: HPET period out of range; skip");
+            LOG.info_u64("hpet_period_fs", period);
+            return false;
+        }
+        HPET_PERIOD_FS.store(period, Ordering::SeqCst);
+        HPET_COUNTER_64.store(caps & HPET_CAPS_COUNTER_64 != 0, Ordering::SeqCst);
+
+        let config = hpet_read(HPET_REG_CONFIG);
+        hpet_write(HPET_REG_CONFIG, config | HPET_CONFIG_ENABLE);
+    }
+
+    let start = unsafe { hpet_read(HPET_REG_COUNTER) };
+    let start = if HPET_COUNTER_64.load(Ordering::SeqCst) { start } else { start & 0xFFFF_FFFF };
+    HPET_START.store(start, Ordering::SeqCst);
+    HPET_LAST.store(start, Ordering::SeqCst);
+    SOURCE.store(ClockSource::Hpet.as_u8(), Ordering::SeqCst);
+
+    LOG.info("clock: source = hpet");
+    LOG.info_u64("hpet_period_fs", HPET_PERIOD_FS.load(Ordering::SeqCst));
+    LOG.info_u64("clock_hz", frequency_hz());
+    LOG.info_u64("hpet_counter_64bit", HPET_COUNTER_64.load(Ordering::SeqCst) as u64);
+    true
+}
+
+fn init_tsc() -> bool {
+    let edx = unsafe { core::arch::x86_64::__cpuid(1).edx };
+    if edx & CPUID_EDX_TSC == 0 {
+        return false;
+    }
+
+    let t0 = rdtsc();
+    timer::pit_delay_us(TSC_CALIBRATE_US);
+    let t1 = rdtsc();
+    let hz = t1.wrapping_sub(t0) * (1_000_000 / TSC_CALIBRATE_US);
+    if hz == 0 {
+        LOG.error("clock: TSC calibration failed");
+        return false;
+    }
+
+    let invariant = unsafe {
+        core::arch::x86_64::__cpuid(0x8000_0000).eax >= 0x8000_0007
+            && core::arch::x86_64::__cpuid(0x8000_0007).edx & CPUID_EXT_EDX_INVARIANT_TSC != 0
+    };
+
+    TSC_HZ.store(hz, Ordering::SeqCst);
+    TSC_START.store(rdtsc(), Ordering::SeqCst);
+    SOURCE.store(ClockSource::Tsc.as_u8(), Ordering::SeqCst);
+
+    LOG.info("clock: source = tsc");
+    LOG.info_u64("clock_hz", hz);
+    LOG.info_u64("tsc_invariant", invariant as u64);
+    true
+}
@@ -0,0 +1,196 @@
+// kernel/src/arch/context.rs
+//
+// 役割:
+// - task ごとの kernel stack と、その間の stack 切替（callee-saved register の退避 / 復帰）。
+// - KernelState は ArchOps（init_task_context / switch_context）経由でだけ使う。
+//
+// 切替の形（関数呼び出しとして切り替える）:
+// - formal_os_context_switch(save, next):
+//   rbp / rbx / r12..r15 を今の stack に push → rsp を *save に保存 → rsp = next → pop → ret
+// - caller-saved は呼び出し規約どおり呼び出し元が退避済み（extern "C" の call）
+// - 初回の stack は「pop 6 個 + ret で task_entry に入る」形に積んでおく
+// - ring3_tasks の user task は「pop 6 個 + ret で formal_os_user_entry → EOI → iretq」で ring3 に入る
+//   （iretq フレームは stack の上端に置く。入った後の kernel stack は空）
+//
+// 方針:
+// - stack は静的領域（KSTACK_SLOTS 個、task index で引く）。heap は使わない
+// - stack の上端は high-alias（gdt の RSP0 と同じ。user root に切り替えた後でも引ける）
+// - 切替時に TSS.RSP0 を次の task の stack 上端にする（ring3 から入る先をその task の stack にする）
+// - FPU / SSE は退避しない（target は soft-float。kernel は SSE を使わない）
+// - slot ごとに stack の直下に guard page を置く（arch::stack_guard が boot 時に unmap し、踏んだ #PF / #DF を照合する）
+//
+// やらないこと:
+// - 割り込みの中以外からの初回切替（task_entry は timer IRQ の EOI を前提にしている）
+
+/// 切替で保存する状態（callee-saved は stack 上。ここには rsp だけ持つ）
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TaskContext {
+    pub rsp: u64,
+}
+
+impl TaskContext {
+    /// まだ一度も保存していない
+    pub const EMPTY: TaskContext = TaskContext { rsp: 0 };
+}
+
+/// task 用 kernel stack の数（MAX_TASKS 以上であること。kernel/kstack.rs で検査）
+pub const KSTACK_SLOTS: usize = 4;
+
+/// task 用 kernel stack 1 つの大きさ
+pub const KSTACK_SIZE: usize = 4096 * 4;
+
+#[cfg(target_os = "none")]
+pub use self::hw::{init_task_context, init_user_task_context, kernel_stack_guard_page, kernel_stack_top, switch_context};
+
+#[cfg(target_os = "none")]
+mod hw {
+    #![allow(static_mut_refs)] // 単一CPU・slot は task index ごとに専有の前提
+
+    use super::{TaskContext, KSTACK_SIZE, KSTACK_SLOTS};
+    use crate::arch::{gdt, pic, timer, trapframe, virt_layout};
+
+    const GUARD_SIZE: usize = 4096;
+
+    /// 先頭（低い方）の 1 page は guard（arch::stack_guard が unmap する）。stack は buf の上端から下へ伸びる
+    #[repr(C, align(4096))]
+    struct KernelStack {
+        guard: [u8; GUARD_SIZE],
+        buf: [u8; KSTACK_SIZE],
+    }
+
+    static mut KSTACKS: [KernelStack; KSTACK_SLOTS] =
+        [const { KernelStack { guard: [0; GUARD_SIZE], buf: [0; KSTACK_SIZE] } }; KSTACK_SLOTS];
+
+    // rdi = *mut u64（今の rsp の保存先）, rsi = 次の rsp
+    core::arch::global_asm!(
+        ".global formal_os_context_switch",
+        "formal_os_context_switch:",
+        "push rbp",
+        "push rbx",
+        "push r12",
+        "push r13",
+        "push r14",
+        "push r15",
+        "mov [rdi], rsp",
+        "mov rsp, rsi",
+        "pop r15",
+        "pop r14",
+        "pop r13",
+        "pop r12",
+        "pop rbx",
+        "pop rbp",
+        "ret",
+    );
+
+    // 初回の ring3 入り: ret でここに来た時点の rsp が iretq フレーム（16byte align）を指している
+    core::arch::global_asm!(
+        ".global formal_os_user_entry",
+        "formal_os_user_entry:",
+        "call formal_os_user_entry_eoi",
+        "iretq",
+    );
+
+    extern "C" {
+        fn formal_os_context_switch(save: *mut u64, next: u64);
+        fn formal_os_user_entry();
+    }
+
+    /// formal_os_user_entry から呼ぶ（切替元の timer IRQ は EOI 前）
+    #[no_mangle]
+    extern "C" fn formal_os_user_entry_eoi() {
+        pic::end_of_interrupt(timer::TIMER_IRQ);
+    }
+
+    /// 初回の切替先。切替元は timer IRQ の中（EOI 前）なので、ここで EOI してから IRQ を待つ
+    /// - 次の timer IRQ はこの stack の上で受け、tick の末尾で別の task の stack へ切り替わる
+    extern "C" fn task_entry() -> ! {
+        pic::end_of_interrupt(timer::TIMER_IRQ);
+        loop {
+            x86_64::instructions::interrupts::enable_and_hlt();
+        }
+    }
+
+    /// slot の stack 上端（high-alias、16byte align）
+    pub fn kernel_stack_top(slot: usize) -> u64 {
+        let low = unsafe { KSTACKS[slot].buf.as_ptr().add(KSTACK_SIZE) as u64 };
+        virt_layout::kernel_high_alias_of_low(low) & !0xF
+    }
+
+    /// slot の guard page の先頭（low 側。unmap と照合は arch::stack_guard）
+    pub fn kernel_stack_guard_page(slot: usize) -> u64 {
+        unsafe { KSTACKS[slot].guard.as_ptr() as u64 }
+    }
+
+    /// slot の stack に初回の context を積む（pop 6 個 + ret で task_entry に入る）
+    /// - task_entry に入った時点で rsp ≡ 8 (mod 16)（call 直後と同じ）になるよう、上端に 0 を 1 つ置く
+    pub fn init_task_context(slot: usize) -> TaskContext {
+        let top = kernel_stack_top(slot);
+        let entry = virt_layout::kernel_high_alias_of_low(task_entry as usize as u64);
+
+        let mut sp = top;
+        unsafe {
+            sp -= 8;
+            *(sp as *mut u64) = 0; // task_entry の戻り先（戻らない）
+            sp -= 8;
+            *(sp as *mut u64) = entry;
+            for _ in 0..6 {
+                sp -= 8;
+                *(sp as *mut u64) = 0; // rbp / rbx / r12..r15
+            }
+        }
+        TaskContext { rsp: sp }
+    }
+
+    /// slot の stack に、初回の切替で ring3（rip / rsp）へ iretq する context を積む
+    /// - RFLAGS は IF=1（timer IRQ で preempt する）。フレームは arch::trapframe で検査してから積む
+    /// - 違反なら (code, value) を返す（呼び出し側が TrapFrameCorrupt で kill する）
+    pub fn init_user_task_context(slot: usize, rip: u64, rsp: u64) -> Result<TaskContext, (u64, u64)> {
+        let frame = trapframe::UserTrapFrame {
+            rip,
+            cs: (gdt::user_code_selector().0 | 3) as u64,
+            rflags: trapframe::RFLAGS_FIXED1 | trapframe::RFLAGS_IF,
+            rsp,
+            ss: (gdt::user_data_selector().0 | 3) as u64,
+        };
+        if let Err((violation, value)) = trapframe::check_user_trap_frame(&frame) {
+            return Err((violation.code(), value));
+        }
+
+        let top = kernel_stack_top(slot);
+        let entry = virt_layout::kernel_high_alias_of_low(formal_os_user_entry as usize as u64);
+
+        // iretq フレーム（低い方から RIP, CS, RFLAGS, RSP, SS）。先頭が 16byte align になるよう上端に 8byte 空ける
+        let mut sp = top - 8;
+        unsafe {
+            for w in [frame.ss, frame.rsp, frame.rflags, frame.cs, frame.rip] {
+                sp -= 8;
+                *(sp as *mut u64) = w;
+            }
+            sp -= 8;
+            *(sp as *mut u64) = entry;
+            for _ in 0..6 {
+                sp -= 8;
+                *(sp as *mut u64) = 0; // rbp / rbx / r12..r15
+            }
+        }
+        Ok(TaskContext { rsp: sp })
+    }
+
+    /// 今の stack を prev に保存し、next の stack へ切り替える
+    /// - 戻るのは、誰かが prev に切り替えたとき
+    /// - rsp0 が Some なら TSS.RSP0（ring3_tasks では syscall 入口の stack も）をそこにする
+    ///
+    /// # Safety
+    /// - next は init_task_context で作ったか、この関数で保存した context であること
+    /// - 割り込み禁止で呼ぶこと（切替の途中で IRQ を受けない）
+    pub unsafe fn switch_context(prev: *mut TaskContext, next: *const TaskContext, rsp0: Option<u64>) {
+        if let Some(top) = rsp0 {
+            gdt::set_rsp0(top);
+            // syscall 命令の入口も同じ stack に入る
+            #[cfg(feature = "ring3_tasks")]
+            crate::arch::syscall_msr::set_kernel_rsp(top);
+        }
+        formal_os_context_switch(&mut (*prev).rsp, (*next).rsp);
+    }
+}
@@ -8,3 +8,20 @@ pub fn halt_loop() -> ! {
         }
     }
 }
+
+/// RFLAGS.IF が立っているか（外部割り込みが有効か）
+pub fn irqs_enabled() -> bool {
+    x86_64::instructions::interrupts::are_enabled()
+}
+
+/// IF を落とす（kernel::state_ref の BKL 用）
+#[cfg_attr(not(feature = "smp"), allow(dead_code))]
+pub fn disable_irqs() {
+    x86_64::instructions::interrupts::disable();
+}
+
+/// IF を立てる（kernel::state_ref の BKL 用。取る前に立っていたときだけ戻す）
+#[cfg_attr(not(feature = "smp"), allow(dead_code))]
+pub fn enable_irqs() {
+    x86_64::instructions::interrupts::enable();
+}
@@ -0,0 +1,33 @@
+// kernel/src/arch/drivers/mod.rs
+//
+// 役割:
+// - 外部デバイスの最小ドライバをまとめる場所（arch::init の最後に init する）。
+// - virtio: legacy virtio-pci の register と split virtqueue の共通部分
+// - virtio_console: legacy virtio-pci の virtio console。trace（wire_hex / record）の高速な出口（feature virtio_console）
+// - virtio_net: legacy virtio-pci の virtio-net。kernel::net（net service）の下回り（feature virtio_net）
+// - virtio_blk: legacy virtio-pci の virtio-blk。kernel::fs（読み取り専用 fs）の下回り（feature virtio_blk）
+//
+// 方針:
+// - device は arch::pci の一覧（pci::find / pci::devices）から探す。config space の port I/O は pci.rs だけ
+// - 見つからなければ何もしない（呼び出し側は fallback する）
+// - arch::init から init するドライバ（virtio_console）は polling で動かし、DMA buffer は kernel の静的領域に置く
+//   （PMM は KernelState の持ち物なので arch::init からは触らない）
+// - PMM のフレームや IRQ が要るドライバ（virtio_net / virtio_blk）は KernelState 側から seal の前に init する
+//
+// やらないこと:
+// - 汎用のドライバ登録 / probe の仕組み（ドライバが増えたら考える）
+
+#[cfg(any(feature = "virtio_console", feature = "virtio_net", feature = "virtio_blk"))]
+mod virtio;
+#[cfg(feature = "virtio_console")]
+pub mod virtio_console;
+#[cfg(feature = "virtio_net")]
+pub mod virtio_net;
+#[cfg(feature = "virtio_blk")]
+pub mod virtio_blk;
+
+/// 有効な feature のドライバを初期化する（arch::init から 1 回。paging / acpi の後）
+pub fn init() {
+    #[cfg(feature = "virtio_console")]
+    virtio_console::init();
+}
@@ -0,0 +1,308 @@
+// kernel/src/arch/drivers/virtio.rs
+//
+// 役割:
+// - legacy virtio-pci（BAR0 の register）と split virtqueue の共通部分（virtio_console / virtio_net / virtio_blk が使う）。
+//   * register の offset と device status の bit
+//   * Regs: BAR0 の register の窓（I/O BAR は port I/O、memory BAR は paging::map_mmio で uncached に map して volatile）
+//   * queue の layout（desc / avail / 4KiB 境界 / used）の計算
+//   * Virtqueue: 呼び出し側が用意した領域の上で descriptor を書き、avail に出し、used から回収する
+//
+// 方針:
+// - queue の領域（物理で連続・4KiB 境界）と buffer は呼び出し側が用意する（静的領域 / PMM の予約 = reserve_contiguous）
+// - memory BAR0 を map するには PMM が要る（regs_from_bar0）。arch::init から起こす virtio_console は I/O BAR0 だけ
+// - ring は volatile で読み書きし、avail idx の更新と notify の前に fence を置く
+//
+// やらないこと:
+// - modern（virtio 1.0）の capability / MSI-X / indirect descriptor / event idx
+// - descriptor の free list（chain は呼び出し側が固定の番号で組む）
+
+use core::sync::atomic::{fence, Ordering};
+
+use x86_64::instructions::port::Port;
+
+use crate::arch::paging;
+use crate::arch::pci::{Bar, PciDevice};
+use crate::mm::PhysicalMemoryManager;
+
+const LOG: crate::logging::Subsystem = crate::logging::Subsystem::Arch;
+
+// legacy virtio-pci の I/O register（BAR0 からの offset）
+const REG_DEVICE_FEATURES: u16 = 0x00;
+const REG_GUEST_FEATURES: u16 = 0x04;
+const REG_QUEUE_PFN: u16 = 0x08;
+const REG_QUEUE_SIZE: u16 = 0x0C;
+const REG_QUEUE_SELECT: u16 = 0x0E;
+const REG_QUEUE_NOTIFY: u16 = 0x10;
+const REG_STATUS: u16 = 0x12;
+#[cfg_attr(not(feature = "virtio_net"), allow(dead_code))]
+const REG_ISR: u16 = 0x13;
+/// MSI-X なしのときの device 固有 config の先頭
+#[cfg_attr(not(any(feature = "virtio_net", feature = "virtio_blk")), allow(dead_code))]
+const REG_DEVICE_CONFIG: u16 = 0x14;
+
+pub const STATUS_ACKNOWLEDGE: u8 = 1;
+pub const STATUS_DRIVER: u8 = 2;
+pub const STATUS_DRIVER_OK: u8 = 4;
+pub const STATUS_FAILED: u8 = 0x80;
+
+/// descriptor の flags: next に続く（chain）
+#[cfg_attr(not(feature = "virtio_blk"), allow(dead_code))]
+pub const DESC_F_NEXT: u16 = 1;
+/// descriptor の flags: device が書く buffer
+#[cfg_attr(not(any(feature = "virtio_net", feature = "virtio_blk")), allow(dead_code))]
+pub const DESC_F_WRITE: u16 = 2;
+
+pub const PAGE: usize = 4096;
+
+/// memory BAR0 を map する大きさ（legacy の header 0x14 byte と device 固有 config が入る。BAR の大きさは測らない）
+#[cfg_attr(not(any(feature = "virtio_net", feature = "virtio_blk")), allow(dead_code))]
+const LEGACY_REGS_MMIO_SIZE: u64 = 0x100;
+
+/// BAR0 の register の窓
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Regs {
+    /// I/O BAR の port の先頭
+    Io(u16),
+    /// memory BAR を MMIO window に map した仮想アドレス
+    #[cfg_attr(not(any(feature = "virtio_net", feature = "virtio_blk")), allow(dead_code))]
+    Mmio(u64),
+}
+
+impl Regs {
+    /// 静的変数に置く形（0 = 無し）。I/O port は 0x10000 未満、MMIO window は high-half なので重ならない
+    #[cfg_attr(not(feature = "virtio_net"), allow(dead_code))]
+    pub fn to_raw(self) -> u64 {
+        match self {
+            Regs::Io(port) => port as u64,
+            Regs::Mmio(virt) => virt,
+        }
+    }
+
+    #[cfg_attr(not(feature = "virtio_net"), allow(dead_code))]
+    pub fn from_raw(raw: u64) -> Option<Regs> {
+        match raw {
+            0 => None,
+            1..=0xFFFF => Some(Regs::Io(raw as u16)),
+            _ => Some(Regs::Mmio(raw)),
+        }
+    }
+
+    /// ready のログ（I/O なら virtio_io_base、MMIO なら virtio_mmio_base）
+    pub fn log(self, log: crate::logging::Subsystem) {
+        match self {
+            Regs::Io(port) => log.info_hex("virtio_io_base", port as u64),
+            Regs::Mmio(virt) => log.info_hex("virtio_mmio_base", virt),
+        }
+    }
+
+    fn read8(self, reg: u16) -> u8 {
+        match self {
+            Regs::Io(port) => unsafe { Port::<u8>::new(port + reg).read() },
+            Regs::Mmio(virt) => unsafe { core::ptr::read_volatile((virt + reg as u64) as *const u8) },
+        }
+    }
+
+    fn read16(self, reg: u16) -> u16 {
+        match self {
+            Regs::Io(port) => unsafe { Port::<u16>::new(port + reg).read() },
+            Regs::Mmio(virt) => unsafe { core::ptr::read_volatile((virt + reg as u64) as *const u16) },
+        }
+    }
+
+    fn read32(self, reg: u16) -> u32 {
+        match self {
+            Regs::Io(port) => unsafe { Port::<u32>::new(port + reg).read() },
+            Regs::Mmio(virt) => unsafe { core::ptr::read_volatile((virt + reg as u64) as *const u32) },
+        }
+    }
+
+    fn write8(self, reg: u16, v: u8) {
+        match self {
+            Regs::Io(port) => unsafe { Port::<u8>::new(port + reg).write(v) },
+            Regs::Mmio(virt) => unsafe { core::ptr::write_volatile((virt + reg as u64) as *mut u8, v) },
+        }
+    }
+
+    fn write16(self, reg: u16, v: u16) {
+        match self {
+            Regs::Io(port) => unsafe { Port::<u16>::new(port + reg).write(v) },
+            Regs::Mmio(virt) => unsafe { core::ptr::write_volatile((virt + reg as u64) as *mut u16, v) },
+        }
+    }
+
+    fn write32(self, reg: u16, v: u32) {
+        match self {
+            Regs::Io(port) => unsafe { Port::<u32>::new(port + reg).write(v) },
+            Regs::Mmio(virt) => unsafe { core::ptr::write_volatile((virt + reg as u64) as *mut u32, v) },
+        }
+    }
+}
+
+/// BAR0 の register の窓を作る（I/O BAR はそのまま、memory BAR は予約して MMIO window に uncached で map する）
+/// - 使えなければ理由をログに出して None（呼び出し側は device を使わない）
+#[cfg_attr(not(any(feature = "virtio_net", feature = "virtio_blk")), allow(dead_code))]
+pub fn regs_from_bar0(dev: &PciDevice, phys_mem: &mut PhysicalMemoryManager) -> Option<Regs> {
+    match dev.bar(0) {
+        Bar::Io { port } => Some(Regs::Io(port)),
+        Bar::Memory { phys, .. } => match paging::map_mmio(phys, LEGACY_REGS_MMIO_SIZE, phys_mem) {
+            Ok(virt) => Some(Regs::Mmio(virt)),
+            Err(e) => {
+                LOG.error("virtio: map_mmio for BAR0 failed");
+                LOG.info(e.name());
+                None
+            }
+        },
+        Bar::None => {
+            LOG.error("virtio: no BAR0");
+            None
+        }
+    }
+}
+
+/// DMA に使う物理で連続した pages 枚を PMM に予約し、0 で埋める（(物理, physmap 上の仮想)。予約は外さない）
+#[cfg_attr(not(any(feature = "virtio_net", feature = "virtio_blk")), allow(dead_code))]
+pub fn reserve_dma(phys_mem: &mut PhysicalMemoryManager, pages: usize) -> Option<(u64, u64)> {
+    let r = match phys_mem.reserve_contiguous(pages) {
+        Ok(r) => r,
+        Err(e) => {
+            LOG.error("virtio: reserve_contiguous for DMA failed");
+            LOG.info(e.name());
+            LOG.info_u64("pages", pages as u64);
+            return None;
+        }
+    };
+    let virt = paging::physical_memory_offset() + r.start;
+    unsafe { core::ptr::write_bytes(virt as *mut u8, 0, pages * PAGE) };
+    Some((r.start, virt))
+}
+
+/// queue size qsz の legacy layout: (used ring の offset, 全体の byte 数)
+pub fn queue_layout(qsz: usize) -> (usize, usize) {
+    let desc_bytes = 16 * qsz;
+    let avail_bytes = 2 * (3 + qsz);
+    let used_off = (desc_bytes + avail_bytes).div_ceil(PAGE) * PAGE;
+    let used_bytes = 2 * 3 + 8 * qsz;
+    (used_off, used_off + used_bytes)
+}
+
+pub fn write_status(regs: Regs, status: u8) {
+    regs.write8(REG_STATUS, status)
+}
+
+/// reset → ACKNOWLEDGE → DRIVER まで進め、device の feature を返す（受ける feature は guest_features）
+pub fn begin_init(regs: Regs, guest_features: u32) -> u32 {
+    write_status(regs, 0);
+    write_status(regs, STATUS_ACKNOWLEDGE);
+    write_status(regs, STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+    let features = regs.read32(REG_DEVICE_FEATURES);
+    regs.write32(REG_GUEST_FEATURES, features & guest_features);
+    features
+}
+
+/// queue を選んで size を読む（0 なら無い queue）
+pub fn queue_size(regs: Regs, queue: u16) -> u16 {
+    regs.write16(REG_QUEUE_SELECT, queue);
+    regs.read16(REG_QUEUE_SIZE)
+}
+
+/// 選んだ queue の領域（物理 / 4KiB 境界）を device に教える
+pub fn set_queue_phys(regs: Regs, queue: u16, phys: u64) {
+    regs.write16(REG_QUEUE_SELECT, queue);
+    regs.write32(REG_QUEUE_PFN, (phys / PAGE as u64) as u32);
+}
+
+pub fn notify(regs: Regs, queue: u16) {
+    fence(Ordering::SeqCst);
+    regs.write16(REG_QUEUE_NOTIFY, queue)
+}
+
+/// ISR status を読む（読むと device 側の割り込みが落ちる）
+#[cfg_attr(not(feature = "virtio_net"), allow(dead_code))]
+pub fn read_isr(regs: Regs) -> u8 {
+    regs.read8(REG_ISR)
+}
+
+/// device 固有 config（virtio-net なら MAC、virtio-blk なら capacity）の offset の 1 byte
+#[cfg_attr(not(any(feature = "virtio_net", feature = "virtio_blk")), allow(dead_code))]
+pub fn read_config_u8(regs: Regs, offset: u16) -> u8 {
+    regs.read8(REG_DEVICE_CONFIG + offset)
+}
+
+/// split virtqueue の driver 側（領域は呼び出し側のもの。0 で埋めてから new する）
+pub struct Virtqueue {
+    size: u16,
+    desc: *mut u8,
+    avail: *mut u8,
+    used: *const u8,
+    /// 次に書く avail idx
+    avail_idx: u16,
+    /// 次に読む used idx
+    last_used: u16,
+}
+
+// 生ポインタは呼び出し側の queue 領域を指すだけ（呼び出し側の Mutex の下でだけ触る）
+unsafe impl Send for Virtqueue {}
+
+impl Virtqueue {
+    /// base は queue_layout(size) の全体が入る領域の先頭（仮想アドレス）
+    pub unsafe fn new(base: *mut u8, size: u16) -> Virtqueue {
+        let (used_off, _) = queue_layout(size as usize);
+        Virtqueue {
+            size,
+            desc: base,
+            avail: base.add(16 * size as usize),
+            used: base.add(used_off),
+            avail_idx: 0,
+            last_used: 0,
+        }
+    }
+
+    /// descriptor i を書く（next は使わない）
+    pub fn set_desc(&mut self, i: u16, phys: u64, len: u32, flags: u16) {
+        self.set_desc_chained(i, phys, len, flags, 0);
+    }
+
+    /// descriptor i を書く（flags に DESC_F_NEXT があれば next に続く）
+    pub fn set_desc_chained(&mut self, i: u16, phys: u64, len: u32, flags: u16, next: u16) {
+        let d = unsafe { self.desc.add(16 * (i % self.size) as usize) };
+        unsafe {
+            core::ptr::write_volatile(d as *mut u64, phys);
+            core::ptr::write_volatile(d.add(8) as *mut u32, len);
+            core::ptr::write_volatile(d.add(12) as *mut u16, flags);
+            core::ptr::write_volatile(d.add(14) as *mut u16, next % self.size);
+        }
+    }
+
+    /// descriptor head を avail ring に出す（notify は呼び出し側）
+    pub fn offer(&mut self, head: u16) {
+        let slot = (self.avail_idx % self.size) as usize;
+        unsafe {
+            core::ptr::write_volatile(self.avail.add(4 + 2 * slot) as *mut u16, head);
+            fence(Ordering::SeqCst);
+            self.avail_idx = self.avail_idx.wrapping_add(1);
+            core::ptr::write_volatile(self.avail.add(2) as *mut u16, self.avail_idx);
+        }
+    }
+
+    /// 回収できる descriptor があるか（used ring は進めない）
+    #[cfg_attr(not(feature = "virtio_net"), allow(dead_code))]
+    pub fn has_used(&self) -> bool {
+        unsafe { core::ptr::read_volatile(self.used.add(2) as *const u16) != self.last_used }
+    }
+
+    /// device が使い終えた descriptor を 1 つ回収する（(id, 書いた byte 数)）
+    pub fn pop_used(&mut self) -> Option<(u16, u32)> {
+        let used_idx = unsafe { core::ptr::read_volatile(self.used.add(2) as *const u16) };
+        if used_idx == self.last_used {
+            return None;
+        }
+        fence(Ordering::SeqCst);
+        let slot = (self.last_used % self.size) as usize;
+        let (id, len) = unsafe {
+            let e = self.used.add(4 + 8 * slot);
+            (core::ptr::read_volatile(e as *const u32), core::ptr::read_volatile(e.add(4) as *const u32))
+        };
+        self.last_used = self.last_used.wrapping_add(1);
+        Some((id as u16, len))
+    }
+}
@@ -0,0 +1,197 @@
+// kernel/src/arch/drivers/virtio_blk.rs
+//
+// 役割（feature virtio_blk）:
+// - legacy virtio-pci の virtio-blk（vendor 0x1AF4 / device 0x1001）から sector（512 byte）を読む最小ドライバ。
+// - kernel::fs（読み取り専用 fs）だけが使う。user task は FsOpen / FsRead で fs 越しに読む。
+//
+// 方針:
+// - feature は何も受けない（RO / SEG_MAX 等なし。読むだけなので書き込み可否も見ない）
+// - requestq（queue 0）に 1 要求ずつ出し、used ring が進むまで polling で待つ（同期読み出し。IRQ は使わない）
+//   * 要求は 3 つの descriptor の chain: header（type = IN / sector）→ data（device が書く）→ status（device が書く）
+//   * descriptor は 0 / 1 / 2 に固定（同時に出す要求は 1 つだけ）
+// - BAR0 は I/O でも memory でもよい（memory BAR は paging::map_mmio で uncached に map する。virtio::regs_from_bar0）
+// - queue の領域と buffer は PMM の予約 1 つにまとめる（KernelState::fs_mount から phys_mem を借りて
+//   virtio::reserve_dma で 1 回だけ予約し、外さない）。queue / req page / data の順に切り出す
+//   * req page: header（16 byte）と status（1 byte）
+//   * data: MAX_SECTORS_PER_READ 個の sector が入る連続した page。呼び出し側にはその slice を貸す
+// - 2 回目以降の init（scenario_suite で KernelState を作り直す）は確保済みの device をそのまま使う
+//
+// やらないこと:
+// - 書き込み / flush / discard、modern（virtio 1.0）/ MSI-X / 複数要求の同時発行
+
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use super::virtio::{self, Regs, Virtqueue, PAGE};
+use crate::arch::pci;
+use crate::mm::PhysicalMemoryManager;
+
+const LOG: crate::logging::Subsystem = crate::logging::Subsystem::Arch;
+
+const PCI_VENDOR_VIRTIO: u16 = 0x1AF4;
+const PCI_DEVICE_VIRTIO_BLK_LEGACY: u16 = 0x1001;
+
+const REQUEST_QUEUE: u16 = 0;
+
+pub const SECTOR_SIZE: usize = 512;
+
+/// 1 回の read_sectors で読める sector の上限（data は 2 page = 1 page を任意の sector 境界から読める大きさ）
+pub const MAX_SECTORS_PER_READ: usize = 2 * PAGE / SECTOR_SIZE;
+
+/// request header の type: 読み出し
+const VIRTIO_BLK_T_IN: u32 = 0;
+/// status byte: 成功
+const VIRTIO_BLK_S_OK: u8 = 0;
+
+/// req page の中の配置（header は type / reserved / sector の 16 byte）
+const REQ_HEADER_LEN: usize = 16;
+const REQ_STATUS_OFF: usize = 16;
+
+/// used ring が進むのを待つ回数の上限
+const REQ_SPIN_LIMIT: u32 = 10_000_000;
+
+/// 見つけた device の情報（fs の report 用）
+#[derive(Clone, Copy, Debug)]
+pub struct BlkDeviceInfo {
+    pub capacity_sectors: u64,
+    pub queue_size: u16,
+}
+
+/// 初期化済みの device（init が成功したときだけ Some）
+struct Device {
+    regs: Regs,
+    queue: Virtqueue,
+    info: BlkDeviceInfo,
+    req_phys: u64,
+    req_virt: u64,
+    data_phys: u64,
+    data_virt: u64,
+}
+
+static DEVICE: Mutex<Option<Device>> = Mutex::new(None);
+
+/// device を探して requestq を用意する（KernelState::fs_mount から。seal の前）
+pub fn init(phys_mem: &mut PhysicalMemoryManager) -> Option<BlkDeviceInfo> {
+    if let Some(d) = DEVICE.lock().as_ref() {
+        return Some(d.info);
+    }
+
+    let Some(pci_dev) = pci::find(PCI_VENDOR_VIRTIO, PCI_DEVICE_VIRTIO_BLK_LEGACY) else {
+        LOG.info("virtio_blk: device not found");
+        return None;
+    };
+    LOG.info("virtio_blk: device found");
+    LOG.info_hex("pci_bdf", pci_dev.addr.bdf() as u64);
+
+    let Some(regs) = virtio::regs_from_bar0(&pci_dev, phys_mem) else {
+        LOG.error("virtio_blk: BAR0 is not usable; skip");
+        return None;
+    };
+    pci_dev.enable_io_and_bus_master();
+
+    let Some(dev) = setup(regs, phys_mem) else {
+        virtio::write_status(regs, virtio::STATUS_FAILED);
+        return None;
+    };
+    let info = dev.info;
+    *DEVICE.lock() = Some(dev);
+
+    LOG.info("virtio_blk: ready");
+    regs.log(LOG);
+    LOG.info_u64("blk_capacity_sectors", info.capacity_sectors);
+    LOG.info_u64("virtio_queue_size", info.queue_size as u64);
+    Some(info)
+}
+
+/// sector から count 個を読み、読めたら f に渡す（count は 1..=MAX_SECTORS_PER_READ）
+/// - capacity を越える / device が無い / status が OK でなければ None
+pub fn read_sectors<R>(sector: u64, count: usize, f: impl FnOnce(&[u8]) -> R) -> Option<R> {
+    if count == 0 || count > MAX_SECTORS_PER_READ {
+        return None;
+    }
+    interrupts::without_interrupts(|| {
+        let mut guard = DEVICE.lock();
+        let d = guard.as_mut()?;
+        if sector.checked_add(count as u64)? > d.info.capacity_sectors {
+            return None;
+        }
+
+        let len = count * SECTOR_SIZE;
+        unsafe {
+            let req = d.req_virt as *mut u8;
+            core::ptr::write_volatile(req as *mut u32, VIRTIO_BLK_T_IN);
+            core::ptr::write_volatile(req.add(4) as *mut u32, 0);
+            core::ptr::write_volatile(req.add(8) as *mut u64, sector);
+            // device が書かなかったら失敗に見えるようにしておく
+            core::ptr::write_volatile(req.add(REQ_STATUS_OFF), 0xFF);
+        }
+        d.queue.set_desc_chained(0, d.req_phys, REQ_HEADER_LEN as u32, virtio::DESC_F_NEXT, 1);
+        d.queue.set_desc_chained(1, d.data_phys, len as u32, virtio::DESC_F_WRITE | virtio::DESC_F_NEXT, 2);
+        d.queue.set_desc(2, d.req_phys + REQ_STATUS_OFF as u64, 1, virtio::DESC_F_WRITE);
+        d.queue.offer(0);
+        virtio::notify(d.regs, REQUEST_QUEUE);
+
+        let mut spins = 0u32;
+        while d.queue.pop_used().is_none() {
+            spins += 1;
+            if spins >= REQ_SPIN_LIMIT {
+                LOG.error("virtio_blk: request timed out");
+                return None;
+            }
+            core::hint::spin_loop();
+        }
+
+        let status = unsafe { core::ptr::read_volatile((d.req_virt as *const u8).add(REQ_STATUS_OFF)) };
+        if status != VIRTIO_BLK_S_OK {
+            LOG.error("virtio_blk: request failed");
+            LOG.info_u64("status", status as u64);
+            LOG.info_u64("sector", sector);
+            return None;
+        }
+        let data = unsafe { core::slice::from_raw_parts(d.data_virt as *const u8, len) };
+        Some(f(data))
+    })
+}
+
+/// feature 交渉（何も受けない）→ capacity → requestq → DRIVER_OK
+fn setup(regs: Regs, phys_mem: &mut PhysicalMemoryManager) -> Option<Device> {
+    let features = virtio::begin_init(regs, 0);
+    LOG.info_hex("virtio_device_features", features as u64);
+
+    // config の先頭 8 byte = capacity（512 byte sector の数、little-endian）
+    let mut cap = [0u8; 8];
+    for (i, b) in cap.iter_mut().enumerate() {
+        *b = virtio::read_config_u8(regs, i as u16);
+    }
+    let capacity_sectors = u64::from_le_bytes(cap);
+
+    let qsz = virtio::queue_size(regs, REQUEST_QUEUE);
+    if qsz < 3 {
+        LOG.error("virtio_blk: requestq too small");
+        LOG.info_u64("virtio_queue_size", qsz as u64);
+        return None;
+    }
+    let queue_pages = virtio::queue_layout(qsz as usize).1.div_ceil(PAGE);
+    let data_pages = MAX_SECTORS_PER_READ * SECTOR_SIZE / PAGE;
+    let Some((phys, virt)) = virtio::reserve_dma(phys_mem, queue_pages + 1 + data_pages) else {
+        LOG.error("virtio_blk: no DMA region");
+        return None;
+    };
+    if phys / PAGE as u64 > u32::MAX as u64 {
+        LOG.error("virtio_blk: queue above the legacy PFN range");
+        return None;
+    }
+    virtio::set_queue_phys(regs, REQUEST_QUEUE, phys);
+    let queue = unsafe { Virtqueue::new(virt as *mut u8, qsz) };
+
+    let (req_phys, req_virt) = (phys + (queue_pages * PAGE) as u64, virt + (queue_pages * PAGE) as u64);
+    let (data_phys, data_virt) = (req_phys + PAGE as u64, req_virt + PAGE as u64);
+
+    virtio::write_status(
+        regs,
+        virtio::STATUS_ACKNOWLEDGE | virtio::STATUS_DRIVER | virtio::STATUS_DRIVER_OK,
+    );
+
+    let info = BlkDeviceInfo { capacity_sectors, queue_size: qsz };
+    Some(Device { regs, queue, info, req_phys, req_virt, data_phys, data_virt })
+}
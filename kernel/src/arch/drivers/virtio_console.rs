@@ -0,0 +1,243 @@
+// kernel/src/arch/drivers/virtio_console.rs
+//
+// 役割（feature virtio_console）:
+// - legacy virtio-pci の virtio console（vendor 0x1AF4 / device 0x1003）の port 0 に書くだけの最小ドライバ。
+// - logging の trace 出口（LogSink::trace_*）が使う。COM1（115200bps）より速く event trace を外へ出す用。
+//
+// 方針:
+// - device は arch::pci の一覧から探す（config space を直接は触らない）
+// - feature は何も受けない（MULTIPORT なし = port 0 だけ）。使う queue は transmitq（queue 1）だけ
+// - 送信は 1 page の buffer に貯め、改行か満杯で 1 descriptor にして notify し、used ring が進むまで polling で待つ
+// - register と virtqueue の扱いは drivers::virtio の共通部分を使う
+// - queue と buffer は .bss の静的領域。物理で連続していなければ使わない（init が失敗して COM1 のまま）
+// - BAR0 は I/O だけ（arch::init から起こすので PMM が無く、memory BAR を map_mmio できない）
+// - used ring が一定回数待っても進まなければ壊れたとみなし、以後は COM1 に戻す（write_* が false を返す）
+// - 書き込み中は割り込みを止める（tick の IRQ から record を出しても buffer を取り合わない）
+//
+// やらないこと:
+// - 受信（receiveq）/ 複数 port / modern（virtio 1.0）の PCI capability
+// - 割り込み駆動の送信完了（IRQ は使わない）
+
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use super::virtio::{self, Regs, Virtqueue, PAGE};
+use crate::arch::paging;
+use crate::arch::pci::{self, Bar};
+
+const LOG: crate::logging::Subsystem = crate::logging::Subsystem::Arch;
+
+const PCI_VENDOR_VIRTIO: u16 = 0x1AF4;
+const PCI_DEVICE_VIRTIO_CONSOLE_LEGACY: u16 = 0x1003;
+
+/// port 0 の transmitq
+const TX_QUEUE: u16 = 1;
+
+/// queue 用の静的領域（queue size 256 の legacy layout = 10KiB まで入る）
+const QUEUE_MEM_PAGES: usize = 4;
+/// used ring が進むのを待つ回数の上限
+const TX_SPIN_LIMIT: u32 = 10_000_000;
+
+#[repr(C, align(4096))]
+struct QueueMem([u8; QUEUE_MEM_PAGES * PAGE]);
+
+#[repr(C, align(4096))]
+struct TxBuf([u8; PAGE]);
+
+static mut QUEUE_MEM: QueueMem = QueueMem([0; QUEUE_MEM_PAGES * PAGE]);
+static mut TX_BUF: TxBuf = TxBuf([0; PAGE]);
+
+/// 初期化済みの device（init が成功したときだけ Some）
+struct Device {
+    regs: Regs,
+    queue_size: u16,
+    tx: Virtqueue,
+    tx_phys: u64,
+    /// TX_BUF に貯まっている byte 数
+    pending: usize,
+}
+
+static DEVICE: Mutex<Option<Device>> = Mutex::new(None);
+static READY: AtomicBool = AtomicBool::new(false);
+
+static BYTES_SENT: AtomicU64 = AtomicU64::new(0);
+static FLUSHES: AtomicU64 = AtomicU64::new(0);
+
+/// 送信の集計（dump 用）
+#[derive(Clone, Copy, Debug)]
+pub struct Stats {
+    pub ready: bool,
+    pub bytes: u64,
+    pub flushes: u64,
+}
+
+pub fn stats() -> Stats {
+    Stats {
+        ready: READY.load(Ordering::SeqCst),
+        bytes: BYTES_SENT.load(Ordering::SeqCst),
+        flushes: FLUSHES.load(Ordering::SeqCst),
+    }
+}
+
+/// trace の出口として使えるか
+pub fn is_ready() -> bool {
+    READY.load(Ordering::SeqCst)
+}
+
+/// device を探して transmitq を用意する（drivers::init から 1 回）
+pub fn init() {
+    let Some(pci_dev) = pci::find(PCI_VENDOR_VIRTIO, PCI_DEVICE_VIRTIO_CONSOLE_LEGACY) else {
+        LOG.info("virtio_console: device not found; trace stays on COM1");
+        return;
+    };
+    LOG.info("virtio_console: device found");
+    LOG.info_hex("pci_bdf", pci_dev.addr.bdf() as u64);
+
+    let Bar::Io { port: io_base } = pci_dev.bar(0) else {
+        LOG.error("virtio_console: BAR0 is not an I/O BAR; skip");
+        return;
+    };
+    pci_dev.enable_io_and_bus_master();
+
+    let regs = Regs::Io(io_base);
+    match setup_queue(regs) {
+        Some(d) => {
+            LOG.info("virtio_console: ready (trace goes to virtio console)");
+            regs.log(LOG);
+            LOG.info_u64("virtio_tx_queue_size", d.queue_size as u64);
+            *DEVICE.lock() = Some(d);
+            READY.store(true, Ordering::SeqCst);
+        }
+        None => virtio::write_status(regs, virtio::STATUS_FAILED),
+    }
+}
+
+/// s を送信 buffer に積む（使えないときは false。呼び出し側が COM1 に書く）
+pub fn write_str(s: &str) -> bool {
+    write_with(s, false)
+}
+
+/// s と改行を積んで送る
+pub fn write_line(s: &str) -> bool {
+    write_with(s, true)
+}
+
+fn write_with(s: &str, newline: bool) -> bool {
+    if !READY.load(Ordering::SeqCst) {
+        return false;
+    }
+    interrupts::without_interrupts(|| {
+        let mut guard = DEVICE.lock();
+        let Some(d) = guard.as_mut() else {
+            return false;
+        };
+        let ok = d.push(s.as_bytes()) && (!newline || (d.push(b"\n") && d.flush()));
+        if !ok {
+            // 送れなかった分は捨てる（以後は COM1）
+            READY.store(false, Ordering::SeqCst);
+            *guard = None;
+        }
+        ok
+    })
+}
+
+impl Device {
+    fn push(&mut self, mut bytes: &[u8]) -> bool {
+        while !bytes.is_empty() {
+            if self.pending == PAGE && !self.flush() {
+                return false;
+            }
+            let n = bytes.len().min(PAGE - self.pending);
+            unsafe {
+                let buf = core::ptr::addr_of_mut!(TX_BUF.0) as *mut u8;
+                core::ptr::copy_nonoverlapping(bytes.as_ptr(), buf.add(self.pending), n);
+            }
+            self.pending += n;
+            bytes = &bytes[n..];
+        }
+        true
+    }
+
+    /// 貯まった分を descriptor 0 で出し、device が使い終わるまで待つ
+    fn flush(&mut self) -> bool {
+        if self.pending == 0 {
+            return true;
+        }
+        self.tx.set_desc(0, self.tx_phys, self.pending as u32, 0);
+        self.tx.offer(0);
+        virtio::notify(self.regs, TX_QUEUE);
+
+        let mut spins = 0u32;
+        while self.tx.pop_used().is_none() {
+            spins += 1;
+            if spins >= TX_SPIN_LIMIT {
+                return false;
+            }
+            core::hint::spin_loop();
+        }
+        BYTES_SENT.fetch_add(self.pending as u64, Ordering::SeqCst);
+        FLUSHES.fetch_add(1, Ordering::SeqCst);
+        self.pending = 0;
+        true
+    }
+}
+
+/// legacy の split virtqueue を QUEUE_MEM に作り、DRIVER_OK まで進める
+fn setup_queue(regs: Regs) -> Option<Device> {
+    let features = virtio::begin_init(regs, 0);
+    LOG.info_hex("virtio_device_features", features as u64);
+
+    let qsz = virtio::queue_size(regs, TX_QUEUE) as usize;
+    if qsz == 0 {
+        LOG.error("virtio_console: transmitq not available");
+        return None;
+    }
+
+    let (_, total) = virtio::queue_layout(qsz);
+    if total > QUEUE_MEM_PAGES * PAGE {
+        LOG.error("virtio_console: queue too large for static area");
+        LOG.info_u64("virtio_tx_queue_size", qsz as u64);
+        return None;
+    }
+
+    let base = unsafe { core::ptr::addr_of_mut!(QUEUE_MEM.0) as *mut u8 };
+    unsafe { core::ptr::write_bytes(base, 0, QUEUE_MEM_PAGES * PAGE) };
+    let base_phys = contiguous_phys(base as u64, total.div_ceil(PAGE))?;
+    let tx_phys = contiguous_phys(unsafe { core::ptr::addr_of!(TX_BUF.0) } as u64, 1)?;
+    if base_phys / PAGE as u64 > u32::MAX as u64 {
+        LOG.error("virtio_console: queue above the legacy PFN range");
+        return None;
+    }
+
+    virtio::set_queue_phys(regs, TX_QUEUE, base_phys);
+    virtio::write_status(
+        regs,
+        virtio::STATUS_ACKNOWLEDGE | virtio::STATUS_DRIVER | virtio::STATUS_DRIVER_OK,
+    );
+
+    Some(Device {
+        regs,
+        queue_size: qsz as u16,
+        tx: unsafe { Virtqueue::new(base, qsz as u16) },
+        tx_phys,
+        pending: 0,
+    })
+}
+
+/// virt から pages 枚が物理でも連続しているなら先頭の物理アドレス
+fn contiguous_phys(virt: u64, pages: usize) -> Option<u64> {
+    let Some(first) = paging::kernel_virt_to_phys(virt) else {
+        LOG.error("virtio_console: buffer not mapped");
+        return None;
+    };
+    for i in 1..pages {
+        let off = (i * PAGE) as u64;
+        if paging::kernel_virt_to_phys(virt + off) != Some(first + off) {
+            LOG.error("virtio_console: buffer not physically contiguous");
+            return None;
+        }
+    }
+    Some(first)
+}
@@ -0,0 +1,302 @@
+// kernel/src/arch/drivers/virtio_net.rs
+//
+// 役割（feature virtio_net）:
+// - legacy virtio-pci の virtio-net（vendor 0x1AF4 / device 0x1000）で Ethernet frame を送受信する最小ドライバ。
+// - kernel::net（net service）だけが使う。user task へ frame を渡すのは service 側（IPC + shm）。
+//
+// 方針:
+// - feature は MAC（bit 5）だけ受ける（offload / MRG_RXBUF / CTRL_VQ なし = net header は 10 byte 固定）
+// - BAR0 は I/O でも memory でもよい（memory BAR は paging::map_mmio で uncached に map する。virtio::regs_from_bar0）
+// - queue の領域と buffer は PMM の予約 1 つにまとめる（KernelState::net_init から phys_mem を借りて
+//   virtio::reserve_dma で 1 回だけ予約し、外さない）
+//   * receiveq（queue 0）: RX_BUFFERS 個の 2KiB buffer を全部 device に渡しておき、使われたら読んでから渡し直す
+//   * transmitq（queue 1）: 1 page の buffer を 1 つ。送るたびに used ring が進むまで polling で待つ（同期送信）
+//   * フレームには physmap（physical_memory_offset）経由で触る
+// - IRQ は PCI の interrupt_line に登録する。handler は ISR を読んで（= device の割り込みを落とす）数えるだけ
+//   * frame の取り出しと配送は tick の中（kernel::net の net_poll）でだけ行う（event trace の順序を tick にそろえる）
+// - 受け取った buffer は used ring に残る（service が取りに来るまで device は次の空き buffer を使う）
+//
+// やらないこと:
+// - modern（virtio 1.0）/ MSI-X / multiqueue / checksum offload / TSO
+// - 送信の非同期化（TX の descriptor は 1 つだけ）
+
+use core::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use super::virtio::{self, Regs, Virtqueue, PAGE};
+use crate::arch::interrupts::register_irq_handler;
+use crate::arch::pci;
+use crate::mm::PhysicalMemoryManager;
+
+const LOG: crate::logging::Subsystem = crate::logging::Subsystem::Arch;
+
+const PCI_VENDOR_VIRTIO: u16 = 0x1AF4;
+const PCI_DEVICE_VIRTIO_NET_LEGACY: u16 = 0x1000;
+
+const RX_QUEUE: u16 = 0;
+const TX_QUEUE: u16 = 1;
+
+/// device config に MAC がある
+const VIRTIO_NET_F_MAC: u32 = 1 << 5;
+
+/// MRG_RXBUF なしの legacy net header（flags / gso_type / hdr_len / gso_size / csum_start / csum_offset）
+const NET_HDR_LEN: usize = 10;
+
+/// Ethernet frame の最大長（FCS なし）
+pub const MAX_FRAME_LEN: usize = 1514;
+
+/// device に渡しておく受信 buffer の数と大きさ（1 page に 2 つ）
+const RX_BUFFERS: usize = 16;
+const RX_BUF_LEN: usize = 2048;
+
+/// used ring が進むのを待つ回数の上限（送信）
+const TX_SPIN_LIMIT: u32 = 10_000_000;
+
+/// ISR status の bit0: queue に進みがあった
+const ISR_QUEUE: u8 = 1;
+
+/// 見つけた device の情報（net service の report 用）
+#[derive(Clone, Copy, Debug)]
+pub struct NetDeviceInfo {
+    pub mac: [u8; 6],
+    pub irq_line: Option<u8>,
+    pub rx_queue_size: u16,
+    pub tx_queue_size: u16,
+}
+
+/// 初期化済みの device（init が成功したときだけ Some）
+struct Device {
+    regs: Regs,
+    rx: Virtqueue,
+    tx: Virtqueue,
+    /// RX buffer 群の先頭（物理 / physmap 上の仮想）
+    rx_phys: u64,
+    rx_virt: u64,
+    tx_phys: u64,
+    tx_virt: u64,
+}
+
+static DEVICE: Mutex<Option<Device>> = Mutex::new(None);
+
+/// IRQ handler が使う（Mutex を取らずに ISR を読む）。Regs::to_raw の形で、0 = device なし
+static REGS: AtomicU64 = AtomicU64::new(0);
+/// 登録した IRQ（NO_IRQ = 登録していない）
+static IRQ_LINE: AtomicU8 = AtomicU8::new(NO_IRQ);
+const NO_IRQ: u8 = 0xFF;
+
+static IRQS: AtomicU64 = AtomicU64::new(0);
+static IRQS_QUEUE: AtomicU64 = AtomicU64::new(0);
+
+/// IRQ の集計（report 用。割り込みのタイミングに依存するので state hash には入れない）
+#[derive(Clone, Copy, Debug)]
+pub struct IrqStats {
+    pub irqs: u64,
+    pub queue_irqs: u64,
+}
+
+pub fn irq_stats() -> IrqStats {
+    IrqStats { irqs: IRQS.load(Ordering::Relaxed), queue_irqs: IRQS_QUEUE.load(Ordering::Relaxed) }
+}
+
+/// unmask する IRQ（allow_external_irqs 用。device が無い / IRQ を登録していなければ None）
+pub fn irq_line() -> Option<u8> {
+    let irq = IRQ_LINE.load(Ordering::SeqCst);
+    (irq != NO_IRQ).then_some(irq)
+}
+
+/// device を探して receiveq / transmitq を用意する（KernelState::net_init から 1 回。seal の前）
+pub fn init(phys_mem: &mut PhysicalMemoryManager) -> Option<NetDeviceInfo> {
+    let Some(pci_dev) = pci::find(PCI_VENDOR_VIRTIO, PCI_DEVICE_VIRTIO_NET_LEGACY) else {
+        LOG.info("virtio_net: device not found");
+        return None;
+    };
+    LOG.info("virtio_net: device found");
+    LOG.info_hex("pci_bdf", pci_dev.addr.bdf() as u64);
+
+    let Some(regs) = virtio::regs_from_bar0(&pci_dev, phys_mem) else {
+        LOG.error("virtio_net: BAR0 is not usable; skip");
+        return None;
+    };
+    pci_dev.enable_io_and_bus_master();
+
+    let Some((dev, mut info)) = setup(regs, phys_mem) else {
+        virtio::write_status(regs, virtio::STATUS_FAILED);
+        return None;
+    };
+    *DEVICE.lock() = Some(dev);
+    REGS.store(regs.to_raw(), Ordering::SeqCst);
+
+    // interrupt_line は firmware が書いた PIC の IRQ（0xFF / 16 以上は未配線）
+    let line = pci_dev.interrupt_line;
+    if pci_dev.interrupt_pin != 0 && line < 16 && register_irq_handler(line, net_irq) {
+        IRQ_LINE.store(line, Ordering::SeqCst);
+        info.irq_line = Some(line);
+    } else {
+        LOG.info("virtio_net: no usable IRQ; receive is polled from tick only");
+    }
+
+    LOG.info("virtio_net: ready");
+    regs.log(LOG);
+    LOG.info_hex("net_mac", mac_to_u64(info.mac));
+    LOG.info_u64("virtio_rx_queue_size", info.rx_queue_size as u64);
+    LOG.info_u64("virtio_tx_queue_size", info.tx_queue_size as u64);
+    LOG.info_u64("virtio_rx_buffers", RX_BUFFERS as u64);
+    Some(info)
+}
+
+/// MAC を u64 の下位 48bit に詰める（先頭 byte が上位）
+pub fn mac_to_u64(mac: [u8; 6]) -> u64 {
+    mac.iter().fold(0u64, |acc, &b| (acc << 8) | b as u64)
+}
+
+/// frame（Ethernet header から。FCS なし）を 1 つ送る。送り終わる（used ring が進む）まで待つ
+pub fn transmit(frame: &[u8]) -> bool {
+    if frame.is_empty() || frame.len() > MAX_FRAME_LEN {
+        return false;
+    }
+    interrupts::without_interrupts(|| {
+        let mut guard = DEVICE.lock();
+        let Some(d) = guard.as_mut() else {
+            return false;
+        };
+        unsafe {
+            let buf = d.tx_virt as *mut u8;
+            core::ptr::write_bytes(buf, 0, NET_HDR_LEN);
+            core::ptr::copy_nonoverlapping(frame.as_ptr(), buf.add(NET_HDR_LEN), frame.len());
+        }
+        d.tx.set_desc(0, d.tx_phys, (NET_HDR_LEN + frame.len()) as u32, 0);
+        d.tx.offer(0);
+        virtio::notify(d.regs, TX_QUEUE);
+
+        let mut spins = 0u32;
+        while d.tx.pop_used().is_none() {
+            spins += 1;
+            if spins >= TX_SPIN_LIMIT {
+                LOG.error("virtio_net: transmit timed out");
+                return false;
+            }
+            core::hint::spin_loop();
+        }
+        true
+    })
+}
+
+/// 受け取り済みで未読の frame があるか
+pub fn rx_pending() -> bool {
+    interrupts::without_interrupts(|| DEVICE.lock().as_ref().is_some_and(|d| d.rx.has_used()))
+}
+
+/// 受け取った frame を 1 つ f に渡し、buffer を device に渡し直す（無ければ None）
+/// - f には net header を除いた frame を渡す（長さが合わない buffer は空で渡す）
+pub fn receive_one<R>(f: impl FnOnce(&[u8]) -> R) -> Option<R> {
+    interrupts::without_interrupts(|| {
+        let mut guard = DEVICE.lock();
+        let d = guard.as_mut()?;
+        let (id, len) = d.rx.pop_used()?;
+        let id = id as usize % RX_BUFFERS;
+
+        let len = len as usize;
+        let frame: &[u8] = if (NET_HDR_LEN..=RX_BUF_LEN).contains(&len) {
+            unsafe {
+                let buf = (d.rx_virt + (id * RX_BUF_LEN) as u64) as *const u8;
+                core::slice::from_raw_parts(buf.add(NET_HDR_LEN), len - NET_HDR_LEN)
+            }
+        } else {
+            &[]
+        };
+        let r = f(frame);
+
+        d.rx.offer(id as u16);
+        virtio::notify(d.regs, RX_QUEUE);
+        Some(r)
+    })
+}
+
+fn net_irq(_irq: u8) {
+    let Some(regs) = Regs::from_raw(REGS.load(Ordering::SeqCst)) else {
+        return;
+    };
+    // 読むと device の INTx が落ちる（共有 IRQ なら他の device の分は 0 が返る）
+    let isr = virtio::read_isr(regs);
+    IRQS.fetch_add(1, Ordering::Relaxed);
+    if isr & ISR_QUEUE != 0 {
+        IRQS_QUEUE.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// feature 交渉 → MAC → 2 つの queue → RX buffer を渡して DRIVER_OK
+fn setup(regs: Regs, phys_mem: &mut PhysicalMemoryManager) -> Option<(Device, NetDeviceInfo)> {
+    let features = virtio::begin_init(regs, VIRTIO_NET_F_MAC);
+    LOG.info_hex("virtio_device_features", features as u64);
+    if features & VIRTIO_NET_F_MAC == 0 {
+        LOG.error("virtio_net: device has no MAC");
+        return None;
+    }
+
+    let mut mac = [0u8; 6];
+    for (i, b) in mac.iter_mut().enumerate() {
+        *b = virtio::read_config_u8(regs, i as u16);
+    }
+
+    let rx_qsz = queue_size(regs, RX_QUEUE)?;
+    let tx_qsz = queue_size(regs, TX_QUEUE)?;
+    if (rx_qsz as usize) < RX_BUFFERS {
+        LOG.error("virtio_net: receiveq too small");
+        LOG.info_u64("virtio_rx_queue_size", rx_qsz as u64);
+        return None;
+    }
+
+    // receiveq / transmitq / RX buffer 群 / TX buffer の順に 1 つの予約から切り出す（予約表の行を 1 つだけ使う）
+    let rx_q_pages = virtio::queue_layout(rx_qsz as usize).1.div_ceil(PAGE);
+    let tx_q_pages = virtio::queue_layout(tx_qsz as usize).1.div_ceil(PAGE);
+    let rx_pages = RX_BUFFERS * RX_BUF_LEN / PAGE;
+    let Some((phys, virt)) = virtio::reserve_dma(phys_mem, rx_q_pages + tx_q_pages + rx_pages + 1) else {
+        LOG.error("virtio_net: no DMA region");
+        return None;
+    };
+    let page_at = |n: usize| (phys + (n * PAGE) as u64, virt + (n * PAGE) as u64);
+    let (tx_q_phys, _) = page_at(rx_q_pages);
+    if tx_q_phys / PAGE as u64 > u32::MAX as u64 {
+        LOG.error("virtio_net: queue above the legacy PFN range");
+        return None;
+    }
+    let rx = setup_queue(regs, RX_QUEUE, rx_qsz, page_at(0));
+    let tx = setup_queue(regs, TX_QUEUE, tx_qsz, page_at(rx_q_pages));
+    let (rx_phys, rx_virt) = page_at(rx_q_pages + tx_q_pages);
+    let (tx_phys, tx_virt) = page_at(rx_q_pages + tx_q_pages + rx_pages);
+
+    let mut dev = Device { regs, rx, tx, rx_phys, rx_virt, tx_phys, tx_virt };
+    for i in 0..RX_BUFFERS {
+        let phys = dev.rx_phys + (i * RX_BUF_LEN) as u64;
+        dev.rx.set_desc(i as u16, phys, RX_BUF_LEN as u32, virtio::DESC_F_WRITE);
+        dev.rx.offer(i as u16);
+    }
+
+    virtio::write_status(
+        regs,
+        virtio::STATUS_ACKNOWLEDGE | virtio::STATUS_DRIVER | virtio::STATUS_DRIVER_OK,
+    );
+    virtio::notify(regs, RX_QUEUE);
+
+    let info = NetDeviceInfo { mac, irq_line: None, rx_queue_size: rx_qsz, tx_queue_size: tx_qsz };
+    Some((dev, info))
+}
+
+/// queue の大きさを読む（0 なら無い queue）
+fn queue_size(regs: Regs, queue: u16) -> Option<u16> {
+    let qsz = virtio::queue_size(regs, queue);
+    if qsz == 0 {
+        LOG.error("virtio_net: queue not available");
+        LOG.info_u64("virtio_queue", queue as u64);
+        return None;
+    }
+    Some(qsz)
+}
+
+/// 予約から切り出した queue の領域（(物理, physmap 上の仮想)。0 で埋めてある）を device に教える
+fn setup_queue(regs: Regs, queue: u16, qsz: u16, (phys, virt): (u64, u64)) -> Virtqueue {
+    virtio::set_queue_phys(regs, queue, phys);
+    unsafe { Virtqueue::new(virt as *mut u8, qsz) }
+}
@@ -6,11 +6,14 @@
 //
 // やること:
 // - init_high_alias(): high-alias で参照できる GDT/TSS を作成し GDTR/TR を更新
-// - #PF / #DF を IST で受けられるように TSS.ist を設定
+// - #PF / #DF / NMI / #MC を IST で受けられるように TSS.ist を設定
+//   * NMI / #MC はどこにでも割り込む（壊れた stack の上でも来る）ので、それぞれ専用の IST にする
 // - ring3 MVP 用に user code/data セグメントを追加する
 //
+// - feature smp: AP ごとに GDT/TSS/IST を別に持つ（init_ap。selector の並びは BSP と同じ）
+//
 // やらないこと:
-// - per-cpu 構造（単一CPU前提）
+// - AP の GDT/TSS 以外の per-cpu 構造（RSP0 の差し替え set_rsp0 は BSP の TSS だけ）
 //
 // 設計方針:
 // - GDT/TSS は “ロード後に動かない” 静的領域へ固定配置
@@ -30,13 +33,19 @@ use x86_64::structures::gdt::{Descriptor, GlobalDescriptorTable, SegmentSelector
 use x86_64::structures::tss::TaskStateSegment;
 use x86_64::VirtAddr;
 
-use crate::{arch::virt_layout, logging};
+use crate::arch::virt_layout;
+
+const LOG: crate::logging::Subsystem = crate::logging::Subsystem::Arch;
 
 pub const DOUBLE_FAULT_IST_INDEX: u16 = 0; // IST1
 pub const PAGE_FAULT_IST_INDEX: u16 = 1;   // IST2
+pub const NMI_IST_INDEX: u16 = 2;          // IST3
+pub const MACHINE_CHECK_IST_INDEX: u16 = 3; // IST4
 
 const RSP0_STACK_SIZE: usize = 4096 * 8;
 const IST_STACK_SIZE: usize = 4096 * 8;
+/// NMI / #MC の handler は snapshot と emergency 出力だけなので小さくてよい
+const HW_IST_STACK_SIZE: usize = 4096 * 4;
 
 static INIT_DONE: AtomicBool = AtomicBool::new(false);
 
@@ -68,6 +77,8 @@ impl<const N: usize> AlignedStack<N> {
 static mut RSP0_STACK: AlignedStack<RSP0_STACK_SIZE> = AlignedStack { buf: [0; RSP0_STACK_SIZE] };
 static mut DF_IST_STACK: AlignedStack<IST_STACK_SIZE> = AlignedStack { buf: [0; IST_STACK_SIZE] };
 static mut PF_IST_STACK: AlignedStack<IST_STACK_SIZE> = AlignedStack { buf: [0; IST_STACK_SIZE] };
+static mut NMI_IST_STACK: AlignedStack<HW_IST_STACK_SIZE> = AlignedStack { buf: [0; HW_IST_STACK_SIZE] };
+static mut MC_IST_STACK: AlignedStack<HW_IST_STACK_SIZE> = AlignedStack { buf: [0; HW_IST_STACK_SIZE] };
 
 #[inline(always)]
 fn high_alias_u64(low: u64) -> u64 {
@@ -91,14 +102,20 @@ pub fn init_high_alias() {
             let rsp0_low = VirtAddr::from_ptr(RSP0_STACK.top_ptr()).as_u64();
             let df_ist_low = VirtAddr::from_ptr(DF_IST_STACK.top_ptr()).as_u64();
             let pf_ist_low = VirtAddr::from_ptr(PF_IST_STACK.top_ptr()).as_u64();
+            let nmi_ist_low = VirtAddr::from_ptr(NMI_IST_STACK.top_ptr()).as_u64();
+            let mc_ist_low = VirtAddr::from_ptr(MC_IST_STACK.top_ptr()).as_u64();
 
             let rsp0_high = VirtAddr::new(align_down_16(high_alias_u64(rsp0_low)));
             let df_ist_high = VirtAddr::new(align_down_16(high_alias_u64(df_ist_low)));
             let pf_ist_high = VirtAddr::new(align_down_16(high_alias_u64(pf_ist_low)));
+            let nmi_ist_high = VirtAddr::new(align_down_16(high_alias_u64(nmi_ist_low)));
+            let mc_ist_high = VirtAddr::new(align_down_16(high_alias_u64(mc_ist_low)));
 
             tss.privilege_stack_table[0] = rsp0_high;
             tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = df_ist_high;
             tss.interrupt_stack_table[PAGE_FAULT_IST_INDEX as usize] = pf_ist_high;
+            tss.interrupt_stack_table[NMI_IST_INDEX as usize] = nmi_ist_high;
+            tss.interrupt_stack_table[MACHINE_CHECK_IST_INDEX as usize] = mc_ist_high;
 
             TSS.write(tss);
 
@@ -114,8 +131,9 @@ pub fn init_high_alias() {
             let data_sel = gdt.append(Descriptor::kernel_data_segment());
 
             // ★ring3 MVP: user segments
-            let user_code_sel = gdt.append(Descriptor::user_code_segment());
+            // - data → code の順（sysret は STAR の base + 8 を SS、base + 16 を CS にする。arch::syscall_msr）
             let user_data_sel = gdt.append(Descriptor::user_data_segment());
+            let user_code_sel = gdt.append(Descriptor::user_code_segment());
 
             let tss_sel = gdt.append(Descriptor::tss_segment(tss_high_ref));
 
@@ -144,28 +162,43 @@ pub fn init_high_alias() {
             load_tss(sel.tss);
 
             // 5) log
-            logging::info("arch::gdt::init_high_alias: GDT/TSS loaded");
-            logging::info_u64("tss_low", tss_low_ptr_u64);
-            logging::info_u64("tss_high", tss_high_ptr_u64);
-            logging::info_u64("tss_high_pml4", virt_layout::pml4_index(tss_high_ptr_u64) as u64);
-
-            logging::info_u64("rsp0_low", rsp0_low);
-            logging::info_u64("rsp0_high", rsp0_high.as_u64());
-            logging::info_u64("rsp0_high_pml4", virt_layout::pml4_index(rsp0_high.as_u64()) as u64);
-
-            logging::info_u64("df_ist_index", DOUBLE_FAULT_IST_INDEX as u64);
-            logging::info_u64("df_ist_low", df_ist_low);
-            logging::info_u64("df_ist_high", df_ist_high.as_u64());
-            logging::info_u64("df_ist_high_pml4", virt_layout::pml4_index(df_ist_high.as_u64()) as u64);
-
-            logging::info_u64("pf_ist_index", PAGE_FAULT_IST_INDEX as u64);
-            logging::info_u64("pf_ist_low", pf_ist_low);
-            logging::info_u64("pf_ist_high", pf_ist_high.as_u64());
-            logging::info_u64("pf_ist_high_pml4", virt_layout::pml4_index(pf_ist_high.as_u64()) as u64);
+            LOG.info("arch::gdt::init_high_alias: GDT/TSS loaded");
+            LOG.info_u64("tss_low", tss_low_ptr_u64);
+            LOG.info_u64("tss_high", tss_high_ptr_u64);
+            LOG.info_u64("tss_high_pml4", virt_layout::pml4_index(tss_high_ptr_u64) as u64);
+
+            LOG.info_u64("rsp0_low", rsp0_low);
+            LOG.info_u64("rsp0_high", rsp0_high.as_u64());
+            LOG.info_u64("rsp0_high_pml4", virt_layout::pml4_index(rsp0_high.as_u64()) as u64);
+
+            LOG.info_u64("df_ist_index", DOUBLE_FAULT_IST_INDEX as u64);
+            LOG.info_u64("df_ist_low", df_ist_low);
+            LOG.info_u64("df_ist_high", df_ist_high.as_u64());
+            LOG.info_u64("df_ist_high_pml4", virt_layout::pml4_index(df_ist_high.as_u64()) as u64);
+
+            LOG.info_u64("pf_ist_index", PAGE_FAULT_IST_INDEX as u64);
+            LOG.info_u64("pf_ist_low", pf_ist_low);
+            LOG.info_u64("pf_ist_high", pf_ist_high.as_u64());
+            LOG.info_u64("pf_ist_high_pml4", virt_layout::pml4_index(pf_ist_high.as_u64()) as u64);
+
+            LOG.info_u64("nmi_ist_index", NMI_IST_INDEX as u64);
+            LOG.info_u64("nmi_ist_high", nmi_ist_high.as_u64());
+            LOG.info_u64("mc_ist_index", MACHINE_CHECK_IST_INDEX as u64);
+            LOG.info_u64("mc_ist_high", mc_ist_high.as_u64());
         }
     });
 }
 
+#[inline(always)]
+pub fn kernel_code_selector() -> SegmentSelector {
+    unsafe { SELECTORS.assume_init_ref().code }
+}
+
+#[inline(always)]
+pub fn kernel_data_selector() -> SegmentSelector {
+    unsafe { SELECTORS.assume_init_ref().data }
+}
+
 #[inline(always)]
 pub fn user_code_selector() -> SegmentSelector {
     unsafe { SELECTORS.assume_init_ref().user_code }
@@ -175,3 +208,91 @@ pub fn user_code_selector() -> SegmentSelector {
 pub fn user_data_selector() -> SegmentSelector {
     unsafe { SELECTORS.assume_init_ref().user_data }
 }
+
+/// TSS.RSP0（ring3 → ring0 で使う stack の上端）を差し替える（arch::context の stack 切替から）
+/// - top は high-alias の仮想アドレス（init_high_alias と同じ）
+/// - TSS は high-alias 経由で書く（user root に low-half が無くても書ける）
+pub fn set_rsp0(top: u64) {
+    if !INIT_DONE.load(Ordering::SeqCst) {
+        return;
+    }
+    unsafe {
+        let tss_high_ptr = high_alias_u64(TSS.as_ptr() as u64) as *mut TaskStateSegment;
+        (*tss_high_ptr).privilege_stack_table[0] = VirtAddr::new(align_down_16(top));
+    }
+}
+
+// -----------------------------------------------------------------------------
+// AP（feature smp。arch::smp の AP entry から 1 回ずつ呼ばれる）
+// -----------------------------------------------------------------------------
+
+#[cfg(feature = "smp")]
+const AP_SLOTS: usize = crate::arch::smp::MAX_CPUS - 1;
+
+/// AP は park するだけなので RSP0 / IST とも小さくてよい
+#[cfg(feature = "smp")]
+const AP_STACK_SIZE: usize = 4096 * 2;
+
+#[cfg(feature = "smp")]
+struct ApStacks {
+    rsp0: AlignedStack<AP_STACK_SIZE>,
+    ist: [AlignedStack<AP_STACK_SIZE>; 4],
+}
+
+#[cfg(feature = "smp")]
+static mut AP_GDT: [MaybeUninit<GlobalDescriptorTable>; AP_SLOTS] = [const { MaybeUninit::uninit() }; AP_SLOTS];
+#[cfg(feature = "smp")]
+static mut AP_TSS: [MaybeUninit<TaskStateSegment>; AP_SLOTS] = [const { MaybeUninit::uninit() }; AP_SLOTS];
+#[cfg(feature = "smp")]
+static mut AP_STACKS: [ApStacks; AP_SLOTS] = [const {
+    ApStacks {
+        rsp0: AlignedStack { buf: [0; AP_STACK_SIZE] },
+        ist: [const { AlignedStack { buf: [0; AP_STACK_SIZE] } }; 4],
+    }
+}; AP_SLOTS];
+
+/// AP（cpu = 1..MAX_CPUS）の GDT/TSS を作ってこの CPU にロードする
+/// - BSP の init_high_alias が済んでいること（selector の値は BSP と同じになる）
+/// - AP はログを出さない（serial の取り合いを避ける）ので、失敗は false で返すだけ
+#[cfg(feature = "smp")]
+pub fn init_ap(cpu: usize) -> bool {
+    if !INIT_DONE.load(Ordering::SeqCst) || cpu == 0 || cpu > AP_SLOTS {
+        return false;
+    }
+    let slot = cpu - 1;
+
+    unsafe {
+        let stacks = &AP_STACKS[slot];
+        let top = |p: *const u8| VirtAddr::new(align_down_16(high_alias_u64(VirtAddr::from_ptr(p).as_u64())));
+
+        let mut tss = TaskStateSegment::new();
+        tss.privilege_stack_table[0] = top(stacks.rsp0.top_ptr());
+        for (i, ist) in stacks.ist.iter().enumerate() {
+            tss.interrupt_stack_table[i] = top(ist.top_ptr());
+        }
+        AP_TSS[slot].write(tss);
+
+        let tss_high_ref: &'static TaskStateSegment =
+            &*(high_alias_u64(AP_TSS[slot].as_ptr() as u64) as *const TaskStateSegment);
+
+        // BSP と同じ順（code, data, user data, user code, tss）
+        let mut gdt = GlobalDescriptorTable::new();
+        let code_sel = gdt.append(Descriptor::kernel_code_segment());
+        let data_sel = gdt.append(Descriptor::kernel_data_segment());
+        gdt.append(Descriptor::user_data_segment());
+        gdt.append(Descriptor::user_code_segment());
+        let tss_sel = gdt.append(Descriptor::tss_segment(tss_high_ref));
+        AP_GDT[slot].write(gdt);
+
+        let gdt_high_ref: &'static GlobalDescriptorTable =
+            &*(high_alias_u64(AP_GDT[slot].as_ptr() as u64) as *const GlobalDescriptorTable);
+        gdt_high_ref.load();
+
+        CS::set_reg(code_sel);
+        DS::set_reg(data_sel);
+        ES::set_reg(data_sel);
+        SS::set_reg(data_sel);
+        load_tss(tss_sel);
+    }
+    true
+}
@@ -0,0 +1,175 @@
+// kernel/src/arch/hwfault.rs
+//
+// 役割:
+// - NMI / #MC（ハードウェア由来の事象）の最小 CPU 状態を snapshot し、「fatal hardware event」flag を latch する。
+// - dump_events / verdict が latch を読んで、kernel のロジックの bug と区別できるようにする。
+//
+// 方針（NMI / #MC はどこにでも割り込む。lock を持った区間の途中でも来る）:
+// - 記録先は固定長の AtomicU64 の表（static）。lock / heap / KernelState / logging には触らない
+// - IDT を high-alias にした後は表も high-alias 側から書く（user CR3 中は low 側が map されていない）
+// - 書く順は snapshot → 回数 → latch bit（latch を見た側が snapshot を読めるように Release で立てる）
+// - snapshot は最後の 1 件だけ（回数は種類ごとに数える）
+// - MCG_STATUS は CPUID で MCE / MCA があるときだけ読む（無い CPU で rdmsr すると #GP）
+//
+// やらないこと:
+// - MCi_STATUS（bank ごと）の読み出しと解析
+// - NMI の発生源の特定（watchdog / IOCHK / SERR 等の区別）
+// - latch の解除（run の間は立ったまま）
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use x86_64::registers::model_specific::Msr;
+use x86_64::structures::idt::InterruptStackFrame;
+
+use super::interrupts;
+
+/// latch bit
+pub const HW_EVENT_NMI: u64 = 1 << 0;
+pub const HW_EVENT_MACHINE_CHECK: u64 = 1 << 1;
+
+/// IA32_MCG_STATUS
+const MSR_MCG_STATUS: u32 = 0x17A;
+/// MCG_STATUS の bit
+const MCG_STATUS_RIPV: u64 = 1 << 0;
+const MCG_STATUS_MCIP: u64 = 1 << 2;
+
+/// CPUID.01h:EDX の MCE / MCA bit
+const CPUID_EDX_MCE: u32 = 1 << 7;
+const CPUID_EDX_MCA: u32 = 1 << 14;
+
+/// ハードウェア事象の種類
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum HwEventKind {
+    Nmi,
+    MachineCheck,
+}
+
+impl HwEventKind {
+    pub const fn bit(self) -> u64 {
+        match self {
+            HwEventKind::Nmi => HW_EVENT_NMI,
+            HwEventKind::MachineCheck => HW_EVENT_MACHINE_CHECK,
+        }
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            HwEventKind::Nmi => "NMI",
+            HwEventKind::MachineCheck => "MC",
+        }
+    }
+}
+
+// ---- 記録先（word の並び） ----
+const W_LATCHED: usize = 0;
+const W_NMI_COUNT: usize = 1;
+const W_MC_COUNT: usize = 2;
+const W_LAST_KIND: usize = 3;
+const W_RIP: usize = 4;
+const W_RSP: usize = 5;
+const W_CS: usize = 6;
+const W_RFLAGS: usize = 7;
+const W_CR3: usize = 8;
+const W_MCG_STATUS: usize = 9;
+const HW_EVENT_WORDS: usize = 10;
+
+static HW_EVENT_BUF: [AtomicU64; HW_EVENT_WORDS] = [const { AtomicU64::new(0) }; HW_EVENT_WORDS];
+
+/// 表の 1 word（high-alias の IDT を入れた後は high-alias 側を指す）
+#[inline(always)]
+fn slot(w: usize) -> &'static AtomicU64 {
+    interrupts::handler_static(&HW_EVENT_BUF[w])
+}
+
+/// latch した事象の読み出し結果
+#[derive(Clone, Copy)]
+pub struct HwEventReport {
+    /// HW_EVENT_* の OR
+    pub latched: u64,
+    pub nmi_count: u64,
+    pub mc_count: u64,
+    /// 最後の事象（snapshot の持ち主）
+    pub last_kind: HwEventKind,
+    pub rip: u64,
+    pub rsp: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub cr3: u64,
+    /// MCA が無い / NMI のときは 0
+    pub mcg_status: u64,
+}
+
+/// 例外 frame と CR3 を snapshot し、回数を数えて latch する（NMI / #MC の handler から呼ぶ）
+pub fn record(kind: HwEventKind, stack_frame: &InterruptStackFrame, mcg_status: u64) {
+    let cr3: u64;
+    unsafe {
+        core::arch::asm!("mov {}, cr3", out(reg) cr3, options(nomem, nostack, preserves_flags));
+    }
+
+    slot(W_LAST_KIND).store(kind.bit(), Ordering::Relaxed);
+    slot(W_RIP).store(stack_frame.instruction_pointer.as_u64(), Ordering::Relaxed);
+    slot(W_RSP).store(stack_frame.stack_pointer.as_u64(), Ordering::Relaxed);
+    slot(W_CS).store(stack_frame.code_segment.0 as u64, Ordering::Relaxed);
+    slot(W_RFLAGS).store(stack_frame.cpu_flags.bits(), Ordering::Relaxed);
+    slot(W_CR3).store(cr3, Ordering::Relaxed);
+    slot(W_MCG_STATUS).store(mcg_status, Ordering::Relaxed);
+
+    let count = match kind {
+        HwEventKind::Nmi => W_NMI_COUNT,
+        HwEventKind::MachineCheck => W_MC_COUNT,
+    };
+    slot(count).fetch_add(1, Ordering::Relaxed);
+    slot(W_LATCHED).fetch_or(kind.bit(), Ordering::Release);
+}
+
+/// latch が立っていれば最後の snapshot と回数を返す
+pub fn latched() -> Option<HwEventReport> {
+    let latched = slot(W_LATCHED).load(Ordering::Acquire);
+    if latched == 0 {
+        return None;
+    }
+    let last_kind = if slot(W_LAST_KIND).load(Ordering::Relaxed) == HW_EVENT_MACHINE_CHECK {
+        HwEventKind::MachineCheck
+    } else {
+        HwEventKind::Nmi
+    };
+    Some(HwEventReport {
+        latched,
+        nmi_count: slot(W_NMI_COUNT).load(Ordering::Relaxed),
+        mc_count: slot(W_MC_COUNT).load(Ordering::Relaxed),
+        last_kind,
+        rip: slot(W_RIP).load(Ordering::Relaxed),
+        rsp: slot(W_RSP).load(Ordering::Relaxed),
+        cs: slot(W_CS).load(Ordering::Relaxed),
+        rflags: slot(W_RFLAGS).load(Ordering::Relaxed),
+        cr3: slot(W_CR3).load(Ordering::Relaxed),
+        mcg_status: slot(W_MCG_STATUS).load(Ordering::Relaxed),
+    })
+}
+
+/// CPU が MCE と MCA を持つか（MCG_STATUS を読んでよいか）
+fn has_mca() -> bool {
+    let edx = unsafe { core::arch::x86_64::__cpuid(1).edx };
+    edx & (CPUID_EDX_MCE | CPUID_EDX_MCA) == (CPUID_EDX_MCE | CPUID_EDX_MCA)
+}
+
+/// MCG_STATUS（MCA が無ければ None）
+pub fn read_mcg_status() -> Option<u64> {
+    if !has_mca() {
+        return None;
+    }
+    Some(unsafe { Msr::new(MSR_MCG_STATUS).read() })
+}
+
+/// #MC から戻ってよいか（RIPV: 中断した rip から再開できる）
+pub fn mc_restartable(mcg_status: u64) -> bool {
+    mcg_status & MCG_STATUS_RIPV != 0
+}
+
+/// #MC から戻る前に MCIP を落とす（立ったまま次の #MC が来ると shutdown になる）
+pub fn clear_mc_in_progress(mcg_status: u64) {
+    if !has_mca() {
+        return;
+    }
+    unsafe { Msr::new(MSR_MCG_STATUS).write(mcg_status & !MCG_STATUS_MCIP) };
+}
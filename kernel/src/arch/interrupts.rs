@@ -4,6 +4,9 @@
 // - IDT(Interrupt Descriptor Table) を初期化・再ロードする。
 // - high-alias 移行後も例外が確実に handler に届く状態を作る。
 // - ring3 MVP: int 0x80 を追加して user -> kernel の入口にする。
+// - 外部 IRQ（8259 PIC, vector 0x20..0x2F）を IRQ ごとの handler 表に振り分ける（register_irq_handler）。
+// - timer IRQ（PIT, IRQ0）から KernelState::tick() を駆動する（arch::timer）。
+// - keyboard IRQ（PS/2, IRQ1）の scancode を KernelState の入力キューに渡す（kernel/input.rs）。
 //
 // 設計方針:
 // - 例外ハンドラは lock を取らない
@@ -18,6 +21,45 @@
 // - “RAX を ring3 に返す” は x86-interrupt だと保証しづらい（レジスタを触れない）のでやらない。
 // - 代わりに kernel が user stack (ret_slot) に直接書く。
 // - iretq 前は必ず user_root に戻す（CR3 が kernel のままだと命令フェッチで #PF する）。
+// - iretq 前は TrapFrame（CS/SS/RFLAGS/RIP/RSP）を arch::trapframe で検査し、
+//   違反なら ring3 に戻らず task を kill して halt する（壊れたフレームで黙って特権昇格しない）。
+//
+// ★IRQ dispatch:
+// - vector 0x20..0x2F の 16 個の入口は全部 irq_dispatch(irq) に落ちる
+// - handler は register_irq_handler で IRQ ごとに 1 つ（fn(irq)）。EOI は dispatch 側が handler の後に送る
+// - IRQ7 / IRQ15 は先に spurious 判定（arch::pic）。spurious は数えるだけで handler も EOI も呼ばない
+// - handler の無い IRQ は数えて EOI だけ送る
+// - handler の fn pointer は low 側のアドレス。IDT を high-alias にした後は high-alias 側を呼ぶ
+//   （user CR3 中は low 側が map されていない）
+//
+// ★timer IRQ:
+// - handler は state_ref::with_kernel_state 経由でだけ KernelState に触る（seal 前は tick しない）
+// - IRQ が回っている間、main 側は hlt で待つだけ（KernelState を並行して触らない）
+// - ring3 系デモでは timer を起動しない（int80 が tick を駆動する）
+//
+// ★keyboard IRQ:
+// - handler は常に IDT に置くが、IRQ1 を unmask するのは feature ps2_keyboard のときだけ（allow_external_irqs）
+// - status port（0x64）の output buffer full を見てから data port（0x60）を 1 byte 読む
+// - KernelState へは timer と同じく with_kernel_state 経由（seal 前は読んだ byte を捨てる）
+//
+// ★その他の例外（#DE / #DB / #BP / #UD / #NM / #TS / #NP / #SS / #AC / #MC ...）:
+// - 全 vector に handler を置く（未登録の vector は #GP → #DF → triple fault で黙って落ちる）
+// - 出力は #GP / #DF と同じ emergency 形式（"[EXC] #UD rip=... rsp=..."）
+// - ring3_tasks で ring3 から来たものは user の fault: current task を UserException で kill して他の task へ
+// - ring0 の #DB / #BP は trap なので報告して戻る。それ以外は fail-stop
+// ★NMI / #MC（ハードウェア由来。arch::hwfault）:
+// - どちらも専用の IST（gdt::NMI_IST_INDEX / MACHINE_CHECK_IST_INDEX）で受ける（high-alias の IDT から）
+// - emergency 出力と hwfault の snapshot / latch だけを行う（lock / KernelState / logging には触らない）
+// - NMI は記録して戻る。#MC は MCG_STATUS.RIPV が立っていれば MCIP を落として戻り、それ以外は fail-stop
+// - latch は dump_events と verdict が読む（kernel の bug と区別する）
+// ★kernel stack overflow（arch::stack_guard）:
+// - ring0 の #PF で CR2 が kernel stack の guard page なら、generic な #PF ではなく "kernel stack overflow" として止まる
+// - rsp が guard に入ると #PF を積めずに #DF になるので、#DF でも CR2 / rsp を照合する（#DF は IST1 で受ける）
+// - reload_idt_high_alias の後に self-test: 全 vector の handler が high-alias を指しているかをログに出す
+//
+// ★ring3_tasks:
+// - int 0x80 は register ABI の入口（arch/syscall_abi.rs）。x86-interrupt の int80_handler は使わない
+// - IST を使わず TSS.RSP0（task の kernel stack）に入る。実行待ちの間の timer IRQ もその stack の上で受ける
 //
 // 実装メモ:
 // - ring3_* デモは paging 側に (user_root, kernel_root) を登録し、ここから参照する。
@@ -25,7 +67,7 @@
 #![allow(dead_code)]
 
 use core::mem;
-use core::sync::atomic::{AtomicU64, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 
 use spin::Mutex;
 use x86_64::VirtAddr;
@@ -42,20 +84,48 @@ use x86_64::structures::idt::{
 use x86_64::PrivilegeLevel;
 
 use crate::{
-    arch::{gdt, paging, virt_layout},
+    arch::{gdt, hwfault, paging, pic, stack_guard, timer, trapframe, virt_layout},
     logging,
 };
+#[cfg(feature = "ring3_tasks")]
+use crate::arch::syscall_abi;
+
+const LOG: crate::logging::Subsystem = crate::logging::Subsystem::Arch;
 
 type PageFaultHandler = extern "x86-interrupt" fn(InterruptStackFrame, PageFaultErrorCode);
 type GpfHandler = extern "x86-interrupt" fn(InterruptStackFrame, u64);
 type DoubleFaultHandler = extern "x86-interrupt" fn(InterruptStackFrame, u64) -> !;
 type Int80Handler = extern "x86-interrupt" fn(InterruptStackFrame);
+type IrqHandler = extern "x86-interrupt" fn(InterruptStackFrame);
+
+/// PS/2 keyboard（IRQ1）
+pub const KEYBOARD_IRQ: u8 = 1;
+pub const KEYBOARD_VECTOR: u8 = pic::irq_vector(KEYBOARD_IRQ);
+
+/// 外部 IRQ の handler（引数は IRQ 番号。EOI は呼び出し側の dispatch が送る）
+pub type IrqHandlerFn = fn(u8);
+
+const PS2_DATA: u16 = 0x60;
+const PS2_STATUS: u16 = 0x64;
+/// status の output buffer full（data port に読める byte がある）
+const PS2_STATUS_OUTPUT_FULL: u8 = 0x01;
 
 static IDT_LOW: Mutex<Option<InterruptDescriptorTable>> = Mutex::new(None);
 static IDT_HIGH: Mutex<Option<InterruptDescriptorTable>> = Mutex::new(None);
 
 static INT80_COUNT: AtomicU64 = AtomicU64::new(0);
 
+// ---- IRQ dispatch ----
+// IRQ ごとの handler（fn pointer の low 側アドレス。0 = 未登録）
+static IRQ_HANDLERS: [AtomicU64; pic::IRQ_COUNT] = [const { AtomicU64::new(0) }; pic::IRQ_COUNT];
+// IRQ ごとに handler を呼んだ回数
+static IRQ_DISPATCHED: [AtomicU64; pic::IRQ_COUNT] = [const { AtomicU64::new(0) }; pic::IRQ_COUNT];
+// spurious（IRQ7 / IRQ15 で ISR に bit が無かった）と、handler の無い IRQ の回数
+static IRQ_SPURIOUS: AtomicU64 = AtomicU64::new(0);
+static IRQ_UNHANDLED: AtomicU64 = AtomicU64::new(0);
+// high-alias の IDT を読み込んだか（handler を high-alias 側で呼ぶ）
+static IDT_HIGH_LOADED: AtomicBool = AtomicBool::new(false);
+
 // ---- ring3 demo roots cache ----
 static DEMO_USER_ROOT_PHYS: AtomicU64 = AtomicU64::new(0);
 static DEMO_KERNEL_ROOT_PHYS: AtomicU64 = AtomicU64::new(0);
@@ -94,14 +164,26 @@ pub fn init() {
         idt.general_protection_fault
             .set_handler_fn(general_protection_fault_handler);
         idt.double_fault.set_handler_fn(double_fault_handler);
+        unsafe { set_exception_vectors(&mut idt, |addr| addr) };
 
         // ring3: int 0x80
+        #[cfg(not(feature = "ring3_tasks"))]
         unsafe {
             idt[0x80]
                 .set_handler_fn(int80_handler)
                 .set_privilege_level(PrivilegeLevel::Ring3)
                 .set_stack_index(gdt::PAGE_FAULT_IST_INDEX);
         }
+        // ring3_tasks: register ABI の入口。IST なし（RSP0 = task の kernel stack）
+        #[cfg(feature = "ring3_tasks")]
+        unsafe {
+            idt[0x80]
+                .set_handler_addr(VirtAddr::new(syscall_abi::int80_entry_addr()))
+                .set_privilege_level(PrivilegeLevel::Ring3);
+        }
+
+        // 外部 IRQ（PIC remap 後の 0x20..0x2F）は全部 irq_dispatch へ
+        unsafe { set_irq_vectors(&mut idt, |addr| addr) };
 
         *IDT_LOW.lock() = Some(idt);
 
@@ -110,7 +192,11 @@ pub fn init() {
             base: VirtAddr::new(idt_low_addr()),
         };
         unsafe { lidt(&ptr) };
-        logging::info("arch::interrupts::init: IDT loaded");
+        LOG.info("arch::interrupts::init: IDT loaded");
+
+        // timer（IRQ0）と keyboard（IRQ1。unmask は ps2_keyboard のときだけ）
+        register_irq_handler(timer::TIMER_IRQ, timer_irq);
+        register_irq_handler(KEYBOARD_IRQ, keyboard_irq);
     });
 }
 
@@ -132,11 +218,26 @@ pub fn reload_idt_high_alias() {
             idt.double_fault
                 .set_handler_fn(transmute_df(high_alias_addr(double_fault_handler as u64)))
                 .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
-
+            set_exception_vectors(&mut idt, high_alias_addr);
+            // NMI / #MC は専用の IST で受ける（割り込まれた側の stack を信用しない）
+            idt.non_maskable_interrupt
+                .set_handler_addr(VirtAddr::new(high_alias_addr(nmi_handler as u64)))
+                .set_stack_index(gdt::NMI_IST_INDEX);
+            idt.machine_check
+                .set_handler_addr(VirtAddr::new(high_alias_addr(machine_check_handler as u64)))
+                .set_stack_index(gdt::MACHINE_CHECK_IST_INDEX);
+
+            #[cfg(not(feature = "ring3_tasks"))]
             idt[0x80]
                 .set_handler_fn(transmute_int80(high_alias_addr(int80_handler as u64)))
                 .set_privilege_level(PrivilegeLevel::Ring3)
                 .set_stack_index(gdt::PAGE_FAULT_IST_INDEX);
+            #[cfg(feature = "ring3_tasks")]
+            idt[0x80]
+                .set_handler_addr(VirtAddr::new(high_alias_addr(syscall_abi::int80_entry_addr())))
+                .set_privilege_level(PrivilegeLevel::Ring3);
+
+            set_irq_vectors(&mut idt, high_alias_addr);
         }
 
         *IDT_HIGH.lock() = Some(idt);
@@ -148,10 +249,116 @@ pub fn reload_idt_high_alias() {
         };
 
         unsafe { lidt(&ptr) };
-        logging::info("arch::interrupts::reload_idt_high_alias: IDT reloaded (high-alias)");
+        IDT_HIGH_LOADED.store(true, Ordering::SeqCst);
+        LOG.info("arch::interrupts::reload_idt_high_alias: IDT reloaded (high-alias)");
+
+        idt_self_test();
     });
 }
 
+/// BSP が reload_idt_high_alias で作った IDT_HIGH をこの CPU にもロードする（arch::smp の AP 用）
+/// - IDT は全 CPU で共有（handler の中身は CPU を区別しない）。未ロードなら false
+#[cfg(feature = "smp")]
+pub fn load_idt_on_this_cpu() -> bool {
+    if !IDT_HIGH_LOADED.load(Ordering::SeqCst) {
+        return false;
+    }
+    let ptr = DescriptorTablePointer {
+        limit: (mem::size_of::<InterruptDescriptorTable>() - 1) as u16,
+        base: VirtAddr::new(high_alias_addr(idt_high_addr_low())),
+    };
+    unsafe { lidt(&ptr) };
+    true
+}
+
+/// 外部 IRQ の 16 vector に入口を置く（addr_of で low / high-alias を選ぶ）
+unsafe fn set_irq_vectors(idt: &mut InterruptDescriptorTable, addr_of: fn(u64) -> u64) {
+    const STUBS: [IrqHandler; pic::IRQ_COUNT] = [
+        irq0_entry, irq1_entry, irq2_entry, irq3_entry, irq4_entry, irq5_entry, irq6_entry, irq7_entry,
+        irq8_entry, irq9_entry, irq10_entry, irq11_entry, irq12_entry, irq13_entry, irq14_entry, irq15_entry,
+    ];
+    for (irq, stub) in STUBS.iter().enumerate() {
+        idt[pic::irq_vector(irq as u8)].set_handler_addr(VirtAddr::new(addr_of(*stub as u64)));
+    }
+}
+
+/// #PF / #GP / #DF 以外の例外 vector に handler を置く（addr_of で low / high-alias を選ぶ）
+unsafe fn set_exception_vectors(idt: &mut InterruptDescriptorTable, addr_of: fn(u64) -> u64) {
+    let at = |f: u64| VirtAddr::new(addr_of(f));
+
+    idt.divide_error.set_handler_addr(at(divide_error_handler as u64));
+    idt.debug.set_handler_addr(at(debug_handler as u64));
+    idt.non_maskable_interrupt.set_handler_addr(at(nmi_handler as u64));
+    idt.breakpoint.set_handler_addr(at(breakpoint_handler as u64));
+    idt.overflow.set_handler_addr(at(overflow_handler as u64));
+    idt.bound_range_exceeded.set_handler_addr(at(bound_range_handler as u64));
+    idt.invalid_opcode.set_handler_addr(at(invalid_opcode_handler as u64));
+    idt.device_not_available.set_handler_addr(at(device_not_available_handler as u64));
+    idt.invalid_tss.set_handler_addr(at(invalid_tss_handler as u64));
+    idt.segment_not_present.set_handler_addr(at(segment_not_present_handler as u64));
+    idt.stack_segment_fault.set_handler_addr(at(stack_segment_handler as u64));
+    idt.x87_floating_point.set_handler_addr(at(x87_floating_point_handler as u64));
+    idt.alignment_check.set_handler_addr(at(alignment_check_handler as u64));
+    idt.machine_check.set_handler_addr(at(machine_check_handler as u64));
+    idt.simd_floating_point.set_handler_addr(at(simd_floating_point_handler as u64));
+    idt.virtualization.set_handler_addr(at(virtualization_handler as u64));
+    idt.cp_protection_exception.set_handler_addr(at(control_protection_handler as u64));
+    idt.security_exception.set_handler_addr(at(security_exception_handler as u64));
+}
+
+/// high-alias の IDT で、置いた全 vector の handler が high-alias（PML4 508..511）を指しているか
+/// - 1 つでも外れていれば vector ごとに error を出す（その vector の例外は low 側に飛んで落ちる）
+fn idt_self_test() {
+    let guard = IDT_HIGH.lock();
+    let Some(idt) = guard.as_ref() else {
+        LOG.error("idt self-test: IDT_HIGH not initialized");
+        return;
+    };
+
+    let vectors: [(u8, u64); 21] = [
+        (0, idt.divide_error.handler_addr().as_u64()),
+        (1, idt.debug.handler_addr().as_u64()),
+        (2, idt.non_maskable_interrupt.handler_addr().as_u64()),
+        (3, idt.breakpoint.handler_addr().as_u64()),
+        (4, idt.overflow.handler_addr().as_u64()),
+        (5, idt.bound_range_exceeded.handler_addr().as_u64()),
+        (6, idt.invalid_opcode.handler_addr().as_u64()),
+        (7, idt.device_not_available.handler_addr().as_u64()),
+        (8, idt.double_fault.handler_addr().as_u64()),
+        (10, idt.invalid_tss.handler_addr().as_u64()),
+        (11, idt.segment_not_present.handler_addr().as_u64()),
+        (12, idt.stack_segment_fault.handler_addr().as_u64()),
+        (13, idt.general_protection_fault.handler_addr().as_u64()),
+        (14, idt.page_fault.handler_addr().as_u64()),
+        (16, idt.x87_floating_point.handler_addr().as_u64()),
+        (17, idt.alignment_check.handler_addr().as_u64()),
+        (18, idt.machine_check.handler_addr().as_u64()),
+        (19, idt.simd_floating_point.handler_addr().as_u64()),
+        (20, idt.virtualization.handler_addr().as_u64()),
+        (21, idt.cp_protection_exception.handler_addr().as_u64()),
+        (30, idt.security_exception.handler_addr().as_u64()),
+    ];
+    let irqs = (0..pic::IRQ_COUNT as u8).map(|irq| {
+        let vector = pic::irq_vector(irq);
+        (vector, idt[vector].handler_addr().as_u64())
+    });
+
+    let mut checked = 0u64;
+    let mut bad = 0u64;
+    for (vector, addr) in vectors.into_iter().chain(irqs) {
+        checked += 1;
+        if addr == 0 || virt_layout::pml4_index(addr) < virt_layout::KERNEL_ALIAS_DST_PML4_BASE_INDEX {
+            LOG.error("idt self-test: vector not installed high-alias");
+            LOG.info_u64("vector", vector as u64);
+            LOG.info_hex("handler", addr);
+            bad += 1;
+        }
+    }
+
+    LOG.info_u64("idt_self_test_vectors", checked);
+    LOG.info_u64("idt_self_test_bad", bad);
+}
+
 fn idt_low_addr() -> u64 {
     let guard = IDT_LOW.lock();
     guard.as_ref().expect("IDT_LOW not initialized") as *const _ as u64
@@ -163,6 +370,16 @@ fn idt_high_addr_low() -> u64 {
 }
 
 #[inline(always)]
+/// handler から触る static を、今の IDT に合わせた側（low / high-alias）で引く
+/// - high-alias の IDT を入れた後は user CR3 中でも来るので、low 側（map されていない）を避ける
+pub(crate) fn handler_static<T>(low: &'static T) -> &'static T {
+    if !IDT_HIGH_LOADED.load(Ordering::SeqCst) {
+        return low;
+    }
+    let high = high_alias_addr(low as *const T as u64);
+    unsafe { &*(high as *const T) }
+}
+
 fn high_alias_addr(low: u64) -> u64 {
     virt_layout::kernel_high_alias_of_low(low)
 }
@@ -223,8 +440,56 @@ fn set_exception_rip(stack_frame: &mut InterruptStackFrame, new_rip: u64) {
     }
 }
 
+// ---- iretq 前の TrapFrame 検査 ----
+
+/// ring3 へ iretq で戻る直前に呼ぶ。違反なら戻らない。
+/// - KernelState が登録済みなら ring3 task を TrapFrameCorrupt で kill して dump する
+/// - 未登録（ring3_demo 等）なら emergency ログだけ出して halt する
+fn verify_user_frame_before_iretq(stack_frame: &InterruptStackFrame) {
+    verify_user_trap_frame(&trapframe::UserTrapFrame {
+        rip: stack_frame.instruction_pointer.as_u64(),
+        cs: stack_frame.code_segment.0 as u64,
+        rflags: stack_frame.cpu_flags.bits(),
+        rsp: stack_frame.stack_pointer.as_u64(),
+        ss: stack_frame.stack_segment.0 as u64,
+    });
+}
+
+/// iretq 前の TrapFrame 検査（違反なら task を kill して halt。戻らない）
+pub(crate) fn verify_user_trap_frame(f: &trapframe::UserTrapFrame) {
+    let (violation, value) = match trapframe::check_user_trap_frame(f) {
+        Ok(()) => return,
+        Err(v) => v,
+    };
+
+    emergency_write_str("[INT80] trap frame rejected check=");
+    emergency_write_str(violation.name());
+    emergency_write_str(" value=");
+    emergency_write_hex_u64(value);
+    emergency_write_str("\n");
+
+    let _ = crate::kernel::with_kernel_state(|ks| {
+        crate::kernel::mailbox_kill_bad_trap_frame(ks, violation.code(), value)
+    });
+
+    emergency_write_str("[INT80] trap frame rejected -> halt\n");
+    crate::arch::halt_loop();
+}
+
 // ---- int80 handler ----
 
+/// int 0x80 の入口を数える（syscall_abi の入口からも呼ぶ）
+pub(crate) fn note_int80_entry() {
+    INT80_COUNT.fetch_add(1, Ordering::SeqCst);
+}
+
+// mailbox ABI offsets（user rsp からの距離。下向き）
+const MAILBOX_OFF_SYSNO: u64 = 16;
+const MAILBOX_OFF_A0: u64 = 24;
+const MAILBOX_OFF_A1: u64 = 32;
+const MAILBOX_OFF_A2: u64 = 40;
+const MAILBOX_OFF_RET: u64 = 48;
+
 extern "x86-interrupt" fn int80_handler(stack_frame: InterruptStackFrame) {
     #[cfg(feature = "ring3_demo")]
     {
@@ -277,6 +542,7 @@ fn int80_handler_ring3_demo(stack_frame: InterruptStackFrame) {
         let ret = if sysno == 1 { a0.wrapping_add(a1).wrapping_add(a2) } else { 0 };
         let _ = paging::guarded_user_rw_u64_in_root(user_root, kernel_root, p_retslot, ret);
 
+        verify_user_frame_before_iretq(&stack_frame);
         paging::switch_address_space_quiet(user_root);
         return;
     }
@@ -291,6 +557,7 @@ fn int80_handler_ring3_demo(stack_frame: InterruptStackFrame) {
         emergency_write_str(" echo="); emergency_write_hex_u64(echo);
         emergency_write_str("\n");
 
+        verify_user_frame_before_iretq(&stack_frame);
         paging::switch_address_space_quiet(user_root);
         return;
     }
@@ -306,18 +573,11 @@ fn int80_handler_ring3_demo(stack_frame: InterruptStackFrame) {
 fn int80_handler_mailbox(stack_frame: InterruptStackFrame) {
     let user_rsp = stack_frame.stack_pointer.as_u64();
 
-    // mailbox ABI offsets
-    const OFF_SYSNO: u64 = 16;
-    const OFF_A0: u64 = 24;
-    const OFF_A1: u64 = 32;
-    const OFF_A2: u64 = 40;
-    const OFF_RET: u64 = 48;
-
-    let p_sysno = (user_rsp.wrapping_sub(OFF_SYSNO)) as *const u64;
-    let p_a0 = (user_rsp.wrapping_sub(OFF_A0)) as *const u64;
-    let p_a1 = (user_rsp.wrapping_sub(OFF_A1)) as *const u64;
-    let p_a2 = (user_rsp.wrapping_sub(OFF_A2)) as *const u64;
-    let p_retslot = (user_rsp.wrapping_sub(OFF_RET)) as *mut u64;
+    let p_sysno = (user_rsp.wrapping_sub(MAILBOX_OFF_SYSNO)) as *const u64;
+    let p_a0 = (user_rsp.wrapping_sub(MAILBOX_OFF_A0)) as *const u64;
+    let p_a1 = (user_rsp.wrapping_sub(MAILBOX_OFF_A1)) as *const u64;
+    let p_a2 = (user_rsp.wrapping_sub(MAILBOX_OFF_A2)) as *const u64;
+    let p_retslot = (user_rsp.wrapping_sub(MAILBOX_OFF_RET)) as *mut u64;
 
     let (user_root, kernel_root) = match cache_demo_roots_if_needed() {
         Some(v) => v,
@@ -347,10 +607,186 @@ fn int80_handler_mailbox(stack_frame: InterruptStackFrame) {
 
     let _ = paging::guarded_user_rw_u64_in_root(user_root, kernel_root, p_retslot, ret);
 
+    // iretq 前に TrapFrame を検査する（違反なら kill + halt で戻らない）
+    verify_user_frame_before_iretq(&stack_frame);
+
     // iretq 前に user_root に戻す
     paging::switch_address_space_quiet(user_root);
 }
 
+// ---- external IRQ unmask ----
+
+// 外部 IRQ（timer 等）の unmask を許可したか
+static EXTERNAL_IRQS_ALLOWED: AtomicU64 = AtomicU64::new(0);
+
+/// 外部 IRQ の unmask はここだけで行う。
+/// - KernelState が sealed でなければ拒否する（handoff レースを構造的に防ぐ）
+/// - ここでは「許可の記録」と PIC の remap（全 mask）だけ。IF を立てるのは run_timer_ticks
+/// - ps2_keyboard のときは IRQ1 もここで unmask する（IF が立てば入力を受ける）
+/// - virtio_net のときは device の IRQ（PCI の interrupt_line）もここで unmask する
+pub fn allow_external_irqs() -> bool {
+    if !crate::kernel::is_kernel_state_sealed() {
+        LOG.error("allow_external_irqs: kernel_state not sealed; keep IRQs masked");
+        return false;
+    }
+
+    interrupts::without_interrupts(pic::init);
+    pic::log_apic_presence();
+
+    #[cfg(feature = "ps2_keyboard")]
+    {
+        interrupts::without_interrupts(|| pic::set_irq_masked(KEYBOARD_IRQ, false));
+        LOG.info("keyboard: IRQ1 unmasked (ps2_keyboard)");
+    }
+
+    #[cfg(feature = "virtio_net")]
+    if let Some(irq) = crate::arch::drivers::virtio_net::irq_line() {
+        interrupts::without_interrupts(|| pic::set_irq_masked(irq, false));
+        LOG.info("virtio_net: IRQ unmasked");
+        LOG.info_u64("irq", irq as u64);
+    }
+
+    EXTERNAL_IRQS_ALLOWED.store(1, Ordering::SeqCst);
+    LOG.info("external IRQs allowed (after kernel_state seal)");
+    true
+}
+
+pub fn external_irqs_allowed() -> bool {
+    EXTERNAL_IRQS_ALLOWED.load(Ordering::SeqCst) != 0
+}
+
+/// timer IRQ で budget_ticks 回 tick を回し、止まるまで hlt で待つ。
+/// - 戻り値: 実際に tick した回数。外部 IRQ が未許可なら None（呼び出し側で同期ループに落とす）
+/// - 止まる条件: budget 消化 / KernelState::should_halt()（判定は IRQ 側）
+/// - 戻る時点で IF は落ちている（以後 main 側が KernelState を触ってよい）
+pub fn run_timer_ticks(budget_ticks: u64) -> Option<u64> {
+    if !external_irqs_allowed() {
+        LOG.error("run_timer_ticks: external IRQs not allowed");
+        return None;
+    }
+
+    LOG.info("timer: PIT started (tick driven by IRQ0)");
+    LOG.info_u64("timer_hz", timer::TIMER_HZ as u64);
+    LOG.info_u64("timer_budget_ticks", budget_ticks);
+
+    interrupts::without_interrupts(|| timer::start(budget_ticks));
+
+    loop {
+        // 判定と hlt の間に最後の IRQ が来て寝続けないよう、判定は IF=0 で行い sti; hlt で待つ
+        interrupts::disable();
+        if timer::is_stopped() {
+            break;
+        }
+        interrupts::enable_and_hlt();
+    }
+
+    LOG.info("timer: stopped");
+    Some(timer::ticks())
+}
+
+// ---- IRQ dispatch ----
+
+/// IRQ に handler を登録する（IRQ ごとに 1 つ。上書きはしない）
+/// - 戻り値: 登録できたか（範囲外 / 登録済みなら false）
+/// - unmask はしない（pic::set_irq_masked。外部 IRQ の許可は allow_external_irqs の後）
+pub fn register_irq_handler(irq: u8, handler: IrqHandlerFn) -> bool {
+    let Some(slot) = IRQ_HANDLERS.get(irq as usize) else {
+        LOG.error("register_irq_handler: irq out of range");
+        LOG.info_u64("irq", irq as u64);
+        return false;
+    };
+    if slot.compare_exchange(0, handler as u64, Ordering::SeqCst, Ordering::SeqCst).is_err() {
+        LOG.error("register_irq_handler: irq already has a handler");
+        LOG.info_u64("irq", irq as u64);
+        return false;
+    }
+    true
+}
+
+/// IRQ の集計（Counters Dump 用。割り込みのタイミングに依存するので state hash には入れない）
+#[derive(Clone, Copy)]
+pub struct IrqStats {
+    pub dispatched: u64,
+    pub spurious: u64,
+    pub unhandled: u64,
+}
+
+pub fn irq_stats() -> IrqStats {
+    IrqStats {
+        dispatched: IRQ_DISPATCHED.iter().map(|c| c.load(Ordering::Relaxed)).sum(),
+        spurious: IRQ_SPURIOUS.load(Ordering::Relaxed),
+        unhandled: IRQ_UNHANDLED.load(Ordering::Relaxed),
+    }
+}
+
+/// 16 個の IRQ 入口の共通処理: spurious 判定 → handler → EOI
+fn irq_dispatch(irq: u8) {
+    if pic::is_spurious(irq) {
+        IRQ_SPURIOUS.fetch_add(1, Ordering::Relaxed);
+        pic::end_of_spurious(irq);
+        return;
+    }
+
+    let raw = IRQ_HANDLERS[irq as usize].load(Ordering::SeqCst);
+    if raw == 0 {
+        IRQ_UNHANDLED.fetch_add(1, Ordering::Relaxed);
+    } else {
+        IRQ_DISPATCHED[irq as usize].fetch_add(1, Ordering::Relaxed);
+        let addr = if IDT_HIGH_LOADED.load(Ordering::SeqCst) { high_alias_addr(raw) } else { raw };
+        let handler: IrqHandlerFn = unsafe { mem::transmute::<u64, IrqHandlerFn>(addr) };
+        handler(irq);
+    }
+
+    pic::end_of_interrupt(irq);
+}
+
+macro_rules! irq_entries {
+    ($($name:ident = $irq:expr),+ $(,)?) => {
+        $(
+            extern "x86-interrupt" fn $name(_stack_frame: InterruptStackFrame) {
+                irq_dispatch($irq);
+            }
+        )+
+    };
+}
+
+irq_entries! {
+    irq0_entry = 0, irq1_entry = 1, irq2_entry = 2, irq3_entry = 3,
+    irq4_entry = 4, irq5_entry = 5, irq6_entry = 6, irq7_entry = 7,
+    irq8_entry = 8, irq9_entry = 9, irq10_entry = 10, irq11_entry = 11,
+    irq12_entry = 12, irq13_entry = 13, irq14_entry = 14, irq15_entry = 15,
+}
+
+// ---- timer IRQ handler ----
+
+fn timer_irq(_irq: u8) {
+    if timer::take_tick() {
+        // seal 前（None）は止める側に倒す
+        let halt = crate::kernel::with_kernel_state(|ks| {
+            ks.tick();
+            ks.should_halt()
+        })
+        .unwrap_or(true);
+
+        if halt {
+            LOG.info("KernelState requested halt; stop timer");
+            timer::stop();
+        }
+    }
+}
+
+// ---- keyboard IRQ handler ----
+
+fn keyboard_irq(_irq: u8) {
+    let status = unsafe { Port::<u8>::new(PS2_STATUS).read() };
+    if status & PS2_STATUS_OUTPUT_FULL != 0 {
+        let scancode = unsafe { Port::<u8>::new(PS2_DATA).read() };
+        // scrollback のキーも入力キューには入れる（VGA 側は見るだけ）
+        logging::vga_scancode(scancode);
+        let _ = crate::kernel::with_kernel_state(|ks| ks.input_irq(scancode));
+    }
+}
+
 // ---- exception handlers ----
 
 extern "x86-interrupt" fn page_fault_handler(mut stack_frame: InterruptStackFrame, error_code: PageFaultErrorCode) {
@@ -374,6 +810,23 @@ extern "x86-interrupt" fn page_fault_handler(mut stack_frame: InterruptStackFram
         return;
     }
 
+    // ring3_tasks: ring3 で起きた #PF は current task の fault として KernelState に渡す
+    // - SwappedOut の page なら swap in して戻る（fault した命令を再実行）。それ以外は kill して他の task へ
+    #[cfg(feature = "ring3_tasks")]
+    if stack_frame.code_segment.rpl() == PrivilegeLevel::Ring3 {
+        user_page_fault_from_ring3(paging::PageFaultInfo {
+            addr: cr2,
+            err: error_code.bits() as u64,
+            rip,
+            rsp,
+            is_user_fault: true,
+        });
+        return;
+    }
+
+    // kernel stack の guard page（rsp はまだ上にあり、#PF の frame は積めた場合）
+    report_kernel_stack_overflow("#PF", cr2, rip, rsp);
+
     emergency_write_str("[EXC] #PF unguarded\n");
     emergency_write_str(" cr2="); emergency_write_hex_u64(cr2);
     emergency_write_str(" err="); emergency_write_hex_u64(error_code.bits() as u64);
@@ -384,6 +837,41 @@ extern "x86-interrupt" fn page_fault_handler(mut stack_frame: InterruptStackFram
     crate::arch::halt_loop();
 }
 
+/// ring3 task の #PF: SwappedOut の page なら swap in して戻る（iretq で fault した命令を再実行）
+/// - fault handler に委譲したら、FaultResolve で起こされて current に戻るまで IRQ を待ってから戻る（再実行）
+///   * 待ちの判定は IF=0 で行い sti; hlt で待つ（syscall_abi の dispatch と同じ）
+/// - それ以外は kernel 側で kill（guard page なら StackOverflow）してから IRQ を待つ
+/// - task は Dead なので、次の timer tick の末尾で他の task の stack へ切り替わり、ここには戻らない
+#[cfg(feature = "ring3_tasks")]
+fn user_page_fault_from_ring3(pf: paging::PageFaultInfo) {
+    use crate::kernel::UserFaultOutcome;
+
+    let outcome =
+        crate::kernel::with_kernel_state(|ks| ks.ring3_user_page_fault(pf)).unwrap_or(UserFaultOutcome::Killed);
+    match outcome {
+        UserFaultOutcome::Retry => {
+            emergency_write_str("[EXC] #PF from ring3 => swapped in; retry\n");
+            return;
+        }
+        UserFaultOutcome::Delegated => {
+            emergency_write_str("[EXC] #PF from ring3 => delegated to fault handler; wait\n");
+            loop {
+                interrupts::enable_and_hlt();
+                interrupts::disable();
+                if crate::kernel::with_kernel_state(|ks| ks.ring3_fault_resumed()).unwrap_or(false) {
+                    emergency_write_str("[EXC] #PF from ring3 => resolved; retry\n");
+                    return;
+                }
+            }
+        }
+        UserFaultOutcome::Killed => {}
+    }
+    emergency_write_str("[EXC] #PF from ring3 => kill current task\n");
+    loop {
+        interrupts::enable_and_hlt();
+    }
+}
+
 extern "x86-interrupt" fn general_protection_fault_handler(stack_frame: InterruptStackFrame, error_code: u64) {
     interrupts::disable();
 
@@ -398,15 +886,203 @@ extern "x86-interrupt" fn general_protection_fault_handler(stack_frame: Interrup
     crate::arch::halt_loop();
 }
 
+// ---- その他の例外 ----
+
+/// vector の短い名前（"#UD" 等。emergency 出力と kill の理由用）
+pub fn exception_name(vector: u8) -> &'static str {
+    match vector {
+        0 => "#DE",
+        1 => "#DB",
+        2 => "NMI",
+        3 => "#BP",
+        4 => "#OF",
+        5 => "#BR",
+        6 => "#UD",
+        7 => "#NM",
+        10 => "#TS",
+        11 => "#NP",
+        12 => "#SS",
+        16 => "#MF",
+        17 => "#AC",
+        18 => "#MC",
+        19 => "#XM",
+        20 => "#VE",
+        21 => "#CP",
+        30 => "#SX",
+        _ => "#??",
+    }
+}
+
+/// 例外の共通処理（emergency 出力 → ring3 なら kill / ring0 の trap なら戻る / それ以外は halt）
+fn exception_common(stack_frame: &InterruptStackFrame, vector: u8, err: Option<u64>) {
+    interrupts::disable();
+
+    let rip = stack_frame.instruction_pointer.as_u64();
+    let from_ring3 = stack_frame.code_segment.rpl() == PrivilegeLevel::Ring3;
+
+    emergency_write_str("[EXC] ");
+    emergency_write_str(exception_name(vector));
+    if let Some(err) = err {
+        emergency_write_str(" err=");
+        emergency_write_hex_u64(err);
+    }
+    emergency_write_str(" rip=");
+    emergency_write_hex_u64(rip);
+    emergency_write_str(" rsp=");
+    emergency_write_hex_u64(stack_frame.stack_pointer.as_u64());
+    emergency_write_str(if from_ring3 { " ring3\n" } else { " ring0\n" });
+
+    // ring3_tasks: user の fault として current task を kill し、次の timer tick で他の task へ切り替わるのを待つ
+    #[cfg(feature = "ring3_tasks")]
+    if from_ring3 {
+        let killed = crate::kernel::with_kernel_state(|ks| ks.ring3_user_exception(vector, err.unwrap_or(0), rip))
+            .unwrap_or(false);
+        if killed {
+            emergency_write_str("[EXC] from ring3 => kill current task\n");
+            loop {
+                interrupts::enable_and_hlt();
+            }
+        }
+    }
+
+    // ring0 の #DB / #BP は trap（rip は次の命令）。報告だけして戻る
+    if !from_ring3 && (vector == 1 || vector == 3) {
+        return;
+    }
+
+    crate::arch::halt_loop();
+}
+
+extern "x86-interrupt" fn divide_error_handler(stack_frame: InterruptStackFrame) {
+    exception_common(&stack_frame, 0, None);
+}
+extern "x86-interrupt" fn debug_handler(stack_frame: InterruptStackFrame) {
+    exception_common(&stack_frame, 1, None);
+}
+extern "x86-interrupt" fn breakpoint_handler(stack_frame: InterruptStackFrame) {
+    exception_common(&stack_frame, 3, None);
+}
+extern "x86-interrupt" fn overflow_handler(stack_frame: InterruptStackFrame) {
+    exception_common(&stack_frame, 4, None);
+}
+extern "x86-interrupt" fn bound_range_handler(stack_frame: InterruptStackFrame) {
+    exception_common(&stack_frame, 5, None);
+}
+extern "x86-interrupt" fn invalid_opcode_handler(stack_frame: InterruptStackFrame) {
+    exception_common(&stack_frame, 6, None);
+}
+extern "x86-interrupt" fn device_not_available_handler(stack_frame: InterruptStackFrame) {
+    exception_common(&stack_frame, 7, None);
+}
+extern "x86-interrupt" fn invalid_tss_handler(stack_frame: InterruptStackFrame, error_code: u64) {
+    exception_common(&stack_frame, 10, Some(error_code));
+}
+extern "x86-interrupt" fn segment_not_present_handler(stack_frame: InterruptStackFrame, error_code: u64) {
+    exception_common(&stack_frame, 11, Some(error_code));
+}
+extern "x86-interrupt" fn stack_segment_handler(stack_frame: InterruptStackFrame, error_code: u64) {
+    exception_common(&stack_frame, 12, Some(error_code));
+}
+extern "x86-interrupt" fn x87_floating_point_handler(stack_frame: InterruptStackFrame) {
+    exception_common(&stack_frame, 16, None);
+}
+extern "x86-interrupt" fn alignment_check_handler(stack_frame: InterruptStackFrame, error_code: u64) {
+    exception_common(&stack_frame, 17, Some(error_code));
+}
+extern "x86-interrupt" fn simd_floating_point_handler(stack_frame: InterruptStackFrame) {
+    exception_common(&stack_frame, 19, None);
+}
+extern "x86-interrupt" fn virtualization_handler(stack_frame: InterruptStackFrame) {
+    exception_common(&stack_frame, 20, None);
+}
+extern "x86-interrupt" fn control_protection_handler(stack_frame: InterruptStackFrame, error_code: u64) {
+    exception_common(&stack_frame, 21, Some(error_code));
+}
+extern "x86-interrupt" fn security_exception_handler(stack_frame: InterruptStackFrame, error_code: u64) {
+    exception_common(&stack_frame, 30, Some(error_code));
+}
+
+/// NMI: snapshot して latch し、中断したところへ戻る
+extern "x86-interrupt" fn nmi_handler(stack_frame: InterruptStackFrame) {
+    hwfault::record(hwfault::HwEventKind::Nmi, &stack_frame, 0);
+
+    emergency_write_str("[EXC] NMI rip=");
+    emergency_write_hex_u64(stack_frame.instruction_pointer.as_u64());
+    emergency_write_str(" rsp=");
+    emergency_write_hex_u64(stack_frame.stack_pointer.as_u64());
+    emergency_write_str(" => latched, resume\n");
+}
+
+/// #MC は user の fault ではない（ハードウェアの異常）。ring3 でも task の kill にはしない
+/// - MCG_STATUS.RIPV が立っていれば（中断した rip から再開できる）MCIP を落として戻る。それ以外は fail-stop
+extern "x86-interrupt" fn machine_check_handler(stack_frame: InterruptStackFrame) {
+    let mcg_status = hwfault::read_mcg_status();
+    hwfault::record(hwfault::HwEventKind::MachineCheck, &stack_frame, mcg_status.unwrap_or(0));
+
+    emergency_write_str("[EXC] #MC rip=");
+    emergency_write_hex_u64(stack_frame.instruction_pointer.as_u64());
+    emergency_write_str(" rsp=");
+    emergency_write_hex_u64(stack_frame.stack_pointer.as_u64());
+    emergency_write_str(" mcg_status=");
+    emergency_write_hex_u64(mcg_status.unwrap_or(0));
+
+    match mcg_status {
+        Some(status) if hwfault::mc_restartable(status) => {
+            hwfault::clear_mc_in_progress(status);
+            emergency_write_str(" => latched, resume\n");
+        }
+        _ => {
+            interrupts::disable();
+            emergency_write_str(" => latched, fail-stop\n");
+            crate::arch::halt_loop();
+        }
+    }
+}
+
 extern "x86-interrupt" fn double_fault_handler(stack_frame: InterruptStackFrame, error_code: u64) -> ! {
     interrupts::disable();
 
+    let rip = stack_frame.instruction_pointer.as_u64();
+    let rsp = stack_frame.stack_pointer.as_u64();
+
+    // rsp が guard page に入ると #PF の frame を積めずに #DF になる（CR2 は #PF のときのまま）
+    let cr2 = Cr2::read().unwrap_or(VirtAddr::new(0)).as_u64();
+    report_kernel_stack_overflow("#DF", cr2, rip, rsp);
+    report_kernel_stack_overflow("#DF", rsp, rip, rsp);
+
     emergency_write_str("[EXC] #DF err=");
     emergency_write_hex_u64(error_code);
     emergency_write_str(" rip=");
-    emergency_write_hex_u64(stack_frame.instruction_pointer.as_u64());
+    emergency_write_hex_u64(rip);
     emergency_write_str(" rsp=");
-    emergency_write_hex_u64(stack_frame.stack_pointer.as_u64());
+    emergency_write_hex_u64(rsp);
+    emergency_write_str("\n");
+
+    crate::arch::halt_loop();
+}
+
+/// addr が kernel stack の guard page（arch::stack_guard）なら "kernel stack overflow" を出して止まる
+/// - 当たらなければ何もせずに戻る（呼び出し側が通常の報告を続ける）
+fn report_kernel_stack_overflow(exc: &str, addr: u64, rip: u64, rsp: u64) {
+    let Some(stack) = stack_guard::find(addr) else {
+        return;
+    };
+
+    emergency_write_str("[EXC] kernel stack overflow (");
+    emergency_write_str(exc);
+    match stack {
+        stack_guard::KernelStackKind::Boot => emergency_write_str(") stack=boot"),
+        stack_guard::KernelStackKind::Task { slot } => {
+            emergency_write_str(") stack=task slot=");
+            emergency_write_hex_u64(slot as u64);
+        }
+    }
+    emergency_write_str(" addr=");
+    emergency_write_hex_u64(addr);
+    emergency_write_str(" rip=");
+    emergency_write_hex_u64(rip);
+    emergency_write_str(" rsp=");
+    emergency_write_hex_u64(rsp);
     emergency_write_str("\n");
 
     crate::arch::halt_loop();
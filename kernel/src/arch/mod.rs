@@ -4,9 +4,27 @@
 // - cpu: hlt_loop など CPU 固有処理
 // - paging: CR3 / ページテーブル操作
 // - virt_layout: 仮想アドレスレイアウト（low/high, alias, user slot）
-// - interrupts: IDT, page fault など例外処理
+// - interrupts: IDT, page fault など例外処理, timer IRQ
+// - pic: 8259 PIC（remap / IRQ の mask / EOI / spurious 判定）と xAPIC の検出
+// - timer: 8254 PIT（tick を駆動する IRQ0）
 // - gdt: GDT/TSS/IST
 // - ring3: ring3 へ入るための最小 glue（iretq）
+// - trapframe: iretq で ring3 に戻る前の TrapFrame 検査
+// - ops: KernelState から見たアーキ操作の境界（ArchOps。実機 = HwArch / ホスト = MockArch）
+// - context: task ごとの kernel stack と stack 切替（callee-saved の退避 / 復帰）
+// - syscall_abi: ring3_tasks の int 0x80 入口（register ABI）
+// - syscall_msr: ring3_tasks の syscall 命令の入口（LSTAR / STAR / FMASK、sysret で戻る）
+// - qemu: isa-debug-exit で QEMU を終了コード付きで止める（qemu_exit / power）
+// - power: 電源断（isa-debug-exit → ACPI S5 → halt の順に試す。kernel::shutdown が呼ぶ）
+// - unwind: frame pointer を辿って戻りアドレスを集める（panic の backtrace）
+// - stack_guard: kernel stack（boot / task）の直下の guard page の登録と照合（kernel stack overflow の検出）
+// - hwfault: NMI / #MC の snapshot と「fatal hardware event」の latch（dump / verdict が読む）
+// - acpi: RSDP / RSDT / XSDT を辿り、MADT / HPET / FADT（\_S5）を platform の記述（Platform）にまとめる
+// - clock: 起動からの ns を返す hardware clock（HPET、無ければ calibrate した TSC。kernel::time が読む）
+// - rtc: 起動時に CMOS RTC から壁時計を 1 回読む（kernel::time::boot_wallclock）
+// - pci: PCI の config space を走査して device の一覧を作る（drivers が safe な API で引く）
+// - drivers: 外部デバイスの最小ドライバ（virtio console = trace の出口。feature virtio_console）
+// - smp: AP の起動（INIT / SIPI と trampoline）と per-CPU の GDT/TSS/IDT のロード（feature smp）
 //
 // 方針:
 // - 例外が起きてもログが残るよう、割り込み初期化は早め。
@@ -15,8 +33,29 @@
 pub mod cpu;
 pub mod interrupts;
 pub mod paging;
+pub mod pic;
+pub mod timer;
 pub mod virt_layout;
 pub mod gdt;
+pub mod trapframe;
+pub mod ops;
+pub mod context;
+pub mod unwind;
+pub mod hwfault;
+pub mod stack_guard;
+pub mod acpi;
+pub mod clock;
+pub mod rtc;
+pub mod pci;
+pub mod drivers;
+#[cfg(feature = "ring3_tasks")]
+pub mod syscall_abi;
+#[cfg(feature = "ring3_tasks")]
+pub mod syscall_msr;
+pub mod qemu;
+pub mod power;
+#[cfg(feature = "smp")]
+pub mod smp;
 
 // ring3 は ring3 系 feature のときだけビルド（unused warning 対策）
 #[cfg(any(feature = "ring3_demo", feature = "ring3_mailbox", feature = "ring3_mailbox_loop"))]
@@ -28,6 +67,12 @@ use bootloader::BootInfo;
 pub fn init(boot_info: &'static BootInfo) {
     interrupts::init();
     paging::init(boot_info);
+    stack_guard::init();
+    acpi::init();
+    // clock は PMM が要る（HPET を map_mmio する）ので KernelState::new から init する
+    rtc::init();
+    pci::init();
+    drivers::init();
 }
 
 /// CPU を停止させるループ
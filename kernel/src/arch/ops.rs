@@ -0,0 +1,483 @@
+// kernel/src/arch/ops.rs
+//
+// 役割:
+// - KernelState が使うアーキ依存操作（ページテーブル / CR3 / guarded user RW / stack 切替 / hardware clock）と
+//   user 空間の配置（USER_SPACE_BASE / USER_SPACE_SIZE）の境界（ArchOps）。
+// - kernel/ 側は `arch::paging::*` / `arch::clock::*` や CR3 を直接触らず、`Arch`（= この trait の実装）経由で呼ぶ。
+//   * 境界を跨ぐ型（MyPhysFrame / PageFaultInfo / PagingApplyError / ClockSource）もここから引く
+//   * boot の入口（entry.rs）と initrd は KernelState の外なので、従来どおり arch を直接呼ぶ
+//
+// 実装:
+// - HwArch:   実機（x86_64、target_os = "none"）。arch::paging へそのまま委譲する
+// - MockArch: ホスト（target_os != "none"）の test 専用（feature host_test）。ページテーブルに触らず、呼び出し回数だけ数える
+//   * guarded RW は「書いた値をそのまま読めた」とみなす（#PF は起きない）
+//   * stack 切替はしない（回数だけ数えて即座に戻る = 切替先がすぐ切り替え返したのと同じ）
+//   * boot の root は固定の frame（MOCK_BOOT_ROOT_FRAME。PMM が配る範囲の外に置く）
+//   * hardware clock は無い（ClockSource::None。kernel clock は tick_count で進む = trace が再現する）
+//   * user 空間の配置は mem::layout の値（実機と同じ。layout の整合 invariant がホストでも通る）
+//   * ホスト側で scheduler / IPC の状態遷移だけを回すための差し替え
+//
+// 方針:
+// - 関数は self を取らない（KernelState に型引数を増やさない。`Arch` の型 alias で静的に選ぶ）
+// - unsafe の意味は arch::paging と同じ（apply_* は呼び出し側が root / phys_mem の整合を保証する）
+//
+// やらないこと:
+// - interrupts / timer / ring3 の抽象化（KernelState の外側の責務）
+
+use crate::mm::PhysicalMemoryManager;
+use crate::mem::paging::{BatchError, MemAction};
+
+use super::context::TaskContext;
+
+pub use super::clock::ClockSource;
+pub use super::paging::{MyPhysFrame, PageFaultInfo, PagingApplyError};
+
+pub trait ArchOps {
+    /// user 空間の先頭の仮想アドレス（PML4 の USER slot）
+    const USER_SPACE_BASE: u64;
+    /// user 空間の大きさ（PML4 の 1 slot）
+    const USER_SPACE_SIZE: u64;
+
+    /// hardware clock（HPET → TSC）を選ぶ（KernelState::new から。2 回目以降は何もしない）
+    fn clock_init(phys_mem: &mut PhysicalMemoryManager);
+
+    /// 選ばれた hardware clock（無ければ ClockSource::None）
+    fn clock_source() -> ClockSource;
+
+    /// hardware clock の周波数（Hz。None なら 0）
+    fn clock_frequency_hz() -> u64;
+
+    /// clock_init からの経過 ns（None なら 0）
+    fn clock_now_ns() -> u64;
+
+    /// 現在の CR3 のページテーブルに MemAction を反映する
+    unsafe fn apply_mem_action(action: MemAction, phys_mem: &mut PhysicalMemoryManager) -> Result<(), PagingApplyError>;
+
+    /// root のページテーブルに MemAction を反映する（CR3 は切り替えない）
+    unsafe fn apply_mem_action_in_root(
+        action: MemAction,
+        root: MyPhysFrame,
+        phys_mem: &mut PhysicalMemoryManager,
+    ) -> Result<(), PagingApplyError>;
+
+    /// root のページテーブルに MemAction の batch を all-or-nothing で反映する（CR3 は切り替えない。落ちたら落ちた index）
+    unsafe fn apply_mem_actions_in_root(
+        actions: &[MemAction],
+        root: MyPhysFrame,
+        phys_mem: &mut PhysicalMemoryManager,
+    ) -> Result<(), BatchError<PagingApplyError>>;
+
+    /// 新しい user PML4 に現在の kernel 側エントリをコピーする
+    fn init_user_pml4_from_current(new_root: MyPhysFrame);
+
+    /// kernel heap の範囲を今の root に map する（user root を作る前に 1 回）。戻り値は (先頭, 大きさ)
+    fn map_kernel_heap(phys_mem: &mut PhysicalMemoryManager) -> Result<(u64, usize), PagingApplyError>;
+
+    /// CR3 切替（安全でなければ切り替えない。ログあり）
+    fn switch_address_space(root: Option<MyPhysFrame>);
+
+    /// CR3 切替（ログ無し）
+    fn switch_address_space_quiet(root: MyPhysFrame);
+
+    /// 今の CR3 の root（Mock は最後に切り替えた root。まだなら None）
+    fn current_root() -> Option<MyPhysFrame>;
+
+    /// KernelState を作る時点の kernel root（Task0 の root。実機は CR3 を読む）
+    fn boot_root() -> MyPhysFrame;
+
+    /// この CPU の TLB を全部捨てる（kernel/tlb.rs の遅延 flush）
+    fn flush_tlb_all();
+
+    /// user root の USER slot の下のページテーブルを外し、そのフレームを out に入れる（戻り値は数）
+    /// - leaf の user page は先に unmap されていること
+    unsafe fn detach_user_page_tables_in_root(
+        root: MyPhysFrame,
+        out: &mut [Option<MyPhysFrame>],
+    ) -> Result<usize, PagingApplyError>;
+
+    /// user root の USER slot が空か
+    fn user_slot_empty_in_root(root: MyPhysFrame) -> bool;
+
+    /// root での translate 結果をログに出す（デバッグ用）
+    fn debug_translate_in_root(root: MyPhysFrame, virt_addr_u64: u64);
+
+    /// user root に一時切替して ptr を RW し、kernel root に戻してから返す
+    fn guarded_user_rw_u64_in_root(
+        user_root: MyPhysFrame,
+        kernel_root: MyPhysFrame,
+        ptr: *mut u64,
+        value: u64,
+    ) -> Result<u64, PageFaultInfo>;
+
+    /// user root に一時切替して ptr を読むだけ（書かない）。kernel root に戻してから返す
+    fn guarded_user_read_u64_in_root(
+        user_root: MyPhysFrame,
+        kernel_root: MyPhysFrame,
+        ptr: *const u64,
+    ) -> Result<u64, PageFaultInfo>;
+
+    /// task 用 kernel stack の上端（slot = task index）
+    fn kernel_stack_top(slot: usize) -> u64;
+
+    /// slot の kernel stack に、初回の切替で task_entry に入る context を積む
+    fn init_task_context(slot: usize) -> TaskContext;
+
+    /// slot の kernel stack に、初回の切替で ring3（rip / rsp、IF=1）へ iretq する context を積む
+    /// - フレームが arch::trapframe の検査に落ちたら (code, value)
+    fn init_user_task_context(slot: usize, rip: u64, rsp: u64) -> Result<TaskContext, (u64, u64)>;
+
+    /// physmap 経由で frame の先頭に bytes を書く（user の code page の初期化用）
+    unsafe fn write_bytes_to_frame(frame: MyPhysFrame, bytes: &[u8]);
+
+    /// physmap 経由で frame の先頭から buf.len() byte を読む（net service が shm の frame を読む / FsOpen の名前）
+    unsafe fn read_bytes_from_frame(frame: MyPhysFrame, buf: &mut [u8]);
+
+    /// physmap 経由で src の 1 page を dst に写す（swap out / swap in）
+    unsafe fn copy_frame(src: MyPhysFrame, dst: MyPhysFrame);
+
+    /// 今の stack を prev に保存し、next の stack へ切り替える（rsp0 が Some なら TSS.RSP0 も差し替える）
+    /// - 戻るのは、誰かが prev に切り替えたとき
+    unsafe fn switch_context(prev: *mut TaskContext, next: *const TaskContext, rsp0: Option<u64>);
+}
+
+// -----------------------------------------------------------------------------
+// 実機
+// -----------------------------------------------------------------------------
+
+#[cfg(target_os = "none")]
+pub struct HwArch;
+
+#[cfg(target_os = "none")]
+impl ArchOps for HwArch {
+    const USER_SPACE_BASE: u64 = super::paging::USER_SPACE_BASE;
+    const USER_SPACE_SIZE: u64 = super::paging::USER_SPACE_SIZE;
+
+    fn clock_init(phys_mem: &mut PhysicalMemoryManager) {
+        super::clock::init(phys_mem)
+    }
+
+    fn clock_source() -> ClockSource {
+        super::clock::source()
+    }
+
+    fn clock_frequency_hz() -> u64 {
+        super::clock::frequency_hz()
+    }
+
+    fn clock_now_ns() -> u64 {
+        super::clock::now_ns()
+    }
+
+    unsafe fn apply_mem_action(action: MemAction, phys_mem: &mut PhysicalMemoryManager) -> Result<(), PagingApplyError> {
+        super::paging::apply_mem_action(action, phys_mem)
+    }
+
+    unsafe fn apply_mem_action_in_root(
+        action: MemAction,
+        root: MyPhysFrame,
+        phys_mem: &mut PhysicalMemoryManager,
+    ) -> Result<(), PagingApplyError> {
+        super::paging::apply_mem_action_in_root(action, root, phys_mem)
+    }
+
+    unsafe fn apply_mem_actions_in_root(
+        actions: &[MemAction],
+        root: MyPhysFrame,
+        phys_mem: &mut PhysicalMemoryManager,
+    ) -> Result<(), BatchError<PagingApplyError>> {
+        super::paging::apply_mem_actions_in_root(actions, root, phys_mem)
+    }
+
+    fn init_user_pml4_from_current(new_root: MyPhysFrame) {
+        super::paging::init_user_pml4_from_current(new_root)
+    }
+
+    fn map_kernel_heap(phys_mem: &mut PhysicalMemoryManager) -> Result<(u64, usize), PagingApplyError> {
+        super::paging::map_kernel_heap(phys_mem)
+    }
+
+    fn switch_address_space(root: Option<MyPhysFrame>) {
+        super::paging::switch_address_space(root)
+    }
+
+    fn switch_address_space_quiet(root: MyPhysFrame) {
+        super::paging::switch_address_space_quiet(root)
+    }
+
+    fn current_root() -> Option<MyPhysFrame> {
+        Some(super::paging::current_root())
+    }
+
+    fn boot_root() -> MyPhysFrame {
+        super::paging::current_root()
+    }
+
+    fn flush_tlb_all() {
+        super::paging::flush_tlb_all()
+    }
+
+    unsafe fn detach_user_page_tables_in_root(
+        root: MyPhysFrame,
+        out: &mut [Option<MyPhysFrame>],
+    ) -> Result<usize, PagingApplyError> {
+        super::paging::detach_user_page_tables_in_root(root, out)
+    }
+
+    fn user_slot_empty_in_root(root: MyPhysFrame) -> bool {
+        super::paging::user_slot_empty_in_root(root)
+    }
+
+    fn debug_translate_in_root(root: MyPhysFrame, virt_addr_u64: u64) {
+        super::paging::debug_translate_in_root(root, virt_addr_u64)
+    }
+
+    fn guarded_user_rw_u64_in_root(
+        user_root: MyPhysFrame,
+        kernel_root: MyPhysFrame,
+        ptr: *mut u64,
+        value: u64,
+    ) -> Result<u64, PageFaultInfo> {
+        super::paging::guarded_user_rw_u64_in_root(user_root, kernel_root, ptr, value)
+    }
+
+    fn guarded_user_read_u64_in_root(
+        user_root: MyPhysFrame,
+        kernel_root: MyPhysFrame,
+        ptr: *const u64,
+    ) -> Result<u64, PageFaultInfo> {
+        super::paging::guarded_user_read_u64_in_root(user_root, kernel_root, ptr)
+    }
+
+    fn kernel_stack_top(slot: usize) -> u64 {
+        super::context::kernel_stack_top(slot)
+    }
+
+    fn init_task_context(slot: usize) -> TaskContext {
+        super::context::init_task_context(slot)
+    }
+
+    fn init_user_task_context(slot: usize, rip: u64, rsp: u64) -> Result<TaskContext, (u64, u64)> {
+        super::context::init_user_task_context(slot, rip, rsp)
+    }
+
+    unsafe fn write_bytes_to_frame(frame: MyPhysFrame, bytes: &[u8]) {
+        super::paging::write_bytes_to_frame(frame, bytes)
+    }
+
+    unsafe fn read_bytes_from_frame(frame: MyPhysFrame, buf: &mut [u8]) {
+        super::paging::read_bytes_from_frame(frame, buf)
+    }
+
+    unsafe fn copy_frame(src: MyPhysFrame, dst: MyPhysFrame) {
+        super::paging::copy_frame(src, dst)
+    }
+
+    unsafe fn switch_context(prev: *mut TaskContext, next: *const TaskContext, rsp0: Option<u64>) {
+        super::context::switch_context(prev, next, rsp0)
+    }
+}
+
+#[cfg(target_os = "none")]
+pub type Arch = HwArch;
+
+// -----------------------------------------------------------------------------
+// ホスト（mock）
+// -----------------------------------------------------------------------------
+
+#[cfg(all(not(target_os = "none"), feature = "host_test"))]
+pub use self::mock::{MockArch, MockArchStats, MOCK_BOOT_ROOT_FRAME};
+
+#[cfg(all(not(target_os = "none"), feature = "host_test"))]
+pub type Arch = MockArch;
+
+#[cfg(all(not(target_os = "none"), feature = "host_test"))]
+mod mock {
+    use super::*;
+    use crate::mem::layout::{PML4_SLOT_SIZE, USER_SPACE_START};
+    use core::sync::atomic::{AtomicU64, Ordering};
+
+    static APPLIED: AtomicU64 = AtomicU64::new(0);
+    static SWITCHES: AtomicU64 = AtomicU64::new(0);
+    static USER_RW: AtomicU64 = AtomicU64::new(0);
+    static CONTEXT_SWITCHES: AtomicU64 = AtomicU64::new(0);
+    static TLB_FLUSHES: AtomicU64 = AtomicU64::new(0);
+    // 最後に切り替えた root の frame index（u64::MAX = まだ無い）
+    static CURRENT_ROOT: AtomicU64 = AtomicU64::new(u64::MAX);
+
+    /// MockArch が受けた呼び出しの数
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct MockArchStats {
+        pub mem_actions_applied: u64,
+        pub address_space_switches: u64,
+        pub user_rw: u64,
+        pub context_switches: u64,
+        pub tlb_flushes: u64,
+    }
+
+    /// boot_root が返す kernel root の frame index（物理 0 の直後。test の memory map は usable にしない）
+    pub const MOCK_BOOT_ROOT_FRAME: u64 = 1;
+
+    /// map_kernel_heap が返す heap の裏（ホストでは #[global_allocator] にしないので統計用）
+    const MOCK_HEAP_SIZE: usize = 64 * 1024;
+
+    #[repr(C, align(16))]
+    struct MockHeapBuf([u8; MOCK_HEAP_SIZE]);
+
+    static mut MOCK_HEAP: MockHeapBuf = MockHeapBuf([0; MOCK_HEAP_SIZE]);
+
+    pub struct MockArch;
+
+    impl MockArch {
+        pub fn stats() -> MockArchStats {
+            MockArchStats {
+                mem_actions_applied: APPLIED.load(Ordering::Relaxed),
+                address_space_switches: SWITCHES.load(Ordering::Relaxed),
+                user_rw: USER_RW.load(Ordering::Relaxed),
+                context_switches: CONTEXT_SWITCHES.load(Ordering::Relaxed),
+                tlb_flushes: TLB_FLUSHES.load(Ordering::Relaxed),
+            }
+        }
+
+        pub fn reset() {
+            APPLIED.store(0, Ordering::Relaxed);
+            SWITCHES.store(0, Ordering::Relaxed);
+            USER_RW.store(0, Ordering::Relaxed);
+            CONTEXT_SWITCHES.store(0, Ordering::Relaxed);
+            TLB_FLUSHES.store(0, Ordering::Relaxed);
+            CURRENT_ROOT.store(u64::MAX, Ordering::Relaxed);
+        }
+    }
+
+    impl ArchOps for MockArch {
+        const USER_SPACE_BASE: u64 = USER_SPACE_START;
+        const USER_SPACE_SIZE: u64 = PML4_SLOT_SIZE;
+
+        fn clock_init(_phys_mem: &mut PhysicalMemoryManager) {}
+
+        fn clock_source() -> ClockSource {
+            ClockSource::None
+        }
+
+        fn clock_frequency_hz() -> u64 {
+            0
+        }
+
+        fn clock_now_ns() -> u64 {
+            0
+        }
+
+        unsafe fn apply_mem_action(_action: MemAction, _phys_mem: &mut PhysicalMemoryManager) -> Result<(), PagingApplyError> {
+            APPLIED.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        }
+
+        unsafe fn apply_mem_action_in_root(
+            _action: MemAction,
+            _root: MyPhysFrame,
+            _phys_mem: &mut PhysicalMemoryManager,
+        ) -> Result<(), PagingApplyError> {
+            APPLIED.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        }
+
+        unsafe fn apply_mem_actions_in_root(
+            actions: &[MemAction],
+            _root: MyPhysFrame,
+            _phys_mem: &mut PhysicalMemoryManager,
+        ) -> Result<(), BatchError<PagingApplyError>> {
+            APPLIED.fetch_add(actions.len() as u64, Ordering::Relaxed);
+            Ok(())
+        }
+
+        fn init_user_pml4_from_current(_new_root: MyPhysFrame) {}
+
+        /// ページテーブルは触らず、静的なバッファを heap の範囲として返す
+        fn map_kernel_heap(_phys_mem: &mut PhysicalMemoryManager) -> Result<(u64, usize), PagingApplyError> {
+            let base = unsafe { core::ptr::addr_of_mut!(MOCK_HEAP) as u64 };
+            Ok((base, MOCK_HEAP_SIZE))
+        }
+
+        fn switch_address_space(root: Option<MyPhysFrame>) {
+            SWITCHES.fetch_add(1, Ordering::Relaxed);
+            if let Some(r) = root {
+                CURRENT_ROOT.store(r.number, Ordering::Relaxed);
+            }
+        }
+
+        fn switch_address_space_quiet(root: MyPhysFrame) {
+            SWITCHES.fetch_add(1, Ordering::Relaxed);
+            CURRENT_ROOT.store(root.number, Ordering::Relaxed);
+        }
+
+        fn current_root() -> Option<MyPhysFrame> {
+            match CURRENT_ROOT.load(Ordering::Relaxed) {
+                u64::MAX => None,
+                n => Some(MyPhysFrame::from_index(n)),
+            }
+        }
+
+        fn boot_root() -> MyPhysFrame {
+            MyPhysFrame::from_index(MOCK_BOOT_ROOT_FRAME)
+        }
+
+        fn flush_tlb_all() {
+            TLB_FLUSHES.fetch_add(1, Ordering::Relaxed);
+        }
+
+        unsafe fn detach_user_page_tables_in_root(
+            _root: MyPhysFrame,
+            _out: &mut [Option<MyPhysFrame>],
+        ) -> Result<usize, PagingApplyError> {
+            Ok(0)
+        }
+
+        fn user_slot_empty_in_root(_root: MyPhysFrame) -> bool {
+            true
+        }
+
+        fn debug_translate_in_root(_root: MyPhysFrame, _virt_addr_u64: u64) {}
+
+        fn guarded_user_rw_u64_in_root(
+            _user_root: MyPhysFrame,
+            _kernel_root: MyPhysFrame,
+            _ptr: *mut u64,
+            value: u64,
+        ) -> Result<u64, PageFaultInfo> {
+            USER_RW.fetch_add(1, Ordering::Relaxed);
+            Ok(value)
+        }
+
+        fn guarded_user_read_u64_in_root(
+            _user_root: MyPhysFrame,
+            _kernel_root: MyPhysFrame,
+            _ptr: *const u64,
+        ) -> Result<u64, PageFaultInfo> {
+            USER_RW.fetch_add(1, Ordering::Relaxed);
+            Ok(0)
+        }
+
+        fn kernel_stack_top(_slot: usize) -> u64 {
+            0
+        }
+
+        fn init_task_context(_slot: usize) -> TaskContext {
+            TaskContext::EMPTY
+        }
+
+        fn init_user_task_context(_slot: usize, _rip: u64, _rsp: u64) -> Result<TaskContext, (u64, u64)> {
+            Ok(TaskContext::EMPTY)
+        }
+
+        unsafe fn write_bytes_to_frame(_frame: MyPhysFrame, _bytes: &[u8]) {}
+
+        unsafe fn read_bytes_from_frame(_frame: MyPhysFrame, buf: &mut [u8]) {
+            buf.fill(0);
+        }
+
+        unsafe fn copy_frame(_src: MyPhysFrame, _dst: MyPhysFrame) {}
+
+        unsafe fn switch_context(_prev: *mut TaskContext, _next: *const TaskContext, _rsp0: Option<u64>) {
+            CONTEXT_SWITCHES.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
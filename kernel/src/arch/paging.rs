@@ -40,7 +40,19 @@
 //
 // ★追加（今回の安定化修正）:
 // - MemAction::Unmap の VA 計算は「root の有無」で決める（kernel unmap が user を触らない）
+// - MemAction::Protect は leaf の flags だけを update_flags で替え、その page だけ invlpg する（フレームも中間の table も触らない）
+//   VA 計算は Map と同じ（USER の有無。Protect は USER を変えない）
+// - apply_mem_actions_in_root は MemAction の batch を all-or-nothing で反映する
+//   * 先に全 entry を今のページテーブルで検査する（件数 / 同じ page が 2 度出ない / policy / Map は未 map・Unmap と Protect は 4KiB の leaf）
+//     検査で落ちたら何も書かない。検査のときに entry ごとの逆操作（VA と元の frame / flags）を stack に取る
+//   * 反映の途中で落ちたら（中間 table のフレーム切れなど）、反映済みの entry を逆順に戻してから落ちた index を返す
+//   * 逆操作が落ちたら fail-stop（戻せない状態で続けない）
 // - high-alias のコピー数は MVP では MAX 固定（“存在しない alias を参照して #PF” を避ける）
+//
+// ★追加（TLB）:
+// - apply_mem_action_in_root の invlpg は「root が今の CR3 のとき」だけ行う。
+//   別の root の entry を今の CR3 で invlpg しても意味が無い（その root の TLB は残る）。
+//   その root の flush は kernel 側（kernel/tlb.rs）が記録し、次にその root へ切り替えるときに行う。
 
 use bootloader::BootInfo;
 use bootloader::bootinfo::MemoryRegionType;
@@ -63,18 +75,21 @@ use x86_64::{
         Size4KiB,
         Translate,
     },
-    structures::paging::mapper::{MapToError, UnmapError},
+    structures::paging::mapper::{FlagUpdateError, MapToError, MappedFrame, MapperFlush, TranslateResult, UnmapError},
 };
 
 use crate::arch::virt_layout;
-use crate::logging;
-use crate::mm::PhysicalMemoryManager;
-use crate::mem::paging::{MemAction, PageFlags};
+use crate::mm::{PhysicalMemoryManager, ReserveError};
+use crate::mem::paging::{BatchError, MemAction, PageFlags, MAX_MEM_BATCH};
 
 // interrupts.rs など他モジュールからも使うので公開 re-export しておく
 pub use crate::mem::addr::{PhysFrame as MyPhysFrame, PAGE_SIZE};
 
 pub use crate::arch::virt_layout::{USER_PML4_INDEX, USER_SPACE_BASE, USER_SPACE_SIZE};
+pub use crate::arch::virt_layout::{KERNEL_HEAP_BASE, KERNEL_HEAP_PML4_INDEX, KERNEL_HEAP_SIZE};
+pub use crate::arch::virt_layout::{KERNEL_MMIO_BASE, KERNEL_MMIO_SIZE};
+
+const LOG: crate::logging::Subsystem = crate::logging::Subsystem::Arch;
 
 const ENABLE_REAL_PAGING: bool = true;
 const ENABLE_HIGH_ALIAS_EXEC_TEST: bool = true;
@@ -101,6 +116,9 @@ static GUARD_STACK_HIGH_VIRT: AtomicU64 = AtomicU64::new(0);
 // alias copy count（install 時に確定）
 static ALIAS_COPY_COUNT: AtomicUsize = AtomicUsize::new(0);
 
+// MMIO window で次に使う page（KERNEL_MMIO_BASE からの page 数。前から詰めて、外さない）
+static MMIO_NEXT_PAGE: AtomicU64 = AtomicU64::new(0);
+
 // -----------------------------------------------------------------------------
 // ring3 demo roots (観測用)
 // -----------------------------------------------------------------------------
@@ -392,6 +410,18 @@ pub fn guarded_user_read_u64_in_root(
 pub enum PagingApplyError {
     MapFailed,
     UnmapFailed,
+    /// Protect: leaf が無い / huge page の下
+    ProtectFailed,
+    /// batch: entry が MAX_MEM_BATCH を超える
+    BatchTooLarge,
+    /// batch: 同じ page が 2 度出てくる
+    BatchDuplicatePage,
+    /// batch: USER の有無と user slot が合わない（単発の apply なら fail-stop の panic になるもの）
+    PolicyViolation,
+    /// USER slot の下に present な leaf（または huge page）が残っている
+    UserSlotNotEmpty,
+    /// 外すページテーブルのフレームが呼び出し側のバッファに入りきらない
+    TableBufferFull,
 }
 
 #[inline]
@@ -405,16 +435,16 @@ fn enforce_user_mapping_policy(virt: VirtAddr, flags: PageTableFlags) {
     let user_accessible = flags.contains(PageTableFlags::USER_ACCESSIBLE);
 
     if user_accessible && !in_user_slot {
-        logging::error("paging policy violation: USER mapping outside reserved user slot");
-        logging::info_u64("virt_addr", virt.as_u64());
-        logging::info_u64("flags_bits", flags.bits() as u64);
+        LOG.error("paging policy violation: USER mapping outside reserved user slot");
+        LOG.info_u64("virt_addr", virt.as_u64());
+        LOG.info_u64("flags_bits", flags.bits() as u64);
         panic!("USER mapping outside reserved user slot");
     }
 
     if !user_accessible && in_user_slot {
-        logging::error("paging policy violation: KERNEL mapping inside reserved user slot");
-        logging::info_u64("virt_addr", virt.as_u64());
-        logging::info_u64("flags_bits", flags.bits() as u64);
+        LOG.error("paging policy violation: KERNEL mapping inside reserved user slot");
+        LOG.info_u64("virt_addr", virt.as_u64());
+        LOG.info_u64("flags_bits", flags.bits() as u64);
         panic!("KERNEL mapping inside reserved user slot");
     }
 }
@@ -436,57 +466,57 @@ fn assert_no_physmap_user_slot_collision() {
     let physmap_pml4 = virt_layout::pml4_index(physmap_off);
 
     if USER_PML4_INDEX >= 256 {
-        logging::error("SPEC VIOLATION: USER_PML4_INDEX must be < 256");
-        logging::info_u64("USER_PML4_INDEX", USER_PML4_INDEX as u64);
+        LOG.error("SPEC VIOLATION: USER_PML4_INDEX must be < 256");
+        LOG.info_u64("USER_PML4_INDEX", USER_PML4_INDEX as u64);
         panic!("USER_PML4_INDEX must be < 256");
     }
 
     if physmap_pml4 == USER_PML4_INDEX {
-        logging::error("SPEC VIOLATION: physmap PML4 index collides with USER slot");
-        logging::info_u64("physmap_pml4_index", physmap_pml4 as u64);
-        logging::info_u64("USER_PML4_INDEX", USER_PML4_INDEX as u64);
+        LOG.error("SPEC VIOLATION: physmap PML4 index collides with USER slot");
+        LOG.info_u64("physmap_pml4_index", physmap_pml4 as u64);
+        LOG.info_u64("USER_PML4_INDEX", USER_PML4_INDEX as u64);
         panic!("physmap collides with USER slot (PML4 index)");
     }
 
     if physmap_pml4 < 256 {
         let end = min(physmap_pml4 + PHYSMAP_PML4_COPY_COUNT, 256);
         if (physmap_pml4..end).contains(&USER_PML4_INDEX) {
-            logging::error("SPEC VIOLATION: physmap PML4 copy range overlaps USER slot");
-            logging::info_u64("physmap_pml4_start", physmap_pml4 as u64);
-            logging::info_u64("physmap_pml4_end", end as u64);
-            logging::info_u64("USER_PML4_INDEX", USER_PML4_INDEX as u64);
+            LOG.error("SPEC VIOLATION: physmap PML4 copy range overlaps USER slot");
+            LOG.info_u64("physmap_pml4_start", physmap_pml4 as u64);
+            LOG.info_u64("physmap_pml4_end", end as u64);
+            LOG.info_u64("USER_PML4_INDEX", USER_PML4_INDEX as u64);
             panic!("physmap copy range overlaps USER slot");
         }
     }
 }
 
 pub fn init(boot_info: &'static BootInfo) {
-    logging::info("arch::paging::init: start");
+    LOG.info("arch::paging::init: start");
 
     PHYSICAL_MEMORY_OFFSET.store(boot_info.physical_memory_offset, Ordering::Relaxed);
     assert_no_physmap_user_slot_collision();
 
-    logging::info("arch::paging::init: memory map dump start");
+    LOG.info("arch::paging::init: memory map dump start");
     for (i, region) in boot_info.memory_map.iter().enumerate() {
         let start = region.range.start_frame_number * 4096;
         let end = region.range.end_frame_number * 4096;
 
-        logging::info("mem_region:");
-        logging::info_u64("index", i as u64);
-        logging::info_u64("start_phys", start);
-        logging::info_u64("end_phys", end);
+        LOG.info("mem_region:");
+        LOG.info_u64("index", i as u64);
+        LOG.info_u64("start_phys", start);
+        LOG.info_u64("end_phys", end);
 
         match region.region_type {
-            MemoryRegionType::Usable => logging::info("type = Usable"),
-            MemoryRegionType::Reserved => logging::info("type = Reserved"),
-            MemoryRegionType::AcpiReclaimable => logging::info("type = AcpiReclaimable"),
-            MemoryRegionType::AcpiNvs => logging::info("type = AcpiNvs"),
-            MemoryRegionType::BadMemory => logging::info("type = BadMemory"),
-            _ => logging::info("type = Other"),
+            MemoryRegionType::Usable => LOG.info("type = Usable"),
+            MemoryRegionType::Reserved => LOG.info("type = Reserved"),
+            MemoryRegionType::AcpiReclaimable => LOG.info("type = AcpiReclaimable"),
+            MemoryRegionType::AcpiNvs => LOG.info("type = AcpiNvs"),
+            MemoryRegionType::BadMemory => LOG.info("type = BadMemory"),
+            _ => LOG.info("type = Other"),
         }
     }
-    logging::info("arch::paging::init: memory map dump end");
-    logging::info("arch::paging::init: done");
+    LOG.info("arch::paging::init: memory map dump end");
+    LOG.info("arch::paging::init: done");
 }
 
 /// physmap（physical_memory_offset）の getter
@@ -494,6 +524,34 @@ pub fn physical_memory_offset() -> u64 {
     PHYSICAL_MEMORY_OFFSET.load(Ordering::Relaxed)
 }
 
+/// physmap 経由で frame の先頭から bytes を書く（user の code page の初期化用）
+/// - frame は確保済みで、bytes は 1 page に収まること
+pub unsafe fn write_bytes_to_frame(frame: MyPhysFrame, bytes: &[u8]) {
+    let len = bytes.len().min(PAGE_SIZE as usize);
+    let base = (PHYSICAL_MEMORY_OFFSET.load(Ordering::Relaxed) + frame.start_address().0) as *mut u8;
+    for (i, b) in bytes[..len].iter().enumerate() {
+        core::ptr::write_volatile(base.add(i), *b);
+    }
+}
+
+/// physmap 経由で frame の先頭から buf を埋める（buf は 1 page に収まる分だけ読む）
+pub unsafe fn read_bytes_from_frame(frame: MyPhysFrame, buf: &mut [u8]) {
+    let len = buf.len().min(PAGE_SIZE as usize);
+    let base = (PHYSICAL_MEMORY_OFFSET.load(Ordering::Relaxed) + frame.start_address().0) as *const u8;
+    for (i, b) in buf[..len].iter_mut().enumerate() {
+        *b = core::ptr::read_volatile(base.add(i));
+    }
+}
+
+/// physmap 経由で src の 1 page を dst に写す（swap の退避 / 復元用）
+/// - src と dst は別フレーム（重なりは無い）
+pub unsafe fn copy_frame(src: MyPhysFrame, dst: MyPhysFrame) {
+    let off = PHYSICAL_MEMORY_OFFSET.load(Ordering::Relaxed);
+    let from = (off + src.start_address().0) as *const u8;
+    let to = (off + dst.start_address().0) as *mut u8;
+    core::ptr::copy_nonoverlapping(from, to, PAGE_SIZE as usize);
+}
+
 /// physmap 経由で phys_u64 が current CR3 で引けるかを検証する（デバッグ用）
 pub fn debug_physmap_can_access_phys(phys_u64: u64) -> bool {
     if !ENABLE_REAL_PAGING {
@@ -509,6 +567,106 @@ pub fn debug_physmap_can_access_phys(phys_u64: u64) -> bool {
     }
 }
 
+/// kernel 側の u64（8 byte 境界）が current CR3 で引けるか（arch::unwind が読む前に確かめる用）
+/// - USER slot と非 canonical は false（user CR3 中でも kernel の stack だけを見る）
+/// - paging::init 前（physmap 未設定）は page table を辿れないので false
+pub fn is_kernel_readable_u64(addr: u64) -> bool {
+    if addr % 8 != 0 || addr != virt_layout::canonicalize_virt(addr) || is_user_space_addr_u64(addr) {
+        return false;
+    }
+    if !ENABLE_REAL_PAGING {
+        return true;
+    }
+    if PHYSICAL_MEMORY_OFFSET.load(Ordering::Relaxed) == 0 {
+        return false;
+    }
+
+    unsafe {
+        let mapper = init_offset_page_table();
+        mapper.translate_addr(VirtAddr::new(addr)).is_some()
+    }
+}
+
+/// kernel 側の仮想アドレスを current CR3 で物理アドレスにする（arch::drivers の DMA buffer 用）
+/// - 引けない / paging::init 前は None
+#[cfg_attr(not(feature = "virtio_console"), allow(dead_code))]
+pub fn kernel_virt_to_phys(virt: u64) -> Option<u64> {
+    if !ENABLE_REAL_PAGING || PHYSICAL_MEMORY_OFFSET.load(Ordering::Relaxed) == 0 {
+        return None;
+    }
+    if virt != virt_layout::canonicalize_virt(virt) {
+        return None;
+    }
+
+    unsafe {
+        let mapper = init_offset_page_table();
+        mapper.translate_addr(VirtAddr::new(virt)).map(|p| p.as_u64())
+    }
+}
+
+/// kernel の静的領域の 1 page を guard として unmap する（arch::stack_guard 用）
+/// - frame は .bss の一部なので PMM には返さない
+/// - high-alias は PML4 entry の共有なので、low を外せば alias 側（と user root から見た kernel 部分）も外れる
+pub fn unmap_kernel_guard_page(virt_low: u64) -> bool {
+    if !ENABLE_REAL_PAGING || PHYSICAL_MEMORY_OFFSET.load(Ordering::Relaxed) == 0 {
+        return false;
+    }
+
+    let page: Page<Size4KiB> = Page::containing_address(VirtAddr::new(virt_low));
+    unsafe {
+        let mut mapper = init_offset_page_table();
+        match mapper.unmap(page) {
+            Ok((_frame, flush)) => {
+                flush.flush();
+                x86_64::instructions::tlb::flush(VirtAddr::new(virt_layout::kernel_high_alias_of_low(virt_low)));
+                true
+            }
+            Err(e) => {
+                LOG.error("unmap_kernel_guard_page: unmap failed");
+                log_unmap_error(e);
+                false
+            }
+        }
+    }
+}
+
+/// 4GiB 未満の物理 page を今の root に identity map する（arch::smp 用）
+/// - AP の起動 trampoline: AP は paging を有効にした直後もこの page の命令を実行するので、物理 = 仮想で引ける必要がある
+/// - MMIO は map_mmio（MMIO window に uncached で map する）
+/// - 既に同じ物理へ identity で引けるなら何もしない。別の物理へ map 済みなら false
+/// - 中間の page table は phys_mem から取る（map は外さない）
+#[cfg_attr(not(feature = "smp"), allow(dead_code))]
+pub fn identity_map_page_below_4g(phys: u64, phys_mem: &mut PhysicalMemoryManager) -> bool {
+    if !ENABLE_REAL_PAGING || PHYSICAL_MEMORY_OFFSET.load(Ordering::Relaxed) == 0 {
+        return false;
+    }
+    if phys % PAGE_SIZE != 0 || phys >= (1u64 << 32) {
+        return false;
+    }
+
+    unsafe {
+        let mut mapper = init_offset_page_table();
+        if let Some(p) = mapper.translate_addr(VirtAddr::new(phys)) {
+            return p.as_u64() == phys;
+        }
+
+        let frame: PhysFrame<Size4KiB> = PhysFrame::containing_address(PhysAddr::new(phys));
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        let mut frame_alloc = KernelFrameAllocator::new(phys_mem);
+        match mapper.identity_map(frame, flags, &mut frame_alloc) {
+            Ok(flush) => {
+                flush.flush();
+                true
+            }
+            Err(e) => {
+                LOG.error("identity_map_page_below_4g: identity_map failed");
+                log_map_to_error(e);
+                false
+            }
+        }
+    }
+}
+
 fn to_x86_flags(flags: PageFlags) -> PageTableFlags {
     let mut res = PageTableFlags::empty();
     if flags.contains(PageFlags::PRESENT) { res |= PageTableFlags::PRESENT; }
@@ -556,6 +714,146 @@ unsafe impl<'a> FrameAllocator<Size4KiB> for KernelFrameAllocator<'a> {
     }
 }
 
+// -----------------------------------------------------------------------------
+// kernel heap
+// -----------------------------------------------------------------------------
+
+/// kernel heap の範囲（KERNEL_HEAP_BASE から KERNEL_HEAP_SIZE）を今の root に map する
+/// - user root を作る前に kernel root で呼ぶ（high-half の entry ごと user root にコピーされる）
+/// - PML4 slot が既に使われていれば何もせずに Err（bootloader の mapping を壊さない）
+/// - 戻り値: (heap の先頭, 大きさ)
+pub fn map_kernel_heap(phys_mem: &mut PhysicalMemoryManager) -> Result<(u64, usize), PagingApplyError> {
+    if !ENABLE_REAL_PAGING {
+        return Err(PagingApplyError::MapFailed);
+    }
+
+    if unsafe { !active_level_4_table()[KERNEL_HEAP_PML4_INDEX].is_unused() } {
+        LOG.error("map_kernel_heap: heap pml4 slot already in use");
+        LOG.info_u64("pml4_index", KERNEL_HEAP_PML4_INDEX as u64);
+        return Err(PagingApplyError::MapFailed);
+    }
+
+    let mut mapper = unsafe { init_offset_page_table() };
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+
+    for i in 0..(KERNEL_HEAP_SIZE / PAGE_SIZE) {
+        let page: Page<Size4KiB> = Page::containing_address(VirtAddr::new(KERNEL_HEAP_BASE + i * PAGE_SIZE));
+        let frame = phys_mem.allocate_frame().ok_or(PagingApplyError::MapFailed)?;
+
+        let mut frame_alloc = KernelFrameAllocator::new(phys_mem);
+        match unsafe { mapper.map_to(page, frame, flags, &mut frame_alloc) } {
+            Ok(flush) => flush.flush(),
+            Err(e) => {
+                LOG.error("map_kernel_heap: map_to failed");
+                LOG.info_u64("page_index", i);
+                log_map_to_error(e);
+                return Err(PagingApplyError::MapFailed);
+            }
+        }
+    }
+
+    Ok((KERNEL_HEAP_BASE, KERNEL_HEAP_SIZE as usize))
+}
+
+// -----------------------------------------------------------------------------
+// MMIO window
+// -----------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy)]
+pub enum MmioError {
+    /// paging / physmap が無効
+    Disabled,
+    /// 物理範囲を予約できない（重なり / 配布中 / 表が満杯）
+    Reserve(ReserveError),
+    /// MMIO window に空きが無い
+    WindowFull,
+    /// map_to が失敗した（予約は残る）
+    MapFailed,
+}
+
+impl MmioError {
+    /// ログ用の名前
+    pub fn name(&self) -> &'static str {
+        match self {
+            MmioError::Disabled => "mmio_disabled",
+            MmioError::Reserve(e) => e.name(),
+            MmioError::WindowFull => "mmio_window_full",
+            MmioError::MapFailed => "mmio_map_failed",
+        }
+    }
+}
+
+/// 物理範囲 [phys, phys + len) を予約し（PhysicalMemoryManager::reserve_range）、kernel の MMIO window に uncached で map する
+/// - flags は PRESENT | WRITABLE | NO_CACHE | WRITE_THROUGH | NO_EXECUTE（kernel 専用。USER は付けない）
+/// - window は前から詰めて使い、外さない（device は停止まで居る）
+/// - map の途中で落ちたら map した分を外す。予約は残す（範囲は配らないまま。同じ範囲の map_mmio は Overlap）
+/// - 戻り値: phys に対応する仮想アドレス（page 内の offset も保つ）
+pub fn map_mmio(phys: u64, len: u64, phys_mem: &mut PhysicalMemoryManager) -> Result<u64, MmioError> {
+    if !ENABLE_REAL_PAGING || PHYSICAL_MEMORY_OFFSET.load(Ordering::Relaxed) == 0 {
+        return Err(MmioError::Disabled);
+    }
+
+    let next = MMIO_NEXT_PAGE.load(Ordering::Relaxed);
+    let pages = match phys.checked_add(len) {
+        Some(end) if len > 0 => end.div_ceil(PAGE_SIZE) - phys / PAGE_SIZE,
+        _ => return Err(MmioError::Reserve(ReserveError::Empty)),
+    };
+    if next + pages > KERNEL_MMIO_SIZE / PAGE_SIZE {
+        LOG.error("map_mmio: MMIO window is full");
+        LOG.info_u64("pages", pages);
+        return Err(MmioError::WindowFull);
+    }
+
+    let r = phys_mem.reserve_range(phys, len).map_err(|e| {
+        LOG.error("map_mmio: reserve_range failed");
+        LOG.info(e.name());
+        LOG.info_hex("phys_addr", phys);
+        if let ReserveError::Overlap(other) = e {
+            LOG.info_hex("reserved_start", other.start);
+            LOG.info_u64("reserved_len", other.len);
+        }
+        MmioError::Reserve(e)
+    })?;
+
+    let virt_base = KERNEL_MMIO_BASE + next * PAGE_SIZE;
+    let flags = PageTableFlags::PRESENT
+        | PageTableFlags::WRITABLE
+        | PageTableFlags::NO_CACHE
+        | PageTableFlags::WRITE_THROUGH
+        | PageTableFlags::NO_EXECUTE;
+    let mut mapper = unsafe { init_offset_page_table() };
+
+    for i in 0..pages {
+        let page: Page<Size4KiB> = Page::containing_address(VirtAddr::new(virt_base + i * PAGE_SIZE));
+        let frame: PhysFrame<Size4KiB> = PhysFrame::containing_address(PhysAddr::new(r.start + i * PAGE_SIZE));
+
+        let mut frame_alloc = KernelFrameAllocator::new(phys_mem);
+        match unsafe { mapper.map_to(page, frame, flags, &mut frame_alloc) } {
+            Ok(flush) => flush.flush(),
+            Err(e) => {
+                LOG.error("map_mmio: map_to failed; range stays reserved");
+                LOG.info_u64("page_index", i);
+                log_map_to_error(e);
+                for j in 0..i {
+                    let p: Page<Size4KiB> = Page::containing_address(VirtAddr::new(virt_base + j * PAGE_SIZE));
+                    if let Ok((_, flush)) = mapper.unmap(p) {
+                        flush.flush();
+                    }
+                }
+                return Err(MmioError::MapFailed);
+            }
+        }
+    }
+    MMIO_NEXT_PAGE.store(next + pages, Ordering::Relaxed);
+
+    let virt = virt_base + (phys - r.start);
+    LOG.info("map_mmio: mapped uncached");
+    LOG.info_hex("phys_addr", phys);
+    LOG.info_hex("virt_addr", virt);
+    LOG.info_u64("pages", pages);
+    Ok(virt)
+}
+
 // -----------------------------------------------------------------------------
 // CR3 preflight
 // -----------------------------------------------------------------------------
@@ -617,13 +915,13 @@ fn preflight_check_before_cr3_write(target: MyPhysFrame) {
         let rbp_phys_tgt = translate_u64(&tgt_mapper, rbp);
 
         if rip_phys_tgt == 0 || rsp_phys_tgt == 0 {
-            logging::error("CR3 preflight: target translate failed (RIP/RSP)");
-            logging::info_u64("rip", rip);
-            logging::info_u64("rsp", rsp);
-            logging::info_u64("rbp", rbp);
-            logging::info_u64("rip_phys_tgt", rip_phys_tgt);
-            logging::info_u64("rsp_phys_tgt", rsp_phys_tgt);
-            logging::info_u64("rbp_phys_tgt", rbp_phys_tgt);
+            LOG.error("CR3 preflight: target translate failed (RIP/RSP)");
+            LOG.info_u64("rip", rip);
+            LOG.info_u64("rsp", rsp);
+            LOG.info_u64("rbp", rbp);
+            LOG.info_u64("rip_phys_tgt", rip_phys_tgt);
+            LOG.info_u64("rsp_phys_tgt", rsp_phys_tgt);
+            LOG.info_u64("rbp_phys_tgt", rbp_phys_tgt);
             panic!("CR3 preflight failed (target missing RIP/RSP mapping)");
         }
 
@@ -645,36 +943,36 @@ fn preflight_check_before_cr3_write(target: MyPhysFrame) {
         let tgt_e = (&*tgt_pml4_ptr)[physmap_pml4].clone();
 
         if cur_e.is_unused() || !cur_e.flags().contains(PageTableFlags::PRESENT) {
-            logging::error("CR3 preflight: current lacks physmap PML4 entry (physmap index calc likely wrong)");
-            logging::info_u64("physmap_pml4_index", physmap_pml4 as u64);
-            logging::info_u64("cur_addr", cur_e.addr().as_u64());
-            logging::info_u64("cur_flags", cur_e.flags().bits() as u64);
+            LOG.error("CR3 preflight: current lacks physmap PML4 entry (physmap index calc likely wrong)");
+            LOG.info_u64("physmap_pml4_index", physmap_pml4 as u64);
+            LOG.info_u64("cur_addr", cur_e.addr().as_u64());
+            LOG.info_u64("cur_flags", cur_e.flags().bits() as u64);
             panic!("CR3 preflight failed (current physmap missing)");
         }
 
         if tgt_e.is_unused() || !tgt_e.flags().contains(PageTableFlags::PRESENT) {
-            logging::error("CR3 preflight: target lacks physmap PML4 entry");
-            logging::info_u64("physmap_pml4_index", physmap_pml4 as u64);
-            logging::info_u64("target_pml4_phys", target_phys_u64);
-            logging::info_u64("tgt_addr", tgt_e.addr().as_u64());
-            logging::info_u64("tgt_flags", tgt_e.flags().bits() as u64);
+            LOG.error("CR3 preflight: target lacks physmap PML4 entry");
+            LOG.info_u64("physmap_pml4_index", physmap_pml4 as u64);
+            LOG.info_u64("target_pml4_phys", target_phys_u64);
+            LOG.info_u64("tgt_addr", tgt_e.addr().as_u64());
+            LOG.info_u64("tgt_flags", tgt_e.flags().bits() as u64);
             panic!("CR3 preflight failed (target physmap missing)");
         }
 
         if tgt_e.flags().contains(PageTableFlags::USER_ACCESSIBLE) {
-            logging::error("CR3 preflight: physmap entry is USER_ACCESSIBLE in target (forbidden)");
-            logging::info_u64("physmap_pml4_index", physmap_pml4 as u64);
+            LOG.error("CR3 preflight: physmap entry is USER_ACCESSIBLE in target (forbidden)");
+            LOG.info_u64("physmap_pml4_index", physmap_pml4 as u64);
             panic!("CR3 preflight failed (physmap user bit)");
         }
 
         // strong check: physmap PML4 entry が current と一致すること
         if tgt_e.addr() != cur_e.addr() || tgt_e.flags() != cur_e.flags() {
-            logging::error("CR3 preflight: physmap PML4 entry mismatch (target vs current)");
-            logging::info_u64("physmap_pml4_index", physmap_pml4 as u64);
-            logging::info_u64("cur_addr", cur_e.addr().as_u64());
-            logging::info_u64("tgt_addr", tgt_e.addr().as_u64());
-            logging::info_u64("cur_flags", cur_e.flags().bits() as u64);
-            logging::info_u64("tgt_flags", tgt_e.flags().bits() as u64);
+            LOG.error("CR3 preflight: physmap PML4 entry mismatch (target vs current)");
+            LOG.info_u64("physmap_pml4_index", physmap_pml4 as u64);
+            LOG.info_u64("cur_addr", cur_e.addr().as_u64());
+            LOG.info_u64("tgt_addr", tgt_e.addr().as_u64());
+            LOG.info_u64("cur_flags", cur_e.flags().bits() as u64);
+            LOG.info_u64("tgt_flags", tgt_e.flags().bits() as u64);
             panic!("CR3 preflight failed (physmap pml4 mismatch)");
         }
 
@@ -692,16 +990,16 @@ fn preflight_check_before_cr3_write(target: MyPhysFrame) {
                 let code_phys_tgt = translate_u64(&tgt_mapper, code_low);
                 let stack_phys_tgt = translate_u64(&tgt_mapper, stack_low);
                 if code_phys_tgt != exp_code_phys || stack_phys_tgt != exp_stack_phys {
-                    logging::error("CR3 preflight: guard(low) phys mismatch in kernel root");
-                    logging::info_u64("expected_code_phys", exp_code_phys);
-                    logging::info_u64("got_code_phys", code_phys_tgt);
-                    logging::info_u64("expected_stack_phys", exp_stack_phys);
-                    logging::info_u64("got_stack_phys", stack_phys_tgt);
+                    LOG.error("CR3 preflight: guard(low) phys mismatch in kernel root");
+                    LOG.info_u64("expected_code_phys", exp_code_phys);
+                    LOG.info_u64("got_code_phys", code_phys_tgt);
+                    LOG.info_u64("expected_stack_phys", exp_stack_phys);
+                    LOG.info_u64("got_stack_phys", stack_phys_tgt);
                     panic!("CR3 preflight failed (guard low mismatch)");
                 }
             }
         } else {
-            logging::info("CR3 preflight: skipping guard(low) check for user root (by design)");
+            LOG.info("CR3 preflight: skipping guard(low) check for user root (by design)");
         }
 
         // guard(high) は user root でも必須
@@ -709,11 +1007,11 @@ fn preflight_check_before_cr3_write(target: MyPhysFrame) {
             let code_phys_tgt = translate_u64(&tgt_mapper, code_high);
             let stack_phys_tgt = translate_u64(&tgt_mapper, stack_high);
             if code_phys_tgt != exp_code_phys || stack_phys_tgt != exp_stack_phys {
-                logging::error("CR3 preflight: guard(high) phys mismatch in target");
-                logging::info_u64("expected_code_phys", exp_code_phys);
-                logging::info_u64("got_code_phys", code_phys_tgt);
-                logging::info_u64("expected_stack_phys", exp_stack_phys);
-                logging::info_u64("got_stack_phys", stack_phys_tgt);
+                LOG.error("CR3 preflight: guard(high) phys mismatch in target");
+                LOG.info_u64("expected_code_phys", exp_code_phys);
+                LOG.info_u64("got_code_phys", code_phys_tgt);
+                LOG.info_u64("expected_stack_phys", exp_stack_phys);
+                LOG.info_u64("got_stack_phys", stack_phys_tgt);
                 panic!("CR3 preflight failed (guard high mismatch)");
             }
         }
@@ -725,12 +1023,12 @@ fn preflight_check_before_cr3_write(target: MyPhysFrame) {
 // -----------------------------------------------------------------------------
 
 pub fn configure_cr3_switch_safety(code_addr: u64, stack_addr: u64) {
-    logging::info("arch::paging::configure_cr3_switch_safety");
-    logging::info_u64("code_addr", code_addr);
-    logging::info_u64("stack_addr", stack_addr);
+    LOG.info("arch::paging::configure_cr3_switch_safety");
+    LOG.info_u64("code_addr", code_addr);
+    LOG.info_u64("stack_addr", stack_addr);
 
     if !ENABLE_REAL_PAGING {
-        logging::info("CR3 real switch: DISABLED (real paging disabled)");
+        LOG.info("CR3 real switch: DISABLED (real paging disabled)");
         ALLOW_REAL_CR3_SWITCH.store(false, Ordering::Relaxed);
         return;
     }
@@ -742,7 +1040,7 @@ pub fn configure_cr3_switch_safety(code_addr: u64, stack_addr: u64) {
         let stack_p = mapper.translate_addr(VirtAddr::new(stack_addr)).map(|p| p.as_u64()).unwrap_or(0);
 
         if code_p == 0 || stack_p == 0 {
-            logging::error("CR3 real switch: DISABLED (translate failed)");
+            LOG.error("CR3 real switch: DISABLED (translate failed)");
             ALLOW_REAL_CR3_SWITCH.store(false, Ordering::Relaxed);
             return;
         }
@@ -755,9 +1053,9 @@ pub fn configure_cr3_switch_safety(code_addr: u64, stack_addr: u64) {
         GUARD_CODE_HIGH_VIRT.store(virt_layout::kernel_high_alias_of_low(code_addr), Ordering::Relaxed);
         GUARD_STACK_HIGH_VIRT.store(virt_layout::kernel_high_alias_of_low(stack_addr), Ordering::Relaxed);
 
-        logging::info("CR3 real switch: ENABLED (translate-based guard)");
-        logging::info_u64("expected_code_phys", code_p);
-        logging::info_u64("expected_stack_phys", stack_p);
+        LOG.info("CR3 real switch: ENABLED (translate-based guard)");
+        LOG.info_u64("expected_code_phys", code_p);
+        LOG.info_u64("expected_stack_phys", stack_p);
 
         ALLOW_REAL_CR3_SWITCH.store(true, Ordering::Relaxed);
     }
@@ -800,11 +1098,11 @@ pub fn switch_address_space_quiet_nocheck(frame: MyPhysFrame) {
 pub fn switch_address_space(root: Option<MyPhysFrame>) {
     match root {
         Some(frame) => {
-            logging::info("switch_address_space: would switch to root_page_frame");
-            logging::info_u64("root_page_frame_index", frame.number);
+            LOG.info("switch_address_space: would switch to root_page_frame");
+            LOG.info_u64("root_page_frame_index", frame.number);
 
             if !ALLOW_REAL_CR3_SWITCH.load(Ordering::Relaxed) {
-                logging::info("switch_address_space: CR3 switch skipped (guard disabled)");
+                LOG.info("switch_address_space: CR3 switch skipped (guard disabled)");
                 return;
             }
 
@@ -812,14 +1110,14 @@ pub fn switch_address_space(root: Option<MyPhysFrame>) {
             switch_address_space_quiet(frame);
         }
         None => {
-            logging::info("switch_address_space: no root_page_frame (None)");
+            LOG.info("switch_address_space: no root_page_frame (None)");
         }
     }
 }
 
 pub fn debug_translate_in_root(root: MyPhysFrame, virt_addr_u64: u64) {
     if !ENABLE_REAL_PAGING {
-        logging::info("debug_translate_in_root: REAL PAGING disabled");
+        LOG.info("debug_translate_in_root: REAL PAGING disabled");
         return;
     }
 
@@ -828,13 +1126,13 @@ pub fn debug_translate_in_root(root: MyPhysFrame, virt_addr_u64: u64) {
         let v = VirtAddr::new(virt_addr_u64);
         match mapper.translate_addr(v) {
             Some(p) => {
-                logging::info("translate: OK");
-                logging::info_u64("virt_addr", virt_addr_u64);
-                logging::info_u64("phys_addr", p.as_u64());
+                LOG.info("translate: OK");
+                LOG.info_u64("virt_addr", virt_addr_u64);
+                LOG.info_u64("phys_addr", p.as_u64());
             }
             None => {
-                logging::info("translate: NONE (not mapped)");
-                logging::info_u64("virt_addr", virt_addr_u64);
+                LOG.info("translate: NONE (not mapped)");
+                LOG.info_u64("virt_addr", virt_addr_u64);
             }
         }
     }
@@ -846,7 +1144,7 @@ pub fn debug_translate_in_root(root: MyPhysFrame, virt_addr_u64: u64) {
 
 pub fn install_kernel_high_alias_from_current() {
     if !ENABLE_REAL_PAGING {
-        logging::info("arch::paging::install_kernel_high_alias_from_current: skipped (real paging disabled)");
+        LOG.info("arch::paging::install_kernel_high_alias_from_current: skipped (real paging disabled)");
         return;
     }
 
@@ -877,9 +1175,9 @@ pub fn install_kernel_high_alias_from_current() {
 
     let dst_base = virt_layout::KERNEL_ALIAS_DST_PML4_BASE_INDEX;
 
-    logging::info("arch::paging::install_kernel_high_alias_from_current: start");
-    logging::info_u64("alias_dst_base_pml4", dst_base as u64);
-    logging::info_u64("alias_copy_count", copy_count as u64);
+    LOG.info("arch::paging::install_kernel_high_alias_from_current: start");
+    LOG.info_u64("alias_dst_base_pml4", dst_base as u64);
+    LOG.info_u64("alias_copy_count", copy_count as u64);
 
     unsafe {
         let pml4 = active_level_4_table();
@@ -892,23 +1190,23 @@ pub fn install_kernel_high_alias_from_current() {
             }
 
             if pml4[src].flags().contains(PageTableFlags::USER_ACCESSIBLE) {
-                logging::error("kernel alias source contains USER_ACCESSIBLE; abort");
-                logging::info_u64("src_pml4_index", src as u64);
+                LOG.error("kernel alias source contains USER_ACCESSIBLE; abort");
+                LOG.info_u64("src_pml4_index", src as u64);
                 panic!("kernel alias source contains USER_ACCESSIBLE");
             }
 
             pml4[dst] = pml4[src].clone();
 
-            logging::info("installed kernel alias pml4 entry");
-            logging::info_u64("src_pml4_index", src as u64);
-            logging::info_u64("dst_pml4_index", dst as u64);
+            LOG.info("installed kernel alias pml4 entry");
+            LOG.info_u64("src_pml4_index", src as u64);
+            LOG.info_u64("dst_pml4_index", dst as u64);
         }
 
         let (frame, flags) = Cr3::read();
         Cr3::write(frame, flags);
     }
 
-    logging::info("arch::paging::install_kernel_high_alias_from_current: done");
+    LOG.info("arch::paging::install_kernel_high_alias_from_current: done");
 
     let code_p_exp = GUARD_CODE_PHYS.load(Ordering::Relaxed);
     let stack_p_exp = GUARD_STACK_PHYS.load(Ordering::Relaxed);
@@ -923,18 +1221,18 @@ pub fn install_kernel_high_alias_from_current() {
             let stack_p = mapper.translate_addr(VirtAddr::new(stack_high)).map(|p| p.as_u64()).unwrap_or(0);
 
             if code_p != code_p_exp || stack_p != stack_p_exp {
-                logging::error("kernel high-alias self-check: FAILED");
-                logging::info_u64("expected_code_phys", code_p_exp);
-                logging::info_u64("actual_code_phys", code_p);
-                logging::info_u64("expected_stack_phys", stack_p_exp);
-                logging::info_u64("actual_stack_phys", stack_p);
+                LOG.error("kernel high-alias self-check: FAILED");
+                LOG.info_u64("expected_code_phys", code_p_exp);
+                LOG.info_u64("actual_code_phys", code_p);
+                LOG.info_u64("expected_stack_phys", stack_p_exp);
+                LOG.info_u64("actual_stack_phys", stack_p);
                 panic!("kernel high-alias mapping mismatch");
             }
         }
 
-        logging::info("kernel high-alias self-check: OK");
-        logging::info_u64("code_high_virt", virt_layout::kernel_high_alias_of_low(code_low));
-        logging::info_u64("stack_high_virt", virt_layout::kernel_high_alias_of_low(stack_low));
+        LOG.info("kernel high-alias self-check: OK");
+        LOG.info_u64("code_high_virt", virt_layout::kernel_high_alias_of_low(code_low));
+        LOG.info_u64("stack_high_virt", virt_layout::kernel_high_alias_of_low(stack_low));
     }
 
     if ENABLE_HIGH_ALIAS_EXEC_TEST {
@@ -960,17 +1258,17 @@ fn run_kernel_high_alias_exec_test() {
     let got = high_fn(arg);
 
     if got != expected {
-        logging::error("kernel high-alias exec test: FAILED");
-        logging::info_u64("low_fn_addr", low_addr);
-        logging::info_u64("high_fn_addr", high_addr);
-        logging::info_u64("expected", expected);
-        logging::info_u64("got", got);
+        LOG.error("kernel high-alias exec test: FAILED");
+        LOG.info_u64("low_fn_addr", low_addr);
+        LOG.info_u64("high_fn_addr", high_addr);
+        LOG.info_u64("expected", expected);
+        LOG.info_u64("got", got);
         panic!("kernel high-alias exec test failed");
     }
 
-    logging::info("kernel high-alias exec test: OK");
-    logging::info_u64("low_fn_addr", low_addr);
-    logging::info_u64("high_fn_addr", high_addr);
+    LOG.info("kernel high-alias exec test: OK");
+    LOG.info_u64("low_fn_addr", low_addr);
+    LOG.info_u64("high_fn_addr", high_addr);
 }
 
 // -----------------------------------------------------------------------------
@@ -984,6 +1282,94 @@ pub unsafe fn apply_mem_action(
     apply_mem_action_with_mapper(action, None, phys_mem)
 }
 
+#[inline]
+unsafe fn table_at(phys: u64) -> &'static mut PageTable {
+    &mut *(phys_to_virt(PhysAddr::new(phys)).as_mut_ptr::<PageTable>())
+}
+
+fn push_table_frame(out: &mut [Option<MyPhysFrame>], n: &mut usize, phys: u64) -> Result<(), PagingApplyError> {
+    let slot = out.get_mut(*n).ok_or(PagingApplyError::TableBufferFull)?;
+    *slot = Some(MyPhysFrame::from_index(phys / PAGE_SIZE));
+    *n += 1;
+    Ok(())
+}
+
+/// user root の USER slot（PML4[USER_PML4_INDEX]）の下のページテーブル（L3 / L2 / L1）を外し、そのフレームを out に入れる
+/// - leaf の user page は先に全部 unmap されていること（present な leaf が残っていれば何も変えずに Err）
+/// - out が足りなければ何も変えずに Err
+/// - フレームを返すのは呼び出し側（kernel。FrameFreed の記録を持つ）。戻り値は out に入れた数
+pub unsafe fn detach_user_page_tables_in_root(
+    root: MyPhysFrame,
+    out: &mut [Option<MyPhysFrame>],
+) -> Result<usize, PagingApplyError> {
+    if !ENABLE_REAL_PAGING {
+        return Ok(0);
+    }
+
+    let pml4 = table_at(root.start_address().0);
+    if pml4[USER_PML4_INDEX].is_unused() {
+        return Ok(0);
+    }
+
+    // 先に全部たどって集める（途中で Err なら entry はどれも変えていない）
+    let mut n = 0;
+    let l3_phys = pml4[USER_PML4_INDEX].addr().as_u64();
+    for e3 in table_at(l3_phys).iter() {
+        if e3.is_unused() {
+            continue;
+        }
+        if e3.flags().contains(PageTableFlags::HUGE_PAGE) {
+            return Err(PagingApplyError::UserSlotNotEmpty);
+        }
+        let l2_phys = e3.addr().as_u64();
+        for e2 in table_at(l2_phys).iter() {
+            if e2.is_unused() {
+                continue;
+            }
+            if e2.flags().contains(PageTableFlags::HUGE_PAGE) {
+                return Err(PagingApplyError::UserSlotNotEmpty);
+            }
+            let l1_phys = e2.addr().as_u64();
+            if table_at(l1_phys).iter().any(|e1| !e1.is_unused()) {
+                return Err(PagingApplyError::UserSlotNotEmpty);
+            }
+            push_table_frame(out, &mut n, l1_phys)?;
+        }
+        push_table_frame(out, &mut n, l2_phys)?;
+    }
+    push_table_frame(out, &mut n, l3_phys)?;
+
+    pml4[USER_PML4_INDEX].set_unused();
+    Ok(n)
+}
+
+/// user root の USER slot が空か（Dead task の root の検査用）
+pub fn user_slot_empty_in_root(root: MyPhysFrame) -> bool {
+    if !ENABLE_REAL_PAGING {
+        return true;
+    }
+    unsafe { table_at(root.start_address().0)[USER_PML4_INDEX].is_unused() }
+}
+
+/// root が今の CR3 か（None = 今の CR3 を触る apply_mem_action）
+fn root_is_active(root: Option<MyPhysFrame>) -> bool {
+    match root {
+        Some(r) => current_root().number == r.number,
+        None => true,
+    }
+}
+
+/// 今の CR3 の root
+pub fn current_root() -> MyPhysFrame {
+    let (frame, _) = Cr3::read();
+    MyPhysFrame::from_index(frame.start_address().as_u64() / PAGE_SIZE)
+}
+
+/// この CPU の TLB を全部捨てる（global でない entry。CR3 の再読込と同じ）
+pub fn flush_tlb_all() {
+    x86_64::instructions::tlb::flush_all();
+}
+
 pub unsafe fn apply_mem_action_in_root(
     action: MemAction,
     root: MyPhysFrame,
@@ -1000,9 +1386,9 @@ unsafe fn apply_mem_action_with_mapper(
     match action {
         MemAction::Map { page, frame, flags } => {
             if root.is_some() {
-                logging::info("arch::paging::apply_mem_action_in_root: Map");
+                LOG.info("arch::paging::apply_mem_action_in_root: Map");
             } else {
-                logging::info("arch::paging::apply_mem_action: Map");
+                LOG.info("arch::paging::apply_mem_action: Map");
             }
 
             let mut virt_u64 = page.start_address().0;
@@ -1017,15 +1403,15 @@ unsafe fn apply_mem_action_with_mapper(
             let virt = VirtAddr::new(virt_u64);
             enforce_user_mapping_policy(virt, xflags);
 
-            logging::info_u64("virt_addr", virt_u64);
-            logging::info_u64("phys_addr", phys_u64);
-            logging::info_u64("flags_bits", xflags.bits() as u64);
+            LOG.info_u64("virt_addr", virt_u64);
+            LOG.info_u64("phys_addr", phys_u64);
+            LOG.info_u64("flags_bits", xflags.bits() as u64);
 
             let page4k: Page<Size4KiB> = Page::containing_address(virt);
             let frame4k: PhysFrame<Size4KiB> = PhysFrame::containing_address(PhysAddr::new(phys_u64));
 
             if ENABLE_REAL_PAGING {
-                logging::info("REAL PAGING: map_to() will be executed");
+                LOG.info("REAL PAGING: map_to() will be executed");
 
                 let mut mapper = match root {
                     Some(r) => init_offset_page_table_for_root(r),
@@ -1035,12 +1421,17 @@ unsafe fn apply_mem_action_with_mapper(
 
                 match mapper.map_to(page4k, frame4k, xflags, &mut alloc) {
                     Ok(flush) => {
-                        flush.flush();
-                        logging::info("map_to: OK (flush done)");
+                        if root_is_active(root) {
+                            flush.flush();
+                            LOG.info("map_to: OK (flush done)");
+                        } else {
+                            flush.ignore();
+                            LOG.info("map_to: OK (flush deferred; root not active)");
+                        }
                         Ok(())
                     }
                     Err(e) => {
-                        logging::error("map_to: ERROR");
+                        LOG.error("map_to: ERROR");
                         log_map_to_error(e);
                         Err(PagingApplyError::MapFailed)
                     }
@@ -1052,9 +1443,9 @@ unsafe fn apply_mem_action_with_mapper(
 
         MemAction::Unmap { page } => {
             if root.is_some() {
-                logging::info("arch::paging::apply_mem_action_in_root: Unmap");
+                LOG.info("arch::paging::apply_mem_action_in_root: Unmap");
             } else {
-                logging::info("arch::paging::apply_mem_action: Unmap");
+                LOG.info("arch::paging::apply_mem_action: Unmap");
             }
 
             // VirtPage は「オフセット表現」。
@@ -1065,12 +1456,12 @@ unsafe fn apply_mem_action_with_mapper(
                 virt_u64 = USER_SPACE_BASE + virt_u64;
             }
 
-            logging::info_u64("virt_addr", virt_u64);
+            LOG.info_u64("virt_addr", virt_u64);
 
             let page4k: Page<Size4KiB> = Page::containing_address(VirtAddr::new(virt_u64));
 
             if ENABLE_REAL_PAGING {
-                logging::info("REAL PAGING: unmap() will be executed");
+                LOG.info("REAL PAGING: unmap() will be executed");
 
                 let mut mapper = match root {
                     Some(r) => init_offset_page_table_for_root(r),
@@ -1079,12 +1470,17 @@ unsafe fn apply_mem_action_with_mapper(
 
                 match mapper.unmap(page4k) {
                     Ok((_f, flush)) => {
-                        flush.flush();
-                        logging::info("unmap: OK (flush done)");
+                        if root_is_active(root) {
+                            flush.flush();
+                            LOG.info("unmap: OK (flush done)");
+                        } else {
+                            flush.ignore();
+                            LOG.info("unmap: OK (flush deferred; root not active)");
+                        }
                         Ok(())
                     }
                     Err(e) => {
-                        logging::error("unmap: ERROR");
+                        LOG.error("unmap: ERROR");
                         log_unmap_error(e);
                         Err(PagingApplyError::UnmapFailed)
                     }
@@ -1093,28 +1489,228 @@ unsafe fn apply_mem_action_with_mapper(
                 Ok(())
             }
         }
+
+        MemAction::Protect { page, new_flags } => {
+            if root.is_some() {
+                LOG.info("arch::paging::apply_mem_action_in_root: Protect");
+            } else {
+                LOG.info("arch::paging::apply_mem_action: Protect");
+            }
+
+            let mut virt_u64 = page.start_address().0;
+            let xflags = to_x86_flags(new_flags);
+            if xflags.contains(PageTableFlags::USER_ACCESSIBLE) {
+                virt_u64 += USER_SPACE_BASE;
+            }
+
+            let virt = VirtAddr::new(virt_u64);
+            enforce_user_mapping_policy(virt, xflags);
+
+            LOG.info_u64("virt_addr", virt_u64);
+            LOG.info_u64("flags_bits", xflags.bits());
+
+            let page4k: Page<Size4KiB> = Page::containing_address(virt);
+
+            if ENABLE_REAL_PAGING {
+                let mut mapper = match root {
+                    Some(r) => init_offset_page_table_for_root(r),
+                    None => init_offset_page_table(),
+                };
+
+                match mapper.update_flags(page4k, xflags) {
+                    Ok(flush) => {
+                        if root_is_active(root) {
+                            flush.flush();
+                            LOG.info("update_flags: OK (invlpg done)");
+                        } else {
+                            flush.ignore();
+                            LOG.info("update_flags: OK (flush deferred; root not active)");
+                        }
+                        Ok(())
+                    }
+                    Err(e) => {
+                        LOG.error("update_flags: ERROR");
+                        match e {
+                            FlagUpdateError::PageNotMapped => LOG.error("FlagUpdateError::PageNotMapped"),
+                            FlagUpdateError::ParentEntryHugePage => LOG.error("FlagUpdateError::ParentEntryHugePage"),
+                        }
+                        Err(PagingApplyError::ProtectFailed)
+                    }
+                }
+            } else {
+                Ok(())
+            }
+        }
+    }
+}
+
+/// MemAction の対象の仮想アドレス（apply_mem_action_with_mapper と同じ規則）
+/// - Map / Protect は USER の有無、Unmap は root の有無で USER_SPACE_BASE を足す
+fn mem_action_virt(action: MemAction, root: Option<MyPhysFrame>) -> VirtAddr {
+    let offset = action.page().start_address().0;
+    let user = match action {
+        MemAction::Map { flags, .. } => flags.contains(PageFlags::USER),
+        MemAction::Protect { new_flags, .. } => new_flags.contains(PageFlags::USER),
+        MemAction::Unmap { .. } => root.is_some(),
+    };
+    VirtAddr::new(if user { USER_SPACE_BASE + offset } else { offset })
+}
+
+/// batch の entry の逆操作（検査のときの leaf の状態から作る）
+#[derive(Clone, Copy)]
+enum BatchUndo {
+    /// Map した page を外す
+    Unmap(Page<Size4KiB>),
+    /// Unmap した page を元の frame / flags で戻す
+    Map(Page<Size4KiB>, PhysFrame<Size4KiB>, PageTableFlags),
+    /// Protect した page の flags を戻す
+    SetFlags(Page<Size4KiB>, PageTableFlags),
+}
+
+pub unsafe fn apply_mem_actions_in_root(
+    actions: &[MemAction],
+    root: MyPhysFrame,
+    phys_mem: &mut PhysicalMemoryManager,
+) -> Result<(), BatchError<PagingApplyError>> {
+    apply_mem_actions_with_mapper(actions, Some(root), phys_mem)
+}
+
+/// batch を all-or-nothing で反映する（検査 → 反映 → 途中で落ちたら反映済みを逆順に戻す）
+unsafe fn apply_mem_actions_with_mapper(
+    actions: &[MemAction],
+    root: Option<MyPhysFrame>,
+    phys_mem: &mut PhysicalMemoryManager,
+) -> Result<(), BatchError<PagingApplyError>> {
+    LOG.info("arch::paging::apply_mem_actions_in_root: batch");
+    LOG.info_u64("actions", actions.len() as u64);
+
+    if actions.len() > MAX_MEM_BATCH {
+        LOG.error("apply_mem_actions: batch too large; nothing applied");
+        return Err(BatchError { index: MAX_MEM_BATCH, error: PagingApplyError::BatchTooLarge });
+    }
+
+    let undo = match validate_mem_batch(actions, root) {
+        Ok(undo) => undo,
+        Err(e) => {
+            LOG.error("apply_mem_actions: entry rejected by validation; nothing applied");
+            LOG.info_u64("index", e.index as u64);
+            return Err(e);
+        }
+    };
+
+    for (index, &action) in actions.iter().enumerate() {
+        if let Err(error) = apply_mem_action_with_mapper(action, root, phys_mem) {
+            LOG.error("apply_mem_actions: entry failed; roll back applied entries");
+            LOG.info_u64("index", index as u64);
+            rollback_mem_batch(&undo[..index], root, phys_mem);
+            return Err(BatchError { index, error });
+        }
+    }
+    Ok(())
+}
+
+/// 全 entry を今のページテーブルで検査し、entry ごとの逆操作を返す（ページテーブルは書かない）
+unsafe fn validate_mem_batch(
+    actions: &[MemAction],
+    root: Option<MyPhysFrame>,
+) -> Result<[Option<BatchUndo>; MAX_MEM_BATCH], BatchError<PagingApplyError>> {
+    let mut undo = [None; MAX_MEM_BATCH];
+    if !ENABLE_REAL_PAGING {
+        return Ok(undo);
+    }
+
+    let mapper = match root {
+        Some(r) => init_offset_page_table_for_root(r),
+        None => init_offset_page_table(),
+    };
+
+    for (index, &action) in actions.iter().enumerate() {
+        let reject = |error| Err(BatchError { index, error });
+
+        let virt = mem_action_virt(action, root);
+        if actions[..index].iter().any(|&a| mem_action_virt(a, root) == virt) {
+            return reject(PagingApplyError::BatchDuplicatePage);
+        }
+        let xflags = match action {
+            MemAction::Map { flags, .. } => Some(to_x86_flags(flags)),
+            MemAction::Protect { new_flags, .. } => Some(to_x86_flags(new_flags)),
+            MemAction::Unmap { .. } => None,
+        };
+        if xflags.is_some_and(|f| f.contains(PageTableFlags::USER_ACCESSIBLE) != is_user_space_addr(virt)) {
+            return reject(PagingApplyError::PolicyViolation);
+        }
+
+        let page4k: Page<Size4KiB> = Page::containing_address(virt);
+        undo[index] = Some(match (action, mapper.translate(virt)) {
+            (MemAction::Map { .. }, TranslateResult::NotMapped) => BatchUndo::Unmap(page4k),
+            (MemAction::Map { .. }, _) => return reject(PagingApplyError::MapFailed),
+            (MemAction::Unmap { .. }, TranslateResult::Mapped { frame: MappedFrame::Size4KiB(f), flags, .. }) => {
+                BatchUndo::Map(page4k, f, flags)
+            }
+            (MemAction::Unmap { .. }, _) => return reject(PagingApplyError::UnmapFailed),
+            (MemAction::Protect { .. }, TranslateResult::Mapped { frame: MappedFrame::Size4KiB(_), flags, .. }) => {
+                BatchUndo::SetFlags(page4k, flags)
+            }
+            (MemAction::Protect { .. }, _) => return reject(PagingApplyError::ProtectFailed),
+        });
+    }
+    Ok(undo)
+}
+
+/// 反映済みの entry を逆順に戻す（戻せなければ fail-stop）
+unsafe fn rollback_mem_batch(undo: &[Option<BatchUndo>], root: Option<MyPhysFrame>, phys_mem: &mut PhysicalMemoryManager) {
+    if !ENABLE_REAL_PAGING {
+        return;
+    }
+
+    let mut mapper = match root {
+        Some(r) => init_offset_page_table_for_root(r),
+        None => init_offset_page_table(),
+    };
+    let mut alloc = KernelFrameAllocator::new(phys_mem);
+    let active = root_is_active(root);
+    let finish = |flush: MapperFlush<Size4KiB>| {
+        if active {
+            flush.flush();
+        } else {
+            flush.ignore();
+        }
+    };
+
+    for u in undo.iter().rev().flatten() {
+        let ok = match *u {
+            BatchUndo::Unmap(page) => mapper.unmap(page).map(|(_, flush)| finish(flush)).is_ok(),
+            BatchUndo::Map(page, frame, flags) => mapper.map_to(page, frame, flags, &mut alloc).map(finish).is_ok(),
+            BatchUndo::SetFlags(page, flags) => mapper.update_flags(page, flags).map(finish).is_ok(),
+        };
+        if !ok {
+            LOG.error("apply_mem_actions: rollback failed; abort (fail-stop)");
+            panic!("apply_mem_actions: rollback failed");
+        }
     }
+    LOG.info("apply_mem_actions: rolled back");
+    LOG.info_u64("entries", undo.iter().flatten().count() as u64);
 }
 
 fn log_map_to_error(err: MapToError<Size4KiB>) {
     match err {
-        MapToError::FrameAllocationFailed => logging::error("MapToError::FrameAllocationFailed"),
-        MapToError::ParentEntryHugePage => logging::error("MapToError::ParentEntryHugePage"),
+        MapToError::FrameAllocationFailed => LOG.error("MapToError::FrameAllocationFailed"),
+        MapToError::ParentEntryHugePage => LOG.error("MapToError::ParentEntryHugePage"),
         MapToError::PageAlreadyMapped(old) => {
-            logging::error("MapToError::PageAlreadyMapped");
-            logging::info_u64("already_mapped_phys_addr", old.start_address().as_u64());
+            LOG.error("MapToError::PageAlreadyMapped");
+            LOG.info_u64("already_mapped_phys_addr", old.start_address().as_u64());
         }
     }
 }
 
 fn log_unmap_error(err: UnmapError) {
     match err {
-        UnmapError::PageNotMapped => logging::error("UnmapError::PageNotMapped"),
+        UnmapError::PageNotMapped => LOG.error("UnmapError::PageNotMapped"),
         UnmapError::InvalidFrameAddress(p) => {
-            logging::error("UnmapError::InvalidFrameAddress");
-            logging::info_u64("invalid_frame_phys_addr", PhysAddr::from(p).as_u64());
+            LOG.error("UnmapError::InvalidFrameAddress");
+            LOG.info_u64("invalid_frame_phys_addr", PhysAddr::from(p).as_u64());
         }
-        UnmapError::ParentEntryHugePage => logging::error("UnmapError::ParentEntryHugePage"),
+        UnmapError::ParentEntryHugePage => LOG.error("UnmapError::ParentEntryHugePage"),
     }
 }
 
@@ -1159,8 +1755,8 @@ pub fn init_user_pml4_from_current(new_root: MyPhysFrame) {
                 continue;
             }
             if cur_p4[i].flags().contains(PageTableFlags::USER_ACCESSIBLE) {
-                logging::error("init_user_pml4_from_current: physmap entry has USER_ACCESSIBLE; abort");
-                logging::info_u64("pml4_index", i as u64);
+                LOG.error("init_user_pml4_from_current: physmap entry has USER_ACCESSIBLE; abort");
+                LOG.info_u64("pml4_index", i as u64);
                 panic!("physmap pml4 entry contains USER_ACCESSIBLE");
             }
             user_p4[i] = cur_p4[i].clone();
@@ -1170,14 +1766,14 @@ pub fn init_user_pml4_from_current(new_root: MyPhysFrame) {
         if user_p4[physmap_pml4].is_unused()
             || !user_p4[physmap_pml4].flags().contains(PageTableFlags::PRESENT)
         {
-            logging::error("init_user_pml4_from_current: physmap base entry missing after copy");
-            logging::info_u64("physmap_pml4_index", physmap_pml4 as u64);
+            LOG.error("init_user_pml4_from_current: physmap base entry missing after copy");
+            LOG.info_u64("physmap_pml4_index", physmap_pml4 as u64);
 
-            logging::info_u64("cur_entry_addr", cur_p4[physmap_pml4].addr().as_u64());
-            logging::info_u64("cur_entry_flags", cur_p4[physmap_pml4].flags().bits() as u64);
+            LOG.info_u64("cur_entry_addr", cur_p4[physmap_pml4].addr().as_u64());
+            LOG.info_u64("cur_entry_flags", cur_p4[physmap_pml4].flags().bits() as u64);
 
-            logging::info_u64("new_entry_addr", user_p4[physmap_pml4].addr().as_u64());
-            logging::info_u64("new_entry_flags", user_p4[physmap_pml4].flags().bits() as u64);
+            LOG.info_u64("new_entry_addr", user_p4[physmap_pml4].addr().as_u64());
+            LOG.info_u64("new_entry_flags", user_p4[physmap_pml4].flags().bits() as u64);
 
             panic!("init_user_pml4_from_current: physmap entry missing (post-check)");
         }
@@ -1188,8 +1784,8 @@ pub fn init_user_pml4_from_current(new_root: MyPhysFrame) {
                 continue;
             }
             if cur_p4[i].flags().contains(PageTableFlags::USER_ACCESSIBLE) {
-                logging::error("init_user_pml4_from_current: kernel pml4 entry has USER_ACCESSIBLE; abort");
-                logging::info_u64("pml4_index", i as u64);
+                LOG.error("init_user_pml4_from_current: kernel pml4 entry has USER_ACCESSIBLE; abort");
+                LOG.info_u64("pml4_index", i as u64);
                 panic!("kernel pml4 entry contains USER_ACCESSIBLE");
             }
             user_p4[i] = cur_p4[i].clone();
@@ -1205,23 +1801,23 @@ pub fn init_user_pml4_from_current(new_root: MyPhysFrame) {
                 continue;
             }
             if cur_p4[idx].flags().contains(PageTableFlags::USER_ACCESSIBLE) {
-                logging::error("init_user_pml4_from_current: alias window has USER_ACCESSIBLE; abort");
-                logging::info_u64("pml4_index", idx as u64);
+                LOG.error("init_user_pml4_from_current: alias window has USER_ACCESSIBLE; abort");
+                LOG.info_u64("pml4_index", idx as u64);
                 panic!("alias window pml4 entry contains USER_ACCESSIBLE");
             }
             user_p4[idx] = cur_p4[idx].clone();
         }
 
         // 3) USER slot は空
-        logging::info("init_user_pml4_from_current: clearing user pml4 entry");
-        logging::info_u64("pml4_index", USER_PML4_INDEX as u64);
+        LOG.info("init_user_pml4_from_current: clearing user pml4 entry");
+        LOG.info_u64("pml4_index", USER_PML4_INDEX as u64);
         user_p4[USER_PML4_INDEX].set_unused();
 
-        logging::info("init_user_pml4_from_current: copied kernel high-half + physmap (+alias window)");
-        logging::info_u64("kernel_pml4_base", 256);
-        logging::info_u64("physmap_pml4_index", physmap_pml4 as u64);
-        logging::info_u64("alias_dst_base_pml4", alias_base as u64);
-        logging::info_u64("alias_copy_count", alias_cnt as u64);
+        LOG.info("init_user_pml4_from_current: copied kernel high-half + physmap (+alias window)");
+        LOG.info_u64("kernel_pml4_base", 256);
+        LOG.info_u64("physmap_pml4_index", physmap_pml4 as u64);
+        LOG.info_u64("alias_dst_base_pml4", alias_base as u64);
+        LOG.info_u64("alias_copy_count", alias_cnt as u64);
     }
 }
 
@@ -1232,21 +1828,21 @@ pub fn init_user_pml4_from_current(new_root: MyPhysFrame) {
 pub fn debug_log_execution_context(tag: &str) {
     let (rip, rsp, rbp) = read_rip_rsp_rbp();
 
-    logging::info("exec_context:");
-    logging::info(tag);
-    logging::info_u64("rip", rip);
-    logging::info_u64("rsp", rsp);
-    logging::info_u64("rbp", rbp);
-    logging::info_u64("rip_pml4", virt_layout::pml4_index(rip) as u64);
-    logging::info_u64("rsp_pml4", virt_layout::pml4_index(rsp) as u64);
-    logging::info_u64("rbp_pml4", virt_layout::pml4_index(rbp) as u64);
+    LOG.info("exec_context:");
+    LOG.info(tag);
+    LOG.info_hex("rip", rip);
+    LOG.info_hex("rsp", rsp);
+    LOG.info_hex("rbp", rbp);
+    LOG.info_u64("rip_pml4", virt_layout::pml4_index(rip) as u64);
+    LOG.info_u64("rsp_pml4", virt_layout::pml4_index(rsp) as u64);
+    LOG.info_u64("rbp_pml4", virt_layout::pml4_index(rbp) as u64);
 }
 
 pub fn enter_kernel_high_alias(
     entry: extern "C" fn(&'static BootInfo) -> !,
     boot_info: &'static BootInfo,
 ) -> ! {
-    logging::info("enter_kernel_high_alias: switching stack and CALL high entry");
+    LOG.info("enter_kernel_high_alias: switching stack and CALL high entry");
 
     let low_entry = entry as usize as u64;
     let high_entry = virt_layout::kernel_high_alias_of_low(low_entry);
@@ -1267,12 +1863,12 @@ pub fn enter_kernel_high_alias(
     let rsp_high = virt_layout::kernel_high_alias_of_low(rsp_low) & !0xFu64;
     let rbp_high = virt_layout::kernel_high_alias_of_low(rbp_low);
 
-    logging::info_u64("low_entry", low_entry);
-    logging::info_u64("high_entry", high_entry);
-    logging::info_u64("rsp_low", rsp_low);
-    logging::info_u64("rsp_high_aligned", rsp_high);
-    logging::info_u64("rbp_low", rbp_low);
-    logging::info_u64("rbp_high", rbp_high);
+    LOG.info_u64("low_entry", low_entry);
+    LOG.info_u64("high_entry", high_entry);
+    LOG.info_u64("rsp_low", rsp_low);
+    LOG.info_u64("rsp_high_aligned", rsp_high);
+    LOG.info_u64("rbp_low", rbp_low);
+    LOG.info_u64("rbp_high", rbp_high);
 
     unsafe {
         core::arch::asm!(
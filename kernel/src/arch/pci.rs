@@ -0,0 +1,342 @@
+// kernel/src/arch/pci.rs
+//
+// 役割:
+// - PCI の configuration space を mechanism #1（port 0xCF8 / 0xCFC）で読み、つながっている device を列挙する。
+// - 見つけた device を値だけの構造体（PciDevice）の表に残し、ドライバ（arch::drivers）に safe な API で渡す。
+//   * devices(): 表を先頭から返す iterator（1 個ずつ写しを返す）
+//   * find(vendor, device): vendor / device ID で最初の 1 個
+//   * PciDevice::bar / enable_io_and_bus_master: BAR の解釈と command register の設定
+// - init()（arch::init、acpi の後）で 1 回だけ走査し、一覧をログに出す。
+//
+// 方針:
+// - port I/O（unsafe）はこのファイルの config_read32 / config_write32 だけに閉じ込める
+// - bus 0 から始め、PCI-to-PCI bridge の secondary bus を辿る（同じ bus は 2 回見ない）
+// - function 0 の header type が multi-function のときだけ function 1..7 を見る
+// - 表は固定長（MAX_PCI_DEVICES）。溢れた device は数だけ数える（pci_devices_ignored）
+//
+// やらないこと:
+// - PCIe の ECAM（MCFG）/ 拡張 config space（0x100 以上）
+// - BAR のサイズ測定・再配置、MSI / MSI-X、hotplug
+// - IRQ の routing（interrupt line の値をそのまま残すだけ）
+
+use spin::Mutex;
+use x86_64::instructions::port::Port;
+
+const LOG: crate::logging::Subsystem = crate::logging::Subsystem::Arch;
+
+/// 表に残す device の上限
+pub const MAX_PCI_DEVICES: usize = 32;
+
+const CONFIG_ADDR: u16 = 0xCF8;
+const CONFIG_DATA: u16 = 0xCFC;
+const CONFIG_ENABLE: u32 = 1 << 31;
+
+const REG_ID: u8 = 0x00;
+// command register（ドライバが enable_io_and_bus_master で書く）
+#[cfg_attr(not(any(feature = "virtio_console", feature = "virtio_net", feature = "virtio_blk")), allow(dead_code))]
+const REG_COMMAND: u8 = 0x04;
+const REG_CLASS: u8 = 0x08;
+const REG_HEADER: u8 = 0x0C;
+const REG_BAR0: u8 = 0x10;
+const REG_BRIDGE_BUSES: u8 = 0x18;
+const REG_INTERRUPT: u8 = 0x3C;
+
+const VENDOR_NONE: u16 = 0xFFFF;
+const HEADER_MULTIFUNCTION: u8 = 0x80;
+const HEADER_TYPE_MASK: u8 = 0x7F;
+const HEADER_TYPE_DEVICE: u8 = 0x00;
+const HEADER_TYPE_PCI_BRIDGE: u8 = 0x01;
+
+#[cfg_attr(not(any(feature = "virtio_console", feature = "virtio_net", feature = "virtio_blk")), allow(dead_code))]
+const COMMAND_IO: u16 = 1 << 0;
+#[cfg_attr(not(any(feature = "virtio_console", feature = "virtio_net", feature = "virtio_blk")), allow(dead_code))]
+const COMMAND_MEMORY: u16 = 1 << 1;
+#[cfg_attr(not(any(feature = "virtio_console", feature = "virtio_net", feature = "virtio_blk")), allow(dead_code))]
+const COMMAND_BUS_MASTER: u16 = 1 << 2;
+
+/// bus / device / function
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PciAddress {
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+}
+
+impl PciAddress {
+    /// ログ用の 16bit 表記（bus << 8 | device << 3 | function）
+    pub fn bdf(self) -> u16 {
+        (self.bus as u16) << 8 | (self.device as u16) << 3 | self.function as u16
+    }
+
+    fn config_address(self, offset: u8) -> u32 {
+        CONFIG_ENABLE
+            | (self.bus as u32) << 16
+            | (self.device as u32) << 11
+            | (self.function as u32) << 8
+            | (offset as u32 & 0xFC)
+    }
+}
+
+/// BAR の中身（サイズは測らない）
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Bar {
+    None,
+    Io { port: u16 },
+    Memory { phys: u64, prefetchable: bool },
+}
+
+/// 列挙した device（走査した時点の config space の写し）
+#[derive(Clone, Copy, Debug)]
+pub struct PciDevice {
+    pub addr: PciAddress,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub prog_if: u8,
+    pub revision: u8,
+    pub header_type: u8,
+    /// header type 0 は 6 本、bridge は 2 本（残りは 0）
+    pub bars: [u32; 6],
+    pub interrupt_line: u8,
+    pub interrupt_pin: u8,
+}
+
+impl PciDevice {
+    /// BAR i を解釈する（64bit BAR は i と i+1 を合わせる。i+1 側を指すと None）
+    pub fn bar(&self, i: usize) -> Bar {
+        if i >= self.bars.len() {
+            return Bar::None;
+        }
+        if i > 0 && is_bar_64bit(self.bars[i - 1]) {
+            return Bar::None;
+        }
+        let raw = self.bars[i];
+        if raw == 0 {
+            return Bar::None;
+        }
+        if raw & 1 != 0 {
+            return Bar::Io { port: (raw & !0x3) as u16 };
+        }
+        let mut phys = (raw & !0xF) as u64;
+        if is_bar_64bit(raw) && i + 1 < self.bars.len() {
+            phys |= (self.bars[i + 1] as u64) << 32;
+        }
+        Bar::Memory { phys, prefetchable: raw & 0x8 != 0 }
+    }
+
+    /// command register で I/O / memory の decode と bus master を有効にする（DMA するドライバ用）
+    #[cfg_attr(not(any(feature = "virtio_console", feature = "virtio_net", feature = "virtio_blk")), allow(dead_code))]
+    pub fn enable_io_and_bus_master(&self) {
+        let cmd = config_read32(self.addr, REG_COMMAND);
+        let on = (COMMAND_IO | COMMAND_MEMORY | COMMAND_BUS_MASTER) as u32;
+        // 上位 16bit は status（write-1-to-clear）なので 0 を書く
+        config_write32(self.addr, REG_COMMAND, (cmd & 0xFFFF) | on);
+    }
+
+    /// class の大まかな名前（ログ用）
+    pub fn class_name(&self) -> &'static str {
+        match (self.class, self.subclass) {
+            (0x01, 0x01) => "ide",
+            (0x01, 0x06) => "sata",
+            (0x01, 0x08) => "nvme",
+            (0x01, _) => "storage",
+            (0x02, _) => "network",
+            (0x03, _) => "display",
+            (0x04, _) => "multimedia",
+            (0x06, 0x00) => "host_bridge",
+            (0x06, 0x01) => "isa_bridge",
+            (0x06, 0x04) => "pci_bridge",
+            (0x06, _) => "bridge",
+            (0x07, _) => "communication",
+            (0x0C, 0x03) => "usb",
+            (0x0C, _) => "serial_bus",
+            _ => "other",
+        }
+    }
+}
+
+fn is_bar_64bit(raw: u32) -> bool {
+    raw & 1 == 0 && (raw >> 1) & 0x3 == 0x2
+}
+
+struct DeviceTable {
+    devices: [Option<PciDevice>; MAX_PCI_DEVICES],
+    count: usize,
+    ignored: usize,
+}
+
+static TABLE: Mutex<DeviceTable> = Mutex::new(DeviceTable { devices: [None; MAX_PCI_DEVICES], count: 0, ignored: 0 });
+
+fn config_read32(addr: PciAddress, offset: u8) -> u32 {
+    unsafe {
+        Port::<u32>::new(CONFIG_ADDR).write(addr.config_address(offset));
+        Port::<u32>::new(CONFIG_DATA).read()
+    }
+}
+
+#[cfg_attr(not(any(feature = "virtio_console", feature = "virtio_net", feature = "virtio_blk")), allow(dead_code))]
+fn config_write32(addr: PciAddress, offset: u8, value: u32) {
+    unsafe {
+        Port::<u32>::new(CONFIG_ADDR).write(addr.config_address(offset));
+        Port::<u32>::new(CONFIG_DATA).write(value);
+    }
+}
+
+/// bus を全部走査して表を作り、一覧をログに出す（arch::init から 1 回）
+pub fn init() {
+    let mut table = TABLE.lock();
+    table.devices = [None; MAX_PCI_DEVICES];
+    table.count = 0;
+    table.ignored = 0;
+
+    // 見た bus（bit = bus 番号）。bridge の設定が循環していても止まる
+    let mut seen = [0u64; 4];
+    scan_bus(0, &mut table, &mut seen);
+    let (count, ignored) = (table.count, table.ignored);
+    drop(table);
+
+    log_summary(count, ignored);
+}
+
+fn scan_bus(bus: u8, table: &mut DeviceTable, seen: &mut [u64; 4]) {
+    let (word, bit) = (bus as usize / 64, bus as usize % 64);
+    if seen[word] & (1 << bit) != 0 {
+        return;
+    }
+    seen[word] |= 1 << bit;
+
+    for device in 0..32u8 {
+        let f0 = PciAddress { bus, device, function: 0 };
+        if config_read32(f0, REG_ID) as u16 == VENDOR_NONE {
+            continue;
+        }
+        let multi = (config_read32(f0, REG_HEADER) >> 16) as u8 & HEADER_MULTIFUNCTION != 0;
+        let functions = if multi { 8 } else { 1 };
+        for function in 0..functions {
+            let addr = PciAddress { bus, device, function };
+            let Some(dev) = probe(addr) else {
+                continue;
+            };
+            if table.count < MAX_PCI_DEVICES {
+                let at = table.count;
+                table.devices[at] = Some(dev);
+                table.count += 1;
+            } else {
+                table.ignored += 1;
+            }
+            if dev.header_type == HEADER_TYPE_PCI_BRIDGE {
+                let secondary = (config_read32(addr, REG_BRIDGE_BUSES) >> 8) as u8;
+                if secondary != 0 {
+                    scan_bus(secondary, table, seen);
+                }
+            }
+        }
+    }
+}
+
+fn probe(addr: PciAddress) -> Option<PciDevice> {
+    let id = config_read32(addr, REG_ID);
+    if id as u16 == VENDOR_NONE {
+        return None;
+    }
+    let class = config_read32(addr, REG_CLASS);
+    let header_type = (config_read32(addr, REG_HEADER) >> 16) as u8 & HEADER_TYPE_MASK;
+    let bar_count = match header_type {
+        HEADER_TYPE_DEVICE => 6,
+        HEADER_TYPE_PCI_BRIDGE => 2,
+        _ => 0,
+    };
+    let mut bars = [0u32; 6];
+    for (i, bar) in bars.iter_mut().enumerate().take(bar_count) {
+        *bar = config_read32(addr, REG_BAR0 + 4 * i as u8);
+    }
+    let int = config_read32(addr, REG_INTERRUPT);
+
+    Some(PciDevice {
+        addr,
+        vendor_id: id as u16,
+        device_id: (id >> 16) as u16,
+        class: (class >> 24) as u8,
+        subclass: (class >> 16) as u8,
+        prog_if: (class >> 8) as u8,
+        revision: class as u8,
+        header_type,
+        bars,
+        interrupt_line: int as u8,
+        interrupt_pin: (int >> 8) as u8,
+    })
+}
+
+/// 列挙した device を先頭から 1 個ずつ返す iterator（next のたびに表の写しを取る）
+pub struct Devices {
+    next: usize,
+}
+
+impl Iterator for Devices {
+    type Item = PciDevice;
+
+    fn next(&mut self) -> Option<PciDevice> {
+        let table = TABLE.lock();
+        let dev = table.devices.get(self.next).copied().flatten();
+        if dev.is_some() {
+            self.next += 1;
+        }
+        dev
+    }
+}
+
+/// 列挙した device（init の前は空）
+pub fn devices() -> Devices {
+    Devices { next: 0 }
+}
+
+/// vendor / device ID が一致する最初の device
+#[cfg_attr(not(any(feature = "virtio_console", feature = "virtio_net", feature = "virtio_blk")), allow(dead_code))]
+pub fn find(vendor_id: u16, device_id: u16) -> Option<PciDevice> {
+    devices().find(|d| d.vendor_id == vendor_id && d.device_id == device_id)
+}
+
+/// "pci: device <class>" を 1 行で出す
+fn log_device_line(class_name: &str) {
+    let mut buf = [0u8; 32];
+    let mut n = 0;
+    for &b in b"pci: device ".iter().chain(class_name.as_bytes()) {
+        if n < buf.len() {
+            buf[n] = b;
+            n += 1;
+        }
+    }
+    let s = unsafe { core::str::from_utf8_unchecked(&buf[..n]) };
+    LOG.info(s);
+}
+
+fn log_summary(count: usize, ignored: usize) {
+    LOG.info("=== PCI Devices ===");
+    for d in devices() {
+        log_device_line(d.class_name());
+        LOG.info_hex("pci_bdf", d.addr.bdf() as u64);
+        LOG.info_hex("pci_vendor_id", d.vendor_id as u64);
+        LOG.info_hex("pci_device_id", d.device_id as u64);
+        LOG.info_hex("pci_class", (d.class as u64) << 16 | (d.subclass as u64) << 8 | d.prog_if as u64);
+        LOG.info_u64("pci_revision", d.revision as u64);
+        for i in 0..d.bars.len() {
+            match d.bar(i) {
+                Bar::None => {}
+                Bar::Io { port } => LOG.info_hex("pci_bar_io", port as u64),
+                Bar::Memory { phys, prefetchable } => {
+                    LOG.info_hex("pci_bar_mem", phys);
+                    if prefetchable {
+                        LOG.info("pci: bar prefetchable");
+                    }
+                }
+            }
+        }
+        if d.interrupt_pin != 0 {
+            LOG.info_u64("pci_irq_line", d.interrupt_line as u64);
+        }
+    }
+    LOG.info_u64("pci_devices", count as u64);
+    LOG.info_u64("pci_devices_ignored", ignored as u64);
+    LOG.info("=== End of PCI Devices ===");
+}
@@ -0,0 +1,131 @@
+// kernel/src/arch/pic.rs
+//
+// 役割:
+// - 8259 PIC（master / slave）の remap・IRQ ごとの mask・EOI・spurious 判定（ISR の読み出し）。
+// - xAPIC の有無を CPUID で調べてログに出す（検出だけ。使うのは 8259）。
+//
+// 設計方針:
+// - vector は 0x20..0x2F（例外 0..31 と重ならないよう remap）。IRQ n = PIC1_OFFSET + n
+// - remap 直後は全 IRQ を mask。開けるのは arch::interrupts（timer は timer::start、他は allow_external_irqs）
+// - slave の IRQ（8..15）を開けるときは master の cascade（IRQ2）も開ける
+// - IRQ7 / IRQ15 は ISR を見て本物か spurious かを判定する（spurious には EOI しない。IRQ15 は master にだけ送る）
+//
+// やらないこと:
+// - LAPIC / IOAPIC の初期化と切替（xAPIC は検出してログに出すだけ）
+// - 自動 EOI / special mask mode
+
+use x86_64::instructions::port::Port;
+
+const LOG: crate::logging::Subsystem = crate::logging::Subsystem::Arch;
+
+/// remap 後の vector（IRQ0 = PIC1_OFFSET、IRQ8 = PIC2_OFFSET）
+pub const PIC1_OFFSET: u8 = 0x20;
+pub const PIC2_OFFSET: u8 = 0x28;
+
+/// IRQ の数（master 8 + slave 8）
+pub const IRQ_COUNT: usize = 16;
+
+/// slave がつながる master の IRQ
+const CASCADE_IRQ: u8 = 2;
+
+const PIC1_CMD: u16 = 0x20;
+const PIC1_DATA: u16 = 0x21;
+const PIC2_CMD: u16 = 0xA0;
+const PIC2_DATA: u16 = 0xA1;
+const PIC_EOI: u8 = 0x20;
+/// OCW3: 次の command port の read で ISR を返す
+const PIC_READ_ISR: u8 = 0x0B;
+
+/// CPUID.01h:EDX の APIC bit
+const CPUID_EDX_APIC: u32 = 1 << 9;
+
+/// IRQ 番号の vector
+pub const fn irq_vector(irq: u8) -> u8 {
+    PIC1_OFFSET + irq
+}
+
+/// PIC を remap し、全 IRQ を mask する（IF は触らない）
+pub fn init() {
+    unsafe {
+        let mut c1 = Port::<u8>::new(PIC1_CMD);
+        let mut d1 = Port::<u8>::new(PIC1_DATA);
+        let mut c2 = Port::<u8>::new(PIC2_CMD);
+        let mut d2 = Port::<u8>::new(PIC2_DATA);
+
+        // ICW1: init + ICW4 あり
+        c1.write(0x11);
+        c2.write(0x11);
+        // ICW2: vector offset
+        d1.write(PIC1_OFFSET);
+        d2.write(PIC2_OFFSET);
+        // ICW3: master の IRQ2 に slave
+        d1.write(1 << CASCADE_IRQ);
+        d2.write(CASCADE_IRQ);
+        // ICW4: 8086 mode
+        d1.write(0x01);
+        d2.write(0x01);
+
+        // 全部 mask（開けるのは呼び出し側）
+        d1.write(0xFF);
+        d2.write(0xFF);
+    }
+}
+
+/// IRQ（0..15）を mask / unmask する
+/// - slave の IRQ を unmask するときは cascade（IRQ2）も unmask する
+pub fn set_irq_masked(irq: u8, masked: bool) {
+    if irq as usize >= IRQ_COUNT {
+        return;
+    }
+    let (port, bit) = if irq < 8 { (PIC1_DATA, 1u8 << irq) } else { (PIC2_DATA, 1u8 << (irq - 8)) };
+    unsafe {
+        let mut data = Port::<u8>::new(port);
+        let v = data.read();
+        data.write(if masked { v | bit } else { v & !bit });
+    }
+    if irq >= 8 && !masked {
+        set_irq_masked(CASCADE_IRQ, false);
+    }
+}
+
+/// IRQ の EOI（slave の IRQ は slave → master の順に送る）
+pub fn end_of_interrupt(irq: u8) {
+    unsafe {
+        if irq >= 8 {
+            Port::<u8>::new(PIC2_CMD).write(PIC_EOI);
+        }
+        Port::<u8>::new(PIC1_CMD).write(PIC_EOI);
+    }
+}
+
+/// IRQ7 / IRQ15 が spurious か（その PIC の ISR に bit が立っていない）
+/// - IRQ15 の spurious でも master は cascade を in-service にしているので、呼び出し側が master にだけ EOI する
+pub fn is_spurious(irq: u8) -> bool {
+    let (cmd, bit) = match irq {
+        7 => (PIC1_CMD, 1u8 << 7),
+        15 => (PIC2_CMD, 1u8 << 7),
+        _ => return false,
+    };
+    unsafe {
+        let mut port = Port::<u8>::new(cmd);
+        port.write(PIC_READ_ISR);
+        port.read() & bit == 0
+    }
+}
+
+/// spurious IRQ15 の後始末（master の cascade だけ EOI）
+pub fn end_of_spurious(irq: u8) {
+    if irq == 15 {
+        unsafe { Port::<u8>::new(PIC1_CMD).write(PIC_EOI) };
+    }
+}
+
+/// CPUID で xAPIC があるかを調べてログに出す（切替はしない）
+pub fn log_apic_presence() {
+    let edx = unsafe { core::arch::x86_64::__cpuid(1).edx };
+    if edx & CPUID_EDX_APIC != 0 {
+        LOG.info("pic: xAPIC present (not used; IRQs go through the 8259 PIC)");
+    } else {
+        LOG.info("pic: no xAPIC (8259 PIC only)");
+    }
+}
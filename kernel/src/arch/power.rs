@@ -0,0 +1,66 @@
+// kernel/src/arch/power.rs
+//
+// 役割:
+// - 電源を切る（kernel::shutdown の最後。戻らない）。
+//   1. QEMU の isa-debug-exit に終了値を書く（device があれば QEMU がその終了コードで止まる）
+//   2. ACPI の soft-off（S5）: PM1a（と PM1b）control block に SLP_TYP | SLP_EN を書く（arch::acpi::s5）
+//   3. どちらも効かなければ halt_loop
+//
+// 方針:
+// - 割り込みは最初に止める（timer IRQ が途中で tick を回さない）
+// - isa-debug-exit を先に試す（自動実行では pass / fail を終了コードで返したい。ACPI の S5 は常に 0 終了）
+// - ACPI は BIOS が ACPI mode（SCI_EN = 1）にしている前提（QEMU の SeaBIOS はそう）。SMI_CMD での切り替えはしない
+// - 手順ごとに 1 行ログを出す（どこで止まったかを serial で追える）
+//
+// やらないこと:
+// - reboot（reset register / 8042）、S1..S4
+
+use x86_64::instructions::{interrupts, port::Port};
+
+use super::acpi;
+use super::qemu::{self, QemuExitCode};
+
+const LOG: crate::logging::Subsystem = crate::logging::Subsystem::Arch;
+
+/// PM1 control の SLP_TYP（bit10-12）と SLP_EN（bit13）
+const PM1_CNT_SLP_TYP_SHIFT: u16 = 10;
+const PM1_CNT_SLP_TYP_MASK: u16 = 0x7 << PM1_CNT_SLP_TYP_SHIFT;
+const PM1_CNT_SLP_EN: u16 = 1 << 13;
+
+/// S5 を書いてから電源が落ちるのを待つ回数の上限
+const POWER_OFF_SPIN_LIMIT: u32 = 10_000_000;
+
+/// 電源を切る（success は isa-debug-exit の終了値に使う）
+pub fn power_off(success: bool) -> ! {
+    interrupts::disable();
+
+    LOG.info("power: isa-debug-exit");
+    qemu::write_exit(if success { QemuExitCode::Success } else { QemuExitCode::Failed });
+
+    match acpi::s5() {
+        Some(s5) => {
+            LOG.info("power: ACPI S5");
+            unsafe {
+                write_slp(s5.pm1a_cnt, s5.slp_typ_a);
+                if s5.pm1b_cnt != 0 {
+                    write_slp(s5.pm1b_cnt, s5.slp_typ_b);
+                }
+            }
+            for _ in 0..POWER_OFF_SPIN_LIMIT {
+                core::hint::spin_loop();
+            }
+            LOG.error("power: ACPI S5 did not power off");
+        }
+        None => LOG.error("power: no ACPI S5"),
+    }
+
+    LOG.error("power: poweroff failed; halt");
+    super::halt_loop()
+}
+
+/// PM1 control に SLP_TYP | SLP_EN を書く（他の bit は読んだ値のまま）
+unsafe fn write_slp(port: u16, slp_typ: u16) {
+    let mut p = Port::<u16>::new(port);
+    let v = p.read() & !PM1_CNT_SLP_TYP_MASK;
+    p.write(v | (slp_typ << PM1_CNT_SLP_TYP_SHIFT) | PM1_CNT_SLP_EN);
+}
@@ -0,0 +1,38 @@
+// kernel/src/arch/qemu.rs
+//
+// 役割:
+// - QEMU の isa-debug-exit device に書いて、QEMU ごと終了させる（自動実行で pass/fail を終了コードにする）。
+//
+// 前提:
+// - QEMU を `-device isa-debug-exit,iobase=0xf4,iosize=0x04` 付きで起動していること
+//   * QEMU の終了コードは (value << 1) | 1 になる（Success = 33、Failed = 35）
+// - device が無い（実機 / 引数なし）なら書いても何も起きないので、halt に落ちる
+//   （arch::power は write_exit で書いてから ACPI poweroff に進む）
+
+use x86_64::instructions::port::Port;
+
+/// isa-debug-exit の I/O port（QEMU の iobase と合わせる）
+const ISA_DEBUG_EXIT_PORT: u16 = 0xf4;
+
+/// QEMU に渡す終了値（0 / 1 は QEMU 自身の終了コードとぶつかるので避ける）
+#[derive(Clone, Copy)]
+#[repr(u32)]
+pub enum QemuExitCode {
+    Success = 0x10,
+    Failed = 0x11,
+}
+
+/// QEMU を終了させる（戻らない）
+#[cfg_attr(not(feature = "qemu_exit"), allow(dead_code))]
+pub fn exit_qemu(code: QemuExitCode) -> ! {
+    write_exit(code);
+    // device が無かった
+    super::halt_loop()
+}
+
+/// isa-debug-exit に書く（device があれば戻らない。無ければ何も起きずに戻る。arch::power 用）
+pub fn write_exit(code: QemuExitCode) {
+    unsafe {
+        Port::<u32>::new(ISA_DEBUG_EXIT_PORT).write(code as u32);
+    }
+}
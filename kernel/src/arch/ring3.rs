@@ -15,13 +15,14 @@
 // - MVP では ring3 へ入る時に IF=0 にして外部 IRQ による事故を避ける。
 //   （int 0x80 は IF=0 でも動く）
 // - 戻りは int 0x80 handler 側で停止する。
+// - 最初の iretq も arch::trapframe で検査する（int80 からの戻りと同じ境界条件）。
 
 /// ring3 用の RFLAGS を作る。
 /// - bit1 は常に 1（予約ビット）
 /// - MVP では IF=0（外部割り込み無効）にして安定化
 #[inline(always)]
 fn rflags_user_mvp() -> u64 {
-    1u64 << 1 // 0x2
+    crate::arch::trapframe::RFLAGS_FIXED1 // 0x2
 }
 
 /// ring3 へ遷移する（戻らない想定）。
@@ -38,6 +39,22 @@ pub unsafe fn enter_user_mode_iretq(
 ) -> ! {
     let rflags = rflags_user_mvp();
 
+    let frame = crate::arch::trapframe::UserTrapFrame {
+        rip: user_rip,
+        cs: user_cs as u64,
+        rflags,
+        rsp: user_rsp,
+        ss: user_ss as u64,
+    };
+    if let Err((violation, value)) = crate::arch::trapframe::check_user_trap_frame(&frame) {
+        crate::arch::interrupts::emergency_write_str("[RING3] initial frame rejected check=");
+        crate::arch::interrupts::emergency_write_str(violation.name());
+        crate::arch::interrupts::emergency_write_str(" value=");
+        crate::arch::interrupts::emergency_write_hex_u64(value);
+        crate::arch::interrupts::emergency_write_str("\n");
+        crate::arch::halt_loop();
+    }
+
     core::arch::asm!(
     // iretq フレーム: SS, RSP, RFLAGS, CS, RIP
     "push {ss}",
@@ -0,0 +1,210 @@
+// kernel/src/arch/rtc.rs
+//
+// 役割:
+// - 起動時に CMOS RTC（port 0x70 / 0x71）から壁時計（UTC 前提の年月日時分秒）を 1 回読む。
+// - kernel::time::boot_wallclock から読めるように残し、ログと [REC] boot_wallclock に出す（ホスト側の時刻と突き合わせる用）。
+//
+// 方針:
+// - update-in-progress（status A bit7）が立っている間は待ち、同じ値が 2 回続けて読めるまで読み直す
+// - status B で BCD / binary と 12h / 24h を見分けて、binary / 24h に直す
+// - 年は 2000 + 下 2 桁（century register は読まない）
+// - 読めなかった（UIP が落ちない / 値が範囲外）ときは None のまま（kernel の動作には使わない）
+//
+// やらないこと:
+// - RTC への書き込み・alarm / periodic 割り込み（IRQ8）
+// - timezone / 夏時間の扱い（RTC は UTC として読む）
+// - 起動後の壁時計の更新（起動時点の値と kernel::time の経過 ns を足して見る）
+
+use spin::Mutex;
+use x86_64::instructions::port::Port;
+
+const LOG: crate::logging::Subsystem = crate::logging::Subsystem::Arch;
+
+const CMOS_ADDR: u16 = 0x70;
+const CMOS_DATA: u16 = 0x71;
+// bit7 は NMI disable。読むときも立てておく（NMI を RTC の index 書き込みと混ぜない）
+const CMOS_NMI_DISABLE: u8 = 0x80;
+
+const REG_SECONDS: u8 = 0x00;
+const REG_MINUTES: u8 = 0x02;
+const REG_HOURS: u8 = 0x04;
+const REG_DAY: u8 = 0x07;
+const REG_MONTH: u8 = 0x08;
+const REG_YEAR: u8 = 0x09;
+const REG_STATUS_A: u8 = 0x0A;
+const REG_STATUS_B: u8 = 0x0B;
+
+const STATUS_A_UPDATE_IN_PROGRESS: u8 = 1 << 7;
+const STATUS_B_24H: u8 = 1 << 1;
+const STATUS_B_BINARY: u8 = 1 << 2;
+const HOURS_PM: u8 = 0x80;
+
+/// UIP が落ちるのを待つ回数の上限（更新は 1 秒に 1 回・2ms 弱）
+const UIP_SPIN_LIMIT: u32 = 1_000_000;
+/// 2 回続けて同じ値が読めるまでの読み直しの上限
+const READ_RETRY_LIMIT: u32 = 8;
+
+/// RTC から読んだ壁時計（binary / 24h に直したもの）
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct WallClock {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl WallClock {
+    /// 1970-01-01 00:00:00 UTC からの秒
+    pub fn unix_seconds(&self) -> u64 {
+        let days = days_from_civil(self.year as i64, self.month as i64, self.day as i64);
+        days as u64 * 86_400 + self.hour as u64 * 3_600 + self.minute as u64 * 60 + self.second as u64
+    }
+
+    fn is_valid(&self) -> bool {
+        (2000..=2099).contains(&self.year)
+            && (1..=12).contains(&self.month)
+            && (1..=31).contains(&self.day)
+            && self.hour < 24
+            && self.minute < 60
+            && self.second < 60
+    }
+}
+
+/// 起動時に読んだ値（init の前・読めなかったときは None）
+static BOOT_WALLCLOCK: Mutex<Option<WallClock>> = Mutex::new(None);
+
+/// RTC を読んで残す（arch::init から 1 回）
+pub fn init() {
+    let Some(wc) = read() else {
+        LOG.error("rtc: wall clock not available");
+        return;
+    };
+    *BOOT_WALLCLOCK.lock() = Some(wc);
+
+    LOG.info("rtc: boot wall clock (UTC)");
+    LOG.info_u64("rtc_year", wc.year as u64);
+    LOG.info_u64("rtc_month", wc.month as u64);
+    LOG.info_u64("rtc_day", wc.day as u64);
+    LOG.info_u64("rtc_hour", wc.hour as u64);
+    LOG.info_u64("rtc_minute", wc.minute as u64);
+    LOG.info_u64("rtc_second", wc.second as u64);
+    LOG.info_u64("rtc_unix_seconds", wc.unix_seconds());
+
+    use crate::logging::record;
+    record::begin("boot_wallclock");
+    record::field("year", wc.year as u64);
+    record::field("month", wc.month as u64);
+    record::field("day", wc.day as u64);
+    record::field("hour", wc.hour as u64);
+    record::field("minute", wc.minute as u64);
+    record::field("second", wc.second as u64);
+    record::field("unix", wc.unix_seconds());
+    record::end();
+}
+
+/// 起動時の壁時計（kernel::time 用）
+pub fn boot_wallclock() -> Option<WallClock> {
+    *BOOT_WALLCLOCK.lock()
+}
+
+fn cmos_read(reg: u8) -> u8 {
+    unsafe {
+        Port::<u8>::new(CMOS_ADDR).write(CMOS_NMI_DISABLE | reg);
+        Port::<u8>::new(CMOS_DATA).read()
+    }
+}
+
+fn wait_update_done() -> bool {
+    for _ in 0..UIP_SPIN_LIMIT {
+        if cmos_read(REG_STATUS_A) & STATUS_A_UPDATE_IN_PROGRESS == 0 {
+            return true;
+        }
+        core::hint::spin_loop();
+    }
+    false
+}
+
+/// 生の register 値（BCD / 12h のまま）
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct RawTime {
+    second: u8,
+    minute: u8,
+    hour: u8,
+    day: u8,
+    month: u8,
+    year: u8,
+}
+
+fn read_raw() -> Option<RawTime> {
+    if !wait_update_done() {
+        return None;
+    }
+    Some(RawTime {
+        second: cmos_read(REG_SECONDS),
+        minute: cmos_read(REG_MINUTES),
+        hour: cmos_read(REG_HOURS),
+        day: cmos_read(REG_DAY),
+        month: cmos_read(REG_MONTH),
+        year: cmos_read(REG_YEAR),
+    })
+}
+
+/// 同じ値が 2 回続けて読めるまで読み直す（読んでいる途中の更新で桁がずれるのを避ける）
+fn read() -> Option<WallClock> {
+    let mut prev = read_raw()?;
+    for _ in 0..READ_RETRY_LIMIT {
+        let cur = read_raw()?;
+        if cur == prev {
+            return decode(cur, cmos_read(REG_STATUS_B));
+        }
+        prev = cur;
+    }
+    LOG.error("rtc: value kept changing while reading");
+    None
+}
+
+fn bcd_to_binary(v: u8) -> u8 {
+    (v >> 4) * 10 + (v & 0x0F)
+}
+
+fn decode(raw: RawTime, status_b: u8) -> Option<WallClock> {
+    let conv = |v: u8| if status_b & STATUS_B_BINARY != 0 { v } else { bcd_to_binary(v) };
+
+    let pm = raw.hour & HOURS_PM != 0;
+    let mut hour = conv(raw.hour & !HOURS_PM);
+    if status_b & STATUS_B_24H == 0 {
+        // 12h: 12 AM = 0 時、12 PM = 12 時
+        hour %= 12;
+        if pm {
+            hour += 12;
+        }
+    }
+
+    let wc = WallClock {
+        year: 2000 + conv(raw.year) as u16,
+        month: conv(raw.month),
+        day: conv(raw.day),
+        hour,
+        minute: conv(raw.minute),
+        second: conv(raw.second),
+    };
+    if !wc.is_valid() {
+        LOG.error("rtc: value out of range");
+        LOG.info_hex("rtc_status_b", status_b as u64);
+        return None;
+    }
+    Some(wc)
+}
+
+/// 1970-01-01 からの日数（proleptic Gregorian。Howard Hinnant の days_from_civil）
+fn days_from_civil(y: i64, m: i64, d: i64) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (m + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
@@ -0,0 +1,408 @@
+// kernel/src/arch/smp.rs
+//
+// 役割（feature smp）:
+// - ACPI MADT（arch::acpi が init で読んだもの）に載っている AP（BSP 以外の CPU）を INIT / SIPI / SIPI で起こす。
+// - AP ごとに GDT/TSS（gdt::init_ap）と共有の IDT（interrupts::load_idt_on_this_cpu）をロードさせ、
+//   online になったことを per-CPU の表（CPU_STATE / CPU_APIC_ID）に記録させる。
+//
+// 起動の流れ（BSP、start_aps。bootstrap の後・seal の前に 1 回）:
+// - 1MiB 未満のフレームを PMM から取り、TRAMPOLINE を書き込んで identity map する
+// - LAPIC の register を予約して MMIO window に uncached で map する（paging::map_mmio。できなければ physmap 経由）
+// - LAPIC の ICR（MMIO）で INIT → 10ms → SIPI → 200us → （まだなら）SIPI、online を最大 100ms 待つ
+// - AP は 1 つずつ起こす（trampoline の stack / cpu 番号の欄を使い回すため）
+//
+// trampoline（物理 B。4KiB 境界・1MiB 未満で、SIPI の vector = B >> 12）:
+// - 0x00 real mode: DS = CS、lgdt [B+0xA0]、CR0.PE → far jmp 0x08:B+0x20
+// - 0x20 32bit:     DS/ES/SS = 0x10、CR4.PAE、CR3 = [B+0xA8]、EFER.LME|NXE、CR0.PG|WP → far jmp 0x18:B+0x60
+// - 0x60 64bit:     rsp = [B+0xB0]、rdi = [B+0xC0]、call [B+0xB8]（ap_entry。戻らない）
+// - 0x80 一時 GDT（null / 32bit code / data / 64bit code）、0xA0 GDTR、0xA8.. は BSP が書く欄
+// - 即値のアドレス（jmp 先 / 欄の位置）は B に合わせて start_aps が書き換える
+//
+// AP 側（ap_entry）:
+// - GDT/TSS → IDT → online を記録 → seal を待って with_kernel_state（BKL）で check-in
+// - run_tasks: BSP の tick（timer::ticks）が進むたびに with_kernel_state で KernelState::ap_step を 1 回呼び、
+//   task を 1 step 走らせる（kernel/smp.rs）。IF は落としたまま（timer IRQ は 8259 経由で BSP にだけ来る）
+// - kernel::secondary_cpus_stopping か ap_step が false を返したら、parked を記録して cli; hlt で park
+// - 自分ではログを出さない（ap_step の中の task のログは BKL を持って出る。起動の結果は BSP が start_aps の戻り値で出す）
+//
+// やらないこと:
+// - AP での tick（時間を進めるのは BSP の timer IRQ だけ）
+// - x2APIC（firmware が x2APIC を有効にしていたら AP は起こさない）
+// - AP の再起動、trampoline フレームの返却（park したまま・起動後も持ったまま）
+
+use core::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+use x86_64::registers::model_specific::Msr;
+
+use super::{acpi, gdt, interrupts, paging, timer, virt_layout};
+use crate::mm::PhysicalMemoryManager;
+
+const LOG: crate::logging::Subsystem = crate::logging::Subsystem::Arch;
+
+/// 扱う CPU の上限（BSP を含む。cpu 番号 0 = BSP）
+pub const MAX_CPUS: usize = acpi::MADT_MAX_CPUS;
+
+/// SIPI で飛べるのは 1MiB 未満
+const TRAMPOLINE_LIMIT: u64 = 0x10_0000;
+
+// trampoline の中の配置
+const T_PM_ENTRY: u64 = 0x20;
+const T_LM_ENTRY: u64 = 0x60;
+const T_GDT: u64 = 0x80;
+const T_GDTR: u64 = 0xA0;
+const T_CR3: u64 = 0xA8;
+const T_STACK: u64 = 0xB0;
+const T_ENTRY: u64 = 0xB8;
+const T_CPU: u64 = 0xC0;
+
+// 書き換える 32bit 即値の位置
+const P_RM_JMP: u64 = 0x17;
+const P_PM_CR3: u64 = 0x34;
+const P_PM_JMP: u64 = 0x55;
+const P_LM_STACK: u64 = 0x64;
+const P_LM_CPU: u64 = 0x6C;
+const P_LM_ENTRY: u64 = 0x74;
+
+/// 一時 GDT（null / 0x08 = 32bit code / 0x10 = data / 0x18 = 64bit code）
+const TRAMPOLINE_GDT: [u64; 4] = [0, 0x00CF_9A00_0000_FFFF, 0x00CF_9200_0000_FFFF, 0x00AF_9A00_0000_FFFF];
+
+/// trampoline の命令（0x00..0x80）。即値の 0 は start_aps が書き換える
+#[rustfmt::skip]
+const TRAMPOLINE_CODE: [u8; 0x80] = [
+    // 0x00: real mode
+    0xFA,                                     // cli
+    0x8C, 0xC8,                               // mov ax, cs
+    0x8E, 0xD8,                               // mov ds, ax
+    0x66, 0x0F, 0x01, 0x16, 0xA0, 0x00,       // lgdt [0xA0]（32bit base）
+    0x0F, 0x20, 0xC0,                         // mov eax, cr0
+    0x66, 0x83, 0xC8, 0x01,                   // or eax, 1（PE）
+    0x0F, 0x22, 0xC0,                         // mov cr0, eax
+    0x66, 0xEA, 0, 0, 0, 0, 0x08, 0x00,       // jmp far 0x08:B+0x20
+    0xF4, 0xF4, 0xF4,
+    // 0x20: protected mode（32bit）
+    0x66, 0xB8, 0x10, 0x00,                   // mov ax, 0x10
+    0x8E, 0xD8,                               // mov ds, ax
+    0x8E, 0xC0,                               // mov es, ax
+    0x8E, 0xD0,                               // mov ss, ax
+    0x0F, 0x20, 0xE0,                         // mov eax, cr4
+    0x83, 0xC8, 0x20,                         // or eax, 0x20（PAE）
+    0x0F, 0x22, 0xE0,                         // mov cr4, eax
+    0xA1, 0, 0, 0, 0,                         // mov eax, [B+0xA8]
+    0x0F, 0x22, 0xD8,                         // mov cr3, eax
+    0xB9, 0x80, 0x00, 0x00, 0xC0,             // mov ecx, 0xC0000080（EFER）
+    0x0F, 0x32,                               // rdmsr
+    0x0D, 0x00, 0x09, 0x00, 0x00,             // or eax, 0x900（LME | NXE）
+    0x0F, 0x30,                               // wrmsr
+    0x0F, 0x20, 0xC0,                         // mov eax, cr0
+    0x0D, 0x00, 0x00, 0x01, 0x80,             // or eax, 0x80010000（PG | WP）
+    0x0F, 0x22, 0xC0,                         // mov cr0, eax
+    0xEA, 0, 0, 0, 0, 0x18, 0x00,             // jmp far 0x18:B+0x60
+    0xF4, 0xF4, 0xF4, 0xF4, 0xF4,
+    // 0x60: long mode
+    0x48, 0x8B, 0x24, 0x25, 0, 0, 0, 0,       // mov rsp, [B+0xB0]
+    0x48, 0x8B, 0x3C, 0x25, 0, 0, 0, 0,       // mov rdi, [B+0xC0]
+    0x48, 0x8B, 0x04, 0x25, 0, 0, 0, 0,       // mov rax, [B+0xB8]
+    0xFF, 0xD0,                               // call rax
+    0xF4,                                     // hlt
+    0xEB, 0xFD,                               // jmp（hlt へ）
+    0xF4, 0xF4, 0xF4,
+];
+
+// LAPIC
+const IA32_APIC_BASE: u32 = 0x1B;
+const APIC_BASE_X2APIC_ENABLE: u64 = 1 << 10;
+const LAPIC_ICR_LOW: u64 = 0x300;
+const LAPIC_ICR_HIGH: u64 = 0x310;
+/// LAPIC の register 領域（map_mmio で予約・map する大きさ）
+const LAPIC_MMIO_SIZE: u64 = 0x1000;
+const ICR_DELIVERY_PENDING: u32 = 1 << 12;
+const ICR_INIT_ASSERT: u32 = 0x0000_4500;
+const ICR_STARTUP: u32 = 0x0000_4600;
+const ICR_WAIT_SPINS: u32 = 1_000_000;
+
+// AP の起動直後の stack（gdt::init_ap の後もこのまま ap_step を走らせて park する。syscall の経路が載る大きさ）
+const AP_BOOT_STACK_SIZE: usize = 4096 * 16;
+
+#[repr(align(16))]
+struct ApBootStack([u8; AP_BOOT_STACK_SIZE]);
+
+static mut AP_BOOT_STACKS: [ApBootStack; MAX_CPUS] = [const { ApBootStack([0; AP_BOOT_STACK_SIZE]) }; MAX_CPUS];
+
+// per-CPU の状態（cpu 番号で引く）
+const CPU_OFFLINE: u8 = 0;
+const CPU_ONLINE: u8 = 1;
+/// 来たが GDT/IDT のロードに失敗した（park だけする）
+const CPU_FAILED: u8 = 2;
+/// task の実行を止めて park した（stop_secondary_cpus の待ちが見る）
+const CPU_PARKED: u8 = 3;
+
+static CPU_STATE: [AtomicU8; MAX_CPUS] = [const { AtomicU8::new(CPU_OFFLINE) }; MAX_CPUS];
+static CPU_APIC_ID: [AtomicU64; MAX_CPUS] = [const { AtomicU64::new(0) }; MAX_CPUS];
+
+/// start_aps の結果（BSP がログと KernelState に残す）
+#[derive(Clone, Copy)]
+pub struct SmpReport {
+    /// MADT に載っていた使える CPU の数（BSP を含む。MADT が無ければ 1）
+    pub cpus_in_madt: usize,
+    /// online になった CPU（bit = cpu 番号。BSP の bit 0 は常に立つ）
+    pub online_mask: u64,
+    /// 起こそうとして来なかった / ロードに失敗した AP の数
+    pub failed: usize,
+    /// MAX_CPUS を超えて起こさなかった CPU の数
+    pub ignored: usize,
+}
+
+/// この CPU の APIC ID（CPUID.01h:EBX[31:24]）
+pub fn current_apic_id() -> u32 {
+    let ebx = unsafe { core::arch::x86_64::__cpuid(1).ebx };
+    ebx >> 24
+}
+
+#[inline(always)]
+fn phys_ptr(phys: u64) -> *mut u8 {
+    (paging::physical_memory_offset() + phys) as *mut u8
+}
+
+unsafe fn write_u32(phys: u64, v: u32) {
+    core::ptr::write_unaligned(phys_ptr(phys) as *mut u32, v);
+}
+
+unsafe fn write_u64(phys: u64, v: u64) {
+    core::ptr::write_unaligned(phys_ptr(phys) as *mut u64, v);
+}
+
+/// MADT の AP を起こす（BSP から 1 回。bootstrap の後・seal の前）
+/// - 起こせなかった AP があっても BSP はそのまま進む（単一 CPU として動く）
+pub fn start_aps(phys_mem: &mut PhysicalMemoryManager) -> SmpReport {
+    let bsp_apic = current_apic_id();
+    CPU_APIC_ID[0].store(bsp_apic as u64, Ordering::SeqCst);
+    CPU_STATE[0].store(CPU_ONLINE, Ordering::SeqCst);
+
+    let mut report = SmpReport { cpus_in_madt: 1, online_mask: 1, failed: 0, ignored: 0 };
+
+    let madt = match acpi::madt() {
+        Some(m) => m,
+        None => {
+            LOG.info("smp: no MADT; single CPU");
+            return report;
+        }
+    };
+    report.cpus_in_madt = madt.cpu_count;
+    report.ignored = madt.cpus_ignored;
+    if madt.cpu_count <= 1 {
+        return report;
+    }
+
+    if unsafe { Msr::new(IA32_APIC_BASE).read() } & APIC_BASE_X2APIC_ENABLE != 0 {
+        LOG.error("smp: x2APIC mode is enabled; APs are not started");
+        return report;
+    }
+
+    let lapic = match map_lapic(madt.lapic_phys, phys_mem) {
+        Some(v) => v,
+        None => {
+            LOG.error("smp: LAPIC is not accessible; APs are not started");
+            return report;
+        }
+    };
+    let tramp = match setup_trampoline(phys_mem) {
+        Some(b) => b,
+        None => {
+            LOG.error("smp: trampoline setup failed; APs are not started");
+            return report;
+        }
+    };
+    LOG.info_hex("smp_lapic_phys", madt.lapic_phys);
+    LOG.info_hex("smp_trampoline_phys", tramp);
+
+    let mut next_cpu = 1;
+    for &apic_id in madt.apic_ids[..madt.cpu_count].iter() {
+        if apic_id as u32 == bsp_apic {
+            continue;
+        }
+        if next_cpu >= MAX_CPUS {
+            report.ignored += 1;
+            continue;
+        }
+        let cpu = next_cpu;
+        next_cpu += 1;
+
+        if boot_ap(lapic, tramp, cpu, apic_id) {
+            report.online_mask |= 1u64 << cpu;
+        } else {
+            report.failed += 1;
+        }
+    }
+
+    report
+}
+
+/// LAPIC の MMIO を引ける仮想アドレス（予約して MMIO window に uncached で map する。できなければ physmap）
+fn map_lapic(lapic_phys: u64, phys_mem: &mut PhysicalMemoryManager) -> Option<u64> {
+    if lapic_phys == 0 {
+        return None;
+    }
+    match paging::map_mmio(lapic_phys, LAPIC_MMIO_SIZE, phys_mem) {
+        Ok(v) => return Some(v),
+        Err(e) => {
+            LOG.error("smp: map_mmio for LAPIC failed; fall back to physmap");
+            LOG.info(e.name());
+        }
+    }
+    if paging::debug_physmap_can_access_phys(lapic_phys) {
+        return Some(paging::physical_memory_offset() + lapic_phys);
+    }
+    None
+}
+
+/// trampoline を 1MiB 未満のフレームに置き、B に合わせて即値を書き換える。戻り値は B
+fn setup_trampoline(phys_mem: &mut PhysicalMemoryManager) -> Option<u64> {
+    let mut frame = phys_mem.allocate_frame_below(TRAMPOLINE_LIMIT)?;
+    if frame.start_address().as_u64() == 0 {
+        // 物理 0 は identity map すると null が引けてしまうので使わない（確保したまま捨てる）
+        frame = phys_mem.allocate_frame_below(TRAMPOLINE_LIMIT)?;
+    }
+    let b = frame.start_address().as_u64();
+
+    // CR3 は 32bit の mov で入れるので 4GiB 未満であること
+    let cr3 = paging::current_root().start_address().0;
+    if cr3 >= (1u64 << 32) {
+        LOG.error("smp: kernel root is above 4GiB");
+        return None;
+    }
+    if !paging::identity_map_page_below_4g(b, phys_mem) {
+        return None;
+    }
+
+    let entry = virt_layout::kernel_high_alias_of_low(ap_entry as usize as u64);
+
+    unsafe {
+        core::ptr::copy_nonoverlapping(TRAMPOLINE_CODE.as_ptr(), phys_ptr(b), TRAMPOLINE_CODE.len());
+        for (i, d) in TRAMPOLINE_GDT.iter().enumerate() {
+            write_u64(b + T_GDT + (i as u64) * 8, *d);
+        }
+        core::ptr::write_unaligned(phys_ptr(b + T_GDTR) as *mut u16, (TRAMPOLINE_GDT.len() * 8 - 1) as u16);
+        write_u32(b + T_GDTR + 2, (b + T_GDT) as u32);
+
+        write_u32(b + P_RM_JMP, (b + T_PM_ENTRY) as u32);
+        write_u32(b + P_PM_CR3, (b + T_CR3) as u32);
+        write_u32(b + P_PM_JMP, (b + T_LM_ENTRY) as u32);
+        write_u32(b + P_LM_STACK, (b + T_STACK) as u32);
+        write_u32(b + P_LM_CPU, (b + T_CPU) as u32);
+        write_u32(b + P_LM_ENTRY, (b + T_ENTRY) as u32);
+
+        write_u64(b + T_CR3, cr3);
+        write_u64(b + T_ENTRY, entry);
+    }
+
+    Some(b)
+}
+
+/// ICR に IPI を書いて、送り終わる（delivery status が落ちる）まで待つ
+fn send_ipi(lapic: u64, apic_id: u8, low: u32) -> bool {
+    unsafe {
+        core::ptr::write_volatile((lapic + LAPIC_ICR_HIGH) as *mut u32, (apic_id as u32) << 24);
+        core::ptr::write_volatile((lapic + LAPIC_ICR_LOW) as *mut u32, low);
+        for _ in 0..ICR_WAIT_SPINS {
+            if core::ptr::read_volatile((lapic + LAPIC_ICR_LOW) as *const u32) & ICR_DELIVERY_PENDING == 0 {
+                return true;
+            }
+            core::hint::spin_loop();
+        }
+    }
+    false
+}
+
+fn arrived(cpu: usize) -> bool {
+    CPU_STATE[cpu].load(Ordering::SeqCst) != CPU_OFFLINE
+}
+
+/// 1 つの AP を INIT / SIPI / SIPI で起こし、online になるまで待つ
+fn boot_ap(lapic: u64, tramp: u64, cpu: usize, apic_id: u8) -> bool {
+    let stack_low = unsafe { core::ptr::addr_of!(AP_BOOT_STACKS[cpu].0) } as u64 + AP_BOOT_STACK_SIZE as u64;
+    let stack_top = virt_layout::kernel_high_alias_of_low(stack_low) & !0xF;
+    unsafe {
+        write_u64(tramp + T_STACK, stack_top);
+        write_u64(tramp + T_CPU, cpu as u64);
+    }
+
+    if !send_ipi(lapic, apic_id, ICR_INIT_ASSERT) {
+        return false;
+    }
+    timer::pit_delay_us(10_000);
+
+    let vector = (tramp >> 12) as u32;
+    for _ in 0..2 {
+        if !send_ipi(lapic, apic_id, ICR_STARTUP | vector) {
+            return false;
+        }
+        timer::pit_delay_us(200);
+        if arrived(cpu) {
+            break;
+        }
+    }
+
+    for _ in 0..100 {
+        if arrived(cpu) {
+            break;
+        }
+        timer::pit_delay_us(1_000);
+    }
+
+    CPU_STATE[cpu].load(Ordering::SeqCst) == CPU_ONLINE
+}
+
+/// AP の 64bit 入口（trampoline から call される。rdi = cpu 番号）
+extern "C" fn ap_entry(cpu: u64) -> ! {
+    let cpu = cpu as usize;
+    let loaded = cpu < MAX_CPUS && gdt::init_ap(cpu) && interrupts::load_idt_on_this_cpu();
+
+    if cpu < MAX_CPUS {
+        CPU_APIC_ID[cpu].store(current_apic_id() as u64, Ordering::SeqCst);
+        CPU_STATE[cpu].store(if loaded { CPU_ONLINE } else { CPU_FAILED }, Ordering::SeqCst);
+    }
+
+    if loaded {
+        // seal まで KernelState は見えない（BSP の bootstrap と重ならない）
+        while !crate::kernel::is_kernel_state_sealed() {
+            core::hint::spin_loop();
+        }
+        let _ = crate::kernel::with_kernel_state(|ks| ks.note_cpu_online(cpu));
+        run_tasks(cpu);
+        CPU_STATE[cpu].store(CPU_PARKED, Ordering::SeqCst);
+    }
+
+    park()
+}
+
+/// BSP の tick が進むたびに task を 1 step 走らせる（止められるまで）
+fn run_tasks(cpu: usize) {
+    let mut seen = timer::ticks();
+    loop {
+        while timer::ticks() == seen {
+            if crate::kernel::secondary_cpus_stopping() {
+                break;
+            }
+            core::hint::spin_loop();
+        }
+        seen = timer::ticks();
+
+        // stop の後も 1 回は入る（持っていた task を ap_step が Ready に戻す）
+        if !crate::kernel::with_kernel_state(|ks| ks.ap_step(cpu)).unwrap_or(false) {
+            return;
+        }
+    }
+}
+
+/// online になった AP が全部 park したか（ロードに失敗した AP は最初から park している）
+pub fn all_aps_parked() -> bool {
+    (1..MAX_CPUS).all(|cpu| CPU_STATE[cpu].load(Ordering::SeqCst) != CPU_ONLINE)
+}
+
+/// AP を止めておく（IF は落としたまま。NMI で起きても hlt に戻る）
+fn park() -> ! {
+    loop {
+        x86_64::instructions::interrupts::disable();
+        x86_64::instructions::hlt();
+    }
+}
@@ -0,0 +1,120 @@
+// kernel/src/arch/stack_guard.rs
+//
+// 役割:
+// - kernel stack（boot stack と task 用 kernel stack）の直下の guard page を登録し、
+//   #PF / #DF の handler が「kernel stack overflow」として区別できるようにする。
+//
+// guard page:
+// - boot stack: bootloader（0.9）が stack の最下端の 1 page を map せずに残している。
+//   今の rsp から page 単位で下へ辿り、最初に引けない page を guard として登録する（自分では unmap しない）
+// - task 用 kernel stack（arch::context の KSTACKS）: slot ごとに stack の直下に 1 page を置き、ここで unmap する
+// - 登録は low 側のアドレス。照合は low と high-alias の両方（kernel は high-alias 側の stack で走る）
+//
+// 検出:
+// - guard に触れても rsp がまだ上にあれば #PF が届く（stack probe 等）→ #PF handler が照合する
+// - rsp 自体が guard に入ると #PF の frame を積めずに #DF になる → #DF handler（IST1）が CR2 / rsp で照合する
+// - どちらも "[EXC] kernel stack overflow" を出して fail-stop（task の kill にはしない）
+//
+// やらないこと:
+// - gdt の RSP0 / IST stack の guard（例外 handler 自身の overflow）
+// - low の IDT の間（reload_idt_high_alias 前）の #DF（IST が無いので triple fault のまま）
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use super::{interrupts, paging, virt_layout};
+
+const LOG: crate::logging::Subsystem = crate::logging::Subsystem::Arch;
+
+const PAGE: u64 = 4096;
+
+/// boot stack の最下端を探すときに辿る page 数の上限（bootloader 0.9 の既定 stack より十分大きい）
+const BOOT_STACK_MAX_PAGES: u64 = 512;
+
+/// 登録の slot（0 = boot stack、1.. = task 用 kernel stack の slot + 1）
+#[cfg(target_os = "none")]
+const GUARD_SLOTS: usize = 1 + super::context::KSTACK_SLOTS;
+#[cfg(not(target_os = "none"))]
+const GUARD_SLOTS: usize = 1;
+
+/// guard page の low 側の先頭（0 = 未登録）
+static GUARD_PAGES: [AtomicU64; GUARD_SLOTS] = [const { AtomicU64::new(0) }; GUARD_SLOTS];
+
+/// どの stack の guard か
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum KernelStackKind {
+    Boot,
+    Task { slot: usize },
+}
+
+impl KernelStackKind {
+    fn from_slot(i: usize) -> KernelStackKind {
+        if i == 0 {
+            KernelStackKind::Boot
+        } else {
+            KernelStackKind::Task { slot: i - 1 }
+        }
+    }
+}
+
+/// boot stack の guard を探して登録し、task 用 kernel stack の guard page を unmap して登録する
+/// - paging::init の後（page table を辿れるようになってから）に 1 回呼ぶ
+pub fn init() {
+    match find_boot_stack_guard() {
+        Some(page) => {
+            GUARD_PAGES[0].store(page, Ordering::SeqCst);
+            LOG.info_hex("boot_stack_guard", page);
+        }
+        None => LOG.error("stack_guard: boot stack guard page not found"),
+    }
+
+    #[cfg(target_os = "none")]
+    {
+        let mut installed = 0u64;
+        for slot in 0..super::context::KSTACK_SLOTS {
+            let page = super::context::kernel_stack_guard_page(slot);
+            if paging::unmap_kernel_guard_page(page) {
+                GUARD_PAGES[1 + slot].store(page, Ordering::SeqCst);
+                installed += 1;
+            }
+        }
+        LOG.info_u64("kstack_guard_pages", installed);
+    }
+}
+
+/// 今の rsp から page 単位で下へ辿り、最初に map されていない page を返す
+fn find_boot_stack_guard() -> Option<u64> {
+    let probe: u64 = 0;
+    let mut page = (&probe as *const u64 as u64) & !(PAGE - 1);
+
+    for _ in 0..BOOT_STACK_MAX_PAGES {
+        let below = page.checked_sub(PAGE)?;
+        if !paging::is_kernel_readable_u64(below) {
+            return Some(below);
+        }
+        page = below;
+    }
+    None
+}
+
+/// addr が low 側 page の [page, page + PAGE) か、その high-alias に入っているか
+fn in_guard(page: u64, addr: u64) -> bool {
+    if addr.wrapping_sub(page) < PAGE {
+        return true;
+    }
+    if virt_layout::pml4_index(page) >= virt_layout::KERNEL_ALIAS_MAX_COPY_COUNT {
+        return false;
+    }
+    addr.wrapping_sub(virt_layout::kernel_high_alias_of_low(page)) < PAGE
+}
+
+/// addr がどれかの kernel stack の guard page に入っていれば、その stack を返す
+/// - 例外 handler から呼ぶ（lock / logging なし）
+pub fn find(addr: u64) -> Option<KernelStackKind> {
+    for i in 0..GUARD_SLOTS {
+        let page = interrupts::handler_static(&GUARD_PAGES[i]).load(Ordering::Relaxed);
+        if page != 0 && in_guard(page, addr) {
+            return Some(KernelStackKind::from_slot(i));
+        }
+    }
+    None
+}
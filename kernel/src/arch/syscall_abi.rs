@@ -0,0 +1,175 @@
+// kernel/src/arch/syscall_abi.rs
+//
+// 役割:
+// - ring3_tasks: int 0x80 の register ABI（kernel への本当の入口）。
+//   * 入口 stub が GPR を全部 kernel stack に積み、SyscallFrame として Rust 側に渡す
+//   * syscall 命令の入口（arch::syscall_msr）も同じ SyscallFrame を積んで dispatch_frame に入る
+//   * sysno / 引数をレジスタから読み、KernelState（ring3_syscall_enter）に渡す
+//   * 戻り値は積んだ rax / rdx を書き換えて iretq で返す
+//
+// register ABI:
+// - rax = sysno（番号は kernel/abi.rs の SYS_*）, rdi / rsi / rdx = a0..a2
+// - 戻り: rax = 主な戻り値, rdx = 2 つ目（MR1 / NotifyWait の bits / IpcRecv の reply cap）。それ以外のレジスタは保存される
+//   * syscall 命令で入った場合は rcx / r11 も壊れる（sysret の仕様）
+//
+// 方針:
+// - IDT の int 0x80 は IST を使わない（TSS.RSP0 = 今の task の kernel stack に入る）
+//   * 実行待ちの間にこの stack の上で timer IRQ を受け、他の task の stack へ切り替わるため
+//     （task 共有の IST stack の上で待つと、次に int 0x80 した task がそれを上書きする）
+// - Syscall への変換は kernel 側（syscall.rs の decode_syscall。Syscall の型は kernel の中にある）
+// - iretq 前に TrapFrame を検査する（interrupts.rs と同じ fail-stop）
+//
+// やらないこと:
+// - ring3_demo / ring3_mailbox 系（mailbox ABI、x86-interrupt の int80_handler のまま）
+// - FPU / SSE の退避（kernel は SSE を使わない）
+
+use x86_64::instructions::interrupts;
+
+use crate::arch::{interrupts as arch_interrupts, trapframe};
+
+/// 入口 stub が積むフレーム（低い方から。push の逆順）
+/// - rip 以降は CPU が積んだ iretq フレーム
+#[repr(C)]
+#[derive(Debug)]
+pub struct SyscallFrame {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+const _: () = assert!(core::mem::size_of::<SyscallFrame>() == 20 * 8);
+
+/// どの命令で入ってきたか（counters で int 0x80 / syscall を分けて数える）
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyscallEntry {
+    Int80,
+    Syscall,
+}
+
+/// register ABI の引数
+#[derive(Clone, Copy, Debug)]
+pub struct SyscallArgs {
+    pub sysno: u64,
+    pub a0: u64,
+    pub a1: u64,
+    pub a2: u64,
+}
+
+impl SyscallArgs {
+    pub fn from_frame(f: &SyscallFrame) -> Self {
+        Self { sysno: f.rax, a0: f.rdi, a1: f.rsi, a2: f.rdx }
+    }
+}
+
+// 入口: CPU は RSP0 の stack に iretq フレーム（5 word）を積んでいる
+// - 15 個 push すると 16byte align（RSP0 の上端は align 済み）→ そのまま call できる
+core::arch::global_asm!(
+    ".global formal_os_int80_entry",
+    "formal_os_int80_entry:",
+    "push rax",
+    "push rbx",
+    "push rcx",
+    "push rdx",
+    "push rsi",
+    "push rdi",
+    "push rbp",
+    "push r8",
+    "push r9",
+    "push r10",
+    "push r11",
+    "push r12",
+    "push r13",
+    "push r14",
+    "push r15",
+    "mov rdi, rsp",
+    "cld",
+    "call formal_os_int80_dispatch",
+    "pop r15",
+    "pop r14",
+    "pop r13",
+    "pop r12",
+    "pop r11",
+    "pop r10",
+    "pop r9",
+    "pop r8",
+    "pop rbp",
+    "pop rdi",
+    "pop rsi",
+    "pop rdx",
+    "pop rcx",
+    "pop rbx",
+    "pop rax",
+    "iretq",
+);
+
+extern "C" {
+    fn formal_os_int80_entry();
+}
+
+/// IDT に入れる入口の（low の）アドレス
+pub fn int80_entry_addr() -> u64 {
+    formal_os_int80_entry as usize as u64
+}
+
+#[no_mangle]
+extern "C" fn formal_os_int80_dispatch(frame: *mut SyscallFrame) {
+    arch_interrupts::note_int80_entry();
+    dispatch_frame(frame, SyscallEntry::Int80);
+}
+
+/// 入口 stub から: frame の引数で syscall を実行し、戻り値を frame の rax / rdx に書く
+/// - frame は入口 stub が今の stack に積んだもの（iretq / sysretq まで生きている）
+pub fn dispatch_frame(frame: *mut SyscallFrame, entry: SyscallEntry) {
+    // Safety: 上記のとおり、戻るまで他から触られない
+    let f = unsafe { &mut *frame };
+
+    let (rax, rdx) = dispatch(entry, SyscallArgs::from_frame(f));
+    f.rax = rax;
+    f.rdx = rdx;
+
+    // iretq / sysretq 前に TrapFrame を検査する（違反なら kill + halt で戻らない）
+    arch_interrupts::verify_user_trap_frame(&trapframe::UserTrapFrame {
+        rip: f.rip,
+        cs: f.cs,
+        rflags: f.rflags,
+        rsp: f.rsp,
+        ss: f.ss,
+    });
+}
+
+/// syscall を current task に積み、実行されるまで待って (rax, rdx) を返す
+/// - 判定は IF=0 で行い sti; hlt で待つ（run_timer_ticks と同じ）
+/// - CR3 は tick の末尾で current の root に戻っている（kernel/kstack.rs）
+fn dispatch(entry: SyscallEntry, args: SyscallArgs) -> (u64, u64) {
+    let immediate = crate::kernel::with_kernel_state(|ks| ks.ring3_syscall_enter(entry, args)).unwrap_or(Some((0, 0)));
+
+    if let Some(ret) = immediate {
+        return ret;
+    }
+
+    loop {
+        interrupts::enable_and_hlt();
+        interrupts::disable();
+        if let Some(ret) = crate::kernel::with_kernel_state(|ks| ks.ring3_syscall_poll()).flatten() {
+            return ret;
+        }
+    }
+}
@@ -0,0 +1,139 @@
+// kernel/src/arch/syscall_msr.rs
+//
+// 役割:
+// - ring3_tasks: `syscall` 命令の入口（IA32_LSTAR / STAR / FMASK と EFER.SCE）と sysret での戻り。
+// - 入口 stub は int 0x80 と同じ SyscallFrame を積み、arch::syscall_abi の同じ dispatch に入る。
+//
+// syscall / sysret の形:
+// - syscall: rcx = user RIP, r11 = user RFLAGS。RSP は user のまま（CPU は stack を切り替えない）
+//   * 入口で user RSP を退避し、今の task の kernel stack 上端（KERNEL_RSP）に切り替える
+//   * その上に iretq フレーム（SS / RSP / RFLAGS / CS / RIP）を自分で積む → int 0x80 と同じ SyscallFrame
+// - 戻り: 積んだフレームから rcx / r11 / rsp を作って sysretq（rcx / r11 は user から見て壊れる）
+//   * RIP は戻る前に TrapFrame 検査（user slot 内）を通している（非 canonical な RIP で sysret しない）
+//
+// 方針:
+// - 単一CPU前提: 退避先は静的変数（swapgs / per-cpu 領域は使わない）
+//   * 退避した user RSP はすぐ kernel stack に積む（待っている間に他の task の syscall が上書きしてよい）
+// - KERNEL_RSP は TSS.RSP0 と同じ値（arch::context の stack 切替で一緒に更新する）
+// - FMASK で IF / TF / DF / AC / NT を落とす（入口は int 0x80 の interrupt gate と同じ IF=0）
+// - GDT は user data → user code の順（STAR の user base + 8 = SS、+ 16 = CS。arch::gdt）
+//
+// やらないこと:
+// - syscall 命令の ring3_demo / ring3_mailbox 系対応（あちらは int 0x80 の mailbox ABI のみ）
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use x86_64::registers::model_specific::{Efer, EferFlags, LStar, SFMask, Star};
+use x86_64::registers::rflags::RFlags;
+use x86_64::VirtAddr;
+
+use crate::arch::{gdt, syscall_abi, virt_layout};
+
+const LOG: crate::logging::Subsystem = crate::logging::Subsystem::Arch;
+
+// 入口 stub が rip 相対で読み書きする
+static KERNEL_RSP: AtomicU64 = AtomicU64::new(0);
+static USER_RSP_SCRATCH: AtomicU64 = AtomicU64::new(0);
+static USER_CS: AtomicU64 = AtomicU64::new(0);
+static USER_SS: AtomicU64 = AtomicU64::new(0);
+
+core::arch::global_asm!(
+    ".global formal_os_syscall_entry",
+    "formal_os_syscall_entry:",
+    "mov [rip + {user_rsp}], rsp",
+    "mov rsp, [rip + {kernel_rsp}]",
+    // iretq フレームを int 0x80 と同じ形で積む
+    "push qword ptr [rip + {user_ss}]",
+    "push qword ptr [rip + {user_rsp}]",
+    "push r11",
+    "push qword ptr [rip + {user_cs}]",
+    "push rcx",
+    "push rax",
+    "push rbx",
+    "push rcx",
+    "push rdx",
+    "push rsi",
+    "push rdi",
+    "push rbp",
+    "push r8",
+    "push r9",
+    "push r10",
+    "push r11",
+    "push r12",
+    "push r13",
+    "push r14",
+    "push r15",
+    "mov rdi, rsp",
+    "cld",
+    "call formal_os_syscall_dispatch",
+    "pop r15",
+    "pop r14",
+    "pop r13",
+    "pop r12",
+    "pop r11",
+    "pop r10",
+    "pop r9",
+    "pop r8",
+    "pop rbp",
+    "pop rdi",
+    "pop rsi",
+    "pop rdx",
+    "pop rcx",
+    "pop rbx",
+    "pop rax",
+    // [rsp] = RIP, [rsp+16] = RFLAGS, [rsp+24] = user RSP
+    "mov rcx, [rsp]",
+    "mov r11, [rsp + 16]",
+    "mov rsp, [rsp + 24]",
+    "sysretq",
+    user_rsp = sym USER_RSP_SCRATCH,
+    kernel_rsp = sym KERNEL_RSP,
+    user_cs = sym USER_CS,
+    user_ss = sym USER_SS,
+);
+
+extern "C" {
+    fn formal_os_syscall_entry();
+}
+
+#[no_mangle]
+extern "C" fn formal_os_syscall_dispatch(frame: *mut syscall_abi::SyscallFrame) {
+    syscall_abi::dispatch_frame(frame, syscall_abi::SyscallEntry::Syscall);
+}
+
+/// MSR を設定して syscall 命令を有効にする（gdt::init_high_alias の後、high-alias 移行前に 1 回）
+/// - LSTAR は high-alias の入口（user root からも引ける）
+pub fn init() {
+    let kernel_cs = gdt::kernel_code_selector();
+    let kernel_ss = gdt::kernel_data_selector();
+    let user_cs = gdt::user_code_selector();
+    let user_ss = gdt::user_data_selector();
+
+    USER_CS.store((user_cs.0 | 3) as u64, Ordering::SeqCst);
+    USER_SS.store((user_ss.0 | 3) as u64, Ordering::SeqCst);
+
+    if let Err(e) = Star::write(user_cs, user_ss, kernel_cs, kernel_ss) {
+        LOG.error("arch::syscall_msr::init: STAR rejected (syscall disabled)");
+        LOG.error(e);
+        return;
+    }
+
+    let entry = virt_layout::kernel_high_alias_of_low(formal_os_syscall_entry as usize as u64);
+    LStar::write(VirtAddr::new(entry));
+    SFMask::write(
+        RFlags::INTERRUPT_FLAG
+            | RFlags::TRAP_FLAG
+            | RFlags::DIRECTION_FLAG
+            | RFlags::ALIGNMENT_CHECK
+            | RFlags::NESTED_TASK,
+    );
+    unsafe { Efer::update(|f| f.insert(EferFlags::SYSTEM_CALL_EXTENSIONS)) };
+
+    LOG.info("arch::syscall_msr::init: syscall/sysret enabled");
+    LOG.info_u64("lstar", entry);
+}
+
+/// syscall 入口で使う kernel stack の上端（TSS.RSP0 と同じ値。arch::context から）
+pub fn set_kernel_rsp(top: u64) {
+    KERNEL_RSP.store(top & !0xF, Ordering::SeqCst);
+}
@@ -0,0 +1,134 @@
+// kernel/src/arch/timer.rs
+//
+// 役割:
+// - 8254 PIT channel 0 を周期 IRQ（IRQ0）として動かす（PIC の remap / EOI は arch::pic）。
+// - timer IRQ から KernelState::tick() を駆動するための budget / 停止フラグを持つ。
+//
+// 設計方針:
+// - IRQ0 の unmask / mask は start / stop だけが行う
+// - 停止条件（budget 消化 / KernelState の halt 要求）は割り込み側で判定し、
+//   IRQ0 を mask してから TIMER_STOPPED を立てる（main 側は hlt で待つだけ）
+// - KernelState への到達は state_ref::with_kernel_state だけ（seal 前は tick しない）
+//
+// やらないこと:
+// - LAPIC / HPET / TSC deadline（PIT で十分な粒度）
+// - tick の取りこぼし補正（PIC は pending を 1 つしか持たない。遅れた tick は詰めて進む）
+
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use x86_64::instructions::port::Port;
+
+use super::pic;
+
+/// timer の IRQ と vector
+pub const TIMER_IRQ: u8 = 0;
+pub const TIMER_VECTOR: u8 = pic::irq_vector(TIMER_IRQ);
+
+/// timer IRQ の周波数
+pub const TIMER_HZ: u32 = 100;
+
+/// PIT の入力クロック
+const PIT_BASE_HZ: u32 = 1_193_182;
+
+const PIT_CH0: u16 = 0x40;
+const PIT_CH2: u16 = 0x42;
+const PIT_CMD: u16 = 0x43;
+/// channel 2 の gate（bit0）/ speaker（bit1）/ OUT2（bit5）
+const PIT_CH2_GATE: u16 = 0x61;
+
+// 残り tick 数（0 になったら止める）
+static TICK_BUDGET: AtomicU64 = AtomicU64::new(0);
+// timer IRQ で tick した回数
+static TIMER_TICKS: AtomicU64 = AtomicU64::new(0);
+// IRQ0 を止めたか（main の待ちループが見る）
+static TIMER_STOPPED: AtomicBool = AtomicBool::new(true);
+
+/// PIT channel 0 を TIMER_HZ の rate generator にする
+fn program_pit() {
+    let divisor = (PIT_BASE_HZ / TIMER_HZ) as u16;
+    unsafe {
+        // channel 0, lobyte/hibyte, mode 2, binary
+        Port::<u8>::new(PIT_CMD).write(0x34);
+        let mut ch0 = Port::<u8>::new(PIT_CH0);
+        ch0.write((divisor & 0xFF) as u8);
+        ch0.write((divisor >> 8) as u8);
+    }
+}
+
+/// PIT channel 2（IRQ を出さない）で us マイクロ秒 busy wait する
+/// - arch::smp の INIT / SIPI の間の待ち、arch::clock の TSC の calibrate 用。channel 0（tick）には触らない
+/// - 1 回の one-shot は 16bit（約 55ms）までなので、長い待ちは分けて数える
+pub fn pit_delay_us(us: u64) {
+    const CHUNK_US: u64 = 50_000;
+    let mut left = us;
+    while left > 0 {
+        let step = core::cmp::min(left, CHUNK_US);
+        let count = core::cmp::max(1, PIT_BASE_HZ as u64 * step / 1_000_000) as u16;
+        unsafe {
+            let mut gate = Port::<u8>::new(PIT_CH2_GATE);
+            let saved = gate.read();
+            // gate を落として speaker を切る → channel 2, lobyte/hibyte, mode 0（terminal count で OUT2 が上がる）
+            gate.write(saved & !0x03);
+            Port::<u8>::new(PIT_CMD).write(0xB0);
+            let mut ch2 = Port::<u8>::new(PIT_CH2);
+            ch2.write((count & 0xFF) as u8);
+            ch2.write((count >> 8) as u8);
+            gate.write((saved & !0x02) | 0x01);
+            while gate.read() & 0x20 == 0 {
+                core::hint::spin_loop();
+            }
+            gate.write(saved);
+        }
+        left -= step;
+    }
+}
+
+fn set_irq0_masked(masked: bool) {
+    pic::set_irq_masked(TIMER_IRQ, masked);
+}
+
+/// budget tick 分だけ timer IRQ を動かす（IF を立てるのは呼び出し側）
+pub fn start(budget_ticks: u64) {
+    TICK_BUDGET.store(budget_ticks, Ordering::SeqCst);
+    TIMER_TICKS.store(0, Ordering::SeqCst);
+    TIMER_STOPPED.store(budget_ticks == 0, Ordering::SeqCst);
+    if budget_ticks == 0 {
+        return;
+    }
+    program_pit();
+    set_irq0_masked(false);
+}
+
+/// IRQ0 を止める（割り込み側・main 側どちらからでも呼べる）
+pub fn stop() {
+    set_irq0_masked(true);
+    TIMER_STOPPED.store(true, Ordering::SeqCst);
+}
+
+pub fn is_stopped() -> bool {
+    TIMER_STOPPED.load(Ordering::SeqCst)
+}
+
+pub fn ticks() -> u64 {
+    TIMER_TICKS.load(Ordering::SeqCst)
+}
+
+/// 残り budget（0 なら次の IRQ0 で止まる）
+pub fn budget_left() -> u64 {
+    TICK_BUDGET.load(Ordering::SeqCst)
+}
+
+/// timer IRQ 1 回分の budget を消費する。tick してよければ true
+pub fn take_tick() -> bool {
+    if is_stopped() {
+        return false;
+    }
+    let left = TICK_BUDGET.load(Ordering::SeqCst);
+    if left == 0 {
+        stop();
+        return false;
+    }
+    TICK_BUDGET.store(left - 1, Ordering::SeqCst);
+    TIMER_TICKS.fetch_add(1, Ordering::SeqCst);
+    true
+}
@@ -0,0 +1,141 @@
+// kernel/src/arch/trapframe.rs
+//
+// 役割:
+// - ring3 へ iretq で戻る直前に、保存された TrapFrame（iretq フレーム）を検査する。
+// - 壊れたフレームで黙って特権昇格（CS/SS の RPL=0 や IOPL=3）しないことを境界条件として固定する。
+//
+// 検査項目:
+// - CS / SS: RPL=3、かつ GDT の user selector と一致
+// - RFLAGS: bit1=1、予約ビット=0、IOPL=0、IF は ring3 MVP の方針（IF=0）と一致
+// - RIP / RSP: user slot（USER_SPACE_BASE..+USER_SPACE_SIZE）内
+//   * RSP は「スタック上端（exclusive）」を指しうるので上端を含める
+//
+// やらないこと:
+// - kill / halt の判断（呼び出し側に任せる。ここは純粋な判定だけ）
+//
+// 設計方針:
+// - 違反は「どの項目か（code）」と「その値」の組で返す（TaskKillReason にそのまま載せる）。
+
+use crate::arch::{gdt, virt_layout};
+use spec_macros::spec;
+
+/// RFLAGS: bit1 は常に 1（予約ビット）
+pub const RFLAGS_FIXED1: u64 = 1 << 1;
+/// RFLAGS: IF
+pub const RFLAGS_IF: u64 = 1 << 9;
+/// RFLAGS: IOPL（bit12-13）
+pub const RFLAGS_IOPL_MASK: u64 = 0b11 << 12;
+/// RFLAGS: 予約ビット（0 でなければならない）: bit3, bit5, bit15, bit22..63
+pub const RFLAGS_RESERVED_MASK: u64 = (1 << 3) | (1 << 5) | (1 << 15) | !((1u64 << 22) - 1);
+
+/// ring3 MVP は IF=0 で user に入る（arch/ring3.rs と同じ方針）
+/// - ring3_tasks は timer IRQ で preempt するので IF=1（arch/context.rs の初回フレームも IF=1）
+pub const USER_RFLAGS_IF_EXPECTED: bool = cfg!(feature = "ring3_tasks");
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrapFrameViolation {
+    CsRpl,
+    CsSelector,
+    SsRpl,
+    SsSelector,
+    RflagsFixed1,
+    RflagsReserved,
+    RflagsIopl,
+    RflagsIf,
+    RipOutOfSlot,
+    RspOutOfSlot,
+}
+
+impl TrapFrameViolation {
+    /// ログ / ワイヤ形式に載せる安定コード（docs/LOG_FORMAT.md と一致させる）
+    pub const fn code(self) -> u64 {
+        match self {
+            TrapFrameViolation::CsRpl => 1,
+            TrapFrameViolation::CsSelector => 2,
+            TrapFrameViolation::SsRpl => 3,
+            TrapFrameViolation::SsSelector => 4,
+            TrapFrameViolation::RflagsFixed1 => 5,
+            TrapFrameViolation::RflagsReserved => 6,
+            TrapFrameViolation::RflagsIopl => 7,
+            TrapFrameViolation::RflagsIf => 8,
+            TrapFrameViolation::RipOutOfSlot => 9,
+            TrapFrameViolation::RspOutOfSlot => 10,
+        }
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            TrapFrameViolation::CsRpl => "cs_rpl",
+            TrapFrameViolation::CsSelector => "cs_selector",
+            TrapFrameViolation::SsRpl => "ss_rpl",
+            TrapFrameViolation::SsSelector => "ss_selector",
+            TrapFrameViolation::RflagsFixed1 => "rflags_fixed1",
+            TrapFrameViolation::RflagsReserved => "rflags_reserved",
+            TrapFrameViolation::RflagsIopl => "rflags_iopl",
+            TrapFrameViolation::RflagsIf => "rflags_if",
+            TrapFrameViolation::RipOutOfSlot => "rip_out_of_slot",
+            TrapFrameViolation::RspOutOfSlot => "rsp_out_of_slot",
+        }
+    }
+}
+
+/// iretq で ring3 に戻るフレームの値
+#[derive(Clone, Copy)]
+pub struct UserTrapFrame {
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+fn in_user_slot(addr: u64) -> bool {
+    addr >= virt_layout::USER_SPACE_BASE && addr < virt_layout::USER_SPACE_BASE + virt_layout::USER_SPACE_SIZE
+}
+
+fn in_user_slot_or_top(addr: u64) -> bool {
+    addr > virt_layout::USER_SPACE_BASE && addr <= virt_layout::USER_SPACE_BASE + virt_layout::USER_SPACE_SIZE
+}
+
+/// 検査順は固定（同じフレームなら常に同じ違反を返す）。
+/// 違反時は (項目, その項目の値) を返す。
+#[spec("INV-TRAP-001")]
+pub fn check_user_trap_frame(f: &UserTrapFrame) -> Result<(), (TrapFrameViolation, u64)> {
+    let user_cs = (gdt::user_code_selector().0 | 3) as u64;
+    let user_ss = (gdt::user_data_selector().0 | 3) as u64;
+
+    if f.cs & 3 != 3 {
+        return Err((TrapFrameViolation::CsRpl, f.cs));
+    }
+    if f.cs != user_cs {
+        return Err((TrapFrameViolation::CsSelector, f.cs));
+    }
+    if f.ss & 3 != 3 {
+        return Err((TrapFrameViolation::SsRpl, f.ss));
+    }
+    if f.ss != user_ss {
+        return Err((TrapFrameViolation::SsSelector, f.ss));
+    }
+
+    if f.rflags & RFLAGS_FIXED1 == 0 {
+        return Err((TrapFrameViolation::RflagsFixed1, f.rflags));
+    }
+    if f.rflags & RFLAGS_RESERVED_MASK != 0 {
+        return Err((TrapFrameViolation::RflagsReserved, f.rflags));
+    }
+    if f.rflags & RFLAGS_IOPL_MASK != 0 {
+        return Err((TrapFrameViolation::RflagsIopl, f.rflags));
+    }
+    if (f.rflags & RFLAGS_IF != 0) != USER_RFLAGS_IF_EXPECTED {
+        return Err((TrapFrameViolation::RflagsIf, f.rflags));
+    }
+
+    if !in_user_slot(f.rip) {
+        return Err((TrapFrameViolation::RipOutOfSlot, f.rip));
+    }
+    if !in_user_slot_or_top(f.rsp) {
+        return Err((TrapFrameViolation::RspOutOfSlot, f.rsp));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,78 @@
+// kernel/src/arch/unwind.rs
+//
+// 役割:
+// - frame pointer（rbp の連鎖）を辿って戻りアドレスを集める（panic の backtrace 用）。
+//
+// 前提:
+// - target json の "frame-pointer": "always" で core を含めて全関数が rbp を積む
+//   * frame は [rbp] = 呼び出し元の rbp、[rbp + 8] = 戻りアドレス
+//
+// 方針（panic 中に二度目の fault を起こさない）:
+// - 読む前に毎回 paging::is_kernel_readable_u64 で current CR3 に map されているかを確かめる
+//   * user CR3 中でも USER slot は読まない（kernel stack だけを辿る）
+// - rbp は必ず増える（stack は下に伸びる）こと、開始点から UNWIND_MAX_SPAN 以内であることを要求する
+//   → 壊れた連鎖や循環でも有限回で止まる
+// - 書き込みもログも行わない（出力は呼び出し側の emergency writer）
+//
+// やらないこと:
+// - シンボル解決（アドレスだけ。addr2line 等はホスト側）
+// - 例外 frame（InterruptStackFrame）をまたいだ unwind
+
+use super::paging;
+
+/// 集める戻りアドレスの最大数
+pub const BACKTRACE_MAX_FRAMES: usize = 16;
+
+/// 開始点の rbp からこれ以上離れた frame は辿らない（kernel stack より十分大きい）
+const UNWIND_MAX_SPAN: u64 = 1024 * 1024;
+
+/// 呼び出した関数自身の rbp
+#[inline(always)]
+pub fn current_frame_pointer() -> u64 {
+    let rbp: u64;
+    unsafe {
+        core::arch::asm!("mov {}, rbp", out(reg) rbp, options(nomem, nostack, preserves_flags));
+    }
+    rbp
+}
+
+/// rbp から frame の連鎖を辿り、戻りアドレスを out に詰める（詰めた数を返す）
+/// - 読めない / 連鎖が壊れている / 戻りアドレスが 0 のところで止まる
+pub fn walk_frames(rbp: u64, out: &mut [u64]) -> usize {
+    let start = rbp;
+    let mut rbp = rbp;
+    let mut n = 0;
+
+    while n < out.len() {
+        if rbp == 0 || rbp.wrapping_sub(start) > UNWIND_MAX_SPAN {
+            break;
+        }
+        let ret_slot = match rbp.checked_add(8) {
+            Some(v) => v,
+            None => break,
+        };
+        if !paging::is_kernel_readable_u64(rbp) || !paging::is_kernel_readable_u64(ret_slot) {
+            break;
+        }
+
+        let (next, ret) = unsafe {
+            (
+                core::ptr::read_volatile(rbp as *const u64),
+                core::ptr::read_volatile(ret_slot as *const u64),
+            )
+        };
+        if ret == 0 {
+            break;
+        }
+        out[n] = ret;
+        n += 1;
+
+        // 呼び出し元の frame は必ず上（大きいアドレス）にある
+        if next <= rbp {
+            break;
+        }
+        rbp = next;
+    }
+
+    n
+}
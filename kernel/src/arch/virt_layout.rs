@@ -39,6 +39,23 @@ pub const USER_SPACE_BASE: u64 = pml4_index_base_addr(USER_PML4_INDEX);
 /// USER 空間サイズ（PML4 1スロット分: 512GiB）
 pub const USER_SPACE_SIZE: u64 = PML4_SLOT_SIZE;
 
+/// kernel heap を置く PML4 index（kernel high-half。physmap / alias window と重ならない）
+/// - kernel root に map し、init_user_pml4_from_current が high-half ごと user root にコピーする
+pub const KERNEL_HEAP_PML4_INDEX: usize = 384;
+
+/// kernel heap の先頭
+pub const KERNEL_HEAP_BASE: u64 = pml4_index_base_addr(KERNEL_HEAP_PML4_INDEX);
+
+/// kernel heap の大きさ（64KiB = 16 page）
+pub const KERNEL_HEAP_SIZE: u64 = 64 * 1024;
+
+/// MMIO window の先頭（heap と同じ PML4 slot の 1GiB 先）
+/// - PML4 entry（L3 table）を heap と共有するので、user root を作った後に map しても user root から引ける
+pub const KERNEL_MMIO_BASE: u64 = KERNEL_HEAP_BASE + (1u64 << 30);
+
+/// MMIO window の大きさ（2MiB = 512 page）
+pub const KERNEL_MMIO_SIZE: u64 = 2 * 1024 * 1024;
+
 /// kernel high-alias を配置する先の PML4 index（508..511）
 pub const KERNEL_ALIAS_DST_PML4_BASE_INDEX: usize = 508;
 
@@ -0,0 +1,1262 @@
+// kernel/src/kernel/abi.rs
+//
+// 役割:
+// - カーネル外へ出すレコード（event / counters / task info / endpoint info）の
+//   固定レイアウト・バージョン付き・little-endian のワイヤ形式を定義する。
+//
+// 設計方針:
+// - 1 レコード = 固定 80 byte（header 16 byte + u64 word × 8）
+//   * header: [0]=version, [1]=kind, [2..4]=sub(u16 LE), [4..8]=reserved(0),
+//             [8..16]=seq(u64 LE: logging::next_seq の通し番号、0 = 未設定)
+//   * word:   little-endian u64
+// - レイアウト定義と decode は core のみに依存させる。
+//   ホスト側ツールは `#[path]` でこのファイルをそのまま取り込み、decoder を共有する。
+// - カーネル型（LogEvent 等）からの encode は kernel crate の中だけで有効にする（実機でもホストの test でも）。
+//   取り込むツール側は build.rs で cfg(wire_decode_only) を立てて encode を外す。
+// - 「値が無い」は WIRE_NONE（u64::MAX）で表す。
+// - word の名前（event_schema / COUNTER_KEYS / TASK_INFO_KEYS / ENDPOINT_INFO_KEYS）もここが正本。
+//   key=value のレコード出力（logging::record）はこの名前で word を出す。
+//
+// やらないこと:
+// - 可変長レコード（必要になったら kind を増やす）
+// - 旧 version の decode（version 不一致はエラーとして返す）
+
+#![allow(dead_code)]
+
+// v2: header に seq を追加（16 byte）
+pub const WIRE_VERSION: u8 = 2;
+
+pub const WIRE_HEADER_SIZE: usize = 16;
+pub const WIRE_WORDS: usize = 8;
+pub const WIRE_RECORD_SIZE: usize = WIRE_HEADER_SIZE + WIRE_WORDS * 8;
+
+/// Option の None を表す word 値
+pub const WIRE_NONE: u64 = u64::MAX;
+
+// ★compile-time size assertions（レイアウトが動いたらビルドで止める）
+const _: () = assert!(WIRE_HEADER_SIZE == 16);
+const _: () = assert!(WIRE_RECORD_SIZE == 80);
+const _: () = assert!(core::mem::size_of::<WireRecord>() == WIRE_RECORD_SIZE);
+
+// -----------------------------------------------------------------------------
+// syscall / IPC の戻り値コード（ユーザから見える契約の正本）
+// - syscall.rs / ipc.rs はここを参照する（値を二重定義しない）
+// -----------------------------------------------------------------------------
+
+// mem 系 syscall（last_syscall_ret）
+pub const SYSCALL_OK: u64 = 0;
+pub const SYSCALL_ERR_ALREADY_MAPPED: u64 = 1;
+pub const SYSCALL_ERR_NOT_MAPPED: u64 = 2;
+pub const SYSCALL_ERR_CAPACITY: u64 = 3;
+pub const SYSCALL_ERR_ARCH_FAILED: u64 = 10;
+pub const SYSCALL_ERR_BAD_ASPACE: u64 = 11;
+/// PageMap: AddressSpace のフレーム quota を超える
+pub const SYSCALL_ERR_QUOTA: u64 = 16;
+/// PageMap: user stack の guard page は map できない
+pub const SYSCALL_ERR_GUARD_PAGE: u64 = 20;
+/// PageProtect: PRESENT を落とす / USER を変える flags（AddressSpaceError::BadFlags）
+pub const SYSCALL_ERR_BAD_FLAGS: u64 = 36;
+
+// task 系 syscall（TaskCreate / TaskExit、last_syscall_ret）
+pub const SYSCALL_ERR_NO_TASK_SLOT: u64 = 12;
+pub const SYSCALL_ERR_BAD_PRIORITY: u64 = 13;
+pub const SYSCALL_ERR_FORBIDDEN: u64 = 14;
+/// TaskCreate 成功時の戻り値の上位 16bit（bit 32..47 は呼び出し元の table の子の task handle、下位 32bit は新しい TaskId）
+pub const TASK_CREATE_OK_TAG: u64 = 0x7A5C_0000_0000_0000;
+pub const TASK_CREATE_OK_TAG_MASK: u64 = 0xFFFF_0000_0000_0000;
+pub const TASK_CREATE_CAP_SHIFT: u32 = 32;
+pub const TASK_CREATE_ID_MASK: u64 = 0xFFFF_FFFF;
+/// TaskCreate で指定できる priority の上限（さらに親の base_priority 以下に限る）
+pub const TASK_PRIORITY_MAX: u8 = 7;
+
+// notification 系 syscall（NotifySignal / NotifyWait、last_syscall_ret）
+pub const SYSCALL_ERR_BAD_NOTIFICATION: u64 = 15;
+
+// endpoint 系 syscall（EndpointCreate / EndpointDelete、last_syscall_ret）
+/// EndpointCreate: 空いている endpoint slot が無い
+pub const SYSCALL_ERR_NO_ENDPOINT_SLOT: u64 = 17;
+/// EndpointDelete: cap が引けない / 起動時から在る endpoint
+pub const SYSCALL_ERR_BAD_ENDPOINT: u64 = 18;
+/// EndpointCreate 成功時の戻り値の上位 16bit（下位 48bit は作った task の cap index）
+pub const ENDPOINT_CREATE_OK_TAG: u64 = 0xE9C0_0000_0000_0000;
+pub const ENDPOINT_CREATE_OK_TAG_MASK: u64 = 0xFFFF_0000_0000_0000;
+
+// shared memory 系 syscall（ShmCreate / ShmMap、last_syscall_ret）
+/// ShmCreate: pages が 0 / 上限超え。ShmMap: handle の segment が使われていない
+pub const SYSCALL_ERR_BAD_SHM: u64 = 21;
+/// ShmCreate: 空いている segment slot が無い
+pub const SYSCALL_ERR_NO_SHM_SLOT: u64 = 22;
+/// ShmMap: page..page + pages が user slot に収まらない
+pub const SYSCALL_ERR_BAD_PAGE_RANGE: u64 = 23;
+/// ShmCreate 成功時の戻り値の上位 16bit（下位 48bit は segment の handle = cap index）
+pub const SHM_CREATE_OK_TAG: u64 = 0x5A11_0000_0000_0000;
+pub const SHM_CREATE_OK_TAG_MASK: u64 = 0xFFFF_0000_0000_0000;
+
+// 入力系 syscall（ReadInput、last_syscall_ret）
+/// ReadInput: 溜まっている scancode が無い
+pub const SYSCALL_ERR_NO_INPUT: u64 = 24;
+/// ReadInput 成功時の戻り値の上位 16bit（下位 8bit は PS/2 scancode set 1 の byte）
+pub const INPUT_OK_TAG: u64 = 0x1B9D_0000_0000_0000;
+pub const INPUT_OK_TAG_MASK: u64 = 0xFFFF_0000_0000_0000;
+
+// 読み取り専用 fs 系 syscall（FsOpen / FsRead、last_syscall_ret。fs.rs）
+/// FsOpen: 名前の file が無い（disk / fs が無いときも）、名前の長さが 0 / 上限超え
+pub const SYSCALL_ERR_NO_FILE: u64 = 25;
+/// FsRead: fd が開いていない
+pub const SYSCALL_ERR_BAD_FD: u64 = 26;
+/// FsOpen: task の fd table が満杯
+pub const SYSCALL_ERR_NO_FD_SLOT: u64 = 27;
+/// FsRead: block device の読み出しに失敗した
+pub const SYSCALL_ERR_IO: u64 = 28;
+/// FsOpen 成功時の戻り値の上位 16bit（下位 48bit は fd）
+pub const FS_OPEN_OK_TAG: u64 = 0xF5F0_0000_0000_0000;
+pub const FS_OPEN_OK_TAG_MASK: u64 = 0xFFFF_0000_0000_0000;
+/// FsRead 成功時の戻り値の上位 16bit（下位 48bit は読んだ byte 数。0 = offset が末尾以降）
+pub const FS_READ_OK_TAG: u64 = 0xF5D0_0000_0000_0000;
+pub const FS_READ_OK_TAG_MASK: u64 = 0xFFFF_0000_0000_0000;
+
+// fault handler 系 syscall（FaultHandlerSet / FaultResolve、last_syscall_ret。fault_handler.rs）
+/// FaultResolve: handle の task に未解決の fault が無い
+pub const SYSCALL_ERR_NO_FAULT: u64 = 29;
+/// FaultHandlerSet の cap に渡すと登録を外す
+pub const FAULT_HANDLER_NONE: u64 = u64::MAX;
+/// FaultResolve の action
+pub const FAULT_ACTION_RESUME: u64 = 0;
+/// page を faulter の AddressSpace に RW で map してから起こす
+pub const FAULT_ACTION_MAP_RESUME: u64 = 1;
+pub const FAULT_ACTION_KILL: u64 = 2;
+/// handler に渡す msg の MR0 上位 16bit（下位 48bit は faulter の TaskId、MR1 = addr、MR2 = err、MR3 = rip。
+/// cap_transfer = Resolve 付きの faulter の task handle）
+pub const FAULT_MSG_TAG: u64 = 0xFA17_0000_0000_0000;
+pub const FAULT_MSG_TAG_MASK: u64 = 0xFFFF_0000_0000_0000;
+
+// page grant 系 syscall（GrantWindowSet / PageUnmap、last_syscall_ret。grant.rs）
+/// GrantWindowSet: window に grant が在る間は変えられない。PageUnmap: page は受け取った grant の window
+pub const SYSCALL_ERR_GRANT_ACTIVE: u64 = 30;
+/// GrantWindowSet の page に渡すと window を外す
+pub const GRANT_WINDOW_NONE: u64 = u64::MAX;
+/// GrantRevoked の reason
+pub const GRANT_REVOKE_REPLY: u64 = 0;
+/// granter が reply 以外で起こされた（timeout / endpoint close / dead partner の救済）
+pub const GRANT_REVOKE_CALL_ENDED: u64 = 1;
+/// granter か grantee が死んだ
+pub const GRANT_REVOKE_TASK_DEAD: u64 = 2;
+/// 祖先の page の持ち主が Revoke した（derivation.rs）
+pub const GRANT_REVOKE_REVOKED: u64 = 3;
+
+// Revoke syscall（last_syscall_ret。derivation.rs）
+/// Revoke の cap index が空 slot / 範囲外。handle で object を指す syscall（NotifySignal / NotifyWait / ShmMap）も同じ
+pub const SYSCALL_ERR_BAD_CAP: u64 = 31;
+/// Revoke の対象の種類（SYS_REVOKE の a0、DerivationRevoked の kind）
+pub const REVOKE_KIND_CAP: u64 = 0;
+pub const REVOKE_KIND_PAGE: u64 = 1;
+
+// TaskSuspend / TaskResume（last_syscall_ret。suspend.rs）。handle の違反は SYSCALL_ERR_BAD_CAP / FORBIDDEN / WRONG_TYPE
+/// handle の task が死んでいる / 自分 / kernel task / idle（TaskInfo / FaultResolve は死んでいるときだけ）
+pub const SYSCALL_ERR_BAD_TASK: u64 = 32;
+/// TaskSuspend: 既に Suspended。TaskResume: Suspended ではない
+pub const SYSCALL_ERR_TASK_STATE: u64 = 33;
+
+// TaskInfo（last_syscall_ret = SYSCALL_OK / SYSCALL_ERR_BAD_TASK / handle の違反。task_info.rs）
+/// ring3 の rdx に詰めた TaskInfo の配置: state（STATE_*、8bit）/ 実効 priority（8bit）/ address_space_id（16bit）/
+/// runtime_ticks（32bit、飽和）
+pub const TASK_INFO_STATE_SHIFT: u32 = 0;
+pub const TASK_INFO_PRIORITY_SHIFT: u32 = 8;
+pub const TASK_INFO_ASID_SHIFT: u32 = 16;
+pub const TASK_INFO_RUNTIME_SHIFT: u32 = 32;
+
+// MemInfo（last_syscall_ret = SYSCALL_OK のみ。mem_info.rs）
+/// ring3 の rdx に詰めた MemInfo の配置: free_frames（24bit）/ used_frames（24bit）/
+/// 自分の AddressSpace の resident pages（16bit）。どれも飽和する
+pub const MEM_INFO_FREE_SHIFT: u32 = 0;
+pub const MEM_INFO_USED_SHIFT: u32 = 24;
+pub const MEM_INFO_RESIDENT_SHIFT: u32 = 48;
+
+// handle table（cspace.rs）
+/// handle の型が syscall の求める object の種類と違う（例: endpoint の handle で NotifyWait）
+pub const SYSCALL_ERR_WRONG_TYPE: u64 = 34;
+/// handle が指す object の種類（CapGranted / CapReceived の kind、capability denied のログの kind_*）
+pub const OBJECT_KIND_ENDPOINT: u64 = 0;
+pub const OBJECT_KIND_NOTIFICATION: u64 = 1;
+pub const OBJECT_KIND_SHM: u64 = 2;
+pub const OBJECT_KIND_TASK: u64 = 3;
+
+// reply cap（reply_cap.rs）
+/// IpcReply: reply cap が払い出されていない / 使い終わった / 別の server のもの（成功時は last_syscall_ret を書かない）
+pub const SYSCALL_ERR_BAD_REPLY_CAP: u64 = 35;
+
+// endpoint の message buffer（ipc_buffer.rs）
+/// buffer に入った send の last_reply（MR0）: tag | 入れた後の buffer の長さ（sender は block しない）
+pub const IPC_BUFFERED_OK_TAG: u64 = 0xB0F0_0000_0000_0000;
+pub const IPC_BUFFERED_OK_TAG_MASK: u64 = 0xFFFF_0000_0000_0000;
+
+// ring3 の入口（ring3_tasks、rax に返る）
+/// 知らない sysno / 引数が範囲外で Syscall にできない
+pub const SYSCALL_ERR_BAD_SYSCALL: u64 = 19;
+
+// IPC メッセージ
+/// message register の数（MR0..MR3）。IpcDelivered 等のレコードに全 MR を載せられる上限
+pub const IPC_MSG_REGS: usize = 4;
+const _: () = assert!(IPC_MSG_REGS + 4 <= WIRE_WORDS);
+
+// IPC（last_reply に入るエラー。MR0 に入れ、len = 1）
+/// reply エラーコード（Dead partner を待っていた等）
+pub const IPC_ERR_DEAD_PARTNER: u64 = 0xDEAD_DEAD_DEAD_DEAD;
+/// endpoint close エラーコード（owner dead 等）
+pub const IPC_ERR_ENDPOINT_CLOSED: u64 = 0xC105_ED00_C105_ED00;
+/// キュー満杯などの capacity エラー
+pub const IPC_ERR_CAPACITY: u64 = 0xC0DE_C0DE_C0DE_C0DE;
+/// prototype 制限: recv_waiter が既に存在
+pub const IPC_ERR_RECV_ALREADY_WAITING: u64 = 0xBADC_0FFE_BADC_0FFE;
+/// reply obligation 超過で client を起こした（ipc_reply_timeout_abort のみ）
+pub const IPC_ERR_SERVER_TIMEOUT: u64 = 0x510B_510B_510B_510B;
+/// IpcSend の timeout（send + reply 待ちの合計 tick 数）が切れた
+pub const IPC_ERR_TIMEOUT: u64 = 0x7E0D_7E0D_7E0D_7E0D;
+/// wait-for graph の閉路を切るために起こされた（ipc_deadlock_break のみ）
+pub const IPC_ERR_DEADLOCK: u64 = 0xDEAD_10C4_DEAD_10C4;
+
+// timer_service（TIMER_SERVICE_EP への send の reply / 通知 msg）
+pub const TIMER_SVC_OK: u64 = 0;
+pub const TIMER_SVC_ERR_BAD_EP: u64 = 1;
+pub const TIMER_SVC_ERR_BAD_TASK: u64 = 2;
+/// 通知 msg の MR0 上位 16bit（下位 48bit は client ごとの通知番号、MR1 は coalesce した周期数）
+pub const TIMER_TICK_TAG: u64 = 0x71C0_0000_0000_0000;
+pub const TIMER_TICK_TAG_MASK: u64 = 0xFFFF_0000_0000_0000;
+
+// net service（NET_SERVICE_EP への send の MR0 = op / reply / 受信通知 msg。feature virtio_net）
+/// MR1 = 受信通知の notify_ep、MR2 = frame を受け渡す shm の handle（client の CapIndex）。reply は [NET_SVC_OK, MAC（下位 48bit）]
+pub const NET_OP_BIND: u64 = 1;
+/// MR1 = shm の handle、MR2 = frame 長（byte）。frame は shm の先頭 page に置く
+pub const NET_OP_SEND: u64 = 2;
+pub const NET_SVC_OK: u64 = 0;
+pub const NET_SVC_ERR_BAD_EP: u64 = 1;
+pub const NET_SVC_ERR_BAD_SHM: u64 = 2;
+pub const NET_SVC_ERR_BAD_LEN: u64 = 3;
+pub const NET_SVC_ERR_NO_DEVICE: u64 = 4;
+pub const NET_SVC_ERR_BAD_OP: u64 = 5;
+pub const NET_SVC_ERR_TX_FAILED: u64 = 6;
+/// 別の task が受信側として BIND 済み
+pub const NET_SVC_ERR_BUSY: u64 = 7;
+/// 受信通知 msg の MR0 上位 16bit（下位 48bit は通知番号、MR1 = frame 長、MR2 = ethertype、MR3 = 捨てた frame の累計）
+pub const NET_RX_TAG: u64 = 0x4E70_0000_0000_0000;
+pub const NET_RX_TAG_MASK: u64 = 0xFFFF_0000_0000_0000;
+
+// -----------------------------------------------------------------------------
+// syscall 番号（ring3 からの入口の sysno。register ABI では rax、mailbox ABI では [rsp-16]）
+// - 引数は a0..a2（register ABI では rdi / rsi / rdx）
+// - mailbox ABI（ring3_mailbox 系）が受け付けるのは 1 / 2 / IPC の 3 つ（+ デモ専用の 30 / 31）
+// -----------------------------------------------------------------------------
+
+/// a0 + a1 + a2 をその場で返す（疎通確認）
+pub const SYS_DEBUG_ADD: u64 = 1;
+/// tick_count をその場で返す（tick の回数。Sleep / IpcSend の期限を測る kernel clock の ns ではない）
+pub const SYS_GET_TICKS: u64 = 2;
+/// IpcRecv { cap = a0 }。ring3 は rax = MR0、rdx = reply cap（0 = 無し）
+pub const SYS_IPC_RECV: u64 = 10;
+/// IpcSend { cap = a0, msg = word(a1), timeout = a2（tick 数、0 = なし。期限は Sleep と同じ kernel clock で測る。time.rs）}
+pub const SYS_IPC_SEND: u64 = 11;
+/// IpcReply { reply_cap = a0（IpcRecv の rdx）, msg = word(a1) }。ring3 は rax = last_syscall_ret
+pub const SYS_IPC_REPLY: u64 = 12;
+/// IpcRecvAny { cap_mask = a0 }。ring3 の戻りは IpcRecv と同じ
+pub const SYS_IPC_RECV_ANY: u64 = 13;
+/// PageMap { page = a0, flags = a1（WRITABLE のみ意味を持つ。PRESENT / USER は常に付く） }
+pub const SYS_PAGE_MAP: u64 = 20;
+/// PageUnmap { page = a0 }
+pub const SYS_PAGE_UNMAP: u64 = 21;
+/// TaskCreate { entry_hint = a0, priority = a1 }
+pub const SYS_TASK_CREATE: u64 = 22;
+/// TaskExit
+pub const SYS_TASK_EXIT: u64 = 23;
+/// EndpointCreate
+pub const SYS_ENDPOINT_CREATE: u64 = 24;
+/// EndpointDelete { cap = a0 }
+pub const SYS_ENDPOINT_DELETE: u64 = 25;
+/// NotifySignal { cap = a0（notification の handle）, bits = a1 }
+pub const SYS_NOTIFY_SIGNAL: u64 = 26;
+/// NotifyWait { cap = a0（notification の handle）}
+pub const SYS_NOTIFY_WAIT: u64 = 27;
+/// Sleep { ticks = a0 }（期限は kernel clock の now + ticks × NS_PER_TICK。IpcSend の timeout と同じ時計。time.rs）
+pub const SYS_SLEEP: u64 = 28;
+// 30 / 31 は mailbox ABI のデモ専用（tick / take_last_reply）なので使わない
+/// ShmCreate { pages = a0 }
+pub const SYS_SHM_CREATE: u64 = 32;
+/// ShmMap { cap = a0（segment の handle）, page = a1 }
+pub const SYS_SHM_MAP: u64 = 33;
+/// ReadInput
+pub const SYS_READ_INPUT: u64 = 34;
+/// FsOpen { page = a0（名前を置いた page）, name_len = a1 }
+pub const SYS_FS_OPEN: u64 = 35;
+/// FsRead { fd = a0, page = a1（書き込み先の page）, offset = a2 }
+pub const SYS_FS_READ: u64 = 36;
+/// Shutdown { code = a0（0 = 成功）}
+pub const SYS_SHUTDOWN: u64 = 37;
+/// FaultHandlerSet { cap = a0（FAULT_HANDLER_NONE = 解除）}
+pub const SYS_FAULT_HANDLER_SET: u64 = 38;
+/// FaultResolve { cap = a0（faulter の task handle）, action = a1（FAULT_ACTION_*）, page = a2（MAP_RESUME のときだけ）}
+pub const SYS_FAULT_RESOLVE: u64 = 39;
+/// GrantWindowSet { page = a0（GRANT_WINDOW_NONE = 外す）}
+pub const SYS_GRANT_WINDOW_SET: u64 = 40;
+/// IpcSend { cap = a0, msg = word(a1) + grant（page = a2）, timeout なし }
+pub const SYS_IPC_SEND_GRANT: u64 = 41;
+/// Revoke { kind = a0（REVOKE_KIND_*）, cap index / page = a1 }
+pub const SYS_REVOKE: u64 = 42;
+/// TaskSuspend { cap = a0（task handle、Control）}
+pub const SYS_TASK_SUSPEND: u64 = 43;
+/// TaskResume { cap = a0（task handle、Control）}
+pub const SYS_TASK_RESUME: u64 = 44;
+/// TaskInfo { cap = a0（task handle、Info）}。ring3 は rdx に TaskInfo（TASK_INFO_*_SHIFT）
+pub const SYS_TASK_INFO: u64 = 45;
+/// EndpointSetBuffer { cap = a0, slots = a1（0 = rendezvous に戻す）}
+pub const SYS_ENDPOINT_SET_BUFFER: u64 = 46;
+/// PageProtect { page = a0, flags = a1（PageMap と同じく WRITABLE のみ意味を持つ）}
+pub const SYS_PAGE_PROTECT: u64 = 47;
+/// MemInfo（引数なし）。ring3 は rdx に MemInfo（MEM_INFO_*_SHIFT）
+pub const SYS_MEM_INFO: u64 = 48;
+
+// -----------------------------------------------------------------------------
+// record kind / sub code
+// -----------------------------------------------------------------------------
+
+pub const KIND_EVENT: u8 = 1;
+pub const KIND_COUNTERS: u8 = 2;
+pub const KIND_TASK_INFO: u8 = 3;
+pub const KIND_ENDPOINT_INFO: u8 = 4;
+pub const KIND_ENDPOINT_STATS: u8 = 5;
+
+// KIND_EVENT の sub（LogEvent の variant）
+pub const EV_TICK_STARTED: u16 = 1;
+pub const EV_TIMER_UPDATED: u16 = 2;
+pub const EV_FRAME_ALLOCATED: u16 = 3;
+pub const EV_TASK_SWITCHED: u16 = 4;
+pub const EV_TASK_STATE_CHANGED: u16 = 5;
+pub const EV_READY_QUEUED: u16 = 6;
+pub const EV_READY_DEQUEUED: u16 = 7;
+pub const EV_WAIT_QUEUED: u16 = 8;
+pub const EV_WAIT_DEQUEUED: u16 = 9;
+pub const EV_RUNTIME_UPDATED: u16 = 10;
+pub const EV_QUANTUM_EXPIRED: u16 = 11;
+pub const EV_MEM_ACTION_APPLIED: u16 = 12;
+pub const EV_SYSCALL_ISSUED: u16 = 13;
+pub const EV_SYSCALL_HANDLED: u16 = 14;
+pub const EV_IPC_RECV_CALLED: u16 = 15;
+pub const EV_IPC_RECV_BLOCKED: u16 = 16;
+pub const EV_IPC_SEND_CALLED: u16 = 17;
+pub const EV_IPC_SEND_BLOCKED: u16 = 18;
+pub const EV_IPC_DELIVERED: u16 = 19;
+pub const EV_IPC_REPLY_CALLED: u16 = 20;
+pub const EV_IPC_REPLY_DELIVERED: u16 = 21;
+pub const EV_TASK_KILLED: u16 = 22;
+pub const EV_SERVER_SLOW: u16 = 23;
+pub const EV_TIMER_FIRED: u16 = 24;
+pub const EV_PRIORITY_INHERITED: u16 = 25;
+pub const EV_PRIORITY_RESTORED: u16 = 26;
+pub const EV_TASK_CREATED: u16 = 27;
+pub const EV_TASK_EXITED: u16 = 28;
+pub const EV_NOTIFY_SIGNALED: u16 = 29;
+pub const EV_NOTIFY_WAIT_BLOCKED: u16 = 30;
+pub const EV_NOTIFY_DELIVERED: u16 = 31;
+pub const EV_FRAME_FREED: u16 = 32;
+pub const EV_CAP_DENIED: u16 = 33;
+pub const EV_CAP_GRANTED: u16 = 34;
+pub const EV_CAP_RECEIVED: u16 = 35;
+pub const EV_ENDPOINT_CREATED: u16 = 36;
+pub const EV_ENDPOINT_DELETED: u16 = 37;
+pub const EV_PAGE_SWAPPED_OUT: u16 = 38;
+pub const EV_PAGE_SWAPPED_IN: u16 = 39;
+pub const EV_SHM_CREATED: u16 = 40;
+pub const EV_SHM_MAPPED: u16 = 41;
+pub const EV_SHM_DESTROYED: u16 = 42;
+pub const EV_TLB_FLUSH_DEFERRED: u16 = 43;
+pub const EV_TLB_FLUSHED: u16 = 44;
+pub const EV_LEVEL_CHANGED: u16 = 45;
+pub const EV_NO_PROGRESS_DETECTED: u16 = 46;
+pub const EV_DEADLOCK_DETECTED: u16 = 47;
+pub const EV_INVARIANT_VIOLATED: u16 = 48;
+pub const EV_INPUT_RECEIVED: u16 = 49;
+pub const EV_FAULT_DELEGATED: u16 = 50;
+pub const EV_FAULT_RESOLVED: u16 = 51;
+pub const EV_PAGE_GRANTED: u16 = 52;
+pub const EV_GRANT_REVOKED: u16 = 53;
+pub const EV_DERIVATION_REVOKED: u16 = 54;
+
+/// event の名前と word の名前（word[i] = keys[i]。keys の後ろの word は使わない）
+pub fn event_schema(sub: u16) -> Option<(&'static str, &'static [&'static str])> {
+    Some(match sub {
+        EV_TICK_STARTED => ("TickStarted", &["tick"]),
+        EV_TIMER_UPDATED => ("TimerUpdated", &["time"]),
+        EV_FRAME_ALLOCATED => ("FrameAllocated", &[]),
+        EV_FRAME_FREED => ("FrameFreed", &["frame"]),
+        EV_TASK_SWITCHED => ("TaskSwitched", &["task"]),
+        EV_TASK_STATE_CHANGED => ("TaskStateChanged", &["task", "state"]),
+        EV_READY_QUEUED => ("ReadyQueued", &["task"]),
+        EV_READY_DEQUEUED => ("ReadyDequeued", &["task"]),
+        EV_WAIT_QUEUED => ("WaitQueued", &["task"]),
+        EV_WAIT_DEQUEUED => ("WaitDequeued", &["task"]),
+        EV_RUNTIME_UPDATED => ("RuntimeUpdated", &["task", "runtime"]),
+        EV_QUANTUM_EXPIRED => ("QuantumExpired", &["task", "used_ticks"]),
+        EV_MEM_ACTION_APPLIED => ("MemActionApplied", &["task", "address_space_id", "action", "page", "frame", "flags"]),
+        EV_SYSCALL_ISSUED => ("SyscallIssued", &["task"]),
+        EV_SYSCALL_HANDLED => ("SyscallHandled", &["task"]),
+        EV_IPC_RECV_CALLED => ("IpcRecvCalled", &["task", "ep"]),
+        EV_IPC_RECV_BLOCKED => ("IpcRecvBlocked", &["task", "ep"]),
+        EV_IPC_SEND_CALLED => ("IpcSendCalled", &["task", "ep", "msg", "msg_len", "mr1", "mr2", "mr3"]),
+        EV_IPC_SEND_BLOCKED => ("IpcSendBlocked", &["task", "ep"]),
+        EV_IPC_DELIVERED => ("IpcDelivered", &["from", "to", "ep", "msg", "msg_len", "mr1", "mr2", "mr3"]),
+        EV_IPC_REPLY_CALLED => ("IpcReplyCalled", &["task", "ep", "to"]),
+        EV_IPC_REPLY_DELIVERED => ("IpcReplyDelivered", &["from", "to", "ep", "msg", "msg_len", "mr1", "mr2", "mr3"]),
+        // arg0..2 の意味は reason（KILL_*）ごと
+        EV_TASK_KILLED => ("TaskKilled", &["task", "reason", "arg0", "arg1", "arg2"]),
+        EV_SERVER_SLOW => ("ServerSlow", &["server", "client", "ep", "held_ticks"]),
+        EV_TIMER_FIRED => ("TimerFired", &["task", "ep", "missed"]),
+        EV_PRIORITY_INHERITED => ("PriorityInherited", &["task", "from", "priority"]),
+        EV_PRIORITY_RESTORED => ("PriorityRestored", &["task", "priority"]),
+        EV_TASK_CREATED => ("TaskCreated", &["task", "parent", "slot", "priority"]),
+        EV_TASK_EXITED => ("TaskExited", &["task"]),
+        EV_NOTIFY_SIGNALED => ("NotifySignaled", &["task", "ntfn", "bits"]),
+        EV_NOTIFY_WAIT_BLOCKED => ("NotifyWaitBlocked", &["task", "ntfn"]),
+        EV_NOTIFY_DELIVERED => ("NotifyDelivered", &["to", "ntfn", "bits"]),
+        EV_CAP_DENIED => ("CapDenied", &["task", "cap", "need"]),
+        // object は kind（OBJECT_KIND_*）の table の id（kind = endpoint なら EndpointId）
+        EV_CAP_GRANTED => ("CapGranted", &["from", "to", "cap", "object", "kind"]),
+        EV_CAP_RECEIVED => ("CapReceived", &["task", "slot", "object", "rights", "kind"]),
+        EV_ENDPOINT_CREATED => ("EndpointCreated", &["task", "ep", "cap"]),
+        EV_ENDPOINT_DELETED => ("EndpointDeleted", &["task", "ep", "revoked"]),
+        EV_PAGE_SWAPPED_OUT => ("PageSwappedOut", &["task", "page", "slot"]),
+        EV_PAGE_SWAPPED_IN => ("PageSwappedIn", &["task", "page", "slot"]),
+        EV_SHM_CREATED => ("ShmCreated", &["task", "shm", "pages"]),
+        EV_SHM_MAPPED => ("ShmMapped", &["task", "shm", "page"]),
+        EV_SHM_DESTROYED => ("ShmDestroyed", &["owner", "shm", "unmapped"]),
+        EV_TLB_FLUSH_DEFERRED => ("TlbFlushDeferred", &["address_space_id", "page"]),
+        EV_TLB_FLUSHED => ("TlbFlushed", &["address_space_id", "pages", "stale_ticks"]),
+        EV_LEVEL_CHANGED => ("LevelChanged", &["task", "from", "to"]),
+        EV_NO_PROGRESS_DETECTED => ("NoProgressDetected", &["stalled_ticks", "last_progress_tick", "task_states"]),
+        EV_DEADLOCK_DETECTED => ("DeadlockDetected", &["len", "task0", "task1", "task2", "task3"]),
+        EV_INVARIANT_VIOLATED => ("InvariantViolated", &["invariant", "task", "detail"]),
+        EV_INPUT_RECEIVED => ("InputReceived", &["scancode", "queued"]),
+        EV_FAULT_DELEGATED => ("FaultDelegated", &["task", "ep", "addr"]),
+        // action は FAULT_ACTION_*
+        EV_FAULT_RESOLVED => ("FaultResolved", &["task", "by", "action"]),
+        // page は受け取った側の window
+        EV_PAGE_GRANTED => ("PageGranted", &["from", "to", "page", "frame"]),
+        // reason は GRANT_REVOKE_*
+        EV_GRANT_REVOKED => ("GrantRevoked", &["from", "to", "page", "reason"]),
+        // kind は REVOKE_KIND_*、object は task の cap index / page、depth は Revoke した object から何段目か
+        EV_DERIVATION_REVOKED => ("DerivationRevoked", &["by", "task", "kind", "object", "depth"]),
+        _ => return None,
+    })
+}
+
+/// KIND_COUNTERS の並び（page をまたいで通し。encode_counters と同じ順）
+pub const WIRE_COUNTERS: usize = 89;
+pub const COUNTER_KEYS: [&str; WIRE_COUNTERS] = [
+    "sched_switches",
+    "ipc_send_fast",
+    "ipc_send_slow",
+    "ipc_recv_fast",
+    "ipc_recv_slow",
+    "ipc_reply_delivered",
+    "task_killed_user_pf",
+    "task_killed_demo_injected",
+    "ipc_events_seen",
+    "ipc_events_skipped",
+    "task_killed_trap_frame",
+    "ipc_server_slow",
+    "ipc_reply_timeouts",
+    "task_killed_fault_storm",
+    "user_pf_total",
+    "prio_inherited",
+    "prio_restored",
+    "task_created",
+    "task_exited",
+    "sched_rr_max_passes",
+    "notify_signals",
+    "notify_delivered",
+    "notify_wait_blocked",
+    "frames_allocated",
+    "frames_freed",
+    "frames_reused",
+    "frames_double_free",
+    "cap_denied",
+    "cap_transfers",
+    "cap_transfer_failed",
+    "ipc_call_timeouts",
+    "syscall_entry_int80",
+    "syscall_entry_syscall",
+    "task_killed_stack_overflow",
+    "swap_out",
+    "swap_in",
+    "tlb_flush_deferred",
+    "tlb_flushes",
+    "mlfq_demotions",
+    "mlfq_promotions",
+    "mlfq_level0_ticks",
+    "mlfq_level1_ticks",
+    "mlfq_level2_ticks",
+    "idle_entries",
+    "watchdog_no_progress",
+    "ipc_deadlocks_detected",
+    "ipc_deadlocks_broken",
+    "events_overwritten",
+    "events_overwritten_sched",
+    "events_overwritten_ipc",
+    "events_overwritten_mem",
+    "events_overwritten_task",
+    "events_overwritten_diag",
+    "critical_events_retained",
+    "critical_events_lost",
+    "invariant_violations",
+    "invariant_checks_full",
+    "invariant_checks_cheap",
+    "input_received",
+    "input_dropped",
+    "input_read",
+    "task_killed_user_exception",
+    "task_killed_shutdown",
+    "fault_delegated",
+    "fault_resumed",
+    "fault_orphaned",
+    "grant_mapped",
+    "grant_revoked",
+    "grant_failed",
+    "derivations_recorded",
+    "derivations_revoked",
+    "derivation_table_full",
+    "tasks_suspended",
+    "tasks_resumed",
+    "suspend_recv_deferred",
+    "task_info_queries",
+    "reply_caps_minted",
+    "reply_caps_rejected",
+    "ipc_buffered_sends",
+    "ipc_buffered_delivered",
+    "ipc_buffer_dropped",
+    "ipc_rendezvous_delivered",
+    "ipc_handoff_direct",
+    "ipc_handoff_scheduled",
+    "ipc_handoff_scheduled_ticks",
+    "ipc_handoff_scheduled_max_ticks",
+    "ipc_direct_switch_declined",
+    "mappings_rejected",
+    "task_killed_swap_failed",
+];
+
+/// KIND_TASK_INFO の word の名前（sub = task index）
+pub const TASK_INFO_KEYS: [&str; WIRE_WORDS] =
+    ["task", "state", "priority", "runtime", "address_space_id", "blocked", "blocked_ep", "blocked_partner"];
+
+/// KIND_ENDPOINT_INFO の word の名前（sub = ep id）
+/// - send_queue_head: 次に deliver される sender の task index（send_queue は FIFO。task_queue.rs）
+pub const ENDPOINT_INFO_KEYS: [&str; 8] =
+    ["ep", "owner", "closed", "recv_waiter", "send_queue_len", "reply_queue_len", "allocated", "send_queue_head"];
+
+/// KIND_ENDPOINT_STATS の word の名前（sub = ep id。endpoint_stats.rs、一度も使われていない endpoint は出さない）
+pub const ENDPOINT_STATS_KEYS: [&str; WIRE_WORDS] =
+    ["ep", "delivered", "send_queue_max", "reply_queue_max", "rejected_closed", "rejected_kernel_task", "waits", "block_ticks"];
+
+// TaskState
+pub const STATE_READY: u64 = 0;
+pub const STATE_RUNNING: u64 = 1;
+pub const STATE_BLOCKED: u64 = 2;
+pub const STATE_DEAD: u64 = 3;
+pub const STATE_SUSPENDED: u64 = 4;
+
+// BlockedReason
+pub const BLOCKED_NONE: u64 = 0;
+pub const BLOCKED_SLEEP: u64 = 1;
+pub const BLOCKED_IPC_RECV: u64 = 2;
+pub const BLOCKED_IPC_SEND: u64 = 3;
+pub const BLOCKED_IPC_REPLY: u64 = 4;
+pub const BLOCKED_NOTIFY_WAIT: u64 = 5;
+/// IpcRecvAny（task info の blocked_ep は endpoint の mask）
+pub const BLOCKED_IPC_RECV_ANY: u64 = 6;
+/// FaultWait（task info の blocked_ep は fault handler の endpoint）
+pub const BLOCKED_FAULT_WAIT: u64 = 7;
+
+/// EV_INVARIANT_VIOLATED の invariant（InvariantId の code）の名前
+/// - docs/spec/clauses.txt の ID。条項の無い構造的な検査は "STRUCT-*"
+/// - 追加は末尾のみ（invariant.rs の InvariantId と同じ順）
+pub const INVARIANT_NAMES: [&str; 44] = [
+    "STRUCT-ADDRESS-SPACE",
+    "STRUCT-TASK-STATE",
+    "INV-SCHED-001",
+    "INV-SCHED-002",
+    "INV-SCHED-003",
+    "INV-SCHED-004",
+    "INV-SCHED-005",
+    "INV-SCHED-006",
+    "INV-WAIT-001",
+    "INV-WAIT-002",
+    "INV-IPC-001",
+    "INV-IPC-002",
+    "INV-IPC-003",
+    "STRUCT-IPC-SEND-QUEUE",
+    "INV-IPC-006",
+    "INV-IPC-007",
+    "INV-IPC-008",
+    "INV-IPC-009",
+    "INV-EP-001",
+    "INV-EP-002",
+    "INV-NTFN-001",
+    "INV-NTFN-002",
+    "INV-TASK-001",
+    "INV-CAP-002",
+    "INV-KILL-001",
+    "INV-MEM-002",
+    "INV-MEM-003",
+    "INV-MEM-004",
+    "INV-MEM-005",
+    "INV-MEM-006",
+    "INV-MEM-007",
+    "INV-MEM-008",
+    "INV-MEM-009",
+    "INV-MEM-010",
+    "INV-MEM-011",
+    "INV-HEAP-001",
+    "INV-FAULT-001",
+    "INV-GRANT-001",
+    "INV-DERIV-001",
+    "INV-TASK-002",
+    "INV-CAP-003",
+    "INV-IPC-010",
+    "INV-IPC-011",
+    "INV-IPC-012",
+];
+
+// MemAction
+pub const MEM_ACTION_MAP: u64 = 1;
+pub const MEM_ACTION_UNMAP: u64 = 2;
+pub const MEM_ACTION_PROTECT: u64 = 3;
+
+// TaskKillReason
+pub const KILL_USER_PAGE_FAULT: u64 = 1;
+pub const KILL_DEMO_INJECTED: u64 = 2;
+pub const KILL_TRAP_FRAME_CORRUPT: u64 = 3;
+pub const KILL_FAULT_STORM: u64 = 4;
+pub const KILL_STACK_OVERFLOW: u64 = 5;
+pub const KILL_USER_EXCEPTION: u64 = 6;
+pub const KILL_SHUTDOWN: u64 = 7;
+pub const KILL_SWAP_FAILED: u64 = 8;
+
+// -----------------------------------------------------------------------------
+// record
+// -----------------------------------------------------------------------------
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WireDecodeError {
+    BadLength,
+    BadVersion,
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct WireRecord {
+    pub bytes: [u8; WIRE_RECORD_SIZE],
+}
+
+impl WireRecord {
+    pub const fn new(kind: u8, sub: u16) -> Self {
+        let mut bytes = [0u8; WIRE_RECORD_SIZE];
+        let sub_le = sub.to_le_bytes();
+        bytes[0] = WIRE_VERSION;
+        bytes[1] = kind;
+        bytes[2] = sub_le[0];
+        bytes[3] = sub_le[1];
+        WireRecord { bytes }
+    }
+
+    pub fn from_bytes(src: &[u8]) -> Result<Self, WireDecodeError> {
+        if src.len() != WIRE_RECORD_SIZE {
+            return Err(WireDecodeError::BadLength);
+        }
+        if src[0] != WIRE_VERSION {
+            return Err(WireDecodeError::BadVersion);
+        }
+        let mut bytes = [0u8; WIRE_RECORD_SIZE];
+        bytes.copy_from_slice(src);
+        Ok(WireRecord { bytes })
+    }
+
+    pub fn version(&self) -> u8 {
+        self.bytes[0]
+    }
+
+    pub fn kind(&self) -> u8 {
+        self.bytes[1]
+    }
+
+    pub fn sub(&self) -> u16 {
+        u16::from_le_bytes([self.bytes[2], self.bytes[3]])
+    }
+
+    /// 全 sink 共通の通し番号（0 = 未設定）
+    pub fn seq(&self) -> u64 {
+        let mut b = [0u8; 8];
+        b.copy_from_slice(&self.bytes[8..16]);
+        u64::from_le_bytes(b)
+    }
+
+    pub fn set_seq(&mut self, seq: u64) {
+        self.bytes[8..16].copy_from_slice(&seq.to_le_bytes());
+    }
+
+    /// word i に little-endian で書く（範囲外は無視）
+    pub fn put(&mut self, i: usize, v: u64) {
+        if i >= WIRE_WORDS {
+            return;
+        }
+        let off = WIRE_HEADER_SIZE + i * 8;
+        self.bytes[off..off + 8].copy_from_slice(&v.to_le_bytes());
+    }
+
+    /// word i を読む（範囲外は 0）
+    pub fn word(&self, i: usize) -> u64 {
+        if i >= WIRE_WORDS {
+            return 0;
+        }
+        let off = WIRE_HEADER_SIZE + i * 8;
+        let mut b = [0u8; 8];
+        b.copy_from_slice(&self.bytes[off..off + 8]);
+        u64::from_le_bytes(b)
+    }
+}
+
+pub fn opt_word(v: Option<u64>) -> u64 {
+    v.unwrap_or(WIRE_NONE)
+}
+
+// -----------------------------------------------------------------------------
+// encode（カーネル側のみ）
+// -----------------------------------------------------------------------------
+
+#[cfg(not(wire_decode_only))]
+pub use self::encode::*;
+
+#[cfg(not(wire_decode_only))]
+mod encode {
+    use super::*;
+    use crate::kernel::endpoint_stats::EndpointStats;
+    use crate::kernel::ipc::{Endpoint, IpcMessage};
+    use crate::kernel::{
+        BlockedReason, KernelCounters, LogEvent, Task, TaskKillReason, TaskState,
+    };
+    use crate::mem::paging::MemAction;
+    use crate::mm::FrameStats;
+
+    fn state_code(s: TaskState) -> u64 {
+        match s {
+            TaskState::Ready => STATE_READY,
+            TaskState::Running => STATE_RUNNING,
+            TaskState::Blocked => STATE_BLOCKED,
+            TaskState::Dead => STATE_DEAD,
+            TaskState::Suspended => STATE_SUSPENDED,
+        }
+    }
+
+    fn simple(sub: u16, w0: u64) -> WireRecord {
+        let mut r = WireRecord::new(KIND_EVENT, sub);
+        r.put(0, w0);
+        r
+    }
+
+    /// IPC メッセージ: word[at] = MR0, word[at+1] = len, word[at+2..] = MR1..（len を超える MR は 0）
+    fn put_msg(r: &mut WireRecord, at: usize, msg: &IpcMessage) {
+        r.put(at, msg.mr0());
+        r.put(at + 1, msg.len() as u64);
+        for i in 1..IPC_MSG_REGS {
+            r.put(at + 1 + i, msg.mr(i));
+        }
+    }
+
+    pub fn encode_event(ev: &LogEvent) -> WireRecord {
+        match *ev {
+            LogEvent::TickStarted(n) => simple(EV_TICK_STARTED, n),
+            LogEvent::TimerUpdated(n) => simple(EV_TIMER_UPDATED, n),
+            LogEvent::FrameAllocated => WireRecord::new(KIND_EVENT, EV_FRAME_ALLOCATED),
+            LogEvent::FrameFreed { frame } => simple(EV_FRAME_FREED, frame),
+            LogEvent::TaskSwitched(t) => simple(EV_TASK_SWITCHED, t.0),
+            LogEvent::TaskStateChanged(t, s) => {
+                let mut r = simple(EV_TASK_STATE_CHANGED, t.0);
+                r.put(1, state_code(s));
+                r
+            }
+            LogEvent::ReadyQueued(t) => simple(EV_READY_QUEUED, t.0),
+            LogEvent::ReadyDequeued(t) => simple(EV_READY_DEQUEUED, t.0),
+            LogEvent::WaitQueued(t) => simple(EV_WAIT_QUEUED, t.0),
+            LogEvent::WaitDequeued(t) => simple(EV_WAIT_DEQUEUED, t.0),
+            LogEvent::RuntimeUpdated(t, rt) => {
+                let mut r = simple(EV_RUNTIME_UPDATED, t.0);
+                r.put(1, rt);
+                r
+            }
+            LogEvent::QuantumExpired(t, used) => {
+                let mut r = simple(EV_QUANTUM_EXPIRED, t.0);
+                r.put(1, used);
+                r
+            }
+            LogEvent::MemActionApplied { task, address_space, action } => {
+                let mut r = simple(EV_MEM_ACTION_APPLIED, task.0);
+                r.put(1, address_space.0 as u64);
+                match action {
+                    MemAction::Map { page, frame, flags } => {
+                        r.put(2, MEM_ACTION_MAP);
+                        r.put(3, page.number);
+                        r.put(4, frame.number);
+                        r.put(5, flags.bits());
+                    }
+                    MemAction::Unmap { page } => {
+                        r.put(2, MEM_ACTION_UNMAP);
+                        r.put(3, page.number);
+                    }
+                    MemAction::Protect { page, new_flags } => {
+                        r.put(2, MEM_ACTION_PROTECT);
+                        r.put(3, page.number);
+                        r.put(5, new_flags.bits());
+                    }
+                }
+                r
+            }
+            LogEvent::SyscallIssued { task } => simple(EV_SYSCALL_ISSUED, task.0),
+            LogEvent::SyscallHandled { task } => simple(EV_SYSCALL_HANDLED, task.0),
+            LogEvent::IpcRecvCalled { task, ep } => {
+                let mut r = simple(EV_IPC_RECV_CALLED, task.0);
+                r.put(1, ep.0 as u64);
+                r
+            }
+            LogEvent::IpcRecvBlocked { task, ep } => {
+                let mut r = simple(EV_IPC_RECV_BLOCKED, task.0);
+                r.put(1, ep.0 as u64);
+                r
+            }
+            LogEvent::IpcSendCalled { task, ep, msg } => {
+                let mut r = simple(EV_IPC_SEND_CALLED, task.0);
+                r.put(1, ep.0 as u64);
+                put_msg(&mut r, 2, &msg);
+                r
+            }
+            LogEvent::IpcSendBlocked { task, ep } => {
+                let mut r = simple(EV_IPC_SEND_BLOCKED, task.0);
+                r.put(1, ep.0 as u64);
+                r
+            }
+            LogEvent::IpcDelivered { from, to, ep, msg } => {
+                let mut r = simple(EV_IPC_DELIVERED, from.0);
+                r.put(1, to.0);
+                r.put(2, ep.0 as u64);
+                put_msg(&mut r, 3, &msg);
+                r
+            }
+            LogEvent::IpcReplyCalled { task, ep, to } => {
+                let mut r = simple(EV_IPC_REPLY_CALLED, task.0);
+                r.put(1, ep.0 as u64);
+                r.put(2, to.0);
+                r
+            }
+            LogEvent::IpcReplyDelivered { from, to, ep, msg } => {
+                let mut r = simple(EV_IPC_REPLY_DELIVERED, from.0);
+                r.put(1, to.0);
+                r.put(2, ep.0 as u64);
+                put_msg(&mut r, 3, &msg);
+                r
+            }
+            LogEvent::ServerSlow { server, client, ep, held_ticks } => {
+                let mut r = simple(EV_SERVER_SLOW, server.0);
+                r.put(1, client.0);
+                r.put(2, ep.0 as u64);
+                r.put(3, held_ticks);
+                r
+            }
+            LogEvent::TimerFired { task, ep, missed } => {
+                let mut r = simple(EV_TIMER_FIRED, task.0);
+                r.put(1, ep.0 as u64);
+                r.put(2, missed);
+                r
+            }
+            LogEvent::PriorityInherited { task, from, priority } => {
+                let mut r = simple(EV_PRIORITY_INHERITED, task.0);
+                r.put(1, from.0);
+                r.put(2, priority as u64);
+                r
+            }
+            LogEvent::PriorityRestored { task, priority } => {
+                let mut r = simple(EV_PRIORITY_RESTORED, task.0);
+                r.put(1, priority as u64);
+                r
+            }
+            LogEvent::TaskCreated { task, parent, slot, priority } => {
+                let mut r = simple(EV_TASK_CREATED, task.0);
+                r.put(1, parent.0);
+                r.put(2, slot as u64);
+                r.put(3, priority as u64);
+                r
+            }
+            LogEvent::TaskExited { task } => simple(EV_TASK_EXITED, task.0),
+            LogEvent::NotifySignaled { task, ntfn, bits } => {
+                let mut r = simple(EV_NOTIFY_SIGNALED, task.0);
+                r.put(1, ntfn.0 as u64);
+                r.put(2, bits);
+                r
+            }
+            LogEvent::NotifyWaitBlocked { task, ntfn } => {
+                let mut r = simple(EV_NOTIFY_WAIT_BLOCKED, task.0);
+                r.put(1, ntfn.0 as u64);
+                r
+            }
+            LogEvent::NotifyDelivered { to, ntfn, bits } => {
+                let mut r = simple(EV_NOTIFY_DELIVERED, to.0);
+                r.put(1, ntfn.0 as u64);
+                r.put(2, bits);
+                r
+            }
+            LogEvent::CapDenied { task, cap, need } => {
+                let mut r = simple(EV_CAP_DENIED, task.0);
+                r.put(1, cap as u64);
+                r.put(2, need as u64);
+                r
+            }
+            LogEvent::CapGranted { from, to, cap, object } => {
+                let mut r = simple(EV_CAP_GRANTED, from.0);
+                r.put(1, to.0);
+                r.put(2, cap as u64);
+                r.put(3, object.id());
+                r.put(4, object.kind().code());
+                r
+            }
+            LogEvent::CapReceived { task, slot, object, rights } => {
+                let mut r = simple(EV_CAP_RECEIVED, task.0);
+                r.put(1, slot as u64);
+                r.put(2, object.id());
+                r.put(3, rights as u64);
+                r.put(4, object.kind().code());
+                r
+            }
+            LogEvent::EndpointCreated { task, ep, cap } => {
+                let mut r = simple(EV_ENDPOINT_CREATED, task.0);
+                r.put(1, ep.0 as u64);
+                r.put(2, cap as u64);
+                r
+            }
+            LogEvent::EndpointDeleted { task, ep, revoked } => {
+                let mut r = simple(EV_ENDPOINT_DELETED, task.0);
+                r.put(1, ep.0 as u64);
+                r.put(2, revoked as u64);
+                r
+            }
+            LogEvent::PageSwappedOut { task, page, slot } => {
+                let mut r = simple(EV_PAGE_SWAPPED_OUT, task.0);
+                r.put(1, page);
+                r.put(2, slot as u64);
+                r
+            }
+            LogEvent::PageSwappedIn { task, page, slot } => {
+                let mut r = simple(EV_PAGE_SWAPPED_IN, task.0);
+                r.put(1, page);
+                r.put(2, slot as u64);
+                r
+            }
+            LogEvent::ShmCreated { task, shm, pages } => {
+                let mut r = simple(EV_SHM_CREATED, task.0);
+                r.put(1, shm.0 as u64);
+                r.put(2, pages as u64);
+                r
+            }
+            LogEvent::ShmMapped { task, shm, page } => {
+                let mut r = simple(EV_SHM_MAPPED, task.0);
+                r.put(1, shm.0 as u64);
+                r.put(2, page);
+                r
+            }
+            LogEvent::ShmDestroyed { owner, shm, unmapped } => {
+                let mut r = simple(EV_SHM_DESTROYED, owner.0);
+                r.put(1, shm.0 as u64);
+                r.put(2, unmapped as u64);
+                r
+            }
+            LogEvent::TlbFlushDeferred { address_space, page } => {
+                let mut r = simple(EV_TLB_FLUSH_DEFERRED, address_space.0 as u64);
+                r.put(1, page);
+                r
+            }
+            LogEvent::TlbFlushed { address_space, pages, stale_ticks } => {
+                let mut r = simple(EV_TLB_FLUSHED, address_space.0 as u64);
+                r.put(1, pages as u64);
+                r.put(2, stale_ticks);
+                r
+            }
+            LogEvent::LevelChanged { task, from, to } => {
+                let mut r = simple(EV_LEVEL_CHANGED, task.0);
+                r.put(1, from as u64);
+                r.put(2, to as u64);
+                r
+            }
+            LogEvent::NoProgressDetected { stalled_ticks, last_progress_tick, task_states } => {
+                let mut r = simple(EV_NO_PROGRESS_DETECTED, stalled_ticks);
+                r.put(1, last_progress_tick);
+                r.put(2, task_states);
+                r
+            }
+            LogEvent::DeadlockDetected { len, tasks } => {
+                let mut r = simple(EV_DEADLOCK_DETECTED, len as u64);
+                for (i, t) in tasks.iter().enumerate() {
+                    r.put(1 + i, opt_word(t.map(|t| t.0)));
+                }
+                r
+            }
+            LogEvent::InvariantViolated { id, task, detail } => {
+                let mut r = simple(EV_INVARIANT_VIOLATED, id.code());
+                r.put(1, opt_word(task.map(|t| t.0)));
+                r.put(2, opt_word(detail));
+                r
+            }
+            LogEvent::InputReceived { scancode, queued } => {
+                let mut r = simple(EV_INPUT_RECEIVED, scancode as u64);
+                r.put(1, queued as u64);
+                r
+            }
+            LogEvent::FaultDelegated { task, ep, addr } => {
+                let mut r = simple(EV_FAULT_DELEGATED, task.0);
+                r.put(1, ep.0 as u64);
+                r.put(2, addr);
+                r
+            }
+            LogEvent::FaultResolved { task, by, action } => {
+                let mut r = simple(EV_FAULT_RESOLVED, task.0);
+                r.put(1, by.0);
+                r.put(2, action);
+                r
+            }
+            LogEvent::PageGranted { from, to, page, frame } => {
+                let mut r = simple(EV_PAGE_GRANTED, from.0);
+                r.put(1, to.0);
+                r.put(2, page);
+                r.put(3, frame);
+                r
+            }
+            LogEvent::GrantRevoked { from, to, page, reason } => {
+                let mut r = simple(EV_GRANT_REVOKED, from.0);
+                r.put(1, to.0);
+                r.put(2, page);
+                r.put(3, reason);
+                r
+            }
+            LogEvent::DerivationRevoked { by, task, kind, object, depth } => {
+                let mut r = simple(EV_DERIVATION_REVOKED, by.0);
+                r.put(1, task.0);
+                r.put(2, kind);
+                r.put(3, object);
+                r.put(4, depth);
+                r
+            }
+            LogEvent::TaskKilled { task, reason } => {
+                let mut r = simple(EV_TASK_KILLED, task.0);
+                match reason {
+                    TaskKillReason::UserPageFault { addr, err, rip } => {
+                        r.put(1, KILL_USER_PAGE_FAULT);
+                        r.put(2, addr);
+                        r.put(3, err);
+                        r.put(4, rip);
+                    }
+                    TaskKillReason::DemoInjected { code } => {
+                        r.put(1, KILL_DEMO_INJECTED);
+                        r.put(2, code);
+                    }
+                    TaskKillReason::TrapFrameCorrupt { check, value } => {
+                        r.put(1, KILL_TRAP_FRAME_CORRUPT);
+                        r.put(2, check);
+                        r.put(3, value);
+                    }
+                    TaskKillReason::FaultStorm { faults, window_ticks } => {
+                        r.put(1, KILL_FAULT_STORM);
+                        r.put(2, faults);
+                        r.put(3, window_ticks);
+                    }
+                    TaskKillReason::StackOverflow { addr, rip } => {
+                        r.put(1, KILL_STACK_OVERFLOW);
+                        r.put(2, addr);
+                        r.put(3, rip);
+                    }
+                    TaskKillReason::UserException { vector, err, rip } => {
+                        r.put(1, KILL_USER_EXCEPTION);
+                        r.put(2, vector);
+                        r.put(3, err);
+                        r.put(4, rip);
+                    }
+                    TaskKillReason::Shutdown { by } => {
+                        r.put(1, KILL_SHUTDOWN);
+                        r.put(2, by.0);
+                    }
+                    TaskKillReason::SwapFailed { page, stage } => {
+                        r.put(1, KILL_SWAP_FAILED);
+                        r.put(2, page);
+                        r.put(3, stage);
+                    }
+                }
+                r
+            }
+        }
+    }
+
+    /// counters は 8 個ずつ page に分ける（sub = page 番号）
+    /// - 物理フレームの集計（FrameStats）は KernelCounters の後ろに続ける
+    pub fn encode_counters(c: &KernelCounters, frames: &FrameStats, page: u16) -> Option<WireRecord> {
+        let all = counter_values(c, frames);
+
+        let start = page as usize * WIRE_WORDS;
+        if start >= all.len() {
+            return None;
+        }
+
+        let mut r = WireRecord::new(KIND_COUNTERS, page);
+        for (i, v) in all[start..].iter().take(WIRE_WORDS).enumerate() {
+            r.put(i, *v);
+        }
+        Some(r)
+    }
+
+    /// counters を COUNTER_KEYS の順に並べる（追加は末尾のみ）
+    pub fn counter_values(c: &KernelCounters, frames: &FrameStats) -> [u64; WIRE_COUNTERS] {
+        [
+            c.sched_switches,
+            c.ipc_send_fast,
+            c.ipc_send_slow,
+            c.ipc_recv_fast,
+            c.ipc_recv_slow,
+            c.ipc_reply_delivered,
+            c.task_killed_user_pf,
+            c.task_killed_demo_injected,
+            c.ipc_events_seen,
+            c.ipc_events_skipped,
+            c.task_killed_trap_frame,
+            c.ipc_server_slow,
+            c.ipc_reply_timeouts,
+            c.task_killed_fault_storm,
+            c.user_pf_total,
+            c.prio_inherited,
+            c.prio_restored,
+            c.task_created,
+            c.task_exited,
+            c.sched_rr_max_passes,
+            c.notify_signals,
+            c.notify_delivered,
+            c.notify_wait_blocked,
+            frames.allocated,
+            frames.freed,
+            frames.reused,
+            frames.double_free,
+            c.cap_denied,
+            c.cap_transfers,
+            c.cap_transfer_failed,
+            c.ipc_call_timeouts,
+            c.syscall_entry_int80,
+            c.syscall_entry_syscall,
+            c.task_killed_stack_overflow,
+            c.swap_out,
+            c.swap_in,
+            c.tlb_flush_deferred,
+            c.tlb_flushes,
+            c.mlfq_demotions,
+            c.mlfq_promotions,
+            c.mlfq_level_ticks[0],
+            c.mlfq_level_ticks[1],
+            c.mlfq_level_ticks[2],
+            c.idle_entries,
+            c.watchdog_no_progress,
+            c.ipc_deadlocks_detected,
+            c.ipc_deadlocks_broken,
+            c.events_overwritten,
+            c.events_overwritten_by_class[0],
+            c.events_overwritten_by_class[1],
+            c.events_overwritten_by_class[2],
+            c.events_overwritten_by_class[3],
+            c.events_overwritten_by_class[4],
+            c.critical_events_retained,
+            c.critical_events_lost,
+            c.invariant_violations,
+            c.invariant_checks_full,
+            c.invariant_checks_cheap,
+            c.input_received,
+            c.input_dropped,
+            c.input_read,
+            c.task_killed_user_exception,
+            c.task_killed_shutdown,
+            c.fault_delegated,
+            c.fault_resumed,
+            c.fault_orphaned,
+            c.grant_mapped,
+            c.grant_revoked,
+            c.grant_failed,
+            c.derivations_recorded,
+            c.derivations_revoked,
+            c.derivation_table_full,
+            c.tasks_suspended,
+            c.tasks_resumed,
+            c.suspend_recv_deferred,
+            c.task_info_queries,
+            c.reply_caps_minted,
+            c.reply_caps_rejected,
+            c.ipc_buffered_sends,
+            c.ipc_buffered_delivered,
+            c.ipc_buffer_dropped,
+            c.ipc_rendezvous_delivered,
+            c.ipc_handoff_direct,
+            c.ipc_handoff_scheduled,
+            c.ipc_handoff_scheduled_ticks,
+            c.ipc_handoff_scheduled_max_ticks,
+            c.ipc_direct_switch_declined,
+            c.mappings_rejected,
+            c.task_killed_swap_failed,
+        ]
+    }
+
+    pub fn encode_task_info(task_index: usize, t: &Task) -> WireRecord {
+        let mut r = WireRecord::new(KIND_TASK_INFO, task_index as u16);
+        r.put(0, t.id.0);
+        r.put(1, state_code(t.state));
+        r.put(2, t.priority as u64);
+        r.put(3, t.runtime_ticks);
+        r.put(4, t.address_space_id.0 as u64);
+
+        let (code, ep, partner) = match t.blocked_reason {
+            None => (BLOCKED_NONE, WIRE_NONE, WIRE_NONE),
+            Some(BlockedReason::Sleep) => (BLOCKED_SLEEP, WIRE_NONE, WIRE_NONE),
+            Some(BlockedReason::IpcRecv { ep }) => (BLOCKED_IPC_RECV, ep.0 as u64, WIRE_NONE),
+            Some(BlockedReason::IpcRecvAny { ep_mask }) => (BLOCKED_IPC_RECV_ANY, ep_mask, WIRE_NONE),
+            Some(BlockedReason::IpcSend { ep }) => (BLOCKED_IPC_SEND, ep.0 as u64, WIRE_NONE),
+            Some(BlockedReason::IpcReply { partner, ep }) => {
+                (BLOCKED_IPC_REPLY, ep.0 as u64, partner.0)
+            }
+            // ep の word に notification id を入れる
+            Some(BlockedReason::NotifyWait { ntfn }) => (BLOCKED_NOTIFY_WAIT, ntfn.0 as u64, WIRE_NONE),
+            Some(BlockedReason::FaultWait { ep }) => (BLOCKED_FAULT_WAIT, ep.0 as u64, WIRE_NONE),
+        };
+        r.put(5, code);
+        r.put(6, ep);
+        r.put(7, partner);
+        r
+    }
+
+    pub fn encode_endpoint_info(ep: &Endpoint) -> WireRecord {
+        let mut r = WireRecord::new(KIND_ENDPOINT_INFO, ep.id.0 as u16);
+        r.put(0, ep.id.0 as u64);
+        r.put(1, opt_word(ep.owner.map(|t| t.0)));
+        r.put(2, ep.is_closed as u64);
+        r.put(3, opt_word(ep.recv_waiter.map(|i| i as u64)));
+        r.put(4, ep.send_queue.len() as u64);
+        r.put(5, ep.reply_queue.len() as u64);
+        r.put(6, ep.allocated as u64);
+        r.put(7, opt_word(ep.send_queue.front().map(|i| i as u64)));
+        r
+    }
+
+    pub fn encode_endpoint_stats(ep_index: usize, s: &EndpointStats) -> WireRecord {
+        let mut r = WireRecord::new(KIND_ENDPOINT_STATS, ep_index as u16);
+        r.put(0, ep_index as u64);
+        r.put(1, s.delivered);
+        r.put(2, s.send_queue_max);
+        r.put(3, s.reply_queue_max);
+        r.put(4, s.rejected_closed);
+        r.put(5, s.rejected_kernel_task);
+        r.put(6, s.waits);
+        r.put(7, s.block_ticks);
+        r
+    }
+}
@@ -0,0 +1,209 @@
+// kernel/src/kernel/boot_params.rs
+//
+// 役割:
+// - kernel の command line（"key=value" を空白で区切った列）を BootParams に読み、build し直さずに変えたい値を実行時に選ぶ。
+//   * max_ticks: 通常起動 / scenario_suite の 1 シナリオで回す tick 数（既定 120。stress_ipc は STRESS_IPC_TICKS）
+//   * quantum:   scheduler の time slice（既定 DEFAULT_QUANTUM）
+//   * log:       global のログ閾値（trace / debug / info / warn / error / off。既定 trace）
+//   * scenario:  有効にする fault injection のシナリオ 1 つ（demo/scenario.rs の名前。baseline = 注入なし）
+//   * invariant: 検査 level（off / cheap / full / periodic / 数。word 表現は invariant.rs）
+//   * faults / fault_seed: fault injection の台本（demo/fault_inject.rs の書き方 / seed から作る。faults が優先）
+//   * frame_fail: frame 確保の失敗 mode（off / after:<n> / random:<seed>:<one_in>。bootstrap の後に入る）
+//   * sched_seed: sched_chaos の乱数の seed（既定 CHAOS_DEFAULT_SEED。sched_chaos でなければ使わない）
+//
+// command line の出どころ（後のものが同じ key を上書きする）:
+// - 埋め込み: build 時の環境変数 FORMAL_OS_CMDLINE（option_env!。無ければ空）
+// - initrd の "cmdline" という file（feature initrd。image を作り直すだけで変えられる）
+//
+// 方針:
+// - 指定が無い key は compile 時の既定（feature / const）のまま。BootParams の各 field は Option で「指定されたか」を持つ
+// - 読めない token（key が無い / 知らない key / 値が不正）はログに残して飛ばす（起動は止めない）
+// - init は kernel_main で 1 回（initrd::init の後、KernelState より前）。log は init の時点で効かせる
+// - quantum / invariant / sched_seed は KernelState を作るたびに apply_boot_params で入れる（scenario_suite の作り直しでも同じ値）
+//
+// やらないこと:
+// - 走行中の再読み込み（走行中の切り替えは debug_console の loglevel / set_invariant_level）
+// - 複数シナリオの同時指定（scenario は 1 つだけ。scenario_suite では各シナリオを suite が選ぶので無視する）
+// - scenario_suite での faults / fault_seed（シナリオごとの verdict に混ざるので無視する）
+// - bootloader からの command line（bootloader 0.9 の BootInfo には無い）
+
+use spin::Mutex;
+
+use super::demo::fault_inject::{self, FaultSchedule};
+use super::demo::scenario::{self, Scenario, SCENARIOS};
+use super::invariant::{InvariantLevel, INVARIANT_FULL_PERIOD};
+use super::sched_policy::{ChaosState, CHAOS_DEFAULT_SEED};
+use super::KernelState;
+use crate::logging::{self, Level};
+use crate::mm::FrameFailMode;
+
+/// build 時に埋め込む command line
+const EMBEDDED_CMDLINE: Option<&str> = option_env!("FORMAL_OS_CMDLINE");
+
+/// initrd で command line を入れる file の名前
+const INITRD_CMDLINE_FILE: &str = "cmdline";
+
+/// command line から読んだ値（None = 指定なし。compile 時の既定を使う）
+#[derive(Clone, Copy)]
+pub struct BootParams {
+    pub max_ticks: Option<u64>,
+    pub quantum: Option<u64>,
+    pub log_level: Option<Level>,
+    pub scenario: Option<Scenario>,
+    pub invariant_level: Option<InvariantLevel>,
+    pub faults: Option<FaultSchedule>,
+    pub fault_seed: Option<u64>,
+    pub frame_fail: Option<FrameFailMode>,
+    pub sched_seed: Option<u64>,
+}
+
+impl BootParams {
+    const fn new() -> Self {
+        BootParams {
+            max_ticks: None,
+            quantum: None,
+            log_level: None,
+            scenario: None,
+            invariant_level: None,
+            faults: None,
+            fault_seed: None,
+            frame_fail: None,
+            sched_seed: None,
+        }
+    }
+
+    /// token を 1 つずつ読んで上書きする
+    fn parse(&mut self, cmdline: &str) {
+        for token in cmdline.split_ascii_whitespace() {
+            let Some((key, value)) = token.split_once('=') else {
+                logging::error("boot_params: token without '='; ignored");
+                logging::info(token);
+                continue;
+            };
+            let ok = match key {
+                "max_ticks" => parse_u64(value).filter(|&n| n > 0).map(|n| self.max_ticks = Some(n)),
+                "quantum" => parse_u64(value).filter(|&n| n > 0).map(|n| self.quantum = Some(n)),
+                "log" => Level::from_name(value).map(|l| self.log_level = Some(l)),
+                "scenario" => scenario_from_name(value).map(|s| self.scenario = Some(s)),
+                "invariant" => invariant_from_name(value).map(|l| self.invariant_level = Some(l)),
+                "faults" => FaultSchedule::parse(value).map(|f| self.faults = Some(f)),
+                "fault_seed" => parse_u64(value).map(|n| self.fault_seed = Some(n)),
+                "frame_fail" => fault_inject::parse_frame_fail(value).map(|m| self.frame_fail = Some(m)),
+                "sched_seed" => parse_u64(value).map(|n| self.sched_seed = Some(n)),
+                _ => {
+                    logging::error("boot_params: unknown key; ignored");
+                    logging::info(token);
+                    continue;
+                }
+            };
+            if ok.is_none() {
+                logging::error("boot_params: bad value; ignored");
+                logging::info(token);
+            }
+        }
+    }
+}
+
+static PARAMS: Mutex<BootParams> = Mutex::new(BootParams::new());
+
+/// 10 進 or 0x 付き 16 進（demo/fault_inject.rs の faults= も使う）
+pub(super) fn parse_u64(s: &str) -> Option<u64> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+fn scenario_from_name(name: &str) -> Option<Scenario> {
+    SCENARIOS.iter().copied().find(|s| s.name() == name)
+}
+
+/// off / cheap / full / periodic（INVARIANT_FULL_PERIOD）/ word（3 以上は PeriodicFull の周期）
+fn invariant_from_name(name: &str) -> Option<InvariantLevel> {
+    match name {
+        "off" => Some(InvariantLevel::Off),
+        "cheap" => Some(InvariantLevel::Cheap),
+        "full" => Some(InvariantLevel::Full),
+        "periodic" => Some(InvariantLevel::PeriodicFull { every: INVARIANT_FULL_PERIOD }),
+        _ => parse_u64(name).map(InvariantLevel::from_word),
+    }
+}
+
+/// command line を読み、log と scenario をその場で効かせる（kernel_main から 1 回。initrd::init の後）
+pub fn init() {
+    let mut params = BootParams::new();
+
+    if let Some(cmdline) = EMBEDDED_CMDLINE.filter(|s| !s.trim().is_empty()) {
+        logging::info("boot_params: embedded cmdline");
+        logging::info(cmdline);
+        params.parse(cmdline);
+    }
+    if let Some(bytes) = super::initrd::find(INITRD_CMDLINE_FILE) {
+        match core::str::from_utf8(bytes) {
+            Ok(cmdline) => {
+                logging::info("boot_params: initrd cmdline");
+                logging::info(cmdline.trim_end());
+                params.parse(cmdline);
+            }
+            Err(_) => logging::error("boot_params: initrd cmdline is not UTF-8; ignored"),
+        }
+    }
+
+    if let Some(n) = params.max_ticks {
+        logging::info_u64("boot_param_max_ticks", n);
+    }
+    if let Some(n) = params.quantum {
+        logging::info_u64("boot_param_quantum", n);
+    }
+    if let Some(l) = params.invariant_level {
+        logging::info_u64("boot_param_invariant_level", l.word());
+    }
+    if let Some(s) = params.scenario {
+        if cfg!(feature = "scenario_suite") {
+            logging::info("boot_params: scenario is ignored under scenario_suite");
+        } else {
+            scenario::begin(s);
+        }
+    }
+    if params.faults.is_some() || params.fault_seed.is_some() {
+        if cfg!(feature = "scenario_suite") {
+            logging::info("boot_params: faults / fault_seed are ignored under scenario_suite");
+        } else if let Some(f) = params.faults {
+            fault_inject::begin(f, None);
+        } else if let Some(seed) = params.fault_seed {
+            fault_inject::begin(FaultSchedule::from_seed(seed), Some(seed));
+        }
+    }
+    // 閾値は最後に変える（ここまでのログは既定の閾値で出す）
+    if let Some(l) = params.log_level {
+        logging::info("boot_params: log level set");
+        logging::set_level(l);
+    }
+
+    *PARAMS.lock() = params;
+}
+
+/// init で読んだ値（init の前は全部 None）
+pub fn get() -> BootParams {
+    *PARAMS.lock()
+}
+
+impl KernelState {
+    /// command line の quantum / invariant / sched_seed を入れる（KernelState を作るたびに。指定が無ければ何もしない）
+    /// - sched_chaos は seed を毎回ログに出す（既定の seed でも。失敗した run をその seed で再現する）
+    pub(super) fn apply_boot_params(&mut self) {
+        let params = get();
+        if let Some(q) = params.quantum {
+            self.quantum = q;
+        }
+        if let Some(level) = params.invariant_level {
+            self.set_invariant_level(level);
+        }
+        if cfg!(feature = "sched_chaos") {
+            let seed = params.sched_seed.unwrap_or(CHAOS_DEFAULT_SEED);
+            self.chaos = ChaosState::new(seed);
+            logging::info("sched_chaos: seed");
+            logging::info_u64("sched_seed", seed);
+        }
+    }
+}
@@ -0,0 +1,277 @@
+// kernel/src/kernel/caps.rs
+//
+// 役割:
+// - 起動直後に「このビルドのカーネルが何を実装しているか」を機械可読な形で広告する
+//   （capability advertisement）。
+//
+// 設計方針:
+// - kernel_main の logging::init 直後に 1 回だけ出す（ログ先頭の構造化レコード）。
+// - 1 行 = 1 capability: "cap <kind>=<name>"。数値は info_u64("cap_<key>", v)。
+// - 列挙は静的テーブル（syscall 追加時はここも更新する）。
+// - feature は cfg!() で評価し、有効なものだけを出す。
+//
+// やらないこと:
+// - 実行時の状態（task 数や queue 長）を出す（それは dump_events の役割）
+
+use crate::logging;
+use super::sched_policy::{ActivePolicy, Chaos, FixedPriority, Mlfq, RoundRobin, SchedPolicy, MLFQ_LEVELS};
+
+/// 形式の版（key の追加は互換、意味の変更は版を上げる）
+const CAPS_VERSION: u64 = 1;
+
+/// Syscall enum（カーネル内部 syscall 境界）
+const SYSCALLS: &[&str] = &[
+    "ipc_recv",
+    "ipc_recv_any",
+    "ipc_send",
+    "ipc_reply",
+    "page_map",
+    "page_unmap",
+    "page_protect",
+    "task_create",
+    "task_exit",
+    "endpoint_create",
+    "endpoint_delete",
+    "endpoint_set_buffer",
+    "notify_signal",
+    "notify_wait",
+    "sleep",
+    "shm_create",
+    "shm_map",
+    "read_input",
+    "fs_open",
+    "fs_read",
+    "shutdown",
+    "fault_handler_set",
+    "fault_resolve",
+    "grant_window_set",
+    "revoke",
+    "task_suspend",
+    "task_resume",
+    "task_info",
+    "mem_info",
+];
+
+/// ring3 mailbox ABI（int 0x80）: (sysno, name)
+const MAILBOX_SYSNOS: &[(u64, &str)] = &[
+    (1, "add"),
+    (2, "tick_count"),
+    (10, "ipc_recv"),
+    (11, "ipc_send"),
+    (12, "ipc_reply"),
+    (30, "tick"),
+    (31, "take_last_reply"),
+];
+
+const ENDPOINT_KINDS: &[&str] = &["sync_rendezvous", "bounded_buffer"];
+
+/// cfg!() で評価する feature 一覧（kernel/Cargo.toml と揃える）
+const FEATURES: &[(&str, bool)] = &[
+    ("evil_double_map", cfg!(feature = "evil_double_map")),
+    ("evil_unmap_not_mapped", cfg!(feature = "evil_unmap_not_mapped")),
+    ("evil_ipc", cfg!(feature = "evil_ipc")),
+    ("pf_demo", cfg!(feature = "pf_demo")),
+    ("ipc_demo_single_slow", cfg!(feature = "ipc_demo_single_slow")),
+    ("ipc_trace_syscall", cfg!(feature = "ipc_trace_syscall")),
+    ("ipc_trace_paths", cfg!(feature = "ipc_trace_paths")),
+    ("trace_wire", cfg!(feature = "trace_wire")),
+    ("trace_records", cfg!(feature = "trace_records")),
+    ("log_seq", cfg!(feature = "log_seq")),
+    ("log_dedup", cfg!(feature = "log_dedup")),
+    ("event_log_retain_critical", cfg!(feature = "event_log_retain_critical")),
+    ("kill_cleanup_test", cfg!(feature = "kill_cleanup_test")),
+    ("dead_partner_test", cfg!(feature = "dead_partner_test")),
+    ("endpoint_close_test", cfg!(feature = "endpoint_close_test")),
+    ("stress_ipc", cfg!(feature = "stress_ipc")),
+    ("abi_selftest", cfg!(feature = "abi_selftest")),
+    ("timer_service", cfg!(feature = "timer_service")),
+    ("task_lifecycle_demo", cfg!(feature = "task_lifecycle_demo")),
+    ("ring3_demo", cfg!(feature = "ring3_demo")),
+    ("ring3_mailbox", cfg!(feature = "ring3_mailbox")),
+    ("ring3_mailbox_loop", cfg!(feature = "ring3_mailbox_loop")),
+    ("ring3_mailbox_loop_skip_rx", cfg!(feature = "ring3_mailbox_loop_skip_rx")),
+    ("alias_copycount_auto", cfg!(feature = "alias_copycount_auto")),
+    ("ignore_user_pf_demo", cfg!(feature = "ignore_user_pf_demo")),
+    ("ipc_reply_timeout_abort", cfg!(feature = "ipc_reply_timeout_abort")),
+    ("ipc_deadlock_break", cfg!(feature = "ipc_deadlock_break")),
+    ("invariant_fail_stop", cfg!(feature = "invariant_fail_stop")),
+    ("invariant_level_off", cfg!(feature = "invariant_level_off")),
+    ("invariant_level_cheap", cfg!(feature = "invariant_level_cheap")),
+    ("invariant_level_periodic", cfg!(feature = "invariant_level_periodic")),
+    ("synthetic_tick", cfg!(feature = "synthetic_tick")),
+    ("qemu_exit", cfg!(feature = "qemu_exit")),
+    ("scenario_suite", cfg!(feature = "scenario_suite")),
+    ("fault_inject", cfg!(feature = "fault_inject")),
+    ("frame_alloc_fail", cfg!(feature = "frame_alloc_fail")),
+    ("replay", cfg!(feature = "replay")),
+    ("replay_kill_server", cfg!(feature = "replay_kill_server")),
+    ("replay_ipc_timeout", cfg!(feature = "replay_ipc_timeout")),
+    ("kstack_switch", cfg!(feature = "kstack_switch")),
+    ("ring3_tasks", cfg!(feature = "ring3_tasks")),
+    ("swap_demo", cfg!(feature = "swap_demo")),
+    ("shm_demo", cfg!(feature = "shm_demo")),
+    ("sched_round_robin", cfg!(feature = "sched_round_robin")),
+    ("sched_mlfq", cfg!(feature = "sched_mlfq")),
+    ("sched_chaos", cfg!(feature = "sched_chaos")),
+    ("checkpoint_demo", cfg!(feature = "checkpoint_demo")),
+    ("refinement_trace", cfg!(feature = "refinement_trace")),
+    ("state_dump_verbose", cfg!(feature = "state_dump_verbose")),
+    ("ps2_keyboard", cfg!(feature = "ps2_keyboard")),
+    ("debug_console", cfg!(feature = "debug_console")),
+    ("smp", cfg!(feature = "smp")),
+    ("virtio_console", cfg!(feature = "virtio_console")),
+    ("virtio_net", cfg!(feature = "virtio_net")),
+    ("virtio_blk", cfg!(feature = "virtio_blk")),
+    ("fs_demo", cfg!(feature = "fs_demo")),
+    ("initrd", cfg!(feature = "initrd")),
+    ("shutdown_demo", cfg!(feature = "shutdown_demo")),
+    ("fault_handler_demo", cfg!(feature = "fault_handler_demo")),
+    ("grant_demo", cfg!(feature = "grant_demo")),
+    ("revoke_demo", cfg!(feature = "revoke_demo")),
+    ("suspend_demo", cfg!(feature = "suspend_demo")),
+    ("fb_console", cfg!(feature = "fb_console")),
+];
+
+/// trace（wire_hex / record）の出口。virtio console が見つからなければ COM1 のまま
+fn trace_export_name() -> &'static str {
+    #[cfg(feature = "virtio_console")]
+    if crate::arch::drivers::virtio_console::is_ready() {
+        return "virtio_console";
+    }
+    "com1"
+}
+
+fn cap_line(kind: &str, name: &str) {
+    // "cap <kind>=<name>"（format! なしで組み立てる）
+    let mut buf = [0u8; 96];
+    let mut n = 0;
+    for part in ["cap ", kind, "=", name] {
+        for &b in part.as_bytes() {
+            if n < buf.len() {
+                buf[n] = b;
+                n += 1;
+            }
+        }
+    }
+    let s = unsafe { core::str::from_utf8_unchecked(&buf[..n]) };
+    logging::info(s);
+}
+
+/// 起動時の capability advertisement を出す
+pub fn emit_capabilities() {
+    logging::info("=== Capabilities ===");
+    logging::info_u64("caps_version", CAPS_VERSION);
+    logging::info_u64("abi_wire_version", super::abi::WIRE_VERSION as u64);
+
+    for name in SYSCALLS {
+        cap_line("syscall", name);
+    }
+    for (sysno, name) in MAILBOX_SYSNOS {
+        cap_line("mailbox", name);
+        logging::info_u64("cap_mailbox_sysno", *sysno);
+    }
+    for name in ENDPOINT_KINDS {
+        cap_line("endpoint_kind", name);
+    }
+    cap_line("ipc_addressing", "cap_index");
+    cap_line("ipc_reply", "one_shot_reply_cap");
+    cap_line("ipc_queue_order", "fifo");
+    cap_line("handle_table", "typed_endpoint_notification_shm_task");
+    cap_line("ipc_send_timeout", "ticks");
+    cap_line("ipc_page_grant", "window_revoke_on_reply");
+    cap_line("revoke", "derivation_tree");
+    cap_line("task_suspend", "defer_recv");
+    cap_line("task_info", "state_priority_runtime_aspace");
+    logging::info_u64("cap_max_cap_slots", super::cspace::MAX_CAP_SLOTS as u64);
+    logging::info_u64("cap_cap_transfer_slots", super::cspace::CAP_TRANSFER_SLOTS as u64);
+    logging::info_u64("cap_ntfn_cap_base", super::cspace::NTFN_CAP_BASE as u64);
+    logging::info_u64("cap_task_cap_base", super::cspace::TASK_CAP_BASE as u64);
+    logging::info_u64("cap_max_derivations", super::derivation::MAX_DERIVATIONS as u64);
+
+    cap_line("sched_policy", <ActivePolicy as SchedPolicy>::NAME);
+    cap_line("sched_policy_available", FixedPriority::NAME);
+    cap_line("sched_policy_available", RoundRobin::NAME);
+    cap_line("sched_policy_available", Mlfq::NAME);
+    cap_line("sched_policy_available", Chaos::NAME);
+    cap_line("sched_priority", "ipc_inheritance");
+    cap_line("sched_same_priority", if cfg!(feature = "sched_chaos") { "uniform_random" } else { "fifo_round_robin" });
+    cap_line("ipc_handoff", if cfg!(feature = "ipc_direct_switch") { "direct_switch" } else { "scheduler" });
+    cap_line("tick_source", if cfg!(feature = "synthetic_tick") { "synthetic" } else { "pit_irq0" });
+    cap_line("kernel_stack", if cfg!(feature = "kstack_switch") { "per_task" } else { "shared_boot" });
+    cap_line("user_mode", if cfg!(feature = "ring3_tasks") { "ring3_tasks" } else { "simulated" });
+    cap_line("user_syscall_abi", if cfg!(feature = "ring3_tasks") { "int80_regs+syscall" } else { "int80_mailbox" });
+    cap_line("swap", if cfg!(feature = "swap_demo") { "simulated_store" } else { "none" });
+    cap_line("tlb_invalidation", "deferred_flush_on_switch");
+    cap_line("kernel_heap", "linked_list_first_fit");
+    cap_line("idle", "dedicated_task_hlt");
+    cap_line("input", if cfg!(feature = "ps2_keyboard") { "ps2_keyboard_irq1" } else { "none" });
+    cap_line("console", if cfg!(feature = "debug_console") { "com1_polled" } else { "none" });
+    cap_line("trace_export", trace_export_name());
+    cap_line("cpus", if cfg!(feature = "smp") { "bsp_tick_aps_run_tasks" } else { "single" });
+    logging::info_u64("cap_sched_quantum", super::DEFAULT_QUANTUM);
+    logging::info_u64("cap_mlfq_levels", MLFQ_LEVELS as u64);
+    logging::info_u64("cap_max_tasks", super::MAX_TASKS as u64);
+    logging::info_u64("cap_max_endpoints", super::MAX_ENDPOINTS as u64);
+    logging::info_u64("cap_dynamic_endpoint_slots", super::DYNAMIC_ENDPOINT_SLOTS as u64);
+    logging::info_u64("cap_endpoint_buffer_slots", super::ipc_buffer::ENDPOINT_BUFFER_SLOTS as u64);
+    logging::info_u64("cap_max_notifications", super::MAX_NOTIFICATIONS as u64);
+    logging::info_u64("cap_max_shm_segments", super::shm::MAX_SHM_SEGMENTS as u64);
+    logging::info_u64("cap_max_shm_pages", super::shm::MAX_SHM_PAGES as u64);
+    logging::info_u64("cap_kernel_heap_bytes", crate::arch::virt_layout::KERNEL_HEAP_SIZE);
+    logging::info_u64("cap_user_frame_quota", crate::mem::address_space::DEFAULT_USER_FRAME_QUOTA as u64);
+    logging::info_u64("cap_aspace_max_regions", crate::mem::address_space::MAX_REGIONS as u64);
+    logging::info_u64("cap_ipc_msg_regs", super::abi::IPC_MSG_REGS as u64);
+    logging::info_u64("cap_ipc_event_sample_every", super::IPC_EVENT_SAMPLE_EVERY);
+    logging::info_u64("cap_event_log_cap", super::EVENT_LOG_CAP as u64);
+    logging::info_u64("cap_retained_critical_events", super::event_log::CRITICAL_EVENT_CAP as u64);
+    logging::info_u64("cap_invariant_level", super::invariant::DEFAULT_INVARIANT_LEVEL.word());
+    logging::info_u64("cap_invariant_full_period", super::invariant::INVARIANT_FULL_PERIOD);
+    logging::info_u64("cap_input_queue_cap", super::input::INPUT_QUEUE_CAP as u64);
+    cap_line("fs", if cfg!(feature = "virtio_blk") { "ustar_ro_virtio_blk" } else { "none" });
+    logging::info_u64("cap_fs_max_files", super::fs::MAX_FS_FILES as u64);
+    logging::info_u64("cap_fs_name_max", super::fs::FS_NAME_MAX as u64);
+    logging::info_u64("cap_fs_max_fds", super::fs::MAX_FDS as u64);
+    cap_line("initrd", if cfg!(feature = "initrd") { "ustar_bootinfo_package" } else { "none" });
+    cap_line("boot_params", "max_ticks,quantum,log,scenario,invariant,faults,fault_seed,frame_fail,sched_seed");
+    cap_line("fault_inject", "kill,close,oom,delay");
+    cap_line("frame_fail", "after,random");
+    cap_line("checkpoint", "abstract_state_single_slot");
+    cap_line("refinement_trace", if cfg!(feature = "refinement_trace") { "delta_v2" } else { "none" });
+    cap_line("page_protect", "update_flags_invlpg");
+    cap_line("phys_reserve", "mmio_uncached_window");
+    cap_line("mem_info", "frames_resident_swapped");
+    logging::info_u64("cap_fault_schedule_max", super::demo::fault_inject::FAULT_SCHEDULE_MAX as u64);
+    cap_line("poweroff", "isa_debug_exit,acpi_s5");
+    logging::info_u64("cap_ipc_reply_obligation_ticks", super::IPC_REPLY_OBLIGATION_TICKS);
+    logging::info_u64("cap_pf_storm_window_ticks", super::PF_STORM_WINDOW_TICKS);
+    logging::info_u64("cap_pf_storm_threshold", super::PF_STORM_THRESHOLD);
+    logging::info_u64("cap_watchdog_no_progress_ticks", super::watchdog::WATCHDOG_NO_PROGRESS_TICKS);
+
+    #[cfg(feature = "smp")]
+    {
+        cap_line("kernel_lock", "bkl");
+        logging::info_u64("cap_max_cpus", crate::arch::smp::MAX_CPUS as u64);
+    }
+
+    #[cfg(feature = "timer_service")]
+    {
+        cap_line("service", "timer");
+        logging::info_u64("cap_timer_service_ep", super::timer_service::TIMER_SERVICE_EP.0 as u64);
+        logging::info_u64("cap_timer_wheel_slots", super::timer_service::TIMER_WHEEL_SLOTS as u64);
+    }
+
+    #[cfg(feature = "virtio_net")]
+    {
+        cap_line("service", "net");
+        logging::info_u64("cap_net_service_ep", super::net::NET_SERVICE_EP.0 as u64);
+        logging::info_u64("cap_net_max_frame_len", crate::arch::drivers::virtio_net::MAX_FRAME_LEN as u64);
+    }
+
+    for (name, enabled) in FEATURES {
+        if *enabled {
+            cap_line("feature", name);
+        }
+    }
+
+    logging::info("=== End of Capabilities ===");
+}
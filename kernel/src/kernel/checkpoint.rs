@@ -0,0 +1,313 @@
+// kernel/src/kernel/checkpoint.rs
+//
+// 役割:
+// - 抽象状態の checkpoint / rollback: ある tick の KernelState の抽象部分を予約した slot に写し取り、
+//   後でそこへ巻き戻す。1 boot の中で、同じ状態から別の event の順序（branch）を何度も試す（demo/checkpoint_explore.rs）。
+//
+// 写し取るもの（Checkpoint）:
+// - 時刻: tick_count / time_ticks / clock_now（kernel clock の今）/ activity（期限と一緒に戻す）
+//   * cpu_time も戻す（user + kernel + idle = tick_count、INV-SCHED-005）
+// - task: tasks / num_tasks / current_task / next_task_id
+// - queue: ready_queue / wait_queue / rq_same_prio_passes
+// - IPC: endpoints（send_queue / reply_queue / buffer ごと）/ notifications / reply object / reply 待ちと timeout の期限
+//   / ipc_handoff_since
+// - capability: cspaces / next_cap_grant_seq / derivations / grants / fault handler
+// - logical な AddressSpace（region / guard page / swap out 中の記録 / frame quota）と mem_demo の進行
+// - MLFQ の level（Chaos の乱数は戻さない。branch ごとに違う順で dispatch される）
+// - user_program の進行（demo_msgs_delivered など）
+//
+// 写し取らないもの:
+// - 物理の側: page table / PMM（フレームの確保・参照カウント）/ TLB / swap の中身 / shm segment
+// - 観測: event_log / counters / watchdog / deadlock / invariant の latch（branch の分も積み上がる）
+// - 外から来るもの: keyboard の入力 / fs / timer_service / net
+//
+// rollback:
+// - 物理の側は戻せないので、logical な mapping が checkpoint から変わっていたら拒否する（MemoryDiverged、何も戻さない）
+//   * 指紋（mem_fingerprint）: AddressSpace ごとの root フレームと、mapping / guard page / swap out 中の page、
+//     shm segment（確保した segment とそのフレーム）
+//   * tick の AllocateFrame はどこにも map されないフレームなので見ない（巻き戻した後も確保済みのまま）
+// - 戻したら state_hash を checkpoint のときの値と比べる（HashMismatch は写し漏れ。戻した状態のまま error にする）
+// - current_task の AddressSpace へ CR3 / VGA を切り替え直す（page table は同じなので root を選び直すだけ）
+// - checkpoint は何度でも rollback できる（rollback しても slot は残る。discard で捨てる）
+//
+// やらないこと:
+// - ring3 で走る task（user のレジスタは arch の側）/ kstack_switch（task ごとの kernel stack）/ smp との併用
+// - 複数の checkpoint（slot は 1 つ。take し直すと上書き）
+// - branch の間に増えた event / counters の取り消し
+
+use spin::Mutex;
+
+use super::ipc::Endpoint;
+use super::notification::Notification;
+use super::state_hash::Fnv64;
+use super::time::Instant;
+use super::{
+    cspace::CapTable, derivation, fault_handler, grant, reply_cap, sched_policy, CpuTime, KernelActivity, KernelState, Task,
+    MAX_ENDPOINTS, MAX_NOTIFICATIONS, MAX_TASKS, KERNEL_ASID_INDEX,
+};
+use crate::arch::ops::{Arch, ArchOps};
+use crate::logging;
+use crate::mem::addr::PhysFrame;
+use crate::mem::address_space::{AddressSpace, AddressSpaceKind};
+
+/// KernelState の抽象部分の写し（フィールドの意味は KernelState と同じ）
+struct Checkpoint {
+    tick_count: u64,
+    time_ticks: u64,
+    clock_now: Instant,
+    activity: KernelActivity,
+    cpu_time: CpuTime,
+    syscall_since_account: bool,
+
+    tasks: [Task; MAX_TASKS],
+    num_tasks: usize,
+    current_task: usize,
+    next_task_id: u64,
+
+    ready_queue: [usize; MAX_TASKS],
+    rq_len: usize,
+    rq_same_prio_passes: [u64; MAX_TASKS],
+    wait_queue: [usize; MAX_TASKS],
+    wq_len: usize,
+
+    endpoints: [Endpoint; MAX_ENDPOINTS],
+    notifications: [Notification; MAX_NOTIFICATIONS],
+    reply_wait_since: [Option<u64>; MAX_TASKS],
+    reply_slow_reported: [bool; MAX_TASKS],
+    reply_objects: [Option<reply_cap::ReplyObject>; MAX_TASKS],
+    next_reply_seq: u64,
+    ipc_deadline: [Option<Instant>; MAX_TASKS],
+    ipc_handoff_since: [Option<u64>; MAX_TASKS],
+
+    cspaces: [CapTable; MAX_TASKS],
+    next_cap_grant_seq: u64,
+    derivations: derivation::Derivations,
+    grants: grant::Grants,
+    faults: fault_handler::FaultHandlers,
+
+    address_spaces: [AddressSpace; MAX_TASKS],
+    mem_demo_mapped: [bool; MAX_TASKS],
+    mem_demo_stage: [u8; MAX_TASKS],
+    mem_demo_frame: [Option<PhysFrame>; MAX_TASKS],
+
+    mlfq: sched_policy::MlfqState,
+
+    demo_msgs_delivered: u8,
+    demo_replies_sent: u8,
+    demo_sent_by_task2: bool,
+    demo_sent_by_task1: bool,
+    demo_early_sent_by_task0: bool,
+
+    /// take したときの state_hash / mem_fingerprint
+    state_hash: u64,
+    mem_fingerprint: u64,
+}
+
+/// 予約した slot（KernelState の外。stack を倍に食わないように static に置く）
+static SLOT: Mutex<Option<Checkpoint>> = Mutex::new(None);
+
+#[derive(Clone, Copy)]
+pub enum RollbackError {
+    /// take していない（discard 済み）
+    NoCheckpoint,
+    /// logical な mapping が checkpoint から変わった（戻すと page table と食い違う）
+    MemoryDiverged { expected: u64, actual: u64 },
+    /// 戻した後の state_hash が take したときと違う（写し漏れ）
+    HashMismatch { expected: u64, actual: u64 },
+}
+
+impl RollbackError {
+    pub fn name(self) -> &'static str {
+        match self {
+            RollbackError::NoCheckpoint => "no_checkpoint",
+            RollbackError::MemoryDiverged { .. } => "memory_diverged",
+            RollbackError::HashMismatch { .. } => "hash_mismatch",
+        }
+    }
+}
+
+impl KernelState {
+    /// logical な mapping の指紋（root フレーム、mapping、guard page、swap out 中の page、shm segment のフレーム）
+    fn mem_fingerprint(&self) -> u64 {
+        let mut h = Fnv64::new();
+        for (as_idx, aspace) in self.address_spaces.iter().enumerate() {
+            h.write_u64(as_idx as u64);
+            h.write_u64(aspace.root_page_frame.map_or(u64::MAX, |f| f.number));
+            aspace.for_each_mapping(|m| {
+                h.write_u64(m.page.number);
+                h.write_u64(m.frame.number);
+                h.write_u64(m.flags.bits());
+            });
+            aspace.for_each_guard_page(|g| h.write_u64(g.number));
+            aspace.for_each_swapped_page(|s| {
+                h.write_u64(s.page.number);
+                h.write_u64(s.slot as u64);
+            });
+        }
+        for seg in self.shm.iter().filter(|s| s.allocated) {
+            for f in seg.frames.iter().take(seg.pages) {
+                h.write_u64(f.map_or(u64::MAX, |f| f.number));
+            }
+        }
+        h.finish()
+    }
+
+    /// 今の抽象状態を slot に写す（前の checkpoint は上書き）。take したときの state_hash を返す
+    pub(super) fn checkpoint_take(&mut self) -> u64 {
+        let state_hash = self.state_hash();
+        let mem_fingerprint = self.mem_fingerprint();
+
+        *SLOT.lock() = Some(Checkpoint {
+            tick_count: self.tick_count,
+            time_ticks: self.time_ticks,
+            clock_now: self.clock_now,
+            activity: self.activity,
+            cpu_time: self.cpu_time,
+            syscall_since_account: self.syscall_since_account,
+            tasks: self.tasks,
+            num_tasks: self.num_tasks,
+            current_task: self.current_task,
+            next_task_id: self.next_task_id,
+            ready_queue: self.ready_queue,
+            rq_len: self.rq_len,
+            rq_same_prio_passes: self.rq_same_prio_passes,
+            wait_queue: self.wait_queue,
+            wq_len: self.wq_len,
+            endpoints: self.endpoints,
+            notifications: self.notifications,
+            reply_wait_since: self.reply_wait_since,
+            reply_slow_reported: self.reply_slow_reported,
+            reply_objects: self.reply_objects,
+            next_reply_seq: self.next_reply_seq,
+            ipc_deadline: self.ipc_deadline,
+            ipc_handoff_since: self.ipc_handoff_since,
+            cspaces: self.cspaces,
+            next_cap_grant_seq: self.next_cap_grant_seq,
+            derivations: self.derivations,
+            grants: self.grants,
+            faults: self.faults,
+            address_spaces: self.address_spaces,
+            mem_demo_mapped: self.mem_demo_mapped,
+            mem_demo_stage: self.mem_demo_stage,
+            mem_demo_frame: self.mem_demo_frame,
+            mlfq: self.mlfq,
+            demo_msgs_delivered: self.demo_msgs_delivered,
+            demo_replies_sent: self.demo_replies_sent,
+            demo_sent_by_task2: self.demo_sent_by_task2,
+            demo_sent_by_task1: self.demo_sent_by_task1,
+            demo_early_sent_by_task0: self.demo_early_sent_by_task0,
+            state_hash,
+            mem_fingerprint,
+        });
+
+        logging::info("checkpoint: taken");
+        logging::info_u64("tick", self.tick_count);
+        logging::info_u64("state_hash", state_hash);
+        logging::info_u64("mem_fingerprint", mem_fingerprint);
+        state_hash
+    }
+
+    /// slot の checkpoint へ巻き戻す（slot は残す）
+    pub(super) fn checkpoint_rollback(&mut self) -> Result<(), RollbackError> {
+        let slot = SLOT.lock();
+        let Some(cp) = slot.as_ref() else {
+            logging::error("checkpoint: rollback without checkpoint");
+            return Err(RollbackError::NoCheckpoint);
+        };
+
+        let actual = self.mem_fingerprint();
+        if actual != cp.mem_fingerprint {
+            logging::error("checkpoint: rollback refused; logical mappings diverged");
+            logging::info_u64("tick", self.tick_count);
+            logging::info_u64("expected_fingerprint", cp.mem_fingerprint);
+            logging::info_u64("actual_fingerprint", actual);
+            return Err(RollbackError::MemoryDiverged { expected: cp.mem_fingerprint, actual });
+        }
+
+        let from_tick = self.tick_count;
+        self.tick_count = cp.tick_count;
+        self.time_ticks = cp.time_ticks;
+        self.clock_now = cp.clock_now;
+        self.activity = cp.activity;
+        self.cpu_time = cp.cpu_time;
+        self.syscall_since_account = cp.syscall_since_account;
+        self.tasks = cp.tasks;
+        self.num_tasks = cp.num_tasks;
+        self.current_task = cp.current_task;
+        self.next_task_id = cp.next_task_id;
+        self.ready_queue = cp.ready_queue;
+        self.rq_len = cp.rq_len;
+        self.rq_same_prio_passes = cp.rq_same_prio_passes;
+        self.wait_queue = cp.wait_queue;
+        self.wq_len = cp.wq_len;
+        self.endpoints = cp.endpoints;
+        self.notifications = cp.notifications;
+        self.reply_wait_since = cp.reply_wait_since;
+        self.reply_slow_reported = cp.reply_slow_reported;
+        self.reply_objects = cp.reply_objects;
+        self.next_reply_seq = cp.next_reply_seq;
+        self.ipc_deadline = cp.ipc_deadline;
+        self.ipc_handoff_since = cp.ipc_handoff_since;
+        self.cspaces = cp.cspaces;
+        self.next_cap_grant_seq = cp.next_cap_grant_seq;
+        self.derivations = cp.derivations;
+        self.grants = cp.grants;
+        self.faults = cp.faults;
+        self.address_spaces = cp.address_spaces;
+        self.mem_demo_mapped = cp.mem_demo_mapped;
+        self.mem_demo_stage = cp.mem_demo_stage;
+        self.mem_demo_frame = cp.mem_demo_frame;
+        self.mlfq = cp.mlfq;
+        self.demo_msgs_delivered = cp.demo_msgs_delivered;
+        self.demo_replies_sent = cp.demo_replies_sent;
+        self.demo_sent_by_task2 = cp.demo_sent_by_task2;
+        self.demo_sent_by_task1 = cp.demo_sent_by_task1;
+        self.demo_early_sent_by_task0 = cp.demo_early_sent_by_task0;
+        let expected = cp.state_hash;
+        drop(slot);
+
+        self.checkpoint_switch_to_current();
+        // 巻き戻しも 1 つの遷移として区切る（refinement.rs）
+        self.refinement_boundary("rollback", None, true);
+
+        logging::info("checkpoint: rolled back");
+        logging::info_u64("from_tick", from_tick);
+        logging::info_u64("tick", self.tick_count);
+
+        let actual = self.state_hash();
+        if actual != expected {
+            logging::error("checkpoint: state_hash differs after rollback");
+            logging::info_u64("expected_state_hash", expected);
+            logging::info_u64("actual_state_hash", actual);
+            return Err(RollbackError::HashMismatch { expected, actual });
+        }
+        logging::info_u64("state_hash", actual);
+        Ok(())
+    }
+
+    /// slot を空にする
+    pub(super) fn checkpoint_discard(&mut self) {
+        if SLOT.lock().take().is_some() {
+            logging::info("checkpoint: discarded");
+        }
+    }
+
+    /// 戻した current_task の AddressSpace へ CR3 / VGA を合わせる（dispatch_task の切替部分と同じ）
+    fn checkpoint_switch_to_current(&mut self) {
+        let as_idx = self.tasks[self.current_task].address_space_id.0;
+        match self.address_spaces[as_idx].kind {
+            AddressSpaceKind::User => {
+                logging::set_vga_enabled(false);
+                Arch::switch_address_space(self.address_spaces[as_idx].root_page_frame);
+                self.flush_deferred_tlb(as_idx);
+            }
+            AddressSpaceKind::Kernel => {
+                let kernel_root = self.address_spaces[KERNEL_ASID_INDEX]
+                    .root_page_frame
+                    .expect("kernel root_page_frame must exist");
+                Arch::switch_address_space_quiet(kernel_root);
+                logging::set_vga_enabled(true);
+            }
+        }
+    }
+}
@@ -0,0 +1,183 @@
+// kernel/src/kernel/console.rs
+//
+// 役割:
+// - COM1 の受信を polling で読み、1 行ずつコマンドとして実行する最小の debug console（feature debug_console）。
+//
+// 流れ:
+// - tick() の先頭で console_poll: 受信済みの byte を最大 CONSOLE_POLL_BUDGET 個読んで行バッファに溜める
+//   * CR / LF で 1 行確定 → "console> <line>" をログに出してから実行する
+//   * 行が CONSOLE_LINE_CAP を超えたら、その行は改行まで捨てて error を出す
+// - run が終わった後（dump_events の後）は console_loop が halt の代わりに polling を続ける
+//
+// コマンド（既存の KernelState の API を呼ぶだけ）:
+// - help            : コマンド一覧
+// - dump            : dump_events（event log のダンプ）
+// - tasks           : Task Dump
+// - counters        : Counters Dump
+// - kill <task_id>  : task を kill する（TaskId は 10 進 or 0x 16 進）
+//   * reason は DemoInjected { code: CONSOLE_KILL_CODE }（replay の kill と同じ経路）
+//   * task0 / idle / Dead / 存在しない id は拒否する
+// - loglevel <level>               : log の global 閾値（trace / debug / info / warn / error / off）
+// - loglevel <subsystem> <level>   : subsystem（sched / ipc / mem / arch）の閾値。inherit で global に戻す
+// - loglevel force on|off          : 閾値に関係なく Error を出すか
+//
+// 方針:
+// - IRQ4 は使わない（polling だけ。PIC の mask もいじらない）
+// - 入力は外から来る非決定的なものなので、state hash の正準状態には含めない
+//
+// やらないこと:
+// - 行編集（backspace / 履歴）とエコーバック
+// - 任意の状態書き換え（kill 以外の変更系コマンド）
+
+use super::{KernelState, TaskId, TaskKillReason, TaskState, IDLE_TASK_INDEX, TASK0_INDEX};
+use crate::logging;
+
+/// 1 行の最大長（byte）
+pub(super) const CONSOLE_LINE_CAP: usize = 64;
+
+/// 1 回の console_poll で読む byte 数の上限（tick を長く止めない）
+const CONSOLE_POLL_BUDGET: usize = 32;
+
+/// 実行する行をログに出すときの前置き
+const CONSOLE_PROMPT: &str = "console> ";
+
+/// console からの kill に付ける DemoInjected の code（"CONS"）
+pub(super) const CONSOLE_KILL_CODE: u64 = 0x434F_4E53;
+
+/// 組み立て中の 1 行
+pub(super) struct ConsoleState {
+    buf: [u8; CONSOLE_LINE_CAP],
+    len: usize,
+    // 今の行が CONSOLE_LINE_CAP を超えた（改行まで捨てる）
+    overflow: bool,
+}
+
+impl ConsoleState {
+    pub(super) const fn new() -> Self {
+        Self { buf: [0; CONSOLE_LINE_CAP], len: 0, overflow: false }
+    }
+}
+
+impl KernelState {
+    /// 受信済みの byte を読んで、行が揃ったら実行する
+    pub(super) fn console_poll(&mut self) {
+        for _ in 0..CONSOLE_POLL_BUDGET {
+            let Some(b) = logging::serial_read_byte() else {
+                return;
+            };
+            self.console_feed(b);
+        }
+    }
+
+    /// run の後: halt の代わりに console を回し続ける
+    pub fn console_loop(&mut self) -> ! {
+        logging::info("console: ready (help for commands)");
+        loop {
+            self.console_poll();
+            core::hint::spin_loop();
+        }
+    }
+
+    fn console_feed(&mut self, b: u8) {
+        if b == b'\r' || b == b'\n' {
+            let len = self.console.len;
+            let overflow = self.console.overflow;
+            self.console.len = 0;
+            self.console.overflow = false;
+
+            if overflow {
+                logging::error("console: line too long; dropped");
+                return;
+            }
+            if len == 0 {
+                return;
+            }
+            let buf = self.console.buf;
+            self.console_execute(&buf[..len]);
+            return;
+        }
+
+        if self.console.len == CONSOLE_LINE_CAP {
+            self.console.overflow = true;
+            return;
+        }
+        self.console.buf[self.console.len] = b;
+        self.console.len += 1;
+    }
+
+    fn console_execute(&mut self, line: &[u8]) {
+        let Ok(line) = core::str::from_utf8(line) else {
+            logging::error("console: non-utf8 input");
+            return;
+        };
+        let mut echo = [0u8; CONSOLE_PROMPT.len() + CONSOLE_LINE_CAP];
+        echo[..CONSOLE_PROMPT.len()].copy_from_slice(CONSOLE_PROMPT.as_bytes());
+        echo[CONSOLE_PROMPT.len()..CONSOLE_PROMPT.len() + line.len()].copy_from_slice(line.as_bytes());
+        if let Ok(echo) = core::str::from_utf8(&echo[..CONSOLE_PROMPT.len() + line.len()]) {
+            logging::info(echo);
+        }
+
+        let mut words = line.split_ascii_whitespace();
+        let Some(cmd) = words.next() else {
+            return;
+        };
+        match (cmd, words.next(), words.next(), words.next()) {
+            ("help", None, _, _) => {
+                logging::info("console: commands = help | dump | tasks | counters | kill <task_id> | loglevel [<subsystem>] <level> | loglevel force on|off");
+            }
+            ("dump", None, _, _) => self.dump_events(),
+            ("tasks", None, _, _) => self.dump_task_table(),
+            ("counters", None, _, _) => self.dump_counters(),
+            ("kill", Some(arg), None, _) => match parse_u64(arg) {
+                Some(id) => self.console_kill(TaskId(id)),
+                None => logging::error("console: kill: bad task id"),
+            },
+            ("loglevel", Some("force"), Some(arg), None) => match arg {
+                "on" => logging::set_force_errors(true),
+                "off" => logging::set_force_errors(false),
+                _ => logging::error("console: loglevel: force takes on|off"),
+            },
+            ("loglevel", Some(arg), None, _) => match logging::Level::from_name(arg) {
+                Some(level) => logging::set_level(level),
+                None => logging::error("console: loglevel: bad level"),
+            },
+            ("loglevel", Some(sub), Some(arg), None) => {
+                let Some(sub) = logging::Subsystem::from_name(sub) else {
+                    logging::error("console: loglevel: bad subsystem");
+                    return;
+                };
+                match (arg, logging::Level::from_name(arg)) {
+                    ("inherit", _) => logging::set_subsystem_level(sub, None),
+                    (_, Some(level)) => logging::set_subsystem_level(sub, Some(level)),
+                    (_, None) => logging::error("console: loglevel: bad level"),
+                }
+            }
+            _ => logging::error("console: unknown command (try help)"),
+        }
+    }
+
+    fn console_kill(&mut self, id: TaskId) {
+        let Some(idx) = self.task_index_of(id) else {
+            logging::error("console: kill: no such task");
+            logging::info_u64("task_id", id.0);
+            return;
+        };
+        if idx == TASK0_INDEX || idx == IDLE_TASK_INDEX || self.tasks[idx].state == TaskState::Dead {
+            logging::error("console: kill: refused (task0 / idle / dead)");
+            logging::info_u64("task_id", id.0);
+            return;
+        }
+        logging::error("console: kill task (DemoInjected)");
+        logging::info_u64("killed_task_id", id.0);
+        logging::info_u64("demo_code", CONSOLE_KILL_CODE);
+        self.kill_task(idx, TaskKillReason::DemoInjected { code: CONSOLE_KILL_CODE });
+    }
+}
+
+/// 10 進 or 0x 付き 16 進
+fn parse_u64(s: &str) -> Option<u64> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
@@ -0,0 +1,596 @@
+// kernel/src/kernel/cspace.rs
+//
+// 役割:
+// - task ごとの handle table（CapTable）: kernel object を指す syscall は、生の id ではなく小さな handle（cap index）で
+//   object を指す。slot は型付きの参照（KernelObject）と rights を持つ。
+//   * Endpoint（IPC / EndpointDelete）、Notification（NotifySignal / NotifyWait）、Shm（ShmMap）、
+//     Task（TaskSuspend / TaskResume / TaskInfo / FaultResolve）
+// - 引くときは型と rights を syscall 境界で検査し、違反はログ + CapDenied event で残す。
+//   * 型違い（endpoint の handle で NotifyWait 等）は WrongType（last_syscall_ret = SYSCALL_ERR_WRONG_TYPE）
+//
+// 初期配置（default_for_task）:
+// - slot i → EndpointId(i)（起動時から在る endpoint 分。demo は従来の endpoint 番号をそのまま cap index に使える）
+// - NTFN_CAP_BASE + n → NotificationId(n)（Signal | Wait）
+// - 末尾の MAX_TASKS 個（TASK_CAP_BASE + t）→ task index t の task（KernelState::install_task_handle）
+//   * kernel AS の task と t の親（TaskCreate の呼び出し元）は Control | Info、それ以外は Info。idle は持たず、指されない
+//   * TaskCreate で t を使ったら全 task の table に入れ直し、t の kill / TaskExit で全 table から消す
+// - Task0（kernel）: notification の Signal だけ（IPC も wait もしない）。idle task: 空
+// - Task1（client）: endpoint は Send | Recv
+// - それ以外（server）: endpoint は Recv | Reply
+// - TaskCreate で slot を再利用したときも index ごとの既定を入れ直し、kill / TaskExit で空にする
+// - 間の slot は空き: EndpointCreate / ShmCreate の handle と cap transfer で受け取った handle が入る
+//   （insert は TASK_CAP_BASE より前だけを探す。task の slot は kernel だけが書く）
+//
+// fault handler（fault_handler.rs）:
+// - fault を渡した handler の TASK_CAP_BASE + faulter に Resolve を足し、msg の cap_transfer でその slot を知らせる
+// - 解決 / ep の close で Resolve を外す（Resolve は kernel が渡す以外に増えない）
+//
+// cap transfer（ipc.rs の deliver から呼ぶ）:
+// - IpcMessage.cap_transfer の cap を、送信側と同じ object / rights で受信側の空き slot に複製する（型は問わない）
+// - 複製した slot には CapGrantTag { seq, to } を付ける（seq は転送ごとに一意）
+//   → invariant: 同じ seq の slot は全 task の table で高々 1 つ、しかも to の task の table にだけある
+// - 送信側の cap は syscall 入口で検査する（空 slot を添付した send / reply は CapDenied で無視）
+// - 受信側に空き slot が無い等は cap だけ落として msg は届ける（cap_transfer_failed）
+// - 複製は derivation tree（derivation.rs）に送信側の slot → 受信側の slot の辺として記録する
+//   → Revoke で、その cap から推移的に複製された cap を全 task から消せる（表が満杯なら cap を落とす）
+//
+// object が消えるとき（EndpointDelete / 動的 endpoint の owner の死 / shm の owner の死 / task の kill・TaskExit）:
+// - 全 task の table からその object の handle を消す（revoke_object。slot の再利用で古い handle が生き返らない）
+//
+// 違反時:
+// - IPC syscall は入口で無視する（kernel task の IPC 禁止と同じ扱い。last_reply は入れない）
+// - それ以外は last_syscall_ret にエラー（CapError::syscall_code。EndpointDelete は従来どおり SYSCALL_ERR_BAD_ENDPOINT）
+// - "syscall: capability denied" + task_id / cap_index / rights_need / rights_held（WrongType は kind_need / kind_held も）
+//
+// やらないこと:
+// - mint（rights を絞った複製）/ badge
+// - task の handle を TaskCreate 以外で作る（親子関係の外の task を操りたければ cap transfer で受け取る）
+// - page 操作の capability 化（page は AddressSpace が持ち主）
+
+use super::abi::{
+    OBJECT_KIND_ENDPOINT, OBJECT_KIND_NOTIFICATION, OBJECT_KIND_SHM, OBJECT_KIND_TASK, SYSCALL_ERR_BAD_CAP,
+    SYSCALL_ERR_BAD_TASK, SYSCALL_ERR_FORBIDDEN, SYSCALL_ERR_WRONG_TYPE,
+};
+use super::{
+    EndpointId, InvariantId, IpcMessage, KernelState, LogEvent, NotificationId, ShmId, TaskId, TaskState,
+    IDLE_TASK_INDEX, MAX_ENDPOINTS, MAX_NOTIFICATIONS, MAX_TASKS, STATIC_ENDPOINTS, TASK0_INDEX, TASK1_INDEX,
+};
+use super::derivation::DerivObject;
+use super::shm::MAX_SHM_SEGMENTS;
+use spec_macros::spec;
+
+bitflags::bitflags! {
+    /// handle の権限（意味を持つのは object の種類ごとに一部だけ）
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct CapRights: u16 {
+        // Endpoint
+        const SEND   = 1 << 0;
+        const RECV   = 1 << 1;
+        const REPLY  = 1 << 2;
+        // Notification
+        const SIGNAL = 1 << 3;
+        const WAIT   = 1 << 4;
+        // Shm
+        const MAP    = 1 << 5;
+        // Task（Control = TaskSuspend / TaskResume、Info = TaskInfo、Resolve = FaultResolve）
+        const CONTROL = 1 << 6;
+        const INFO    = 1 << 7;
+        const RESOLVE = 1 << 8;
+    }
+}
+
+/// handle が指す kernel object（型付きの参照）
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum KernelObject {
+    Endpoint(EndpointId),
+    Notification(NotificationId),
+    Shm(ShmId),
+    Task(TaskId),
+}
+
+/// KernelObject の種類（型の検査とログ用）
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ObjectKind {
+    Endpoint,
+    Notification,
+    Shm,
+    Task,
+}
+
+impl ObjectKind {
+    /// この種類の handle が持てる rights
+    pub fn rights(self) -> CapRights {
+        match self {
+            ObjectKind::Endpoint => CapRights::SEND | CapRights::RECV | CapRights::REPLY,
+            ObjectKind::Notification => CapRights::SIGNAL | CapRights::WAIT,
+            ObjectKind::Shm => CapRights::MAP,
+            ObjectKind::Task => CapRights::CONTROL | CapRights::INFO | CapRights::RESOLVE,
+        }
+    }
+
+    /// wire / ログの種類番号（abi.rs の OBJECT_KIND_*）
+    pub fn code(self) -> u64 {
+        match self {
+            ObjectKind::Endpoint => OBJECT_KIND_ENDPOINT,
+            ObjectKind::Notification => OBJECT_KIND_NOTIFICATION,
+            ObjectKind::Shm => OBJECT_KIND_SHM,
+            ObjectKind::Task => OBJECT_KIND_TASK,
+        }
+    }
+}
+
+impl KernelObject {
+    pub fn kind(self) -> ObjectKind {
+        match self {
+            KernelObject::Endpoint(_) => ObjectKind::Endpoint,
+            KernelObject::Notification(_) => ObjectKind::Notification,
+            KernelObject::Shm(_) => ObjectKind::Shm,
+            KernelObject::Task(_) => ObjectKind::Task,
+        }
+    }
+
+    /// 種類ごとの id（table の index。task は TaskId）
+    pub fn id(self) -> u64 {
+        match self {
+            KernelObject::Endpoint(ep) => ep.0 as u64,
+            KernelObject::Notification(n) => n.0 as u64,
+            KernelObject::Shm(shm) => shm.0 as u64,
+            KernelObject::Task(task) => task.0,
+        }
+    }
+}
+
+/// task 内の cap の位置（syscall の引数）
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct CapIndex(pub usize);
+
+/// cap transfer で複製された slot の印
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct CapGrantTag {
+    pub seq: u64,
+    pub to: TaskId,
+}
+
+#[derive(Clone, Copy)]
+pub struct CapSlot {
+    pub object: KernelObject,
+    pub rights: CapRights,
+    /// 初期配置の slot は None
+    pub grant: Option<CapGrantTag>,
+}
+
+/// cap transfer で受け取る分の予備 slot 数
+pub const CAP_TRANSFER_SLOTS: usize = 4;
+
+/// task あたりの slot 数（全 endpoint 分 + 全 shm segment 分 + 受け取り用の予備 + 全 notification 分 + 全 task 分）
+pub const MAX_CAP_SLOTS: usize = MAX_ENDPOINTS + MAX_SHM_SEGMENTS + CAP_TRANSFER_SLOTS + MAX_NOTIFICATIONS + MAX_TASKS;
+
+/// task の handle の先頭（task index t は TASK_CAP_BASE + t）
+pub const TASK_CAP_BASE: usize = MAX_CAP_SLOTS - MAX_TASKS;
+
+/// notification の初期 handle の先頭（NotificationId(n) は NTFN_CAP_BASE + n）
+pub const NTFN_CAP_BASE: usize = TASK_CAP_BASE - MAX_NOTIFICATIONS;
+
+#[derive(Clone, Copy, Debug)]
+pub enum CapError {
+    /// index が MAX_CAP_SLOTS 以上
+    BadIndex,
+    /// slot が空
+    EmptySlot,
+    /// 必要な rights が無い
+    MissingRights,
+    /// slot の object の種類が違う
+    WrongType,
+}
+
+impl CapError {
+    /// last_syscall_ret で返すコード（IPC 以外の syscall）
+    pub fn syscall_code(self) -> u64 {
+        match self {
+            CapError::BadIndex | CapError::EmptySlot => SYSCALL_ERR_BAD_CAP,
+            CapError::MissingRights => SYSCALL_ERR_FORBIDDEN,
+            CapError::WrongType => SYSCALL_ERR_WRONG_TYPE,
+        }
+    }
+}
+
+/// task ごとの capability table（固定長）
+#[derive(Clone, Copy)]
+pub struct CapTable {
+    slots: [Option<CapSlot>; MAX_CAP_SLOTS],
+}
+
+impl CapTable {
+    pub const fn empty() -> Self {
+        CapTable { slots: [None; MAX_CAP_SLOTS] }
+    }
+
+    /// task index ごとの既定（ヘッダの「初期配置」）
+    pub fn default_for_task(task_idx: usize) -> Self {
+        let mut t = CapTable::empty();
+        let (ep_rights, ntfn_rights) = match task_idx {
+            IDLE_TASK_INDEX => return t,
+            TASK0_INDEX => (CapRights::empty(), CapRights::SIGNAL),
+            TASK1_INDEX => (CapRights::SEND | CapRights::RECV, CapRights::SIGNAL | CapRights::WAIT),
+            _ => (CapRights::RECV | CapRights::REPLY, CapRights::SIGNAL | CapRights::WAIT),
+        };
+        if !ep_rights.is_empty() {
+            for (i, slot) in t.slots.iter_mut().enumerate().take(STATIC_ENDPOINTS) {
+                *slot = Some(CapSlot { object: KernelObject::Endpoint(EndpointId(i)), rights: ep_rights, grant: None });
+            }
+        }
+        for (n, slot) in t.slots[NTFN_CAP_BASE..TASK_CAP_BASE].iter_mut().enumerate() {
+            let object = KernelObject::Notification(NotificationId(n));
+            *slot = Some(CapSlot { object, rights: ntfn_rights, grant: None });
+        }
+        t
+    }
+
+    /// 空き slot に入れる（task の slot は使わない。満杯なら None）
+    pub fn insert(&mut self, slot: CapSlot) -> Option<CapIndex> {
+        let (i, free) = self.slots[..TASK_CAP_BASE].iter_mut().enumerate().find(|(_, s)| s.is_none())?;
+        *free = Some(slot);
+        Some(CapIndex(i))
+    }
+
+    /// slot を決め打ちで書く（task の handle。kernel だけが使う）
+    pub fn put(&mut self, cap: CapIndex, slot: Option<CapSlot>) {
+        if let Some(s) = self.slots.get_mut(cap.0) {
+            *s = slot;
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (CapIndex, &CapSlot)> {
+        self.slots.iter().enumerate().filter_map(|(i, s)| s.as_ref().map(|s| (CapIndex(i), s)))
+    }
+
+    /// object を指す slot を全部空にする（消した数を返す）
+    pub fn revoke_object(&mut self, object: KernelObject) -> usize {
+        let mut n = 0;
+        for slot in self.slots.iter_mut() {
+            if slot.is_some_and(|s| s.object == object) {
+                *slot = None;
+                n += 1;
+            }
+        }
+        n
+    }
+
+    /// slot を空にする（Revoke。中身を返す）
+    pub fn take(&mut self, cap: CapIndex) -> Option<CapSlot> {
+        self.slots.get_mut(cap.0)?.take()
+    }
+
+    pub fn clear(&mut self) {
+        self.slots = [None; MAX_CAP_SLOTS];
+    }
+
+    pub fn get(&self, cap: CapIndex) -> Option<CapSlot> {
+        self.slots.get(cap.0).copied().flatten()
+    }
+
+    /// cap を引いて種類と rights を検査する
+    pub fn lookup(&self, cap: CapIndex, kind: ObjectKind, need: CapRights) -> Result<KernelObject, CapError> {
+        let slot = self.slots.get(cap.0).ok_or(CapError::BadIndex)?;
+        let slot = slot.ok_or(CapError::EmptySlot)?;
+        if slot.object.kind() != kind {
+            return Err(CapError::WrongType);
+        }
+        if !slot.rights.contains(need) {
+            return Err(CapError::MissingRights);
+        }
+        Ok(slot.object)
+    }
+
+    /// cap を引いて rights だけ検査する（種類は問わない。cap transfer で添付する handle）
+    pub fn lookup_any(&self, cap: CapIndex, need: CapRights) -> Result<KernelObject, CapError> {
+        let slot = self.slots.get(cap.0).ok_or(CapError::BadIndex)?;
+        let slot = slot.ok_or(CapError::EmptySlot)?;
+        if !slot.rights.contains(need) {
+            return Err(CapError::MissingRights);
+        }
+        Ok(slot.object)
+    }
+}
+
+impl KernelState {
+    /// syscall の handle を kind の object に解決する（違反ならログ + CapDenied で Err）
+    pub(super) fn resolve_cap(
+        &mut self,
+        task_idx: usize,
+        cap: CapIndex,
+        kind: ObjectKind,
+        need: CapRights,
+        api_name: &'static str,
+    ) -> Result<KernelObject, CapError> {
+        self.resolve_cap_of_kind(task_idx, cap, Some(kind), need, api_name)
+    }
+
+    /// 添付された cap（IpcMessage.cap_transfer）が送信側の table に実在するか（種類は問わない。違反はログ + CapDenied）
+    pub(super) fn resolve_transfer_cap(&mut self, task_idx: usize, cap: CapIndex, api_name: &'static str) -> bool {
+        self.resolve_cap_of_kind(task_idx, cap, None, CapRights::empty(), api_name).is_ok()
+    }
+
+    /// resolve_cap の本体（kind = None は種類を検査しない）
+    #[spec("INV-CAP-001")]
+    fn resolve_cap_of_kind(
+        &mut self,
+        task_idx: usize,
+        cap: CapIndex,
+        kind: Option<ObjectKind>,
+        need: CapRights,
+        api_name: &'static str,
+    ) -> Result<KernelObject, CapError> {
+        let found = match kind {
+            Some(kind) => self.cspaces[task_idx].lookup(cap, kind, need),
+            None => self.cspaces[task_idx].lookup_any(cap, need),
+        };
+        let e = match found {
+            Ok(object) => return Ok(object),
+            Err(e) => e,
+        };
+
+        let tid = self.tasks[task_idx].id;
+        let held = self.cspaces[task_idx].get(cap);
+
+        crate::logging::error("syscall: capability denied");
+        crate::logging::info(api_name);
+        match e {
+            CapError::BadIndex => crate::logging::info("reason = BadIndex"),
+            CapError::EmptySlot => crate::logging::info("reason = EmptySlot"),
+            CapError::MissingRights => crate::logging::info("reason = MissingRights"),
+            CapError::WrongType => crate::logging::info("reason = WrongType"),
+        }
+        crate::logging::info_u64("task_id", tid.0);
+        crate::logging::info_u64("cap_index", cap.0 as u64);
+        crate::logging::info_u64("rights_need", need.bits() as u64);
+        crate::logging::info_u64("rights_held", held.map_or(0, |s| s.rights.bits()) as u64);
+        if let (CapError::WrongType, Some(s), Some(kind)) = (e, held, kind) {
+            crate::logging::info_u64("kind_need", kind.code());
+            crate::logging::info_u64("kind_held", s.object.kind().code());
+        }
+
+        self.counters.cap_denied += 1;
+        self.push_event(LogEvent::CapDenied { task: tid, cap: cap.0, need: need.bits() });
+        Err(e)
+    }
+
+    /// IPC syscall の cap を endpoint に解決する（違反なら None）
+    pub(super) fn resolve_endpoint_cap(
+        &mut self,
+        task_idx: usize,
+        cap: CapIndex,
+        need: CapRights,
+        api_name: &'static str,
+    ) -> Option<EndpointId> {
+        match self.resolve_cap(task_idx, cap, ObjectKind::Endpoint, need, api_name) {
+            Ok(KernelObject::Endpoint(ep)) => Some(ep),
+            _ => None,
+        }
+    }
+
+    /// NotifySignal / NotifyWait の handle を notification に解決する（違反なら last_syscall_ret のコード）
+    pub(super) fn resolve_notification_cap(
+        &mut self,
+        task_idx: usize,
+        cap: CapIndex,
+        need: CapRights,
+        api_name: &'static str,
+    ) -> Result<NotificationId, u64> {
+        match self.resolve_cap(task_idx, cap, ObjectKind::Notification, need, api_name) {
+            Ok(KernelObject::Notification(n)) => Ok(n),
+            Ok(_) => Err(SYSCALL_ERR_WRONG_TYPE),
+            Err(e) => Err(e.syscall_code()),
+        }
+    }
+
+    /// ShmMap の handle を segment に解決する（違反なら last_syscall_ret のコード）
+    pub(super) fn resolve_shm_cap(&mut self, task_idx: usize, cap: CapIndex, api_name: &'static str) -> Result<ShmId, u64> {
+        match self.resolve_cap(task_idx, cap, ObjectKind::Shm, CapRights::MAP, api_name) {
+            Ok(KernelObject::Shm(shm)) => Ok(shm),
+            Ok(_) => Err(SYSCALL_ERR_WRONG_TYPE),
+            Err(e) => Err(e.syscall_code()),
+        }
+    }
+
+    /// TaskSuspend / TaskResume / TaskInfo / FaultResolve の handle を task index に解決する
+    /// （違反なら last_syscall_ret のコード。handle の task が居ない / 死んでいれば SYSCALL_ERR_BAD_TASK）
+    pub(super) fn resolve_task_cap(
+        &mut self,
+        task_idx: usize,
+        cap: CapIndex,
+        need: CapRights,
+        api_name: &'static str,
+    ) -> Result<usize, u64> {
+        let task = match self.resolve_cap(task_idx, cap, ObjectKind::Task, need, api_name) {
+            Ok(KernelObject::Task(task)) => task,
+            Ok(_) => return Err(SYSCALL_ERR_WRONG_TYPE),
+            Err(e) => return Err(e.syscall_code()),
+        };
+        match self.task_index_of(task).filter(|&t| self.tasks[t].state != TaskState::Dead) {
+            Some(t) => Ok(t),
+            None => {
+                crate::logging::error("syscall: task handle points to a dead task");
+                crate::logging::info(api_name);
+                crate::logging::info_u64("task_id", task.0);
+                Err(SYSCALL_ERR_BAD_TASK)
+            }
+        }
+    }
+
+    /// owner の table の TASK_CAP_BASE + t に既定の task handle を入れる（ヘッダの「初期配置」）
+    pub(super) fn install_task_handle(&mut self, owner: usize, t: usize) {
+        if owner == IDLE_TASK_INDEX || t == IDLE_TASK_INDEX || t >= MAX_TASKS {
+            return;
+        }
+        if self.tasks[owner].state == TaskState::Dead || self.tasks[t].state == TaskState::Dead {
+            return;
+        }
+        let rights = if self.is_kernel_address_space_of(owner) || self.tasks[t].parent == Some(self.tasks[owner].id) {
+            CapRights::CONTROL | CapRights::INFO
+        } else {
+            CapRights::INFO
+        };
+        let slot = CapSlot { object: KernelObject::Task(self.tasks[t].id), rights, grant: None };
+        self.cspaces[owner].put(CapIndex(TASK_CAP_BASE + t), Some(slot));
+    }
+
+    /// 全 task の組に既定の task handle を入れる（起動時）
+    pub(super) fn install_task_handles(&mut self) {
+        for owner in 0..self.num_tasks {
+            for t in 0..self.num_tasks {
+                self.install_task_handle(owner, t);
+            }
+        }
+    }
+
+    /// object の handle を全 task の table から消す（消した数を返す）
+    pub(super) fn revoke_object_handles(&mut self, object: KernelObject) -> usize {
+        // 消える handle の派生の辺も外す（derivation.rs）
+        self.derivation_forget_object(object);
+        let mut revoked = 0;
+        for t in self.cspaces.iter_mut().take(self.num_tasks) {
+            revoked += t.revoke_object(object);
+        }
+        revoked
+    }
+
+    /// deliver 時の cap transfer（from の cap を to の空き slot に複製し、to から見た msg を返す）
+    pub(super) fn transfer_cap_with_message(&mut self, from_idx: usize, to_idx: usize, msg: IpcMessage) -> IpcMessage {
+        let Some(cap) = msg.cap_transfer() else {
+            return msg;
+        };
+
+        let from_id = self.tasks[from_idx].id;
+        let to_id = self.tasks[to_idx].id;
+        let mut out = msg;
+        out.set_cap_transfer(None);
+
+        let Some(src) = self.cspaces[from_idx].get(cap) else {
+            crate::logging::error("cap_transfer: sender cap vanished; drop cap");
+            crate::logging::info_u64("task_id", from_id.0);
+            crate::logging::info_u64("cap_index", cap.0 as u64);
+            self.counters.cap_transfer_failed += 1;
+            return out;
+        };
+
+        // 派生の辺を足せないなら渡さない（Revoke で消せない複製を作らない。derivation.rs）
+        if !self.derivation_has_room("cap_transfer") {
+            self.counters.cap_transfer_failed += 1;
+            return out;
+        }
+
+        let tag = CapGrantTag { seq: self.next_cap_grant_seq, to: to_id };
+        let granted = CapSlot { object: src.object, rights: src.rights, grant: Some(tag) };
+
+        let Some(slot) = self.cspaces[to_idx].insert(granted) else {
+            crate::logging::error("cap_transfer: receiver cap table full; drop cap");
+            crate::logging::info_u64("task_id", to_id.0);
+            self.counters.cap_transfer_failed += 1;
+            return out;
+        };
+        self.next_cap_grant_seq += 1;
+        self.derivation_record(DerivObject::Cap { task: from_idx, cap }, DerivObject::Cap { task: to_idx, cap: slot });
+
+        self.counters.cap_transfers += 1;
+        self.push_event(LogEvent::CapGranted { from: from_id, to: to_id, cap: cap.0, object: src.object });
+        self.push_event(LogEvent::CapReceived { task: to_id, slot: slot.0, object: src.object, rights: src.rights.bits() });
+
+        out.set_cap_transfer(Some(slot));
+        out
+    }
+
+    /// 転送された cap は、意図した task（tag.to）の table にだけ、ちょうど 1 つある
+    #[spec("INV-CAP-002")]
+    pub(super) fn check_cap_transfer_invariants(&self) {
+        for (idx, table) in self.cspaces.iter().enumerate().take(self.num_tasks) {
+            let owner = self.tasks[idx].id;
+            for (cap, slot) in table.iter() {
+                let Some(tag) = slot.grant else {
+                    continue;
+                };
+
+                if tag.to != owner {
+                    self.invariant_violated(
+                        InvariantId::CapTransfer,
+                        Some(owner),
+                        Some(cap.0 as u64),
+                        "INVARIANT VIOLATION: transferred cap in unintended task",
+                    );
+                    crate::logging::info_u64("task_id", owner.0);
+                    crate::logging::info_u64("cap_index", cap.0 as u64);
+                    crate::logging::info_u64("intended_task_id", tag.to.0);
+                }
+                if tag.seq >= self.next_cap_grant_seq {
+                    self.invariant_violated(
+                        InvariantId::CapTransfer,
+                        Some(owner),
+                        Some(tag.seq),
+                        "INVARIANT VIOLATION: transferred cap seq not issued",
+                    );
+                    crate::logging::info_u64("grant_seq", tag.seq);
+                }
+
+                let copies: usize = self.cspaces[..self.num_tasks]
+                    .iter()
+                    .map(|t| t.iter().filter(|(_, s)| s.grant.map(|g| g.seq) == Some(tag.seq)).count())
+                    .sum();
+                if copies != 1 {
+                    self.invariant_violated(
+                        InvariantId::CapTransfer,
+                        Some(owner),
+                        Some(tag.seq),
+                        "INVARIANT VIOLATION: transferred cap duplicated",
+                    );
+                    crate::logging::info_u64("grant_seq", tag.seq);
+                    crate::logging::info_u64("copies", copies as u64);
+                }
+            }
+        }
+    }
+
+    /// handle は種類に合った rights だけを持ち、生きている object を指す
+    /// - endpoint の handle が未使用の slot を指さないことは check_endpoint_slot_invariants が見る
+    #[spec("INV-CAP-003")]
+    pub(super) fn check_handle_invariants(&self) {
+        for (idx, table) in self.cspaces.iter().enumerate().take(self.num_tasks) {
+            let owner = self.tasks[idx].id;
+            for (cap, slot) in table.iter() {
+                let kind = slot.object.kind();
+                if !kind.rights().contains(slot.rights) {
+                    self.invariant_violated(
+                        InvariantId::Handle,
+                        Some(owner),
+                        Some(cap.0 as u64),
+                        "INVARIANT VIOLATION: handle has rights of another object kind",
+                    );
+                    crate::logging::info_u64("task_id", owner.0);
+                    crate::logging::info_u64("cap_index", cap.0 as u64);
+                    crate::logging::info_u64("kind", kind.code());
+                    crate::logging::info_u64("rights", slot.rights.bits() as u64);
+                }
+
+                let live = match slot.object {
+                    KernelObject::Endpoint(_) => true,
+                    KernelObject::Notification(n) => n.0 < MAX_NOTIFICATIONS,
+                    KernelObject::Shm(shm) => shm.0 < MAX_SHM_SEGMENTS && self.shm[shm.0].allocated,
+                    KernelObject::Task(task) => {
+                        self.task_index_of(task).is_some_and(|t| self.tasks[t].state != TaskState::Dead)
+                    }
+                };
+                if !live {
+                    self.invariant_violated(
+                        InvariantId::Handle,
+                        Some(owner),
+                        Some(cap.0 as u64),
+                        "INVARIANT VIOLATION: handle points to a missing object",
+                    );
+                    crate::logging::info_u64("task_id", owner.0);
+                    crate::logging::info_u64("cap_index", cap.0 as u64);
+                    crate::logging::info_u64("kind", kind.code());
+                    crate::logging::info_u64("object_id", slot.object.id());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,139 @@
+// kernel/src/kernel/deadlock.rs
+//
+// 役割:
+// - IPC の wait-for graph の閉路（send → recv → reply が endpoint をまたいで輪になった deadlock）を検出する。
+//
+// wait-for graph:
+// - 辺は priority.rs の ipc_waits_for と同じ（継承先 = 待っている相手）
+//   * Blocked(IpcReply{partner}) → partner
+//   * Blocked(IpcSend{ep})       → ep の owner
+// - Blocked(IpcRecv / IpcRecvAny / NotifyWait / Sleep) は相手が特定できないので辺を持たない
+// - 各 task の出る辺は高々 1 本なので、閉路は「辿って自分に戻る」で見つかる（互いに素）
+//
+// 検出:
+// - tick の末尾で毎回全体を見る（detect_ipc_deadlocks は on demand にも呼べる）
+// - 閉路ごとに DeadlockDetected（参加 task の TaskId）を出す。同じ閉路は続く限り 1 回だけ
+//
+// 切断（feature ipc_deadlock_break）:
+// - 閉路の中で base_priority が最も低い task（同じなら TaskId の大きい方）を 1 つ選び、
+//   endpoint のキューから外して IPC_ERR_DEADLOCK で起こす（timeout と同じ経路、ipc.rs の abort_ipc_wait）
+// - 既定は検出と記録だけ（待ちはそのまま）
+//
+// やらないこと:
+// - Recv 待ちを含む「誰も send しない」型の停滞（watchdog.rs が拾う）
+// - notification / timer_service をまたぐ閉路
+
+use super::{BlockedReason, KernelState, LogEvent, TaskId, MAX_TASKS};
+use crate::logging;
+
+// abi の EV_DEADLOCK_DETECTED（task0..task3）と揃える
+const _: () = assert!(MAX_TASKS == 4);
+
+pub(super) struct DeadlockState {
+    // 前回の検出で閉路に居た task（bit i = task index i）。続いている閉路は出し直さない
+    reported_mask: u64,
+}
+
+impl DeadlockState {
+    pub(super) const fn new() -> Self {
+        Self { reported_mask: 0 }
+    }
+}
+
+impl KernelState {
+    /// start から辿って start に戻るなら、その閉路（task index の並び）を返す
+    /// - 閉路の最小 index からだけ返す（同じ閉路を 2 回数えない）
+    fn ipc_wait_cycle_from(&self, start: usize) -> Option<([usize; MAX_TASKS], usize)> {
+        let mut members = [0usize; MAX_TASKS];
+        let mut len = 0;
+        let mut cur = start;
+
+        for _ in 0..self.num_tasks {
+            members[len] = cur;
+            len += 1;
+
+            let next = self.ipc_waits_for(cur)?;
+            if next == start {
+                return members[..len].iter().all(|&i| i >= start).then_some((members, len));
+            }
+            if members[..len].contains(&next) {
+                // start を含まない閉路に入った（その閉路の最小 index から見つける）
+                return None;
+            }
+            cur = next;
+        }
+        None
+    }
+
+    /// wait-for graph の閉路を探し、新しい閉路を DeadlockDetected として記録する
+    pub(super) fn detect_ipc_deadlocks(&mut self) {
+        let mut mask = 0u64;
+
+        for start in 0..self.num_tasks {
+            let Some((members, len)) = self.ipc_wait_cycle_from(start) else {
+                continue;
+            };
+
+            let cycle_mask = members[..len].iter().fold(0u64, |m, &i| m | (1 << i));
+            mask |= cycle_mask;
+            if cycle_mask & self.deadlock.reported_mask == cycle_mask {
+                continue;
+            }
+
+            self.counters.ipc_deadlocks_detected += 1;
+
+            logging::error("ipc: deadlock detected");
+            logging::info_u64("cycle_len", len as u64);
+            let mut tasks: [Option<TaskId>; MAX_TASKS] = [None; MAX_TASKS];
+            for (pos, &idx) in members[..len].iter().enumerate() {
+                let t = &self.tasks[idx];
+                tasks[pos] = Some(t.id);
+                logging::info_u64("task_id", t.id.0);
+                match t.blocked_reason {
+                    Some(BlockedReason::IpcSend { ep }) => {
+                        logging::info("blocked_reason = IpcSend");
+                        logging::info_u64("blocked_ep", ep.0 as u64);
+                    }
+                    Some(BlockedReason::IpcReply { partner, ep }) => {
+                        logging::info("blocked_reason = IpcReply");
+                        logging::info_u64("blocked_ep", ep.0 as u64);
+                        logging::info_u64("blocked_partner_task_id", partner.0);
+                    }
+                    _ => {}
+                }
+            }
+            self.push_event(LogEvent::DeadlockDetected { len, tasks });
+
+            #[cfg(feature = "ipc_deadlock_break")]
+            {
+                self.break_ipc_deadlock(&members[..len]);
+                mask &= !cycle_mask;
+            }
+        }
+
+        self.deadlock.reported_mask = mask;
+    }
+
+    /// 閉路の 1 つを IPC_ERR_DEADLOCK で起こして切る（base_priority が最低、同じなら TaskId の大きい方）
+    #[cfg(feature = "ipc_deadlock_break")]
+    fn break_ipc_deadlock(&mut self, members: &[usize]) {
+        let Some(&victim) = members
+            .iter()
+            .min_by_key(|&&i| (self.tasks[i].base_priority, core::cmp::Reverse(self.tasks[i].id.0)))
+        else {
+            return;
+        };
+
+        let ep = match self.tasks[victim].blocked_reason {
+            Some(BlockedReason::IpcSend { ep }) | Some(BlockedReason::IpcReply { ep, .. }) => ep,
+            _ => return,
+        };
+
+        logging::error("ipc: deadlock broken; wake waiter with DEADLOCK");
+        logging::info_u64("task_id", self.tasks[victim].id.0);
+        logging::info_u64("ep_id", ep.0 as u64);
+
+        self.counters.ipc_deadlocks_broken += 1;
+        self.abort_ipc_wait(victim, ep, super::abi::IPC_ERR_DEADLOCK);
+    }
+}
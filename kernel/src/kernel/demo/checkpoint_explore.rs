@@ -0,0 +1,208 @@
+// kernel/src/kernel/demo/checkpoint_explore.rs
+//
+// 役割:
+// - checkpoint_demo: 同じ状態から event の順序を変えた branch を 1 boot の中で何本も走らせる（kernel/checkpoint.rs）。
+//
+// 手順（tick の末尾、state_hash の後）:
+// - tick CHECKPOINT_TICK: checkpoint_take
+// - branch k（0..CHECKPOINT_BRANCHES）を BRANCH_TICKS だけ走らせ、末尾の state_hash を記録して checkpoint_rollback
+//   * branch 0: 巻き戻した状態のまま（元の順序）
+//   * branch k: 始めに走っている task を k 回 preempt する（schedule_next_task。次に dispatch される task を k 個ずらす）
+// - 最後の branch の後も checkpoint へ巻き戻し、discard してから run の残りを元の順序で続ける
+// - rollback が拒否された（mapping が変わった）/ hash が合わなかったら、そこで探索をやめる
+//
+// 方針:
+// - 進行状態は demo 側の static で持つ（KernelState 本体を汚さない）
+// - mem_demo は止める（branch の間に mapping が変わると rollback できない）
+// - 終了時に === Checkpoint Report ===（branch ごとの末尾の state_hash と、違う値になった branch の数）
+//   * 全 branch を走らせて巻き戻せなければ milestone 未達
+//
+// やらないこと:
+// - ring3_tasks / kstack_switch / smp（checkpoint.rs が戻さない状態を持つ）/ replay / scenario_suite / fault_inject
+//   （tick を外から回す・tick で注入する）との併用（compile_error）
+// - 他の demo の static の巻き戻し（branch で進んだ分は戻らない。併用するとその demo の判定が崩れうる）
+
+#[cfg(all(
+    feature = "checkpoint_demo",
+    any(
+        feature = "ring3_tasks",
+        feature = "kstack_switch",
+        feature = "smp",
+        feature = "replay",
+        feature = "scenario_suite",
+        feature = "fault_inject"
+    )
+))]
+compile_error!("checkpoint_demo rolls back only the abstract state; it cannot be combined with ring3_tasks / kstack_switch / smp / replay / scenario_suite / fault_inject");
+
+use spin::Mutex;
+
+use super::super::checkpoint::RollbackError;
+use super::super::{KernelState, IDLE_TASK_INDEX};
+use crate::logging;
+
+/// checkpoint を取る tick
+const CHECKPOINT_TICK: u64 = 24;
+
+/// branch の数と、1 branch で走らせる tick 数
+const CHECKPOINT_BRANCHES: usize = 3;
+const BRANCH_TICKS: u64 = 12;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    /// checkpoint の前
+    Waiting,
+    /// branch `no` を ends_at まで走らせている
+    Branch { no: usize, ends_at: u64 },
+    /// 探索を終えた（全部巻き戻した / 途中でやめた）
+    Done,
+}
+
+struct Explorer {
+    phase: Phase,
+    checkpoint_hash: Option<u64>,
+    /// branch ごとの末尾の state_hash
+    branch_hash: [Option<u64>; CHECKPOINT_BRANCHES],
+    rollbacks: u64,
+    failure: Option<RollbackError>,
+}
+
+static EXPLORER: Mutex<Explorer> = Mutex::new(Explorer {
+    phase: Phase::Waiting,
+    checkpoint_hash: None,
+    branch_hash: [None; CHECKPOINT_BRANCHES],
+    rollbacks: 0,
+    failure: None,
+});
+
+pub fn suppress_mem_demo() -> bool {
+    cfg!(feature = "checkpoint_demo")
+}
+
+/// 毎 tick の末尾: checkpoint を取る / branch を終えて巻き戻す
+pub fn on_tick_end(ks: &mut KernelState) {
+    if !cfg!(feature = "checkpoint_demo") || ks.should_halt {
+        return;
+    }
+
+    let mut e = EXPLORER.lock();
+    match e.phase {
+        Phase::Waiting => {
+            if ks.tick_count < CHECKPOINT_TICK {
+                return;
+            }
+            e.checkpoint_hash = Some(ks.checkpoint_take());
+            e.phase = Phase::Branch { no: 0, ends_at: ks.tick_count + BRANCH_TICKS };
+            logging::info("checkpoint_demo: branch start");
+            logging::info_u64("branch", 0);
+        }
+        Phase::Branch { no, ends_at } => {
+            if ks.tick_count < ends_at {
+                return;
+            }
+            let hash = ks.state_hash();
+            e.branch_hash[no] = Some(hash);
+            logging::info("checkpoint_demo: branch done");
+            logging::info_u64("branch", no as u64);
+            logging::info_u64("state_hash", hash);
+
+            if let Err(err) = ks.checkpoint_rollback() {
+                logging::error("checkpoint_demo: rollback failed; stop exploring");
+                logging::info(err.name());
+                e.failure = Some(err);
+                e.phase = Phase::Done;
+                ks.checkpoint_discard();
+                return;
+            }
+            e.rollbacks += 1;
+
+            let next = no + 1;
+            if next >= CHECKPOINT_BRANCHES {
+                e.phase = Phase::Done;
+                ks.checkpoint_discard();
+                logging::info("checkpoint_demo: all branches explored; continue from the checkpoint");
+                return;
+            }
+            let preempted = preempt_running(ks, next);
+            e.phase = Phase::Branch { no: next, ends_at: ks.tick_count + BRANCH_TICKS };
+            logging::info("checkpoint_demo: branch start");
+            logging::info_u64("branch", next as u64);
+            logging::info_u64("preempted", preempted);
+        }
+        Phase::Done => {}
+    }
+}
+
+/// 走っている task を最大 times 回 preempt する（Ready が無い / idle なら止める）。preempt した回数
+fn preempt_running(ks: &mut KernelState, times: usize) -> u64 {
+    let mut n = 0;
+    for _ in 0..times {
+        if ks.rq_len == 0 || ks.current_task == IDLE_TASK_INDEX {
+            break;
+        }
+        ks.schedule_next_task();
+        n += 1;
+    }
+    n
+}
+
+/// 終了時の集計（checkpoint_demo でなければ何も出さない）
+pub fn report(_ks: &KernelState) {
+    if !cfg!(feature = "checkpoint_demo") {
+        return;
+    }
+    let e = EXPLORER.lock();
+
+    logging::info("=== Checkpoint Report ===");
+    if let Some(h) = e.checkpoint_hash {
+        logging::info_u64("checkpoint_state_hash", h);
+    }
+    logging::info_u64("checkpoint_tick", CHECKPOINT_TICK);
+    logging::info_u64("branch_ticks", BRANCH_TICKS);
+    for (no, h) in e.branch_hash.iter().enumerate() {
+        if let Some(h) = h {
+            logging::info_u64("branch", no as u64);
+            logging::info_u64("branch_state_hash", *h);
+        }
+    }
+    logging::info_u64("rollbacks", e.rollbacks);
+    logging::info_u64("distinct_outcomes", distinct_outcomes(&e.branch_hash));
+    match e.failure {
+        None => {}
+        Some(RollbackError::NoCheckpoint) => logging::info("rollback_failure = no_checkpoint"),
+        Some(RollbackError::MemoryDiverged { expected, actual }) => {
+            logging::info("rollback_failure = memory_diverged");
+            logging::info_u64("expected_fingerprint", expected);
+            logging::info_u64("actual_fingerprint", actual);
+        }
+        Some(RollbackError::HashMismatch { expected, actual }) => {
+            logging::info("rollback_failure = hash_mismatch");
+            logging::info_u64("expected_state_hash", expected);
+            logging::info_u64("actual_state_hash", actual);
+        }
+    }
+    logging::info("=== End of Checkpoint Report ===");
+}
+
+/// 末尾の state_hash が違った branch の数（同じ値は 1 つに数える）
+fn distinct_outcomes(hashes: &[Option<u64>]) -> u64 {
+    let mut n = 0;
+    for (i, h) in hashes.iter().enumerate() {
+        if h.is_some() && !hashes[..i].contains(h) {
+            n += 1;
+        }
+    }
+    n
+}
+
+/// 全 branch を走らせて、毎回 checkpoint へ巻き戻せなかった
+pub fn missed_milestone() -> Option<&'static str> {
+    if !cfg!(feature = "checkpoint_demo") {
+        return None;
+    }
+    let e = EXPLORER.lock();
+    if e.failure.is_some() || e.rollbacks < CHECKPOINT_BRANCHES as u64 {
+        return Some("checkpoint_demo");
+    }
+    None
+}
@@ -0,0 +1,248 @@
+// kernel/src/kernel/demo/fault_handler.rs
+//
+// 役割:
+// - fault_handler_demo: Task1 の #PF を Task2（fault handler）が ep1 で受け取り、
+//   Task1 の代わりに page を map して再開させるデモ（kernel/fault_handler.rs）。
+//
+// 手順:
+// - Task1: FaultHandlerSet（cap1 = ep1）→ map していない FAULT_DEMO_PAGE に guarded RW → #PF は委譲されて Blocked(FaultWait)
+//   → 再開したら同じ RW をやり直し、値が読めたら "fault_handler_demo: resumed; page is mapped"
+//   → FaultHandlerSet（解除）で通常の client に戻る
+// - Task2: ep1 で IpcRecv → FAULT_MSG_TAG の msg（MR1 = addr、cap_transfer = faulter の task handle）を受けたら
+//   その handle で FaultResolve（MapAndResume、addr の page）
+//   → 戻り値を見てから通常の ep0 の server に戻る
+//
+// 方針:
+// - 通常の syscall 経路（pending_syscall -> handle_syscall）をそのまま通す。#PF は mem_demo と同じく
+//   guarded access の Err を kill_current_task_due_to_user_pf に渡す（ring3 の #PF と同じ入口）
+// - 進行状態は demo 側の static で持つ（KernelState 本体を汚さない）
+// - mem_demo は止める（last_syscall_ret を FaultHandlerSet / FaultResolve の結果と混線させない）
+// - Task1 / Task2 / ep1 を使う demo・service とは併用しない（compile_error）
+
+#[cfg(all(
+    feature = "fault_handler_demo",
+    any(
+        feature = "timer_service",
+        feature = "virtio_net",
+        feature = "stress_ipc",
+        feature = "shm_demo",
+        feature = "fs_demo",
+        feature = "abi_selftest",
+        feature = "task_lifecycle_demo",
+        feature = "ring3_tasks"
+    )
+))]
+compile_error!("fault_handler_demo drives Task1 / Task2 over ep1; it cannot be combined with other demos that use them");
+
+use super::super::KernelState;
+
+#[cfg(feature = "fault_handler_demo")]
+use super::super::{
+    abi::{FAULT_MSG_TAG, FAULT_MSG_TAG_MASK, SYSCALL_OK},
+    fault_handler::FaultAction,
+    CapIndex, Syscall, TaskId, TaskState, KERNEL_ASID_INDEX, TASK1_INDEX, TASK2_INDEX,
+};
+#[cfg(feature = "fault_handler_demo")]
+use crate::arch::ops::{Arch, ArchOps, PageFaultInfo};
+#[cfg(feature = "fault_handler_demo")]
+#[cfg(feature = "fault_handler_demo")]
+use crate::mem::addr::{VirtPage, PAGE_SIZE};
+
+#[cfg(feature = "fault_handler_demo")]
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+/// Task1 が map せずに触るページ（fs demo の 0x170 と重ねない）
+#[cfg(feature = "fault_handler_demo")]
+const FAULT_DEMO_PAGE: u64 = 0x180;
+
+/// fault handler の endpoint を指す cap（初期配置で slot 1 = ep1。Task1 は Send、Task2 は Recv を持つ）
+#[cfg(feature = "fault_handler_demo")]
+const FAULT_DEMO_CAP: CapIndex = CapIndex(1);
+
+/// Task1 が書いて読み戻す値
+#[cfg(feature = "fault_handler_demo")]
+const FAULT_DEMO_PATTERN: u64 = 0xFA17_D00D_0000_0001;
+
+// Task1 の段階: 0 = 登録前, 1 = 登録待ち, 2 = 再開待ち, 3 = 解除待ち, 4 = 終了
+#[cfg(feature = "fault_handler_demo")]
+static TASK1_STAGE: AtomicU8 = AtomicU8::new(0);
+// Task2 の段階: 0 = recv 前, 1 = fault 待ち, 2 = resolve 待ち, 3 = 終了
+#[cfg(feature = "fault_handler_demo")]
+static TASK2_STAGE: AtomicU8 = AtomicU8::new(0);
+#[cfg(feature = "fault_handler_demo")]
+static RESUMED_OK: AtomicBool = AtomicBool::new(false);
+
+/// FAULT_DEMO_PAGE の先頭に guarded に書いて読み戻す
+#[cfg(feature = "fault_handler_demo")]
+fn rw_demo_page(ks: &KernelState, idx: usize) -> Option<Result<u64, PageFaultInfo>> {
+    let as_idx = ks.tasks[idx].address_space_id.0;
+    let root = ks.address_spaces[as_idx].root_page_frame?;
+    let kernel_root = ks.address_spaces[KERNEL_ASID_INDEX].root_page_frame?;
+    let virt = Arch::USER_SPACE_BASE + VirtPage::from_index(FAULT_DEMO_PAGE).start_address().0;
+    Some(Arch::guarded_user_rw_u64_in_root(root, kernel_root, virt as *mut u64, FAULT_DEMO_PATTERN))
+}
+
+#[cfg(feature = "fault_handler_demo")]
+fn task1_step(ks: &mut KernelState, idx: usize) -> bool {
+    let stage = TASK1_STAGE.load(Ordering::Relaxed);
+    let ret = ks.take_unread_last_syscall_ret(idx);
+
+    match stage {
+        0 => {
+            crate::logging::info("fault_handler_demo: Task1 registers ep1 as its fault handler");
+            ks.tasks[idx].pending_syscall = Some(Syscall::FaultHandlerSet { cap: Some(FAULT_DEMO_CAP) });
+            TASK1_STAGE.store(1, Ordering::Relaxed);
+            true
+        }
+        1 => match ret {
+            Some(SYSCALL_OK) => match rw_demo_page(ks, idx) {
+                Some(Err(pf)) => {
+                    crate::logging::info("fault_handler_demo: Task1 touches an unmapped page");
+                    TASK1_STAGE.store(2, Ordering::Relaxed);
+                    ks.kill_current_task_due_to_user_pf(pf);
+                    true
+                }
+                _ => {
+                    crate::logging::error("fault_handler_demo: demo page did not fault");
+                    TASK1_STAGE.store(4, Ordering::Relaxed);
+                    false
+                }
+            },
+            Some(v) => {
+                crate::logging::error("fault_handler_demo: FaultHandlerSet failed");
+                crate::logging::info_u64("ret", v);
+                TASK1_STAGE.store(4, Ordering::Relaxed);
+                false
+            }
+            None => true,
+        },
+        2 => {
+            // Blocked(FaultWait) の間は user step が来ない。来たら resolve 済み
+            match rw_demo_page(ks, idx) {
+                Some(Ok(v)) if v == FAULT_DEMO_PATTERN => {
+                    RESUMED_OK.store(true, Ordering::Relaxed);
+                    crate::logging::info("fault_handler_demo: resumed; page is mapped");
+                }
+                _ => crate::logging::error("fault_handler_demo: resumed but the page is still not usable"),
+            }
+            ks.tasks[idx].pending_syscall = Some(Syscall::FaultHandlerSet { cap: None });
+            TASK1_STAGE.store(3, Ordering::Relaxed);
+            true
+        }
+        3 => match ret {
+            Some(_) => {
+                TASK1_STAGE.store(4, Ordering::Relaxed);
+                false
+            }
+            None => true,
+        },
+        _ => false,
+    }
+}
+
+#[cfg(feature = "fault_handler_demo")]
+fn task2_step(ks: &mut KernelState, idx: usize) -> bool {
+    let stage = TASK2_STAGE.load(Ordering::Relaxed);
+
+    match stage {
+        0 => {
+            ks.tasks[idx].pending_syscall = Some(Syscall::IpcRecv { cap: FAULT_DEMO_CAP });
+            TASK2_STAGE.store(1, Ordering::Relaxed);
+            true
+        }
+        1 => {
+            if let Some(m) = ks.tasks[idx].last_reply.take() {
+                crate::logging::error("fault_handler_demo: recv on ep1 failed");
+                crate::logging::info_u64("reply", m.mr0());
+                TASK2_STAGE.store(3, Ordering::Relaxed);
+                return false;
+            }
+            let Some(msg) = ks.tasks[idx].last_msg.take() else {
+                return true;
+            };
+            if msg.mr0() & FAULT_MSG_TAG_MASK != FAULT_MSG_TAG {
+                crate::logging::error("fault_handler_demo: unexpected msg on ep1");
+                crate::logging::info_u64("msg", msg.mr0());
+                TASK2_STAGE.store(3, Ordering::Relaxed);
+                return false;
+            }
+
+            let task = TaskId(msg.mr0() & !FAULT_MSG_TAG_MASK);
+            let Some(cap) = msg.cap_transfer() else {
+                crate::logging::error("fault_handler_demo: fault msg without a task handle");
+                TASK2_STAGE.store(3, Ordering::Relaxed);
+                return false;
+            };
+            let addr = msg.mr(1);
+            crate::logging::info("fault_handler_demo: Task2 got a fault; mapping the page for the faulter");
+            crate::logging::info_u64("task_id", task.0);
+            crate::logging::info_u64("cap_index", cap.0 as u64);
+            crate::logging::info_hex("addr", addr);
+
+            let page = VirtPage::from_index(addr.wrapping_sub(Arch::USER_SPACE_BASE) / PAGE_SIZE);
+            ks.tasks[idx].pending_syscall = Some(Syscall::FaultResolve { cap, action: FaultAction::MapAndResume { page } });
+            TASK2_STAGE.store(2, Ordering::Relaxed);
+            true
+        }
+        2 => match ks.take_unread_last_syscall_ret(idx) {
+            Some(v) => {
+                if v != SYSCALL_OK {
+                    crate::logging::error("fault_handler_demo: FaultResolve failed");
+                    crate::logging::info_u64("ret", v);
+                }
+                TASK2_STAGE.store(3, Ordering::Relaxed);
+                false
+            }
+            None => true,
+        },
+        _ => false,
+    }
+}
+
+/// Task1 / Task2 の user step を乗っ取る（fault_handler_demo のときだけ）
+pub fn on_user_step(ks: &mut KernelState, task_idx: usize) -> bool {
+    #[cfg(feature = "fault_handler_demo")]
+    {
+        if ks.tasks[task_idx].state == TaskState::Dead {
+            return false;
+        }
+        match task_idx {
+            TASK1_INDEX => task1_step(ks, task_idx),
+            TASK2_INDEX => task2_step(ks, task_idx),
+            _ => false,
+        }
+    }
+
+    #[cfg(not(feature = "fault_handler_demo"))]
+    {
+        let _ = (ks, task_idx);
+        false
+    }
+}
+
+/// mem_demo を止めるか（FaultHandlerSet / FaultResolve の last_syscall_ret を混線させない）
+pub fn suppress_mem_demo() -> bool {
+    cfg!(feature = "fault_handler_demo")
+}
+
+/// run の verdict 用: Task1 が handler の map で再開できなかったか（届かなかった milestone の名前）
+pub fn missed_milestone() -> Option<&'static str> {
+    #[cfg(feature = "fault_handler_demo")]
+    if !RESUMED_OK.load(Ordering::Relaxed) {
+        return Some("fault_handler_resume");
+    }
+
+    None
+}
+
+/// tick ループ終了後の集計
+pub fn report(ks: &KernelState) {
+    #[cfg(feature = "fault_handler_demo")]
+    {
+        ks.fault_handler_report();
+        crate::logging::info_u64("fault_handler_demo_resumed_ok", RESUMED_OK.load(Ordering::Relaxed) as u64);
+    }
+
+    #[cfg(not(feature = "fault_handler_demo"))]
+    let _ = ks;
+}
@@ -0,0 +1,401 @@
+// kernel/src/kernel/demo/fault_inject.rs
+//
+// 役割:
+// - fault injection の汎用エンジン: 「tick N に fault F を task T（endpoint E）へ入れる」という台本（FaultStep の表）を
+//   毎 tick 進める。ipc_faults / mem_faults のような「1 fault = 1 static」の手書きをせずに、rescue 経路を台本で踏ませる。
+//
+// fault（FaultKind、command line の書き方）:
+// - kill:  <tick>:kill:<task_id>         task を DemoInjected（code = FAULT_INJECT_CODE | 台本の番号）で kill する
+// - close: <tick>:close:<ep_id>          endpoint を close して待ち手を ENDPOINT_CLOSED で救済する（EndpointDelete と同じ経路）
+// - oom:   <tick>:oom:<count>            この後の allocate_frame を count 回失敗させる（mm の inject_alloc_failures）
+// - delay: <tick>:delay:<task_id>:<ticks> Sleep 中の task の wake_at を ticks 遅らせる（timer の遅れ）
+//
+// 台本の出どころ（上から順に、最初に在るもの）:
+// - command line の faults=<step>,<step>,...（最大 FAULT_SCHEDULE_MAX 個）
+// - command line の fault_seed=<n>: seed から xorshift64 で FAULT_SEED_STEPS 個作る（seed をログに出すので同じ run を再現できる）
+//   * kill / close / delay だけを選ぶ（oom は tick の AllocateFrame が halt するので、明示した台本でだけ使う）
+// - feature fault_inject: 組み込みの DEFAULT_STEPS
+// - どれも無ければ空（on_tick は何もしない）
+//
+// 進め方（tick_body の action の後、Sleep の期限より前）:
+// - tick <= 今の tick で、まだ入れていない step を台本の順に見る
+//   * 入れた: fired
+//   * 対象が無い（Dead の task / idle / closed の endpoint / 範囲外）: skipped（二度と見ない）
+//   * delay の task が Sleep していない: 入れられる tick まで持ち越す（pending）
+// - kill / close は KernelState の正規の経路を通す（invariant は同じ tick の末尾で検査される）
+//
+// frame 確保の失敗 mode（FrameFailMode、mm）:
+// - 台本の oom（n 回だけ）とは別に、bootstrap の後の allocate_frame をずっと失敗させうる switch
+//   * after:<n>:               bootstrap の後、n 回成功したら以後は全部失敗
+//   * random:<seed>:<one_in>:  seed の xorshift64 で 1/one_in の確率で失敗（seed が同じなら同じ回が失敗する）
+// - command line の frame_fail=（off / after:<n> / random:<seed>:<one_in>）。無ければ feature frame_alloc_fail で
+//   after:FRAME_FAIL_AFTER_DEFAULT、どちらも無ければ off
+// - bootstrap の後に入れる（bootstrap の page table / ring3 の program が失敗して起動できなくなるのを避ける）
+//
+// 方針:
+// - 進行状態は demo 側の static で持つ（KernelState 本体を汚さない）
+// - 終了時に === Fault Inject Report ===（fired / skipped / pending）。1 つも入らなければ milestone 未達
+//
+// やらないこと:
+// - scenario_suite との併用（シナリオごとの verdict に混ざる。compile_error / command line は無視）
+// - replay 中の注入（台本の再現性を崩す）
+// - 本物の fault への偽装（kill は DemoInjected、close は通常の close。ログで区別できる）
+
+#[cfg(all(feature = "fault_inject", feature = "scenario_suite"))]
+compile_error!("fault_inject injects into every scenario of scenario_suite; run it as a normal boot instead");
+
+use spin::Mutex;
+
+use super::super::boot_params::parse_u64;
+use super::super::{
+    BlockedReason, EndpointId, KernelState, TaskId, TaskKillReason, IDLE_TASK_INDEX, MAX_ENDPOINTS, STATIC_ENDPOINTS,
+    TASK0_ID, TASK1_ID, TASK2_ID,
+};
+use crate::logging;
+use crate::mm::FrameFailMode;
+
+/// 台本に書ける step の数
+pub const FAULT_SCHEDULE_MAX: usize = 8;
+
+/// fault_seed= で作る step の数
+const FAULT_SEED_STEPS: usize = 4;
+
+/// seed で作る step の tick の範囲（[FIRST, FIRST + SPAN)）
+const FAULT_SEED_FIRST_TICK: u64 = 10;
+const FAULT_SEED_TICK_SPAN: u64 = 100;
+
+/// seed で作る delay の最大 tick 数
+const FAULT_SEED_MAX_DELAY: u64 = 8;
+
+/// feature frame_alloc_fail の既定: bootstrap の後、この回数だけ成功させる
+const FRAME_FAIL_AFTER_DEFAULT: u64 = 16;
+
+/// kill の DemoInjected code（下位 8 bit に台本の番号）
+const FAULT_INJECT_CODE: u64 = 0xFA17_0000;
+
+/// seed で kill / delay の対象にする task（idle は選ばない）
+const FAULT_SEED_TASKS: [TaskId; 3] = [TASK0_ID, TASK1_ID, TASK2_ID];
+
+#[derive(Clone, Copy)]
+pub enum FaultKind {
+    Kill { task: TaskId },
+    CloseEndpoint { ep: EndpointId },
+    FrameAllocFail { count: u64 },
+    DelayWakeup { task: TaskId, ticks: u64 },
+}
+
+impl FaultKind {
+    /// command line / ログでの名前
+    pub fn name(self) -> &'static str {
+        match self {
+            FaultKind::Kill { .. } => "kill",
+            FaultKind::CloseEndpoint { .. } => "close",
+            FaultKind::FrameAllocFail { .. } => "oom",
+            FaultKind::DelayWakeup { .. } => "delay",
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct FaultStep {
+    pub tick: u64,
+    pub kind: FaultKind,
+}
+
+/// feature fault_inject の台本: ep0 を close して IPC の待ち手を救済させ、その後 server（Task2）を kill する
+const DEFAULT_STEPS: [FaultStep; 2] = [
+    FaultStep { tick: 30, kind: FaultKind::CloseEndpoint { ep: EndpointId(0) } },
+    FaultStep { tick: 60, kind: FaultKind::Kill { task: TASK2_ID } },
+];
+
+/// 台本（固定長。tick の昇順でなくてよい）
+#[derive(Clone, Copy)]
+pub struct FaultSchedule {
+    steps: [Option<FaultStep>; FAULT_SCHEDULE_MAX],
+    len: usize,
+}
+
+impl FaultSchedule {
+    const fn empty() -> Self {
+        FaultSchedule { steps: [None; FAULT_SCHEDULE_MAX], len: 0 }
+    }
+
+    /// feature fault_inject なら DEFAULT_STEPS、無ければ空
+    const fn feature_default() -> Self {
+        let mut s = Self::empty();
+        if cfg!(feature = "fault_inject") {
+            let mut i = 0;
+            while i < DEFAULT_STEPS.len() {
+                s.steps[i] = Some(DEFAULT_STEPS[i]);
+                i += 1;
+            }
+            s.len = DEFAULT_STEPS.len();
+        }
+        s
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    fn push(&mut self, step: FaultStep) -> bool {
+        if self.len >= FAULT_SCHEDULE_MAX {
+            return false;
+        }
+        self.steps[self.len] = Some(step);
+        self.len += 1;
+        true
+    }
+
+    /// command line の faults= の値（<tick>:<kind>:<arg>[:<arg>] を ',' で区切った列）。1 つでも読めなければ None
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut schedule = Self::empty();
+        for item in s.split(',') {
+            let mut f = item.split(':');
+            let tick = parse_u64(f.next()?)?;
+            let kind = match f.next()? {
+                "kill" => FaultKind::Kill { task: TaskId(parse_u64(f.next()?)?) },
+                "close" => FaultKind::CloseEndpoint { ep: EndpointId(parse_u64(f.next()?)? as usize) },
+                "oom" => FaultKind::FrameAllocFail { count: parse_u64(f.next()?)? },
+                "delay" => {
+                    let task = TaskId(parse_u64(f.next()?)?);
+                    FaultKind::DelayWakeup { task, ticks: parse_u64(f.next()?)? }
+                }
+                _ => return None,
+            };
+            if f.next().is_some() || !schedule.push(FaultStep { tick, kind }) {
+                return None;
+            }
+        }
+        Some(schedule)
+    }
+
+    /// seed から作る（xorshift64。seed 0 は xorshift が回らないので定数に置き換える）
+    pub fn from_seed(seed: u64) -> Self {
+        let mut x = if seed == 0 { 0x9E37_79B9_7F4A_7C15 } else { seed };
+        let mut next = move || {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            x
+        };
+
+        let mut schedule = Self::empty();
+        for _ in 0..FAULT_SEED_STEPS {
+            let tick = FAULT_SEED_FIRST_TICK + next() % FAULT_SEED_TICK_SPAN;
+            let task = FAULT_SEED_TASKS[(next() % FAULT_SEED_TASKS.len() as u64) as usize];
+            let kind = match next() % 3 {
+                0 => FaultKind::Kill { task },
+                1 => FaultKind::CloseEndpoint { ep: EndpointId((next() % STATIC_ENDPOINTS as u64) as usize) },
+                _ => FaultKind::DelayWakeup { task, ticks: 1 + next() % FAULT_SEED_MAX_DELAY },
+            };
+            schedule.push(FaultStep { tick, kind });
+        }
+        schedule
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum StepState {
+    Pending,
+    Fired,
+    Skipped,
+}
+
+struct Engine {
+    schedule: FaultSchedule,
+    state: [StepState; FAULT_SCHEDULE_MAX],
+    /// 台本を作った seed（fault_seed= のときだけ。report 用）
+    seed: Option<u64>,
+}
+
+static ENGINE: Mutex<Engine> = Mutex::new(Engine {
+    schedule: FaultSchedule::feature_default(),
+    state: [StepState::Pending; FAULT_SCHEDULE_MAX],
+    seed: None,
+});
+
+/// 台本を入れ替える（boot_params::init から。KernelState より前）
+pub fn begin(schedule: FaultSchedule, seed: Option<u64>) {
+    let mut e = ENGINE.lock();
+    e.schedule = schedule;
+    e.state = [StepState::Pending; FAULT_SCHEDULE_MAX];
+    e.seed = seed;
+
+    logging::info("fault_inject: schedule");
+    if let Some(seed) = seed {
+        logging::info_u64("fault_seed", seed);
+    }
+    logging::info_u64("fault_steps", schedule.len() as u64);
+    for step in schedule.steps.iter().flatten() {
+        log_step(step);
+    }
+}
+
+fn log_step(step: &FaultStep) {
+    logging::info(step.kind.name());
+    logging::info_u64("fault_tick", step.tick);
+    match step.kind {
+        FaultKind::Kill { task } => logging::info_u64("task_id", task.0),
+        FaultKind::CloseEndpoint { ep } => logging::info_u64("ep_id", ep.0 as u64),
+        FaultKind::FrameAllocFail { count } => logging::info_u64("count", count),
+        FaultKind::DelayWakeup { task, ticks } => {
+            logging::info_u64("task_id", task.0);
+            logging::info_u64("delay_ticks", ticks);
+        }
+    }
+}
+
+/// command line の frame_fail= の値（off / after:<n> / random:<seed>:<one_in>。one_in は 1 以上）
+pub fn parse_frame_fail(s: &str) -> Option<FrameFailMode> {
+    let mut f = s.split(':');
+    let mode = match f.next()? {
+        "off" => FrameFailMode::Off,
+        "after" => FrameFailMode::AfterCount { n: parse_u64(f.next()?)? },
+        "random" => {
+            let seed = parse_u64(f.next()?)?;
+            FrameFailMode::Random { seed, one_in: parse_u64(f.next()?).filter(|&n| n > 0)? }
+        }
+        _ => return None,
+    };
+    if f.next().is_some() {
+        return None;
+    }
+    Some(mode)
+}
+
+/// bootstrap の後: frame 確保の失敗 mode を入れる（command line → feature の既定 → off）
+pub fn on_bootstrap_done(ks: &mut KernelState) {
+    let mode = super::super::boot_params::get().frame_fail.unwrap_or(if cfg!(feature = "frame_alloc_fail") {
+        FrameFailMode::AfterCount { n: FRAME_FAIL_AFTER_DEFAULT }
+    } else {
+        FrameFailMode::Off
+    });
+    ks.phys_mem.set_fail_mode(mode);
+
+    match mode {
+        FrameFailMode::Off => {}
+        FrameFailMode::AfterCount { n } => {
+            logging::info("frame_fail: armed (after)");
+            logging::info_u64("frame_fail_after", n);
+        }
+        FrameFailMode::Random { seed, one_in } => {
+            logging::info("frame_fail: armed (random)");
+            logging::info_u64("frame_fail_seed", seed);
+            logging::info_u64("frame_fail_one_in", one_in);
+        }
+    }
+}
+
+/// 毎 tick: 時刻の来た step を入れる
+pub fn on_tick(ks: &mut KernelState) {
+    if super::super::replay::is_active() {
+        return;
+    }
+
+    let mut e = ENGINE.lock();
+    let now = ks.tick_count;
+    for i in 0..e.schedule.len {
+        if e.state[i] != StepState::Pending {
+            continue;
+        }
+        let Some(step) = e.schedule.steps[i] else {
+            continue;
+        };
+        if step.tick > now {
+            continue;
+        }
+
+        let state = inject(ks, i, step.kind);
+        match state {
+            StepState::Fired => {
+                logging::error("fault_inject: injected");
+                logging::info_u64("fault_step", i as u64);
+                log_step(&step);
+            }
+            StepState::Skipped => {
+                logging::info("fault_inject: target gone; step skipped");
+                logging::info_u64("fault_step", i as u64);
+                log_step(&step);
+            }
+            StepState::Pending => {}
+        }
+        e.state[i] = state;
+    }
+}
+
+/// id の task が生きていれば index（idle は対象にしない）
+fn live_task(ks: &KernelState, task: TaskId) -> Option<usize> {
+    ks.task_index_of(task)
+        .filter(|&idx| idx != IDLE_TASK_INDEX && ks.tasks[idx].state != super::super::TaskState::Dead)
+}
+
+fn inject(ks: &mut KernelState, step_no: usize, kind: FaultKind) -> StepState {
+    match kind {
+        FaultKind::Kill { task } => {
+            let Some(idx) = live_task(ks, task) else {
+                return StepState::Skipped;
+            };
+            let code = FAULT_INJECT_CODE | (step_no as u64 & 0xFF);
+            ks.demo_kill_task(idx, TaskKillReason::DemoInjected { code });
+            StepState::Fired
+        }
+        FaultKind::CloseEndpoint { ep } => {
+            if ep.0 >= MAX_ENDPOINTS || ks.endpoints[ep.0].is_closed {
+                return StepState::Skipped;
+            }
+            ks.close_endpoint_and_rescue_waiters(ep);
+            StepState::Fired
+        }
+        FaultKind::FrameAllocFail { count } => {
+            ks.phys_mem.inject_alloc_failures(count);
+            StepState::Fired
+        }
+        FaultKind::DelayWakeup { task, ticks } => {
+            let Some(idx) = live_task(ks, task) else {
+                return StepState::Skipped;
+            };
+            let t = &mut ks.tasks[idx];
+            if !t.state.can_wait() || t.blocked_reason != Some(BlockedReason::Sleep) {
+                return StepState::Pending;
+            }
+            let Some(wake_at) = t.wake_at else {
+                return StepState::Pending;
+            };
+            t.wake_at = Some(wake_at.after_ticks(ticks));
+            StepState::Fired
+        }
+    }
+}
+
+fn count(e: &Engine, state: StepState) -> u64 {
+    e.state[..e.schedule.len].iter().filter(|&&s| s == state).count() as u64
+}
+
+/// 終了時の集計（台本が空なら何も出さない）
+pub fn report(ks: &KernelState) {
+    let e = ENGINE.lock();
+    if e.schedule.len == 0 {
+        return;
+    }
+
+    logging::info("=== Fault Inject Report ===");
+    if let Some(seed) = e.seed {
+        logging::info_u64("fault_seed", seed);
+    }
+    logging::info_u64("fault_steps", e.schedule.len as u64);
+    logging::info_u64("fault_fired", count(&e, StepState::Fired));
+    logging::info_u64("fault_skipped", count(&e, StepState::Skipped));
+    logging::info_u64("fault_pending", count(&e, StepState::Pending));
+    logging::info_u64("frame_alloc_injected_failures", ks.phys_mem.stats().injected_failures);
+    logging::info("=== End of Fault Inject Report ===");
+}
+
+/// 台本が在るのに 1 つも入らなかった
+pub fn missed_milestone() -> Option<&'static str> {
+    let e = ENGINE.lock();
+    if e.schedule.len > 0 && count(&e, StepState::Fired) == 0 {
+        return Some("fault_inject");
+    }
+    None
+}
@@ -0,0 +1,239 @@
+// kernel/src/kernel/demo/fs_read.rs
+//
+// 役割:
+// - fs_demo: Task1 に disk（virtio-blk の TAR image）の hello.txt を FsOpen / FsRead で読ませるデモ。
+//
+// 手順:
+// - Task1: PageMap（FS_DEMO_PAGE、RW）→ page の先頭に名前を書く（guarded RW）→ FsOpen → FsRead（offset 0）
+//   → page の先頭を guarded に読み、"fs_demo: read" と中身の先頭（最大 PREVIEW_LEN byte）を出す
+// - Task2 は通常どおり ep0 の server
+//
+// 方針:
+// - disk が無い（-device virtio-blk-pci なし）/ hello.txt が無いときは FsOpen が SYSCALL_ERR_NO_FILE で返り、demo は何もしない
+//   （milestone は mount できたときだけ見る）
+// - 通常の syscall 経路（pending_syscall -> handle_syscall）をそのまま通す
+// - 進行状態は demo 側の static で持つ（KernelState 本体を汚さない）
+// - mem_demo は止める（last_syscall_ret を PageMap / FsOpen の結果と混線させない）
+// - virtio_net（net_loopback demo）/ shm_demo とは併用しない（どれも Task1 を使う。compile_error）
+
+#[cfg(all(feature = "fs_demo", any(feature = "virtio_net", feature = "shm_demo")))]
+compile_error!("fs_demo cannot be combined with virtio_net / shm_demo (all of them drive Task1)");
+
+use super::super::KernelState;
+
+#[cfg(feature = "fs_demo")]
+use super::super::{
+    abi::{FS_OPEN_OK_TAG, FS_OPEN_OK_TAG_MASK, FS_READ_OK_TAG, FS_READ_OK_TAG_MASK, SYSCALL_ERR_NO_FILE, SYSCALL_OK},
+    Syscall, TaskState, KERNEL_ASID_INDEX, TASK1_INDEX,
+};
+#[cfg(feature = "fs_demo")]
+use crate::arch::ops::{Arch, ArchOps};
+#[cfg(feature = "fs_demo")]
+use crate::mem::addr::VirtPage;
+#[cfg(feature = "fs_demo")]
+use crate::mem::paging::PageFlags;
+
+#[cfg(feature = "fs_demo")]
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+
+/// Task1 が名前と中身の受け渡しに使うページ（net demo の 0x160 と重ねない）
+#[cfg(feature = "fs_demo")]
+const FS_DEMO_PAGE: u64 = 0x170;
+
+/// 読む file の名前
+#[cfg(feature = "fs_demo")]
+const FS_DEMO_FILE: &[u8] = b"hello.txt";
+
+/// ログに出す中身の先頭の長さ
+#[cfg(feature = "fs_demo")]
+const PREVIEW_LEN: usize = 64;
+
+// Task1 の段階: 0 = map 前, 1 = map 待ち, 2 = open 待ち, 3 = read 待ち, 4 = 終了
+#[cfg(feature = "fs_demo")]
+static TASK1_STAGE: AtomicU8 = AtomicU8::new(0);
+#[cfg(feature = "fs_demo")]
+static BYTES_READ: AtomicU64 = AtomicU64::new(0);
+#[cfg(feature = "fs_demo")]
+static READ_OK: AtomicBool = AtomicBool::new(false);
+
+/// user root の page にある u64 を guarded に読む / 書く（value が Some なら書く）
+#[cfg(feature = "fs_demo")]
+fn access_user_u64(ks: &KernelState, task_idx: usize, offset: u64, value: Option<u64>) -> Option<u64> {
+    let as_idx = ks.tasks[task_idx].address_space_id.0;
+    let root = ks.address_spaces[as_idx].root_page_frame?;
+    let kernel_root = ks.address_spaces[KERNEL_ASID_INDEX].root_page_frame?;
+    let virt = Arch::USER_SPACE_BASE + VirtPage::from_index(FS_DEMO_PAGE).start_address().0 + offset;
+
+    let res = match value {
+        Some(v) => Arch::guarded_user_rw_u64_in_root(root, kernel_root, virt as *mut u64, v),
+        None => Arch::guarded_user_read_u64_in_root(root, kernel_root, virt as *const u64),
+    };
+    match res {
+        Ok(v) => Some(v),
+        Err(pf) => {
+            crate::logging::error("fs_demo: #PF on demo page");
+            crate::logging::info_u64("addr", pf.addr);
+            None
+        }
+    }
+}
+
+/// page の先頭に FS_DEMO_FILE を書く（u64 単位。残りは 0）
+#[cfg(feature = "fs_demo")]
+fn write_name(ks: &KernelState, idx: usize) -> bool {
+    for (i, chunk) in FS_DEMO_FILE.chunks(8).enumerate() {
+        let mut word = [0u8; 8];
+        word[..chunk.len()].copy_from_slice(chunk);
+        if access_user_u64(ks, idx, (i * 8) as u64, Some(u64::from_le_bytes(word))).is_none() {
+            return false;
+        }
+    }
+    true
+}
+
+/// 読んだ中身の先頭をログに出す（UTF-8 でなければ先頭の 1 語を hex で）
+#[cfg(feature = "fs_demo")]
+fn log_preview(ks: &KernelState, idx: usize, len: usize) {
+    let mut buf = [0u8; PREVIEW_LEN];
+    let n = len.min(PREVIEW_LEN);
+    for (i, chunk) in buf[..n.div_ceil(8) * 8].chunks_exact_mut(8).enumerate() {
+        let Some(word) = access_user_u64(ks, idx, (i * 8) as u64, None) else {
+            return;
+        };
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+    let text = &buf[..n];
+    let text = text.strip_suffix(b"\n").unwrap_or(text);
+    match core::str::from_utf8(text) {
+        Ok(s) => crate::logging::info(s),
+        Err(_) => crate::logging::info_hex("fs_demo_head", u64::from_le_bytes(buf[..8].try_into().unwrap_or([0; 8]))),
+    }
+}
+
+#[cfg(feature = "fs_demo")]
+fn task1_step(ks: &mut KernelState, idx: usize) -> bool {
+    let stage = TASK1_STAGE.load(Ordering::Relaxed);
+    let ret = ks.take_unread_last_syscall_ret(idx);
+    let page = VirtPage::from_index(FS_DEMO_PAGE);
+
+    match stage {
+        0 => {
+            crate::logging::info("fs_demo: Task1 maps the demo page");
+            let flags = PageFlags::PRESENT | PageFlags::WRITABLE | PageFlags::USER;
+            ks.tasks[idx].pending_syscall = Some(Syscall::PageMap { page, flags });
+            TASK1_STAGE.store(1, Ordering::Relaxed);
+            true
+        }
+        1 => match ret {
+            Some(SYSCALL_OK) => {
+                if !write_name(ks, idx) {
+                    TASK1_STAGE.store(4, Ordering::Relaxed);
+                    return false;
+                }
+                let name_len = FS_DEMO_FILE.len() as u64;
+                ks.tasks[idx].pending_syscall = Some(Syscall::FsOpen { page, name_len });
+                TASK1_STAGE.store(2, Ordering::Relaxed);
+                true
+            }
+            Some(v) => {
+                crate::logging::error("fs_demo: PageMap failed");
+                crate::logging::info_u64("ret", v);
+                TASK1_STAGE.store(4, Ordering::Relaxed);
+                false
+            }
+            None => true,
+        },
+        2 => match ret {
+            Some(v) if (v & FS_OPEN_OK_TAG_MASK) == FS_OPEN_OK_TAG => {
+                let fd = v & !FS_OPEN_OK_TAG_MASK;
+                ks.tasks[idx].pending_syscall = Some(Syscall::FsRead { fd, page, offset: 0 });
+                TASK1_STAGE.store(3, Ordering::Relaxed);
+                true
+            }
+            Some(SYSCALL_ERR_NO_FILE) => {
+                if ks.fs.mounted() {
+                    crate::logging::error("fs_demo: hello.txt is not on the disk");
+                } else {
+                    crate::logging::info("fs_demo: no disk; demo skipped");
+                }
+                TASK1_STAGE.store(4, Ordering::Relaxed);
+                false
+            }
+            Some(v) => {
+                crate::logging::error("fs_demo: FsOpen failed");
+                crate::logging::info_u64("ret", v);
+                TASK1_STAGE.store(4, Ordering::Relaxed);
+                false
+            }
+            None => true,
+        },
+        3 => match ret {
+            Some(v) if (v & FS_READ_OK_TAG_MASK) == FS_READ_OK_TAG => {
+                let n = v & !FS_READ_OK_TAG_MASK;
+                BYTES_READ.store(n, Ordering::Relaxed);
+                READ_OK.store(n > 0, Ordering::Relaxed);
+                crate::logging::info("fs_demo: read");
+                crate::logging::info_u64("bytes", n);
+                log_preview(ks, idx, n as usize);
+                TASK1_STAGE.store(4, Ordering::Relaxed);
+                false
+            }
+            Some(v) => {
+                crate::logging::error("fs_demo: FsRead failed");
+                crate::logging::info_u64("ret", v);
+                TASK1_STAGE.store(4, Ordering::Relaxed);
+                false
+            }
+            None => true,
+        },
+        _ => false,
+    }
+}
+
+/// Task1 の user step を乗っ取る（fs_demo のときだけ）
+pub fn on_user_step(ks: &mut KernelState, task_idx: usize) -> bool {
+    #[cfg(feature = "fs_demo")]
+    {
+        if task_idx != TASK1_INDEX || ks.tasks[task_idx].state == TaskState::Dead {
+            return false;
+        }
+        task1_step(ks, task_idx)
+    }
+
+    #[cfg(not(feature = "fs_demo"))]
+    {
+        let _ = (ks, task_idx);
+        false
+    }
+}
+
+/// mem_demo を止めるか（PageMap / FsOpen の last_syscall_ret を混線させない）
+pub fn suppress_mem_demo() -> bool {
+    cfg!(feature = "fs_demo")
+}
+
+/// run の verdict 用: disk を mount できたのに hello.txt を読めなかったか（届かなかった milestone の名前）
+pub fn missed_milestone(ks: &KernelState) -> Option<&'static str> {
+    #[cfg(feature = "fs_demo")]
+    if ks.fs.mounted() && !READ_OK.load(Ordering::Relaxed) {
+        return Some("fs_demo_read");
+    }
+
+    let _ = ks;
+    None
+}
+
+/// tick ループ終了後の集計（fs の report は virtio_blk なら demo が無くても出す）
+pub fn report(ks: &KernelState) {
+    #[cfg(feature = "virtio_blk")]
+    ks.fs_report();
+
+    #[cfg(feature = "fs_demo")]
+    {
+        crate::logging::info_u64("fs_demo_bytes_read", BYTES_READ.load(Ordering::Relaxed));
+        crate::logging::info_u64("fs_demo_read_ok", READ_OK.load(Ordering::Relaxed) as u64);
+    }
+
+    #[cfg(not(feature = "virtio_blk"))]
+    let _ = ks;
+}
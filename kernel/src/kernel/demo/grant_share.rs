@@ -0,0 +1,272 @@
+// kernel/src/kernel/demo/grant_share.rs
+//
+// 役割:
+// - grant_demo: Task1 が自分の page を IPC の page grant として Task2 に貸し、Task2 が window 越しに読み書きして
+//   reply すると window から外れることを確かめるデモ（kernel/grant.rs）。
+//
+// 手順:
+// - Task2: GrantWindowSet（GRANT_DEMO_WINDOW）→ ep0 で IpcRecv
+//   → GRANT_SHARE_TAG の msg の grant が window なら、window を guarded read で確認して返事の模様を書く
+//   → IpcReply [GRANT_SHARE_TAG, 一致なら 1]
+// - Task1: PageMap（GRANT_DEMO_PAGE）→ 模様を書く → grant 付き IpcSend [GRANT_SHARE_TAG, 模様]
+//   → reply を受けたら、自分の page に Task2 の模様があり、Task2 の window が外れていれば
+//   "grant_demo: Task2 wrote through the grant; window revoked on reply"
+//
+// 方針:
+// - 通常の syscall 経路（pending_syscall -> handle_syscall）をそのまま通す
+// - 進行状態は demo 側の static で持つ（KernelState 本体を汚さない）
+// - mem_demo は止める（last_syscall_ret を PageMap / GrantWindowSet の結果と混線させない）
+// - Task1 / Task2 / ep0 を使う demo・service とは併用しない（compile_error）
+
+#[cfg(all(
+    feature = "grant_demo",
+    any(
+        feature = "timer_service",
+        feature = "virtio_net",
+        feature = "stress_ipc",
+        feature = "shm_demo",
+        feature = "fs_demo",
+        feature = "abi_selftest",
+        feature = "task_lifecycle_demo",
+        feature = "ring3_tasks",
+        feature = "fault_handler_demo"
+    )
+))]
+compile_error!("grant_demo drives Task1 / Task2 over ep0; it cannot be combined with other demos that use them");
+
+use super::super::KernelState;
+
+#[cfg(feature = "grant_demo")]
+use super::super::{
+    abi::SYSCALL_OK, IpcMessage, Syscall, TaskState, IPC_DEMO_CAP0, KERNEL_ASID_INDEX, TASK1_INDEX, TASK2_INDEX,
+};
+#[cfg(feature = "grant_demo")]
+use crate::arch::ops::{Arch, ArchOps};
+#[cfg(feature = "grant_demo")]
+use crate::mem::addr::VirtPage;
+#[cfg(feature = "grant_demo")]
+use crate::mem::paging::PageFlags;
+
+#[cfg(feature = "grant_demo")]
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+/// Task1 が貸すページと、Task2 の window（fault demo の 0x180 と重ねない）
+#[cfg(feature = "grant_demo")]
+const GRANT_DEMO_PAGE: u64 = 0x190;
+#[cfg(feature = "grant_demo")]
+const GRANT_DEMO_WINDOW: u64 = 0x1A0;
+
+/// Task1 ↔ Task2 の msg の MR0
+#[cfg(feature = "grant_demo")]
+const GRANT_SHARE_TAG: u64 = 0x6EA7_0000_0000_0001;
+
+/// Task1 が書く値 / Task2 が window に書き返す値
+#[cfg(feature = "grant_demo")]
+const GRANT_DEMO_PATTERN: u64 = 0x6EA7_D00D_0000_0001;
+#[cfg(feature = "grant_demo")]
+const GRANT_DEMO_REPLY_PATTERN: u64 = 0x6EA7_D00D_0000_0002;
+
+// Task1 の段階: 0 = map 前, 1 = map 待ち, 2 = reply 待ち, 3 = 終了
+#[cfg(feature = "grant_demo")]
+static TASK1_STAGE: AtomicU8 = AtomicU8::new(0);
+// Task2 の段階: 0 = window 前, 1 = window 待ち, 2 = msg 待ち, 3 = 終了
+#[cfg(feature = "grant_demo")]
+static TASK2_STAGE: AtomicU8 = AtomicU8::new(0);
+#[cfg(feature = "grant_demo")]
+static GRANT_OK: AtomicBool = AtomicBool::new(false);
+
+/// user root の page にある u64 を guarded に読む / 書く（value が Some なら書く）
+#[cfg(feature = "grant_demo")]
+fn access_user_u64(ks: &KernelState, task_idx: usize, page: u64, value: Option<u64>) -> Option<u64> {
+    let as_idx = ks.tasks[task_idx].address_space_id.0;
+    let root = ks.address_spaces[as_idx].root_page_frame?;
+    let kernel_root = ks.address_spaces[KERNEL_ASID_INDEX].root_page_frame?;
+    let virt = Arch::USER_SPACE_BASE + VirtPage::from_index(page).start_address().0;
+
+    let res = match value {
+        Some(v) => Arch::guarded_user_rw_u64_in_root(root, kernel_root, virt as *mut u64, v),
+        None => Arch::guarded_user_read_u64_in_root(root, kernel_root, virt as *const u64),
+    };
+    match res {
+        Ok(v) => Some(v),
+        Err(pf) => {
+            crate::logging::error("grant_demo: #PF on granted page");
+            crate::logging::info_u64("addr", pf.addr);
+            None
+        }
+    }
+}
+
+#[cfg(feature = "grant_demo")]
+fn task1_step(ks: &mut KernelState, idx: usize) -> bool {
+    let stage = TASK1_STAGE.load(Ordering::Relaxed);
+
+    match stage {
+        0 => {
+            let page = VirtPage::from_index(GRANT_DEMO_PAGE);
+            let flags = PageFlags::PRESENT | PageFlags::WRITABLE | PageFlags::USER;
+            ks.tasks[idx].pending_syscall = Some(Syscall::PageMap { page, flags });
+            TASK1_STAGE.store(1, Ordering::Relaxed);
+            true
+        }
+        1 => match ks.take_unread_last_syscall_ret(idx) {
+            Some(SYSCALL_OK) => {
+                if access_user_u64(ks, idx, GRANT_DEMO_PAGE, Some(GRANT_DEMO_PATTERN)).is_none() {
+                    TASK1_STAGE.store(3, Ordering::Relaxed);
+                    return false;
+                }
+                let msg = IpcMessage::from_words(&[GRANT_SHARE_TAG, GRANT_DEMO_PATTERN])
+                    .unwrap_or(IpcMessage::word(GRANT_SHARE_TAG))
+                    .with_grant(VirtPage::from_index(GRANT_DEMO_PAGE));
+                crate::logging::info("grant_demo: Task1 wrote pattern; sending the page as a grant");
+                ks.tasks[idx].pending_syscall = Some(Syscall::IpcSend { cap: IPC_DEMO_CAP0, msg, timeout: None });
+                TASK1_STAGE.store(2, Ordering::Relaxed);
+                true
+            }
+            Some(v) => {
+                crate::logging::error("grant_demo: PageMap failed");
+                crate::logging::info_u64("ret", v);
+                TASK1_STAGE.store(3, Ordering::Relaxed);
+                false
+            }
+            None => true,
+        },
+        2 => {
+            let Some(m) = ks.tasks[idx].last_reply.take() else {
+                return true;
+            };
+            TASK1_STAGE.store(3, Ordering::Relaxed);
+
+            let got = access_user_u64(ks, idx, GRANT_DEMO_PAGE, None);
+            let t2_as = ks.tasks[TASK2_INDEX].address_space_id.0;
+            let revoked = ks.address_spaces[t2_as].lookup(VirtPage::from_index(GRANT_DEMO_WINDOW)).is_none();
+
+            if m.mr0() == GRANT_SHARE_TAG && m.mr(1) == 1 && got == Some(GRANT_DEMO_REPLY_PATTERN) && revoked {
+                GRANT_OK.store(true, Ordering::Relaxed);
+                crate::logging::info("grant_demo: Task2 wrote through the grant; window revoked on reply");
+            } else {
+                crate::logging::error("grant_demo: grant round trip failed");
+                crate::logging::info_u64("reply", m.mr0());
+                crate::logging::info_u64("matched", m.mr(1));
+                crate::logging::info_u64("value", got.unwrap_or(0));
+                crate::logging::info_u64("window_revoked", revoked as u64);
+            }
+            true
+        }
+        _ => false,
+    }
+}
+
+#[cfg(feature = "grant_demo")]
+fn task2_step(ks: &mut KernelState, idx: usize) -> bool {
+    let stage = TASK2_STAGE.load(Ordering::Relaxed);
+
+    match stage {
+        0 => {
+            let page = VirtPage::from_index(GRANT_DEMO_WINDOW);
+            ks.tasks[idx].pending_syscall = Some(Syscall::GrantWindowSet { page: Some(page) });
+            TASK2_STAGE.store(1, Ordering::Relaxed);
+            true
+        }
+        1 => match ks.take_unread_last_syscall_ret(idx) {
+            Some(SYSCALL_OK) => {
+                ks.tasks[idx].pending_syscall = Some(Syscall::IpcRecv { cap: IPC_DEMO_CAP0 });
+                TASK2_STAGE.store(2, Ordering::Relaxed);
+                true
+            }
+            Some(v) => {
+                crate::logging::error("grant_demo: GrantWindowSet failed");
+                crate::logging::info_u64("ret", v);
+                TASK2_STAGE.store(3, Ordering::Relaxed);
+                false
+            }
+            None => true,
+        },
+        2 => {
+            if let Some(m) = ks.tasks[idx].last_reply.take() {
+                crate::logging::error("grant_demo: recv on ep0 failed");
+                crate::logging::info_u64("reply", m.mr0());
+                TASK2_STAGE.store(3, Ordering::Relaxed);
+                return false;
+            }
+            let Some(msg) = ks.tasks[idx].last_msg.take() else {
+                return true;
+            };
+            TASK2_STAGE.store(3, Ordering::Relaxed);
+            if msg.mr0() != GRANT_SHARE_TAG {
+                crate::logging::error("grant_demo: unexpected msg on ep0");
+                crate::logging::info_u64("msg", msg.mr0());
+                return false;
+            }
+
+            let matched = if msg.grant() == Some(VirtPage::from_index(GRANT_DEMO_WINDOW)) {
+                let got = access_user_u64(ks, idx, GRANT_DEMO_WINDOW, None);
+                crate::logging::info("grant_demo: Task2 read the granted page through its window");
+                crate::logging::info_u64("value", got.unwrap_or(0));
+                let ok = got == Some(msg.mr(1));
+                if ok {
+                    let _ = access_user_u64(ks, idx, GRANT_DEMO_WINDOW, Some(GRANT_DEMO_REPLY_PATTERN));
+                }
+                ok
+            } else {
+                crate::logging::error("grant_demo: msg arrived without the grant");
+                false
+            };
+
+            let reply = IpcMessage::from_words(&[GRANT_SHARE_TAG, matched as u64]).unwrap_or(IpcMessage::word(GRANT_SHARE_TAG));
+            if let Some(reply_cap) = ks.tasks[idx].last_reply_cap.take() {
+                ks.tasks[idx].pending_syscall = Some(Syscall::IpcReply { reply_cap, msg: reply });
+            }
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Task1 / Task2 の user step を乗っ取る（grant_demo のときだけ）
+pub fn on_user_step(ks: &mut KernelState, task_idx: usize) -> bool {
+    #[cfg(feature = "grant_demo")]
+    {
+        if ks.tasks[task_idx].state == TaskState::Dead {
+            return false;
+        }
+        match task_idx {
+            TASK1_INDEX => task1_step(ks, task_idx),
+            TASK2_INDEX => task2_step(ks, task_idx),
+            _ => false,
+        }
+    }
+
+    #[cfg(not(feature = "grant_demo"))]
+    {
+        let _ = (ks, task_idx);
+        false
+    }
+}
+
+/// mem_demo を止めるか（PageMap / GrantWindowSet の last_syscall_ret を混線させない）
+pub fn suppress_mem_demo() -> bool {
+    cfg!(feature = "grant_demo")
+}
+
+/// run の verdict 用: grant の往復が確かめられなかったか（届かなかった milestone の名前）
+pub fn missed_milestone() -> Option<&'static str> {
+    #[cfg(feature = "grant_demo")]
+    if !GRANT_OK.load(Ordering::Relaxed) {
+        return Some("grant_round_trip");
+    }
+
+    None
+}
+
+/// tick ループ終了後の集計
+pub fn report(ks: &KernelState) {
+    #[cfg(feature = "grant_demo")]
+    {
+        ks.grant_report();
+        crate::logging::info_u64("grant_demo_ok", GRANT_OK.load(Ordering::Relaxed) as u64);
+    }
+
+    #[cfg(not(feature = "grant_demo"))]
+    let _ = ks;
+}
@@ -5,24 +5,38 @@
 // - endpoint_close_test / dead_partner_test など “テスト都合の分岐” を本体から排除する。
 //
 // 方針:
-// - feature off では完全に no-op
+// - シナリオが無効なら完全に no-op（どれが有効かは scenario.rs。既定は feature から決まる）
 // - 本体状態機械を壊さない（kill は KernelState の正規 API で行う）
 // - 「本物の fault」と「テスト注入」を混線させない（reason を分ける）
 
+use core::sync::atomic::{AtomicBool, Ordering};
+
 use super::super::{EndpointId, KernelState, TaskId};
+use super::scenario::{self, Scenario};
+
+// dead_partner_test: kill 済み
+static DEAD_PARTNER_FIRED: AtomicBool = AtomicBool::new(false);
 
 /// KernelState 初期化後の “テスト用初期設定”
 pub fn on_kernel_state_init(ks: &mut KernelState) {
-    #[cfg(feature = "endpoint_close_test")]
-    {
+    if scenario::is_active(Scenario::EndpointClose) {
         use super::super::{IPC_DEMO_EP0, TASK2_ID};
 
         ks.endpoints[IPC_DEMO_EP0.0].owner = Some(TASK2_ID);
-        return;
     }
+}
+
+/// 進行状態を初期値に戻す（scenario suite）
+pub fn reset() {
+    DEAD_PARTNER_FIRED.store(false, Ordering::Relaxed);
+}
 
-    // feature off のときは何もしない
-    let _ = ks;
+/// 有効なシナリオが注入まで届いたか（届かなかったシナリオの名前）
+pub fn missed_milestone() -> Option<&'static str> {
+    if scenario::is_active(Scenario::DeadPartner) && !DEAD_PARTNER_FIRED.load(Ordering::Relaxed) {
+        return Some(Scenario::DeadPartner.name());
+    }
+    None
 }
 
 /// IpcRecv の直後に注入（dead_partner_test）
@@ -30,15 +44,11 @@ pub fn on_kernel_state_init(ks: &mut KernelState) {
 /// 目的:
 /// - partner が死んだ場合の rescue（reply_waiter 等）を確実に踏ませる
 pub fn on_after_ipc_recv(ks: &mut KernelState, task_index: usize, tid: TaskId, ep: EndpointId) {
-    #[cfg(feature = "dead_partner_test")]
-    {
-        use core::sync::atomic::{AtomicBool, Ordering};
+    if scenario::is_active(Scenario::DeadPartner) {
         use super::super::TaskKillReason;
 
-        static FIRED: AtomicBool = AtomicBool::new(false);
-
         // 受信側（TaskId=3）を一度だけ kill
-        if tid.0 == 3 && !FIRED.swap(true, Ordering::SeqCst) {
+        if tid.0 == 3 && !DEAD_PARTNER_FIRED.swap(true, Ordering::SeqCst) {
             // “テスト注入” がログで判別できるように、コードを固定で出す
             // ★修正: u64 に統一（TaskKillReason::DemoInjected { code: u64 } と整合）
             let demo_code: u64 = 0xD34D_0001;
@@ -50,10 +60,5 @@ pub fn on_after_ipc_recv(ks: &mut KernelState, task_index: usize, tid: TaskId, e
 
             ks.demo_kill_task(task_index, TaskKillReason::DemoInjected { code: demo_code });
         }
-        return;
     }
-
-    // dead_partner_test 無効時だけ参照して unused warning を避ける
-    #[cfg(not(feature = "dead_partner_test"))]
-    let _ = (ks, task_index, tid, ep);
 }
@@ -8,44 +8,60 @@
 // - 再現性を最優先（Task固定・1回だけ等）
 // - panic しない（エラーは syscall 戻り値で観測）
 // - ★重要: KernelState 本体の mem_demo 状態機械（mem_demo_stage）を汚さない
-//   → 注入の進行状態は demo 側の static で管理する
+//   → 注入の進行状態は demo 側の static で管理する（scenario suite が reset で戻す）
+// - どれを注入するかは scenario.rs（既定は feature から決まる）
 
 use super::super::KernelState;
+use super::scenario::{self, Scenario};
 
 use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
 
+// evil_double_map: 0: 未実行, 1: 1回目済み, 2: 2回目済み(終了)
+static DOUBLE_MAP_STAGE: AtomicU8 = AtomicU8::new(0);
+// evil_unmap_not_mapped: 注入済み
+static UNMAP_FIRED: AtomicBool = AtomicBool::new(false);
+
 /// mem_demo のタイミングで fault injection を試す。
 /// - 何か注入したら true（通常 mem_demo はスキップしてよい）
 pub fn on_mem_demo(ks: &mut KernelState) -> bool {
-    #[cfg(feature = "evil_double_map")]
-    {
+    if scenario::is_active(Scenario::EvilDoubleMap) {
         return evil_double_map(ks);
     }
 
-    #[cfg(feature = "evil_unmap_not_mapped")]
-    {
+    if scenario::is_active(Scenario::EvilUnmapNotMapped) {
         return evil_unmap_not_mapped(ks);
     }
 
-    // feature off
-    let _ = ks;
     false
 }
 
+/// 進行状態を初期値に戻す（scenario suite）
+pub fn reset() {
+    DOUBLE_MAP_STAGE.store(0, Ordering::Relaxed);
+    UNMAP_FIRED.store(false, Ordering::Relaxed);
+}
+
+/// 有効なシナリオが最後まで注入できたか（届かなかったシナリオの名前）
+pub fn missed_milestone() -> Option<&'static str> {
+    if scenario::is_active(Scenario::EvilDoubleMap) && DOUBLE_MAP_STAGE.load(Ordering::Relaxed) < 2 {
+        return Some(Scenario::EvilDoubleMap.name());
+    }
+    if scenario::is_active(Scenario::EvilUnmapNotMapped) && !UNMAP_FIRED.load(Ordering::Relaxed) {
+        return Some(Scenario::EvilUnmapNotMapped.name());
+    }
+    None
+}
+
 // -----------------------------------------------------------------------------
 // evil_double_map
 // - 同一ページを 2 回 Map して、2 回目が AlreadyMapped を返すことを確認
 // -----------------------------------------------------------------------------
 
-#[cfg(feature = "evil_double_map")]
 fn evil_double_map(ks: &mut KernelState) -> bool {
     use super::super::{TaskState, TASK0_INDEX, TASK1_INDEX};
     use super::super::Syscall;
     use crate::mem::paging::PageFlags;
 
-    // 0: 未実行, 1: 1回目済み, 2: 2回目済み(終了)
-    static STAGE: AtomicU8 = AtomicU8::new(0);
-
     let task_idx = ks.current_task;
 
     if task_idx == TASK0_INDEX {
@@ -63,7 +79,7 @@ fn evil_double_map(ks: &mut KernelState) -> bool {
         return true;
     }
 
-    let stage = STAGE.load(Ordering::Relaxed);
+    let stage = DOUBLE_MAP_STAGE.load(Ordering::Relaxed);
     if stage >= 2 {
         return false;
     }
@@ -74,13 +90,13 @@ fn evil_double_map(ks: &mut KernelState) -> bool {
     if stage == 0 {
         crate::logging::info("evil_double_map: PageMap #1");
         ks.tasks[task_idx].pending_syscall = Some(Syscall::PageMap { page, flags });
-        STAGE.store(1, Ordering::Relaxed);
+        DOUBLE_MAP_STAGE.store(1, Ordering::Relaxed);
         return true;
     }
 
     crate::logging::info("evil_double_map: PageMap #2 (expect AlreadyMapped)");
     ks.tasks[task_idx].pending_syscall = Some(Syscall::PageMap { page, flags });
-    STAGE.store(2, Ordering::Relaxed);
+    DOUBLE_MAP_STAGE.store(2, Ordering::Relaxed);
     true
 }
 
@@ -89,13 +105,10 @@ fn evil_double_map(ks: &mut KernelState) -> bool {
 // - 未Map のページを Unmap して NotMapped を返すことを確認
 // -----------------------------------------------------------------------------
 
-#[cfg(feature = "evil_unmap_not_mapped")]
 fn evil_unmap_not_mapped(ks: &mut KernelState) -> bool {
     use super::super::{TaskState, TASK0_INDEX, TASK1_INDEX};
     use super::super::Syscall;
 
-    static FIRED: AtomicBool = AtomicBool::new(false);
-
     let task_idx = ks.current_task;
 
     if task_idx == TASK0_INDEX {
@@ -113,7 +126,7 @@ fn evil_unmap_not_mapped(ks: &mut KernelState) -> bool {
         return true;
     }
 
-    if FIRED.swap(true, Ordering::SeqCst) {
+    if UNMAP_FIRED.swap(true, Ordering::SeqCst) {
         return false;
     }
 
@@ -11,6 +11,21 @@
 
 pub mod mem_faults;
 pub mod ipc_faults;
+pub mod stress_ipc;
+pub mod abitest;
+pub mod timer_client;
+pub mod task_lifecycle;
+pub mod shm_share;
+pub mod net_loopback;
+pub mod fs_read;
+pub mod shutdown;
+pub mod fault_handler;
+pub mod grant_share;
+pub mod revoke_chain;
+pub mod suspend_resume;
+pub mod scenario;
+pub mod fault_inject;
+pub mod checkpoint_explore;
 
 use super::{EndpointId, KernelState, TaskId};
 
@@ -22,9 +37,112 @@ pub fn on_kernel_state_init(ks: &mut KernelState) {
 /// mem_demo のタイミングで “注入” を試す
 /// - 注入したら true（通常 mem_demo をスキップしてよい）
 pub fn on_mem_demo(ks: &mut KernelState) -> bool {
+    if abitest::suppress_mem_demo()
+        || shm_share::suppress_mem_demo()
+        || net_loopback::suppress_mem_demo()
+        || fs_read::suppress_mem_demo()
+        || fault_handler::suppress_mem_demo()
+        || grant_share::suppress_mem_demo()
+        || revoke_chain::suppress_mem_demo()
+        || suspend_resume::suppress_mem_demo()
+        || checkpoint_explore::suppress_mem_demo()
+        || super::replay::is_active()
+    {
+        return true;
+    }
     mem_faults::on_mem_demo(ks)
 }
 
+/// bootstrap の後（tick の前）に fault の switch を入れる（frame 確保の失敗 mode）
+pub fn on_bootstrap_done(ks: &mut KernelState) {
+    fault_inject::on_bootstrap_done(ks);
+}
+
+/// 毎 tick（action の後、Sleep の期限より前）に台本の fault を入れる（fault_inject）
+pub fn on_tick(ks: &mut KernelState) {
+    fault_inject::on_tick(ks);
+}
+
+/// 毎 tick の末尾（state_hash の後）に checkpoint を取る / branch を巻き戻す（checkpoint_demo）
+pub fn on_tick_end(ks: &mut KernelState) {
+    checkpoint_explore::on_tick_end(ks);
+}
+
+/// user_program の代わりに syscall を積む（stress_ipc など）
+/// - 積んだら true（通常の user_program はスキップする）
+pub fn on_user_step(ks: &mut KernelState, task_idx: usize) -> bool {
+    // replay 中は台本だけが syscall を積む
+    if super::replay::is_active() {
+        return true;
+    }
+    if abitest::on_user_step(ks, task_idx) {
+        return true;
+    }
+    if stress_ipc::on_user_step(ks, task_idx) {
+        return true;
+    }
+    if task_lifecycle::on_user_step(ks, task_idx) {
+        return true;
+    }
+    if shm_share::on_user_step(ks, task_idx) {
+        return true;
+    }
+    if net_loopback::on_user_step(ks, task_idx) {
+        return true;
+    }
+    if fs_read::on_user_step(ks, task_idx) {
+        return true;
+    }
+    if fault_handler::on_user_step(ks, task_idx) {
+        return true;
+    }
+    if grant_share::on_user_step(ks, task_idx) {
+        return true;
+    }
+    if revoke_chain::on_user_step(ks, task_idx) {
+        return true;
+    }
+    if suspend_resume::on_user_step(ks, task_idx) {
+        return true;
+    }
+    if shutdown::on_user_step(ks, task_idx) {
+        return true;
+    }
+    timer_client::on_user_step(ks, task_idx)
+}
+
+/// tick ループ終了後（dump_events の前）の集計
+pub fn on_run_finished(ks: &KernelState) {
+    stress_ipc::report(ks);
+    timer_client::report(ks);
+    task_lifecycle::report(ks);
+    net_loopback::report(ks);
+    fs_read::report(ks);
+    fault_handler::report(ks);
+    grant_share::report(ks);
+    revoke_chain::report(ks);
+    suspend_resume::report(ks);
+    fault_inject::report(ks);
+    checkpoint_explore::report(ks);
+}
+
+/// run の verdict 用: 有効な demo のうち、期待した到達点に届かなかった最初のもの
+pub fn missed_milestone(ks: &KernelState) -> Option<&'static str> {
+    abitest::missed_milestone()
+        .or_else(stress_ipc::missed_milestone)
+        .or_else(timer_client::missed_milestone)
+        .or_else(|| task_lifecycle::missed_milestone(ks))
+        .or_else(|| net_loopback::missed_milestone(ks))
+        .or_else(|| fs_read::missed_milestone(ks))
+        .or_else(fault_handler::missed_milestone)
+        .or_else(grant_share::missed_milestone)
+        .or_else(revoke_chain::missed_milestone)
+        .or_else(suspend_resume::missed_milestone)
+        .or_else(scenario::missed_milestone)
+        .or_else(fault_inject::missed_milestone)
+        .or_else(checkpoint_explore::missed_milestone)
+}
+
 /// IpcRecv の直後に “テスト用イベント” を注入する（dead_partner_test など）
 pub fn on_after_ipc_recv(ks: &mut KernelState, task_index: usize, tid: TaskId, ep: EndpointId) {
     ipc_faults::on_after_ipc_recv(ks, task_index, tid, ep);
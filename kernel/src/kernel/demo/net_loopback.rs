@@ -0,0 +1,310 @@
+// kernel/src/kernel/demo/net_loopback.rs
+//
+// 役割:
+// - virtio_net: Task1 を net service の client にして、frame を 1 つ送り、返ってきた frame を IPC + shm で受け取るデモ。
+//
+// 手順:
+// - Task1: ShmCreate（1 page）→ ShmMap（NET_DEMO_PAGE）→ NET_SERVICE_EP に BIND（notify_ep = ep2）で MAC を得る
+//   → shm の page に ARP request（who-has 10.0.2.2 tell 10.0.2.15）を書く（guarded RW）→ SEND
+//   → ep2 で recv を繰り返し、ARP reply（sender = 10.0.2.2）が来たら "net_demo: ARP reply from gateway"
+// - Task2 は通常どおり ep0 の server
+//
+// 方針:
+// - QEMU の user networking（slirp）は送った frame をそのまま折り返さないので、
+//   「自分宛てに返ってくる frame」は slirp の仮想 gateway（10.0.2.2）の ARP reply で作る
+// - device が無い（-device virtio-net-pci なし）ときは BIND が NET_SVC_ERR_NO_DEVICE で返り、demo は何もしない
+//   （milestone も device が居たときだけ見る）
+// - 通常の syscall 経路（pending_syscall -> handle_syscall）をそのまま通す
+// - 進行状態は demo 側の static で持つ（KernelState 本体を汚さない）
+// - mem_demo は止める（last_syscall_ret を ShmMap の結果と混線させない）
+
+use super::super::KernelState;
+
+#[cfg(feature = "virtio_net")]
+use super::super::{
+    abi::{
+        NET_OP_BIND, NET_OP_SEND, NET_RX_TAG, NET_RX_TAG_MASK, NET_SVC_ERR_NO_DEVICE, NET_SVC_OK, SHM_CREATE_OK_TAG,
+        SHM_CREATE_OK_TAG_MASK, SYSCALL_OK,
+    },
+    net::NET_SERVICE_EP,
+    CapIndex, EndpointId, IpcMessage, Syscall, TaskState, KERNEL_ASID_INDEX, TASK1_INDEX,
+};
+#[cfg(feature = "virtio_net")]
+use crate::arch::ops::{Arch, ArchOps};
+#[cfg(feature = "virtio_net")]
+use crate::mem::addr::VirtPage;
+
+#[cfg(feature = "virtio_net")]
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+
+/// 受信通知の受け口
+#[cfg(feature = "virtio_net")]
+const NET_CLIENT_EP: EndpointId = EndpointId(2);
+
+/// service / 通知受け口を指す cap（初期配置で slot i = EndpointId(i)）
+#[cfg(feature = "virtio_net")]
+const NET_SERVICE_CAP: CapIndex = CapIndex(NET_SERVICE_EP.0);
+#[cfg(feature = "virtio_net")]
+const NET_CLIENT_CAP: CapIndex = CapIndex(NET_CLIENT_EP.0);
+
+/// Task1 が segment を map するページ（shm_demo の 0x140 / 0x150 と重ねない）
+#[cfg(feature = "virtio_net")]
+const NET_DEMO_PAGE: u64 = 0x160;
+
+/// slirp の既定のアドレス（guest / gateway）
+#[cfg(feature = "virtio_net")]
+const GUEST_IP: [u8; 4] = [10, 0, 2, 15];
+#[cfg(feature = "virtio_net")]
+const GATEWAY_IP: [u8; 4] = [10, 0, 2, 2];
+
+#[cfg(feature = "virtio_net")]
+const ETHERTYPE_ARP: u64 = 0x0806;
+#[cfg(feature = "virtio_net")]
+const ARP_OP_REPLY: u16 = 2;
+/// ARP over Ethernet / IPv4 の frame 長（padding なし）
+#[cfg(feature = "virtio_net")]
+const ARP_FRAME_LEN: usize = 42;
+/// frame を u64 で読み書きする語数（ARP_FRAME_LEN を覆う）
+#[cfg(feature = "virtio_net")]
+const ARP_FRAME_WORDS: usize = ARP_FRAME_LEN.div_ceil(8);
+
+// Task1 の段階: 0 = create 前, 1 = create 待ち, 2 = map 待ち, 3 = BIND 待ち, 4 = SEND 待ち, 5 = 受信中, 6 = 終了
+#[cfg(feature = "virtio_net")]
+static TASK1_STAGE: AtomicU8 = AtomicU8::new(0);
+#[cfg(feature = "virtio_net")]
+static SHM_HANDLE: AtomicU64 = AtomicU64::new(0);
+#[cfg(feature = "virtio_net")]
+static FRAMES_RECEIVED: AtomicU64 = AtomicU64::new(0);
+#[cfg(feature = "virtio_net")]
+static ARP_REPLIED: AtomicBool = AtomicBool::new(false);
+
+/// user root の page にある u64 を guarded に読む / 書く（value が Some なら書く）
+#[cfg(feature = "virtio_net")]
+fn access_user_u64(ks: &KernelState, task_idx: usize, offset: u64, value: Option<u64>) -> Option<u64> {
+    let as_idx = ks.tasks[task_idx].address_space_id.0;
+    let root = ks.address_spaces[as_idx].root_page_frame?;
+    let kernel_root = ks.address_spaces[KERNEL_ASID_INDEX].root_page_frame?;
+    let virt = Arch::USER_SPACE_BASE + VirtPage::from_index(NET_DEMO_PAGE).start_address().0 + offset;
+
+    let res = match value {
+        Some(v) => Arch::guarded_user_rw_u64_in_root(root, kernel_root, virt as *mut u64, v),
+        None => Arch::guarded_user_read_u64_in_root(root, kernel_root, virt as *const u64),
+    };
+    match res {
+        Ok(v) => Some(v),
+        Err(pf) => {
+            crate::logging::error("net_demo: #PF on shared page");
+            crate::logging::info_u64("addr", pf.addr);
+            None
+        }
+    }
+}
+
+/// ARP request（broadcast、who-has GATEWAY_IP tell GUEST_IP）
+#[cfg(feature = "virtio_net")]
+fn arp_request(mac: [u8; 6]) -> [u8; ARP_FRAME_WORDS * 8] {
+    let mut f = [0u8; ARP_FRAME_WORDS * 8];
+    f[0..6].copy_from_slice(&[0xFF; 6]);
+    f[6..12].copy_from_slice(&mac);
+    f[12..14].copy_from_slice(&(ETHERTYPE_ARP as u16).to_be_bytes());
+    // htype = Ethernet / ptype = IPv4 / hlen = 6 / plen = 4 / oper = request
+    f[14..22].copy_from_slice(&[0x00, 0x01, 0x08, 0x00, 6, 4, 0x00, 0x01]);
+    f[22..28].copy_from_slice(&mac);
+    f[28..32].copy_from_slice(&GUEST_IP);
+    // tha は 0 のまま
+    f[38..42].copy_from_slice(&GATEWAY_IP);
+    f
+}
+
+#[cfg(feature = "virtio_net")]
+fn task1_step(ks: &mut KernelState, idx: usize) -> bool {
+    let stage = TASK1_STAGE.load(Ordering::Relaxed);
+    let ret = ks.take_unread_last_syscall_ret(idx);
+
+    match stage {
+        0 => {
+            crate::logging::info("net_demo: Task1 creates frame segment");
+            ks.tasks[idx].pending_syscall = Some(Syscall::ShmCreate { pages: 1 });
+            TASK1_STAGE.store(1, Ordering::Relaxed);
+            true
+        }
+        1 => match ret {
+            Some(v) if (v & SHM_CREATE_OK_TAG_MASK) == SHM_CREATE_OK_TAG => {
+                let handle = v & !SHM_CREATE_OK_TAG_MASK;
+                SHM_HANDLE.store(handle, Ordering::Relaxed);
+                let page = VirtPage::from_index(NET_DEMO_PAGE);
+                ks.tasks[idx].pending_syscall = Some(Syscall::ShmMap { cap: CapIndex(handle as usize), page });
+                TASK1_STAGE.store(2, Ordering::Relaxed);
+                true
+            }
+            Some(v) => {
+                crate::logging::error("net_demo: ShmCreate failed");
+                crate::logging::info_u64("ret", v);
+                TASK1_STAGE.store(6, Ordering::Relaxed);
+                false
+            }
+            None => true,
+        },
+        2 => match ret {
+            Some(SYSCALL_OK) => {
+                let handle = SHM_HANDLE.load(Ordering::Relaxed);
+                let msg = IpcMessage::from_words(&[NET_OP_BIND, NET_CLIENT_EP.0 as u64, handle])
+                    .unwrap_or(IpcMessage::word(NET_OP_BIND));
+                ks.tasks[idx].pending_syscall = Some(Syscall::IpcSend { cap: NET_SERVICE_CAP, msg, timeout: None });
+                TASK1_STAGE.store(3, Ordering::Relaxed);
+                true
+            }
+            Some(v) => {
+                crate::logging::error("net_demo: ShmMap failed");
+                crate::logging::info_u64("ret", v);
+                TASK1_STAGE.store(6, Ordering::Relaxed);
+                false
+            }
+            None => true,
+        },
+        3 => {
+            let Some(m) = ks.tasks[idx].last_reply.take() else {
+                return true;
+            };
+            match m.mr0() {
+                NET_SVC_OK => {}
+                NET_SVC_ERR_NO_DEVICE => {
+                    crate::logging::info("net_demo: no virtio-net device; demo skipped");
+                    TASK1_STAGE.store(6, Ordering::Relaxed);
+                    return false;
+                }
+                r => {
+                    crate::logging::error("net_demo: BIND failed");
+                    crate::logging::info_u64("ret", r);
+                    TASK1_STAGE.store(6, Ordering::Relaxed);
+                    return false;
+                }
+            }
+
+            let mac_word = m.mr(1);
+            let mut mac = [0u8; 6];
+            mac.copy_from_slice(&mac_word.to_be_bytes()[2..8]);
+            crate::logging::info("net_demo: bound; sending ARP request for the gateway");
+            crate::logging::info_hex("mac", mac_word);
+
+            let frame = arp_request(mac);
+            for (i, chunk) in frame.chunks_exact(8).enumerate() {
+                let word = u64::from_le_bytes(chunk.try_into().unwrap_or([0; 8]));
+                if access_user_u64(ks, idx, (i * 8) as u64, Some(word)).is_none() {
+                    TASK1_STAGE.store(6, Ordering::Relaxed);
+                    return false;
+                }
+            }
+
+            let handle = SHM_HANDLE.load(Ordering::Relaxed);
+            let msg = IpcMessage::from_words(&[NET_OP_SEND, handle, ARP_FRAME_LEN as u64])
+                .unwrap_or(IpcMessage::word(NET_OP_SEND));
+            ks.tasks[idx].pending_syscall = Some(Syscall::IpcSend { cap: NET_SERVICE_CAP, msg, timeout: None });
+            TASK1_STAGE.store(4, Ordering::Relaxed);
+            true
+        }
+        4 => {
+            let Some(m) = ks.tasks[idx].last_reply.take() else {
+                return true;
+            };
+            if m.mr0() != NET_SVC_OK {
+                crate::logging::error("net_demo: SEND failed");
+                crate::logging::info_u64("ret", m.mr0());
+                TASK1_STAGE.store(6, Ordering::Relaxed);
+                return false;
+            }
+            ks.tasks[idx].pending_syscall = Some(Syscall::IpcRecv { cap: NET_CLIENT_CAP });
+            TASK1_STAGE.store(5, Ordering::Relaxed);
+            true
+        }
+        5 => {
+            if let Some(m) = ks.tasks[idx].last_msg.take() {
+                if (m.mr0() & NET_RX_TAG_MASK) == NET_RX_TAG {
+                    FRAMES_RECEIVED.fetch_add(1, Ordering::Relaxed);
+                    crate::logging::info("net_demo: frame received");
+                    crate::logging::info_u64("seq", m.mr0() & !NET_RX_TAG_MASK);
+                    crate::logging::info_u64("len", m.mr(1));
+                    crate::logging::info_hex("ethertype", m.mr(2));
+                    crate::logging::info_u64("dropped", m.mr(3));
+
+                    if m.mr(2) == ETHERTYPE_ARP && m.mr(1) >= ARP_FRAME_LEN as u64 && check_arp_reply(ks, idx) {
+                        ARP_REPLIED.store(true, Ordering::Relaxed);
+                        TASK1_STAGE.store(6, Ordering::Relaxed);
+                        return false;
+                    }
+                }
+            }
+            ks.tasks[idx].pending_syscall = Some(Syscall::IpcRecv { cap: NET_CLIENT_CAP });
+            true
+        }
+        _ => false,
+    }
+}
+
+/// shm の page の frame が GATEWAY_IP からの ARP reply か（guarded read）
+#[cfg(feature = "virtio_net")]
+fn check_arp_reply(ks: &KernelState, idx: usize) -> bool {
+    let mut f = [0u8; ARP_FRAME_WORDS * 8];
+    for (i, chunk) in f.chunks_exact_mut(8).enumerate() {
+        let Some(word) = access_user_u64(ks, idx, (i * 8) as u64, None) else {
+            return false;
+        };
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+
+    let oper = u16::from_be_bytes([f[20], f[21]]);
+    if oper != ARP_OP_REPLY || f[28..32] != GATEWAY_IP {
+        return false;
+    }
+    let mut sha = [0u8; 8];
+    sha[2..8].copy_from_slice(&f[22..28]);
+    crate::logging::info("net_demo: ARP reply from gateway");
+    crate::logging::info_hex("gateway_mac", u64::from_be_bytes(sha));
+    true
+}
+
+/// Task1 の user step を乗っ取る（virtio_net のときだけ）
+pub fn on_user_step(ks: &mut KernelState, task_idx: usize) -> bool {
+    #[cfg(feature = "virtio_net")]
+    {
+        if task_idx != TASK1_INDEX || ks.tasks[task_idx].state == TaskState::Dead {
+            return false;
+        }
+        task1_step(ks, task_idx)
+    }
+
+    #[cfg(not(feature = "virtio_net"))]
+    {
+        let _ = (ks, task_idx);
+        false
+    }
+}
+
+/// mem_demo を止めるか（ShmMap の last_syscall_ret を混線させない）
+pub fn suppress_mem_demo() -> bool {
+    cfg!(feature = "virtio_net")
+}
+
+/// run の verdict 用: device が居たのに ARP reply を受け取れなかったか（届かなかった milestone の名前）
+pub fn missed_milestone(ks: &KernelState) -> Option<&'static str> {
+    #[cfg(feature = "virtio_net")]
+    if ks.net.device_present() && !ARP_REPLIED.load(Ordering::Relaxed) {
+        return Some("net_demo_arp_reply");
+    }
+
+    let _ = ks;
+    None
+}
+
+/// tick ループ終了後の集計
+pub fn report(ks: &KernelState) {
+    #[cfg(feature = "virtio_net")]
+    {
+        ks.net_report();
+        crate::logging::info_u64("net_demo_frames_received", FRAMES_RECEIVED.load(Ordering::Relaxed));
+        crate::logging::info_u64("net_demo_arp_replied", ARP_REPLIED.load(Ordering::Relaxed) as u64);
+    }
+
+    #[cfg(not(feature = "virtio_net"))]
+    let _ = ks;
+}
@@ -0,0 +1,209 @@
+// kernel/src/kernel/demo/revoke_chain.rs
+//
+// 役割:
+// - revoke_demo: Task1 の cap を Task2 に渡し、Task2 がそれを reply で Task1 に渡し返して 2 段の派生を作ってから、
+//   Task1 が元の cap を Revoke すると両方の複製が消えることを確かめるデモ（kernel/derivation.rs）。
+//
+// 手順:
+// - Task2: ep0 で IpcRecv → REVOKE_DEMO_TAG の msg の cap（受け取った slot）を添付して IpcReply
+// - Task1: cap0 を添付して IpcSend [REVOKE_DEMO_TAG]（Task2 に 1 段目の複製）
+//   → reply の cap（Task2 の複製から Task1 に来た 2 段目の複製）を覚えて Revoke { Cap(cap0) }
+//   → cap0 は残り、2 段目の複製と Task2 の受け取った cap が消えていれば
+//   "revoke_demo: both derived caps revoked; cap0 kept"
+//
+// 方針:
+// - 通常の syscall 経路（pending_syscall -> handle_syscall）をそのまま通す
+// - 進行状態は demo 側の static で持つ（KernelState 本体を汚さない）
+// - mem_demo は止める（last_syscall_ret を Revoke の結果と混線させない）
+// - Task1 / Task2 / ep0 を使う demo・service とは併用しない（compile_error）
+
+#[cfg(all(
+    feature = "revoke_demo",
+    any(
+        feature = "timer_service",
+        feature = "virtio_net",
+        feature = "stress_ipc",
+        feature = "shm_demo",
+        feature = "fs_demo",
+        feature = "abi_selftest",
+        feature = "task_lifecycle_demo",
+        feature = "ring3_tasks",
+        feature = "fault_handler_demo",
+        feature = "grant_demo"
+    )
+))]
+compile_error!("revoke_demo drives Task1 / Task2 over ep0; it cannot be combined with other demos that use them");
+
+use super::super::KernelState;
+
+#[cfg(feature = "revoke_demo")]
+use super::super::{
+    abi::SYSCALL_OK, derivation::RevokeTarget, CapIndex, IpcMessage, Syscall, TaskState, IPC_DEMO_CAP0, TASK1_INDEX,
+    TASK2_INDEX,
+};
+
+#[cfg(feature = "revoke_demo")]
+use core::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+
+/// Task1 ↔ Task2 の msg の MR0
+#[cfg(feature = "revoke_demo")]
+const REVOKE_DEMO_TAG: u64 = 0x4E70_4E70_0000_0001;
+
+// Task1 の段階: 0 = send 前, 1 = reply 待ち, 2 = revoke 待ち, 3 = 終了
+#[cfg(feature = "revoke_demo")]
+static TASK1_STAGE: AtomicU8 = AtomicU8::new(0);
+// Task2 の段階: 0 = recv 前, 1 = msg 待ち, 2 = 終了
+#[cfg(feature = "revoke_demo")]
+static TASK2_STAGE: AtomicU8 = AtomicU8::new(0);
+/// Task2 が受け取った slot / Task1 に返ってきた slot
+#[cfg(feature = "revoke_demo")]
+static TASK2_SLOT: AtomicUsize = AtomicUsize::new(usize::MAX);
+#[cfg(feature = "revoke_demo")]
+static TASK1_SLOT: AtomicUsize = AtomicUsize::new(usize::MAX);
+#[cfg(feature = "revoke_demo")]
+static REVOKE_OK: AtomicBool = AtomicBool::new(false);
+
+#[cfg(feature = "revoke_demo")]
+fn task1_step(ks: &mut KernelState, idx: usize) -> bool {
+    let stage = TASK1_STAGE.load(Ordering::Relaxed);
+
+    match stage {
+        0 => {
+            let msg = IpcMessage::word(REVOKE_DEMO_TAG).with_cap(IPC_DEMO_CAP0);
+            crate::logging::info("revoke_demo: Task1 sends cap0 to Task2");
+            ks.tasks[idx].pending_syscall = Some(Syscall::IpcSend { cap: IPC_DEMO_CAP0, msg, timeout: None });
+            TASK1_STAGE.store(1, Ordering::Relaxed);
+            true
+        }
+        1 => {
+            let Some(m) = ks.tasks[idx].last_reply.take() else {
+                return true;
+            };
+            let Some(slot) = m.cap_transfer().filter(|_| m.mr0() == REVOKE_DEMO_TAG) else {
+                crate::logging::error("revoke_demo: reply did not carry the cap back");
+                crate::logging::info_u64("reply", m.mr0());
+                TASK1_STAGE.store(3, Ordering::Relaxed);
+                return false;
+            };
+            TASK1_SLOT.store(slot.0, Ordering::Relaxed);
+            crate::logging::info("revoke_demo: cap came back through Task2; revoking cap0");
+            crate::logging::info_u64("cap_index", slot.0 as u64);
+            ks.tasks[idx].pending_syscall = Some(Syscall::Revoke { cap_or_page: RevokeTarget::Cap(IPC_DEMO_CAP0) });
+            TASK1_STAGE.store(2, Ordering::Relaxed);
+            true
+        }
+        2 => match ks.take_unread_last_syscall_ret(idx) {
+            Some(ret) => {
+                TASK1_STAGE.store(3, Ordering::Relaxed);
+                let kept = ks.cspaces[idx].get(IPC_DEMO_CAP0).is_some();
+                let back_gone = ks.cspaces[idx].get(CapIndex(TASK1_SLOT.load(Ordering::Relaxed))).is_none();
+                let t2_gone = ks.cspaces[TASK2_INDEX].get(CapIndex(TASK2_SLOT.load(Ordering::Relaxed))).is_none();
+
+                if ret == SYSCALL_OK && kept && back_gone && t2_gone {
+                    REVOKE_OK.store(true, Ordering::Relaxed);
+                    crate::logging::info("revoke_demo: both derived caps revoked; cap0 kept");
+                } else {
+                    crate::logging::error("revoke_demo: revoke did not remove the derived caps");
+                    crate::logging::info_u64("ret", ret);
+                    crate::logging::info_u64("cap0_kept", kept as u64);
+                    crate::logging::info_u64("task1_copy_gone", back_gone as u64);
+                    crate::logging::info_u64("task2_copy_gone", t2_gone as u64);
+                }
+                false
+            }
+            None => true,
+        },
+        _ => false,
+    }
+}
+
+#[cfg(feature = "revoke_demo")]
+fn task2_step(ks: &mut KernelState, idx: usize) -> bool {
+    let stage = TASK2_STAGE.load(Ordering::Relaxed);
+
+    match stage {
+        0 => {
+            ks.tasks[idx].pending_syscall = Some(Syscall::IpcRecv { cap: IPC_DEMO_CAP0 });
+            TASK2_STAGE.store(1, Ordering::Relaxed);
+            true
+        }
+        1 => {
+            if let Some(m) = ks.tasks[idx].last_reply.take() {
+                crate::logging::error("revoke_demo: recv on ep0 failed");
+                crate::logging::info_u64("reply", m.mr0());
+                TASK2_STAGE.store(2, Ordering::Relaxed);
+                return false;
+            }
+            let Some(msg) = ks.tasks[idx].last_msg.take() else {
+                return true;
+            };
+            TASK2_STAGE.store(2, Ordering::Relaxed);
+
+            let reply = match msg.cap_transfer().filter(|_| msg.mr0() == REVOKE_DEMO_TAG) {
+                Some(slot) => {
+                    TASK2_SLOT.store(slot.0, Ordering::Relaxed);
+                    crate::logging::info("revoke_demo: Task2 got the cap; passing it back on reply");
+                    crate::logging::info_u64("cap_index", slot.0 as u64);
+                    IpcMessage::word(REVOKE_DEMO_TAG).with_cap(slot)
+                }
+                None => {
+                    crate::logging::error("revoke_demo: msg arrived without the cap");
+                    IpcMessage::word(msg.mr0())
+                }
+            };
+            if let Some(reply_cap) = ks.tasks[idx].last_reply_cap.take() {
+                ks.tasks[idx].pending_syscall = Some(Syscall::IpcReply { reply_cap, msg: reply });
+            }
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Task1 / Task2 の user step を乗っ取る（revoke_demo のときだけ）
+pub fn on_user_step(ks: &mut KernelState, task_idx: usize) -> bool {
+    #[cfg(feature = "revoke_demo")]
+    {
+        if ks.tasks[task_idx].state == TaskState::Dead {
+            return false;
+        }
+        match task_idx {
+            TASK1_INDEX => task1_step(ks, task_idx),
+            TASK2_INDEX => task2_step(ks, task_idx),
+            _ => false,
+        }
+    }
+
+    #[cfg(not(feature = "revoke_demo"))]
+    {
+        let _ = (ks, task_idx);
+        false
+    }
+}
+
+/// mem_demo を止めるか（Revoke の last_syscall_ret を混線させない）
+pub fn suppress_mem_demo() -> bool {
+    cfg!(feature = "revoke_demo")
+}
+
+/// run の verdict 用: 2 段の派生が Revoke で消えるのを確かめられなかったか（届かなかった milestone の名前）
+pub fn missed_milestone() -> Option<&'static str> {
+    #[cfg(feature = "revoke_demo")]
+    if !REVOKE_OK.load(Ordering::Relaxed) {
+        return Some("revoke_transitive");
+    }
+
+    None
+}
+
+/// tick ループ終了後の集計
+pub fn report(ks: &KernelState) {
+    #[cfg(feature = "revoke_demo")]
+    {
+        ks.derivation_report();
+        crate::logging::info_u64("revoke_demo_ok", REVOKE_OK.load(Ordering::Relaxed) as u64);
+    }
+
+    #[cfg(not(feature = "revoke_demo"))]
+    let _ = ks;
+}
@@ -0,0 +1,112 @@
+// kernel/src/kernel/demo/scenario.rs
+//
+// 役割:
+// - fault injection のシナリオ（evil_double_map / dead_partner_test など）を実行時に選べるようにする登録表。
+// - feature scenario_suite のときは、1 回の boot で全シナリオを順に流して verdict を集める（entry.rs）。
+//
+// 選び方:
+// - 有効なシナリオは bitmask（ACTIVE）で持つ。既定は feature から決まる（従来の 1 feature = 1 build と同じ挙動）
+// - command line の scenario=<name>（boot_params.rs）があれば、build し直さずにそのシナリオ 1 つだけにする
+// - demo 側の hook は cfg ではなく is_active(Scenario::X) を見る
+// - suite は begin(s) で「s だけ有効」にし、demo 側の進行状態（static）を戻してから KernelState を作り直す
+//
+// やらないこと:
+// - KernelState に状態を持つシナリオ（pf_demo / kill_cleanup_test）と ring3 系 / replay / abi_selftest は対象外
+//   （feature のまま。suite には入らない）
+// - 複数シナリオの同時実行（suite は 1 つずつ。feature を複数付けたときの同時有効は従来どおり）
+
+#[cfg(all(
+    feature = "scenario_suite",
+    any(feature = "replay", feature = "kstack_switch", feature = "ring3_demo", feature = "ring3_mailbox", feature = "ring3_mailbox_loop")
+))]
+compile_error!("scenario_suite runs synthetic ticks on the boot stack; it cannot be combined with replay / kstack_switch / ring3 features");
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use super::{ipc_faults, mem_faults};
+use crate::logging;
+
+/// 実行時に切り替えられるシナリオ
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Scenario {
+    /// 注入なし（通常の user_program）
+    Baseline = 0,
+    EvilDoubleMap = 1,
+    EvilUnmapNotMapped = 2,
+    DeadPartner = 3,
+    EndpointClose = 4,
+    IpcSingleSlow = 5,
+}
+
+/// suite が流す順（command line の scenario= の名前もここから引く）
+pub const SCENARIOS: [Scenario; 6] = [
+    Scenario::Baseline,
+    Scenario::EvilDoubleMap,
+    Scenario::EvilUnmapNotMapped,
+    Scenario::DeadPartner,
+    Scenario::EndpointClose,
+    Scenario::IpcSingleSlow,
+];
+
+impl Scenario {
+    /// 対応する feature 名（ログ / verdict 用）
+    pub fn name(self) -> &'static str {
+        match self {
+            Scenario::Baseline => "baseline",
+            Scenario::EvilDoubleMap => "evil_double_map",
+            Scenario::EvilUnmapNotMapped => "evil_unmap_not_mapped",
+            Scenario::DeadPartner => "dead_partner_test",
+            Scenario::EndpointClose => "endpoint_close_test",
+            Scenario::IpcSingleSlow => "ipc_demo_single_slow",
+        }
+    }
+
+    const fn bit(self) -> u8 {
+        1 << (self as u8)
+    }
+}
+
+/// feature から決まる既定の bitmask（Baseline は bit を持たない = 何も注入しない）
+const fn feature_mask() -> u8 {
+    let mut mask = 0;
+    if cfg!(feature = "evil_double_map") {
+        mask |= Scenario::EvilDoubleMap.bit();
+    }
+    if cfg!(feature = "evil_unmap_not_mapped") {
+        mask |= Scenario::EvilUnmapNotMapped.bit();
+    }
+    if cfg!(feature = "dead_partner_test") {
+        mask |= Scenario::DeadPartner.bit();
+    }
+    if cfg!(feature = "endpoint_close_test") {
+        mask |= Scenario::EndpointClose.bit();
+    }
+    if cfg!(feature = "ipc_demo_single_slow") {
+        mask |= Scenario::IpcSingleSlow.bit();
+    }
+    mask
+}
+
+static ACTIVE: AtomicU8 = AtomicU8::new(feature_mask());
+
+/// このシナリオが有効か
+pub fn is_active(s: Scenario) -> bool {
+    s != Scenario::Baseline && ACTIVE.load(Ordering::Relaxed) & s.bit() != 0
+}
+
+/// s だけを有効にして、demo 側の進行状態を初期値に戻す（suite が KernelState を作る前 / command line の scenario= で呼ぶ）
+pub fn begin(s: Scenario) {
+    let mask = if s == Scenario::Baseline { 0 } else { s.bit() };
+    ACTIVE.store(mask, Ordering::Relaxed);
+    mem_faults::reset();
+    ipc_faults::reset();
+
+    logging::info("scenario: begin");
+    logging::info(s.name());
+}
+
+/// 有効なシナリオのうち、注入まで届かなかった最初のもの（verdict 用）
+pub fn missed_milestone() -> Option<&'static str> {
+    mem_faults::missed_milestone().or_else(ipc_faults::missed_milestone)
+}
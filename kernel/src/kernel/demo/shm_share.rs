@@ -0,0 +1,230 @@
+// kernel/src/kernel/demo/shm_share.rs
+//
+// 役割:
+// - shm_demo: Task1 が作った shared memory segment を IPC で Task2 に渡し、
+//   Task2 が自分の AddressSpace に map して Task1 の書いた値を読むデモ。
+//
+// 手順:
+// - Task1: ShmCreate（1 page）→ ShmMap（SHM_DEMO_PAGE_TASK1）→ 模様を書く（guarded RW）
+//   → IpcSend [SHM_SHARE_TAG | handle, 模様] + shm handle の cap transfer → reply（MR1 = 1 なら一致）で "shm_demo: shared OK"
+// - Task2: SHM_SHARE_TAG の msg を受けたら、受け取った handle（msg.cap_transfer）で ShmMap（SHM_DEMO_PAGE_TASK2）
+//   → guarded read で模様を確認
+//   → IpcReply [0xABCD タグ, 一致なら 1]
+//   * それ以外の msg は通常の server と同じに任せる（false を返す）
+//
+// 方針:
+// - 通常の syscall 経路（pending_syscall -> handle_syscall）をそのまま通す
+// - segment は handle でしか指せないので、Task2 への受け渡しは cap transfer（MR の handle 値は Task1 側の番号で、ログ用）
+// - 進行状態は demo 側の static で持つ（KernelState 本体を汚さない）
+// - mem_demo は止める（last_syscall_ret を ShmMap の結果と混線させない）
+
+use super::super::KernelState;
+
+#[cfg(feature = "shm_demo")]
+use super::super::{
+    abi::{SHM_CREATE_OK_TAG, SHM_CREATE_OK_TAG_MASK, SYSCALL_OK},
+    CapIndex, IpcMessage, Syscall, TaskState, IPC_DEMO_CAP0, KERNEL_ASID_INDEX, TASK1_INDEX, TASK2_INDEX,
+};
+#[cfg(feature = "shm_demo")]
+use crate::arch::ops::{Arch, ArchOps};
+#[cfg(feature = "shm_demo")]
+use crate::mem::addr::VirtPage;
+
+#[cfg(feature = "shm_demo")]
+use core::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+/// Task1 / Task2 が segment を map するページ（mem_demo の 0x110、abitest の 0x130 と重ねない）
+#[cfg(feature = "shm_demo")]
+const SHM_DEMO_PAGE_TASK1: u64 = 0x140;
+#[cfg(feature = "shm_demo")]
+const SHM_DEMO_PAGE_TASK2: u64 = 0x150;
+
+/// Task1 → Task2 の msg の MR0 上位 16bit（下位は Task1 側の shm handle）
+#[cfg(feature = "shm_demo")]
+const SHM_SHARE_TAG: u64 = 0x5A4E_0000_0000_0000;
+#[cfg(feature = "shm_demo")]
+const SHM_SHARE_TAG_MASK: u64 = 0xFFFF_0000_0000_0000;
+
+/// Task1 が segment に書く値
+#[cfg(feature = "shm_demo")]
+const SHM_DEMO_PATTERN: u64 = 0x5A4E_D00D_CAFE_0001;
+
+// Task1 の段階: 0 = create 前, 1 = create 待ち, 2 = map 待ち, 3 = reply 待ち, 4 = 完了
+#[cfg(feature = "shm_demo")]
+static TASK1_STAGE: AtomicU8 = AtomicU8::new(0);
+#[cfg(feature = "shm_demo")]
+static SHM_HANDLE: AtomicU64 = AtomicU64::new(0);
+// Task2 が map 待ちの msg の MR1（0 = 待っていない）
+#[cfg(feature = "shm_demo")]
+static TASK2_EXPECT: AtomicU64 = AtomicU64::new(0);
+
+/// user root の page にある u64 を guarded に読む / 書く（value が Some なら書く）
+#[cfg(feature = "shm_demo")]
+fn access_user_u64(ks: &KernelState, task_idx: usize, page: u64, value: Option<u64>) -> Option<u64> {
+    let as_idx = ks.tasks[task_idx].address_space_id.0;
+    let root = ks.address_spaces[as_idx].root_page_frame?;
+    let kernel_root = ks.address_spaces[KERNEL_ASID_INDEX].root_page_frame?;
+    let virt = Arch::USER_SPACE_BASE + VirtPage::from_index(page).start_address().0;
+
+    let res = match value {
+        Some(v) => Arch::guarded_user_rw_u64_in_root(root, kernel_root, virt as *mut u64, v),
+        None => Arch::guarded_user_read_u64_in_root(root, kernel_root, virt as *const u64),
+    };
+    match res {
+        Ok(v) => Some(v),
+        Err(pf) => {
+            crate::logging::error("shm_demo: #PF on shared page");
+            crate::logging::info_u64("addr", pf.addr);
+            None
+        }
+    }
+}
+
+#[cfg(feature = "shm_demo")]
+fn task1_step(ks: &mut KernelState, idx: usize) -> bool {
+    let stage = TASK1_STAGE.load(Ordering::Relaxed);
+    let ret = ks.take_unread_last_syscall_ret(idx);
+
+    match stage {
+        0 => {
+            crate::logging::info("shm_demo: Task1 creates segment");
+            ks.tasks[idx].pending_syscall = Some(Syscall::ShmCreate { pages: 1 });
+            TASK1_STAGE.store(1, Ordering::Relaxed);
+            true
+        }
+        1 => match ret {
+            Some(v) if (v & SHM_CREATE_OK_TAG_MASK) == SHM_CREATE_OK_TAG => {
+                let handle = v & !SHM_CREATE_OK_TAG_MASK;
+                SHM_HANDLE.store(handle, Ordering::Relaxed);
+                let page = VirtPage::from_index(SHM_DEMO_PAGE_TASK1);
+                ks.tasks[idx].pending_syscall = Some(Syscall::ShmMap { cap: CapIndex(handle as usize), page });
+                TASK1_STAGE.store(2, Ordering::Relaxed);
+                true
+            }
+            Some(v) => {
+                crate::logging::error("shm_demo: ShmCreate failed");
+                crate::logging::info_u64("ret", v);
+                TASK1_STAGE.store(4, Ordering::Relaxed);
+                false
+            }
+            None => true,
+        },
+        2 => match ret {
+            Some(SYSCALL_OK) => {
+                if access_user_u64(ks, idx, SHM_DEMO_PAGE_TASK1, Some(SHM_DEMO_PATTERN)).is_none() {
+                    TASK1_STAGE.store(4, Ordering::Relaxed);
+                    return false;
+                }
+                let handle = SHM_HANDLE.load(Ordering::Relaxed);
+                let msg = IpcMessage::from_words(&[SHM_SHARE_TAG | handle, SHM_DEMO_PATTERN])
+                    .unwrap_or(IpcMessage::word(SHM_SHARE_TAG | handle))
+                    .with_cap(CapIndex(handle as usize));
+                crate::logging::info("shm_demo: Task1 wrote pattern; sending shm handle");
+                crate::logging::info_u64("cap_index", handle);
+                ks.tasks[idx].pending_syscall = Some(Syscall::IpcSend { cap: IPC_DEMO_CAP0, msg, timeout: None });
+                TASK1_STAGE.store(3, Ordering::Relaxed);
+                true
+            }
+            Some(v) => {
+                crate::logging::error("shm_demo: ShmMap failed (Task1)");
+                crate::logging::info_u64("ret", v);
+                TASK1_STAGE.store(4, Ordering::Relaxed);
+                false
+            }
+            None => true,
+        },
+        3 => {
+            let Some(m) = ks.tasks[idx].last_reply.take() else {
+                return true;
+            };
+            if (m.mr0() >> 48) == 0xABCD && m.mr(1) == 1 {
+                crate::logging::info("shm_demo: shared OK (Task2 read the pattern)");
+            } else {
+                crate::logging::error("shm_demo: Task2 did not see the pattern");
+                crate::logging::info_u64("reply", m.mr0());
+                crate::logging::info_u64("matched", m.mr(1));
+            }
+            TASK1_STAGE.store(4, Ordering::Relaxed);
+            true
+        }
+        _ => false,
+    }
+}
+
+#[cfg(feature = "shm_demo")]
+fn task2_step(ks: &mut KernelState, idx: usize) -> bool {
+    let expect = TASK2_EXPECT.load(Ordering::Relaxed);
+    if expect != 0 {
+        let Some(ret) = ks.take_unread_last_syscall_ret(idx) else {
+            return true;
+        };
+        TASK2_EXPECT.store(0, Ordering::Relaxed);
+
+        let matched = if ret == SYSCALL_OK {
+            let got = access_user_u64(ks, idx, SHM_DEMO_PAGE_TASK2, None);
+            crate::logging::info("shm_demo: Task2 read shared page");
+            crate::logging::info_u64("value", got.unwrap_or(0));
+            got == Some(expect)
+        } else {
+            crate::logging::error("shm_demo: ShmMap failed (Task2)");
+            crate::logging::info_u64("ret", ret);
+            false
+        };
+
+        let tag = 0xABCD_0000_0000_0000u64 ^ (SHM_HANDLE.load(Ordering::Relaxed) & 0xFFFF);
+        let reply = IpcMessage::from_words(&[tag, matched as u64]).unwrap_or(IpcMessage::word(tag));
+        if let Some(reply_cap) = ks.tasks[idx].last_reply_cap.take() {
+            ks.tasks[idx].pending_syscall = Some(Syscall::IpcReply { reply_cap, msg: reply });
+        }
+        return true;
+    }
+
+    let Some(m) = ks.tasks[idx].last_msg else {
+        return false;
+    };
+    if (m.mr0() & SHM_SHARE_TAG_MASK) != SHM_SHARE_TAG {
+        return false;
+    }
+    ks.tasks[idx].last_msg = None;
+
+    let Some(slot) = m.cap_transfer() else {
+        crate::logging::error("shm_demo: msg arrived without the shm handle");
+        let reply = IpcMessage::from_words(&[0xABCD_0000_0000_0000, 0]).unwrap_or(IpcMessage::word(0));
+        if let Some(reply_cap) = ks.tasks[idx].last_reply_cap.take() {
+            ks.tasks[idx].pending_syscall = Some(Syscall::IpcReply { reply_cap, msg: reply });
+        }
+        return true;
+    };
+    crate::logging::info("shm_demo: Task2 got shm handle; mapping");
+    crate::logging::info_u64("cap_index", slot.0 as u64);
+    TASK2_EXPECT.store(m.mr(1), Ordering::Relaxed);
+    let page = VirtPage::from_index(SHM_DEMO_PAGE_TASK2);
+    ks.tasks[idx].pending_syscall = Some(Syscall::ShmMap { cap: slot, page });
+    true
+}
+
+/// Task1 / Task2 の user step を乗っ取る（shm_demo のときだけ）
+pub fn on_user_step(ks: &mut KernelState, task_idx: usize) -> bool {
+    #[cfg(feature = "shm_demo")]
+    {
+        if ks.tasks[task_idx].state == TaskState::Dead {
+            return false;
+        }
+        match task_idx {
+            TASK1_INDEX => task1_step(ks, task_idx),
+            TASK2_INDEX => task2_step(ks, task_idx),
+            _ => false,
+        }
+    }
+
+    #[cfg(not(feature = "shm_demo"))]
+    {
+        let _ = (ks, task_idx);
+        false
+    }
+}
+
+/// mem_demo を止めるか（ShmMap の last_syscall_ret を混線させない）
+pub fn suppress_mem_demo() -> bool {
+    cfg!(feature = "shm_demo")
+}
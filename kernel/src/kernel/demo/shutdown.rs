@@ -0,0 +1,43 @@
+// kernel/src/kernel/demo/shutdown.rs
+//
+// 役割:
+// - shutdown_demo: Task0（kernel task）が SHUTDOWN_DEMO_TICK で Shutdown { code: 0 } を出し、run を電源断で終える。
+//
+// 方針:
+// - 通常の syscall 経路（pending_syscall -> handle_syscall）をそのまま通す
+// - Task0 はふだん syscall を出さないので、他の demo（Task1 / Task2 を使う）とぶつからない
+// - SHUTDOWN_DEMO_TICK より前に run が終わる（max_ticks が小さい）ときは何もしない
+//   （tick ループの終わりの dump / verdict はいつもどおり出る）
+
+use super::super::KernelState;
+
+#[cfg(feature = "shutdown_demo")]
+use super::super::{Syscall, TASK0_INDEX};
+#[cfg(feature = "shutdown_demo")]
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Task0 が Shutdown を出す tick
+#[cfg(feature = "shutdown_demo")]
+const SHUTDOWN_DEMO_TICK: u64 = 60;
+
+#[cfg(feature = "shutdown_demo")]
+static ISSUED: AtomicBool = AtomicBool::new(false);
+
+/// Task0 の user step で Shutdown を積む（shutdown_demo のときだけ）
+pub fn on_user_step(ks: &mut KernelState, task_idx: usize) -> bool {
+    #[cfg(feature = "shutdown_demo")]
+    {
+        if task_idx != TASK0_INDEX || ks.tick_count < SHUTDOWN_DEMO_TICK || ISSUED.swap(true, Ordering::Relaxed) {
+            return false;
+        }
+        crate::logging::info("shutdown_demo: Task0 requests shutdown");
+        ks.tasks[task_idx].pending_syscall = Some(Syscall::Shutdown { code: 0 });
+        true
+    }
+
+    #[cfg(not(feature = "shutdown_demo"))]
+    {
+        let _ = (ks, task_idx);
+        false
+    }
+}
@@ -0,0 +1,151 @@
+// kernel/src/kernel/demo/stress_ipc.rs
+//
+// 役割:
+// - stress_ipc: 多数の endpoint をまたいで send/recv/reply を連続発行する ping storm。
+// - N tick 回した後に集計（往復数・エラー数・endpoint 使用数・event_log の上限）を出す。
+//
+// 方針:
+// - 通常の syscall 経路（pending_syscall -> handle_syscall）をそのまま通す
+//   （ストレス専用の近道を作ると、検証したい経路を踏まなくなる）
+// - Task1 = client, Task2 = server。cursor は client/server で別々に持ち、
+//   client は往復成立で、server は reply 後に進める（両者が同じ endpoint 列を巡回する）。
+//   共有 cursor にすると、reply 直後の server recv が古い endpoint に残ってデッドロックする。
+// - 進行状態は demo 側の static で持つ（KernelState 本体を汚さない）
+// - invariant は tick ごとの debug_check_invariants に任せる（違反は INVARIANT VIOLATION で出る）
+//
+// 制限:
+// - task / endpoint は固定配列のまま（STATIC_ENDPOINTS を feature で拡大するだけ）。
+//   動的生成が入ったら client/server ペア自体を増やす。
+
+use super::super::KernelState;
+
+#[cfg(feature = "stress_ipc")]
+use super::super::{
+    CapIndex, IpcMessage, Syscall, TaskState, STATIC_ENDPOINTS, TASK1_INDEX, TASK2_INDEX,
+};
+
+#[cfg(feature = "stress_ipc")]
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// stress_ipc で回す tick 数（通常は entry.rs の 120）
+#[cfg(feature = "stress_ipc")]
+pub const STRESS_IPC_TICKS: u64 = 2000;
+
+#[cfg(feature = "stress_ipc")]
+static CLIENT_CURSOR: AtomicU64 = AtomicU64::new(0);
+#[cfg(feature = "stress_ipc")]
+static SERVER_CURSOR: AtomicU64 = AtomicU64::new(0);
+#[cfg(feature = "stress_ipc")]
+static SENDS: AtomicU64 = AtomicU64::new(0);
+#[cfg(feature = "stress_ipc")]
+static ROUND_TRIPS: AtomicU64 = AtomicU64::new(0);
+#[cfg(feature = "stress_ipc")]
+static ERRORS: AtomicU64 = AtomicU64::new(0);
+
+/// cursor 位置の endpoint を指す cap（初期配置で slot i = EndpointId(i)）
+#[cfg(feature = "stress_ipc")]
+fn cap_at(cursor: &AtomicU64) -> CapIndex {
+    CapIndex((cursor.load(Ordering::Relaxed) as usize) % STATIC_ENDPOINTS)
+}
+
+/// user_step の代わりに syscall を積む
+/// - 積んだ（= 通常の user_program を使わない）なら true
+pub fn on_user_step(ks: &mut KernelState, task_idx: usize) -> bool {
+    #[cfg(feature = "stress_ipc")]
+    {
+        if task_idx != TASK1_INDEX && task_idx != TASK2_INDEX {
+            return false;
+        }
+        if ks.tasks[task_idx].state == TaskState::Dead {
+            return true;
+        }
+
+        // client: 往復が成立したら次の endpoint へ（エラーなら同じ endpoint で再送）
+        if task_idx == TASK1_INDEX {
+            if let Some(v) = ks.tasks[task_idx].last_reply.take().map(|m| m.mr0()) {
+                if (v >> 48) == 0xABCD {
+                    ROUND_TRIPS.fetch_add(1, Ordering::Relaxed);
+                    CLIENT_CURSOR.fetch_add(1, Ordering::Relaxed);
+                } else {
+                    // IPC_ERR_* 等
+                    ERRORS.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+
+            let cap = cap_at(&CLIENT_CURSOR);
+            let seq = SENDS.fetch_add(1, Ordering::Relaxed);
+            let msg = IpcMessage::word(0x5750_0000_0000_0000u64 ^ (seq & 0xFFFF_FFFF));
+            ks.tasks[task_idx].pending_syscall = Some(Syscall::IpcSend { cap, msg, timeout: None });
+            return true;
+        }
+
+        // server: 受け取ったら reply して cursor を進める、無ければ cursor の endpoint で recv
+        let cap = cap_at(&SERVER_CURSOR);
+        if let Some(msg) = ks.tasks[task_idx].last_msg.take() {
+            let reply = IpcMessage::word(0xABCD_0000_0000_0000u64 ^ (msg.mr0() & 0xFFFF));
+            if let Some(reply_cap) = ks.tasks[task_idx].last_reply_cap.take() {
+                ks.tasks[task_idx].pending_syscall = Some(Syscall::IpcReply { reply_cap, msg: reply });
+            }
+            SERVER_CURSOR.fetch_add(1, Ordering::Relaxed);
+            return true;
+        }
+
+        // recv_waiter 重複などのエラーは捨てて続行（集計のみ）
+        if ks.tasks[task_idx].last_reply.take().is_some() {
+            ERRORS.fetch_add(1, Ordering::Relaxed);
+        }
+
+        ks.tasks[task_idx].pending_syscall = Some(Syscall::IpcRecv { cap });
+        return true;
+    }
+
+    #[cfg(not(feature = "stress_ipc"))]
+    {
+        let _ = (ks, task_idx);
+        false
+    }
+}
+
+/// tick ループ終了後の集計
+/// run の verdict 用: 1 往復以上できたか（届かなかった milestone の名前）
+pub fn missed_milestone() -> Option<&'static str> {
+    #[cfg(feature = "stress_ipc")]
+    if ROUND_TRIPS.load(Ordering::Relaxed) == 0 {
+        return Some("stress_round_trips");
+    }
+    None
+}
+
+pub fn report(ks: &KernelState) {
+    #[cfg(feature = "stress_ipc")]
+    {
+        let round_trips = ROUND_TRIPS.load(Ordering::Relaxed);
+        let ticks = ks.tick_count;
+
+        crate::logging::info("=== Stress IPC Report ===");
+        crate::logging::info_u64("stress_ticks", ticks);
+        crate::logging::info_u64("stress_endpoints", STATIC_ENDPOINTS as u64);
+        crate::logging::info_u64("stress_sends", SENDS.load(Ordering::Relaxed));
+        crate::logging::info_u64("stress_round_trips", round_trips);
+        crate::logging::info_u64("stress_errors", ERRORS.load(Ordering::Relaxed));
+        crate::logging::info_u64(
+            "stress_endpoints_visited",
+            core::cmp::min(CLIENT_CURSOR.load(Ordering::Relaxed), STATIC_ENDPOINTS as u64),
+        );
+        if ticks != 0 {
+            crate::logging::info_u64("stress_round_trips_per_1000_ticks", round_trips * 1000 / ticks);
+        }
+        // event_log のサンプリング条件（解析側の補正用）
+        crate::logging::info_u64("stress_ipc_event_sample_every", super::super::IPC_EVENT_SAMPLE_EVERY);
+        crate::logging::info_u64("stress_ipc_events_seen", ks.counters.ipc_events_seen);
+        crate::logging::info_u64("stress_ipc_events_skipped", ks.counters.ipc_events_skipped);
+        // 上限付き（固定配列）であることの確認用
+        crate::logging::info_u64("stress_event_log_len", ks.event_log_len as u64);
+        crate::logging::info_u64("stress_event_log_cap", super::super::EVENT_LOG_CAP as u64);
+        crate::logging::info_u64("stress_events_overwritten", ks.counters.events_overwritten);
+        crate::logging::info("=== End of Stress IPC Report ===");
+    }
+
+    #[cfg(not(feature = "stress_ipc"))]
+    let _ = ks;
+}
@@ -0,0 +1,260 @@
+// kernel/src/kernel/demo/suspend_resume.rs
+//
+// 役割:
+// - suspend_demo: Task0（kernel task）が ep0 で recv 待ちの Task2 を TaskSuspend し、その間の Task1 の send が
+//   Task2 に届かず send_queue で待つこと、TaskResume で Task2 がそれを受け取ることを確かめるデモ（kernel/suspend.rs）。
+//
+// 手順:
+// - Task2: ep0 で IpcRecv → msg が来たら IpcReply [SUSPEND_DEMO_TAG]
+// - Task0: Task2 が Blocked(IpcRecv{ep0}) になったら TaskSuspend { Task2 }
+//   → Task1 が Blocked(IpcSend{ep0}) になったら、Task2 が Suspended のまま msg を持たず、Task1 が ep0 の send_queue に
+//     居ることを見て TaskResume { Task2 }
+// - Task1: Task2 が止まるまで Sleep で待つ → IpcSend [SUSPEND_DEMO_TAG]
+//   → reply が来れば "suspend_demo: send deferred while suspended; delivered on resume"
+//
+// 方針:
+// - 通常の syscall 経路（pending_syscall -> handle_syscall）をそのまま通す
+// - 進行状態は demo 側の static で持つ（KernelState 本体を汚さない）
+// - mem_demo は止める（last_syscall_ret を TaskSuspend / TaskResume の結果と混線させない）
+// - Task0 / Task1 / Task2 / ep0 を使う demo・service とは併用しない（compile_error）
+
+#[cfg(all(
+    feature = "suspend_demo",
+    any(
+        feature = "timer_service",
+        feature = "virtio_net",
+        feature = "stress_ipc",
+        feature = "shm_demo",
+        feature = "fs_demo",
+        feature = "abi_selftest",
+        feature = "task_lifecycle_demo",
+        feature = "ring3_tasks",
+        feature = "fault_handler_demo",
+        feature = "grant_demo",
+        feature = "revoke_demo",
+        feature = "shutdown_demo"
+    )
+))]
+compile_error!("suspend_demo drives Task0 / Task1 / Task2 over ep0; it cannot be combined with other demos that use them");
+
+use super::super::KernelState;
+
+#[cfg(feature = "suspend_demo")]
+use super::super::{
+    abi::SYSCALL_OK, cspace::TASK_CAP_BASE, BlockedReason, CapIndex, IpcMessage, Syscall, TaskState, IPC_DEMO_CAP0,
+    IPC_DEMO_EP0, TASK0_INDEX, TASK1_INDEX, TASK2_INDEX,
+};
+
+#[cfg(feature = "suspend_demo")]
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+/// Task1 → Task2 の msg と reply の MR0
+#[cfg(feature = "suspend_demo")]
+const SUSPEND_DEMO_TAG: u64 = 0x5B5B_0000_0000_0001;
+
+/// Task2 の task handle（初期配置で TASK_CAP_BASE + task index。kernel task は Control を持つ）
+#[cfg(feature = "suspend_demo")]
+const SUSPEND_DEMO_TASK2_CAP: CapIndex = CapIndex(TASK_CAP_BASE + TASK2_INDEX);
+
+/// Task2 が止まるまで Task1 が眠る tick 数
+#[cfg(feature = "suspend_demo")]
+const SUSPEND_DEMO_POLL_TICKS: u64 = 2;
+
+// Task0 の段階: 0 = Task2 の recv 待ち, 1 = suspend 待ち, 2 = Task1 の send 待ち, 3 = resume 待ち, 4 = 終了
+#[cfg(feature = "suspend_demo")]
+static TASK0_STAGE: AtomicU8 = AtomicU8::new(0);
+// Task1 の段階: 0 = send 前, 1 = reply 待ち, 2 = 終了
+#[cfg(feature = "suspend_demo")]
+static TASK1_STAGE: AtomicU8 = AtomicU8::new(0);
+// Task2 の段階: 0 = recv 前, 1 = msg 待ち, 2 = 終了
+#[cfg(feature = "suspend_demo")]
+static TASK2_STAGE: AtomicU8 = AtomicU8::new(0);
+/// Task0 が Task2 を止めた
+#[cfg(feature = "suspend_demo")]
+static SUSPENDED: AtomicBool = AtomicBool::new(false);
+/// 止まっている間、Task1 の msg が Task2 に届かず send_queue で待っていた
+#[cfg(feature = "suspend_demo")]
+static DEFERRED: AtomicBool = AtomicBool::new(false);
+#[cfg(feature = "suspend_demo")]
+static SUSPEND_OK: AtomicBool = AtomicBool::new(false);
+
+#[cfg(feature = "suspend_demo")]
+fn task0_step(ks: &mut KernelState, idx: usize) -> bool {
+    let stage = TASK0_STAGE.load(Ordering::Relaxed);
+
+    match stage {
+        0 => {
+            let t2 = &ks.tasks[TASK2_INDEX];
+            let recv_waiting = t2.state == TaskState::Blocked
+                && t2.blocked_reason == Some(BlockedReason::IpcRecv { ep: IPC_DEMO_EP0 });
+            if recv_waiting {
+                crate::logging::info("suspend_demo: Task0 suspends Task2 while it waits in recv");
+                ks.tasks[idx].pending_syscall = Some(Syscall::TaskSuspend { cap: SUSPEND_DEMO_TASK2_CAP });
+                TASK0_STAGE.store(1, Ordering::Relaxed);
+            }
+            true
+        }
+        1 | 3 => match ks.take_unread_last_syscall_ret(idx) {
+            Some(SYSCALL_OK) => {
+                if stage == 1 {
+                    SUSPENDED.store(true, Ordering::Relaxed);
+                }
+                TASK0_STAGE.store(stage + 1, Ordering::Relaxed);
+                true
+            }
+            Some(v) => {
+                crate::logging::error("suspend_demo: TaskSuspend / TaskResume failed");
+                crate::logging::info_u64("ret", v);
+                TASK0_STAGE.store(4, Ordering::Relaxed);
+                false
+            }
+            None => true,
+        },
+        2 => {
+            let t1 = &ks.tasks[TASK1_INDEX];
+            let sending = t1.state == TaskState::Blocked
+                && t1.blocked_reason == Some(BlockedReason::IpcSend { ep: IPC_DEMO_EP0 });
+            if !sending {
+                return true;
+            }
+
+            let t2 = &ks.tasks[TASK2_INDEX];
+            let held = t2.state == TaskState::Suspended && t2.last_msg.is_none();
+            let queued = ks.endpoints[IPC_DEMO_EP0.0].send_queue_contains(TASK1_INDEX);
+            if held && queued {
+                DEFERRED.store(true, Ordering::Relaxed);
+                crate::logging::info("suspend_demo: Task1 queued behind suspended Task2; resuming");
+            } else {
+                crate::logging::error("suspend_demo: msg was not held back while Task2 was suspended");
+                crate::logging::info_u64("held", held as u64);
+                crate::logging::info_u64("queued", queued as u64);
+            }
+            ks.tasks[idx].pending_syscall = Some(Syscall::TaskResume { cap: SUSPEND_DEMO_TASK2_CAP });
+            TASK0_STAGE.store(3, Ordering::Relaxed);
+            true
+        }
+        _ => false,
+    }
+}
+
+#[cfg(feature = "suspend_demo")]
+fn task1_step(ks: &mut KernelState, idx: usize) -> bool {
+    let stage = TASK1_STAGE.load(Ordering::Relaxed);
+
+    match stage {
+        0 => {
+            if !SUSPENDED.load(Ordering::Relaxed) {
+                ks.tasks[idx].pending_syscall = Some(Syscall::Sleep { ticks: SUSPEND_DEMO_POLL_TICKS });
+                return true;
+            }
+            crate::logging::info("suspend_demo: Task1 sends to suspended Task2");
+            let msg = IpcMessage::word(SUSPEND_DEMO_TAG);
+            ks.tasks[idx].pending_syscall = Some(Syscall::IpcSend { cap: IPC_DEMO_CAP0, msg, timeout: None });
+            TASK1_STAGE.store(1, Ordering::Relaxed);
+            true
+        }
+        1 => {
+            let Some(m) = ks.tasks[idx].last_reply.take() else {
+                return true;
+            };
+            TASK1_STAGE.store(2, Ordering::Relaxed);
+
+            if m.mr0() == SUSPEND_DEMO_TAG && DEFERRED.load(Ordering::Relaxed) {
+                SUSPEND_OK.store(true, Ordering::Relaxed);
+                crate::logging::info("suspend_demo: send deferred while suspended; delivered on resume");
+            } else {
+                crate::logging::error("suspend_demo: suspend / resume round trip failed");
+                crate::logging::info_u64("reply", m.mr0());
+                crate::logging::info_u64("deferred", DEFERRED.load(Ordering::Relaxed) as u64);
+            }
+            true
+        }
+        _ => false,
+    }
+}
+
+#[cfg(feature = "suspend_demo")]
+fn task2_step(ks: &mut KernelState, idx: usize) -> bool {
+    let stage = TASK2_STAGE.load(Ordering::Relaxed);
+
+    match stage {
+        0 => {
+            ks.tasks[idx].pending_syscall = Some(Syscall::IpcRecv { cap: IPC_DEMO_CAP0 });
+            TASK2_STAGE.store(1, Ordering::Relaxed);
+            true
+        }
+        1 => {
+            if let Some(m) = ks.tasks[idx].last_reply.take() {
+                crate::logging::error("suspend_demo: recv on ep0 failed");
+                crate::logging::info_u64("reply", m.mr0());
+                TASK2_STAGE.store(2, Ordering::Relaxed);
+                return false;
+            }
+            let Some(msg) = ks.tasks[idx].last_msg.take() else {
+                return true;
+            };
+            TASK2_STAGE.store(2, Ordering::Relaxed);
+            crate::logging::info("suspend_demo: Task2 got the msg after resume");
+            crate::logging::info_u64("msg", msg.mr0());
+
+            let reply = IpcMessage::word(SUSPEND_DEMO_TAG);
+            if let Some(reply_cap) = ks.tasks[idx].last_reply_cap.take() {
+                ks.tasks[idx].pending_syscall = Some(Syscall::IpcReply { reply_cap, msg: reply });
+            }
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Task0 / Task1 / Task2 の user step を乗っ取る（suspend_demo のときだけ）
+pub fn on_user_step(ks: &mut KernelState, task_idx: usize) -> bool {
+    #[cfg(feature = "suspend_demo")]
+    {
+        if ks.tasks[task_idx].state == TaskState::Dead {
+            return false;
+        }
+        match task_idx {
+            TASK0_INDEX => task0_step(ks, task_idx),
+            TASK1_INDEX => task1_step(ks, task_idx),
+            TASK2_INDEX => task2_step(ks, task_idx),
+            _ => false,
+        }
+    }
+
+    #[cfg(not(feature = "suspend_demo"))]
+    {
+        let _ = (ks, task_idx);
+        false
+    }
+}
+
+/// mem_demo を止めるか（TaskSuspend / TaskResume の last_syscall_ret を混線させない）
+pub fn suppress_mem_demo() -> bool {
+    cfg!(feature = "suspend_demo")
+}
+
+/// run の verdict 用: suspend 中の send の保留と resume での受け取りを確かめられなかったか（届かなかった milestone の名前）
+pub fn missed_milestone() -> Option<&'static str> {
+    #[cfg(feature = "suspend_demo")]
+    if !SUSPEND_OK.load(Ordering::Relaxed) {
+        return Some("suspend_deferred_recv");
+    }
+
+    None
+}
+
+/// tick ループ終了後の集計
+pub fn report(ks: &KernelState) {
+    #[cfg(feature = "suspend_demo")]
+    {
+        crate::logging::info("=== Suspend Report ===");
+        crate::logging::info_u64("tasks_suspended", ks.counters.tasks_suspended);
+        crate::logging::info_u64("tasks_resumed", ks.counters.tasks_resumed);
+        crate::logging::info_u64("suspend_recv_deferred", ks.counters.suspend_recv_deferred);
+        crate::logging::info_u64("suspend_demo_ok", SUSPEND_OK.load(Ordering::Relaxed) as u64);
+    }
+
+    #[cfg(not(feature = "suspend_demo"))]
+    let _ = ks;
+}
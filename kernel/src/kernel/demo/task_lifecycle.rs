@@ -0,0 +1,120 @@
+// kernel/src/kernel/demo/task_lifecycle.rs
+//
+// 役割:
+// - task_lifecycle_demo: TaskExit で退役した slot を TaskCreate で再利用するデモ。
+//
+// 手順:
+// - Task1（TaskId 2）: TASK_EXIT_AT_TICK 以降の最初の user step で TaskExit
+// - Task0（kernel）: slot 1 が Dead になったら TaskCreate（priority 3）を 1 回だけ発行
+//   → slot 1 に新しい TaskId（4）の task が入り、以後は通常の client として動く
+// - 結果（last_syscall_ret）は次の Task0 の step で読み、"task_lifecycle: created" を出す
+//
+// 方針:
+// - 通常の syscall 経路（pending_syscall -> handle_syscall）をそのまま通す
+// - 進行状態は demo 側の static で持つ（KernelState 本体を汚さない）
+
+use super::super::KernelState;
+
+#[cfg(feature = "task_lifecycle_demo")]
+use super::super::{
+    abi::{TASK_CREATE_ID_MASK, TASK_CREATE_OK_TAG, TASK_CREATE_OK_TAG_MASK},
+    Syscall, TaskState, TASK0_INDEX, TASK1_ID, TASK1_INDEX,
+};
+
+#[cfg(feature = "task_lifecycle_demo")]
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+/// Task1 が退役する tick（IPC デモが一巡した後）
+#[cfg(feature = "task_lifecycle_demo")]
+const TASK_EXIT_AT_TICK: u64 = 20;
+
+/// 新しい task に渡す entry_hint（記録のみ）
+#[cfg(feature = "task_lifecycle_demo")]
+const DEMO_ENTRY_CLIENT: u64 = 1;
+
+#[cfg(feature = "task_lifecycle_demo")]
+static EXIT_SENT: AtomicBool = AtomicBool::new(false);
+#[cfg(feature = "task_lifecycle_demo")]
+static CREATE_SENT: AtomicBool = AtomicBool::new(false);
+#[cfg(feature = "task_lifecycle_demo")]
+static CREATED_TASK_ID: AtomicU64 = AtomicU64::new(0);
+
+/// user_step の代わりに syscall を積む（Task1 の TaskExit / Task0 の TaskCreate）
+pub fn on_user_step(ks: &mut KernelState, task_idx: usize) -> bool {
+    #[cfg(feature = "task_lifecycle_demo")]
+    {
+        if task_idx == TASK1_INDEX
+            && ks.tasks[task_idx].id == TASK1_ID
+            && ks.tick_count >= TASK_EXIT_AT_TICK
+            && !EXIT_SENT.swap(true, Ordering::Relaxed)
+        {
+            crate::logging::info("task_lifecycle: Task1 exits");
+            ks.tasks[task_idx].pending_syscall = Some(Syscall::TaskExit);
+            return true;
+        }
+
+        if task_idx != TASK0_INDEX {
+            return false;
+        }
+
+        if CREATE_SENT.load(Ordering::Relaxed) {
+            if CREATED_TASK_ID.load(Ordering::Relaxed) == 0 {
+                if let Some(r) = ks.take_unread_last_syscall_ret(task_idx) {
+                    if (r & TASK_CREATE_OK_TAG_MASK) == TASK_CREATE_OK_TAG {
+                        let id = r & TASK_CREATE_ID_MASK;
+                        CREATED_TASK_ID.store(id, Ordering::Relaxed);
+                        crate::logging::info("task_lifecycle: created");
+                        crate::logging::info_u64("task_id", id);
+                    } else {
+                        crate::logging::error("task_lifecycle: create failed");
+                        crate::logging::info_u64("ret", r);
+                    }
+                }
+            }
+            return false;
+        }
+
+        if EXIT_SENT.load(Ordering::Relaxed) && ks.tasks[TASK1_INDEX].state == TaskState::Dead {
+            CREATE_SENT.store(true, Ordering::Relaxed);
+            crate::logging::info("task_lifecycle: Task0 creates a task in the free slot");
+            ks.tasks[task_idx].pending_syscall =
+                Some(Syscall::TaskCreate { entry_hint: DEMO_ENTRY_CLIENT, priority: 3 });
+            return true;
+        }
+
+        return false;
+    }
+
+    #[cfg(not(feature = "task_lifecycle_demo"))]
+    {
+        let _ = (ks, task_idx);
+        false
+    }
+}
+
+/// tick ループ終了後の集計
+/// run の verdict 用: task を作って終わらせられたか（届かなかった milestone の名前）
+pub fn missed_milestone(ks: &KernelState) -> Option<&'static str> {
+    #[cfg(feature = "task_lifecycle_demo")]
+    if ks.counters.task_created == 0 || ks.counters.task_exited == 0 {
+        return Some("task_lifecycle_create_exit");
+    }
+
+    #[cfg(not(feature = "task_lifecycle_demo"))]
+    let _ = ks;
+    None
+}
+
+pub fn report(ks: &KernelState) {
+    #[cfg(feature = "task_lifecycle_demo")]
+    {
+        crate::logging::info("=== Task Lifecycle Report ===");
+        crate::logging::info_u64("task_created", ks.counters.task_created);
+        crate::logging::info_u64("task_exited", ks.counters.task_exited);
+        crate::logging::info_u64("created_task_id", CREATED_TASK_ID.load(Ordering::Relaxed));
+        crate::logging::info("=== End of Task Lifecycle Report ===");
+    }
+
+    #[cfg(not(feature = "task_lifecycle_demo"))]
+    let _ = ks;
+}
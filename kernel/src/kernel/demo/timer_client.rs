@@ -0,0 +1,113 @@
+// kernel/src/kernel/demo/timer_client.rs
+//
+// 役割:
+// - timer_service: Task1 を timer_service の client にして、周期通知を受け取り続けるデモ。
+//
+// 手順:
+// - Task1: TIMER_SERVICE_EP に登録（notify_ep = ep2, period = TIMER_CLIENT_PERIOD）
+//   → 以後 ep2 で recv を繰り返し、通知ごとに "timer_client: tick" を出す
+// - Task2 は通常どおり ep0 の server（Task1 が kick しないので recv 待ちのまま）
+//
+// 方針:
+// - 通常の syscall 経路（IpcSend / IpcRecv）をそのまま通す
+// - 進行状態は demo 側の static で持つ（KernelState 本体を汚さない）
+
+use super::super::KernelState;
+
+#[cfg(feature = "timer_service")]
+use super::super::{
+    abi::{TIMER_SVC_OK, TIMER_TICK_TAG, TIMER_TICK_TAG_MASK},
+    timer_service::{encode_register, TIMER_SERVICE_EP},
+    CapIndex, EndpointId, IpcMessage, Syscall, TaskState, TASK1_INDEX,
+};
+
+#[cfg(feature = "timer_service")]
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+/// client の通知受け口
+#[cfg(feature = "timer_service")]
+const TIMER_CLIENT_EP: EndpointId = EndpointId(2);
+
+/// service / 通知受け口を指す cap（初期配置で slot i = EndpointId(i)）
+#[cfg(feature = "timer_service")]
+const TIMER_SERVICE_CAP: CapIndex = CapIndex(TIMER_SERVICE_EP.0);
+#[cfg(feature = "timer_service")]
+const TIMER_CLIENT_CAP: CapIndex = CapIndex(TIMER_CLIENT_EP.0);
+
+/// 通知周期（time_ticks 単位）
+#[cfg(feature = "timer_service")]
+const TIMER_CLIENT_PERIOD: u64 = 3;
+
+#[cfg(feature = "timer_service")]
+static REGISTER_SENT: AtomicBool = AtomicBool::new(false);
+#[cfg(feature = "timer_service")]
+static TICKS_RECEIVED: AtomicU64 = AtomicU64::new(0);
+
+/// user_step の代わりに syscall を積む（Task1 のみ）
+pub fn on_user_step(ks: &mut KernelState, task_idx: usize) -> bool {
+    #[cfg(feature = "timer_service")]
+    {
+        if task_idx != TASK1_INDEX {
+            return false;
+        }
+        if ks.tasks[task_idx].state == TaskState::Dead {
+            return true;
+        }
+
+        if !REGISTER_SENT.swap(true, Ordering::Relaxed) {
+            let msg = IpcMessage::word(encode_register(TIMER_CLIENT_EP, TIMER_CLIENT_PERIOD));
+            ks.tasks[task_idx].pending_syscall = Some(Syscall::IpcSend { cap: TIMER_SERVICE_CAP, msg, timeout: None });
+            return true;
+        }
+
+        // 登録の reply（service は即 reply する）
+        if let Some(r) = ks.tasks[task_idx].last_reply.take().map(|m| m.mr0()) {
+            if r == TIMER_SVC_OK {
+                crate::logging::info("timer_client: registered");
+            } else {
+                crate::logging::error("timer_client: register failed");
+                crate::logging::info_u64("ret", r);
+            }
+        }
+
+        if let Some(m) = ks.tasks[task_idx].last_msg.take() {
+            if (m.mr0() & TIMER_TICK_TAG_MASK) == TIMER_TICK_TAG {
+                let n = TICKS_RECEIVED.fetch_add(1, Ordering::Relaxed) + 1;
+                crate::logging::info("timer_client: tick");
+                crate::logging::info_u64("seq", m.mr0() & !TIMER_TICK_TAG_MASK);
+                crate::logging::info_u64("missed", m.mr(1));
+                crate::logging::info_u64("received", n);
+            }
+        }
+
+        ks.tasks[task_idx].pending_syscall = Some(Syscall::IpcRecv { cap: TIMER_CLIENT_CAP });
+        return true;
+    }
+
+    #[cfg(not(feature = "timer_service"))]
+    {
+        let _ = (ks, task_idx);
+        false
+    }
+}
+
+/// tick ループ終了後の集計
+/// run の verdict 用: timer service から tick 通知を受け取れたか（届かなかった milestone の名前）
+pub fn missed_milestone() -> Option<&'static str> {
+    #[cfg(feature = "timer_service")]
+    if TICKS_RECEIVED.load(Ordering::Relaxed) == 0 {
+        return Some("timer_client_ticks_received");
+    }
+    None
+}
+
+pub fn report(ks: &KernelState) {
+    #[cfg(feature = "timer_service")]
+    {
+        ks.timer_service_report();
+        crate::logging::info_u64("timer_client_ticks_received", TICKS_RECEIVED.load(Ordering::Relaxed));
+    }
+
+    #[cfg(not(feature = "timer_service"))]
+    let _ = ks;
+}
@@ -0,0 +1,340 @@
+// kernel/src/kernel/derivation.rs
+//
+// 役割:
+// - derivation tree: cap transfer（cspace.rs）と page grant（grant.rs）で「どの object からどの object が派生したか」を
+//   固定長の表（MAX_DERIVATIONS 本の辺）で覚える。
+// - Revoke syscall: 呼んだ task の object（cap か page）から推移的に派生したものを、受け取った全 task から取り上げる。
+//
+// object（DerivObject）:
+// - Cap  { task, cap }:         task の cap table の slot
+// - Page { task, page, frame }: task の page と、そこに map されているフレーム（grant の連鎖では全部同じフレーム）
+//
+// 流れ:
+// - 記録: deliver で cap / grant を渡せたら 1 本足す（親 = 送信側の object、子 = 受信側の slot / window）
+//   * 表が満杯なら渡す前に分かるので、cap / grant だけ落として msg は届ける（derivation_table_full）
+// - Revoke { cap_or_page }: 子孫を幅優先で集め、辺を全部外してから object を消す
+//   * cap は slot を空にする。page は grant を外す（GrantRevoked の reason = GRANT_REVOKE_REVOKED）
+//   * 取り上げた 1 つごとに DerivationRevoked（depth = 呼んだ task の object から何段目か）
+//   * 呼んだ task の object 自体は残る（子孫だけを消す）
+// - Revoke 以外で object が消えたとき（reply での grant の取り消し、EndpointDelete、task の死）:
+//   * その object の子は、object の親に付け替える（親が無ければ根になる）。連鎖の途中が消えても取り上げ漏れが出ない
+//
+// 方針:
+// - 表は固定長。子は高々 1 本の辺にしか現れない（tree）。深さは辺の数で抑えられる
+// - 初期配置の cap / EndpointCreate の cap / 自分で map した page は根（辺に現れない限り記録しない）
+// - 戻り値（last_syscall_ret）: SYSCALL_OK / SYSCALL_ERR_BAD_CAP（空の slot）/ SYSCALL_ERR_NOT_MAPPED（page が無い）/
+//   SYSCALL_ERR_FORBIDDEN（kernel task の page）
+//
+// やらないこと:
+// - 呼んだ task の object 自体の削除（PageUnmap / EndpointDelete がある）
+// - 派生の深さ・本数の task ごとの上限（表全体の MAX_DERIVATIONS だけ）
+
+use super::abi::{
+    REVOKE_KIND_CAP, REVOKE_KIND_PAGE, SYSCALL_ERR_BAD_CAP, SYSCALL_ERR_FORBIDDEN, SYSCALL_ERR_NOT_MAPPED, SYSCALL_OK,
+};
+use super::cspace::{CapIndex, KernelObject};
+use super::{InvariantId, KernelState, LogEvent, TaskState};
+use crate::mem::addr::{PhysFrame, VirtPage};
+use spec_macros::spec;
+
+const LOG: crate::logging::Subsystem = crate::logging::Subsystem::Ipc;
+
+/// 表に持てる辺の数
+pub const MAX_DERIVATIONS: usize = 32;
+
+/// Revoke の対象（呼んだ task の cap index か page）
+#[derive(Clone, Copy)]
+pub enum RevokeTarget {
+    Cap(CapIndex),
+    Page(VirtPage),
+}
+
+/// 派生の辺の端
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(super) enum DerivObject {
+    Cap { task: usize, cap: CapIndex },
+    Page { task: usize, page: VirtPage, frame: PhysFrame },
+}
+
+impl DerivObject {
+    fn task(self) -> usize {
+        match self {
+            DerivObject::Cap { task, .. } | DerivObject::Page { task, .. } => task,
+        }
+    }
+
+    /// DerivationRevoked の kind / object
+    fn kind_and_index(self) -> (u64, u64) {
+        match self {
+            DerivObject::Cap { cap, .. } => (REVOKE_KIND_CAP, cap.0 as u64),
+            DerivObject::Page { page, .. } => (REVOKE_KIND_PAGE, page.number),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct DerivEdge {
+    parent: DerivObject,
+    child: DerivObject,
+}
+
+#[derive(Clone, Copy)]
+pub struct Derivations {
+    edges: [Option<DerivEdge>; MAX_DERIVATIONS],
+}
+
+impl Derivations {
+    pub const fn new() -> Self {
+        Derivations { edges: [None; MAX_DERIVATIONS] }
+    }
+
+    fn len(&self) -> usize {
+        self.edges.iter().flatten().count()
+    }
+
+    fn parent_of(&self, obj: DerivObject) -> Option<DerivObject> {
+        self.edges.iter().flatten().find(|e| e.child == obj).map(|e| e.parent)
+    }
+}
+
+impl KernelState {
+    /// deliver の前: 辺をもう 1 本足せるか（満杯ならログ + derivation_table_full）
+    pub(super) fn derivation_has_room(&mut self, what: &'static str) -> bool {
+        if self.derivations.edges.iter().any(|e| e.is_none()) {
+            return true;
+        }
+        LOG.error("derivation: table full; transfer dropped");
+        LOG.info(what);
+        self.counters.derivation_table_full += 1;
+        false
+    }
+
+    /// deliver で渡せた cap / grant の辺を足す（derivation_has_room で空きを確かめてから呼ぶ）
+    pub(super) fn derivation_record(&mut self, parent: DerivObject, child: DerivObject) {
+        let Some(free) = self.derivations.edges.iter_mut().find(|e| e.is_none()) else {
+            LOG.error("derivation: table full after room check; edge dropped");
+            self.counters.derivation_table_full += 1;
+            return;
+        };
+        *free = Some(DerivEdge { parent, child });
+        self.counters.derivations_recorded += 1;
+    }
+
+    /// Revoke 以外で obj が消えた: 子を obj の親に付け替え、obj を子とする辺を外す
+    pub(super) fn derivation_forget(&mut self, obj: DerivObject) {
+        let parent = self.derivations.parent_of(obj);
+        for slot in self.derivations.edges.iter_mut() {
+            let Some(e) = slot else {
+                continue;
+            };
+            if e.child == obj {
+                *slot = None;
+            } else if e.parent == obj {
+                match parent {
+                    Some(p) => e.parent = p,
+                    None => *slot = None,
+                }
+            }
+        }
+    }
+
+    /// teardown_task から: idx の object を全部忘れる（子は付け替える）
+    pub(super) fn derivation_forget_task(&mut self, idx: usize) {
+        // forget のたびに辺が 1 本以上減るので、辺の数の回数で終わる
+        for _ in 0..MAX_DERIVATIONS {
+            let Some(obj) = self.derivations.edges.iter().flatten().find_map(|e| {
+                if e.child.task() == idx {
+                    Some(e.child)
+                } else if e.parent.task() == idx {
+                    Some(e.parent)
+                } else {
+                    None
+                }
+            }) else {
+                return;
+            };
+            self.derivation_forget(obj);
+        }
+    }
+
+    /// object が消えるとき（revoke_object_handles、cap table から消す前）: object を指す cap の辺を全部外す（親も子も一緒に消える）
+    pub(super) fn derivation_forget_object(&mut self, object: KernelObject) {
+        let points_to = |ks: &KernelState, obj: DerivObject| match obj {
+            DerivObject::Cap { task, cap } => ks.cspaces[task].get(cap).is_some_and(|s| s.object == object),
+            DerivObject::Page { .. } => false,
+        };
+        for i in 0..MAX_DERIVATIONS {
+            if let Some(e) = self.derivations.edges[i] {
+                if points_to(self, e.parent) || points_to(self, e.child) {
+                    self.derivations.edges[i] = None;
+                }
+            }
+        }
+    }
+
+    /// Revoke: idx の object から推移的に派生した cap / grant を全部取り上げる
+    pub(super) fn syscall_revoke(&mut self, idx: usize, target: RevokeTarget) -> u64 {
+        let by = self.tasks[idx].id;
+
+        let root = match target {
+            RevokeTarget::Cap(cap) => {
+                if self.cspaces[idx].get(cap).is_none() {
+                    LOG.error("revoke: cap slot is empty");
+                    LOG.info_u64("task_id", by.0);
+                    LOG.info_u64("cap_index", cap.0 as u64);
+                    return SYSCALL_ERR_BAD_CAP;
+                }
+                DerivObject::Cap { task: idx, cap }
+            }
+            RevokeTarget::Page(page) => {
+                let Some(as_idx) = self.grant_user_as_of(idx) else {
+                    LOG.error("revoke: kernel task has no user pages");
+                    return SYSCALL_ERR_FORBIDDEN;
+                };
+                let Some(m) = self.address_spaces[as_idx].lookup(page) else {
+                    LOG.error("revoke: page is not mapped");
+                    LOG.info_u64("task_id", by.0);
+                    LOG.info_u64("page", page.number);
+                    return SYSCALL_ERR_NOT_MAPPED;
+                };
+                DerivObject::Page { task: idx, page, frame: m.frame }
+            }
+        };
+
+        // 1) 子孫を幅優先で集める（found[i] の親は found の前の方か root）
+        let mut found: [Option<(DerivObject, u64)>; MAX_DERIVATIONS] = [None; MAX_DERIVATIONS];
+        let mut n = 0;
+        let mut head = 0;
+        let mut parent = Some((root, 0));
+        while let Some((p, depth)) = parent {
+            for e in self.derivations.edges.iter().flatten() {
+                if e.parent == p && n < MAX_DERIVATIONS {
+                    found[n] = Some((e.child, depth + 1));
+                    n += 1;
+                }
+            }
+            parent = if head < n { found[head] } else { None };
+            head += 1;
+        }
+
+        // 2) 辺を全部外してから object を消す（grant の取り消しが付け替えをしないように）
+        for (child, _) in found.iter().flatten() {
+            for slot in self.derivations.edges.iter_mut() {
+                if slot.is_some_and(|e| e.child == *child) {
+                    *slot = None;
+                }
+            }
+        }
+
+        for &(child, depth) in found.iter().flatten() {
+            match child {
+                DerivObject::Cap { task, cap } => {
+                    self.cspaces[task].take(cap);
+                }
+                DerivObject::Page { task, page, .. } => self.grant_revoke_derived(task, page),
+            }
+
+            let (kind, object) = child.kind_and_index();
+            let task = self.tasks[child.task()].id;
+            self.counters.derivations_revoked += 1;
+            self.push_event(LogEvent::DerivationRevoked { by, task, kind, object, depth });
+        }
+
+        LOG.info("revoke: derivations removed");
+        LOG.info_u64("task_id", by.0);
+        LOG.info_u64("revoked", n as u64);
+        SYSCALL_OK
+    }
+
+    /// 派生の辺: 両端は生きている task の実在する object で、子は受け取った cap（tag 付き）か
+    /// 受け取り中の grant。子は高々 1 本の辺に現れ、親をたどると有限で根に着く
+    #[spec("INV-DERIV-001")]
+    pub(super) fn check_derivation_invariants(&self) {
+        for e in self.derivations.edges.iter().flatten() {
+            let (kind, object) = e.child.kind_and_index();
+            let child_task = e.child.task();
+            let alive = |t: usize| t < self.num_tasks && self.tasks[t].state != TaskState::Dead;
+            if !alive(child_task) || !alive(e.parent.task()) {
+                self.invariant_violated(
+                    InvariantId::Derivation,
+                    None,
+                    Some(object),
+                    "INVARIANT VIOLATION: derivation edge refers to a dead task",
+                );
+                LOG.info_u64("child_task_idx", child_task as u64);
+                LOG.info_u64("parent_task_idx", e.parent.task() as u64);
+                continue;
+            }
+            let task = self.tasks[child_task].id;
+
+            let shape_ok = match (e.parent, e.child) {
+                (DerivObject::Cap { task: pt, cap: pc }, DerivObject::Cap { task: ct, cap: cc }) => {
+                    match (self.cspaces[pt].get(pc), self.cspaces[ct].get(cc)) {
+                        (Some(p), Some(c)) => p.object == c.object && c.grant.is_some_and(|g| g.to == task),
+                        _ => false,
+                    }
+                }
+                (DerivObject::Page { frame: pf, .. }, DerivObject::Page { task: ct, page, frame }) => {
+                    pf == frame && self.grant_received(ct) == Some((page, frame))
+                }
+                _ => false,
+            };
+            if !shape_ok {
+                self.invariant_violated(
+                    InvariantId::Derivation,
+                    Some(task),
+                    Some(object),
+                    "INVARIANT VIOLATION: derivation edge does not match a live cap or grant",
+                );
+                LOG.info_u64("task_id", task.0);
+                LOG.info_u64("kind", kind);
+                LOG.info_u64("object", object);
+            }
+
+            let parents = self.derivations.edges.iter().flatten().filter(|o| o.child == e.child).count();
+            if parents != 1 {
+                self.invariant_violated(
+                    InvariantId::Derivation,
+                    Some(task),
+                    Some(object),
+                    "INVARIANT VIOLATION: derived object has several parents",
+                );
+                LOG.info_u64("parents", parents as u64);
+            }
+
+            // 親をたどる。辺の数より多く続くなら閉路
+            let mut cur = e.parent;
+            let mut steps = 0;
+            while let Some(p) = self.derivations.parent_of(cur) {
+                steps += 1;
+                if steps > self.derivations.len() {
+                    self.invariant_violated(
+                        InvariantId::Derivation,
+                        Some(task),
+                        Some(object),
+                        "INVARIANT VIOLATION: derivation tree has a cycle",
+                    );
+                    break;
+                }
+                cur = p;
+            }
+        }
+    }
+
+    /// revoke_demo の集計
+    #[cfg_attr(not(feature = "revoke_demo"), allow(dead_code))]
+    pub(super) fn derivation_report(&self) {
+        LOG.info("=== Derivation Report ===");
+        for e in self.derivations.edges.iter().flatten() {
+            let (kind, object) = e.child.kind_and_index();
+            LOG.info_u64("task_id", self.tasks[e.child.task()].id.0);
+            LOG.info_u64("kind", kind);
+            LOG.info_u64("object", object);
+        }
+        LOG.info_u64("derivation_edges", self.derivations.len() as u64);
+        LOG.info_u64("derivations_recorded", self.counters.derivations_recorded);
+        LOG.info_u64("derivations_revoked", self.counters.derivations_revoked);
+        LOG.info_u64("derivation_table_full", self.counters.derivation_table_full);
+        LOG.info("=== End of Derivation Report ===");
+    }
+}
@@ -0,0 +1,229 @@
+// kernel/src/kernel/endpoint_lifecycle.rs
+//
+// 役割:
+// - EndpointCreate / EndpointDelete syscall（実行時の endpoint 生成・削除）。
+//
+// 設計方針:
+// - endpoint table は固定長（MAX_ENDPOINTS）のまま。STATIC_ENDPOINTS 以降の slot を払い出す
+//   * 起動時から在る endpoint（0..STATIC_ENDPOINTS）は削除できない
+//   * 未使用の slot は allocated = false かつ closed（IPC の入口で ENDPOINT_CLOSED として拒否される）
+// - create した task が owner になり、その task の空き cap slot に全 rights（Send | Recv | Reply）の cap を入れる
+// - owner が死んだら teardown_task から close_endpoints_owned_by が呼ばれる
+//   * 起動時の endpoint: close して待ち手を ENDPOINT_CLOSED で救済する（slot はそのまま）
+//   * 動的 slot: close → revoke → 未使用に戻す（delete と同じ後始末。slot が漏れない）
+// - delete は owner だけが行える。close_endpoint_and_rescue_waiters で待ち手を救済してから、
+//   全 task の cap table からその endpoint の cap を消し（revoke）、slot を未使用に戻す
+//   → slot を再利用しても、古い cap が新しい endpoint を指すことはない
+//
+// 戻り値（last_syscall_ret、abi.rs が正本）:
+// - EndpointCreate: 成功 = ENDPOINT_CREATE_OK_TAG | cap index / SYSCALL_ERR_NO_ENDPOINT_SLOT /
+//                   SYSCALL_ERR_CAPACITY（cap table が満杯）/ SYSCALL_ERR_FORBIDDEN（kernel task）
+// - EndpointDelete: SYSCALL_OK / SYSCALL_ERR_BAD_ENDPOINT（cap が引けない・起動時の endpoint）/
+//                   SYSCALL_ERR_FORBIDDEN（owner でない）
+//
+// やらないこと:
+// - owner の移譲（cap transfer で渡した相手は使えるが、delete できるのは作った task だけ）
+
+use super::abi::{
+    ENDPOINT_CREATE_OK_TAG, SYSCALL_ERR_BAD_ENDPOINT, SYSCALL_ERR_CAPACITY, SYSCALL_ERR_FORBIDDEN,
+    SYSCALL_ERR_NO_ENDPOINT_SLOT, SYSCALL_OK,
+};
+use super::cspace::{CapIndex, CapRights, CapSlot, KernelObject};
+use super::ipc::Endpoint;
+use super::{AddressSpaceKind, EndpointId, InvariantId, KernelState, LogEvent, TaskId, TaskState, MAX_ENDPOINTS, STATIC_ENDPOINTS};
+use spec_macros::spec;
+
+const LOG: crate::logging::Subsystem = crate::logging::Subsystem::Ipc;
+
+impl KernelState {
+    fn is_kernel_task_of(&self, idx: usize) -> bool {
+        let as_idx = self.tasks[idx].address_space_id.0;
+        as_idx < self.num_tasks && self.address_spaces[as_idx].kind == AddressSpaceKind::Kernel
+    }
+
+    /// 払い出せる slot（未使用の動的 slot）
+    fn find_free_endpoint_slot(&self) -> Option<usize> {
+        (STATIC_ENDPOINTS..MAX_ENDPOINTS).find(|&i| !self.endpoints[i].allocated)
+    }
+
+    pub(super) fn syscall_endpoint_create(&mut self, idx: usize) -> u64 {
+        let tid = self.tasks[idx].id;
+
+        if self.is_kernel_task_of(idx) {
+            LOG.error("endpoint_create: kernel task cannot own endpoints");
+            return SYSCALL_ERR_FORBIDDEN;
+        }
+
+        let Some(slot) = self.find_free_endpoint_slot() else {
+            LOG.error("endpoint_create: no free endpoint slot");
+            LOG.info_u64("task_id", tid.0);
+            return SYSCALL_ERR_NO_ENDPOINT_SLOT;
+        };
+        let ep = EndpointId(slot);
+
+        // cap を先に入れる（入らなければ endpoint は払い出さない）
+        let rights = CapRights::SEND | CapRights::RECV | CapRights::REPLY;
+        let Some(cap) = self.cspaces[idx].insert(CapSlot { object: KernelObject::Endpoint(ep), rights, grant: None }) else {
+            LOG.error("endpoint_create: cap table full");
+            LOG.info_u64("task_id", tid.0);
+            return SYSCALL_ERR_CAPACITY;
+        };
+
+        let mut e = Endpoint::new(ep);
+        e.owner = Some(tid);
+        self.endpoints[slot] = e;
+        // 前に同じ slot を使っていた endpoint の統計は引き継がない（endpoint_stats.rs）
+        self.counters.per_endpoint[slot] = super::endpoint_stats::EndpointStats::new();
+
+        LOG.info("endpoint_create: created");
+        LOG.info_u64("task_id", tid.0);
+        LOG.info_u64("ep_id", slot as u64);
+        LOG.info_u64("cap_index", cap.0 as u64);
+
+        self.push_event(LogEvent::EndpointCreated { task: tid, ep, cap: cap.0 });
+
+        ENDPOINT_CREATE_OK_TAG | cap.0 as u64
+    }
+
+    pub(super) fn syscall_endpoint_delete(&mut self, idx: usize, cap: CapIndex) -> u64 {
+        let tid = self.tasks[idx].id;
+
+        // rights は問わない（owner かどうかで判定する）
+        let Some(ep) = self.resolve_endpoint_cap(idx, cap, CapRights::empty(), "endpoint_delete") else {
+            return SYSCALL_ERR_BAD_ENDPOINT;
+        };
+        if ep.0 < STATIC_ENDPOINTS || ep.0 >= MAX_ENDPOINTS || !self.endpoints[ep.0].allocated {
+            LOG.error("endpoint_delete: not a deletable endpoint");
+            LOG.info_u64("task_id", tid.0);
+            LOG.info_u64("ep_id", ep.0 as u64);
+            return SYSCALL_ERR_BAD_ENDPOINT;
+        }
+        if self.endpoints[ep.0].owner != Some(tid) {
+            LOG.error("endpoint_delete: caller is not the owner");
+            LOG.info_u64("task_id", tid.0);
+            LOG.info_u64("ep_id", ep.0 as u64);
+            return SYSCALL_ERR_FORBIDDEN;
+        }
+
+        // 待ち手は ENDPOINT_CLOSED で起こす（owner の kill と同じ救済）
+        self.close_endpoint_and_rescue_waiters(ep);
+        let revoked = self.release_endpoint_slot(ep);
+
+        LOG.info("endpoint_delete: deleted");
+        LOG.info_u64("task_id", tid.0);
+        LOG.info_u64("ep_id", ep.0 as u64);
+        LOG.info_u64("revoked_caps", revoked as u64);
+
+        self.push_event(LogEvent::EndpointDeleted { task: tid, ep, revoked });
+
+        SYSCALL_OK
+    }
+
+    /// 全 task の cap table から ep の cap を消し、slot を未使用に戻す（close 済みであること）
+    /// 戻り値: 消した cap の数
+    fn release_endpoint_slot(&mut self, ep: EndpointId) -> usize {
+        let revoked = self.revoke_object_handles(KernelObject::Endpoint(ep));
+        self.endpoints[ep.0] = Endpoint::unallocated(ep);
+        revoked
+    }
+
+    /// dead_id が owner の endpoint を全部 close し、待ち手を救済する（teardown_task から）
+    /// - Rust の借用規則のため、ep_id を先に集めてから close を呼ぶ
+    pub(super) fn close_endpoints_owned_by(&mut self, dead_id: TaskId) {
+        let mut to_close: [Option<EndpointId>; MAX_ENDPOINTS] = [None; MAX_ENDPOINTS];
+        let mut n: usize = 0;
+
+        for e in self.endpoints.iter() {
+            if e.allocated && e.owner == Some(dead_id) {
+                to_close[n] = Some(e.id);
+                n += 1;
+            }
+        }
+
+        for ep in to_close.iter().take(n).flatten().copied() {
+            self.close_endpoint_and_rescue_waiters(ep);
+
+            if ep.0 >= STATIC_ENDPOINTS {
+                let revoked = self.release_endpoint_slot(ep);
+                LOG.info("endpoint: owner dead; dynamic slot released");
+                LOG.info_u64("task_id", dead_id.0);
+                LOG.info_u64("ep_id", ep.0 as u64);
+                LOG.info_u64("revoked_caps", revoked as u64);
+            }
+        }
+    }
+
+    /// 開いている endpoint の owner は生きている task（owner の kill で必ず close される）
+    #[spec("INV-EP-002")]
+    pub(super) fn check_endpoint_owner_invariants(&self) {
+        for e in self.endpoints.iter() {
+            if !e.allocated || e.is_closed {
+                continue;
+            }
+            let Some(owner) = e.owner else {
+                continue;
+            };
+
+            let alive = (0..self.num_tasks).any(|i| self.tasks[i].id == owner && self.tasks[i].state != TaskState::Dead);
+            if !alive {
+                self.invariant_violated(
+                    InvariantId::EndpointOwner,
+                    Some(owner),
+                    Some(e.id.0 as u64),
+                    "INVARIANT VIOLATION: open endpoint has dead owner",
+                );
+                LOG.info_u64("ep_id", e.id.0 as u64);
+                LOG.info_u64("owner_task_id", owner.0);
+            }
+        }
+    }
+
+    /// 起動時の endpoint は常に使用中。未使用の slot は closed で owner / 待ち手を持たず、
+    /// どの task の cap table からも指されない
+    #[spec("INV-EP-001")]
+    pub(super) fn check_endpoint_slot_invariants(&self) {
+        for e in self.endpoints.iter() {
+            if e.id.0 < STATIC_ENDPOINTS {
+                if !e.allocated {
+                    self.invariant_violated(
+                        InvariantId::EndpointSlot,
+                        None,
+                        Some(e.id.0 as u64),
+                        "INVARIANT VIOLATION: static endpoint is unallocated",
+                    );
+                    LOG.info_u64("ep_id", e.id.0 as u64);
+                }
+                continue;
+            }
+            if e.allocated {
+                continue;
+            }
+
+            if !e.is_closed || e.owner.is_some() || e.recv_waiter.is_some() || !e.send_queue.is_empty() || !e.reply_queue.is_empty() {
+                self.invariant_violated(
+                    InvariantId::EndpointSlot,
+                    None,
+                    Some(e.id.0 as u64),
+                    "INVARIANT VIOLATION: unallocated endpoint slot has state",
+                );
+                LOG.info_u64("ep_id", e.id.0 as u64);
+            }
+
+            for (idx, table) in self.cspaces.iter().enumerate().take(self.num_tasks) {
+                for (cap, slot) in table.iter() {
+                    if slot.object == KernelObject::Endpoint(e.id) {
+                        self.invariant_violated(
+                            InvariantId::EndpointSlot,
+                            Some(self.tasks[idx].id),
+                            Some(e.id.0 as u64),
+                            "INVARIANT VIOLATION: cap points to unallocated endpoint",
+                        );
+                        LOG.info_u64("task_id", self.tasks[idx].id.0);
+                        LOG.info_u64("cap_index", cap.0 as u64);
+                        LOG.info_u64("ep_id", e.id.0 as u64);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,117 @@
+// kernel/src/kernel/endpoint_stats.rs
+//
+// 役割:
+// - endpoint ごとの IPC 統計（KernelCounters.per_endpoint、endpoint id で引く）。
+//   全体の counters（ipc_send_fast など）では見えない「どの channel が混んでいるか」を dump で出す。
+//
+// 中身（EndpointStats）:
+// - delivered: この endpoint で受け手に渡した msg（send / recv の fastpath と buffer。reply は数えない）
+// - send_queue_max / reply_queue_max: キューの深さの最大（high-water mark）
+// - rejected_closed / rejected_kernel_task: 入口で拒否した IPC（closed の endpoint / kernel task から）
+// - waits: この endpoint で block に入った回数（IpcSend / IpcRecv / IpcReply、IpcRecvAny は mask の全 endpoint）
+// - block_ticks: この endpoint を待っていた task の tick 数の合計（毎 tick、Blocked / Suspended の待ちを 1 ずつ）
+//
+// 方針:
+// - キューへの enqueue は必ず block_task（IpcSend / IpcReply）が続くので、深さの最大はそこで見る
+// - EndpointCreate で slot を払い出したら 0 に戻す（前の持ち主の統計を引き継がない）
+// - 出力は dump の 3 形式（Text: Endpoint Stats、Records: endpoint_stats、wire: KIND_ENDPOINT_STATS）
+//
+// やらないこと:
+// - 時間窓ごとの統計（run の開始からの累計だけ）
+
+use super::ipc::eps_in_mask;
+use super::{BlockedReason, EndpointId, KernelState, MAX_ENDPOINTS};
+use crate::logging;
+
+#[derive(Clone, Copy)]
+pub struct EndpointStats {
+    pub delivered: u64,
+    pub send_queue_max: u64,
+    pub reply_queue_max: u64,
+    pub rejected_closed: u64,
+    pub rejected_kernel_task: u64,
+    pub waits: u64,
+    pub block_ticks: u64,
+}
+
+impl EndpointStats {
+    pub const fn new() -> Self {
+        EndpointStats {
+            delivered: 0,
+            send_queue_max: 0,
+            reply_queue_max: 0,
+            rejected_closed: 0,
+            rejected_kernel_task: 0,
+            waits: 0,
+            block_ticks: 0,
+        }
+    }
+
+    /// 一度も使われていない（dump で省く）
+    pub fn is_idle(&self) -> bool {
+        self.delivered == 0 && self.waits == 0 && self.rejected_closed == 0 && self.rejected_kernel_task == 0
+    }
+}
+
+/// reason が待っている endpoint（IpcRecvAny は mask の全部）を f に渡す
+fn for_each_waited_ep(reason: BlockedReason, mut f: impl FnMut(EndpointId)) {
+    match reason {
+        BlockedReason::IpcRecv { ep } | BlockedReason::IpcSend { ep } | BlockedReason::IpcReply { ep, .. } => f(ep),
+        BlockedReason::IpcRecvAny { ep_mask } => eps_in_mask(ep_mask).for_each(f),
+        _ => {}
+    }
+}
+
+impl KernelState {
+    /// block_task: 待ちに入った回数と、キューの深さの最大
+    pub(super) fn endpoint_stats_on_block(&mut self, reason: BlockedReason) {
+        for_each_waited_ep(reason, |ep| {
+            if ep.0 >= MAX_ENDPOINTS {
+                return;
+            }
+            let e = &self.endpoints[ep.0];
+            let (sq, rq) = (e.send_queue.len() as u64, e.reply_queue.len() as u64);
+            let s = &mut self.counters.per_endpoint[ep.0];
+            s.waits += 1;
+            s.send_queue_max = s.send_queue_max.max(sq);
+            s.reply_queue_max = s.reply_queue_max.max(rq);
+        });
+    }
+
+    /// 毎 tick: endpoint を待っている task 1 つにつき block_ticks を 1 増やす
+    pub(super) fn endpoint_stats_on_tick(&mut self) {
+        for idx in 0..self.num_tasks {
+            if !self.tasks[idx].state.can_wait() {
+                continue;
+            }
+            let Some(reason) = self.tasks[idx].blocked_reason else {
+                continue;
+            };
+            for_each_waited_ep(reason, |ep| {
+                if ep.0 < MAX_ENDPOINTS {
+                    self.counters.per_endpoint[ep.0].block_ticks += 1;
+                }
+            });
+        }
+    }
+
+    /// Text の dump: 使われた endpoint だけ
+    pub(super) fn dump_endpoint_stats(&self) {
+        logging::info("=== Endpoint Stats ===");
+        for (i, s) in self.counters.per_endpoint.iter().enumerate() {
+            if s.is_idle() {
+                continue;
+            }
+            logging::info("ENDPOINT_STATS:");
+            logging::info_u64("ep_id", i as u64);
+            logging::info_u64("delivered", s.delivered);
+            logging::info_u64("send_queue_max", s.send_queue_max);
+            logging::info_u64("reply_queue_max", s.reply_queue_max);
+            logging::info_u64("rejected_closed", s.rejected_closed);
+            logging::info_u64("rejected_kernel_task", s.rejected_kernel_task);
+            logging::info_u64("waits", s.waits);
+            logging::info_u64("block_ticks", s.block_ticks);
+        }
+        logging::info("=== End of Endpoint Stats ===");
+    }
+}
@@ -5,7 +5,8 @@
 // 役割:
 // - low entry から high-alias entry へ遷移する。
 // - feature に応じて ring3 demo / ring3 mailbox demo / ring3 mailbox loop を起動する。
-// - 通常時は KernelState を生成して tick ループを回す。
+// - 通常時は KernelState を生成し、timer IRQ（PIT）で tick を駆動する。
+//   * synthetic_tick feature では従来どおり同期ループで tick を回す（再現性優先）
 //
 // 設計方針:
 // - ring3 デモは「観測性」を最優先し、ログは ring0 でのみ出す。
@@ -111,7 +112,8 @@ fn run_ring3_demo(boot_info: &'static BootInfo) -> ! {
     let stack_frame = PhysFrame::from_index(stack_phys / PAGE_SIZE);
 
     let user_code_page = VirtPage::from_index(0x120);
-    let user_stack_page = VirtPage::from_index(0x121);
+    // stack の直下（0x121）は guard page として空けておく（map しない）
+    let user_stack_page = VirtPage::from_index(0x122);
 
     let stack_flags = PageFlags::PRESENT | PageFlags::WRITABLE | PageFlags::USER;
     let code_flags_init = PageFlags::PRESENT | PageFlags::WRITABLE | PageFlags::USER;
@@ -159,20 +161,17 @@ fn run_ring3_demo(boot_info: &'static BootInfo) -> ! {
         write_bytes_to_phys(code_phys, bytes);
     }
 
+    // map したまま WRITABLE だけ落とす（Unmap → Map の間に code page が消える瞬間を作らない）
     unsafe {
-        arch::paging::apply_mem_action_in_root(MemAction::Unmap { page: user_code_page }, user_root, &mut phys_mem)
-            .expect("ring3_demo: unmap user code to drop WRITABLE failed");
-
         arch::paging::apply_mem_action_in_root(
-            MemAction::Map {
+            MemAction::Protect {
                 page: user_code_page,
-                frame: code_frame,
-                flags: code_flags_final,
+                new_flags: code_flags_final,
             },
             user_root,
             &mut phys_mem,
         )
-            .expect("ring3_demo: remap user code(final RX) failed");
+            .expect("ring3_demo: protect user code(final RX) failed");
     }
 
     let user_rip = arch::paging::USER_SPACE_BASE + user_code_page.start_address().0;
@@ -217,7 +216,8 @@ fn run_ring3_mailbox_demo(boot_info: &'static BootInfo) -> ! {
     let stack_frame = PhysFrame::from_index(stack_phys / PAGE_SIZE);
 
     let user_code_page = VirtPage::from_index(0x120);
-    let user_stack_page = VirtPage::from_index(0x121);
+    // stack の直下（0x121）は guard page として空けておく（map しない）
+    let user_stack_page = VirtPage::from_index(0x122);
 
     let stack_flags = PageFlags::PRESENT | PageFlags::WRITABLE | PageFlags::USER;
     let code_flags_init = PageFlags::PRESENT | PageFlags::WRITABLE | PageFlags::USER;
@@ -306,7 +306,8 @@ fn run_ring3_mailbox_loop_demo(_boot_info: &'static BootInfo, kstate: &mut Kerne
     let stack_frame = PhysFrame::from_index(stack_phys / PAGE_SIZE);
 
     let user_code_page = VirtPage::from_index(0x120);
-    let user_stack_page = VirtPage::from_index(0x121);
+    // stack の直下（0x121）は guard page として空けておく（map しない）
+    let user_stack_page = VirtPage::from_index(0x122);
 
     let stack_flags = PageFlags::PRESENT | PageFlags::WRITABLE | PageFlags::USER;
     let code_flags_init = PageFlags::PRESENT | PageFlags::WRITABLE | PageFlags::USER;
@@ -335,6 +336,11 @@ fn run_ring3_mailbox_loop_demo(_boot_info: &'static BootInfo, kstate: &mut Kerne
             .expect("ring3_mailbox_loop: map user stack failed");
     }
 
+    // guard page を AddressSpace に記録する（踏んだ #PF を StackOverflow として区別する）
+    kstate.address_spaces[1]
+        .reserve_guard_page(VirtPage::from_index(0x121))
+        .expect("ring3_mailbox_loop: reserve user stack guard page failed");
+
     logging::info("ring3_mailbox_loop: mapped user code+stack OK");
     logging::info_u64("ring3_mailbox_loop: user_root_phys", user_root.start_address().0);
     logging::info_u64("ring3_mailbox_loop: kernel_root_phys", kernel_root.start_address().0);
@@ -421,34 +427,26 @@ fn run_ring3_mailbox_loop_demo(_boot_info: &'static BootInfo, kstate: &mut Kerne
 
     #[cfg(not(feature = "ring3_mailbox_loop_skip_rx"))]
     {
-        logging::info("ring3_mailbox_loop: remap code to RX (drop WRITABLE)");
+        logging::info("ring3_mailbox_loop: protect code to RX (drop WRITABLE)");
 
         let code_flags_rx = PageFlags::PRESENT | PageFlags::USER;
 
         unsafe {
             arch::paging::apply_mem_action_in_root(
-                MemAction::Unmap { page: user_code_page },
-                user_root,
-                &mut kstate.phys_mem,
-            )
-                .expect("ring3_mailbox_loop: unmap user code to drop WRITABLE failed");
-
-            arch::paging::apply_mem_action_in_root(
-                MemAction::Map {
+                MemAction::Protect {
                     page: user_code_page,
-                    frame: code_frame,
-                    flags: code_flags_rx,
+                    new_flags: code_flags_rx,
                 },
                 user_root,
                 &mut kstate.phys_mem,
             )
-                .expect("ring3_mailbox_loop: remap user code(final RX) failed");
+                .expect("ring3_mailbox_loop: protect user code(final RX) failed");
         }
     }
 
     #[cfg(feature = "ring3_mailbox_loop_skip_rx")]
     {
-        logging::info("ring3_mailbox_loop: skip RX remap (debug)");
+        logging::info("ring3_mailbox_loop: skip RX protect (debug)");
     }
 
     let user_rip = arch::paging::USER_SPACE_BASE + user_code_page.start_address().0;
@@ -483,6 +481,9 @@ extern "C" fn kernel_high_entry(boot_info: &'static BootInfo) -> ! {
         super::state_ref::register_kernel_state(&mut kstate);
 
         kstate.bootstrap();
+        super::state_ref::seal_kernel_state();
+        arch::interrupts::allow_external_irqs();
+
         for _ in 0..3 {
             if kstate.should_halt() {
                 break;
@@ -501,26 +502,145 @@ extern "C" fn kernel_high_entry(boot_info: &'static BootInfo) -> ! {
         super::state_ref::register_kernel_state(&mut kstate);
 
         kstate.bootstrap();
+        super::state_ref::seal_kernel_state();
+        arch::interrupts::allow_external_irqs();
+
         run_ring3_mailbox_loop_demo(boot_info, &mut kstate);
     }
 
+    // scenario_suite: demo/scenario.rs の全シナリオを 1 boot で順に流して終了する
+    #[cfg(feature = "scenario_suite")]
+    run_scenario_suite(boot_info);
+
     // ------------------------------------------------------------
     // 通常起動（デモ feature が無いとき）
     // ------------------------------------------------------------
     let mut kstate = KernelState::new(boot_info);
     super::state_ref::register_kernel_state(&mut kstate);
 
+    #[cfg(not(feature = "stress_ipc"))]
+    let default_ticks: u64 = 120;
+    #[cfg(feature = "stress_ipc")]
+    let default_ticks: u64 = super::demo::stress_ipc::STRESS_IPC_TICKS;
+    // command line の max_ticks があればそちら
+    let run_ticks = super::boot_params::get().max_ticks.unwrap_or(default_ticks);
+
+    // boot phase 完了 -> seal -> IRQ 許可（この順序以外では割り込み側に state を見せない）
     kstate.bootstrap();
-    for _ in 0..120 {
+    // smp: AP は seal 前に起こす（AP は seal を待ってから BKL を取って check-in し、task を走らせる）
+    #[cfg(feature = "smp")]
+    kstate.start_secondary_cpus();
+    // virtio_net: PMM のフレームを借りて queue を作り、IRQ を登録する（unmask は allow_external_irqs）
+    #[cfg(feature = "virtio_net")]
+    kstate.net_init();
+    super::state_ref::seal_kernel_state();
+    arch::interrupts::allow_external_irqs();
+
+    // replay: tick も台本が回す（run_ticks は使わない）
+    #[cfg(feature = "replay")]
+    {
+        let _ = run_ticks;
+        super::replay::run(&mut kstate);
+    }
+
+    // timer IRQ が tick を回している間は kstate に触らない（戻った時点で IF=0）
+    #[cfg(all(not(feature = "replay"), not(feature = "synthetic_tick")))]
+    match arch::interrupts::run_timer_ticks(run_ticks) {
+        Some(n) => logging::info_u64("timer_irq_ticks", n),
+        None => run_synthetic_ticks(&mut kstate, run_ticks),
+    }
+    #[cfg(all(not(feature = "replay"), feature = "synthetic_tick"))]
+    run_synthetic_ticks(&mut kstate, run_ticks);
+
+    // smp: AP を止めて（持っていた task は Ready に戻る）から、main が kstate を直接触る間 BKL を持ったままにする
+    #[cfg(feature = "smp")]
+    super::smp::stop_secondary_cpus();
+    #[cfg(feature = "smp")]
+    let _bkl = super::state_ref::lock_kernel_state();
+    #[cfg(feature = "smp")]
+    kstate.reclaim_ap_tasks();
+
+    super::demo::on_run_finished(&kstate);
+    kstate.dump_events();
+    let verdict = kstate.evaluate_verdict();
+
+    // qemu_exit: verdict を終了コードにして QEMU を止める（debug_console より優先）
+    #[cfg(feature = "qemu_exit")]
+    super::verdict::exit_with(verdict);
+    #[cfg(not(feature = "qemu_exit"))]
+    let _ = verdict;
+
+    // debug_console: halt せずに COM1 のコマンドを待ち続ける
+    #[cfg(all(not(feature = "qemu_exit"), feature = "debug_console"))]
+    kstate.console_loop();
+    #[cfg(all(not(feature = "qemu_exit"), not(feature = "debug_console")))]
+    arch::halt_loop();
+}
+
+/// SCENARIOS を 1 つずつ流し、シナリオごとの verdict を出してから QEMU を終了する
+///
+/// - シナリオごとに KernelState を作り直す（PMM だけ引き継ぐ。CR3 は kernel root に戻す）
+/// - tick は同期ループ（IRQ は許可しない。timer IRQ に前の state を触らせない）
+/// - 1 つでも FAIL なら終了コードは FAIL
+#[cfg(feature = "scenario_suite")]
+fn run_scenario_suite(boot_info: &'static BootInfo) -> ! {
+    use super::demo::scenario::{self, SCENARIOS};
+    use crate::arch::qemu::{exit_qemu, QemuExitCode};
+    use crate::mm::PhysicalMemoryManager;
+
+    logging::info("scenario_suite: start");
+    logging::info_u64("scenarios", SCENARIOS.len() as u64);
+
+    let kernel_root = arch::paging::current_root();
+    let mut phys_mem = PhysicalMemoryManager::new(boot_info);
+    let mut failed: u64 = 0;
+
+    for (i, &s) in SCENARIOS.iter().enumerate() {
+        scenario::begin(s);
+
+        let mut kstate = KernelState::with_phys_mem(phys_mem);
+        super::state_ref::register_kernel_state(&mut kstate);
+        kstate.bootstrap();
+        super::state_ref::seal_kernel_state();
+
+        run_synthetic_ticks(&mut kstate, super::boot_params::get().max_ticks.unwrap_or(120));
+
+        super::demo::on_run_finished(&kstate);
+        let verdict = kstate.evaluate_verdict();
+        if !verdict.passed() {
+            failed += 1;
+            kstate.dump_events();
+        }
+
+        logging::info(if verdict.passed() { "scenario: PASS" } else { "scenario: FAIL" });
+        logging::info(s.name());
+        crate::log_record!(Info, "scenario", index = dec(i as u64), passed = dec(verdict.passed() as u64));
+
+        // 次のシナリオの前に、割り込み側の参照と CR3 / VGA を boot 直後の状態に戻す
+        super::state_ref::unregister_kernel_state();
+        arch::paging::switch_address_space_quiet(kernel_root);
+        logging::set_vga_enabled(true);
+
+        phys_mem = kstate.into_phys_mem();
+    }
+
+    logging::info("scenario_suite: done");
+    logging::info_u64("scenarios", SCENARIOS.len() as u64);
+    logging::info_u64("failed", failed);
+
+    exit_qemu(if failed == 0 { QemuExitCode::Success } else { QemuExitCode::Failed })
+}
+
+/// 同期ループで tick を回す（synthetic_tick / timer IRQ が使えないときの経路）
+#[cfg(not(feature = "replay"))]
+fn run_synthetic_ticks(kstate: &mut KernelState, run_ticks: u64) {
+    for _ in 0..run_ticks {
         if kstate.should_halt() {
             logging::info("KernelState requested halt; stop ticking");
             break;
         }
         kstate.tick();
     }
-
-    kstate.dump_events();
-    arch::halt_loop();
 }
 
 pub fn start(boot_info: &'static BootInfo) {
@@ -534,6 +654,8 @@ pub fn start(boot_info: &'static BootInfo) {
     arch::paging::configure_cr3_switch_safety(code_addr, stack_addr);
     arch::paging::install_kernel_high_alias_from_current();
     arch::interrupts::reload_idt_high_alias();
+    #[cfg(feature = "ring3_tasks")]
+    arch::syscall_msr::init();
 
     arch::paging::debug_log_execution_context("before enter_kernel_high_alias");
     arch::paging::enter_kernel_high_alias(kernel_high_entry, boot_info);
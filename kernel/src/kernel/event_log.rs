@@ -0,0 +1,186 @@
+// kernel/src/kernel/event_log.rs
+//
+// 役割:
+// - event_log（リングバッファ）の上書きを数える（trace が欠けたことを dump から分かるようにする）。
+// - feature event_log_retain_critical のとき、上書きされる重要 event を別の小さなバッファに退避する。
+//
+// 上書きの計上:
+// - リングが満杯で push したとき、先頭（最古）の event が上書きされる
+//   * counters.events_overwritten（合計）と events_overwritten_by_class（class ごと）を増やす
+// - IPC の決定的サンプリングで log に入らなかった分は別（ipc_events_skipped）
+//
+// event の class（EVENT_CLASS_KEYS の順）:
+// - sched: tick / 切替 / 状態遷移 / queue / runtime / priority / MLFQ level
+// - ipc:   rendezvous IPC / ServerSlow / timer_service / notification / cap / endpoint の作成・削除 / deadlock /
+//          keyboard 入力（外から非同期に届くもの）
+// - mem:   frame / MemAction / swap / shm / TLB
+// - task:  syscall / TaskCreated / TaskExited / TaskKilled
+// - diag:  watchdog / invariant 違反など、全体を見て出す診断
+//
+// 重要 event の退避（event_log_retain_critical）:
+// - 対象は is_critical_event（TaskKilled / DeadlockDetected / NoProgressDetected / InvariantViolated）
+// - リングから上書きされる直前に、push 時の seq ごと retained に移す（先着 CRITICAL_EVENT_CAP 個）
+//   * retained も満杯なら critical_events_lost を増やす（件数だけは残る）
+// - dump はどの形式でも retained → リングの順（どちらも seq の昇順、retained の方が古い）
+// - 既定（feature なし）は従来どおり上書きするだけ（数えはする）
+
+use super::{KernelState, LogEvent, EVENT_LOG_CAP};
+
+/// 上書き数を分ける class の数
+pub(super) const EVENT_CLASSES: usize = 5;
+
+/// Counters Dump の key（class の順。abi の COUNTER_KEYS と同じ名前）
+pub(super) const EVENT_CLASS_KEYS: [&str; EVENT_CLASSES] = [
+    "events_overwritten_sched",
+    "events_overwritten_ipc",
+    "events_overwritten_mem",
+    "events_overwritten_task",
+    "events_overwritten_diag",
+];
+
+const CLASS_SCHED: usize = 0;
+const CLASS_IPC: usize = 1;
+const CLASS_MEM: usize = 2;
+const CLASS_TASK: usize = 3;
+const CLASS_DIAG: usize = 4;
+
+/// 退避できる重要 event の数
+pub(super) const CRITICAL_EVENT_CAP: usize = 32;
+
+/// event の class（EVENT_CLASS_KEYS の index）
+/// - wildcard を使わない（event を足したら class を決めないとコンパイルが通らない）
+fn event_class(ev: &LogEvent) -> usize {
+    match ev {
+        LogEvent::TickStarted(_)
+        | LogEvent::TimerUpdated(_)
+        | LogEvent::TaskSwitched(_)
+        | LogEvent::TaskStateChanged(..)
+        | LogEvent::ReadyQueued(_)
+        | LogEvent::ReadyDequeued(_)
+        | LogEvent::WaitQueued(_)
+        | LogEvent::WaitDequeued(_)
+        | LogEvent::RuntimeUpdated(..)
+        | LogEvent::QuantumExpired(..)
+        | LogEvent::PriorityInherited { .. }
+        | LogEvent::PriorityRestored { .. }
+        | LogEvent::LevelChanged { .. } => CLASS_SCHED,
+
+        LogEvent::IpcRecvCalled { .. }
+        | LogEvent::IpcRecvBlocked { .. }
+        | LogEvent::IpcSendCalled { .. }
+        | LogEvent::IpcSendBlocked { .. }
+        | LogEvent::IpcDelivered { .. }
+        | LogEvent::IpcReplyCalled { .. }
+        | LogEvent::IpcReplyDelivered { .. }
+        | LogEvent::ServerSlow { .. }
+        | LogEvent::TimerFired { .. }
+        | LogEvent::NotifySignaled { .. }
+        | LogEvent::NotifyWaitBlocked { .. }
+        | LogEvent::NotifyDelivered { .. }
+        | LogEvent::CapDenied { .. }
+        | LogEvent::CapGranted { .. }
+        | LogEvent::CapReceived { .. }
+        | LogEvent::DerivationRevoked { .. }
+        | LogEvent::EndpointCreated { .. }
+        | LogEvent::EndpointDeleted { .. }
+        | LogEvent::DeadlockDetected { .. }
+        | LogEvent::InputReceived { .. } => CLASS_IPC,
+
+        LogEvent::FrameAllocated
+        | LogEvent::FrameFreed { .. }
+        | LogEvent::MemActionApplied { .. }
+        | LogEvent::PageSwappedOut { .. }
+        | LogEvent::PageSwappedIn { .. }
+        | LogEvent::ShmCreated { .. }
+        | LogEvent::ShmMapped { .. }
+        | LogEvent::ShmDestroyed { .. }
+        | LogEvent::TlbFlushDeferred { .. }
+        | LogEvent::TlbFlushed { .. }
+        | LogEvent::PageGranted { .. }
+        | LogEvent::GrantRevoked { .. } => CLASS_MEM,
+
+        LogEvent::SyscallIssued { .. }
+        | LogEvent::SyscallHandled { .. }
+        | LogEvent::TaskCreated { .. }
+        | LogEvent::TaskExited { .. }
+        | LogEvent::TaskKilled { .. }
+        | LogEvent::FaultDelegated { .. }
+        | LogEvent::FaultResolved { .. } => CLASS_TASK,
+
+        LogEvent::NoProgressDetected { .. } | LogEvent::InvariantViolated { .. } => CLASS_DIAG,
+    }
+}
+
+/// リングから落としたくない event か（event_log_retain_critical で退避する）
+pub(super) fn is_critical_event(ev: &LogEvent) -> bool {
+    matches!(
+        ev,
+        LogEvent::TaskKilled { .. }
+            | LogEvent::DeadlockDetected { .. }
+            | LogEvent::NoProgressDetected { .. }
+            | LogEvent::InvariantViolated { .. }
+    )
+}
+
+/// 上書きから退避した重要 event（push 時の seq 付き、古い順）
+pub(super) struct RetainedEvents {
+    events: [Option<LogEvent>; CRITICAL_EVENT_CAP],
+    seq: [u64; CRITICAL_EVENT_CAP],
+    len: usize,
+}
+
+impl RetainedEvents {
+    pub(super) const fn new() -> Self {
+        Self { events: [None; CRITICAL_EVENT_CAP], seq: [0; CRITICAL_EVENT_CAP], len: 0 }
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl KernelState {
+    /// push_event から: リングが満杯で、先頭（pos）がこれから上書きされる
+    pub(super) fn note_event_overwritten(&mut self, pos: usize) {
+        let Some(ev) = self.event_log[pos] else {
+            return;
+        };
+
+        self.counters.events_overwritten += 1;
+        self.counters.events_overwritten_by_class[event_class(&ev)] += 1;
+
+        if !is_critical_event(&ev) {
+            return;
+        }
+
+        #[cfg(feature = "event_log_retain_critical")]
+        if self.retained_events.len < CRITICAL_EVENT_CAP {
+            let r = &mut self.retained_events;
+            r.events[r.len] = Some(ev);
+            r.seq[r.len] = self.event_seq[pos];
+            r.len += 1;
+            self.counters.critical_events_retained += 1;
+            return;
+        }
+
+        // 退避しない（feature なし / retained も満杯）: trace から消える
+        self.counters.critical_events_lost += 1;
+    }
+
+    /// dump 用: 退避した重要 event → リングの順に (seq, event) を渡す
+    pub(super) fn for_each_logged_event(&self, mut f: impl FnMut(u64, &LogEvent)) {
+        let r = &self.retained_events;
+        for i in 0..r.len {
+            if let Some(ev) = &r.events[i] {
+                f(r.seq[i], ev);
+            }
+        }
+
+        for i in 0..self.event_log_len {
+            let idx = (self.event_log_head + i) % EVENT_LOG_CAP;
+            if let Some(ev) = &self.event_log[idx] {
+                f(self.event_seq[idx], ev);
+            }
+        }
+    }
+}
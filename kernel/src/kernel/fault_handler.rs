@@ -0,0 +1,394 @@
+// kernel/src/kernel/fault_handler.rs
+//
+// 役割:
+// - user #PF を kernel が kill で閉じる代わりに、task が登録した fault handler endpoint へ IPC の msg として渡す
+//   （microkernel の fault delegation）。handler task が mapping を直して再開させるか、kill を選ぶ。
+// - FaultHandlerSet / FaultResolve syscall。
+//
+// 流れ:
+// - FaultHandlerSet { cap }: 自分の fault handler を cap（Send が要る）の endpoint にする。None で解除
+// - #PF（kill_current_task_due_to_user_pf。swap in / guard page / storm の判定の後）:
+//   * handler が登録されていれば faulter を Blocked(FaultWait{ep}) にし、未解決の fault として持つ
+//   * ep で recv 待ちの task が居ればその場で渡す。居なければ次に ep で recv した task が block せずに受け取る
+//   * msg: MR0 = FAULT_MSG_TAG | faulter の TaskId、MR1 = addr、MR2 = err、MR3 = rip（abi.rs が正本）
+//   * 渡すときに handler の TASK_CAP_BASE + faulter の task handle に Resolve を足し、msg の cap_transfer でその slot を知らせる
+// - FaultResolve { cap, action }（Resolve 付きの faulter の handle を持ち、fault を受け取った task だけが呼べる）:
+//   * Resume: そのまま起こす（fault した命令 / 手順をやり直す）
+//   * MapAndResume { page }: faulter の AddressSpace に PageMap（RW）を代行してから起こす。map に失敗したら Blocked のまま
+//   * Kill: UserPageFault で kill（既定の処理と同じ理由）
+//   * 解決したら handler の Resolve を外す（ep の close で未解決のまま終わったときも）
+//
+// 方針:
+// - 登録できるのは User AddressSpace の task だけ（kernel task の #PF は kernel の誤りとして既定どおり）
+// - handler 側は普通の IpcRecv / IpcRecvAny で受け取る（timer_service と同じく kernel 起点の deliver。reply obligation は無い）
+// - handler task が死んだら、受け取り済みの fault は未配送に戻す（ep で次に recv した task が受け取る）
+// - ep が close されたら、その ep を handler にしている登録を外し、待っている faulter を起こす
+//   （やり直した命令がまた #PF すれば、今度は既定の kill になる。kill を再帰させない）
+// - storm（窓内の #PF が多すぎる）は委譲より先に見る（handler が直せない fault のループは kill で止める）
+//
+// やらないこと:
+// - #PF 以外の例外の委譲（#GP / #UD 等は既定どおり kill）
+// - FaultWait からの priority inheritance（handler が誰になるかは recv するまで決まらない）
+// - 他人の handler の設定（task が自分の handler を決める）
+
+use super::abi::{
+    FAULT_ACTION_KILL, FAULT_ACTION_MAP_RESUME, FAULT_ACTION_RESUME, FAULT_MSG_TAG, SYSCALL_ERR_BAD_ENDPOINT,
+    SYSCALL_ERR_FORBIDDEN, SYSCALL_ERR_NO_FAULT, SYSCALL_OK,
+};
+use super::cspace::{CapIndex, CapRights, CapSlot, KernelObject, TASK_CAP_BASE};
+use super::{
+    AddressSpaceKind, BlockedReason, EndpointId, InvariantId, IpcMessage, KernelState, LogEvent, TaskId,
+    TaskKillReason, TaskState, MAX_ENDPOINTS, MAX_TASKS,
+};
+use crate::arch::ops::PageFaultInfo;
+use crate::mem::addr::VirtPage;
+use crate::mem::paging::PageFlags;
+use spec_macros::spec;
+
+const LOG: crate::logging::Subsystem = crate::logging::Subsystem::Ipc;
+
+/// FaultResolve の処理
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FaultAction {
+    Resume,
+    MapAndResume { page: VirtPage },
+    Kill,
+}
+
+impl FaultAction {
+    /// abi の FAULT_ACTION_*（FaultResolved event の word）
+    pub fn code(self) -> u64 {
+        match self {
+            FaultAction::Resume => FAULT_ACTION_RESUME,
+            FaultAction::MapAndResume { .. } => FAULT_ACTION_MAP_RESUME,
+            FaultAction::Kill => FAULT_ACTION_KILL,
+        }
+    }
+}
+
+/// 未解決の fault（faulter の task index で引く）
+#[derive(Clone, Copy)]
+struct PendingFault {
+    ep: EndpointId,
+    pf: PageFaultInfo,
+    /// msg を渡した task（None = 未配送）
+    handler: Option<usize>,
+}
+
+#[derive(Clone, Copy)]
+pub struct FaultHandlers {
+    /// task ごとの fault handler endpoint（FaultHandlerSet）
+    ep: [Option<EndpointId>; MAX_TASKS],
+    pending: [Option<PendingFault>; MAX_TASKS],
+}
+
+impl FaultHandlers {
+    pub const fn new() -> Self {
+        FaultHandlers { ep: [None; MAX_TASKS], pending: [None; MAX_TASKS] }
+    }
+}
+
+/// faulter に渡す msg（ヘッダの「流れ」）
+fn fault_message(task: TaskId, pf: &PageFaultInfo) -> IpcMessage {
+    let tag = FAULT_MSG_TAG | (task.0 & !super::abi::FAULT_MSG_TAG_MASK);
+    IpcMessage::from_words(&[tag, pf.addr, pf.err, pf.rip]).unwrap_or(IpcMessage::word(tag))
+}
+
+impl KernelState {
+    /// FaultHandlerSet: cap = None で解除
+    pub(super) fn syscall_fault_handler_set(&mut self, idx: usize, cap: Option<CapIndex>) -> u64 {
+        let as_idx = self.tasks[idx].address_space_id.0;
+        if as_idx >= self.num_tasks || self.address_spaces[as_idx].kind != AddressSpaceKind::User {
+            LOG.error("fault_handler: only a user task can register a fault handler");
+            LOG.info_u64("task_id", self.tasks[idx].id.0);
+            return SYSCALL_ERR_FORBIDDEN;
+        }
+
+        let Some(cap) = cap else {
+            self.faults.ep[idx] = None;
+            LOG.info("fault_handler: unregistered");
+            LOG.info_u64("task_id", self.tasks[idx].id.0);
+            return SYSCALL_OK;
+        };
+
+        let Some(ep) = self.resolve_endpoint_cap(idx, cap, CapRights::SEND, "fault_handler_set") else {
+            return SYSCALL_ERR_BAD_ENDPOINT;
+        };
+        if self.endpoints[ep.0].is_closed {
+            LOG.error("fault_handler: endpoint is closed");
+            LOG.info_u64("task_id", self.tasks[idx].id.0);
+            LOG.info_u64("ep_id", ep.0 as u64);
+            return SYSCALL_ERR_BAD_ENDPOINT;
+        }
+
+        self.faults.ep[idx] = Some(ep);
+        LOG.info("fault_handler: registered");
+        LOG.info_u64("task_id", self.tasks[idx].id.0);
+        LOG.info_u64("ep_id", ep.0 as u64);
+        SYSCALL_OK
+    }
+
+    /// user #PF を handler に委譲する（true = 委譲して idx を Blocked(FaultWait) にした）
+    pub(super) fn delegate_user_fault(&mut self, idx: usize, pf: PageFaultInfo) -> bool {
+        let Some(ep) = self.faults.ep[idx] else {
+            return false;
+        };
+        if ep.0 >= MAX_ENDPOINTS || self.endpoints[ep.0].is_closed {
+            self.faults.ep[idx] = None;
+            return false;
+        }
+
+        let task = self.tasks[idx].id;
+        self.faults.pending[idx] = Some(PendingFault { ep, pf, handler: None });
+        self.counters.fault_delegated += 1;
+
+        LOG.info("fault_handler: #PF delegated");
+        LOG.info_u64("task_id", task.0);
+        LOG.info_u64("ep_id", ep.0 as u64);
+        self.push_event(LogEvent::FaultDelegated { task, ep, addr: pf.addr });
+
+        self.block_task(idx, BlockedReason::FaultWait { ep });
+        self.fault_try_deliver(idx);
+
+        if idx == self.current_task {
+            self.schedule_next_task();
+        }
+        true
+    }
+
+    /// idx に未解決の fault があるか（ring3 の #PF handler が委譲されたか / 再開してよいかを見る）
+    #[cfg_attr(not(feature = "ring3_tasks"), allow(dead_code))]
+    pub(super) fn fault_pending(&self, idx: usize) -> bool {
+        self.faults.pending[idx].is_some()
+    }
+
+    /// ep で recv 待ちの task が居れば、faulter の未配送の fault を渡して起こす
+    fn fault_try_deliver(&mut self, faulter: usize) {
+        let Some(p) = self.faults.pending[faulter] else {
+            return;
+        };
+        if p.handler.is_some() {
+            return;
+        }
+        let Some(h) = self.endpoints[p.ep.0].recv_waiter else {
+            return;
+        };
+        let waiting = h < self.num_tasks
+            && self.tasks[h].state == TaskState::Blocked
+            && self.tasks[h].blocked_reason.is_some_and(|r| r.waits_recv_on(p.ep));
+        if !waiting {
+            return;
+        }
+
+        self.release_recv_waiter(h);
+        self.fault_deliver(faulter, h);
+        self.wake_task_to_ready(h);
+    }
+
+    fn fault_deliver(&mut self, faulter: usize, handler: usize) {
+        let Some(mut p) = self.faults.pending[faulter] else {
+            return;
+        };
+        p.handler = Some(handler);
+        self.faults.pending[faulter] = Some(p);
+
+        let task = self.tasks[faulter].id;
+        let cap = self.fault_grant_resolve(handler, faulter);
+        let mut msg = fault_message(task, &p.pf);
+        msg.set_cap_transfer(Some(cap));
+        self.tasks[handler].last_msg = Some(msg);
+        self.tasks[handler].last_recv_ep = Some(p.ep);
+        self.tasks[handler].last_reply_cap = None;
+
+        LOG.info("fault_handler: fault delivered");
+        LOG.info_u64("task_id", task.0);
+        LOG.info_u64("handler_task_id", self.tasks[handler].id.0);
+    }
+
+    /// handler の faulter の task handle に Resolve を足す（slot を返す。無ければ Resolve だけの handle を置く）
+    fn fault_grant_resolve(&mut self, handler: usize, faulter: usize) -> CapIndex {
+        let cap = CapIndex(TASK_CAP_BASE + faulter);
+        let object = KernelObject::Task(self.tasks[faulter].id);
+        let rights = match self.cspaces[handler].get(cap) {
+            Some(s) if s.object == object => s.rights | CapRights::RESOLVE,
+            _ => CapRights::RESOLVE,
+        };
+        self.cspaces[handler].put(cap, Some(CapSlot { object, rights, grant: None }));
+        cap
+    }
+
+    /// fault_grant_resolve の Resolve を外す（rights が残らなければ slot ごと空にする）
+    fn fault_revoke_resolve(&mut self, handler: usize, faulter: usize) {
+        let cap = CapIndex(TASK_CAP_BASE + faulter);
+        let Some(mut slot) = self.cspaces[handler].get(cap) else {
+            return;
+        };
+        slot.rights.remove(CapRights::RESOLVE);
+        self.cspaces[handler].put(cap, (!slot.rights.is_empty()).then_some(slot));
+    }
+
+    /// ipc_recv の入口で呼ぶ。ep 宛の未配送の fault があれば block せずに受け取る（task index の小さい順）
+    pub(super) fn fault_recv_pending(&mut self, recv_idx: usize, ep: EndpointId) -> bool {
+        let found = (0..self.num_tasks)
+            .find(|&i| self.faults.pending[i].is_some_and(|p| p.ep == ep && p.handler.is_none()));
+        match found {
+            Some(faulter) => {
+                self.fault_deliver(faulter, recv_idx);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// FaultResolve: faulter の handle に Resolve があり、その fault を受け取った task だけが呼べる
+    pub(super) fn syscall_fault_resolve(&mut self, idx: usize, cap: CapIndex, action: FaultAction) -> u64 {
+        let faulter = match self.resolve_task_cap(idx, cap, CapRights::RESOLVE, "fault_resolve") {
+            Ok(f) => f,
+            Err(e) => return e,
+        };
+        let task = self.tasks[faulter].id;
+        let Some(p) = self.faults.pending[faulter] else {
+            LOG.error("fault_handler: no pending fault for the task");
+            LOG.info_u64("task_id", task.0);
+            return SYSCALL_ERR_NO_FAULT;
+        };
+        if p.handler != Some(idx) {
+            LOG.error("fault_handler: resolve from a task that did not receive the fault");
+            LOG.info_u64("task_id", task.0);
+            LOG.info_u64("caller_task_id", self.tasks[idx].id.0);
+            return SYSCALL_ERR_FORBIDDEN;
+        }
+
+        if let FaultAction::MapAndResume { page } = action {
+            let flags = PageFlags::PRESENT | PageFlags::WRITABLE | PageFlags::USER;
+            let ret = self.syscall_page_map(faulter, task, page, flags);
+            if ret != SYSCALL_OK {
+                // faulter は Blocked のまま（handler は別の action で解決し直せる）
+                LOG.error("fault_handler: PageMap on behalf of the faulter failed");
+                LOG.info_u64("task_id", task.0);
+                LOG.info_u64("ret", ret);
+                return ret;
+            }
+        }
+
+        let by = self.tasks[idx].id;
+        self.faults.pending[faulter] = None;
+        self.fault_revoke_resolve(idx, faulter);
+        self.push_event(LogEvent::FaultResolved { task, by, action: action.code() });
+
+        if action == FaultAction::Kill {
+            LOG.info("fault_handler: handler chose kill");
+            LOG.info_u64("task_id", task.0);
+            self.kill_task(faulter, TaskKillReason::UserPageFault { addr: p.pf.addr, err: p.pf.err, rip: p.pf.rip });
+            return SYSCALL_OK;
+        }
+
+        self.counters.fault_resumed += 1;
+        LOG.info("fault_handler: faulter resumed");
+        LOG.info_u64("task_id", task.0);
+        LOG.info_u64("handler_task_id", by.0);
+        self.wake_task_to_ready(faulter);
+        SYSCALL_OK
+    }
+
+    /// ep の close: ep を handler にしている登録を外し、待っている faulter を起こす（やり直して既定の処理へ）
+    pub(super) fn fault_on_endpoint_closed(&mut self, ep: EndpointId) {
+        for i in 0..self.num_tasks {
+            if self.faults.ep[i] == Some(ep) {
+                self.faults.ep[i] = None;
+            }
+            if !self.faults.pending[i].is_some_and(|p| p.ep == ep) {
+                continue;
+            }
+            if let Some(h) = self.faults.pending[i].and_then(|p| p.handler) {
+                self.fault_revoke_resolve(h, i);
+            }
+            self.faults.pending[i] = None;
+            self.counters.fault_orphaned += 1;
+            LOG.error("fault_handler: handler endpoint closed; faulter retries without a handler");
+            LOG.info_u64("task_id", self.tasks[i].id.0);
+            LOG.info_u64("ep_id", ep.0 as u64);
+            self.wake_task_to_ready(i);
+        }
+    }
+
+    /// kill / TaskExit（teardown_task）: 登録と fault を消し、idx が受け取っていた fault は未配送に戻す
+    pub(super) fn fault_forget_task(&mut self, idx: usize) {
+        self.faults.ep[idx] = None;
+        self.faults.pending[idx] = None;
+
+        for i in 0..self.num_tasks {
+            let Some(mut p) = self.faults.pending[i] else {
+                continue;
+            };
+            if p.handler == Some(idx) {
+                p.handler = None;
+                self.faults.pending[i] = Some(p);
+                self.fault_try_deliver(i);
+            }
+        }
+    }
+
+    /// Blocked(FaultWait{ep}) ⇔ ep 宛の未解決の fault。受け取った handler は生きていて、ep は開いている
+    #[spec("INV-FAULT-001")]
+    pub(super) fn check_fault_invariants(&self) {
+        for (idx, t) in self.tasks.iter().enumerate().take(self.num_tasks) {
+            let waiting = match t.blocked_reason {
+                Some(BlockedReason::FaultWait { ep }) if t.state.can_wait() => Some(ep),
+                _ => None,
+            };
+            let pending = self.faults.pending[idx];
+
+            if waiting != pending.map(|p| p.ep) {
+                self.invariant_violated(
+                    InvariantId::FaultWait,
+                    Some(t.id),
+                    None,
+                    "INVARIANT VIOLATION: FaultWait task and pending fault disagree",
+                );
+                LOG.info_u64("task_id", t.id.0);
+                continue;
+            }
+            let Some(p) = pending else {
+                continue;
+            };
+
+            if p.ep.0 >= MAX_ENDPOINTS || self.endpoints[p.ep.0].is_closed {
+                self.invariant_violated(
+                    InvariantId::FaultWait,
+                    Some(t.id),
+                    Some(p.ep.0 as u64),
+                    "INVARIANT VIOLATION: pending fault on a closed endpoint",
+                );
+                LOG.info_u64("task_id", t.id.0);
+            }
+            if let Some(h) = p.handler {
+                if h >= self.num_tasks || self.tasks[h].state == TaskState::Dead {
+                    self.invariant_violated(
+                        InvariantId::FaultWait,
+                        Some(t.id),
+                        Some(h as u64),
+                        "INVARIANT VIOLATION: pending fault held by a dead handler",
+                    );
+                    LOG.info_u64("task_id", t.id.0);
+                }
+            }
+        }
+    }
+
+    /// Fault Handler Report（tick ループ終了後）
+    #[cfg_attr(not(feature = "fault_handler_demo"), allow(dead_code))]
+    pub(super) fn fault_handler_report(&self) {
+        LOG.info("=== Fault Handler Report ===");
+        for (idx, t) in self.tasks.iter().enumerate().take(self.num_tasks) {
+            if let Some(ep) = self.faults.ep[idx] {
+                LOG.info_u64("task_id", t.id.0);
+                LOG.info_u64("handler_ep", ep.0 as u64);
+            }
+        }
+        LOG.info_u64("fault_delegated", self.counters.fault_delegated);
+        LOG.info_u64("fault_resumed", self.counters.fault_resumed);
+        LOG.info_u64("fault_orphaned", self.counters.fault_orphaned);
+        LOG.info("=== End of Fault Handler Report ===");
+    }
+}
@@ -0,0 +1,298 @@
+// kernel/src/kernel/fs.rs
+//
+// 役割:
+// - 読み取り専用の最小 fs。virtio-blk（arch::drivers::virtio_blk）の disk を ustar 形式の TAR として読む。
+// - FsOpen / FsRead syscall で user task に file を読ませる。ring3_tasks の user program もここから読む。
+//
+// 流れ:
+// - fs_mount（bootstrap。feature virtio_blk のときだけ）: sector 0 から TAR の header を辿り、
+//   通常 file の (名前, 大きさ, 先頭 sector) を固定長の表に入れる。中身は読まない
+//   * header の後ろに 512 byte 境界で中身が続き、次の header はその後。全 0 の block（name が空）で終わり
+//   * header の解釈（"./" を落とす / 飛ばす entry）は tar.rs（initrd.rs と共有）
+// - FsOpen { page, name_len }: page の先頭 name_len byte を名前として表を引き、task の fd table に入れる
+//   * 成功: last_syscall_ret = FS_OPEN_OK_TAG | fd
+// - FsRead { fd, page, offset }: file の offset から最大 1 page 分を page の先頭に書く（page は WRITABLE で map 済み）
+//   * 成功: last_syscall_ret = FS_READ_OK_TAG | 読んだ byte 数（offset が末尾以降なら 0）
+//   * 読むたびに disk から読む（cache なし）。1 回の読み出しは driver の MAX_SECTORS_PER_READ に収まる
+//
+// 方針:
+// - fd は task ごとの小さな表の index（MAX_FDS）。kill / exit の teardown で全部閉じる
+// - user の page には frame 経由（physmap）で触る（CR3 は切り替えない）。kernel task は使えない（FORBIDDEN）
+// - disk が無い / feature が無いときは file が 1 つも無い fs として振る舞う（FsOpen は常に SYSCALL_ERR_NO_FILE）
+// - file の中身と fd は state hash に入れない（disk image は外から来る値）
+//
+// 制限:
+// - ustar の prefix（155 byte）/ GNU・pax の拡張 header / base-256 の大きさは読まない（その entry は飛ばす）
+// - 通常 file だけ（directory / link 等は飛ばす）。名前は FS_NAME_MAX byte まで、file は MAX_FS_FILES 個まで
+//
+// やらないこと:
+// - 書き込み / FsClose / seek 位置の保持（FsRead は毎回 offset を渡す）/ directory の列挙
+
+use super::abi::{
+    FS_OPEN_OK_TAG, FS_READ_OK_TAG, SYSCALL_ERR_BAD_ASPACE, SYSCALL_ERR_BAD_FD, SYSCALL_ERR_FORBIDDEN, SYSCALL_ERR_IO,
+    SYSCALL_ERR_NOT_MAPPED, SYSCALL_ERR_NO_FD_SLOT, SYSCALL_ERR_NO_FILE,
+};
+#[cfg(feature = "virtio_blk")]
+use super::tar::TarEntry;
+use super::tar;
+use super::{KernelState, MAX_TASKS};
+use crate::arch::ops::{Arch, ArchOps};
+#[cfg(feature = "virtio_blk")]
+use crate::arch::drivers::virtio_blk;
+use crate::mem::address_space::AddressSpaceKind;
+use crate::mem::addr::{PhysFrame, VirtPage, PAGE_SIZE};
+use crate::mem::paging::PageFlags;
+
+const LOG: crate::logging::Subsystem = crate::logging::Subsystem::Mem;
+
+/// mount した file の表の大きさ
+pub(super) const MAX_FS_FILES: usize = 16;
+/// 名前の最大長（byte）
+pub(super) const FS_NAME_MAX: usize = tar::NAME_MAX;
+/// task ごとの fd の数
+pub(super) const MAX_FDS: usize = 4;
+
+const SECTOR_SIZE: u64 = tar::BLOCK_SIZE as u64;
+
+#[derive(Clone, Copy)]
+struct FsFile {
+    name: [u8; FS_NAME_MAX],
+    name_len: usize,
+    size: u64,
+    /// 中身の先頭 sector
+    start_sector: u64,
+}
+
+impl FsFile {
+    fn name(&self) -> &[u8] {
+        &self.name[..self.name_len]
+    }
+}
+
+pub(super) struct FsState {
+    mounted: bool,
+    files: [Option<FsFile>; MAX_FS_FILES],
+    nfiles: usize,
+    /// task index → fd → files の index
+    fds: [[Option<usize>; MAX_FDS]; MAX_TASKS],
+
+    // 集計（report 用）
+    skipped_entries: u64,
+    opens: u64,
+    reads: u64,
+    bytes_read: u64,
+    read_errors: u64,
+}
+
+impl FsState {
+    pub(super) const fn new() -> Self {
+        FsState {
+            mounted: false,
+            files: [None; MAX_FS_FILES],
+            nfiles: 0,
+            fds: [[None; MAX_FDS]; MAX_TASKS],
+            skipped_entries: 0,
+            opens: 0,
+            reads: 0,
+            bytes_read: 0,
+            read_errors: 0,
+        }
+    }
+
+    /// disk を TAR として読めたか（demo の milestone 用）
+    #[cfg_attr(not(feature = "fs_demo"), allow(dead_code))]
+    pub(super) fn mounted(&self) -> bool {
+        self.mounted
+    }
+
+    /// task の fd を全部閉じる（teardown から）
+    pub(super) fn close_all(&mut self, idx: usize) {
+        if idx < MAX_TASKS {
+            self.fds[idx] = [None; MAX_FDS];
+        }
+    }
+
+    fn lookup(&self, name: &[u8]) -> Option<usize> {
+        self.files[..self.nfiles].iter().position(|f| f.is_some_and(|f| f.name() == name))
+    }
+
+    fn file_of_fd(&self, idx: usize, fd: u64) -> Option<FsFile> {
+        let slot = *self.fds.get(idx)?.get(usize::try_from(fd).ok()?)?;
+        self.files[slot?]
+    }
+}
+
+/// disk の sector から count 個を読んで f に渡す（device が無ければ None）
+fn read_sectors<R>(sector: u64, count: usize, f: impl FnOnce(&[u8]) -> R) -> Option<R> {
+    #[cfg(feature = "virtio_blk")]
+    {
+        virtio_blk::read_sectors(sector, count, f)
+    }
+
+    #[cfg(not(feature = "virtio_blk"))]
+    {
+        let _ = (sector, count, f);
+        None
+    }
+}
+
+impl KernelState {
+    /// virtio-blk を探し、disk の TAR を読んで file の表を作る（bootstrap から。ring3_tasks の program 読み込みより前）
+    #[cfg(feature = "virtio_blk")]
+    pub(super) fn fs_mount(&mut self) {
+        let Some(dev) = virtio_blk::init(&mut self.phys_mem) else {
+            LOG.info("fs: no block device; FsOpen always returns SYSCALL_ERR_NO_FILE");
+            return;
+        };
+
+        let mut sector: u64 = 0;
+        while sector < dev.capacity_sectors {
+            let Some(entry) = read_sectors(sector, 1, tar::parse_header).flatten() else {
+                LOG.error("fs: not a ustar image (or read failed); stop scanning");
+                LOG.info_u64("sector", sector);
+                break;
+            };
+            let blocks = entry.blocks();
+            match entry {
+                TarEntry::End => break,
+                TarEntry::Skip { .. } => self.fs.skipped_entries += 1,
+                TarEntry::File { name, name_len, size } => {
+                    if self.fs.nfiles == MAX_FS_FILES {
+                        LOG.error("fs: file table full; the rest of the image is ignored");
+                        break;
+                    }
+                    self.fs.files[self.fs.nfiles] = Some(FsFile { name, name_len, size, start_sector: sector + 1 });
+                    self.fs.nfiles += 1;
+                }
+            }
+            sector = sector.saturating_add(blocks);
+        }
+
+        self.fs.mounted = true;
+        LOG.info("fs: mounted ustar image");
+        LOG.info_u64("fs_files", self.fs.nfiles as u64);
+        LOG.info_u64("fs_skipped_entries", self.fs.skipped_entries);
+        for f in self.fs.files[..self.fs.nfiles].iter().flatten() {
+            LOG.info(core::str::from_utf8(f.name()).unwrap_or("(non-utf8 name)"));
+            LOG.info_u64("fs_file_size", f.size);
+        }
+    }
+
+    /// file の offset から len byte（1 page まで）を読んで frame の先頭に書く（書いた byte 数）
+    /// - offset が末尾以降なら 0。device から読めなければ None
+    fn fs_read_into_frame(&mut self, file: FsFile, offset: u64, len: usize, frame: PhysFrame) -> Option<usize> {
+        if offset >= file.size {
+            return Some(0);
+        }
+        let n = (file.size - offset).min(len.min(PAGE_SIZE as usize) as u64) as usize;
+        let sector = file.start_sector + offset / SECTOR_SIZE;
+        let skip = (offset % SECTOR_SIZE) as usize;
+        let count = (skip + n).div_ceil(SECTOR_SIZE as usize);
+
+        self.fs.reads += 1;
+        let res = read_sectors(sector, count, |data| unsafe { Arch::write_bytes_to_frame(frame, &data[skip..skip + n]) });
+        if res.is_none() {
+            self.fs.read_errors += 1;
+            return None;
+        }
+        self.fs.bytes_read += n as u64;
+        Some(n)
+    }
+
+    /// ring3_tasks: 名前の file を frame に読み込む（無い / 1 page を超える / 読めなければ None）
+    #[cfg_attr(not(feature = "ring3_tasks"), allow(dead_code))]
+    pub(super) fn fs_load_into_frame(&mut self, name: &str, frame: PhysFrame) -> Option<usize> {
+        let file = self.fs.files[self.fs.lookup(name.as_bytes())?]?;
+        if file.size == 0 || file.size > PAGE_SIZE {
+            LOG.error("fs: program file is empty or larger than one page");
+            LOG.info(name);
+            LOG.info_u64("fs_file_size", file.size);
+            return None;
+        }
+        self.fs_read_into_frame(file, 0, file.size as usize, frame)
+    }
+
+    /// task の page の frame（user task で、map 済みで、writable が要るなら WRITABLE）
+    fn fs_user_frame(&self, idx: usize, page: VirtPage, writable: bool) -> Result<PhysFrame, u64> {
+        let as_idx = self.tasks[idx].address_space_id.0;
+        if as_idx >= self.num_tasks {
+            return Err(SYSCALL_ERR_BAD_ASPACE);
+        }
+        let aspace = &self.address_spaces[as_idx];
+        if aspace.kind != AddressSpaceKind::User {
+            LOG.error("fs: kernel task cannot use the fs syscalls");
+            return Err(SYSCALL_ERR_FORBIDDEN);
+        }
+        let Some(m) = aspace.lookup(page) else {
+            return Err(SYSCALL_ERR_NOT_MAPPED);
+        };
+        if writable && !m.flags.contains(PageFlags::WRITABLE) {
+            return Err(SYSCALL_ERR_FORBIDDEN);
+        }
+        Ok(m.frame)
+    }
+
+    pub(super) fn syscall_fs_open(&mut self, idx: usize, page: VirtPage, name_len: u64) -> u64 {
+        let tid = self.tasks[idx].id;
+        let frame = match self.fs_user_frame(idx, page, false) {
+            Ok(f) => f,
+            Err(e) => return e,
+        };
+        let Some(len) = usize::try_from(name_len).ok().filter(|n| (1..=FS_NAME_MAX).contains(n)) else {
+            return SYSCALL_ERR_NO_FILE;
+        };
+
+        let mut name = [0u8; FS_NAME_MAX];
+        unsafe { Arch::read_bytes_from_frame(frame, &mut name[..len]) };
+        let Some(file) = self.fs.lookup(&name[..len]) else {
+            return SYSCALL_ERR_NO_FILE;
+        };
+
+        let Some(fd) = self.fs.fds[idx].iter().position(|s| s.is_none()) else {
+            LOG.error("fs_open: fd table full");
+            LOG.info_u64("task_id", tid.0);
+            return SYSCALL_ERR_NO_FD_SLOT;
+        };
+        self.fs.fds[idx][fd] = Some(file);
+        self.fs.opens += 1;
+
+        LOG.info("fs_open: opened");
+        LOG.info_u64("task_id", tid.0);
+        LOG.info_u64("fd", fd as u64);
+        LOG.info_u64("fs_file_size", self.fs.files[file].map_or(0, |f| f.size));
+        FS_OPEN_OK_TAG | fd as u64
+    }
+
+    pub(super) fn syscall_fs_read(&mut self, idx: usize, fd: u64, page: VirtPage, offset: u64) -> u64 {
+        let Some(file) = self.fs.file_of_fd(idx, fd) else {
+            return SYSCALL_ERR_BAD_FD;
+        };
+        let frame = match self.fs_user_frame(idx, page, true) {
+            Ok(f) => f,
+            Err(e) => return e,
+        };
+        match self.fs_read_into_frame(file, offset, PAGE_SIZE as usize, frame) {
+            Some(n) => FS_READ_OK_TAG | n as u64,
+            None => {
+                LOG.error("fs_read: block device read failed");
+                LOG.info_u64("task_id", self.tasks[idx].id.0);
+                SYSCALL_ERR_IO
+            }
+        }
+    }
+
+    /// tick ループ終了後の集計
+    #[cfg_attr(not(feature = "virtio_blk"), allow(dead_code))]
+    pub(super) fn fs_report(&self) {
+        let s = &self.fs;
+        LOG.info("=== Fs Report ===");
+        LOG.info_u64("fs_mounted", s.mounted as u64);
+        LOG.info_u64("fs_files", s.nfiles as u64);
+        LOG.info_u64("fs_skipped_entries", s.skipped_entries);
+        LOG.info_u64("fs_opens", s.opens);
+        LOG.info_u64("fs_reads", s.reads);
+        LOG.info_u64("fs_bytes_read", s.bytes_read);
+        LOG.info_u64("fs_read_errors", s.read_errors);
+        LOG.info("=== End of Fs Report ===");
+    }
+}
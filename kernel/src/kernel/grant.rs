@@ -0,0 +1,390 @@
+// kernel/src/kernel/grant.rs
+//
+// 役割:
+// - IPC の page grant: sender が send の msg に自分の page を 1 つ添付すると、deliver の時点で kernel がその page の
+//   フレームを receiver の window（receiver が GrantWindowSet で選んだ page）に map する。
+//   コピー無しで 1 page を call の間だけ貸す（shm と違い、誰にいつまで見せるかを kernel が決める）。
+// - GrantWindowSet syscall と、grant の記録 / 取り消し。
+//
+// 流れ:
+// - GrantWindowSet { page }: grant を受け取る window を決める（None で外す）。window に grant が在る間は変えられない
+// - IpcSend の msg に grant（送信側の page）を付ける（IpcMessage::with_grant、abi の SYS_IPC_SEND_GRANT）
+//   * 入口（handle_syscall）で送信側に page が map されていることを見る（無ければ send しない）
+// - deliver（recv fastpath / send fastpath）: transfer_grant_with_message
+//   * sender の page のフレームを receiver の window に sender と同じ flags で map し、記録を 1 つ作る
+//   * receiver の msg では grant = window。map できなければ grant = None で msg だけ届ける（cap transfer と同じ）
+// - 取り消し（receiver の window から unmap して記録を消す。GrantRevoked の reason）:
+//   * Reply:     receiver が granter に reply したとき（ipc_reply、reply を届ける前）
+//   * CallEnded: granter が reply 以外で起こされたとき（timeout / close / dead partner の救済。wake_task_to_ready）
+//   * TaskDead:  granter / grantee のどちらかが死んだとき（teardown_task、mapping の片付けより前）
+//   * Revoked:   祖先の page の持ち主が Revoke したとき（derivation.rs。受け取った window を又貸ししていればその先も）
+//
+// 方針:
+// - 1 task が受け取れる grant は window の 1 つだけ（window が埋まっていれば次の grant は落とす）
+// - フレームは granter のもの。map で refcount を 1 つ取り、取り消しで返す（granter が先に PageUnmap しても解放されない）
+// - 受け取った window は PageUnmap できない（SYSCALL_ERR_GRANT_ACTIVE）し、swap out もしない（記録と mapping をずらさない）
+// - receiver の frame quota に数える（receiver がまだ map していないフレームなら 1 つ）
+//
+// やらないこと:
+// - 複数 page の grant、reply での grant（reply は取り消す側。入口で拒否）
+// - receiver が自分で window を返すこと（reply か call の終わりまで持つ）
+
+use super::abi::{
+    GRANT_REVOKE_CALL_ENDED, GRANT_REVOKE_REPLY, GRANT_REVOKE_REVOKED, GRANT_REVOKE_TASK_DEAD,
+    SYSCALL_ERR_BAD_PAGE_RANGE, SYSCALL_ERR_FORBIDDEN, SYSCALL_ERR_GRANT_ACTIVE, SYSCALL_OK,
+};
+use super::derivation::DerivObject;
+use super::{to_arch_frame, AddressSpaceKind, InvariantId, IpcMessage, KernelState, LogEvent, TaskState, MAX_TASKS};
+use crate::arch::ops::{Arch, ArchOps};
+use crate::mem::addr::{PhysFrame, VirtPage, PAGE_SIZE};
+use crate::mem::paging::MemAction;
+use spec_macros::spec;
+
+const LOG: crate::logging::Subsystem = crate::logging::Subsystem::Mem;
+
+/// grant を外した理由（GrantRevoked の reason）
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum GrantRevokeReason {
+    Reply,
+    CallEnded,
+    TaskDead,
+    Revoked,
+}
+
+impl GrantRevokeReason {
+    fn code(self) -> u64 {
+        match self {
+            GrantRevokeReason::Reply => GRANT_REVOKE_REPLY,
+            GrantRevokeReason::CallEnded => GRANT_REVOKE_CALL_ENDED,
+            GrantRevokeReason::TaskDead => GRANT_REVOKE_TASK_DEAD,
+            GrantRevokeReason::Revoked => GRANT_REVOKE_REVOKED,
+        }
+    }
+}
+
+/// 受け取り中の grant（grantee の task index で引く。page は grantee の window）
+#[derive(Clone, Copy)]
+struct GrantRecord {
+    granter: usize,
+    page: VirtPage,
+    frame: PhysFrame,
+}
+
+#[derive(Clone, Copy)]
+pub struct Grants {
+    /// task ごとの window（GrantWindowSet）
+    window: [Option<VirtPage>; MAX_TASKS],
+    active: [Option<GrantRecord>; MAX_TASKS],
+}
+
+impl Grants {
+    pub const fn new() -> Self {
+        Grants { window: [None; MAX_TASKS], active: [None; MAX_TASKS] }
+    }
+}
+
+impl KernelState {
+    /// user AddressSpace の index（kernel task なら None）
+    pub(super) fn grant_user_as_of(&self, idx: usize) -> Option<usize> {
+        let as_idx = self.tasks[idx].address_space_id.0;
+        (as_idx < self.num_tasks && self.address_spaces[as_idx].kind == AddressSpaceKind::User).then_some(as_idx)
+    }
+
+    /// GrantWindowSet: page = None で外す
+    pub(super) fn syscall_grant_window_set(&mut self, idx: usize, page: Option<VirtPage>) -> u64 {
+        let tid = self.tasks[idx].id;
+
+        if self.grant_user_as_of(idx).is_none() {
+            LOG.error("grant: kernel task cannot receive a grant");
+            return SYSCALL_ERR_FORBIDDEN;
+        }
+        if self.grants.active[idx].is_some() {
+            LOG.error("grant: window has an active grant; not changed");
+            LOG.info_u64("task_id", tid.0);
+            return SYSCALL_ERR_GRANT_ACTIVE;
+        }
+        if let Some(p) = page {
+            let slot_pages = Arch::USER_SPACE_SIZE / PAGE_SIZE;
+            if p.number >= slot_pages {
+                LOG.error("grant: window outside user slot");
+                LOG.info_u64("task_id", tid.0);
+                LOG.info_u64("page", p.number);
+                return SYSCALL_ERR_BAD_PAGE_RANGE;
+            }
+        }
+
+        self.grants.window[idx] = page;
+        LOG.info("grant: window set");
+        LOG.info_u64("task_id", tid.0);
+        LOG.info_u64("page", page.map_or(u64::MAX, |p| p.number));
+        SYSCALL_OK
+    }
+
+    /// send の入口: grant する page が送信側（user task）に map されているか（違えばログ + grant_failed）
+    pub(super) fn grant_source_ok(&mut self, idx: usize, page: VirtPage) -> bool {
+        let mapped = self.grant_user_as_of(idx).is_some_and(|as_idx| self.address_spaces[as_idx].lookup(page).is_some());
+        if !mapped {
+            LOG.error("grant: page to grant is not mapped in the sender; send rejected");
+            LOG.info_u64("task_id", self.tasks[idx].id.0);
+            LOG.info_u64("page", page.number);
+            self.counters.grant_failed += 1;
+        }
+        mapped
+    }
+
+    /// deliver 時の grant（from の page のフレームを to の window に map し、to から見た msg を返す）
+    pub(super) fn transfer_grant_with_message(&mut self, from_idx: usize, to_idx: usize, msg: IpcMessage) -> IpcMessage {
+        let Some(src) = msg.grant() else {
+            return msg;
+        };
+
+        let mut out = msg;
+        out.set_grant(None);
+
+        let Some(m) = self.grant_user_as_of(from_idx).and_then(|a| self.address_spaces[a].lookup(src)) else {
+            self.grant_drop("grant: sender page vanished; drop grant", from_idx);
+            return out;
+        };
+        let Some(to_as) = self.grant_user_as_of(to_idx) else {
+            self.grant_drop("grant: receiver is a kernel task; drop grant", to_idx);
+            return out;
+        };
+        let Some(window) = self.grants.window[to_idx] else {
+            self.grant_drop("grant: receiver has no window; drop grant", to_idx);
+            return out;
+        };
+        if self.grants.active[to_idx].is_some() {
+            self.grant_drop("grant: receiver window is busy; drop grant", to_idx);
+            return out;
+        }
+
+        let aspace = &self.address_spaces[to_as];
+        if aspace.frame_mapping_count(m.frame) == 0 && aspace.frames_in_use() + 1 > aspace.frame_quota() {
+            self.grant_drop("grant: receiver frame quota exceeded; drop grant", to_idx);
+            return out;
+        }
+
+        // 派生の辺を足せないなら渡さない（Revoke で取り上げられない grant を作らない。derivation.rs）
+        if !self.derivation_has_room("grant") {
+            self.grant_drop("grant: derivation table full; drop grant", to_idx);
+            return out;
+        }
+
+        let action = MemAction::Map { page: window, frame: m.frame, flags: m.flags };
+        if self.aspace_apply(to_as, action).is_err() {
+            self.grant_drop("grant: window cannot be mapped; drop grant", to_idx);
+            return out;
+        }
+        self.ref_mapped_frame(m.frame);
+
+        let arch_ok = match self.address_spaces[to_as].root_page_frame {
+            Some(root) => unsafe { self.apply_in_root(to_as, root, action) }.is_ok(),
+            None => false,
+        };
+        if !arch_ok {
+            // 論理 mapping と refcount を戻す（実ページテーブルには入っていない）
+            let _ = self.aspace_apply(to_as, MemAction::Unmap { page: window });
+            self.unref_unmapped_frame(m.frame);
+            self.grant_drop("grant: arch map failed; drop grant", to_idx);
+            return out;
+        }
+
+        self.grants.active[to_idx] = Some(GrantRecord { granter: from_idx, page: window, frame: m.frame });
+        self.counters.grant_mapped += 1;
+        self.derivation_record(
+            DerivObject::Page { task: from_idx, page: src, frame: m.frame },
+            DerivObject::Page { task: to_idx, page: window, frame: m.frame },
+        );
+
+        let from = self.tasks[from_idx].id;
+        let to = self.tasks[to_idx].id;
+        LOG.info("grant: page mapped into receiver window");
+        LOG.info_u64("from_task_id", from.0);
+        LOG.info_u64("to_task_id", to.0);
+        LOG.info_u64("page", window.number);
+        self.push_event(LogEvent::PageGranted { from, to, page: window.number, frame: m.frame.number });
+
+        out.set_grant(Some(window));
+        out
+    }
+
+    fn grant_drop(&mut self, msg: &'static str, idx: usize) {
+        LOG.error(msg);
+        LOG.info_u64("task_id", self.tasks[idx].id.0);
+        self.counters.grant_failed += 1;
+    }
+
+    /// grantee の window から grant を外す（論理 + 実ページテーブル）。フレームは granter の mapping が無ければ返す
+    fn grant_revoke(&mut self, grantee: usize, reason: GrantRevokeReason) {
+        let Some(g) = self.grants.active[grantee].take() else {
+            return;
+        };
+        // window を又貸ししていれば、その先は granter の page の派生に付け替える（Revoke からは辺を外し済み）
+        self.derivation_forget(DerivObject::Page { task: grantee, page: g.page, frame: g.frame });
+
+        let as_idx = self.tasks[grantee].address_space_id.0;
+        let action = MemAction::Unmap { page: g.page };
+        if self.aspace_apply(as_idx, action).is_ok() {
+            self.unref_unmapped_frame(g.frame);
+            if let Some(root) = self.address_spaces[as_idx].root_page_frame {
+                if unsafe { self.apply_in_root(as_idx, root, action) }.is_err() {
+                    LOG.error("grant: arch unmap failed; abort (fail-stop)");
+                    LOG.info_u64("as_idx", as_idx as u64);
+                    LOG.info_u64("virt_page_index", g.page.number);
+                    panic!("grant: arch unmap failed");
+                }
+            }
+            self.release_frame_if_unreferenced(g.frame);
+        } else {
+            LOG.error("grant: window was not mapped; record dropped");
+            LOG.info_u64("task_id", self.tasks[grantee].id.0);
+        }
+
+        self.counters.grant_revoked += 1;
+        let from = self.tasks[g.granter].id;
+        let to = self.tasks[grantee].id;
+        self.push_event(LogEvent::GrantRevoked { from, to, page: g.page.number, reason: reason.code() });
+    }
+
+    /// ipc_reply: replier が to から受け取っている grant を外す（reply を届ける前）
+    pub(super) fn grant_revoke_on_reply(&mut self, replier: usize, to: usize) {
+        if self.grants.active[replier].is_some_and(|g| g.granter == to) {
+            self.grant_revoke(replier, GrantRevokeReason::Reply);
+        }
+    }
+
+    /// Revoke から: grantee の window（page）の grant を外す
+    pub(super) fn grant_revoke_derived(&mut self, grantee: usize, page: VirtPage) {
+        if self.grants.active[grantee].is_some_and(|g| g.page == page) {
+            self.grant_revoke(grantee, GrantRevokeReason::Revoked);
+        }
+    }
+
+    /// granter の call が reply 以外で終わった: granter が渡している grant を全部外す
+    pub(super) fn grant_on_call_ended(&mut self, granter: usize) {
+        for grantee in 0..self.num_tasks {
+            if self.grants.active[grantee].is_some_and(|g| g.granter == granter) {
+                self.grant_revoke(grantee, GrantRevokeReason::CallEnded);
+            }
+        }
+    }
+
+    /// teardown_task から: idx が渡した grant と受け取った grant を外し、window も消す
+    pub(super) fn grant_forget_task(&mut self, idx: usize) {
+        for grantee in 0..self.num_tasks {
+            if grantee == idx || self.grants.active[grantee].is_some_and(|g| g.granter == idx) {
+                self.grant_revoke(grantee, GrantRevokeReason::TaskDead);
+            }
+        }
+        self.grants.window[idx] = None;
+    }
+
+    /// page が idx の受け取り中の grant の window か（PageUnmap が拒否する）
+    pub(super) fn is_grant_window_in_use(&self, idx: usize, page: VirtPage) -> bool {
+        self.grants.active[idx].is_some_and(|g| g.page == page)
+    }
+
+    /// idx が受け取り中の grant の window とフレーム
+    pub(super) fn grant_received(&self, idx: usize) -> Option<(VirtPage, PhysFrame)> {
+        self.grants.active[idx].map(|g| (g.page, g.frame))
+    }
+
+    /// frame が受け取り中の grant のフレームか（swap out しない）
+    pub(super) fn is_granted_frame(&self, frame: PhysFrame) -> bool {
+        self.grants.active.iter().flatten().any(|g| g.frame == frame)
+    }
+
+    /// 受け取り中の grant: grantee / granter は別の生きている user task で、grantee の window に
+    /// 記録と同じ確保中のフレームが map されている。Dead の task は window も grant も持たない
+    #[spec("INV-GRANT-001")]
+    pub(super) fn check_grant_invariants(&self) {
+        for idx in 0..self.num_tasks {
+            let task = self.tasks[idx].id;
+            if self.tasks[idx].state == TaskState::Dead {
+                if self.grants.window[idx].is_some() || self.grants.active[idx].is_some() {
+                    self.invariant_violated(
+                        InvariantId::Grant,
+                        Some(task),
+                        None,
+                        "INVARIANT VIOLATION: dead task keeps a grant window",
+                    );
+                    LOG.info_u64("task_id", task.0);
+                }
+                continue;
+            }
+
+            let Some(g) = self.grants.active[idx] else {
+                continue;
+            };
+
+            let granter_ok = g.granter < self.num_tasks
+                && g.granter != idx
+                && self.tasks[g.granter].state != TaskState::Dead
+                && self.grant_user_as_of(g.granter).is_some();
+            if !granter_ok {
+                self.invariant_violated(
+                    InvariantId::Grant,
+                    Some(task),
+                    Some(g.granter as u64),
+                    "INVARIANT VIOLATION: grant has a dead or invalid granter",
+                );
+                LOG.info_u64("task_id", task.0);
+                LOG.info_u64("granter_idx", g.granter as u64);
+            }
+
+            if self.grants.window[idx] != Some(g.page) {
+                self.invariant_violated(
+                    InvariantId::Grant,
+                    Some(task),
+                    Some(g.page.number),
+                    "INVARIANT VIOLATION: grant is not at the task's window",
+                );
+                LOG.info_u64("task_id", task.0);
+                LOG.info_u64("page", g.page.number);
+            }
+
+            let mapped = self
+                .grant_user_as_of(idx)
+                .and_then(|a| self.address_spaces[a].lookup(g.page))
+                .is_some_and(|m| m.frame == g.frame);
+            if !mapped {
+                self.invariant_violated(
+                    InvariantId::Grant,
+                    Some(task),
+                    Some(g.page.number),
+                    "INVARIANT VIOLATION: grant window does not map the granted frame",
+                );
+                LOG.info_u64("task_id", task.0);
+                LOG.info_u64("page", g.page.number);
+                LOG.info_u64("phys_frame_index", g.frame.number);
+            }
+
+            if !self.phys_mem.is_frame_allocated(to_arch_frame(g.frame)) {
+                self.invariant_violated(
+                    InvariantId::Grant,
+                    Some(task),
+                    Some(g.frame.number),
+                    "INVARIANT VIOLATION: granted frame is not allocated",
+                );
+                LOG.info_u64("phys_frame_index", g.frame.number);
+            }
+        }
+    }
+
+    /// grant_demo の集計
+    #[cfg_attr(not(feature = "grant_demo"), allow(dead_code))]
+    pub(super) fn grant_report(&self) {
+        LOG.info("=== Grant Report ===");
+        for (idx, t) in self.tasks.iter().enumerate().take(self.num_tasks) {
+            if let Some(p) = self.grants.window[idx] {
+                LOG.info_u64("task_id", t.id.0);
+                LOG.info_u64("window_page", p.number);
+                LOG.info_u64("grant_active", self.grants.active[idx].is_some() as u64);
+            }
+        }
+        LOG.info_u64("grant_mapped", self.counters.grant_mapped);
+        LOG.info_u64("grant_revoked", self.counters.grant_revoked);
+        LOG.info_u64("grant_failed", self.counters.grant_failed);
+        LOG.info("=== End of Grant Report ===");
+    }
+}
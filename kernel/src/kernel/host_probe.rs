@@ -0,0 +1,118 @@
+// kernel/src/kernel/host_probe.rs
+//
+// 役割:
+// - ホストの test（kernel/tests/）が KernelState を 1 syscall ずつ動かして中を覗く入口（feature host_test のときだけ在る）。
+//   * host_syscall: task を scheduler と同じ手順（dispatch_task）で Running にしてから、Syscall を 1 つ処理する
+//   * host_check_invariants: 全体の invariant 検査（debug_check_invariants）を 1 回回し、その回の違反数を返す
+//   * host_task / host_endpoint / host_cap / host_current_task: 読むだけ
+//   * host_force_state / host_force_blocked_reason: 検査が違反を拾えるかを見る test 用に、task の state / 待ちだけを
+//     書き換える（queue / 登録は触らない）
+// - test が名前で引く型と定数（cap / msg / syscall の戻り値）もここから re-export する
+//
+// 方針:
+// - 実機と同じ経路（dispatch_task / handle_syscall / debug_check_invariants / commit_invariant_violations）を呼ぶ。
+//   test 用の近道で状態を作らない（host_force_* だけが例外で、違反を作るためのもの）
+// - tick は進めない（tick は KernelState::tick を test が直接呼ぶ）
+//
+// やらないこと:
+// - 実機の build に入れる（feature host_test を立てるのは scripts/host-test.sh だけ）
+// - user program の step を止める（host_syscall と tick を混ぜると、tick 側の user program も syscall を出す）
+
+use super::{BlockedReason, KernelState, Task, TaskState};
+
+pub use super::abi::{
+    IPC_ERR_DEAD_PARTNER, IPC_ERR_ENDPOINT_CLOSED, SHM_CREATE_OK_TAG, SHM_CREATE_OK_TAG_MASK, SYSCALL_ERR_BAD_CAP,
+    SYSCALL_ERR_BAD_REPLY_CAP, SYSCALL_ERR_BAD_TASK, SYSCALL_ERR_FORBIDDEN, SYSCALL_ERR_TASK_STATE,
+    SYSCALL_ERR_WRONG_TYPE, SYSCALL_OK,
+};
+pub use super::cspace::{CapIndex, CapRights, CapSlot, KernelObject, TASK_CAP_BASE};
+pub use super::ipc::{Endpoint, IpcMessage};
+pub use super::reply_cap::ReplyCap;
+
+/// 起動時の task の index（mod.rs の TASK*_INDEX と同じ）
+pub const TASK0_INDEX: usize = super::TASK0_INDEX;
+pub const TASK1_INDEX: usize = super::TASK1_INDEX;
+pub const TASK2_INDEX: usize = super::TASK2_INDEX;
+pub const IDLE_TASK_INDEX: usize = super::IDLE_TASK_INDEX;
+
+/// 起動時から在る endpoint の数（cap slot i → EndpointId(i)。stress_ipc では 256）
+pub const STATIC_ENDPOINTS: usize = super::STATIC_ENDPOINTS;
+
+/// task index t を指す task handle の slot（cspace.rs の初期配置）
+pub const fn task_cap(t: usize) -> CapIndex {
+    CapIndex(TASK_CAP_BASE + t)
+}
+
+impl KernelState {
+    /// task idx から sc を 1 つ発行する（Ready なら Running にしてから）。Running にできない task なら false
+    pub fn host_syscall(&mut self, idx: usize, sc: super::Syscall) -> bool {
+        if !self.host_run(idx) {
+            return false;
+        }
+        let tid = self.tasks[idx].id;
+        self.push_event(super::LogEvent::SyscallIssued { task: tid });
+        self.handle_syscall(sc);
+        true
+    }
+
+    /// idx を current_task にする（schedule_next_task と同じく、前の Running は Ready に戻して ready_queue へ）
+    fn host_run(&mut self, idx: usize) -> bool {
+        if idx >= self.num_tasks {
+            return false;
+        }
+        let prev = self.current_task;
+        if prev == idx {
+            return self.tasks[idx].state == TaskState::Running;
+        }
+        if self.tasks[idx].state != TaskState::Ready {
+            return false;
+        }
+
+        if self.tasks[prev].state == TaskState::Running {
+            self.tasks[prev].state = TaskState::Ready;
+            self.tasks[prev].time_slice_used = 0;
+            self.push_event(super::LogEvent::TaskStateChanged(self.tasks[prev].id, TaskState::Ready));
+            if prev != super::IDLE_TASK_INDEX {
+                self.enqueue_ready(prev);
+            }
+        }
+        let _ = self.remove_from_ready_queue(idx);
+        self.dispatch_task(prev, idx);
+        true
+    }
+
+    /// 全体の invariant 検査を 1 回回し、この回に見つかった違反の数を返す
+    pub fn host_check_invariants(&mut self) -> u64 {
+        let before = self.counters.invariant_violations;
+        self.debug_check_invariants();
+        self.commit_invariant_violations();
+        self.counters.invariant_violations - before
+    }
+
+    pub fn host_current_task(&self) -> usize {
+        self.current_task
+    }
+
+    pub fn host_task(&self, idx: usize) -> &Task {
+        &self.tasks[idx]
+    }
+
+    pub fn host_endpoint(&self, ep: usize) -> &Endpoint {
+        &self.endpoints[ep]
+    }
+
+    /// task idx の handle table の slot
+    pub fn host_cap(&self, idx: usize, cap: CapIndex) -> Option<CapSlot> {
+        self.cspaces[idx].get(cap)
+    }
+
+    /// task の state だけを書き換える（ready_queue / endpoint の登録はそのまま。invariant 違反を作る用）
+    pub fn host_force_state(&mut self, idx: usize, state: TaskState) {
+        self.tasks[idx].state = state;
+    }
+
+    /// task の blocked_reason だけを書き換える（endpoint の recv_waiter などの登録はそのまま。invariant 違反を作る用）
+    pub fn host_force_blocked_reason(&mut self, idx: usize, reason: Option<BlockedReason>) {
+        self.tasks[idx].blocked_reason = reason;
+    }
+}
@@ -0,0 +1,155 @@
+// kernel/src/kernel/initrd.rs
+//
+// 役割（feature initrd）:
+// - boot image に付けた initrd（ustar 形式の TAR）を、名前で引いて中身の byte slice を返す。
+//   * ring3_tasks の user program（task1.bin / task2.bin）はまずここから探す（無ければ fs → 組み込み）
+//   * disk（virtio_blk / fs.rs）と違い device も syscall も要らない。kernel の中から boot 直後に読める
+//
+// 流れ:
+// - init（kernel_main、arch::init の後）: BootInfo の memory map から Package の region を探し、
+//   physmap（physical_memory_offset）越しの slice として覚える。先頭が ustar でなければ捨てる
+//   * 中身は写さない。region は Usable ではないので PMM は配らない（boot からずっと有効）
+// - find(name): header を先頭から辿り、名前が一致した通常 file の中身を返す（毎回辿る。file の数は少ない前提）
+//
+// 方針:
+// - header の解釈は tar.rs（fs.rs と同じ。"./" は落とす、読めない entry は飛ばす）
+// - Package の region が無い / feature が無いときは file が 1 つも無い initrd として振る舞う（find は常に None）
+// - 中身は読み取り専用として扱う（&'static [u8] しか渡さない）。state hash にも入れない（外から来る値）
+// - Package の region が複数あれば最初の 1 つだけを見る
+//
+// 制限:
+// - tar.rs と同じ（prefix / GNU・pax の拡張 header / base-256 の大きさは読まない。名前は tar::NAME_MAX byte まで）
+// - Package の region を作るのは bootloader の役割（bootloader 0.9 の BootInfo に initrd 専用の field は無い）
+//
+// やらないこと:
+// - ELF ローダ（この tree には無い。ring3_tasks の program は 1 page の生の機械語）
+// - replay の台本を initrd から読むこと（台本は const 表のまま。読むなら find で足りる）
+// - 書き込み / 展開して PMM のフレームへ写すこと
+
+use bootloader::BootInfo;
+#[cfg(feature = "initrd")]
+use bootloader::bootinfo::MemoryRegionType;
+
+#[cfg(feature = "initrd")]
+use core::sync::atomic::{AtomicU64, Ordering};
+
+#[cfg(feature = "initrd")]
+use super::tar::{self, TarEntry};
+#[cfg(feature = "initrd")]
+use crate::arch::paging;
+
+#[cfg(feature = "initrd")]
+const LOG: crate::logging::Subsystem = crate::logging::Subsystem::Mem;
+
+/// physmap 上の initrd の先頭と長さ（init で 1 回だけ書く。長さ 0 = 無し）
+#[cfg(feature = "initrd")]
+static INITRD_VIRT: AtomicU64 = AtomicU64::new(0);
+#[cfg(feature = "initrd")]
+static INITRD_LEN: AtomicU64 = AtomicU64::new(0);
+
+/// memory map から Package の region を探して覚える（arch::init の後。physmap が要る）
+pub fn init(boot_info: &'static BootInfo) {
+    #[cfg(feature = "initrd")]
+    {
+        if paging::physical_memory_offset() == 0 {
+            LOG.error("initrd: physmap is not ready; skip");
+            return;
+        }
+        let Some(region) = boot_info.memory_map.iter().find(|r| r.region_type == MemoryRegionType::Package) else {
+            LOG.info("initrd: no package region; find always returns None");
+            return;
+        };
+        let phys = region.range.start_frame_number * 4096;
+        let len = (region.range.end_frame_number - region.range.start_frame_number) * 4096;
+        LOG.info("initrd: package region found");
+        LOG.info_hex("initrd_phys", phys);
+        LOG.info_u64("initrd_bytes", len);
+
+        let virt = paging::physical_memory_offset() + phys;
+        let image = unsafe { core::slice::from_raw_parts(virt as *const u8, len as usize) };
+        if image.len() < tar::BLOCK_SIZE || tar::parse_header(&image[..tar::BLOCK_SIZE]).is_none() {
+            LOG.error("initrd: package is not a ustar image; ignored");
+            return;
+        }
+        INITRD_VIRT.store(virt, Ordering::Relaxed);
+        INITRD_LEN.store(len, Ordering::Relaxed);
+
+        let mut files = 0u64;
+        for_each_file(|name, data| {
+            files += 1;
+            LOG.info(core::str::from_utf8(name).unwrap_or("(non-utf8 name)"));
+            LOG.info_u64("initrd_file_size", data.len() as u64);
+            true
+        });
+        LOG.info_u64("initrd_files", files);
+    }
+
+    #[cfg(not(feature = "initrd"))]
+    let _ = boot_info;
+}
+
+/// initrd 全体（init で見つからなければ None）
+#[cfg(feature = "initrd")]
+fn image() -> Option<&'static [u8]> {
+    let len = INITRD_LEN.load(Ordering::Relaxed) as usize;
+    if len == 0 {
+        return None;
+    }
+    Some(unsafe { core::slice::from_raw_parts(INITRD_VIRT.load(Ordering::Relaxed) as *const u8, len) })
+}
+
+/// 通常 file を先頭から順に f に渡す（f が false を返したらそこで止める）
+/// - header が読めない / 中身が image の外にはみ出すところで止める（それ以降は無いものとする）
+#[cfg(feature = "initrd")]
+fn for_each_file(mut f: impl FnMut(&[u8], &'static [u8]) -> bool) {
+    let Some(image) = image() else {
+        return;
+    };
+    let mut off = 0usize;
+    while let Some(header) = image.get(off..off + tar::BLOCK_SIZE) {
+        let Some(entry) = tar::parse_header(header) else {
+            LOG.error("initrd: bad header; stop scanning");
+            LOG.info_u64("offset", off as u64);
+            return;
+        };
+        let blocks = entry.blocks() as usize;
+        match entry {
+            TarEntry::End => return,
+            TarEntry::Skip { .. } => {}
+            TarEntry::File { name, name_len, size } => {
+                let start = off + tar::BLOCK_SIZE;
+                let Some(data) = image.get(start..start.saturating_add(size as usize)) else {
+                    LOG.error("initrd: file runs past the end of the image; stop scanning");
+                    LOG.info_u64("offset", off as u64);
+                    return;
+                };
+                if !f(&name[..name_len], data) {
+                    return;
+                }
+            }
+        }
+        off = off.saturating_add(blocks.saturating_mul(tar::BLOCK_SIZE));
+    }
+}
+
+/// 名前の file の中身（無い / initrd が無ければ None）
+#[cfg_attr(not(feature = "ring3_tasks"), allow(dead_code))]
+pub fn find(name: &str) -> Option<&'static [u8]> {
+    #[cfg(feature = "initrd")]
+    {
+        let mut found = None;
+        for_each_file(|n, data| {
+            if n == name.as_bytes() {
+                found = Some(data);
+            }
+            found.is_none()
+        });
+        found
+    }
+
+    #[cfg(not(feature = "initrd"))]
+    {
+        let _ = name;
+        None
+    }
+}
@@ -0,0 +1,85 @@
+// kernel/src/kernel/input.rs
+//
+// 役割:
+// - PS/2 keyboard（IRQ1）の scancode を固定長のリングに溜め、ReadInput syscall で user task に渡す。
+//
+// 流れ:
+// - arch::interrupts の keyboard IRQ handler が port 0x60 を読み、with_kernel_state 経由で input_irq を呼ぶ
+//   * seal 前（None）は捨てる（IRQ1 は allow_external_irqs まで mask されているので普通は来ない）
+//   * リングに入れたら InputReceived（scancode / 入れた後の queued 数）を push する
+//   * 満杯なら新しい方を捨てて counters.input_dropped を増やす（event は出さない）
+// - ReadInput: 先頭（最古）の scancode を 1 つ取り出す。block しない
+//   * 成功: last_syscall_ret = INPUT_OK_TAG | scancode（下位 8bit）
+//   * 空:   SYSCALL_ERR_NO_INPUT
+//
+// 方針:
+// - scancode は set 1 の生の byte（make / break とも）。keymap への変換はしない（user 側の仕事）
+// - IRQ1 の unmask は feature ps2_keyboard のときだけ（既定は来ない。ReadInput は常に NO_INPUT）
+// - 入力は外から来る非決定的なものなので、state hash の正準状態には含めない
+//
+// やらないこと:
+// - 入力待ちの block（NotifyWait と組み合わせる等は後で）
+// - 複数 reader の振り分け（どの task が読んでも先頭を取る）
+// - 8042 controller の初期化（BIOS / QEMU の設定のまま使う）
+
+use super::abi::{INPUT_OK_TAG, SYSCALL_ERR_NO_INPUT};
+use super::{KernelState, LogEvent};
+
+/// 溜められる scancode の数
+pub(super) const INPUT_QUEUE_CAP: usize = 64;
+
+/// scancode のリングバッファ
+pub(super) struct InputQueue {
+    buf: [u8; INPUT_QUEUE_CAP],
+    head: usize,
+    len: usize,
+}
+
+impl InputQueue {
+    pub(super) const fn new() -> Self {
+        Self { buf: [0; INPUT_QUEUE_CAP], head: 0, len: 0 }
+    }
+
+    /// 末尾に入れる（満杯なら false）
+    fn push(&mut self, sc: u8) -> bool {
+        if self.len == INPUT_QUEUE_CAP {
+            return false;
+        }
+        self.buf[(self.head + self.len) % INPUT_QUEUE_CAP] = sc;
+        self.len += 1;
+        true
+    }
+
+    /// 先頭（最古）を取り出す
+    fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        let sc = self.buf[self.head];
+        self.head = (self.head + 1) % INPUT_QUEUE_CAP;
+        self.len -= 1;
+        Some(sc)
+    }
+}
+
+impl KernelState {
+    /// keyboard IRQ から: scancode を 1 つ溜める
+    pub fn input_irq(&mut self, scancode: u8) {
+        if !self.input.push(scancode) {
+            self.counters.input_dropped += 1;
+            return;
+        }
+        self.counters.input_received += 1;
+        self.push_event(LogEvent::InputReceived { scancode, queued: self.input.len });
+    }
+
+    pub(super) fn syscall_read_input(&mut self) -> u64 {
+        match self.input.pop() {
+            Some(sc) => {
+                self.counters.input_read += 1;
+                INPUT_OK_TAG | sc as u64
+            }
+            None => SYSCALL_ERR_NO_INPUT,
+        }
+    }
+}
@@ -0,0 +1,304 @@
+// kernel/src/kernel/invariant.rs
+//
+// 役割:
+// - invariant 違反を文字列のログだけでなく、型付きの event（InvariantViolated）として event log に残す。
+//
+// 記録の流れ:
+// - 各 check（debug_check_invariants と check_*_invariants、フレーム参照の更新）は &self のまま
+//   invariant_violated(id, task, detail, msg) を呼ぶ
+//   * msg は従来どおり ERROR 行で出す（"INVARIANT VIOLATION: ..."）
+//   * 違反は Cell の pending に積む（&self から書けるように。溢れた分は数だけ）
+// - tick の末尾（tick_body の直後）で commit_invariant_violations が pending を event にする
+//   * 前回の commit で出ていなかった InvariantId の違反だけ InvariantViolated を push する
+//     （同じ違反が毎 tick 続いても event log を埋めない。counters.invariant_violations は毎回数える）
+//   * 最初の違反は latch に tick ごと残す（以後変えない。Counters Dump の後に出す）
+//
+// fail-stop（feature invariant_fail_stop）:
+// - commit で違反があれば "invariant: fail-stop; halt" を出して should_halt を立てる（以後 tick は進まない）
+// - 既定は記録だけで走り続ける
+//
+// 検査の level（InvariantLevel。tick_body の run_invariant_checks が選ぶ）:
+// - Off:   何も見ない（性能計測用。違反は検出されない）
+// - Cheap: O(tasks) の部分集合だけ（task 状態 / current_task / TaskId 一意 / Sleep 期限 / CPU 時間 / idle）
+// - Full:  debug_check_invariants 全体（O(tasks × endpoints) の走査を含む。既定）
+// - PeriodicFull { every }: every tick ごとに Full、それ以外の tick は Cheap
+// - 起動時の level は feature で選ぶ（invariant_level_off / invariant_level_cheap / invariant_level_periodic）。
+//   command line の invariant=（boot_params.rs）で上書きでき、走行中も set_invariant_level で切り替えられる
+//   （同じ binary で計測と検証を回す）
+// - 実行した回数は counters.invariant_checks_full / invariant_checks_cheap（Off の tick はどちらも増えない）
+//
+// InvariantId:
+// - 値（code）は wire の word。名前は abi::INVARIANT_NAMES[code]（docs/spec/clauses.txt の ID、無いものは構造名）
+// - 追加は末尾のみ（code を変えない）
+
+use core::cell::Cell;
+
+use super::{abi, KernelState, LogEvent, TaskId};
+use crate::logging;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(u16)]
+pub enum InvariantId {
+    AddressSpaceLayout = 0,
+    TaskState = 1,
+    CurrentTask = 2,
+    ReadyQueue = 3,
+    PriorityInheritance = 4,
+    SamePriorityFifo = 5,
+    CpuTime = 6,
+    IdleTask = 7,
+    WaitQueue = 8,
+    SleepDeadline = 9,
+    IpcKernelTask = 10,
+    IpcReplyQueue = 11,
+    IpcRecvWaiter = 12,
+    IpcSendQueue = 13,
+    IpcClosedEndpoint = 14,
+    IpcDeadPartner = 15,
+    IpcDeadline = 16,
+    IpcRecvAny = 17,
+    EndpointSlot = 18,
+    EndpointOwner = 19,
+    NotificationWaiters = 20,
+    NotificationWord = 21,
+    TaskIdUnique = 22,
+    CapTransfer = 23,
+    DeadTaskCleanup = 24,
+    UserMappingRange = 25,
+    FrameRelease = 26,
+    FrameAllocated = 27,
+    FrameRefcount = 28,
+    GuardPage = 29,
+    Swap = 30,
+    Shm = 31,
+    Tlb = 32,
+    DeadRoot = 33,
+    Regions = 34,
+    KernelHeap = 35,
+    FaultWait = 36,
+    Grant = 37,
+    Derivation = 38,
+    Suspended = 39,
+    Handle = 40,
+    ReplyCap = 41,
+    IpcFifo = 42,
+    IpcBuffer = 43,
+}
+
+// 名前の表と数を揃える（最後の variant + 1）
+const _: () = assert!(abi::INVARIANT_NAMES.len() == InvariantId::IpcBuffer as usize + 1);
+// reported_mask は code ごとに 1 bit
+const _: () = assert!(abi::INVARIANT_NAMES.len() <= 64);
+
+impl InvariantId {
+    pub(super) fn code(self) -> u64 {
+        self as u64
+    }
+
+    pub(super) fn name(self) -> &'static str {
+        abi::INVARIANT_NAMES[self as usize]
+    }
+}
+
+/// 検査の level（word 表現は 0 = Off / 1 = Cheap / 2 = Full / n >= 3 = PeriodicFull { every: n }）
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum InvariantLevel {
+    Off,
+    Cheap,
+    Full,
+    PeriodicFull { every: u64 },
+}
+
+/// invariant_level_periodic の周期（tick）
+pub(super) const INVARIANT_FULL_PERIOD: u64 = 64;
+
+#[cfg(not(any(feature = "invariant_level_off", feature = "invariant_level_cheap", feature = "invariant_level_periodic")))]
+const DEFAULT_INVARIANT_LEVEL_WORD: u64 = 2;
+
+#[cfg(all(feature = "invariant_level_off", not(any(feature = "invariant_level_cheap", feature = "invariant_level_periodic"))))]
+const DEFAULT_INVARIANT_LEVEL_WORD: u64 = 0;
+
+#[cfg(all(feature = "invariant_level_cheap", not(any(feature = "invariant_level_off", feature = "invariant_level_periodic"))))]
+const DEFAULT_INVARIANT_LEVEL_WORD: u64 = 1;
+
+#[cfg(all(feature = "invariant_level_periodic", not(any(feature = "invariant_level_off", feature = "invariant_level_cheap"))))]
+const DEFAULT_INVARIANT_LEVEL_WORD: u64 = INVARIANT_FULL_PERIOD;
+
+#[cfg(any(
+    all(feature = "invariant_level_off", feature = "invariant_level_cheap"),
+    all(feature = "invariant_level_off", feature = "invariant_level_periodic"),
+    all(feature = "invariant_level_cheap", feature = "invariant_level_periodic"),
+))]
+compile_error!("invariant_level_off / invariant_level_cheap / invariant_level_periodic select different levels; enable only one");
+
+/// 起動時の level（feature で選ぶ）
+pub(super) const DEFAULT_INVARIANT_LEVEL: InvariantLevel = InvariantLevel::from_word(DEFAULT_INVARIANT_LEVEL_WORD);
+
+impl InvariantLevel {
+    pub const fn from_word(w: u64) -> Self {
+        match w {
+            0 => InvariantLevel::Off,
+            1 => InvariantLevel::Cheap,
+            2 => InvariantLevel::Full,
+            every => InvariantLevel::PeriodicFull { every },
+        }
+    }
+
+    pub fn word(self) -> u64 {
+        match self {
+            InvariantLevel::Off => 0,
+            InvariantLevel::Cheap => 1,
+            InvariantLevel::Full => 2,
+            InvariantLevel::PeriodicFull { every } => every,
+        }
+    }
+
+    /// この tick に Full を回すか（false なら Cheap か、Off なら何もしない）
+    fn full_at(self, tick: u64) -> bool {
+        match self {
+            InvariantLevel::Off | InvariantLevel::Cheap => false,
+            InvariantLevel::Full => true,
+            InvariantLevel::PeriodicFull { every } => tick % every == 0,
+        }
+    }
+}
+
+/// 1 つの違反（task / detail は違反ごとの補足。detail は ep id / frame index / as_idx など）
+#[derive(Clone, Copy)]
+pub(super) struct Violation {
+    id: InvariantId,
+    task: Option<TaskId>,
+    detail: Option<u64>,
+}
+
+/// 1 回の commit までに溜められる違反の数（超えた分は pending_dropped で数だけ）
+const PENDING_CAP: usize = 16;
+
+pub(super) struct InvariantLatch {
+    pending: [Cell<Option<Violation>>; PENDING_CAP],
+    pending_len: Cell<usize>,
+    pending_dropped: Cell<u64>,
+    // 前回の commit で違反していた InvariantId（bit = code）。続いている違反は event を出し直さない
+    reported_mask: u64,
+    // 最初の違反（tick, 違反）
+    first: Option<(u64, Violation)>,
+    // 今の検査 level
+    level: InvariantLevel,
+}
+
+impl InvariantLatch {
+    pub(super) const fn new() -> Self {
+        Self {
+            pending: [const { Cell::new(None) }; PENDING_CAP],
+            pending_len: Cell::new(0),
+            pending_dropped: Cell::new(0),
+            reported_mask: 0,
+            first: None,
+            level: DEFAULT_INVARIANT_LEVEL,
+        }
+    }
+}
+
+impl KernelState {
+    /// 検査 level を切り替える（次の tick から効く）
+    /// - 起動時の level は feature の既定。command line の invariant（boot_params.rs）があればここで入れる
+    pub fn set_invariant_level(&mut self, level: InvariantLevel) {
+        // PeriodicFull の every は 3 以上（word 表現で Off / Cheap / Full と区別できるように）
+        let level = InvariantLevel::from_word(level.word());
+        self.invariants.level = level;
+        logging::info_u64("invariant_level", level.word());
+    }
+
+    /// tick_body の末尾: level に応じて Full / Cheap の検査を回す（Off なら何もしない）
+    pub(super) fn run_invariant_checks(&mut self) {
+        let level = self.invariants.level;
+        if level == InvariantLevel::Off {
+            return;
+        }
+
+        if level.full_at(self.tick_count) {
+            self.counters.invariant_checks_full += 1;
+            self.debug_check_invariants();
+        } else {
+            self.counters.invariant_checks_cheap += 1;
+            self.check_cheap_invariants();
+        }
+    }
+
+    /// O(tasks) の部分集合（endpoint / mapping / フレームの走査をしない）
+    fn check_cheap_invariants(&self) {
+        self.check_task_state_invariants();
+        self.check_task_table_invariants();
+        self.check_sleep_deadline_invariants();
+        self.check_cpu_time_invariants();
+        self.check_idle_invariants();
+    }
+
+    /// 違反を ERROR 行で出し、次の commit まで pending に積む（&self の check から呼べる）
+    pub(super) fn invariant_violated(&self, id: InvariantId, task: Option<TaskId>, detail: Option<u64>, msg: &str) {
+        logging::error(msg);
+
+        let l = &self.invariants;
+        let len = l.pending_len.get();
+        if len < PENDING_CAP {
+            l.pending[len].set(Some(Violation { id, task, detail }));
+            l.pending_len.set(len + 1);
+        } else {
+            l.pending_dropped.set(l.pending_dropped.get() + 1);
+        }
+    }
+
+    /// tick の末尾: pending を InvariantViolated にする（新しい InvariantId の違反だけ event）
+    pub(super) fn commit_invariant_violations(&mut self) {
+        let len = self.invariants.pending_len.replace(0);
+        let dropped = self.invariants.pending_dropped.replace(0);
+
+        let mut mask = 0u64;
+        for i in 0..len {
+            let Some(v) = self.invariants.pending[i].take() else {
+                continue;
+            };
+            self.counters.invariant_violations += 1;
+
+            if self.invariants.first.is_none() {
+                self.invariants.first = Some((self.tick_count, v));
+            }
+
+            let bit = 1u64 << v.id.code();
+            if (self.invariants.reported_mask | mask) & bit == 0 {
+                self.push_event(LogEvent::InvariantViolated { id: v.id, task: v.task, detail: v.detail });
+            }
+            mask |= bit;
+        }
+        self.counters.invariant_violations += dropped;
+        self.invariants.reported_mask = mask;
+
+        #[cfg(feature = "invariant_fail_stop")]
+        if len != 0 && !self.should_halt {
+            logging::error("invariant: fail-stop; halt");
+            if let Some((tick, v)) = self.invariants.first {
+                logging::info_u64("first_violation_tick", tick);
+                logging::info_u64("invariant", v.id.code());
+            }
+            self.should_halt = true;
+        }
+    }
+
+    /// 最初の違反（latch）を出す。違反が無ければ invariant_violations = 0 だけ
+    pub(super) fn dump_invariant_latch(&self) {
+        logging::info("=== Invariant Latch ===");
+        logging::info_u64("invariant_violations", self.counters.invariant_violations);
+        if let Some((tick, v)) = self.invariants.first {
+            logging::info_u64("first_violation_tick", tick);
+            logging::info_u64("invariant", v.id.code());
+            logging::info(v.id.name());
+            if let Some(t) = v.task {
+                logging::info_u64("task", t.0);
+            }
+            if let Some(d) = v.detail {
+                logging::info_u64("detail", d);
+            }
+        }
+        logging::info("=== End of Invariant Latch ===");
+    }
+}
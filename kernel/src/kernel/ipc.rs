@@ -3,11 +3,12 @@
 // IPC（同期: send/recv/reply）
 // - Endpoint に send_queue / recv_waiter / reply_queue を持たせる。
 // - KernelState の ipc_* は、syscall からのみ呼ばれる想定。
-// - キュー順序は swap-remove で抽象化（公平性は後回し）。
+// - send_queue / reply_queue は FIFO（task_queue.rs）。先に並んだ sender が先に deliver される（INV-IPC-011）。
 //
 // 設計メモ（フォーマル化を意識）:
 // - 「前提崩れ」は panic せず、ログ＋return（fail-safe）で状態破壊を避ける。
-// - reply は “待っている sender(=reply_waiter)” に対して deliver する。
+// - reply は server が受け取った reply cap（reply_cap.rs）が名指しする client にだけ deliver する。
+//   reply_queue は endpoint ごとの待ち（close / timeout の救済用）で、reply の相手探しには使わない。
 // - Dead partner を待つ reply_waiter は永遠待ちになるため、kill 側で救済する（mod.rs 側で実施）。
 //
 // ★fastpath/slowpath 分離 + counters:
@@ -25,23 +26,176 @@
 // - キュー満杯時は “block させない/救済する” を徹底（永久待ち防止）
 // - 壊れた待ち要素（Dead / blocked_reason mismatch / pending_send_msg None 等）は掃除して救済
 // - recv_waiter が既にいる prototype 制限は明示エラーで返す（無限スピン抑制）
+//
+// ★reply obligation:
+// - deliver 後、client は Blocked(IpcReply{partner=server}) で待つ。この間 server が reply 義務を持つ。
+// - 同時に reply cap を払い出し、server の Task.last_reply_cap に入れる（reply 待ちにならない deliver では None）。
+// - 保持 tick 数が IPC_REPLY_OBLIGATION_TICKS を超えたら ServerSlow（server の TaskId）を 1 回出す。
+//   → 応答しないサービスが「client の無名 stall」ではなく server の責任として記録される。
+// - ipc_reply_timeout_abort のときは client を reply_queue から外し IPC_ERR_SERVER_TIMEOUT で起こす。
+//
+// ★recv any:
+// - IpcRecvAny は ep_mask（bit i = EndpointId(i)、i < RECV_ANY_MAX_EP）の全 endpoint に recv_waiter として登録する。
+// - どれか 1 つで deliver（または close で救済）されたら、残りの endpoint の登録も外す（release_recv_waiter）。
+// - どの endpoint から届いたかは Task.last_recv_ep に残す（IpcRecv でも同じ）。
+// - 1 つでも recv_waiter が居る endpoint があれば、登録せずに IPC_ERR_RECV_ALREADY_WAITING。
+//
+// ★call timeout:
+// - IpcSend { timeout: Some(n) } は block した時点から n tick 後（kernel clock、time.rs）を期限にする。
+//   期限は send 待ち（send_queue）と reply 待ち（reply_queue）を通して 1 つ（IpcSend -> IpcReply でも延びない）。
+// - 期限が来たら endpoint のキューから外し、IPC_ERR_TIMEOUT で起こす（expire_ipc_deadlines、毎 tick）。
+//   外して起こす部分（abort_ipc_wait）は deadlock の切断（deadlock.rs、ipc_deadlock_break）と共通。
+// - block しなかった send（timer_service / net service / buffer に入った / 即エラー）は期限を持たない。
+//
+// ★メッセージ:
+// - send/recv/reply は IpcMessage（IPC_MSG_REGS 個の MR + len）をそのまま運ぶ。
+// - sender が block している間は Task.pending_send_msg に保持する（Endpoint はコピーを持たない）。
+// - エラーコード（IPC_ERR_*）は len = 1 の MR0 として last_reply に入る。
+//
+// ★cap transfer:
+// - IpcMessage.cap_transfer に送信側の cap index を入れると、deliver（send / reply とも）の時点で
+//   受信側の空き slot に複製する（cspace.rs）。受信側の msg では cap_transfer = 受信側の slot。
+// - 複製できなければ cap_transfer = None で msg だけ届ける（MR は落とさない）。
+//
+// ★page grant:
+// - IpcMessage.grant に送信側の page を入れると、deliver（send のみ）の時点で receiver の window に同じフレームを map する
+//   （grant.rs）。受信側の msg では grant = window。map できなければ grant = None で msg だけ届ける。
+// - reply で取り消す（ipc_reply、deliver の前）。reply の msg に grant は付けられない。
+//
+// ★suspend:
+// - Suspended の recv_waiter（suspend.rs）には send fastpath で届けない。sender は slowpath で send_queue に並び、
+//   TaskResume が ipc_recv_deferred で受け取る（fault / service の通知も同じく未配送のまま残る）。
+// - send_queue / reply_queue の Suspended な sender は Blocked と同じに扱う（msg を受け取り、reply も届ける）。
+//
+// ★buffered mode:
+// - EndpointSetBuffer で slots > 0 にした endpoint は、空きがあれば sender を block させず msg を buffer に溜める（ipc_buffer.rs）。
+//   recv は buffer の先頭を send_queue の sender より先に受け取る。reply は無い（last_reply_cap = None）。
+// - buffer 満杯 / cap・grant 付き / send_queue に先客がいるときは従来の rendezvous。
 
 use super::{
-    trace, AddressSpaceKind, BlockedReason, EndpointId, KernelState, LogEvent, TaskId, TaskState, IPC_DEMO_EP0,
-    MAX_ENDPOINTS, MAX_TASKS,
+    trace, AddressSpaceKind, BlockedReason, EndpointId, InvariantId, KernelState, LogEvent, TaskId, TaskState, IPC_DEMO_EP0,
+    IPC_REPLY_OBLIGATION_TICKS, MAX_ENDPOINTS,
 };
+use super::abi::{SYSCALL_ERR_BAD_REPLY_CAP, SYSCALL_OK};
+use super::cspace::CapIndex;
+use super::ipc_buffer::MsgBuffer;
+use super::reply_cap::ReplyCap;
+use super::task_queue::TaskQueue;
+use crate::mem::addr::VirtPage;
+use spec_macros::spec;
+
+// IPC エラーコードの正本は abi.rs（既存の ipc::IPC_ERR_* 参照は re-export で維持）
+pub use super::abi::{
+    IPC_ERR_CAPACITY, IPC_ERR_DEAD_PARTNER, IPC_ERR_ENDPOINT_CLOSED, IPC_ERR_RECV_ALREADY_WAITING,
+    IPC_ERR_SERVER_TIMEOUT, IPC_ERR_TIMEOUT,
+};
+pub use super::abi::IPC_MSG_REGS;
+
+const LOG: crate::logging::Subsystem = crate::logging::Subsystem::Ipc;
+
+/// IpcRecvAny で待てる endpoint 番号の上限（ep_mask は u64）
+pub const RECV_ANY_MAX_EP: usize = 64;
+
+/// ep_mask の endpoint を番号の小さい順に返す
+pub(super) fn eps_in_mask(ep_mask: u64) -> impl Iterator<Item = EndpointId> {
+    (0..RECV_ANY_MAX_EP).filter(move |i| ep_mask & (1u64 << i) != 0).map(EndpointId)
+}
+
+/// recv 待ち（IpcRecv / IpcRecvAny）の endpoint を番号の小さい順に返す。recv 待ちでなければ空
+/// - IpcRecv の ep は mask に畳まない（endpoint id は RECV_ANY_MAX_EP を超えうる。stress_ipc は 256 個）
+pub(super) fn recv_wait_eps(reason: Option<BlockedReason>) -> impl Iterator<Item = EndpointId> {
+    let (single, ep_mask) = match reason {
+        Some(BlockedReason::IpcRecv { ep }) => (Some(ep), 0),
+        Some(BlockedReason::IpcRecvAny { ep_mask }) => (None, ep_mask),
+        _ => (None, 0),
+    };
+    single.into_iter().chain(eps_in_mask(ep_mask))
+}
+
+/// IPC メッセージ（固定長の message register + 有効語数）
+/// - len を超える MR は常に 0（ログ・比較を決定的にする）
+/// - 単語 1 個のメッセージ（従来の u64 msg / エラーコード）は word() で作る
+/// - cap_transfer: 送信時は送信側の cap index、受信後は受信側の slot（ヘッダの「cap transfer」）
+/// - grant: 送信時は送信側の page、受信後は受信側の window（ヘッダの「page grant」）
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct IpcMessage {
+    len: usize,
+    mrs: [u64; IPC_MSG_REGS],
+    cap_transfer: Option<CapIndex>,
+    grant: Option<VirtPage>,
+}
+
+impl IpcMessage {
+    pub const fn word(v: u64) -> Self {
+        let mut mrs = [0; IPC_MSG_REGS];
+        mrs[0] = v;
+        IpcMessage { len: 1, mrs, cap_transfer: None, grant: None }
+    }
 
-/// reply エラーコード（Dead partner を待っていた等）
-pub const IPC_ERR_DEAD_PARTNER: u64 = 0xDEAD_DEAD_DEAD_DEAD;
+    /// words.len() が IPC_MSG_REGS を超えたら None（切り詰めない）
+    pub fn from_words(words: &[u64]) -> Option<Self> {
+        if words.len() > IPC_MSG_REGS {
+            return None;
+        }
+        let mut mrs = [0; IPC_MSG_REGS];
+        mrs[..words.len()].copy_from_slice(words);
+        Some(IpcMessage { len: words.len(), mrs, cap_transfer: None, grant: None })
+    }
+
+    /// cap を添付する（cap は送信側の table の index）
+    pub const fn with_cap(mut self, cap: CapIndex) -> Self {
+        self.cap_transfer = Some(cap);
+        self
+    }
 
-/// endpoint close エラーコード（owner dead 等）
-pub const IPC_ERR_ENDPOINT_CLOSED: u64 = 0xC105_ED00_C105_ED00;
+    pub const fn cap_transfer(&self) -> Option<CapIndex> {
+        self.cap_transfer
+    }
 
-/// キュー満杯などの capacity エラー
-pub const IPC_ERR_CAPACITY: u64 = 0xC0DE_C0DE_C0DE_C0DE;
+    pub(super) fn set_cap_transfer(&mut self, cap: Option<CapIndex>) {
+        self.cap_transfer = cap;
+    }
 
-/// prototype 制限: recv_waiter が既に存在
-pub const IPC_ERR_RECV_ALREADY_WAITING: u64 = 0xBADC_0FFE_BADC_0FFE;
+    /// page を grant として添付する（page は送信側の AddressSpace の page）
+    pub const fn with_grant(mut self, page: VirtPage) -> Self {
+        self.grant = Some(page);
+        self
+    }
+
+    pub const fn grant(&self) -> Option<VirtPage> {
+        self.grant
+    }
+
+    pub(super) fn set_grant(&mut self, page: Option<VirtPage>) {
+        self.grant = page;
+    }
+
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// MR0（len = 0 なら 0）
+    pub const fn mr0(&self) -> u64 {
+        self.mrs[0]
+    }
+
+    /// MRi（len 以上は 0）
+    pub const fn mr(&self, i: usize) -> u64 {
+        if i < IPC_MSG_REGS {
+            self.mrs[i]
+        } else {
+            0
+        }
+    }
+
+    pub fn words(&self) -> &[u64] {
+        &self.mrs[..self.len]
+    }
+}
 
 /// Endpoint（reply_queue 版）
 #[derive(Clone, Copy)]
@@ -54,16 +208,20 @@ pub struct Endpoint {
     /// Step2: close フラグ（closed の endpoint では send/recv/reply しない）
     pub is_closed: bool,
 
+    /// slot が使用中か（EndpointCreate 前 / EndpointDelete 後の slot は false で、closed 扱い）
+    pub allocated: bool,
+
     /// “受信待ち” は単独 waiter（prototype）
     pub recv_waiter: Option<usize>,
 
-    /// “送信待ち” キュー
-    pub send_queue: [usize; MAX_TASKS],
-    pub sq_len: usize,
+    /// “送信待ち” キュー（FIFO。先頭から deliver する）
+    pub send_queue: TaskQueue,
+
+    /// “返信待ち” キュー（blocked_reason で partner を識別。reply は reply cap で途中から外す）
+    pub reply_queue: TaskQueue,
 
-    /// “返信待ち” キュー（blocked_reason で partner を識別）
-    pub reply_queue: [usize; MAX_TASKS],
-    pub rq_len: usize,
+    /// buffered mode の msg（capacity = 0 なら rendezvous。ipc_buffer.rs）
+    pub buffer: MsgBuffer,
 }
 
 impl Endpoint {
@@ -72,94 +230,53 @@ impl Endpoint {
             id,
             owner: None,
             is_closed: false,
+            allocated: true,
             recv_waiter: None,
-            send_queue: [0; MAX_TASKS],
-            sq_len: 0,
-            reply_queue: [0; MAX_TASKS],
-            rq_len: 0,
+            send_queue: TaskQueue::new(),
+            reply_queue: TaskQueue::new(),
+            buffer: MsgBuffer::new(),
         }
     }
 
-    fn send_queue_contains(&self, idx: usize) -> bool {
-        for pos in 0..self.sq_len {
-            if self.send_queue[pos] == idx {
-                return true;
-            }
-        }
-        false
+    /// 未使用の slot（EndpointCreate で払い出す）。IPC の入口は closed として拒否する
+    pub const fn unallocated(id: EndpointId) -> Self {
+        let mut e = Endpoint::new(id);
+        e.is_closed = true;
+        e.allocated = false;
+        e
+    }
+
+    pub(super) fn send_queue_contains(&self, idx: usize) -> bool {
+        self.send_queue.contains(idx)
     }
 
     fn reply_queue_contains(&self, idx: usize) -> bool {
-        for pos in 0..self.rq_len {
-            if self.reply_queue[pos] == idx {
-                return true;
-            }
-        }
-        false
+        self.reply_queue.contains(idx)
     }
 
     /// ★追加: enqueue が可能か（満杯なら false）
     fn try_enqueue_sender(&mut self, idx: usize) -> bool {
-        if self.sq_len >= MAX_TASKS {
-            return false;
-        }
-        if self.send_queue_contains(idx) {
-            return true;
-        }
-        self.send_queue[self.sq_len] = idx;
-        self.sq_len += 1;
-        true
+        self.send_queue.push_back(idx)
     }
 
+    /// 一番先に並んだ sender
     fn dequeue_sender(&mut self) -> Option<usize> {
-        if self.sq_len == 0 {
-            return None;
-        }
-        // swap-remove
-        let last = self.sq_len - 1;
-        let idx = self.send_queue[last];
-        self.sq_len -= 1;
-        Some(idx)
+        self.send_queue.pop_front()
     }
 
     /// ★追加: enqueue が可能か（満杯なら false）
     fn try_enqueue_reply_waiter(&mut self, idx: usize) -> bool {
-        if self.rq_len >= MAX_TASKS {
-            return false;
-        }
-        if self.reply_queue_contains(idx) {
-            return true;
-        }
-        self.reply_queue[self.rq_len] = idx;
-        self.rq_len += 1;
-        true
+        self.reply_queue.push_back(idx)
     }
 
-    fn remove_reply_waiter_at(&mut self, pos: usize) -> Option<usize> {
-        if pos >= self.rq_len {
-            return None;
-        }
-        // swap-remove（順序は抽象化）
-        let last = self.rq_len - 1;
-        let idx = self.reply_queue[pos];
-        self.reply_queue[pos] = self.reply_queue[last];
-        self.rq_len -= 1;
-        Some(idx)
+    /// reply_queue から idx を外す（居なければ false）
+    fn remove_reply_waiter(&mut self, idx: usize) -> bool {
+        self.reply_queue.remove(idx)
     }
 
-    /// ★追加: send_queue から特定 idx を 1つ除去（swap-remove）
+    /// ★追加: send_queue から特定 idx を 1つ除去（後ろは詰めて順序を保つ）
     fn remove_sender_idx(&mut self, idx: usize) -> bool {
-        let mut pos = 0;
-        while pos < self.sq_len {
-            if self.send_queue[pos] == idx {
-                let last = self.sq_len - 1;
-                self.send_queue[pos] = self.send_queue[last];
-                self.sq_len -= 1;
-                return true;
-            }
-            pos += 1;
-        }
-        false
+        self.send_queue.remove(idx)
     }
 }
 
@@ -188,13 +305,16 @@ impl KernelState {
 
         if self.is_kernel_task_index(idx) {
             let tid = self.tasks[idx].id;
-            crate::logging::error("ipc: kernel task is forbidden to call IPC (rejected at entry)");
-            crate::logging::info(api_name);
-            crate::logging::info_u64("task_id", tid.0);
-            crate::logging::info_u64("ep_id", ep.0 as u64);
+            LOG.error("ipc: kernel task is forbidden to call IPC (rejected at entry)");
+            LOG.info(api_name);
+            LOG.info_u64("task_id", tid.0);
+            LOG.info_u64("ep_id", ep.0 as u64);
 
             // 最小のエラー返し
-            self.tasks[idx].last_reply = Some(IPC_ERR_DEAD_PARTNER);
+            self.tasks[idx].last_reply = Some(IpcMessage::word(IPC_ERR_DEAD_PARTNER));
+            if ep.0 < MAX_ENDPOINTS {
+                self.counters.per_endpoint[ep.0].rejected_kernel_task += 1;
+            }
             return true;
         }
 
@@ -210,12 +330,13 @@ impl KernelState {
             let idx = self.current_task;
             if idx < self.num_tasks && self.tasks[idx].state != TaskState::Dead {
                 let tid = self.tasks[idx].id;
-                crate::logging::error("ipc: endpoint is CLOSED (rejected at entry)");
-                crate::logging::info(api_name);
-                crate::logging::info_u64("task_id", tid.0);
-                crate::logging::info_u64("ep_id", ep.0 as u64);
-                self.tasks[idx].last_reply = Some(IPC_ERR_ENDPOINT_CLOSED);
+                LOG.error("ipc: endpoint is CLOSED (rejected at entry)");
+                LOG.info(api_name);
+                LOG.info_u64("task_id", tid.0);
+                LOG.info_u64("ep_id", ep.0 as u64);
+                self.tasks[idx].last_reply = Some(IpcMessage::word(IPC_ERR_ENDPOINT_CLOSED));
             }
+            self.counters.per_endpoint[ep.0].rejected_closed += 1;
             return true;
         }
         false
@@ -233,7 +354,7 @@ impl KernelState {
 
         self.tasks[idx].pending_send_msg = None;
         self.tasks[idx].blocked_reason = None;
-        self.tasks[idx].last_reply = Some(err);
+        self.tasks[idx].last_reply = Some(IpcMessage::word(err));
 
         // Blocked のまま終えない
         if self.tasks[idx].state == TaskState::Blocked {
@@ -252,11 +373,12 @@ impl KernelState {
 
         self.tasks[idx].pending_send_msg = None;
         self.tasks[idx].blocked_reason = None;
-        self.tasks[idx].last_reply = Some(err);
+        self.tasks[idx].last_reply = Some(IpcMessage::word(err));
         self.wake_task_to_ready(idx);
     }
 
     /// Step2: endpoint を close し、待ちタスクを rescue する
+    #[spec("INV-IPC-006")]
     pub(super) fn close_endpoint_and_rescue_waiters(&mut self, ep: EndpointId) {
         if ep.0 >= MAX_ENDPOINTS {
             return;
@@ -267,112 +389,51 @@ impl KernelState {
         }
         self.endpoints[ep.0].is_closed = true;
 
-        crate::logging::error("ipc: endpoint CLOSED; rescuing waiters");
-        crate::logging::info_u64("ep_id", ep.0 as u64);
+        LOG.error("ipc: endpoint CLOSED; rescuing waiters");
+        LOG.info_u64("ep_id", ep.0 as u64);
+
+        // buffer に溜まった msg は受け取る task が居なくなるので捨てる（ipc_buffer.rs）
+        self.buffer_drop_on_close(ep);
+
+        // 0) ep を fault handler にしていた登録を外し、FaultWait の faulter を起こす（fault_handler.rs）
+        self.fault_on_endpoint_closed(ep);
 
         // 1) recv_waiter rescue
         if let Some(recv_idx) = self.endpoints[ep.0].recv_waiter.take() {
             if recv_idx < self.num_tasks && self.tasks[recv_idx].state != TaskState::Dead {
+                // IpcRecvAny なら他の endpoint の登録も外す
+                self.release_recv_waiter(recv_idx);
                 self.tasks[recv_idx].blocked_reason = None;
-                self.tasks[recv_idx].last_reply = Some(IPC_ERR_ENDPOINT_CLOSED);
+                self.tasks[recv_idx].last_reply = Some(IpcMessage::word(IPC_ERR_ENDPOINT_CLOSED));
                 self.wake_task_to_ready(recv_idx);
             }
         }
 
         // 2) send_queue rescue
-        while self.endpoints[ep.0].sq_len > 0 {
-            let last = self.endpoints[ep.0].sq_len - 1;
-            let send_idx = self.endpoints[ep.0].send_queue[last];
-            self.endpoints[ep.0].sq_len -= 1;
-
+        while let Some(send_idx) = self.endpoints[ep.0].send_queue.pop_front() {
             if send_idx < self.num_tasks && self.tasks[send_idx].state != TaskState::Dead {
                 self.tasks[send_idx].pending_send_msg = None;
                 self.tasks[send_idx].blocked_reason = None;
-                self.tasks[send_idx].last_reply = Some(IPC_ERR_ENDPOINT_CLOSED);
+                self.tasks[send_idx].last_reply = Some(IpcMessage::word(IPC_ERR_ENDPOINT_CLOSED));
                 self.wake_task_to_ready(send_idx);
             }
         }
 
         // 3) reply_queue rescue
-        while self.endpoints[ep.0].rq_len > 0 {
-            let last = self.endpoints[ep.0].rq_len - 1;
-            let widx = self.endpoints[ep.0].reply_queue[last];
-            self.endpoints[ep.0].rq_len -= 1;
-
+        while let Some(widx) = self.endpoints[ep.0].reply_queue.pop_front() {
             if widx < self.num_tasks && self.tasks[widx].state != TaskState::Dead {
                 self.tasks[widx].blocked_reason = None;
-                self.tasks[widx].last_reply = Some(IPC_ERR_ENDPOINT_CLOSED);
+                self.tasks[widx].last_reply = Some(IpcMessage::word(IPC_ERR_ENDPOINT_CLOSED));
                 self.wake_task_to_ready(widx);
             }
         }
     }
 
-    /// reply_queue から「partner を待っている waiter」を 1つ取り出す
-    /// ★追加: 探索中に壊れた要素は掃除して詰まりを防ぐ
-    fn take_reply_waiter_for_partner(&mut self, ep: EndpointId, partner: TaskId) -> Option<usize> {
-        if ep.0 >= MAX_ENDPOINTS {
-            crate::logging::error("ipc: take_reply_waiter_for_partner: ep out of range");
-            return None;
-        }
-
-        // ここで “救済すべき waiter” を一時退避して、endpoint への &mut 借用を先に解放する
-        let mut to_rescue: Option<usize> = None;
-
-        {
-            let e = &mut self.endpoints[ep.0];
-
-            // 後ろから見る（swap-remove との相性が良い）
-            let mut pos = e.rq_len;
-            while pos > 0 {
-                pos -= 1;
-
-                let idx = e.reply_queue[pos];
-
-                if idx >= self.num_tasks {
-                    crate::logging::error("ipc: reply_queue contains out-of-range task idx; drop");
-                    let _ = e.remove_reply_waiter_at(pos);
-                    continue;
-                }
-                if self.tasks[idx].state == TaskState::Dead {
-                    crate::logging::error("ipc: reply_queue contains DEAD task; drop");
-                    crate::logging::info_u64("task_id", self.tasks[idx].id.0);
-                    let _ = e.remove_reply_waiter_at(pos);
-                    continue;
-                }
-
-                match self.tasks[idx].blocked_reason {
-                    Some(BlockedReason::IpcReply { partner: p, ep: pep }) if p == partner && pep == ep => {
-                        // 期待ケース：この waiter を取り出して返す
-                        return e.remove_reply_waiter_at(pos);
-                    }
-
-                    _ => {
-                        // mismatch は “壊れている可能性が高い” ので掃除する（永久待ちの種になる）
-                        crate::logging::error("ipc: reply_queue blocked_reason mismatch; drop (will rescue)");
-                        crate::logging::info_u64("task_id", self.tasks[idx].id.0);
-
-                        let removed = e.remove_reply_waiter_at(pos);
-                        if removed.is_some() && to_rescue.is_none() {
-                            to_rescue = removed;
-                        }
-                        continue;
-                    }
-                }
-            }
-        } // ← ここで e の &mut borrow が確実に解放される
-
-        // endpoint から外した waiter を rescue（&mut self が使える）
-        if let Some(widx) = to_rescue {
-            self.rescue_task_with_error(widx, IPC_ERR_DEAD_PARTNER);
-        }
-
-        None
-    }
-
     // -------------------------------------------------------------------------
     // recv (fastpath/slowpath)
     // -------------------------------------------------------------------------
 
+    #[spec("INV-IPC-002", "INV-IPC-005")]
     fn ipc_recv_fastpath(&mut self, ep: EndpointId, recv_idx: usize) -> bool {
         // sender を取り出す。壊れた要素（state/blocked_reason 不整合）は捨てて次を試す。
         let send_idx = loop {
@@ -387,27 +448,28 @@ impl KernelState {
             };
 
             if idx >= self.num_tasks {
-                crate::logging::error("ipc_recv_fastpath: dequeued sender idx out of range; drop");
+                LOG.error("ipc_recv_fastpath: dequeued sender idx out of range; drop");
                 continue;
             }
             if self.tasks[idx].state == TaskState::Dead {
-                crate::logging::error("ipc_recv_fastpath: dequeued sender is DEAD; drop");
+                LOG.error("ipc_recv_fastpath: dequeued sender is DEAD; drop");
                 continue;
             }
 
             // send_queue に居る sender は Blocked(IpcSend) のはず
             match self.tasks[idx].blocked_reason {
                 Some(BlockedReason::IpcSend { ep: sep }) if sep == ep => {
-                    if self.tasks[idx].state != TaskState::Blocked {
-                        crate::logging::error("ipc_recv_fastpath: sender state is not BLOCKED; drop");
-                        crate::logging::info_u64("task_id", self.tasks[idx].id.0);
+                    // Suspended の sender の msg は受け取ってよい（sender は止まったまま reply 待ちになる）
+                    if !self.tasks[idx].state.can_wait() {
+                        LOG.error("ipc_recv_fastpath: sender state is not BLOCKED; drop");
+                        LOG.info_u64("task_id", self.tasks[idx].id.0);
                         continue;
                     }
                     break idx;
                 }
                 _ => {
-                    crate::logging::error("ipc_recv_fastpath: sender blocked_reason mismatch; drop");
-                    crate::logging::info_u64("task_id", self.tasks[idx].id.0);
+                    LOG.error("ipc_recv_fastpath: sender blocked_reason mismatch; drop");
+                    LOG.info_u64("task_id", self.tasks[idx].id.0);
                     continue;
                 }
             }
@@ -417,9 +479,9 @@ impl KernelState {
         let msg = match self.tasks[send_idx].pending_send_msg.take() {
             Some(m) => m,
             None => {
-                crate::logging::error("ipc_recv_fastpath: sender had no pending_send_msg; rescue+continue");
+                LOG.error("ipc_recv_fastpath: sender had no pending_send_msg; rescue+continue");
                 let sid = self.tasks[send_idx].id;
-                crate::logging::info_u64("sender_task_id", sid.0);
+                LOG.info_u64("sender_task_id", sid.0);
 
                 // sender は send_queue から既に外れているので、ここで rescue しないと詰む
                 self.rescue_task_with_error(send_idx, IPC_ERR_DEAD_PARTNER);
@@ -437,8 +499,8 @@ impl KernelState {
             e.try_enqueue_reply_waiter(send_idx)
         };
         if !ok {
-            crate::logging::error("ipc_recv_fastpath: reply_queue full; rescue sender");
-            crate::logging::info_u64("sender_task_id", send_id.0);
+            LOG.error("ipc_recv_fastpath: reply_queue full; rescue sender");
+            LOG.info_u64("sender_task_id", send_id.0);
             self.rescue_task_with_error(send_idx, IPC_ERR_CAPACITY);
 
             // receiver 側は msg を受け取らない（deliver しない）
@@ -446,14 +508,20 @@ impl KernelState {
         }
 
         self.block_task(send_idx, BlockedReason::IpcReply { partner: recv_id, ep });
+        self.reply_cap_mint(send_idx, recv_idx, ep);
 
+        let msg = self.transfer_cap_with_message(send_idx, recv_idx, msg);
+        let msg = self.transfer_grant_with_message(send_idx, recv_idx, msg);
         self.tasks[recv_idx].last_msg = Some(msg);
+        self.tasks[recv_idx].last_recv_ep = Some(ep);
 
         if ep == IPC_DEMO_EP0 && recv_idx == super::TASK2_INDEX && self.demo_msgs_delivered < 2 {
             self.demo_msgs_delivered += 1;
         }
 
         self.counters.ipc_recv_fast += 1;
+        self.counters.ipc_rendezvous_delivered += 1;
+        self.counters.per_endpoint[ep.0].delivered += 1;
         trace::trace_ipc_path(trace::IpcPathEvent::RecvFast);
 
         self.push_event(LogEvent::IpcDelivered { from: send_id, to: recv_id, ep, msg });
@@ -464,9 +532,9 @@ impl KernelState {
         let recv_id = self.tasks[recv_idx].id;
 
         if self.endpoints[ep.0].recv_waiter.is_some() {
-            crate::logging::error("ipc_recv_slowpath: recv_waiter already exists; recv rejected (prototype)");
+            LOG.error("ipc_recv_slowpath: recv_waiter already exists; recv rejected (prototype)");
             // ★明示エラー（無限スピン抑制）
-            self.tasks[recv_idx].last_reply = Some(IPC_ERR_RECV_ALREADY_WAITING);
+            self.tasks[recv_idx].last_reply = Some(IpcMessage::word(IPC_ERR_RECV_ALREADY_WAITING));
             return;
         }
 
@@ -483,9 +551,10 @@ impl KernelState {
         self.schedule_next_task();
     }
 
+    #[spec("INV-IPC-001")]
     pub(super) fn ipc_recv(&mut self, ep: EndpointId) {
         if ep.0 >= MAX_ENDPOINTS {
-            crate::logging::error("ipc_recv: ep out of range");
+            LOG.error("ipc_recv: ep out of range");
             return;
         }
         if self.reject_ipc_if_kernel_current("api=ipc_recv", ep) {
@@ -497,7 +566,7 @@ impl KernelState {
 
         let recv_idx = self.current_task;
         if recv_idx >= self.num_tasks {
-            crate::logging::error("ipc_recv: current_task out of range");
+            LOG.error("ipc_recv: current_task out of range");
             return;
         }
         if self.tasks[recv_idx].state == TaskState::Dead {
@@ -507,6 +576,28 @@ impl KernelState {
         let recv_id = self.tasks[recv_idx].id;
         self.push_event(LogEvent::IpcRecvCalled { task: recv_id, ep });
 
+        // 委譲された未配送の #PF があれば block せずに受け取る（fault_handler.rs）
+        if self.fault_recv_pending(recv_idx, ep) {
+            return;
+        }
+
+        // timer_service の未配送通知があれば block せずに受け取る
+        #[cfg(feature = "timer_service")]
+        if self.timer_service_recv_pending(recv_idx, ep) {
+            return;
+        }
+
+        // net service に届いている frame があれば block せずに受け取る
+        #[cfg(feature = "virtio_net")]
+        if self.net_recv_pending(recv_idx, ep) {
+            return;
+        }
+
+        // buffer に溜まった msg があれば send_queue の sender より先に受け取る（ipc_buffer.rs）
+        if self.buffer_recv_pending(recv_idx, ep) {
+            return;
+        }
+
         if self.ipc_recv_fastpath(ep, recv_idx) {
             return;
         }
@@ -514,15 +605,181 @@ impl KernelState {
         self.ipc_recv_slowpath(ep, recv_idx);
     }
 
+    /// idx を全 endpoint の recv_waiter から外す（IpcRecvAny は複数の endpoint に登録している）
+    pub(super) fn release_recv_waiter(&mut self, idx: usize) {
+        for e in self.endpoints.iter_mut() {
+            if e.recv_waiter == Some(idx) {
+                e.recv_waiter = None;
+            }
+        }
+    }
+
+    /// TaskResume（suspend.rs）: Suspended の間に届けなかった fault / service の通知 / sender を、
+    /// ipc_recv の入口と同じ順で 1 つ受け取る。受け取れたら recv_waiter から外す（起こすのは呼び出し側）
+    pub(super) fn ipc_recv_deferred(&mut self, recv_idx: usize) -> bool {
+        let reason = self.tasks[recv_idx].blocked_reason;
+        if recv_wait_eps(reason).next().is_none() {
+            return false;
+        }
+
+        let mut got = recv_wait_eps(reason).any(|ep| self.fault_recv_pending(recv_idx, ep));
+
+        #[cfg(feature = "timer_service")]
+        {
+            got = got || recv_wait_eps(reason).any(|ep| self.timer_service_recv_pending(recv_idx, ep));
+        }
+
+        #[cfg(feature = "virtio_net")]
+        {
+            got = got || recv_wait_eps(reason).any(|ep| self.net_recv_pending(recv_idx, ep));
+        }
+
+        got = got || recv_wait_eps(reason).any(|ep| self.buffer_recv_pending(recv_idx, ep));
+        got = got || recv_wait_eps(reason).any(|ep| self.ipc_recv_fastpath(ep, recv_idx));
+
+        if got {
+            self.release_recv_waiter(recv_idx);
+        }
+        got
+    }
+
+    /// ep_mask（bit i = EndpointId(i)）のどれかに届くまで待つ
+    /// - sender が既に居る endpoint があれば、番号の小さい順に fastpath で受け取る
+    /// - 届いた endpoint は last_recv_ep
+    #[spec("INV-IPC-001", "INV-IPC-009")]
+    pub(super) fn ipc_recv_any(&mut self, ep_mask: u64) {
+        if ep_mask == 0 {
+            LOG.error("ipc_recv_any: empty ep_mask");
+            return;
+        }
+        if eps_in_mask(ep_mask).any(|ep| ep.0 >= MAX_ENDPOINTS) {
+            LOG.error("ipc_recv_any: ep out of range");
+            LOG.info_u64("ep_mask", ep_mask);
+            return;
+        }
+
+        let first = EndpointId(ep_mask.trailing_zeros() as usize);
+        if self.reject_ipc_if_kernel_current("api=ipc_recv_any", first) {
+            return;
+        }
+        for ep in eps_in_mask(ep_mask) {
+            if self.reject_ipc_if_endpoint_closed("api=ipc_recv_any", ep) {
+                return;
+            }
+        }
+
+        let recv_idx = self.current_task;
+        if recv_idx >= self.num_tasks {
+            LOG.error("ipc_recv_any: current_task out of range");
+            return;
+        }
+        if self.tasks[recv_idx].state == TaskState::Dead {
+            return;
+        }
+
+        let recv_id = self.tasks[recv_idx].id;
+        LOG.info("ipc_recv_any: called");
+        LOG.info_u64("task_id", recv_id.0);
+        LOG.info_u64("ep_mask", ep_mask);
+
+        for ep in eps_in_mask(ep_mask) {
+            if self.fault_recv_pending(recv_idx, ep) {
+                return;
+            }
+        }
+
+        #[cfg(feature = "timer_service")]
+        for ep in eps_in_mask(ep_mask) {
+            if self.timer_service_recv_pending(recv_idx, ep) {
+                return;
+            }
+        }
+
+        #[cfg(feature = "virtio_net")]
+        for ep in eps_in_mask(ep_mask) {
+            if self.net_recv_pending(recv_idx, ep) {
+                return;
+            }
+        }
+
+        for ep in eps_in_mask(ep_mask) {
+            if self.buffer_recv_pending(recv_idx, ep) {
+                return;
+            }
+        }
+
+        for ep in eps_in_mask(ep_mask) {
+            if self.ipc_recv_fastpath(ep, recv_idx) {
+                return;
+            }
+        }
+
+        // slowpath: 全 endpoint に登録できるときだけ block する（部分的に登録しない）
+        if eps_in_mask(ep_mask).any(|ep| self.endpoints[ep.0].recv_waiter.is_some()) {
+            LOG.error("ipc_recv_any: recv_waiter already exists; recv rejected (prototype)");
+            self.tasks[recv_idx].last_reply = Some(IpcMessage::word(IPC_ERR_RECV_ALREADY_WAITING));
+            return;
+        }
+
+        self.counters.ipc_recv_slow += 1;
+        trace::trace_ipc_path(trace::IpcPathEvent::RecvSlow);
+
+        self.block_task(recv_idx, BlockedReason::IpcRecvAny { ep_mask });
+        for ep in eps_in_mask(ep_mask) {
+            self.endpoints[ep.0].recv_waiter = Some(recv_idx);
+            self.push_event(LogEvent::IpcRecvBlocked { task: recv_id, ep });
+        }
+
+        #[cfg(not(feature = "ring3_mailbox"))]
+        self.schedule_next_task();
+    }
+
+    /// Blocked(IpcRecvAny) の task は ep_mask の全 endpoint の recv_waiter に居る
+    /// （IpcRecvAny 以外の task が複数の endpoint に居ないことは recv_waiter の整合で見る）
+    #[spec("INV-IPC-009")]
+    pub(super) fn check_recv_any_invariants(&self) {
+        for (idx, t) in self.tasks.iter().enumerate().take(self.num_tasks) {
+            if !t.state.can_wait() {
+                continue;
+            }
+            let Some(BlockedReason::IpcRecvAny { ep_mask }) = t.blocked_reason else {
+                continue;
+            };
+            if ep_mask == 0 {
+                self.invariant_violated(
+                    InvariantId::IpcRecvAny,
+                    Some(t.id),
+                    None,
+                    "INVARIANT VIOLATION: IpcRecvAny with empty ep_mask",
+                );
+                LOG.info_u64("task_id", t.id.0);
+                continue;
+            }
+            for ep in eps_in_mask(ep_mask) {
+                if ep.0 >= MAX_ENDPOINTS || self.endpoints[ep.0].recv_waiter != Some(idx) {
+                    self.invariant_violated(
+                        InvariantId::IpcRecvAny,
+                        Some(t.id),
+                        Some(ep.0 as u64),
+                        "INVARIANT VIOLATION: IpcRecvAny task not registered as recv_waiter",
+                    );
+                    LOG.info_u64("task_id", t.id.0);
+                    LOG.info_u64("ep", ep.0 as u64);
+                }
+            }
+        }
+    }
+
     // -------------------------------------------------------------------------
     // send (fastpath/slowpath)
     // -------------------------------------------------------------------------
 
-    fn ipc_send_fastpath(&mut self, ep: EndpointId, send_idx: usize, msg: u64) -> bool {
+    #[spec("INV-IPC-002", "INV-IPC-005")]
+    fn ipc_send_fastpath(&mut self, ep: EndpointId, send_idx: usize, msg: IpcMessage) -> bool {
         if send_idx != self.current_task {
-            crate::logging::error("ipc_send_fastpath: send_idx != current_task; reject");
-            crate::logging::info_u64("send_idx", send_idx as u64);
-            crate::logging::info_u64("current_task", self.current_task as u64);
+            LOG.error("ipc_send_fastpath: send_idx != current_task; reject");
+            LOG.info_u64("send_idx", send_idx as u64);
+            LOG.info_u64("current_task", self.current_task as u64);
             return false;
         }
 
@@ -532,31 +789,47 @@ impl KernelState {
         };
 
         if recv_idx >= self.num_tasks {
-            crate::logging::error("ipc_send_fastpath: recv_waiter idx out of range");
+            LOG.error("ipc_send_fastpath: recv_waiter idx out of range");
             return false;
         }
         if self.tasks[recv_idx].state == TaskState::Dead {
-            crate::logging::error("ipc_send_fastpath: recv_waiter is DEAD; abort deliver");
+            LOG.error("ipc_send_fastpath: recv_waiter is DEAD; abort deliver");
             return false;
         }
 
         match self.tasks[recv_idx].blocked_reason {
-            Some(BlockedReason::IpcRecv { ep: rep }) if rep == ep => {}
+            Some(r) if r.waits_recv_on(ep) => {}
             _ => {
-                crate::logging::error("ipc_send_fastpath: recv_waiter blocked_reason mismatch; abort deliver");
+                LOG.error("ipc_send_fastpath: recv_waiter blocked_reason mismatch; abort deliver");
                 return false;
             }
         }
 
-        // OKなら消費
-        let _ = self.endpoints[ep.0].recv_waiter.take();
+        // Suspended の recv_waiter には届けない（sender は send_queue で待ち、TaskResume で受け取られる。suspend.rs）
+        if self.tasks[recv_idx].state == TaskState::Suspended {
+            LOG.info("ipc_send_fastpath: recv_waiter is SUSPENDED; sender queues");
+            LOG.info_u64("task_id", self.tasks[recv_idx].id.0);
+            self.counters.suspend_recv_deferred += 1;
+            return false;
+        }
+
+        // OKなら消費（IpcRecvAny なら他の endpoint の登録も外す）
+        self.release_recv_waiter(recv_idx);
 
         let send_id = self.tasks[send_idx].id;
         let recv_id = self.tasks[recv_idx].id;
+        // block で 0 に戻る前の sender の time slice（direct switch で receiver に引き継ぐ。ipc_handoff.rs）
+        #[cfg_attr(any(feature = "ring3_mailbox", feature = "ring3_mailbox_loop"), allow(unused_variables))]
+        let slice_used = self.tasks[send_idx].time_slice_used;
 
-        // receiver を READY へ
+        // receiver を READY へ（走るまでの tick 数を測る）
         self.wake_task_to_ready(recv_idx);
+        self.ipc_handoff_mark(recv_idx);
+        let msg = self.transfer_cap_with_message(send_idx, recv_idx, msg);
+        let msg = self.transfer_grant_with_message(send_idx, recv_idx, msg);
         self.tasks[recv_idx].last_msg = Some(msg);
+        self.tasks[recv_idx].last_recv_ep = Some(ep);
+        self.tasks[recv_idx].last_reply_cap = None;
 
         // sender は reply wait
         // ★reply_queue 満杯なら block させない（永久待ち防止）
@@ -565,19 +838,24 @@ impl KernelState {
             e.try_enqueue_reply_waiter(send_idx)
         };
         if !ok {
-            crate::logging::error("ipc_send_fastpath: reply_queue full; rescue sender");
-            crate::logging::info_u64("task_id", send_id.0);
-            self.tasks[send_idx].last_reply = Some(IPC_ERR_CAPACITY);
+            LOG.error("ipc_send_fastpath: reply_queue full; rescue sender");
+            LOG.info_u64("task_id", send_id.0);
+            self.tasks[send_idx].last_reply = Some(IpcMessage::word(IPC_ERR_CAPACITY));
+            // reply 待ちに入らないので call はここで終わり（渡した grant も外す）
+            self.grant_on_call_ended(send_idx);
             return true; // deliver は成立させた（recv は起こして msg を渡した）
         }
 
         self.block_task(send_idx, BlockedReason::IpcReply { partner: recv_id, ep });
+        self.reply_cap_mint(send_idx, recv_idx, ep);
 
         if ep == IPC_DEMO_EP0 && recv_idx == super::TASK2_INDEX && self.demo_msgs_delivered < 2 {
             self.demo_msgs_delivered += 1;
         }
 
         self.counters.ipc_send_fast += 1;
+        self.counters.ipc_rendezvous_delivered += 1;
+        self.counters.per_endpoint[ep.0].delivered += 1;
         trace::trace_ipc_path(trace::IpcPathEvent::SendFast);
 
         self.push_event(LogEvent::IpcDelivered { from: send_id, to: recv_id, ep, msg });
@@ -590,18 +868,20 @@ impl KernelState {
         #[cfg(all(feature = "ring3_mailbox", not(feature = "ring3_mailbox_loop")))]
         trace::trace_ipc_path(trace::IpcPathEvent::SendFast);
 
-        // それ以外は通常通り schedule
+        // それ以外は通常通り schedule（ipc_direct_switch なら receiver へ直接切り替える。ipc_handoff.rs）
         #[cfg(not(any(feature = "ring3_mailbox", feature = "ring3_mailbox_loop")))]
-        self.schedule_next_task();
+        if !self.ipc_direct_switch(send_idx, recv_idx, slice_used) {
+            self.schedule_next_task();
+        }
 
         true
     }
 
-    fn ipc_send_slowpath(&mut self, ep: EndpointId, send_idx: usize, msg: u64) {
+    fn ipc_send_slowpath(&mut self, ep: EndpointId, send_idx: usize, msg: IpcMessage) {
         if send_idx != self.current_task {
-            crate::logging::error("ipc_send_slowpath: send_idx != current_task; reject");
-            crate::logging::info_u64("send_idx", send_idx as u64);
-            crate::logging::info_u64("current_task", self.current_task as u64);
+            LOG.error("ipc_send_slowpath: send_idx != current_task; reject");
+            LOG.info_u64("send_idx", send_idx as u64);
+            LOG.info_u64("current_task", self.current_task as u64);
             return;
         }
 
@@ -616,9 +896,9 @@ impl KernelState {
             e.try_enqueue_sender(send_idx)
         };
         if !ok {
-            crate::logging::error("ipc_send_slowpath: send_queue full; reject");
-            crate::logging::info_u64("task_id", send_id.0);
-            self.tasks[send_idx].last_reply = Some(IPC_ERR_CAPACITY);
+            LOG.error("ipc_send_slowpath: send_queue full; reject");
+            LOG.info_u64("task_id", send_id.0);
+            self.tasks[send_idx].last_reply = Some(IpcMessage::word(IPC_ERR_CAPACITY));
             return;
         }
 
@@ -641,9 +921,11 @@ impl KernelState {
         self.schedule_next_task();
     }
 
-    pub(super) fn ipc_send(&mut self, ep: EndpointId, msg: u64) {
+    /// timeout: Some(n) なら block した時点から n tick で IPC_ERR_TIMEOUT（send + reply 待ちの合計）
+    #[spec("INV-IPC-001")]
+    pub(super) fn ipc_send(&mut self, ep: EndpointId, msg: IpcMessage, timeout: Option<u64>) {
         if ep.0 >= MAX_ENDPOINTS {
-            crate::logging::error("ipc_send: ep out of range");
+            LOG.error("ipc_send: ep out of range");
             return;
         }
         if self.reject_ipc_if_kernel_current("api=ipc_send", ep) {
@@ -655,7 +937,7 @@ impl KernelState {
 
         let send_idx = self.current_task;
         if send_idx >= self.num_tasks {
-            crate::logging::error("ipc_send: current_task out of range");
+            LOG.error("ipc_send: current_task out of range");
             return;
         }
         if self.tasks[send_idx].state == TaskState::Dead {
@@ -665,63 +947,80 @@ impl KernelState {
         let send_id = self.tasks[send_idx].id;
         self.push_event(LogEvent::IpcSendCalled { task: send_id, ep, msg });
 
-        if self.ipc_send_fastpath(ep, send_idx, msg) {
+        // timer_service 宛ては kernel service が即 reply する（sender は block しない）
+        #[cfg(feature = "timer_service")]
+        if ep == super::timer_service::TIMER_SERVICE_EP {
+            let r = self.timer_service_handle_send(send_idx, msg.mr0());
+            self.tasks[send_idx].last_reply = Some(IpcMessage::word(r));
             return;
         }
 
-        self.ipc_send_slowpath(ep, send_idx, msg);
-    }
-
-    // -------------------------------------------------------------------------
-    // reply
-    // -------------------------------------------------------------------------
-
-    pub(super) fn ipc_reply(&mut self, ep: EndpointId, msg: u64) {
-        if ep.0 >= MAX_ENDPOINTS {
-            crate::logging::error("ipc_reply: ep out of range");
+        // net service 宛ても kernel service が即 reply する（SEND は送り終わってから）
+        #[cfg(feature = "virtio_net")]
+        if ep == super::net::NET_SERVICE_EP {
+            let r = self.net_service_handle_send(send_idx, msg);
+            self.tasks[send_idx].last_reply = Some(r);
             return;
         }
-        if self.reject_ipc_if_kernel_current("api=ipc_reply", ep) {
+
+        // buffered mode の endpoint に空きがあれば buffer に入れて block しない（ipc_buffer.rs）
+        if self.ipc_send_buffered(ep, send_idx, msg) {
             return;
         }
-        if self.reject_ipc_if_endpoint_closed("api=ipc_reply", ep) {
-            return;
+
+        if !self.ipc_send_fastpath(ep, send_idx, msg) {
+            self.ipc_send_slowpath(ep, send_idx, msg);
+        }
+
+        // block した（send_queue / reply_queue に入った）ときだけ期限を付ける
+        if let Some(ticks) = timeout {
+            if self.tasks[send_idx].state == TaskState::Blocked {
+                let deadline = self.deadline_after_ticks(ticks);
+                self.ipc_deadline[send_idx] = Some(deadline);
+                LOG.info("ipc_send: timeout armed");
+                LOG.info_u64("task_id", send_id.0);
+                LOG.info_u64("deadline_ns", deadline.as_ns());
+            }
         }
+    }
+
+    // -------------------------------------------------------------------------
+    // reply
+    // -------------------------------------------------------------------------
 
+    /// reply cap が名指しする client に reply を届ける（戻り値は last_syscall_ret 用。成功時の書き込みは呼び出し側が省く）
+    #[spec("INV-IPC-004")]
+    pub(super) fn ipc_reply(&mut self, reply_cap: ReplyCap, msg: IpcMessage) -> u64 {
         let recv_idx = self.current_task;
         if recv_idx >= self.num_tasks {
-            crate::logging::error("ipc_reply: current_task out of range");
-            return;
+            LOG.error("ipc_reply: current_task out of range");
+            return SYSCALL_ERR_BAD_REPLY_CAP;
         }
         if self.tasks[recv_idx].state == TaskState::Dead {
-            return;
+            return SYSCALL_ERR_BAD_REPLY_CAP;
         }
 
         let recv_id = self.tasks[recv_idx].id;
 
-        let send_idx = match self.take_reply_waiter_for_partner(ep, recv_id) {
-            Some(i) => i,
-            None => {
-                trace::trace_ipc_path(trace::IpcPathEvent::ReplyNoWaiter);
-                return;
-            }
+        let Some((send_idx, ep)) = self.reply_cap_take(recv_idx, reply_cap) else {
+            trace::trace_ipc_path(trace::IpcPathEvent::ReplyNoWaiter);
+            return SYSCALL_ERR_BAD_REPLY_CAP;
         };
 
-        if send_idx >= self.num_tasks {
-            crate::logging::error("ipc_reply: reply_waiter idx out of range");
-            return;
-        }
-        if self.tasks[send_idx].state == TaskState::Dead {
-            crate::logging::error("ipc_reply: reply_waiter is DEAD; abort");
-            return;
+        // reply object が在る間は client は ep の reply_queue に居る（INV-IPC-010）。居なければ壊れているので救済する
+        if !self.endpoints[ep.0].remove_reply_waiter(send_idx) {
+            LOG.error("ipc_reply: client not in reply_queue; rescue");
+            LOG.info_u64("task_id", self.tasks[send_idx].id.0);
+            self.rescue_task_with_error(send_idx, IPC_ERR_DEAD_PARTNER);
+            return SYSCALL_OK;
         }
 
         match self.tasks[send_idx].blocked_reason {
             Some(BlockedReason::IpcReply { partner, ep: pep }) if partner == recv_id && pep == ep => {}
             _ => {
-                crate::logging::error("ipc_reply: reply_waiter blocked_reason mismatch; abort+rescue");
+                LOG.error("ipc_reply: reply_waiter blocked_reason mismatch; abort+rescue");
                 self.rescue_task_with_error(send_idx, IPC_ERR_DEAD_PARTNER);
-                return;
+                return SYSCALL_OK;
             }
         }
 
@@ -729,6 +1028,10 @@ impl KernelState {
 
         self.push_event(LogEvent::IpcReplyCalled { task: recv_id, ep, to: send_id });
 
+        // send で受け取った grant を外してから reply を届ける（grant.rs）
+        self.grant_revoke_on_reply(recv_idx, send_idx);
+
+        let msg = self.transfer_cap_with_message(recv_idx, send_idx, msg);
         self.tasks[send_idx].last_reply = Some(msg);
         self.wake_task_to_ready(send_idx);
 
@@ -739,6 +1042,181 @@ impl KernelState {
         self.counters.ipc_reply_delivered += 1;
         trace::trace_ipc_path(trace::IpcPathEvent::ReplyDelivered);
 
-        self.push_event(LogEvent::IpcReplyDelivered { from: recv_id, to: send_id, ep });
+        self.push_event(LogEvent::IpcReplyDelivered { from: recv_id, to: send_id, ep, msg });
+        SYSCALL_OK
+    }
+
+    // -------------------------------------------------------------------------
+    // reply obligation（server の応答遅延の検出）
+    // -------------------------------------------------------------------------
+
+    /// 毎 tick 呼ぶ。Blocked(IpcReply) の client ごとに server の保持 tick 数を見る。
+    pub(super) fn check_reply_obligations(&mut self) {
+        for idx in 0..self.num_tasks {
+            let since = match self.reply_wait_since[idx] {
+                Some(t) => t,
+                None => continue,
+            };
+            if !self.tasks[idx].state.can_wait() {
+                continue;
+            }
+            let (server, ep) = match self.tasks[idx].blocked_reason {
+                Some(BlockedReason::IpcReply { partner, ep }) => (partner, ep),
+                _ => continue,
+            };
+
+            let held = self.tick_count.saturating_sub(since);
+            if held <= IPC_REPLY_OBLIGATION_TICKS {
+                continue;
+            }
+
+            if !self.reply_slow_reported[idx] {
+                self.reply_slow_reported[idx] = true;
+                self.counters.ipc_server_slow += 1;
+
+                let client = self.tasks[idx].id;
+                LOG.error("ipc: server held reply obligation too long");
+                LOG.info_u64("server_task_id", server.0);
+                LOG.info_u64("client_task_id", client.0);
+                LOG.info_u64("held_ticks", held);
+
+                self.push_event(LogEvent::ServerSlow { server, client, ep, held_ticks: held });
+            }
+
+            #[cfg(feature = "ipc_reply_timeout_abort")]
+            {
+                self.abort_reply_wait(idx, ep);
+            }
+        }
+    }
+
+    /// client を reply_queue から外し、IPC_ERR_SERVER_TIMEOUT で起こす
+    #[cfg(feature = "ipc_reply_timeout_abort")]
+    fn abort_reply_wait(&mut self, idx: usize, ep: EndpointId) {
+        if ep.0 >= MAX_ENDPOINTS {
+            return;
+        }
+
+        let _ = self.endpoints[ep.0].remove_reply_waiter(idx);
+
+        self.counters.ipc_reply_timeouts += 1;
+        self.rescue_task_with_error(idx, IPC_ERR_SERVER_TIMEOUT);
+    }
+
+    // -------------------------------------------------------------------------
+    // call timeout（IpcSend { timeout }）
+    // -------------------------------------------------------------------------
+
+    /// 毎 tick 呼ぶ。期限（ipc_deadline <= now）の来た sender を
+    /// send_queue / reply_queue から外し、IPC_ERR_TIMEOUT で起こす
+    pub(super) fn expire_ipc_deadlines(&mut self) {
+        for idx in 0..self.num_tasks {
+            let deadline = match self.ipc_deadline[idx] {
+                Some(d) => d,
+                None => continue,
+            };
+            if deadline > self.now() {
+                continue;
+            }
+
+            let ep = match self.tasks[idx].blocked_reason {
+                Some(BlockedReason::IpcSend { ep }) | Some(BlockedReason::IpcReply { ep, .. })
+                    if self.tasks[idx].state.can_wait() =>
+                {
+                    ep
+                }
+                _ => {
+                    // IPC 待ちでなくなっているのに期限だけ残った（取りこぼし）。期限だけ捨てる
+                    LOG.error("ipc: stale ipc_deadline on non-IPC-blocked task; drop");
+                    LOG.info_u64("task_id", self.tasks[idx].id.0);
+                    self.ipc_deadline[idx] = None;
+                    continue;
+                }
+            };
+
+            LOG.error("ipc: call timeout; wake sender with TIMEOUT");
+            LOG.info_u64("task_id", self.tasks[idx].id.0);
+            LOG.info_u64("ep_id", ep.0 as u64);
+            LOG.info_u64("deadline_ns", deadline.as_ns());
+
+            self.counters.ipc_call_timeouts += 1;
+            self.abort_ipc_wait(idx, ep, IPC_ERR_TIMEOUT);
+        }
+    }
+
+    /// Blocked(IpcSend / IpcReply) の task を ep のキューから外し、err で起こす（timeout / deadlock の切断）
+    pub(super) fn abort_ipc_wait(&mut self, idx: usize, ep: EndpointId, err: u64) {
+        if ep.0 < MAX_ENDPOINTS {
+            let e = &mut self.endpoints[ep.0];
+            let _ = e.remove_sender_idx(idx);
+            let _ = e.remove_reply_waiter(idx);
+        }
+
+        self.rescue_task_with_error(idx, err);
+    }
+
+    /// send_queue / reply_queue は並んだ順（enqueue seq の昇順）で、send_queue の残りは deliver 済みの sender より後に並んだ
+    #[spec("INV-IPC-011")]
+    pub(super) fn check_ipc_fifo_invariants(&self) {
+        for e in self.endpoints.iter() {
+            for (name, q) in [("send_queue", &e.send_queue), ("reply_queue", &e.reply_queue)] {
+                let Some((idx, seq)) = q.order_violation() else {
+                    continue;
+                };
+                let tid = self.tasks.get(idx).map(|t| t.id);
+                self.invariant_violated(
+                    InvariantId::IpcFifo,
+                    tid,
+                    Some(e.id.0 as u64),
+                    "INVARIANT VIOLATION: endpoint queue out of FIFO order",
+                );
+                LOG.info(name);
+                LOG.info_u64("ep_id", e.id.0 as u64);
+                LOG.info_u64("task_index", idx as u64);
+                LOG.info_u64("enqueue_seq", seq);
+            }
+        }
+    }
+
+    /// 期限は Blocked(IpcSend / IpcReply) の task だけが持ち、
+    /// endpoint のキューに居る waiter の期限は過ぎていない（期限の tick で外される）
+    #[spec("INV-IPC-008")]
+    pub(super) fn check_ipc_deadline_invariants(&self) {
+        for idx in 0..self.num_tasks {
+            let Some(deadline) = self.ipc_deadline[idx] else {
+                continue;
+            };
+            let t = &self.tasks[idx];
+            let ipc_blocked = t.state.can_wait()
+                && matches!(t.blocked_reason, Some(BlockedReason::IpcSend { .. }) | Some(BlockedReason::IpcReply { .. }));
+            if !ipc_blocked {
+                self.invariant_violated(
+                    InvariantId::IpcDeadline,
+                    Some(t.id),
+                    None,
+                    "INVARIANT VIOLATION: ipc_deadline on task not blocked in IpcSend/IpcReply",
+                );
+                LOG.info_u64("task_id", t.id.0);
+                continue;
+            }
+
+            if deadline >= self.now() {
+                continue;
+            }
+            for e in self.endpoints.iter() {
+                if e.send_queue_contains(idx) || e.reply_queue_contains(idx) {
+                    self.invariant_violated(
+                        InvariantId::IpcDeadline,
+                        Some(t.id),
+                        Some(e.id.0 as u64),
+                        "INVARIANT VIOLATION: expired IPC waiter left in endpoint queue",
+                    );
+                    LOG.info_u64("task_id", t.id.0);
+                    LOG.info_u64("ep_id", e.id.0 as u64);
+                    LOG.info_u64("deadline_ns", deadline.as_ns());
+                    LOG.info_u64("now_ns", self.now().as_ns());
+                }
+            }
+        }
     }
 }
@@ -0,0 +1,251 @@
+// kernel/src/kernel/ipc_buffer.rs
+//
+// 役割:
+// - endpoint の message buffer（任意）: 小さな固定長のリングに msg を溜め、buffer に空きがあれば sender を block させない。
+//   buffer を持たない endpoint（既定）は従来どおりの rendezvous（sender は deliver 後に reply 待ち）。
+//
+// 使い方:
+// - EndpointSetBuffer { cap, slots }: cap は Recv の rights が要る（受け取る側が決める）。slots = 0 で rendezvous に戻す
+//   * slots > ENDPOINT_BUFFER_SLOTS / 今溜まっている数より小さい: SYSCALL_ERR_CAPACITY（変えない）
+//   * closed の endpoint: SYSCALL_ERR_BAD_ENDPOINT
+//
+// send（ipc.rs の ipc_send から、fastpath より前）:
+// - buffer に入れる条件: slots > 0、空きがある、send_queue が空（先に rendezvous で並んだ sender を追い越さない）、
+//   msg に cap / page grant が付いていない（どちらも deliver の時点で sender から写すので、sender が待たないと渡せない）
+// - 入れたら sender は last_reply = IPC_BUFFERED_OK_TAG | 入れた後の長さ で即座に戻る（reply は無い）
+//   * Blocked の recv_waiter が居ればその場で渡して起こす（Suspended なら TaskResume まで buffer に残す）
+// - 条件を満たさなければ従来の rendezvous（recv_waiter へ fastpath / send_queue で待つ）
+//
+// recv（ipc_recv / ipc_recv_any / ipc_recv_deferred）:
+// - buffer が空でなければ先頭の msg を block せずに受け取る（send_queue の sender より先。先に届いたもの）
+//   * reply 待ちの sender は居ないので last_reply_cap = None
+//
+// 後始末:
+// - endpoint の close（owner の死 / EndpointDelete）で溜まった msg は捨てる（ipc_buffer_dropped）
+// - sender が死んでも、buffer に入った msg は届ける（送った時点で sender の手を離れている）
+//
+// やらないこと:
+// - buffer 満杯の sender を buffer の空き待ちで block させること（満杯なら rendezvous で送る）
+// - 動的な大きさ（slot 数は ENDPOINT_BUFFER_SLOTS まで）
+
+use super::abi::{IPC_BUFFERED_OK_TAG, SYSCALL_ERR_BAD_ENDPOINT, SYSCALL_ERR_CAPACITY, SYSCALL_ERR_WRONG_TYPE, SYSCALL_OK};
+use super::cspace::{CapIndex, CapRights, KernelObject, ObjectKind};
+use super::{EndpointId, InvariantId, IpcMessage, KernelState, LogEvent, TaskId, TaskState};
+use spec_macros::spec;
+
+const LOG: crate::logging::Subsystem = crate::logging::Subsystem::Ipc;
+
+/// endpoint 1 つが持てる buffer の slot 数の上限
+pub const ENDPOINT_BUFFER_SLOTS: usize = 4;
+
+/// 溜まった msg のリング（capacity = 0 なら rendezvous の endpoint）
+#[derive(Clone, Copy)]
+pub struct MsgBuffer {
+    /// (送った task, msg)
+    msgs: [Option<(TaskId, IpcMessage)>; ENDPOINT_BUFFER_SLOTS],
+    head: usize,
+    len: usize,
+    capacity: usize,
+}
+
+impl MsgBuffer {
+    pub const fn new() -> Self {
+        MsgBuffer { msgs: [None; ENDPOINT_BUFFER_SLOTS], head: 0, len: 0, capacity: 0 }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn has_space(&self) -> bool {
+        self.len < self.capacity
+    }
+
+    /// 古い順の (送った task, msg)（dump / state_hash 用）
+    pub fn iter(&self) -> impl Iterator<Item = (TaskId, IpcMessage)> + '_ {
+        (0..self.len).filter_map(move |pos| self.msgs[(self.head + pos) % ENDPOINT_BUFFER_SLOTS])
+    }
+
+    fn push(&mut self, from: TaskId, msg: IpcMessage) -> bool {
+        if !self.has_space() {
+            return false;
+        }
+        self.msgs[(self.head + self.len) % ENDPOINT_BUFFER_SLOTS] = Some((from, msg));
+        self.len += 1;
+        true
+    }
+
+    fn pop(&mut self) -> Option<(TaskId, IpcMessage)> {
+        if self.len == 0 {
+            return None;
+        }
+        let m = self.msgs[self.head].take();
+        self.head = (self.head + 1) % ENDPOINT_BUFFER_SLOTS;
+        self.len -= 1;
+        m
+    }
+
+    /// 溜まった msg を捨てる（捨てた数）
+    fn clear(&mut self) -> usize {
+        let n = self.len;
+        while self.pop().is_some() {}
+        n
+    }
+}
+
+impl KernelState {
+    pub(super) fn syscall_endpoint_set_buffer(&mut self, idx: usize, cap: CapIndex, slots: usize) -> u64 {
+        let tid = self.tasks[idx].id;
+
+        let ep = match self.resolve_cap(idx, cap, ObjectKind::Endpoint, CapRights::RECV, "endpoint_set_buffer") {
+            Ok(KernelObject::Endpoint(ep)) => ep,
+            Ok(_) => return SYSCALL_ERR_WRONG_TYPE,
+            Err(e) => return e.syscall_code(),
+        };
+        if self.endpoints[ep.0].is_closed {
+            LOG.error("endpoint_set_buffer: endpoint is closed");
+            LOG.info_u64("task_id", tid.0);
+            LOG.info_u64("ep_id", ep.0 as u64);
+            return SYSCALL_ERR_BAD_ENDPOINT;
+        }
+
+        let buf = &mut self.endpoints[ep.0].buffer;
+        if slots > ENDPOINT_BUFFER_SLOTS || slots < buf.len() {
+            LOG.error("endpoint_set_buffer: slots out of range");
+            LOG.info_u64("task_id", tid.0);
+            LOG.info_u64("ep_id", ep.0 as u64);
+            LOG.info_u64("slots", slots as u64);
+            LOG.info_u64("buffered", buf.len() as u64);
+            return SYSCALL_ERR_CAPACITY;
+        }
+        buf.capacity = slots;
+
+        LOG.info("endpoint_set_buffer: set");
+        LOG.info_u64("task_id", tid.0);
+        LOG.info_u64("ep_id", ep.0 as u64);
+        LOG.info_u64("slots", slots as u64);
+        SYSCALL_OK
+    }
+
+    /// buffer に入れられれば入れて sender を戻す（入れなければ false で、呼び出し側が rendezvous で送る）
+    pub(super) fn ipc_send_buffered(&mut self, ep: EndpointId, send_idx: usize, msg: IpcMessage) -> bool {
+        let e = &self.endpoints[ep.0];
+        if !e.buffer.has_space() || !e.send_queue.is_empty() {
+            return false;
+        }
+        if msg.cap_transfer().is_some() || msg.grant().is_some() {
+            return false;
+        }
+
+        let send_id = self.tasks[send_idx].id;
+        let buf = &mut self.endpoints[ep.0].buffer;
+        if !buf.push(send_id, msg) {
+            return false;
+        }
+        let len = buf.len() as u64;
+
+        self.counters.ipc_buffered_sends += 1;
+        self.tasks[send_idx].last_reply = Some(IpcMessage::word(IPC_BUFFERED_OK_TAG | len));
+
+        self.ipc_buffer_wake_waiter(ep);
+        true
+    }
+
+    /// Blocked の recv_waiter が居れば buffer の先頭を渡して起こす
+    fn ipc_buffer_wake_waiter(&mut self, ep: EndpointId) {
+        let Some(w) = self.endpoints[ep.0].recv_waiter else {
+            return;
+        };
+        if w >= self.num_tasks || self.tasks[w].state != TaskState::Blocked {
+            return;
+        }
+        if !matches!(self.tasks[w].blocked_reason, Some(r) if r.waits_recv_on(ep)) {
+            return;
+        }
+
+        // IpcRecvAny なら他の endpoint の登録も外す
+        self.release_recv_waiter(w);
+        self.wake_task_to_ready(w);
+        let _ = self.buffer_recv_pending(w, ep);
+    }
+
+    /// buffer に msg があれば先頭を idx に渡す（block しない recv）
+    pub(super) fn buffer_recv_pending(&mut self, idx: usize, ep: EndpointId) -> bool {
+        let Some((from, msg)) = self.endpoints[ep.0].buffer.pop() else {
+            return false;
+        };
+        self.tasks[idx].last_msg = Some(msg);
+        self.tasks[idx].last_recv_ep = Some(ep);
+        self.tasks[idx].last_reply_cap = None;
+
+        self.counters.ipc_buffered_delivered += 1;
+        self.counters.per_endpoint[ep.0].delivered += 1;
+        let to = self.tasks[idx].id;
+        self.push_event(LogEvent::IpcDelivered { from, to, ep, msg });
+        true
+    }
+
+    /// close（close_endpoint_and_rescue_waiters）: 溜まった msg を捨てる
+    pub(super) fn buffer_drop_on_close(&mut self, ep: EndpointId) {
+        let dropped = self.endpoints[ep.0].buffer.clear();
+        if dropped == 0 {
+            return;
+        }
+        LOG.error("ipc_buffer: endpoint closed; buffered msgs dropped");
+        LOG.info_u64("ep_id", ep.0 as u64);
+        LOG.info_u64("dropped", dropped as u64);
+        self.counters.ipc_buffer_dropped += dropped as u64;
+    }
+
+    /// buffer の長さ <= slots <= ENDPOINT_BUFFER_SLOTS、closed の endpoint と Blocked の recv_waiter の前に msg は溜まらない
+    #[spec("INV-IPC-012")]
+    pub(super) fn check_ipc_buffer_invariants(&self) {
+        for e in self.endpoints.iter() {
+            let b = &e.buffer;
+            if b.capacity() > ENDPOINT_BUFFER_SLOTS || b.len() > b.capacity() {
+                self.invariant_violated(
+                    InvariantId::IpcBuffer,
+                    None,
+                    Some(e.id.0 as u64),
+                    "INVARIANT VIOLATION: endpoint buffer exceeds its capacity",
+                );
+                LOG.info_u64("ep_id", e.id.0 as u64);
+                LOG.info_u64("buffer_len", b.len() as u64);
+                LOG.info_u64("buffer_capacity", b.capacity() as u64);
+            }
+
+            if b.is_empty() {
+                continue;
+            }
+            if e.is_closed {
+                self.invariant_violated(
+                    InvariantId::IpcBuffer,
+                    None,
+                    Some(e.id.0 as u64),
+                    "INVARIANT VIOLATION: closed endpoint holds buffered msgs",
+                );
+                LOG.info_u64("ep_id", e.id.0 as u64);
+            }
+            if let Some(w) = e.recv_waiter {
+                if w < self.num_tasks && self.tasks[w].state == TaskState::Blocked {
+                    self.invariant_violated(
+                        InvariantId::IpcBuffer,
+                        Some(self.tasks[w].id),
+                        Some(e.id.0 as u64),
+                        "INVARIANT VIOLATION: buffered msgs wait while a receiver is blocked",
+                    );
+                    LOG.info_u64("ep_id", e.id.0 as u64);
+                    LOG.info_u64("task_id", self.tasks[w].id.0);
+                }
+            }
+        }
+    }
+}
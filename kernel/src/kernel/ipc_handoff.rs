@@ -0,0 +1,102 @@
+// kernel/src/kernel/ipc_handoff.rs
+//
+// 役割:
+// - IPC の send fastpath で起こした receiver が実際に走るまでの「受け渡し」を扱う。
+//   * ipc_direct_switch: scheduler に選ばせず、その場で receiver に切り替える（seL4 の direct switch）
+//   * 計測（feature に関係なく）: 起こされてから Running になるまでの tick 数を、direct / scheduled で分けて数える
+//
+// direct switch（ipc_direct_switch、ipc.rs の ipc_send_fastpath の末尾）:
+// - sender は Blocked(IpcReply) で、receiver は Ready（ready_queue に居る）のときだけ
+// - receiver より実効優先度の高い Ready task が居れば切り替えない（優先度順の scheduling を崩さない）
+// - 同優先度の Ready task を追い越すのは、その task の追い越され回数（rq_same_prio_passes）が
+//   bounded waiting（< num_tasks、INV-SCHED-004）に収まるときだけ
+// - sender の time slice の残り（使った tick 数）を receiver に引き継ぐ。client / server の往復は 1 つの quantum を分け合う
+// - 切り替えなかったときは従来どおり schedule_next_task（ipc_direct_switch_declined）
+// - ring3_mailbox 系は fastpath で schedule しないので direct switch も無い（計測だけ）
+//
+// 計測（counters）:
+// - ipc_handoff_direct: direct switch で走らせた回数（起こされた tick のうちに走るので 0 tick）
+// - ipc_handoff_scheduled / ipc_handoff_scheduled_ticks / ipc_handoff_scheduled_max_ticks:
+//   scheduler が選んだ回数と、起こされてから走るまでの tick 数の合計 / 最大
+// - feature の有無で同じ台本を回し、scheduled_ticks / scheduled の平均と direct の割合を比べる
+//
+// やらないこと:
+// - reply / recv fastpath での direct switch（reply は client を Ready に戻すだけ。server は走り続ける）
+// - 優先度の逆転を許す切替（優先度の高い Ready task を飛ばして receiver を走らせること）
+
+use super::{KernelState, LogEvent, TaskState, IDLE_TASK_INDEX};
+
+impl KernelState {
+    /// send fastpath で receiver を起こした: 走るまでの tick 数を測り始める
+    pub(super) fn ipc_handoff_mark(&mut self, recv_idx: usize) {
+        self.ipc_handoff_since[recv_idx] = Some(self.tick_count);
+    }
+
+    /// schedule_next_task が next_idx を選んだ（send fastpath で起こされた task なら待った tick 数を数える）
+    pub(super) fn ipc_handoff_record_scheduled(&mut self, next_idx: usize) {
+        let Some(since) = self.ipc_handoff_since[next_idx].take() else {
+            return;
+        };
+        let waited = self.tick_count.saturating_sub(since);
+        self.counters.ipc_handoff_scheduled += 1;
+        self.counters.ipc_handoff_scheduled_ticks += waited;
+        if waited > self.counters.ipc_handoff_scheduled_max_ticks {
+            self.counters.ipc_handoff_scheduled_max_ticks = waited;
+        }
+    }
+
+    /// send fastpath の末尾: receiver へ直接切り替えられれば切り替える（切り替えなければ false で、呼び出し側が schedule）
+    /// - slice_used: block する前の sender の time_slice_used（receiver に引き継ぐ）
+    #[cfg_attr(any(feature = "ring3_mailbox", feature = "ring3_mailbox_loop"), allow(dead_code))]
+    pub(super) fn ipc_direct_switch(&mut self, send_idx: usize, recv_idx: usize, slice_used: u64) -> bool {
+        if !cfg!(feature = "ipc_direct_switch") {
+            return false;
+        }
+        if send_idx != self.current_task || self.tasks[send_idx].state == TaskState::Running {
+            return false;
+        }
+        if recv_idx == IDLE_TASK_INDEX || self.tasks[recv_idx].state != TaskState::Ready {
+            return false;
+        }
+        if !self.ipc_direct_switch_allowed(recv_idx) {
+            self.counters.ipc_direct_switch_declined += 1;
+            return false;
+        }
+
+        // 追い越した同優先度の Ready task は「dispatch を 1 回待った」（dequeue_ready_by_policy と同じ数え方）
+        let prio = self.tasks[recv_idx].priority;
+        for pos in 0..self.rq_len {
+            let idx = self.ready_queue[pos];
+            if idx != recv_idx && idx < self.num_tasks && self.tasks[idx].priority == prio {
+                self.rq_same_prio_passes[idx] += 1;
+                if self.rq_same_prio_passes[idx] > self.counters.sched_rr_max_passes {
+                    self.counters.sched_rr_max_passes = self.rq_same_prio_passes[idx];
+                }
+            }
+        }
+
+        let _ = self.remove_from_ready_queue(recv_idx);
+        self.push_event(LogEvent::ReadyDequeued(self.tasks[recv_idx].id));
+
+        self.ipc_handoff_since[recv_idx] = None;
+        self.counters.ipc_handoff_direct += 1;
+
+        self.dispatch_task(send_idx, recv_idx);
+        // dispatch_task は 0 にするので、sender の残りをここで引き継ぐ
+        self.tasks[recv_idx].time_slice_used = slice_used;
+        true
+    }
+
+    /// receiver より優先度の高い Ready task が無く、追い越す同優先度の task が bounded waiting に収まる
+    #[cfg_attr(any(feature = "ring3_mailbox", feature = "ring3_mailbox_loop"), allow(dead_code))]
+    fn ipc_direct_switch_allowed(&self, recv_idx: usize) -> bool {
+        let prio = self.tasks[recv_idx].priority;
+        (0..self.rq_len).map(|pos| self.ready_queue[pos]).all(|idx| {
+            if idx == recv_idx || idx >= self.num_tasks || self.tasks[idx].state != TaskState::Ready {
+                return true;
+            }
+            let p = self.tasks[idx].priority;
+            p < prio || (p == prio && self.rq_same_prio_passes[idx] + 1 < self.num_tasks as u64)
+        })
+    }
+}
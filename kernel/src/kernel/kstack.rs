@@ -0,0 +1,146 @@
+// kernel/src/kernel/kstack.rs
+//
+// 役割:
+// - kstack_switch: task ごとの kernel stack を持たせ、schedule_next_task が選んだ task の stack へ実際に切り替える。
+//
+// 切り替える場所:
+// - schedule_next_task は IPC / kill の途中からも呼ばれるので、そこでは current_task を決めるだけにする
+// - 実際の切替は tick() の末尾（切替元の stack は「tick から戻る直前」で止まる。途中の呼び出し元を宙に浮かせない）
+//   * 切替元は timer IRQ の中で止まり、次にその task が選ばれた tick の末尾から戻って iretq する
+//   * 初めて走る task は arch::context の task_entry から始まる（EOI → IRQ 待ち）
+//   * ring3_tasks の user task は初回の切替で iretq して ring3 に入る（ring3_task.rs）
+// - 切替の前に CR3 を行き先 task の root にする（tick の途中で kernel root に戻っていても、
+//   timer IRQ の iretq で user に戻る前に user root になっている）
+//
+// stack の割り当て:
+// - TASK0（kernel）は boot stack をそのまま使う（entry.rs の待ちループもこの stack 上）
+// - それ以外は task index と同じ slot の静的 stack（arch::context::KSTACK_SLOTS）
+//   * idle task も自分の slot を使う。task_entry の IRQ 待ち（sti; hlt）がそのまま idle loop になる
+// - task が Dead になったら slot の context を捨てる（slot の再利用時は task_entry からやり直す）
+// - halt 要求 / tick budget 切れでは TASK0 の stack に戻す（entry.rs の後始末と dump を boot stack で走らせる）
+//
+// やらないこと:
+// - ring3_demo / ring3_mailbox 系との併用（あちらは boot stack から一発で ring3 に入る）
+// - synthetic_tick / replay との併用（初回の切替が timer IRQ の中であることを前提にしている）
+
+#[cfg(any(feature = "synthetic_tick", feature = "replay"))]
+compile_error!("kstack_switch requires the PIT-driven tick (not synthetic_tick / replay)");
+
+#[cfg(any(feature = "ring3_demo", feature = "ring3_mailbox", feature = "ring3_mailbox_loop"))]
+compile_error!("kstack_switch cannot be combined with ring3 features");
+
+use super::{AddressSpaceKind, KernelState, TaskState, IDLE_TASK_INDEX, KERNEL_ASID_INDEX, MAX_TASKS, TASK0_INDEX};
+use crate::arch::context::{TaskContext, KSTACK_SLOTS};
+use crate::arch::ops::{Arch, ArchOps};
+
+const _: () = assert!(MAX_TASKS <= KSTACK_SLOTS);
+
+/// task ごとの保存済み context
+pub(super) struct KernelStacks {
+    contexts: [TaskContext; MAX_TASKS],
+    // contexts[i] が有効か（false なら次の切替で task_entry から始める）
+    started: [bool; MAX_TASKS],
+    // 今どの task の stack の上で走っているか
+    on_stack_of: usize,
+    switches: u64,
+}
+
+impl KernelStacks {
+    pub(super) const fn new() -> Self {
+        let mut started = [false; MAX_TASKS];
+        // TASK0 は boot stack の上で起動している（最初の切替で保存される）
+        started[TASK0_INDEX] = true;
+        Self { contexts: [TaskContext::EMPTY; MAX_TASKS], started, on_stack_of: TASK0_INDEX, switches: 0 }
+    }
+
+    pub(super) fn switches(&self) -> u64 {
+        self.switches
+    }
+}
+
+impl KernelState {
+    /// task が Dead になった（teardown_task から）。その slot の context を捨てる
+    /// - 今その stack の上に居ても、tick の末尾で切り替えて離れるので問題ない
+    pub(super) fn forget_kernel_stack(&mut self, idx: usize) {
+        if idx != TASK0_INDEX && idx < MAX_TASKS {
+            self.kstacks.started[idx] = false;
+        }
+    }
+
+    /// tick の末尾: current_task の stack に切り替える（既にその上なら何もしない）
+    pub(super) fn switch_kernel_stack_to_current(&mut self) {
+        let to = if self.should_halt || crate::arch::timer::budget_left() == 0 {
+            TASK0_INDEX
+        } else {
+            self.current_task
+        };
+        let from = self.kstacks.on_stack_of;
+
+        if to >= self.num_tasks || self.tasks[to].state == TaskState::Dead {
+            return;
+        }
+
+        self.load_root_of(to);
+
+        if to == from {
+            return;
+        }
+
+        if !self.kstacks.started[to] {
+            #[cfg(feature = "ring3_tasks")]
+            if let Some(entry) = self.ring3_entry_of(to) {
+                match Arch::init_user_task_context(to, entry.rip, entry.rsp) {
+                    Ok(ctx) => self.kstacks.contexts[to] = ctx,
+                    Err((check, value)) => {
+                        // kill で current が変わる。次の tick の末尾で改めて切り替える
+                        self.ring3_reject_entry(to, check, value);
+                        return;
+                    }
+                }
+                self.kstacks.started[to] = true;
+            }
+
+            if !self.kstacks.started[to] {
+                self.kstacks.contexts[to] = Arch::init_task_context(to);
+                self.kstacks.started[to] = true;
+            }
+        }
+
+        // TASK0 / idle は ring3 に降りないので RSP0 は触らない
+        let rsp0 = if to == TASK0_INDEX || to == IDLE_TASK_INDEX { None } else { Some(Arch::kernel_stack_top(to)) };
+
+        crate::logging::info("kstack: switch");
+        crate::logging::info_u64("from_task_id", self.tasks[from].id.0);
+        crate::logging::info_u64("to_task_id", self.tasks[to].id.0);
+
+        self.kstacks.on_stack_of = to;
+        self.kstacks.switches += 1;
+
+        let prev: *mut TaskContext = &mut self.kstacks.contexts[from];
+        let next: *const TaskContext = &self.kstacks.contexts[to];
+        // ここで止まり、誰かが from の stack に切り替えたときに戻ってくる
+        unsafe { Arch::switch_context(prev, next, rsp0) };
+    }
+
+    /// task の root を CR3 に入れる（User なら user root、それ以外は kernel root）
+    /// - user root なら遅延していた TLB flush もここで行う（kernel/tlb.rs）
+    fn load_root_of(&mut self, idx: usize) {
+        let as_idx = self.tasks[idx].address_space_id.0;
+        let user_root = if as_idx < self.num_tasks && self.address_spaces[as_idx].kind == AddressSpaceKind::User {
+            self.address_spaces[as_idx].root_page_frame
+        } else {
+            None
+        };
+        match user_root {
+            Some(root) => {
+                Arch::switch_address_space_quiet(root);
+                self.flush_deferred_tlb(as_idx);
+            }
+            None => {
+                if let Some(kernel_root) = self.address_spaces[KERNEL_ASID_INDEX].root_page_frame {
+                    Arch::switch_address_space_quiet(kernel_root);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,72 @@
+// kernel/src/kernel/mem_info.rs
+//
+// 役割:
+// - MemInfo syscall: 物理フレームの使用量（total / used / free）と、呼んだ task の AddressSpace の
+//   resident / swapped pages を返す。user 側が memory pressure を見て振る舞いを変えられるようにする。
+//
+// 中身:
+// - total / used / free は PhysicalMemoryManager::usage()（mm/mod.rs。page table / kernel stack 用も used に入る）
+// - resident = AddressSpace の mapping_count、swapped = swap out 中の page の数、frame_quota は 0 = 無制限
+//   * Memory Usage Dump（mod.rs の dump_text）と memory record（dump_records）も同じ値を出す
+//
+// 返し方:
+// - last_syscall_ret = SYSCALL_OK（引数が無いので失敗しない）
+// - 中身は Task.last_mem_info（kernel 内の user step が読む）
+// - ring3（ring3_task.rs の ring3_syscall_poll）は rax = last_syscall_ret、rdx = MemInfo::pack()
+//   * pack の配置は abi.rs の MEM_INFO_*_SHIFT。どの値も幅で飽和する
+//
+// 方針:
+// - 読むだけ。権限は見ない（自分の AddressSpace と全体の数しか返さないので cap は要らない）
+//
+// やらないこと:
+// - 他の task の AddressSpace を引く（TaskInfo と違って名指しはしない）
+// - 閾値を超えたときの通知（user は poll する）
+
+use super::abi::{MEM_INFO_FREE_SHIFT, MEM_INFO_RESIDENT_SHIFT, MEM_INFO_USED_SHIFT, SYSCALL_OK};
+use super::KernelState;
+
+/// MemInfo syscall の結果
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct MemInfo {
+    pub total_frames: u64,
+    pub used_frames: u64,
+    pub free_frames: u64,
+    /// 呼んだ task の AddressSpace に map されている page の数
+    pub resident_pages: u64,
+    /// 呼んだ task の AddressSpace の swap out 中の page の数
+    pub swapped_pages: u64,
+    /// AddressSpace の frame quota（0 = 無制限）
+    pub frame_quota: u64,
+}
+
+impl MemInfo {
+    /// ring3 の rdx 1 本に詰める（配置は abi.rs の MEM_INFO_*_SHIFT）
+    #[cfg_attr(not(feature = "ring3_tasks"), allow(dead_code))]
+    pub fn pack(&self) -> u64 {
+        const MASK_24: u64 = (1 << 24) - 1;
+        (self.free_frames.min(MASK_24) << MEM_INFO_FREE_SHIFT)
+            | (self.used_frames.min(MASK_24) << MEM_INFO_USED_SHIFT)
+            | (self.resident_pages.min(u16::MAX as u64) << MEM_INFO_RESIDENT_SHIFT)
+    }
+}
+
+impl KernelState {
+    /// task index の AddressSpace から見た MemInfo
+    pub(super) fn mem_info_of(&self, t: usize) -> MemInfo {
+        let usage = self.phys_mem.usage();
+        let aspace = &self.address_spaces[self.tasks[t].address_space_id.0];
+        MemInfo {
+            total_frames: usage.total,
+            used_frames: usage.used,
+            free_frames: usage.free,
+            resident_pages: aspace.mapping_count() as u64,
+            swapped_pages: aspace.swapped_count() as u64,
+            frame_quota: aspace.frame_quota() as u64,
+        }
+    }
+
+    pub(super) fn syscall_mem_info(&mut self, idx: usize) -> u64 {
+        self.tasks[idx].last_mem_info = Some(self.mem_info_of(idx));
+        SYSCALL_OK
+    }
+}
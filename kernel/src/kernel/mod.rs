@@ -14,6 +14,7 @@
 // - unsafe は arch 側に局所化し、kernel 側は状態遷移＋抽象イベント中心。
 // - WaitQueue は「Blocked 全体」を保持する。
 //   * Sleep の wake は “Sleep のみ” を対象にする（IPC の待ちをタイマで勝手に起こさない）。
+//   * Sleep は wake_at（kernel clock の期限。time.rs）を持ち、毎 tick 期限の来た task だけを全部起こす。
 // - tick 中に schedule が走って current_task が変わるのは自然に起こりうる。
 //   * time_slice 更新は「その tick の最後まで同じ task が RUNNING の場合のみ」行う。
 // - event_log はリングバッファ化し、直近のログを保持する（観測性改善）。
@@ -34,30 +35,146 @@ mod user_program;
 mod trace;
 mod state_ref;
 mod demo;
+mod abi;
+mod caps;
+#[cfg(feature = "timer_service")]
+mod timer_service;
+#[cfg(feature = "virtio_net")]
+mod net;
+mod priority;
+mod task_lifecycle;
+mod notification;
+mod cspace;
+mod endpoint_lifecycle;
+#[cfg(feature = "kstack_switch")]
+mod kstack;
+#[cfg(feature = "ring3_tasks")]
+mod ring3_task;
+mod replay;
+mod swap;
+mod shm;
+mod tlb;
+mod sched_policy;
+mod watchdog;
+mod deadlock;
+mod state_hash;
+mod checkpoint;
+mod refinement;
+mod event_log;
+mod invariant;
+mod input;
+mod fs;
+mod shutdown;
+mod fault_handler;
+mod grant;
+mod derivation;
+mod suspend;
+mod task_info;
+mod mem_info;
+mod reply_cap;
+mod task_queue;
+mod ipc_buffer;
+mod ipc_handoff;
+mod endpoint_stats;
+mod tar;
+pub mod initrd;
+pub mod boot_params;
+#[cfg(feature = "debug_console")]
+mod console;
+#[cfg(feature = "smp")]
+mod smp;
+mod verdict;
+pub mod time;
+#[cfg(feature = "host_test")]
+pub mod host_probe;
 
 
 pub use entry::start;
 pub use syscall::Syscall;
-pub use state_ref::with_kernel_state;
-pub use syscall::mailbox_dispatch;
+pub use state_ref::{is_kernel_state_sealed, with_kernel_state};
+#[cfg(feature = "smp")]
+pub use smp::secondary_cpus_stopping;
+pub use syscall::{mailbox_dispatch, mailbox_kill_bad_trap_frame};
+pub use caps::emit_capabilities;
+#[cfg(feature = "ring3_tasks")]
+pub use ring3_task::UserFaultOutcome;
 
 use bootloader::BootInfo;
-use x86_64::registers::control::Cr3;
 
-use crate::{arch, logging};
-use crate::mm::PhysicalMemoryManager;
+use crate::logging;
+use crate::arch::ops::{Arch, ArchOps, PageFaultInfo};
+use crate::mm::{FrameDeallocError, FrameRefError, PhysicalMemoryManager};
 use crate::mem::addr::{PhysFrame, VirtPage, PAGE_SIZE};
-use crate::mem::paging::{MemAction, PageFlags};
+use crate::mem::paging::{BatchError, MemAction, PageFlags};
 use crate::mem::address_space::{AddressSpace, AddressSpaceError, AddressSpaceKind};
 use crate::mem::layout::{KERNEL_SPACE_START, PML4_SLOT_SIZE, USER_SPACE_START};
 use crate::kernel::ipc::IPC_ERR_DEAD_PARTNER;
+use spec_macros::spec;
 
-use ipc::Endpoint;
+use ipc::{Endpoint, IpcMessage};
+use notification::Notification;
+use cspace::{CapIndex, CapTable};
+use sched_policy::{ActivePolicy, SchedPolicy};
+use invariant::InvariantId;
+use time::Instant;
 
-const MAX_TASKS: usize = 3;
+const MAX_TASKS: usize = 4;
 const EVENT_LOG_CAP: usize = 1024;
 
-const MAX_ENDPOINTS: usize = 2;
+// 起動時から在る endpoint（slot 0..STATIC_ENDPOINTS。cap の初期配置もこの範囲）
+#[cfg(not(any(feature = "stress_ipc", feature = "timer_service", feature = "virtio_net")))]
+const STATIC_ENDPOINTS: usize = 2;
+
+// timer_service: ep1 = service、ep2 = client の通知受け口
+#[cfg(all(feature = "timer_service", not(feature = "stress_ipc")))]
+const STATIC_ENDPOINTS: usize = 3;
+
+// virtio_net: ep1 = net service、ep2 = client の受信通知の受け口
+#[cfg(all(feature = "virtio_net", not(feature = "stress_ipc")))]
+const STATIC_ENDPOINTS: usize = 3;
+
+// stress_ipc: endpoint を大量に用意して巡回させる
+#[cfg(feature = "stress_ipc")]
+const STATIC_ENDPOINTS: usize = 256;
+
+// EndpointCreate で払い出す slot の数（endpoint_lifecycle.rs）
+const DYNAMIC_ENDPOINT_SLOTS: usize = 2;
+
+const MAX_ENDPOINTS: usize = STATIC_ENDPOINTS + DYNAMIC_ENDPOINT_SLOTS;
+
+// Notification（非同期通知）の数
+const MAX_NOTIFICATIONS: usize = 2;
+
+/// Dead task の root から一度に外すページテーブルの最大数（L3 1 + L2 / L1）
+const MAX_USER_PAGE_TABLE_FRAMES: usize = 64;
+
+// scheduler の time slice（tick 数）
+const DEFAULT_QUANTUM: u64 = 5;
+
+// IPC event の決定的サンプリング（N 個に 1 個だけ event_log に残す）
+// - stress 構成で event_log が IPC で埋まり、先頭の trace が流れるのを防ぐ
+// - 正確な件数は counters（ipc_events_seen / ipc_events_skipped）で別に持つ
+#[cfg(not(feature = "stress_ipc"))]
+const IPC_EVENT_SAMPLE_EVERY: u64 = 1;
+#[cfg(feature = "stress_ipc")]
+const IPC_EVENT_SAMPLE_EVERY: u64 = 16;
+
+// reply obligation（server が reply を返すまでの保持 tick 数）の上限
+// - 超えたら ServerSlow（server の TaskId）を 1 回だけ出す
+// - ipc_reply_timeout_abort のときは待っている client を IPC_ERR_SERVER_TIMEOUT で起こす
+const IPC_REPLY_OBLIGATION_TICKS: u64 = 32;
+
+// user #PF のレート制限（task ごとの固定窓）
+// - PF_STORM_WINDOW_TICKS の窓の中で PF_STORM_THRESHOLD 回を超えたら FaultStorm で kill
+// - ignore_user_pf_demo 等で「#PF 後も継続」した task が fault ループで時間を食い潰すのを防ぐ
+const PF_STORM_WINDOW_TICKS: u64 = 16;
+const PF_STORM_THRESHOLD: u64 = 4;
+
+// dump_events の既定の形式（trace_records なら機械可読の 1 行レコード）
+#[cfg(not(feature = "trace_records"))]
+const DEFAULT_DUMP_FORMAT: DumpFormat = DumpFormat::Text;
+#[cfg(feature = "trace_records")]
+const DEFAULT_DUMP_FORMAT: DumpFormat = DumpFormat::Records;
 
 // 固定 ID
 const KERNEL_ASID_INDEX: usize = 0;
@@ -67,6 +184,14 @@ const TASK0_INDEX: usize = 0; // TaskId(1)
 const TASK1_INDEX: usize = 1; // TaskId(2)
 const TASK2_INDEX: usize = 2; // TaskId(3)
 
+// idle 専用 task（kernel AS、最低優先度、ready_queue には入れない）
+// - ready が空のときだけ走る。Blocked / Dead にはならない（INV-SCHED-006）
+// - TaskId(0) は払い出し対象外（TaskCreate の TaskId は従来どおり TASK2_ID の次から）
+// - address_spaces[IDLE_TASK_INDEX] は slot を 1:1 に揃えるためだけに在る（idle は kernel AS を使う）
+const IDLE_TASK_INDEX: usize = 3;
+const IDLE_TASK_ID: TaskId = TaskId(0);
+const IDLE_TASK_PRIORITY: u8 = 0;
+
 const TASK0_ID: TaskId = TaskId(1);
 const TASK1_ID: TaskId = TaskId(2);
 const TASK2_ID: TaskId = TaskId(3);
@@ -76,6 +201,8 @@ const DEMO_VIRT_PAGE_INDEX_TASK0: u64 = 0x100; // 0x0010_0000
 const DEMO_VIRT_PAGE_INDEX_USER:  u64 = 0x110; // 0x0011_0000 (offset)
 
 const IPC_DEMO_EP0: EndpointId = EndpointId(0);
+// IPC_DEMO_EP0 を指す cap（cspace.rs の初期配置で slot i = EndpointId(i)）
+const IPC_DEMO_CAP0: CapIndex = CapIndex(0);
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub struct TaskId(pub u64);
@@ -90,17 +217,50 @@ pub enum TaskState {
     Blocked,
     // ★Top3: user fault を kill できるように Dead を追加
     Dead,
+    // TaskSuspend で止められている（suspend.rs）。ready_queue には居ない
+    // Blocked から止めたときは blocked_reason と待ちの登録をそのまま持つ
+    Suspended,
+}
+
+impl TaskState {
+    /// blocked_reason（と endpoint / notification / wait_queue の登録）を持っていてよい state
+    pub fn can_wait(self) -> bool {
+        matches!(self, TaskState::Blocked | TaskState::Suspended)
+    }
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub struct EndpointId(pub usize);
 
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct NotificationId(pub usize);
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct ShmId(pub usize);
+
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum BlockedReason {
     Sleep,
     IpcRecv { ep: EndpointId },
+    // IpcRecvAny: ep_mask（bit i = EndpointId(i)）のどれかに msg が来るまで待つ
+    IpcRecvAny { ep_mask: u64 },
     IpcSend { ep: EndpointId },
     IpcReply { partner: TaskId, ep: EndpointId },
+    // NotifyWait で bits を待っている（notification.rs）
+    NotifyWait { ntfn: NotificationId },
+    // user #PF を ep の fault handler に委譲して、FaultResolve を待っている（fault_handler.rs）
+    FaultWait { ep: EndpointId },
+}
+
+impl BlockedReason {
+    /// ep で recv 待ちか（IpcRecv { ep } か、ep を含む IpcRecvAny）
+    pub fn waits_recv_on(&self, ep: EndpointId) -> bool {
+        match *self {
+            BlockedReason::IpcRecv { ep: rep } => rep == ep,
+            BlockedReason::IpcRecvAny { ep_mask } => ep.0 < 64 && ep_mask & (1u64 << ep.0) != 0,
+            _ => false,
+        }
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -108,7 +268,17 @@ pub struct Task {
     pub id: TaskId,
     pub state: TaskState,
 
+    // 実効優先度（scheduler が見る値）。IPC 待ちの継承で base_priority より上がることがある
     pub priority: u8,
+    // 本来の優先度（priority inheritance が解けたらここへ戻す）
+    pub base_priority: u8,
+    // 継承中なら、直接待っている task（priority.rs）
+    pub inherited_from: Option<TaskId>,
+
+    // TaskCreate で渡された entry_hint（静的 task は 0）
+    pub entry_hint: u64,
+    // TaskCreate で作った task（静的 task は None）。親には Control 付きの task handle を入れる（cspace.rs）
+    pub parent: Option<TaskId>,
 
     pub runtime_ticks: u64,
     pub time_slice_used: u64,
@@ -116,8 +286,25 @@ pub struct Task {
     pub address_space_id: AddressSpaceId,
     pub blocked_reason: Option<BlockedReason>,
 
-    pub last_msg: Option<u64>,
-    pub last_reply: Option<u64>,
+    // Sleep syscall の起床期限（kernel clock）。Blocked(Sleep) の間だけ Some
+    pub wake_at: Option<Instant>,
+
+    // IPC で受け取ったメッセージ / reply（エラーコードは len = 1 の MR0）
+    pub last_msg: Option<IpcMessage>,
+    // last_msg を運んだ endpoint（IpcRecvAny でどこから来たかを見る）
+    pub last_recv_ep: Option<EndpointId>,
+    // last_msg の call に reply するための reply cap（reply 待ちの無い msg なら None。reply_cap.rs）
+    pub last_reply_cap: Option<reply_cap::ReplyCap>,
+    pub last_reply: Option<IpcMessage>,
+
+    // NotifyWait で受け取った bits
+    pub last_notify: Option<u64>,
+
+    // TaskInfo で引いた task の状態（task_info.rs）
+    pub last_task_info: Option<task_info::TaskInfo>,
+
+    // MemInfo で引いたメモリ使用量（mem_info.rs）
+    pub last_mem_info: Option<mem_info::MemInfo>,
 
     // syscall（mem 系など）の戻り値
     pub last_syscall_ret: Option<u64>,
@@ -125,7 +312,7 @@ pub struct Task {
     // syscall 戻り値の「未読」フラグ（ログ出力を1回にする）
     pub last_syscall_ret_unread: bool,
 
-    pub pending_send_msg: Option<u64>,
+    pub pending_send_msg: Option<IpcMessage>,
     pub pending_syscall: Option<Syscall>,
 }
 
@@ -133,6 +320,18 @@ pub struct Task {
 // ★Top3: kill reason（最小）
 // - UserPageFault: 本物の #PF のみ
 // - DemoInjected: テスト注入（dead_partner_test 等）
+// - TrapFrameCorrupt: iretq 直前の TrapFrame 検査違反（arch::trapframe）
+// - FaultStorm: user #PF のレート制限超過
+// - UserException: ring3 で起きた #PF / #GP 以外の例外（#UD / #NM / #AC ...。arch::interrupts）
+/// dump の出し方
+/// - Text: 人間向けの複数行テキスト（VGA + serial）
+/// - Records: 1 行 = 1 レコードの key=value（serial のみ、logging::record）
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DumpFormat {
+    Text,
+    Records,
+}
+
 #[derive(Clone, Copy)]
 pub enum TaskKillReason {
     UserPageFault { addr: u64, err: u64, rip: u64 },
@@ -140,6 +339,24 @@ pub enum TaskKillReason {
     // reason_code は「どのテストが殺したか」を区別するための小さな識別子
     // 例: 1=dead_partner_test, 2=kill_cleanup_test, 3=endpoint_close_test...
     DemoInjected { code: u64 },
+
+    // check は arch::trapframe::TrapFrameViolation::code()、value は違反した項目の値
+    TrapFrameCorrupt { check: u64, value: u64 },
+
+    // faults は窓内の #PF 回数、window_ticks は窓の長さ
+    FaultStorm { faults: u64, window_ticks: u64 },
+
+    // user stack 直下の guard page への #PF（addr は fault アドレス）
+    StackOverflow { addr: u64, rip: u64 },
+
+    // vector は例外番号（6 = #UD 等）、err は error code（無い vector は 0）
+    UserException { vector: u64, err: u64, rip: u64 },
+
+    // Shutdown syscall の片付け（by は Shutdown を呼んだ task。shutdown.rs）
+    Shutdown { by: TaskId },
+
+    // swap out / swap in の途中で実ページテーブル / AddressSpace を更新できなかった（stage は swap::SwapError::code()）
+    SwapFailed { page: u64, stage: u64 },
 }
 
 #[derive(Clone, Copy)]
@@ -147,6 +364,8 @@ pub enum LogEvent {
     TickStarted(u64),
     TimerUpdated(u64),
     FrameAllocated,
+    // Unmap / kill で参照が無くなったフレームを free list に返した
+    FrameFreed { frame: u64 },
     TaskSwitched(TaskId),
     TaskStateChanged(TaskId, TaskState),
     ReadyQueued(TaskId),
@@ -167,11 +386,81 @@ pub enum LogEvent {
 
     IpcRecvCalled { task: TaskId, ep: EndpointId },
     IpcRecvBlocked { task: TaskId, ep: EndpointId },
-    IpcSendCalled { task: TaskId, ep: EndpointId, msg: u64 },
+    IpcSendCalled { task: TaskId, ep: EndpointId, msg: IpcMessage },
     IpcSendBlocked { task: TaskId, ep: EndpointId },
-    IpcDelivered { from: TaskId, to: TaskId, ep: EndpointId, msg: u64 },
+    IpcDelivered { from: TaskId, to: TaskId, ep: EndpointId, msg: IpcMessage },
     IpcReplyCalled { task: TaskId, ep: EndpointId, to: TaskId },
-    IpcReplyDelivered { from: TaskId, to: TaskId, ep: EndpointId },
+    IpcReplyDelivered { from: TaskId, to: TaskId, ep: EndpointId, msg: IpcMessage },
+
+    // reply obligation が IPC_REPLY_OBLIGATION_TICKS を超えた（責任は server 側）
+    ServerSlow { server: TaskId, client: TaskId, ep: EndpointId, held_ticks: u64 },
+
+    // timer_service の周期通知を deliver した（missed は coalesce した周期数）
+    TimerFired { task: TaskId, ep: EndpointId, missed: u64 },
+
+    // priority inheritance（task が from の待ちにより priority へ上がった / base へ戻った）
+    PriorityInherited { task: TaskId, from: TaskId, priority: u8 },
+    PriorityRestored { task: TaskId, priority: u8 },
+
+    // TaskCreate / TaskExit（slot は再利用した task index）
+    TaskCreated { task: TaskId, parent: TaskId, slot: usize, priority: u8 },
+    TaskExited { task: TaskId },
+
+    // Notification（非同期通知。rendezvous IPC とは別系統）
+    NotifySignaled { task: TaskId, ntfn: NotificationId, bits: u64 },
+    NotifyWaitBlocked { task: TaskId, ntfn: NotificationId },
+    NotifyDelivered { to: TaskId, ntfn: NotificationId, bits: u64 },
+
+    // cap の rights 違反（syscall 境界で拒否。need は CapRights の bits）
+    CapDenied { task: TaskId, cap: usize, need: u16 },
+    // cap transfer（from の cap index → to の slot。rights は CapRights の bits）
+    CapGranted { from: TaskId, to: TaskId, cap: usize, object: cspace::KernelObject },
+    CapReceived { task: TaskId, slot: usize, object: cspace::KernelObject, rights: u16 },
+
+    // EndpointCreate / EndpointDelete（cap は作った task の cap index、revoked は消した cap の数）
+    EndpointCreated { task: TaskId, ep: EndpointId, cap: usize },
+    EndpointDeleted { task: TaskId, ep: EndpointId, revoked: usize },
+
+    // swap out / swap in（page は user page index、slot は swap 領域の slot）
+    PageSwappedOut { task: TaskId, page: u64, slot: usize },
+    PageSwappedIn { task: TaskId, page: u64, slot: usize },
+
+    // shared memory segment（page は map 先の先頭 user page index、unmapped は owner の死で外した mapping の数）
+    ShmCreated { task: TaskId, shm: ShmId, pages: usize },
+    ShmMapped { task: TaskId, shm: ShmId, page: u64 },
+    ShmDestroyed { owner: TaskId, shm: ShmId, unmapped: usize },
+
+    // TLB（今の CR3 でない root を変えた → flush を次の切替まで遅らせた / 切替で flush した）
+    // - page は変えた user page index、pages は flush までに溜まった変更数、stale_ticks は最初の遅延からの tick 数
+    TlbFlushDeferred { address_space: AddressSpaceId, page: u64 },
+    TlbFlushed { address_space: AddressSpaceId, pages: usize, stale_ticks: u64 },
+
+    // MLFQ の level 変更（quantum を使い切って下げた / block して上げた。0 が最上位）
+    LevelChanged { task: TaskId, from: u8, to: u8 },
+
+    // watchdog: 進捗（IpcDelivered / TaskSwitched / MemActionApplied）の無い tick が続いた
+    // - task_states は task index ごとの状態（abi の STATE_*）を 4bit ずつ詰めたもの
+    NoProgressDetected { stalled_ticks: u64, last_progress_tick: u64, task_states: u64 },
+
+    // IPC の wait-for graph の閉路（tasks[..len] が閉路の順。deadlock.rs）
+    DeadlockDetected { len: usize, tasks: [Option<TaskId>; MAX_TASKS] },
+
+    // invariant 違反（同じ id の違反が続く間は最初の 1 回だけ。detail は違反ごとの補足。invariant.rs）
+    InvariantViolated { id: InvariantId, task: Option<TaskId>, detail: Option<u64> },
+
+    // keyboard IRQ の scancode をリングに入れた（queued は入れた後の数。input.rs）
+    InputReceived { scancode: u8, queued: usize },
+
+    // user #PF を fault handler の ep に委譲した / handler が FaultResolve で解決した（action は abi の FAULT_ACTION_*。fault_handler.rs）
+    FaultDelegated { task: TaskId, ep: EndpointId, addr: u64 },
+    FaultResolved { task: TaskId, by: TaskId, action: u64 },
+
+    // IPC の page grant を receiver の window（page）に map した / 外した（reason は abi の GRANT_REVOKE_*。grant.rs）
+    PageGranted { from: TaskId, to: TaskId, page: u64, frame: u64 },
+    GrantRevoked { from: TaskId, to: TaskId, page: u64, reason: u64 },
+
+    // Revoke で取り上げた派生 1 つ（kind は abi の REVOKE_KIND_*、object は cap index / page。derivation.rs）
+    DerivationRevoked { by: TaskId, task: TaskId, kind: u64, object: u64, depth: u64 },
 
     // ★Top3: kill の観測点
     TaskKilled { task: TaskId, reason: TaskKillReason },
@@ -197,10 +486,34 @@ enum KernelAction {
 // counters (計測)
 // -----------------------------------------------------------------------------
 
+/// CPU 時間の内訳（tick 単位。tick_body が 1 tick をどれか 1 つに入れる）
+/// - kernel: その tick（または前の tick の後）に syscall を処理した（int80 経路で tick の外から来た分も次の tick に入る）、
+///           または kernel AS の task（Task0）が走った
+/// - idle:   syscall なしで idle task が走った
+/// - user:   syscall なしで user task が走った
+#[derive(Clone, Copy)]
+pub struct CpuTime {
+    pub user_ticks: u64,
+    pub kernel_ticks: u64,
+    pub idle_ticks: u64,
+}
+
+impl CpuTime {
+    const fn new() -> Self {
+        Self { user_ticks: 0, kernel_ticks: 0, idle_ticks: 0 }
+    }
+
+    fn total(&self) -> u64 {
+        self.user_ticks + self.kernel_ticks + self.idle_ticks
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct KernelCounters {
     // scheduler
     pub sched_switches: u64,
+    // 同優先度 round-robin: ready で待つ間に同優先度 task が走った回数の最大（< MAX_TASKS なら bounded）
+    pub sched_rr_max_passes: u64,
 
     // IPC
     pub ipc_send_fast: u64,
@@ -208,24 +521,224 @@ pub struct KernelCounters {
     pub ipc_recv_fast: u64,
     pub ipc_recv_slow: u64,
     pub ipc_reply_delivered: u64,
+    // reply obligation 超過（ServerSlow）/ それにより client をエラーで起こした数
+    pub ipc_server_slow: u64,
+    pub ipc_reply_timeouts: u64,
+    // IpcSend の timeout 切れで sender をエラーで起こした数
+    pub ipc_call_timeouts: u64,
+
+    // priority inheritance（継承が起きた / base へ戻した回数）
+    pub prio_inherited: u64,
+    pub prio_restored: u64,
+
+    // task lifecycle（TaskCreate / TaskExit）
+    pub task_created: u64,
+    pub task_exited: u64,
+
+    // notification（signal 回数 / bits を渡した回数 / NotifyWait で block した回数）
+    pub notify_signals: u64,
+    pub notify_delivered: u64,
+    pub notify_wait_blocked: u64,
+
+    // capability（syscall 境界で拒否した回数）
+    pub cap_denied: u64,
+    // cap transfer（複製した回数 / cap だけ落とした回数）
+    pub cap_transfers: u64,
+    pub cap_transfer_failed: u64,
 
     // faults / kill
     pub task_killed_user_pf: u64,
     // ★追加: テスト注入 kill（dead_partner_test 等）
     pub task_killed_demo_injected: u64,
+    pub task_killed_trap_frame: u64,
+    pub task_killed_fault_storm: u64,
+    // guard page への #PF（user stack overflow）
+    pub task_killed_stack_overflow: u64,
+    // user #PF の総数（kill / ignore に関係なく数える）
+    pub user_pf_total: u64,
+
+    // event log sampling（IPC_EVENT_SAMPLE_EVERY）
+    pub ipc_events_seen: u64,
+    pub ipc_events_skipped: u64,
+
+    // ring3 からの syscall 入口（ring3_tasks。int 0x80 / syscall 命令）
+    pub syscall_entry_int80: u64,
+    pub syscall_entry_syscall: u64,
+
+    // swap（swap out した page / #PF で戻した page）
+    pub swap_out: u64,
+    pub swap_in: u64,
+
+    // TLB（遅延した flush の記録 / 切替で行った flush）
+    pub tlb_flush_deferred: u64,
+    pub tlb_flushes: u64,
+
+    // MLFQ（level を下げた / 上げた回数、level ごとに走った tick 数）
+    pub mlfq_demotions: u64,
+    pub mlfq_promotions: u64,
+    pub mlfq_level_ticks: [u64; sched_policy::MLFQ_LEVELS],
+
+    // idle task に切り替えた回数（ready が空になった回数）
+    pub idle_entries: u64,
+
+    // watchdog が進捗の無い停滞を検出した回数（watchdog.rs）
+    pub watchdog_no_progress: u64,
+
+    // IPC deadlock（wait-for graph の閉路を検出した / ipc_deadlock_break で切った回数。deadlock.rs）
+    pub ipc_deadlocks_detected: u64,
+    pub ipc_deadlocks_broken: u64,
+
+    // event_log のリングで上書きした event（合計 / class ごと）と、重要 event の退避 / 消失（event_log.rs）
+    pub events_overwritten: u64,
+    pub events_overwritten_by_class: [u64; event_log::EVENT_CLASSES],
+    pub critical_events_retained: u64,
+    pub critical_events_lost: u64,
+
+    // invariant 違反の数（check ごとに数える。同じ違反が続けば毎 tick 増える。invariant.rs）
+    pub invariant_violations: u64,
+    // invariant 検査を回した回数（level ごと。Off の tick は数えない。invariant.rs）
+    pub invariant_checks_full: u64,
+    pub invariant_checks_cheap: u64,
+
+    // keyboard 入力（溜めた / リング満杯で捨てた / ReadInput で渡した scancode の数。input.rs）
+    pub input_received: u64,
+    pub input_dropped: u64,
+    pub input_read: u64,
+
+    // ring3 の #PF / #GP 以外の例外で kill した task の数（arch::interrupts → ring3_task.rs）
+    pub task_killed_user_exception: u64,
+
+    // Shutdown syscall の片付けで kill した task の数（shutdown.rs）
+    pub task_killed_shutdown: u64,
+
+    // fault handler（委譲した #PF / 解決して起こした数 / handler の ep が close されて handler 無しに戻した数。fault_handler.rs）
+    pub fault_delegated: u64,
+    pub fault_resumed: u64,
+    pub fault_orphaned: u64,
+
+    // page grant（window に map した / 外した / 入口か deliver で落とした数。grant.rs）
+    pub grant_mapped: u64,
+    pub grant_revoked: u64,
+    pub grant_failed: u64,
+
+    // derivation tree（辺を足した / Revoke で取り上げた / 表が満杯で cap・grant を落とした数。derivation.rs）
+    pub derivations_recorded: u64,
+    pub derivations_revoked: u64,
+    pub derivation_table_full: u64,
+
+    // suspend / resume（止めた / 戻した数、Suspended の recv_waiter に届けずに sender を待たせた数。suspend.rs）
+    pub tasks_suspended: u64,
+    pub tasks_resumed: u64,
+    pub suspend_recv_deferred: u64,
+    // TaskInfo syscall の呼び出し数（task_info.rs）
+    pub task_info_queries: u64,
+    // reply cap（払い出した数 / IpcReply で引けなかった数。reply_cap.rs）
+    pub reply_caps_minted: u64,
+    pub reply_caps_rejected: u64,
+    // endpoint の message buffer（buffer に入れた send / buffer から渡した msg / close で捨てた msg。ipc_buffer.rs）
+    pub ipc_buffered_sends: u64,
+    pub ipc_buffered_delivered: u64,
+    pub ipc_buffer_dropped: u64,
+    // rendezvous（sender が reply 待ちになる従来の deliver）で渡した msg
+    pub ipc_rendezvous_delivered: u64,
+    // send fastpath で起こした receiver が走るまで（direct switch の回数 / scheduler が選んだ回数と
+    // 待った tick 数の合計・最大 / direct switch を見送った回数。ipc_handoff.rs）
+    pub ipc_handoff_direct: u64,
+    pub ipc_handoff_scheduled: u64,
+    pub ipc_handoff_scheduled_ticks: u64,
+    pub ipc_handoff_scheduled_max_ticks: u64,
+    pub ipc_direct_switch_declined: u64,
+    // AddressSpace の region が足りずに拒否した Map / Unmap（aspace_apply。mem/address_space.rs）
+    pub mappings_rejected: u64,
+    // swap の失敗で kill した task（TaskKillReason::SwapFailed。swap.rs）
+    pub task_killed_swap_failed: u64,
+    // endpoint ごとの統計（endpoint id で引く。endpoint_stats.rs）。wire の counter には入れない
+    pub per_endpoint: [endpoint_stats::EndpointStats; MAX_ENDPOINTS],
 }
 
 impl KernelCounters {
     pub const fn new() -> Self {
         KernelCounters {
             sched_switches: 0,
+            sched_rr_max_passes: 0,
             ipc_send_fast: 0,
             ipc_send_slow: 0,
             ipc_recv_fast: 0,
             ipc_recv_slow: 0,
             ipc_reply_delivered: 0,
+            ipc_server_slow: 0,
+            ipc_reply_timeouts: 0,
+            ipc_call_timeouts: 0,
+            prio_inherited: 0,
+            prio_restored: 0,
+            task_created: 0,
+            task_exited: 0,
+            notify_signals: 0,
+            notify_delivered: 0,
+            notify_wait_blocked: 0,
+            cap_denied: 0,
+            cap_transfers: 0,
+            cap_transfer_failed: 0,
             task_killed_user_pf: 0,
             task_killed_demo_injected: 0,
+            task_killed_trap_frame: 0,
+            task_killed_fault_storm: 0,
+            task_killed_stack_overflow: 0,
+            user_pf_total: 0,
+            ipc_events_seen: 0,
+            ipc_events_skipped: 0,
+            syscall_entry_int80: 0,
+            syscall_entry_syscall: 0,
+            swap_out: 0,
+            swap_in: 0,
+            tlb_flush_deferred: 0,
+            tlb_flushes: 0,
+            mlfq_demotions: 0,
+            mlfq_promotions: 0,
+            mlfq_level_ticks: [0; sched_policy::MLFQ_LEVELS],
+            idle_entries: 0,
+            watchdog_no_progress: 0,
+            ipc_deadlocks_detected: 0,
+            ipc_deadlocks_broken: 0,
+            events_overwritten: 0,
+            events_overwritten_by_class: [0; event_log::EVENT_CLASSES],
+            critical_events_retained: 0,
+            critical_events_lost: 0,
+            invariant_violations: 0,
+            invariant_checks_full: 0,
+            invariant_checks_cheap: 0,
+            input_received: 0,
+            input_dropped: 0,
+            input_read: 0,
+            task_killed_user_exception: 0,
+            task_killed_shutdown: 0,
+            fault_delegated: 0,
+            fault_resumed: 0,
+            fault_orphaned: 0,
+            grant_mapped: 0,
+            grant_revoked: 0,
+            grant_failed: 0,
+            derivations_recorded: 0,
+            derivations_revoked: 0,
+            derivation_table_full: 0,
+            tasks_suspended: 0,
+            tasks_resumed: 0,
+            suspend_recv_deferred: 0,
+            task_info_queries: 0,
+            reply_caps_minted: 0,
+            reply_caps_rejected: 0,
+            ipc_buffered_sends: 0,
+            ipc_buffered_delivered: 0,
+            ipc_buffer_dropped: 0,
+            ipc_rendezvous_delivered: 0,
+            ipc_handoff_direct: 0,
+            ipc_handoff_scheduled: 0,
+            ipc_handoff_scheduled_ticks: 0,
+            ipc_handoff_scheduled_max_ticks: 0,
+            ipc_direct_switch_declined: 0,
+            mappings_rejected: 0,
+            task_killed_swap_failed: 0,
+            per_endpoint: [endpoint_stats::EndpointStats::new(); MAX_ENDPOINTS],
         }
     }
 }
@@ -235,11 +748,18 @@ pub struct KernelState {
 
     tick_count: u64,
     time_ticks: u64,
+    // kernel clock の今（tick の先頭で time.rs の sample_clock が進める）
+    clock_now: Instant,
     should_halt: bool,
     activity: KernelActivity,
 
     address_spaces: [AddressSpace; MAX_TASKS],
 
+    // task ごとの capability table（task index で引く、cspace.rs）
+    cspaces: [CapTable; MAX_TASKS],
+    // cap transfer ごとに払い出す一意番号（CapGrantTag.seq）
+    next_cap_grant_seq: u64,
+
     tasks: [Task; MAX_TASKS],
     num_tasks: usize,
     current_task: usize,
@@ -247,13 +767,20 @@ pub struct KernelState {
     ready_queue: [usize; MAX_TASKS],
     rq_len: usize,
 
+    // ready_queue で待つ間に、同優先度の他 task が dispatch された回数（dispatch / block で 0 に戻す）
+    rq_same_prio_passes: [u64; MAX_TASKS],
+
     wait_queue: [usize; MAX_TASKS],
     wq_len: usize,
 
     // event log（リングバッファ）
     event_log: [Option<LogEvent>; EVENT_LOG_CAP],
+    // ★追加: 各 event の通し番号（logging::next_seq、全 sink 共通）
+    event_seq: [u64; EVENT_LOG_CAP],
     event_log_head: usize,
     event_log_len: usize,
+    // 上書きから退避した重要 event（event_log_retain_critical。event_log.rs）
+    retained_events: event_log::RetainedEvents,
 
     quantum: u64,
 
@@ -263,6 +790,87 @@ pub struct KernelState {
 
     endpoints: [Endpoint; MAX_ENDPOINTS],
 
+    notifications: [Notification; MAX_NOTIFICATIONS],
+
+    // reply obligation 計測（client の task index で引く）
+    // - Blocked(IpcReply) に入った tick と、ServerSlow を出したか
+    reply_wait_since: [Option<u64>; MAX_TASKS],
+    reply_slow_reported: [bool; MAX_TASKS],
+
+    // reply object（client の task index で引く。Blocked(IpcReply) の間だけ Some）と次に払い出す seq（reply_cap.rs）
+    reply_objects: [Option<reply_cap::ReplyObject>; MAX_TASKS],
+    next_reply_seq: u64,
+
+    // IpcSend の timeout 期限（kernel clock、task index で引く）。Blocked(IpcSend / IpcReply) の間だけ Some
+    ipc_deadline: [Option<Instant>; MAX_TASKS],
+
+    // send fastpath で起こされた tick（receiver の task index で引く。走ったら None。ipc_handoff.rs）
+    ipc_handoff_since: [Option<u64>; MAX_TASKS],
+
+    // user #PF レート制限（task index で引く、固定窓）
+    pf_window_start: [u64; MAX_TASKS],
+    pf_window_count: [u64; MAX_TASKS],
+
+    // TaskCreate で払い出す次の TaskId（再利用しない。静的 task の次から）
+    next_task_id: u64,
+
+    #[cfg(feature = "timer_service")]
+    timer_service: timer_service::TimerService,
+
+    #[cfg(feature = "virtio_net")]
+    net: net::NetService,
+
+    // 遷移ごとの抽象状態の差（refinement.rs）
+    #[cfg(feature = "refinement_trace")]
+    refinement: refinement::RefinementTrace,
+
+    // task ごとの kernel stack（tick の末尾で current_task の stack に切り替える）
+    #[cfg(feature = "kstack_switch")]
+    kstacks: kstack::KernelStacks,
+
+    // ring3 で走る task の user RIP / RSP と int 0x80 の待ち
+    #[cfg(feature = "ring3_tasks")]
+    ring3: ring3_task::Ring3Tasks,
+
+    // swap 領域（swap_demo のときだけフレームを持つ）
+    swap: swap::SwapStore,
+
+    // shared memory segment（shm_id = index）
+    shm: [shm::ShmSegment; shm::MAX_SHM_SEGMENTS],
+
+    // AddressSpace ごとの遅延 TLB flush
+    tlb: tlb::TlbState,
+
+    // MLFQ の task ごとの level（sched_mlfq のときだけ使う。sched_policy.rs）
+    mlfq: sched_policy::MlfqState,
+    // Chaos の乱数（sched_chaos のときだけ使う。seed は apply_boot_params で入れる）
+    chaos: sched_policy::ChaosState,
+    watchdog: watchdog::WatchdogState,
+    deadlock: deadlock::DeadlockState,
+    // invariant 違反の pending / latch（invariant.rs）
+    invariants: invariant::InvariantLatch,
+    // keyboard の scancode（IRQ1 → ReadInput。input.rs）
+    input: input::InputQueue,
+    // 読み取り専用 fs の file 表と task ごとの fd（FsOpen / FsRead。fs.rs）
+    fs: fs::FsState,
+    // task ごとの fault handler endpoint と未解決の #PF（FaultHandlerSet / FaultResolve。fault_handler.rs）
+    faults: fault_handler::FaultHandlers,
+    // task ごとの grant window と受け取り中の grant（GrantWindowSet / IPC の page grant。grant.rs）
+    grants: grant::Grants,
+    // cap transfer / page grant の派生の辺（Revoke。derivation.rs）
+    derivations: derivation::Derivations,
+    // debug_console: COM1 から組み立て中の 1 行（console.rs）
+    #[cfg(feature = "debug_console")]
+    console: console::ConsoleState,
+    // smp: AP の起動結果・check-in と AP が持っている task（smp.rs）
+    #[cfg(feature = "smp")]
+    smp: smp::SmpState,
+
+    // CPU 時間の内訳（user / kernel / idle の合計 = tick_count）
+    cpu_time: CpuTime,
+    // 前回の CPU 時間の計上から syscall を処理したか（handle_syscall が立てる）
+    syscall_since_account: bool,
+
     demo_msgs_delivered: u8,
     demo_replies_sent: u8,
     demo_sent_by_task2: bool,
@@ -292,25 +900,48 @@ pub struct KernelState {
 impl KernelState {
     pub fn new(boot_info: &'static BootInfo) -> Self {
         let mut phys_mem = PhysicalMemoryManager::new(boot_info);
+        // hardware clock（HPET の register は PMM に予約して MMIO window に map する。2 回目以降は何もしない）
+        Arch::clock_init(&mut phys_mem);
+        Self::with_phys_mem(phys_mem)
+    }
 
-        let root_frame_for_task0: PhysFrame = {
-            let (level_4_frame, _) = Cr3::read();
-            let phys_u64 = level_4_frame.start_address().as_u64();
-            let frame_index = phys_u64 / PAGE_SIZE;
-            PhysFrame::from_index(frame_index)
-        };
+    /// 既存の PhysicalMemoryManager から作る（PMM は 1 個だけ。scenario suite が KernelState を作り直すとき用）
+    pub fn with_phys_mem(mut phys_mem: PhysicalMemoryManager) -> Self {
+
+        // kernel heap（mm::heap）: user root を作る前に kernel root に map する（high-half ごとコピーされる）
+        if !crate::mm::heap::is_initialized() {
+            match Arch::map_kernel_heap(&mut phys_mem) {
+                Ok((base, size)) => {
+                    unsafe { crate::mm::heap::init(base, size) };
+                    heap_self_test();
+                }
+                Err(_e) => logging::error("kernel heap: map failed; alloc disabled"),
+            }
+        }
+
+        let root_frame_for_task0: PhysFrame = Arch::boot_root();
 
         let tasks = [
             Task {
                 id: TASK0_ID,
                 state: TaskState::Running,
                 priority: 1,
+                base_priority: 1,
+                inherited_from: None,
+                entry_hint: 0,
+                parent: None,
                 runtime_ticks: 0,
                 time_slice_used: 0,
                 address_space_id: AddressSpaceId(KERNEL_ASID_INDEX),
                 blocked_reason: None,
+                wake_at: None,
                 last_msg: None,
+                last_recv_ep: None,
+                last_reply_cap: None,
                 last_reply: None,
+                last_notify: None,
+                last_task_info: None,
+                last_mem_info: None,
                 last_syscall_ret: None,
                 last_syscall_ret_unread: false,
                 pending_send_msg: None,
@@ -320,12 +951,22 @@ impl KernelState {
                 id: TASK1_ID,
                 state: TaskState::Ready,
                 priority: 3,
+                base_priority: 3,
+                inherited_from: None,
+                entry_hint: 0,
+                parent: None,
                 runtime_ticks: 0,
                 time_slice_used: 0,
                 address_space_id: AddressSpaceId(FIRST_USER_ASID_INDEX),
                 blocked_reason: None,
+                wake_at: None,
                 last_msg: None,
+                last_recv_ep: None,
+                last_reply_cap: None,
                 last_reply: None,
+                last_notify: None,
+                last_task_info: None,
+                last_mem_info: None,
                 last_syscall_ret: None,
                 last_syscall_ret_unread: false,
                 pending_send_msg: None,
@@ -335,12 +976,47 @@ impl KernelState {
                 id: TASK2_ID,
                 state: TaskState::Ready,
                 priority: 2,
+                base_priority: 2,
+                inherited_from: None,
+                entry_hint: 0,
+                parent: None,
                 runtime_ticks: 0,
                 time_slice_used: 0,
                 address_space_id: AddressSpaceId(FIRST_USER_ASID_INDEX + 1),
                 blocked_reason: None,
+                wake_at: None,
+                last_msg: None,
+                last_recv_ep: None,
+                last_reply_cap: None,
+                last_reply: None,
+                last_notify: None,
+                last_task_info: None,
+                last_mem_info: None,
+                last_syscall_ret: None,
+                last_syscall_ret_unread: false,
+                pending_send_msg: None,
+                pending_syscall: None,
+            },
+            Task {
+                id: IDLE_TASK_ID,
+                state: TaskState::Ready,
+                priority: IDLE_TASK_PRIORITY,
+                base_priority: IDLE_TASK_PRIORITY,
+                inherited_from: None,
+                entry_hint: 0,
+                parent: None,
+                runtime_ticks: 0,
+                time_slice_used: 0,
+                address_space_id: AddressSpaceId(KERNEL_ASID_INDEX),
+                blocked_reason: None,
+                wake_at: None,
                 last_msg: None,
+                last_recv_ep: None,
+                last_reply_cap: None,
                 last_reply: None,
+                last_notify: None,
+                last_task_info: None,
+                last_mem_info: None,
                 last_syscall_ret: None,
                 last_syscall_ret_unread: false,
                 pending_send_msg: None,
@@ -348,10 +1024,12 @@ impl KernelState {
             },
         ];
 
+        // idle の slot の分も user root を持たせる（使わないが、slot ごとの invariant を揃える）
         let mut address_spaces = [
             AddressSpace::new_kernel(),
             AddressSpace::new_user(),
             AddressSpace::new_user(),
+            AddressSpace::new_user(),
         ];
 
         address_spaces[KERNEL_ASID_INDEX].root_page_frame = Some(root_frame_for_task0);
@@ -371,23 +1049,27 @@ impl KernelState {
             logging::info_u64("as_idx", as_idx as u64);
             logging::info_u64("root_page_frame_index", user_root.number);
 
-            arch::paging::init_user_pml4_from_current(user_root);
+            Arch::init_user_pml4_from_current(user_root);
 
             logging::info("init_user_pml4_from_current: done");
         }
 
-        let ready_queue = [TASK1_INDEX, TASK2_INDEX, 0];
+        let ready_queue = [TASK1_INDEX, TASK2_INDEX, 0, 0];
         let rq_len = 2;
 
         let mut ks = KernelState {
             phys_mem,
             tick_count: 0,
             time_ticks: 0,
+            clock_now: Instant::from_ticks(0),
             should_halt: false,
             activity: KernelActivity::Idle,
 
             address_spaces,
 
+            cspaces: core::array::from_fn(CapTable::default_for_task),
+            next_cap_grant_seq: 0,
+
             tasks,
             num_tasks: MAX_TASKS,
             current_task: TASK0_INDEX,
@@ -395,23 +1077,83 @@ impl KernelState {
             ready_queue,
             rq_len,
 
+            rq_same_prio_passes: [0; MAX_TASKS],
+
             wait_queue: [0; MAX_TASKS],
             wq_len: 0,
 
             event_log: [None; EVENT_LOG_CAP],
+            event_seq: [0; EVENT_LOG_CAP],
             event_log_head: 0,
             event_log_len: 0,
+            retained_events: event_log::RetainedEvents::new(),
 
-            quantum: 5,
+            quantum: DEFAULT_QUANTUM,
 
             mem_demo_mapped: [false; MAX_TASKS],
             mem_demo_stage: [0; MAX_TASKS],
             mem_demo_frame: [None; MAX_TASKS],
 
-            endpoints: [
-                Endpoint::new(EndpointId(0)),
-                Endpoint::new(EndpointId(1)),
-            ],
+            endpoints: core::array::from_fn(|i| {
+                if i < STATIC_ENDPOINTS {
+                    Endpoint::new(EndpointId(i))
+                } else {
+                    Endpoint::unallocated(EndpointId(i))
+                }
+            }),
+
+            notifications: core::array::from_fn(|i| Notification::new(NotificationId(i))),
+
+            reply_wait_since: [None; MAX_TASKS],
+            reply_slow_reported: [false; MAX_TASKS],
+
+            reply_objects: [None; MAX_TASKS],
+            next_reply_seq: 1,
+
+            ipc_deadline: [None; MAX_TASKS],
+            ipc_handoff_since: [None; MAX_TASKS],
+
+            pf_window_start: [0; MAX_TASKS],
+            pf_window_count: [0; MAX_TASKS],
+
+            next_task_id: TASK2_ID.0 + 1,
+
+            #[cfg(feature = "timer_service")]
+            timer_service: timer_service::TimerService::new(),
+
+            #[cfg(feature = "virtio_net")]
+            net: net::NetService::new(),
+
+            #[cfg(feature = "refinement_trace")]
+            refinement: refinement::RefinementTrace::new(),
+
+            #[cfg(feature = "kstack_switch")]
+            kstacks: kstack::KernelStacks::new(),
+
+            #[cfg(feature = "ring3_tasks")]
+            ring3: ring3_task::Ring3Tasks::new(),
+
+            swap: swap::SwapStore::new(),
+
+            shm: [shm::ShmSegment::EMPTY; shm::MAX_SHM_SEGMENTS],
+
+            tlb: tlb::TlbState::new(),
+            mlfq: sched_policy::MlfqState::new(),
+            chaos: sched_policy::ChaosState::new(sched_policy::CHAOS_DEFAULT_SEED),
+            watchdog: watchdog::WatchdogState::new(),
+            deadlock: deadlock::DeadlockState::new(),
+            invariants: invariant::InvariantLatch::new(),
+            input: input::InputQueue::new(),
+            fs: fs::FsState::new(),
+            faults: fault_handler::FaultHandlers::new(),
+            grants: grant::Grants::new(),
+            derivations: derivation::Derivations::new(),
+            #[cfg(feature = "debug_console")]
+            console: console::ConsoleState::new(),
+            #[cfg(feature = "smp")]
+            smp: smp::SmpState::new(),
+            cpu_time: CpuTime::new(),
+            syscall_since_account: false,
 
             demo_msgs_delivered: 0,
             demo_replies_sent: 0,
@@ -437,6 +1179,12 @@ impl KernelState {
         // - 通常ビルドでは owner=None のまま（close の発火源を排除）
         // ---------------------------------------------------------------------
 
+        // task の handle は TaskId が要るので、task table を作ってから入れる（cspace.rs）
+        ks.install_task_handles();
+
+        // command line（boot_params.rs）の quantum / invariant level
+        ks.apply_boot_params();
+
         crate::kernel::demo::on_kernel_state_init(&mut ks);
         ks
     }
@@ -447,13 +1195,37 @@ impl KernelState {
         &mut self.phys_mem
     }
 
+    /// KernelState を捨てて PMM だけ取り出す（次の scenario に引き継ぐ）
+    /// - 捨てた state の user root / task の frame は解放しない（割当済みのまま残る）
+    #[cfg_attr(not(feature = "scenario_suite"), allow(dead_code))]
+    pub fn into_phys_mem(self) -> PhysicalMemoryManager {
+        self.phys_mem
+    }
+
     fn push_event(&mut self, ev: LogEvent) {
         if EVENT_LOG_CAP == 0 {
             return;
         }
 
+        // watchdog の進捗はサンプリングで log から落ちる event も数える
+        self.watchdog_note_event(&ev);
+
+        // IPC event は決定的サンプリング（seen の 1, N+1, 2N+1, ... 番目だけ残す）
+        if is_ipc_event(&ev) {
+            self.counters.ipc_events_seen += 1;
+            if IPC_EVENT_SAMPLE_EVERY > 1 && (self.counters.ipc_events_seen - 1) % IPC_EVENT_SAMPLE_EVERY != 0 {
+                self.counters.ipc_events_skipped += 1;
+                return;
+            }
+        }
+
         let pos = (self.event_log_head + self.event_log_len) % EVENT_LOG_CAP;
+        if self.event_log_len == EVENT_LOG_CAP {
+            // 満杯: pos は最古の event（これから上書きする）
+            self.note_event_overwritten(pos);
+        }
         self.event_log[pos] = Some(ev);
+        self.event_seq[pos] = logging::next_seq();
 
         if self.event_log_len < EVENT_LOG_CAP {
             self.event_log_len += 1;
@@ -499,6 +1271,7 @@ impl KernelState {
         self.tasks[idx].last_syscall_ret.take()
     }
 
+    #[spec("INV-SCHED-001", "INV-SCHED-002", "INV-WAIT-001", "INV-IPC-003", "INV-MEM-004", "INV-MEM-005")]
     fn debug_check_invariants(&self) {
         // -------------------------------------------------------------------------
         // AddressSpace の基本整合
@@ -506,39 +1279,69 @@ impl KernelState {
         {
             let kernel_as = &self.address_spaces[KERNEL_ASID_INDEX];
             if kernel_as.kind != AddressSpaceKind::Kernel {
-                logging::error("INVARIANT VIOLATION: address_spaces[0] is not Kernel");
+                self.invariant_violated(
+                    InvariantId::AddressSpaceLayout,
+                    None,
+                    Some(KERNEL_ASID_INDEX as u64),
+                    "INVARIANT VIOLATION: address_spaces[0] is not Kernel",
+                );
             }
             if kernel_as.root_page_frame.is_none() {
-                logging::error("INVARIANT VIOLATION: kernel address space has no root_page_frame");
+                self.invariant_violated(
+                    InvariantId::AddressSpaceLayout,
+                    None,
+                    Some(KERNEL_ASID_INDEX as u64),
+                    "INVARIANT VIOLATION: kernel address space has no root_page_frame",
+                );
             }
         }
 
         for as_idx in FIRST_USER_ASID_INDEX..self.num_tasks {
             let aspace = &self.address_spaces[as_idx];
             if aspace.kind != AddressSpaceKind::User {
-                logging::error("INVARIANT VIOLATION: user address space kind is not User");
+                self.invariant_violated(
+                    InvariantId::AddressSpaceLayout,
+                    None,
+                    Some(as_idx as u64),
+                    "INVARIANT VIOLATION: user address space kind is not User",
+                );
                 logging::info_u64("as_idx", as_idx as u64);
             }
             if aspace.root_page_frame.is_none() {
-                logging::error("INVARIANT VIOLATION: user address space has no root_page_frame");
+                self.invariant_violated(
+                    InvariantId::AddressSpaceLayout,
+                    None,
+                    Some(as_idx as u64),
+                    "INVARIANT VIOLATION: user address space has no root_page_frame",
+                );
                 logging::info_u64("as_idx", as_idx as u64);
             }
         }
 
         // -------------------------------------------------------------------------
-        // mem::layout と arch::paging のユーザ空間定数の整合（ズレ検知）
+        // mem::layout と ArchOps のユーザ空間定数の整合（ズレ検知）
         // - 将来どちらかだけ更新して事故るのを防ぐ
         // -------------------------------------------------------------------------
         {
-            if arch::paging::USER_SPACE_BASE != USER_SPACE_START {
-                logging::error("INVARIANT VIOLATION: USER_SPACE_BASE mismatch (arch vs mem::layout)");
-                logging::info_u64("arch_USER_SPACE_BASE", arch::paging::USER_SPACE_BASE);
+            if Arch::USER_SPACE_BASE != USER_SPACE_START {
+                self.invariant_violated(
+                    InvariantId::AddressSpaceLayout,
+                    None,
+                    None,
+                    "INVARIANT VIOLATION: USER_SPACE_BASE mismatch (arch vs mem::layout)",
+                );
+                logging::info_u64("arch_USER_SPACE_BASE", Arch::USER_SPACE_BASE);
                 logging::info_u64("layout_USER_SPACE_START", USER_SPACE_START);
             }
 
-            if arch::paging::USER_SPACE_SIZE != PML4_SLOT_SIZE {
-                logging::error("INVARIANT VIOLATION: USER_SPACE_SIZE mismatch (arch vs mem::layout)");
-                logging::info_u64("arch_USER_SPACE_SIZE", arch::paging::USER_SPACE_SIZE);
+            if Arch::USER_SPACE_SIZE != PML4_SLOT_SIZE {
+                self.invariant_violated(
+                    InvariantId::AddressSpaceLayout,
+                    None,
+                    None,
+                    "INVARIANT VIOLATION: USER_SPACE_SIZE mismatch (arch vs mem::layout)",
+                );
+                logging::info_u64("arch_USER_SPACE_SIZE", Arch::USER_SPACE_SIZE);
                 logging::info_u64("layout_PML4_SLOT_SIZE", PML4_SLOT_SIZE);
             }
 
@@ -546,57 +1349,9 @@ impl KernelState {
         }
 
         // -------------------------------------------------------------------------
-        // TaskState と BlockedReason の整合
-        // -------------------------------------------------------------------------
-        for (idx, t) in self.tasks.iter().enumerate().take(self.num_tasks) {
-            match t.state {
-                TaskState::Blocked => {
-                    if t.blocked_reason.is_none() {
-                        logging::error("INVARIANT VIOLATION: BLOCKED task has no blocked_reason");
-                        logging::info_u64("task_index", idx as u64);
-                        logging::info_u64("task_id", t.id.0);
-                    }
-                }
-                TaskState::Dead => {
-                    if t.blocked_reason.is_some() {
-                        logging::error("INVARIANT VIOLATION: DEAD task has blocked_reason");
-                        logging::info_u64("task_index", idx as u64);
-                        logging::info_u64("task_id", t.id.0);
-                    }
-
-                    if t.last_msg.is_some()
-                        || t.last_reply.is_some()
-                        || t.pending_send_msg.is_some()
-                        || t.pending_syscall.is_some()
-                    {
-                        logging::error("INVARIANT VIOLATION: DEAD task has leftover task-local state");
-                        logging::info_u64("task_index", idx as u64);
-                        logging::info_u64("task_id", t.id.0);
-                    }
-                }
-                _ => {
-                    if t.blocked_reason.is_some() {
-                        logging::error("INVARIANT VIOLATION: non-BLOCKED task has blocked_reason");
-                        logging::info_u64("task_index", idx as u64);
-                        logging::info_u64("task_id", t.id.0);
-                    }
-                }
-            }
-        }
-
-        // -------------------------------------------------------------------------
-        // current_task の整合（Dead が current になるのは禁止）
+        // TaskState と BlockedReason / current_task の整合
         // -------------------------------------------------------------------------
-        if self.current_task >= self.num_tasks {
-            logging::error("INVARIANT VIOLATION: current_task out of range");
-        } else {
-            let st = self.tasks[self.current_task].state;
-            if st == TaskState::Dead {
-                logging::error("INVARIANT VIOLATION: current_task is DEAD");
-            } else if st != TaskState::Running {
-                logging::error("INVARIANT VIOLATION: current_task is not RUNNING");
-            }
-        }
+        self.check_task_state_invariants();
 
         // -------------------------------------------------------------------------
         // User AddressSpace の mapping 整合
@@ -614,8 +1369,13 @@ impl KernelState {
 
                 let offset = m.page.number * PAGE_SIZE;
 
-                if offset >= arch::paging::USER_SPACE_SIZE {
-                    logging::error("INVARIANT VIOLATION: user mapping offset out of user slot range");
+                if offset >= Arch::USER_SPACE_SIZE {
+                    self.invariant_violated(
+                        InvariantId::UserMappingRange,
+                        None,
+                        Some(m.page.number),
+                        "INVARIANT VIOLATION: user mapping offset out of user slot range",
+                    );
                     logging::info_u64("as_idx", as_idx as u64);
                     logging::info_u64("virt_page_index", m.page.number);
                     logging::info_u64("offset", offset);
@@ -625,6 +1385,82 @@ impl KernelState {
             });
         }
 
+        // -------------------------------------------------------------------------
+        // mapping region は重ならず、空の region は無い（INV-MEM-011）
+        // -------------------------------------------------------------------------
+        self.check_region_invariants();
+
+        // -------------------------------------------------------------------------
+        // mapping / user root が参照するフレームは allocator 上で「確保中」であること
+        // - 解放済みフレームが map に残っていると、次の確保で二重に配られる
+        // - kernel root は bootloader の page table（allocator 管理外）なので見ない
+        // -------------------------------------------------------------------------
+        for as_idx in 0..self.num_tasks {
+            let aspace = &self.address_spaces[as_idx];
+
+            aspace.for_each_mapping(|m| {
+                if !self.phys_mem.is_frame_allocated(to_arch_frame(m.frame)) {
+                    self.invariant_violated(
+                        InvariantId::FrameAllocated,
+                        None,
+                        Some(m.frame.number),
+                        "INVARIANT VIOLATION: mapped frame is not allocated",
+                    );
+                    logging::info_u64("as_idx", as_idx as u64);
+                    logging::info_u64("virt_page_index", m.page.number);
+                    logging::info_u64("phys_frame_index", m.frame.number);
+                }
+            });
+
+            // 参照カウント = 全 AddressSpace の mapping 数（共有フレームも含む）
+            aspace.for_each_mapping(|m| {
+                let mapped = self.mapping_count_of_frame(m.frame);
+                let refs = self.phys_mem.frame_ref_count(to_arch_frame(m.frame)) as usize;
+                if refs != mapped {
+                    self.invariant_violated(
+                        InvariantId::FrameRefcount,
+                        None,
+                        Some(m.frame.number),
+                        "INVARIANT VIOLATION: frame refcount != mapping count",
+                    );
+                    logging::info_u64("phys_frame_index", m.frame.number);
+                    logging::info_u64("frame_refcount", refs as u64);
+                    logging::info_u64("mapping_count", mapped as u64);
+                }
+            });
+
+            if aspace.kind != AddressSpaceKind::User {
+                continue;
+            }
+            if let Some(root) = aspace.root_page_frame {
+                if !self.phys_mem.is_frame_allocated(to_arch_frame(root)) {
+                    self.invariant_violated(
+                        InvariantId::FrameAllocated,
+                        None,
+                        Some(root.number),
+                        "INVARIANT VIOLATION: user root frame is not allocated",
+                    );
+                    logging::info_u64("as_idx", as_idx as u64);
+                    logging::info_u64("phys_frame_index", root.number);
+                }
+            }
+        }
+
+        // 参照カウント表にあるのに、どこにも map されていないフレーム（unref 漏れ）
+        self.phys_mem.for_each_frame_ref(|raw, count| {
+            let frame = PhysFrame::from_index(raw.start_address().as_u64() / PAGE_SIZE);
+            if self.mapping_count_of_frame(frame) == 0 {
+                self.invariant_violated(
+                    InvariantId::FrameRefcount,
+                    None,
+                    Some(frame.number),
+                    "INVARIANT VIOLATION: frame has refcount but no mapping",
+                );
+                logging::info_u64("phys_frame_index", frame.number);
+                logging::info_u64("frame_refcount", count as u64);
+            }
+        });
+
         // -------------------------------------------------------------------------
         // Step1: Kernel task は endpoint 構造に絶対に現れない（混入検知）
         // -------------------------------------------------------------------------
@@ -647,11 +1483,16 @@ impl KernelState {
             // Step2: closed endpoint は待ち構造を持たない（close で rescue 済みのはず）
             // -----------------------------------------------------------------
             if e.is_closed {
-                if e.recv_waiter.is_some() || e.sq_len != 0 || e.rq_len != 0 {
-                    logging::error("INVARIANT VIOLATION: CLOSED endpoint has waiters/queues");
+                if e.recv_waiter.is_some() || !e.send_queue.is_empty() || !e.reply_queue.is_empty() {
+                    self.invariant_violated(
+                        InvariantId::IpcClosedEndpoint,
+                        None,
+                        Some(e.id.0 as u64),
+                        "INVARIANT VIOLATION: CLOSED endpoint has waiters/queues",
+                    );
                     logging::info_u64("ep_id", e.id.0 as u64);
-                    logging::info_u64("sq_len", e.sq_len as u64);
-                    logging::info_u64("rq_len", e.rq_len as u64);
+                    logging::info_u64("sq_len", e.send_queue.len() as u64);
+                    logging::info_u64("rq_len", e.reply_queue.len() as u64);
                     if let Some(w) = e.recv_waiter {
                         logging::info_u64("recv_waiter_task_index", w as u64);
                     }
@@ -660,40 +1501,69 @@ impl KernelState {
 
             if let Some(tidx) = e.recv_waiter {
                 if tidx >= self.num_tasks {
-                    logging::error("INVARIANT VIOLATION: endpoint.recv_waiter out of range");
+                    self.invariant_violated(
+                        InvariantId::IpcRecvWaiter,
+                        None,
+                        Some(e.id.0 as u64),
+                        "INVARIANT VIOLATION: endpoint.recv_waiter out of range",
+                    );
                 } else {
                     let t = &self.tasks[tidx];
 
                     // ★Step1: kernel task 混入検知
                     if is_kernel_task_index(tidx) {
-                        logging::error("INVARIANT VIOLATION: kernel task appears as endpoint.recv_waiter");
+                        self.invariant_violated(
+                            InvariantId::IpcKernelTask,
+                            Some(t.id),
+                            Some(e.id.0 as u64),
+                            "INVARIANT VIOLATION: kernel task appears as endpoint.recv_waiter",
+                        );
                         logging::info_u64("task_id", t.id.0);
                         logging::info_u64("ep_id", e.id.0 as u64);
                     }
 
                     if t.state == TaskState::Dead {
-                        logging::error("INVARIANT VIOLATION: endpoint.recv_waiter points DEAD task");
+                        self.invariant_violated(
+                            InvariantId::IpcRecvWaiter,
+                            Some(t.id),
+                            Some(e.id.0 as u64),
+                            "INVARIANT VIOLATION: endpoint.recv_waiter points DEAD task",
+                        );
                         logging::info_u64("task_id", t.id.0);
                     }
-                    if t.state != TaskState::Blocked {
-                        logging::error("INVARIANT VIOLATION: recv_waiter is not BLOCKED");
+                    if !t.state.can_wait() {
+                        self.invariant_violated(
+                            InvariantId::IpcRecvWaiter,
+                            Some(t.id),
+                            Some(e.id.0 as u64),
+                            "INVARIANT VIOLATION: recv_waiter is not BLOCKED",
+                        );
                         logging::info_u64("task_id", t.id.0);
                     }
 
                     match t.blocked_reason {
-                        Some(BlockedReason::IpcRecv { ep }) if ep == e.id => {}
+                        Some(r) if r.waits_recv_on(e.id) => {}
                         _ => {
-                            logging::error("INVARIANT VIOLATION: recv_waiter blocked_reason mismatch");
+                            self.invariant_violated(
+                                InvariantId::IpcRecvWaiter,
+                                Some(t.id),
+                                Some(e.id.0 as u64),
+                                "INVARIANT VIOLATION: recv_waiter blocked_reason mismatch",
+                            );
                             logging::info_u64("task_id", t.id.0);
                         }
                     }
                 }
             }
 
-            for pos in 0..e.sq_len {
-                let tidx = e.send_queue[pos];
+            for tidx in e.send_queue.iter() {
                 if tidx >= self.num_tasks {
-                    logging::error("INVARIANT VIOLATION: endpoint.send_queue idx out of range");
+                    self.invariant_violated(
+                        InvariantId::IpcSendQueue,
+                        None,
+                        Some(e.id.0 as u64),
+                        "INVARIANT VIOLATION: endpoint.send_queue idx out of range",
+                    );
                     continue;
                 }
 
@@ -701,33 +1571,57 @@ impl KernelState {
 
                 // ★Step1: kernel task 混入検知
                 if is_kernel_task_index(tidx) {
-                    logging::error("INVARIANT VIOLATION: kernel task appears in endpoint.send_queue");
+                    self.invariant_violated(
+                        InvariantId::IpcKernelTask,
+                        Some(t.id),
+                        Some(e.id.0 as u64),
+                        "INVARIANT VIOLATION: kernel task appears in endpoint.send_queue",
+                    );
                     logging::info_u64("task_id", t.id.0);
                     logging::info_u64("ep_id", e.id.0 as u64);
                 }
 
                 if t.state == TaskState::Dead {
-                    logging::error("INVARIANT VIOLATION: send_queue contains DEAD task");
+                    self.invariant_violated(
+                        InvariantId::IpcSendQueue,
+                        Some(t.id),
+                        Some(e.id.0 as u64),
+                        "INVARIANT VIOLATION: send_queue contains DEAD task",
+                    );
                     logging::info_u64("task_id", t.id.0);
                 }
-                if t.state != TaskState::Blocked {
-                    logging::error("INVARIANT VIOLATION: sender in send_queue is not BLOCKED");
+                if !t.state.can_wait() {
+                    self.invariant_violated(
+                        InvariantId::IpcSendQueue,
+                        Some(t.id),
+                        Some(e.id.0 as u64),
+                        "INVARIANT VIOLATION: sender in send_queue is not BLOCKED",
+                    );
                     logging::info_u64("task_id", t.id.0);
                 }
 
                 match t.blocked_reason {
                     Some(BlockedReason::IpcSend { ep }) if ep == e.id => {}
                     _ => {
-                        logging::error("INVARIANT VIOLATION: sender blocked_reason mismatch");
+                        self.invariant_violated(
+                            InvariantId::IpcSendQueue,
+                            Some(t.id),
+                            Some(e.id.0 as u64),
+                            "INVARIANT VIOLATION: sender blocked_reason mismatch",
+                        );
                         logging::info_u64("task_id", t.id.0);
                     }
                 }
             }
 
-            for pos in 0..e.rq_len {
-                let tidx = e.reply_queue[pos];
+            for tidx in e.reply_queue.iter() {
                 if tidx >= self.num_tasks {
-                    logging::error("INVARIANT VIOLATION: endpoint.reply_queue idx out of range");
+                    self.invariant_violated(
+                        InvariantId::IpcReplyQueue,
+                        None,
+                        Some(e.id.0 as u64),
+                        "INVARIANT VIOLATION: endpoint.reply_queue idx out of range",
+                    );
                     continue;
                 }
 
@@ -735,17 +1629,32 @@ impl KernelState {
 
                 // ★Step1: kernel task 混入検知
                 if is_kernel_task_index(tidx) {
-                    logging::error("INVARIANT VIOLATION: kernel task appears in endpoint.reply_queue");
+                    self.invariant_violated(
+                        InvariantId::IpcKernelTask,
+                        Some(t.id),
+                        Some(e.id.0 as u64),
+                        "INVARIANT VIOLATION: kernel task appears in endpoint.reply_queue",
+                    );
                     logging::info_u64("task_id", t.id.0);
                     logging::info_u64("ep_id", e.id.0 as u64);
                 }
 
                 if t.state == TaskState::Dead {
-                    logging::error("INVARIANT VIOLATION: reply_queue contains DEAD task");
+                    self.invariant_violated(
+                        InvariantId::IpcReplyQueue,
+                        Some(t.id),
+                        Some(e.id.0 as u64),
+                        "INVARIANT VIOLATION: reply_queue contains DEAD task",
+                    );
                     logging::info_u64("task_id", t.id.0);
                 }
-                if t.state != TaskState::Blocked {
-                    logging::error("INVARIANT VIOLATION: reply waiter is not BLOCKED");
+                if !t.state.can_wait() {
+                    self.invariant_violated(
+                        InvariantId::IpcReplyQueue,
+                        Some(t.id),
+                        Some(e.id.0 as u64),
+                        "INVARIANT VIOLATION: reply waiter is not BLOCKED",
+                    );
                     logging::info_u64("task_id", t.id.0);
                 }
 
@@ -753,14 +1662,24 @@ impl KernelState {
                     Some(BlockedReason::IpcReply { ep, partner }) if ep == e.id => {
                         if let Some(pidx) = self.tasks.iter().position(|x| x.id == partner) {
                             if self.tasks[pidx].state == TaskState::Dead {
-                                logging::error("INVARIANT VIOLATION: IpcReply waiter has DEAD partner");
+                                self.invariant_violated(
+                                    InvariantId::IpcDeadPartner,
+                                    Some(t.id),
+                                    Some(partner.0),
+                                    "INVARIANT VIOLATION: IpcReply waiter has DEAD partner",
+                                );
                                 logging::info_u64("waiter_task_id", t.id.0);
                                 logging::info_u64("partner_task_id", partner.0);
                             }
                         }
                     }
                     _ => {
-                        logging::error("INVARIANT VIOLATION: reply waiter blocked_reason mismatch");
+                        self.invariant_violated(
+                            InvariantId::IpcReplyQueue,
+                            Some(t.id),
+                            Some(e.id.0 as u64),
+                            "INVARIANT VIOLATION: reply waiter blocked_reason mismatch",
+                        );
                         logging::info_u64("task_id", t.id.0);
                     }
                 }
@@ -776,13 +1695,23 @@ impl KernelState {
             }
 
             if self.is_in_ready_queue(tidx) {
-                logging::error("INVARIANT VIOLATION: DEAD task is in ready_queue");
+                self.invariant_violated(
+                    InvariantId::ReadyQueue,
+                    Some(t.id),
+                    None,
+                    "INVARIANT VIOLATION: DEAD task is in ready_queue",
+                );
                 logging::info_u64("task_index", tidx as u64);
                 logging::info_u64("task_id", t.id.0);
             }
 
             if self.is_in_wait_queue(tidx) {
-                logging::error("INVARIANT VIOLATION: DEAD task is in wait_queue");
+                self.invariant_violated(
+                    InvariantId::WaitQueue,
+                    Some(t.id),
+                    None,
+                    "INVARIANT VIOLATION: DEAD task is in wait_queue",
+                );
                 logging::info_u64("task_index", tidx as u64);
                 logging::info_u64("task_id", t.id.0);
             }
@@ -797,7 +1726,12 @@ impl KernelState {
                 });
 
                 if found {
-                    logging::error("INVARIANT VIOLATION: DEAD task address space still has USER mappings");
+                    self.invariant_violated(
+                        InvariantId::DeadTaskCleanup,
+                        Some(t.id),
+                        Some(as_idx as u64),
+                        "INVARIANT VIOLATION: DEAD task address space still has USER mappings",
+                    );
                     logging::info_u64("task_index", tidx as u64);
                     logging::info_u64("task_id", t.id.0);
                     logging::info_u64("as_idx", as_idx as u64);
@@ -811,25 +1745,45 @@ impl KernelState {
         for pos in 0..self.wq_len {
             let idx = self.wait_queue[pos];
             if idx >= self.num_tasks {
-                logging::error("INVARIANT VIOLATION: wait_queue contains out-of-range idx");
+                self.invariant_violated(
+                    InvariantId::WaitQueue,
+                    None,
+                    Some(idx as u64),
+                    "INVARIANT VIOLATION: wait_queue contains out-of-range idx",
+                );
                 continue;
             }
 
             let t = &self.tasks[idx];
 
             if t.state == TaskState::Dead {
-                logging::error("INVARIANT VIOLATION: wait_queue contains DEAD task");
+                self.invariant_violated(
+                    InvariantId::WaitQueue,
+                    Some(t.id),
+                    None,
+                    "INVARIANT VIOLATION: wait_queue contains DEAD task",
+                );
                 logging::info_u64("task_id", t.id.0);
                 continue;
             }
 
-            if t.state != TaskState::Blocked {
-                logging::error("INVARIANT VIOLATION: wait_queue contains non-BLOCKED task");
+            if !t.state.can_wait() {
+                self.invariant_violated(
+                    InvariantId::WaitQueue,
+                    Some(t.id),
+                    None,
+                    "INVARIANT VIOLATION: wait_queue contains non-BLOCKED task",
+                );
                 logging::info_u64("task_id", t.id.0);
             }
 
             if t.blocked_reason != Some(BlockedReason::Sleep) {
-                logging::error("INVARIANT VIOLATION: wait_queue contains non-Sleep blocked_reason");
+                self.invariant_violated(
+                    InvariantId::WaitQueue,
+                    Some(t.id),
+                    None,
+                    "INVARIANT VIOLATION: wait_queue contains non-Sleep blocked_reason",
+                );
                 logging::info_u64("task_id", t.id.0);
             }
         }
@@ -838,9 +1792,14 @@ impl KernelState {
             if t.state == TaskState::Dead {
                 continue;
             }
-            if t.state == TaskState::Blocked && t.blocked_reason == Some(BlockedReason::Sleep) {
+            if t.state.can_wait() && t.blocked_reason == Some(BlockedReason::Sleep) {
                 if !self.is_in_wait_queue(idx) {
-                    logging::error("INVARIANT VIOLATION: Sleep BLOCKED task is not in wait_queue");
+                    self.invariant_violated(
+                        InvariantId::WaitQueue,
+                        Some(t.id),
+                        None,
+                        "INVARIANT VIOLATION: Sleep BLOCKED task is not in wait_queue",
+                    );
                     logging::info_u64("task_id", t.id.0);
                 }
             }
@@ -853,14 +1812,21 @@ impl KernelState {
             if t.state == TaskState::Dead {
                 continue;
             }
-            if t.state != TaskState::Blocked {
+            if !t.state.can_wait() {
                 continue;
             }
 
             let reason = match t.blocked_reason {
                 Some(r) => r,
+                // Ready / Running から止めた Suspended は待ちを持たない
+                None if t.state == TaskState::Suspended => continue,
                 None => {
-                    logging::error("INVARIANT VIOLATION: BLOCKED task has no blocked_reason (reverse check)");
+                    self.invariant_violated(
+                        InvariantId::TaskState,
+                        Some(t.id),
+                        None,
+                        "INVARIANT VIOLATION: BLOCKED task has no blocked_reason (reverse check)",
+                    );
                     logging::info_u64("task_id", t.id.0);
                     continue;
                 }
@@ -869,14 +1835,24 @@ impl KernelState {
             match reason {
                 BlockedReason::Sleep => {
                     if !self.is_in_wait_queue(tidx) {
-                        logging::error("INVARIANT VIOLATION: Sleep BLOCKED task not in wait_queue (reverse check)");
+                        self.invariant_violated(
+                            InvariantId::WaitQueue,
+                            Some(t.id),
+                            None,
+                            "INVARIANT VIOLATION: Sleep BLOCKED task not in wait_queue (reverse check)",
+                        );
                         logging::info_u64("task_id", t.id.0);
                     }
                 }
 
                 BlockedReason::IpcRecv { ep } => {
                     if ep.0 >= MAX_ENDPOINTS {
-                        logging::error("INVARIANT VIOLATION: IpcRecv has out-of-range ep (reverse check)");
+                        self.invariant_violated(
+                            InvariantId::IpcRecvWaiter,
+                            Some(t.id),
+                            Some(ep.0 as u64),
+                            "INVARIANT VIOLATION: IpcRecv has out-of-range ep (reverse check)",
+                        );
                         logging::info_u64("task_id", t.id.0);
                         logging::info_u64("ep", ep.0 as u64);
                         continue;
@@ -884,99 +1860,409 @@ impl KernelState {
 
                     let e = &self.endpoints[ep.0];
                     if e.recv_waiter != Some(tidx) {
-                        logging::error("INVARIANT VIOLATION: IpcRecv task not registered as recv_waiter (reverse check)");
+                        self.invariant_violated(
+                            InvariantId::IpcRecvWaiter,
+                            Some(t.id),
+                            Some(ep.0 as u64),
+                            "INVARIANT VIOLATION: IpcRecv task not registered as recv_waiter (reverse check)",
+                        );
                         logging::info_u64("task_id", t.id.0);
                         logging::info_u64("ep", ep.0 as u64);
                     }
 
                     if self.is_in_wait_queue(tidx) {
-                        logging::error("INVARIANT VIOLATION: IpcRecv task is in wait_queue (reverse check)");
+                        self.invariant_violated(
+                            InvariantId::WaitQueue,
+                            Some(t.id),
+                            None,
+                            "INVARIANT VIOLATION: IpcRecv task is in wait_queue (reverse check)",
+                        );
+                        logging::info_u64("task_id", t.id.0);
+                    }
+                }
+
+                // 各 endpoint への登録は check_recv_any_invariants で見る
+                BlockedReason::IpcRecvAny { .. } => {
+                    if self.is_in_wait_queue(tidx) {
+                        self.invariant_violated(
+                            InvariantId::WaitQueue,
+                            Some(t.id),
+                            None,
+                            "INVARIANT VIOLATION: IpcRecvAny task is in wait_queue (reverse check)",
+                        );
                         logging::info_u64("task_id", t.id.0);
                     }
                 }
 
                 BlockedReason::IpcSend { ep } => {
                     if ep.0 >= MAX_ENDPOINTS {
-                        logging::error("INVARIANT VIOLATION: IpcSend has out-of-range ep (reverse check)");
+                        self.invariant_violated(
+                            InvariantId::IpcSendQueue,
+                            Some(t.id),
+                            Some(ep.0 as u64),
+                            "INVARIANT VIOLATION: IpcSend has out-of-range ep (reverse check)",
+                        );
                         logging::info_u64("task_id", t.id.0);
                         logging::info_u64("ep", ep.0 as u64);
                         continue;
                     }
 
                     let e = &self.endpoints[ep.0];
-                    let mut found = false;
-                    for pos in 0..e.sq_len {
-                        if e.send_queue[pos] == tidx {
-                            found = true;
-                            break;
-                        }
-                    }
-                    if !found {
-                        logging::error("INVARIANT VIOLATION: IpcSend task not found in endpoint.send_queue (reverse check)");
+                    if !e.send_queue.contains(tidx) {
+                        self.invariant_violated(
+                            InvariantId::IpcSendQueue,
+                            Some(t.id),
+                            Some(ep.0 as u64),
+                            "INVARIANT VIOLATION: IpcSend task not found in endpoint.send_queue (reverse check)",
+                        );
                         logging::info_u64("task_id", t.id.0);
                         logging::info_u64("ep", ep.0 as u64);
-                        logging::info_u64("sq_len", e.sq_len as u64);
+                        logging::info_u64("sq_len", e.send_queue.len() as u64);
                     }
 
                     if self.is_in_wait_queue(tidx) {
-                        logging::error("INVARIANT VIOLATION: IpcSend task is in wait_queue (reverse check)");
+                        self.invariant_violated(
+                            InvariantId::WaitQueue,
+                            Some(t.id),
+                            None,
+                            "INVARIANT VIOLATION: IpcSend task is in wait_queue (reverse check)",
+                        );
                         logging::info_u64("task_id", t.id.0);
                     }
                 }
 
                 BlockedReason::IpcReply { partner, ep } => {
                     if ep.0 >= MAX_ENDPOINTS {
-                        logging::error("INVARIANT VIOLATION: IpcReply has out-of-range ep (reverse check)");
+                        self.invariant_violated(
+                            InvariantId::IpcReplyQueue,
+                            Some(t.id),
+                            Some(ep.0 as u64),
+                            "INVARIANT VIOLATION: IpcReply has out-of-range ep (reverse check)",
+                        );
                         logging::info_u64("task_id", t.id.0);
                         logging::info_u64("ep", ep.0 as u64);
                         continue;
                     }
 
                     let e = &self.endpoints[ep.0];
-                    let mut found = false;
-                    for pos in 0..e.rq_len {
-                        if e.reply_queue[pos] == tidx {
-                            found = true;
-                            break;
-                        }
-                    }
-                    if !found {
-                        logging::error("INVARIANT VIOLATION: IpcReply task not found in endpoint.reply_queue (reverse check)");
+                    if !e.reply_queue.contains(tidx) {
+                        self.invariant_violated(
+                            InvariantId::IpcReplyQueue,
+                            Some(t.id),
+                            Some(ep.0 as u64),
+                            "INVARIANT VIOLATION: IpcReply task not found in endpoint.reply_queue (reverse check)",
+                        );
                         logging::info_u64("task_id", t.id.0);
                         logging::info_u64("ep", ep.0 as u64);
-                        logging::info_u64("rq_len", e.rq_len as u64);
+                        logging::info_u64("rq_len", e.reply_queue.len() as u64);
                     }
 
                     if let Some(pidx) = self.tasks.iter().position(|x| x.id == partner) {
                         if self.tasks[pidx].state == TaskState::Dead {
-                            logging::error("INVARIANT VIOLATION: IpcReply waiter has DEAD partner (reverse check)");
+                            self.invariant_violated(
+                                InvariantId::IpcDeadPartner,
+                                Some(t.id),
+                                Some(partner.0),
+                                "INVARIANT VIOLATION: IpcReply waiter has DEAD partner (reverse check)",
+                            );
                             logging::info_u64("waiter_task_id", t.id.0);
                             logging::info_u64("partner_task_id", partner.0);
                         }
                     }
 
                     if self.is_in_wait_queue(tidx) {
-                        logging::error("INVARIANT VIOLATION: IpcReply task is in wait_queue (reverse check)");
+                        self.invariant_violated(
+                            InvariantId::WaitQueue,
+                            Some(t.id),
+                            None,
+                            "INVARIANT VIOLATION: IpcReply task is in wait_queue (reverse check)",
+                        );
+                        logging::info_u64("task_id", t.id.0);
+                    }
+                }
+
+                // waiter 列との対応は check_notification_invariants で見る
+                BlockedReason::NotifyWait { .. } => {
+                    if self.is_in_wait_queue(tidx) {
+                        self.invariant_violated(
+                            InvariantId::WaitQueue,
+                            Some(t.id),
+                            None,
+                            "INVARIANT VIOLATION: NotifyWait task is in wait_queue (reverse check)",
+                        );
+                        logging::info_u64("task_id", t.id.0);
+                    }
+                }
+
+                // 未解決の fault との対応は check_fault_invariants で見る
+                BlockedReason::FaultWait { .. } => {
+                    if self.is_in_wait_queue(tidx) {
+                        self.invariant_violated(
+                            InvariantId::WaitQueue,
+                            Some(t.id),
+                            None,
+                            "INVARIANT VIOLATION: FaultWait task is in wait_queue (reverse check)",
+                        );
                         logging::info_u64("task_id", t.id.0);
                     }
                 }
             }
         }
-    }
 
-    /// ring3_mailbox_loop 用:
-    /// - ring3 の int80 を「Task1(User) が呼んだ」として扱う運用に合わせて、
-    ///   KernelState 側の current_task/state を最小限で整合させる。
-    ///
-    /// 目的:
-    /// - debug_check_invariants() の `current_task is not RUNNING` を止める
-    /// - tick の runtime/quantum 更新条件が自然に動くようにする
-    pub fn prepare_ring3_loop_current_task(&mut self) {
-        // この module 内の定数をそのまま参照する（super:: は不要/不可）
-        let t0 = TASK0_INDEX;
-        let t1 = TASK1_INDEX;
+        // -------------------------------------------------------------------------
+        // priority inheritance（実効優先度 = base と待ち手からの再計算値）
+        // -------------------------------------------------------------------------
+        self.check_priority_inheritance_invariants();
 
-        if t1 >= self.num_tasks {
+        // -------------------------------------------------------------------------
+        // task table（TaskCreate の slot 再利用後も TaskId は一意）
+        // -------------------------------------------------------------------------
+        self.check_task_table_invariants();
+
+        // -------------------------------------------------------------------------
+        // suspend（Suspended は走らず、止まっている recv_waiter にだけ sender が溜まる）
+        // -------------------------------------------------------------------------
+        self.check_suspend_invariants();
+
+        // -------------------------------------------------------------------------
+        // notification（waiter 列 ⇔ Blocked(NotifyWait)、waiter が居る間は word = 0）
+        // -------------------------------------------------------------------------
+        self.check_notification_invariants();
+
+        // -------------------------------------------------------------------------
+        // fault handler（Blocked(FaultWait{ep}) ⇔ ep 宛の未解決の fault）
+        // -------------------------------------------------------------------------
+        self.check_fault_invariants();
+
+        // -------------------------------------------------------------------------
+        // page grant（記録 ⇔ grantee の window にある同じフレームの mapping）
+        // -------------------------------------------------------------------------
+        self.check_grant_invariants();
+
+        // -------------------------------------------------------------------------
+        // derivation tree（辺 ⇔ 受け取った cap / grant、子の親は 1 つで閉路なし）
+        // -------------------------------------------------------------------------
+        self.check_derivation_invariants();
+
+        // -------------------------------------------------------------------------
+        // cap transfer（複製した cap は意図した task の table にだけ 1 つ）
+        // -------------------------------------------------------------------------
+        self.check_cap_transfer_invariants();
+
+        // -------------------------------------------------------------------------
+        // handle table（handle の rights は種類に合い、生きている object を指す）
+        // -------------------------------------------------------------------------
+        self.check_handle_invariants();
+
+        // -------------------------------------------------------------------------
+        // reply cap（reply object ⇔ Blocked(IpcReply) の client）
+        // -------------------------------------------------------------------------
+        self.check_reply_cap_invariants();
+
+        // -------------------------------------------------------------------------
+        // Sleep の期限（期限 + 1 tick を過ぎて眠っている task が居ない）
+        // -------------------------------------------------------------------------
+        self.check_sleep_deadline_invariants();
+
+        // -------------------------------------------------------------------------
+        // IPC call timeout（期限切れの waiter を endpoint のキューに残さない）
+        // -------------------------------------------------------------------------
+        self.check_ipc_deadline_invariants();
+
+        // -------------------------------------------------------------------------
+        // endpoint キューの FIFO（先に並んだ sender を追い越して deliver していない）
+        // -------------------------------------------------------------------------
+        self.check_ipc_fifo_invariants();
+
+        // -------------------------------------------------------------------------
+        // endpoint の buffer（長さ <= slot 数、closed / Blocked の receiver の前に msg を溜めない）
+        // -------------------------------------------------------------------------
+        self.check_ipc_buffer_invariants();
+
+        // -------------------------------------------------------------------------
+        // IpcRecvAny（ep_mask の全 endpoint に recv_waiter として登録されている）
+        // -------------------------------------------------------------------------
+        self.check_recv_any_invariants();
+
+        // -------------------------------------------------------------------------
+        // endpoint slot（未使用の slot は closed で、どの cap からも指されない）
+        // -------------------------------------------------------------------------
+        self.check_endpoint_slot_invariants();
+
+        // -------------------------------------------------------------------------
+        // endpoint owner（開いている endpoint の owner は生きている）
+        // -------------------------------------------------------------------------
+        self.check_endpoint_owner_invariants();
+
+        // -------------------------------------------------------------------------
+        // guard page（user stack 直下の page は map されていない）
+        // -------------------------------------------------------------------------
+        self.check_guard_page_invariants();
+
+        // -------------------------------------------------------------------------
+        // swap（page は mapped / guard / swapped のどれか 1 つ、slot と SwappedPage が 1 対 1）
+        // -------------------------------------------------------------------------
+        self.check_swap_invariants();
+
+        // -------------------------------------------------------------------------
+        // shared memory（使用中の segment は owner が生きていてフレームを持つ。未使用は空）
+        // -------------------------------------------------------------------------
+        self.check_shm_invariants();
+
+        // -------------------------------------------------------------------------
+        // TLB（遅延 flush は User AS だけ。今の CR3 の AS は遅延 flush を持たない）
+        // -------------------------------------------------------------------------
+        self.check_tlb_invariants();
+
+        // -------------------------------------------------------------------------
+        // Dead task の root は USER slot が空（ページテーブルの leak が無い）
+        // -------------------------------------------------------------------------
+        self.check_dead_root_invariants();
+
+        // -------------------------------------------------------------------------
+        // kernel heap（空きリストは昇順でつながっていて、空き + 使用中 = heap の大きさ）
+        // -------------------------------------------------------------------------
+        check_heap_invariants(self);
+
+        // -------------------------------------------------------------------------
+        // CPU 時間（user + kernel + idle = tick_count）
+        // -------------------------------------------------------------------------
+        self.check_cpu_time_invariants();
+        self.check_idle_invariants();
+
+        // -------------------------------------------------------------------------
+        // 同優先度 round-robin の bounded waiting
+        // - Ready で待つ task が同優先度の dispatch を待つ回数は、自分より前に居た peer の数まで
+        // - FIFO で選ばない policy（sched_chaos）では見ない
+        // -------------------------------------------------------------------------
+        if !<ActivePolicy as SchedPolicy>::BOUNDED_WAITING {
+            return;
+        }
+        for pos in 0..self.rq_len {
+            let idx = self.ready_queue[pos];
+            if idx >= self.num_tasks {
+                continue;
+            }
+            if self.rq_same_prio_passes[idx] >= self.num_tasks as u64 {
+                self.invariant_violated(
+                    InvariantId::SamePriorityFifo,
+                    Some(self.tasks[idx].id),
+                    Some(self.rq_same_prio_passes[idx]),
+                    "INVARIANT VIOLATION: ready task passed by same-priority peers too often",
+                );
+                logging::info_u64("task_id", self.tasks[idx].id.0);
+                logging::info_u64("passes", self.rq_same_prio_passes[idx]);
+            }
+        }
+    }
+
+    /// task ごとの状態の整合（TaskState ⇔ blocked_reason、current_task は Running）
+    /// - O(tasks)。InvariantLevel::Cheap でも毎回見る
+    fn check_task_state_invariants(&self) {
+        // -------------------------------------------------------------------------
+        // TaskState と BlockedReason の整合
+        // -------------------------------------------------------------------------
+        for (idx, t) in self.tasks.iter().enumerate().take(self.num_tasks) {
+            match t.state {
+                TaskState::Blocked => {
+                    if t.blocked_reason.is_none() {
+                        self.invariant_violated(
+                            InvariantId::TaskState,
+                            Some(t.id),
+                            None,
+                            "INVARIANT VIOLATION: BLOCKED task has no blocked_reason",
+                        );
+                        logging::info_u64("task_index", idx as u64);
+                        logging::info_u64("task_id", t.id.0);
+                    }
+                }
+                TaskState::Dead => {
+                    if t.blocked_reason.is_some() {
+                        self.invariant_violated(
+                            InvariantId::TaskState,
+                            Some(t.id),
+                            None,
+                            "INVARIANT VIOLATION: DEAD task has blocked_reason",
+                        );
+                        logging::info_u64("task_index", idx as u64);
+                        logging::info_u64("task_id", t.id.0);
+                    }
+
+                    if t.last_msg.is_some()
+                        || t.last_reply.is_some()
+                        || t.pending_send_msg.is_some()
+                        || t.pending_syscall.is_some()
+                    {
+                        self.invariant_violated(
+                            InvariantId::TaskState,
+                            Some(t.id),
+                            None,
+                            "INVARIANT VIOLATION: DEAD task has leftover task-local state",
+                        );
+                        logging::info_u64("task_index", idx as u64);
+                        logging::info_u64("task_id", t.id.0);
+                    }
+                }
+                // 止める前が Blocked なら reason を持つ。持たない / 持つのどちらでもよい（suspend.rs が残りを見る）
+                TaskState::Suspended => {}
+                _ => {
+                    if t.blocked_reason.is_some() {
+                        self.invariant_violated(
+                            InvariantId::TaskState,
+                            Some(t.id),
+                            None,
+                            "INVARIANT VIOLATION: non-BLOCKED task has blocked_reason",
+                        );
+                        logging::info_u64("task_index", idx as u64);
+                        logging::info_u64("task_id", t.id.0);
+                    }
+                }
+            }
+        }
+
+        // -------------------------------------------------------------------------
+        // current_task の整合（Dead が current になるのは禁止）
+        // -------------------------------------------------------------------------
+        if self.current_task >= self.num_tasks {
+            self.invariant_violated(
+                InvariantId::CurrentTask,
+                None,
+                Some(self.current_task as u64),
+                "INVARIANT VIOLATION: current_task out of range",
+            );
+        } else {
+            let st = self.tasks[self.current_task].state;
+            if st == TaskState::Dead {
+                self.invariant_violated(
+                    InvariantId::CurrentTask,
+                    Some(self.tasks[self.current_task].id),
+                    None,
+                    "INVARIANT VIOLATION: current_task is DEAD",
+                );
+            } else if st != TaskState::Running {
+                self.invariant_violated(
+                    InvariantId::CurrentTask,
+                    Some(self.tasks[self.current_task].id),
+                    None,
+                    "INVARIANT VIOLATION: current_task is not RUNNING",
+                );
+            }
+        }
+    }
+
+    /// ring3_mailbox_loop 用:
+    /// - ring3 の int80 を「Task1(User) が呼んだ」として扱う運用に合わせて、
+    ///   KernelState 側の current_task/state を最小限で整合させる。
+    ///
+    /// 目的:
+    /// - debug_check_invariants() の `current_task is not RUNNING` を止める
+    /// - tick の runtime/quantum 更新条件が自然に動くようにする
+    pub fn prepare_ring3_loop_current_task(&mut self) {
+        // この module 内の定数をそのまま参照する（super:: は不要/不可）
+        let t0 = TASK0_INDEX;
+        let t1 = TASK1_INDEX;
+
+        if t1 >= self.num_tasks {
             logging::error("prepare_ring3_loop_current_task: TASK1_INDEX out of range");
             self.should_halt = true;
             return;
@@ -1020,6 +2306,22 @@ impl KernelState {
                 }
             }
         }
+
+        // virtio_blk: disk の TAR を読む（ring3_tasks は user program を disk から読むので、その前）
+        #[cfg(feature = "virtio_blk")]
+        self.fs_mount();
+
+        #[cfg(feature = "ring3_tasks")]
+        self.setup_ring3_tasks();
+
+        #[cfg(feature = "swap_demo")]
+        self.reserve_swap_region();
+
+        // frame 確保の失敗 mode（demo/fault_inject.rs）。bootstrap の確保は数えない
+        crate::kernel::demo::on_bootstrap_done(self);
+
+        // refinement trace の起点（init の record。refinement_trace でなければ何もしない）
+        self.refinement_init();
     }
 
     fn is_in_ready_queue(&self, idx: usize) -> bool {
@@ -1041,6 +2343,9 @@ impl KernelState {
     }
 
     fn remove_from_ready_queue(&mut self, idx: usize) -> bool {
+        if idx < self.num_tasks {
+            self.rq_same_prio_passes[idx] = 0;
+        }
         if self.rq_len == 0 {
             return false;
         }
@@ -1083,52 +2388,35 @@ impl KernelState {
                 ep.recv_waiter = None;
             }
 
-            let mut pos = 0;
-            while pos < ep.sq_len {
-                if ep.send_queue[pos] == idx {
-                    ep.send_queue[pos] = ep.send_queue[ep.sq_len - 1];
-                    ep.sq_len -= 1;
-                } else {
-                    pos += 1;
-                }
-            }
-
-            let mut pos = 0;
-            while pos < ep.rq_len {
-                if ep.reply_queue[pos] == idx {
-                    ep.reply_queue[pos] = ep.reply_queue[ep.rq_len - 1];
-                    ep.rq_len -= 1;
-                } else {
-                    pos += 1;
-                }
-            }
+            // 外すだけ（残りの並び順は変えない）
+            let _ = ep.send_queue.remove(idx);
+            let _ = ep.reply_queue.remove(idx);
         }
     }
 
+    #[spec("INV-IPC-007")]
     fn resolve_ipc_reply_waiters_for_dead_partner(&mut self, dead_partner: TaskId) {
         let mut wake_list: [Option<usize>; MAX_TASKS] = [None; MAX_TASKS];
         let mut wake_len: usize = 0;
 
         for ep in self.endpoints.iter_mut() {
-            let mut pos: usize = 0;
-            while pos < ep.rq_len {
-                let waiter_idx = ep.reply_queue[pos];
-
-                let should_rescue = waiter_idx < self.num_tasks
-                    && self.tasks[waiter_idx].state == TaskState::Blocked
-                    && matches!(
-                        self.tasks[waiter_idx].blocked_reason,
-                        Some(BlockedReason::IpcReply { partner, ep: wep })
-                            if partner == dead_partner && wep == ep.id
-                    );
+            // 並んだ順に 1 件ずつ外す（残りの並び順は変えない）
+            loop {
+                let ep_id = ep.id;
+                let found = ep.reply_queue.iter().find(|&w| {
+                    w < self.num_tasks
+                        && self.tasks[w].state.can_wait()
+                        && matches!(
+                            self.tasks[w].blocked_reason,
+                            Some(BlockedReason::IpcReply { partner, ep: wep }) if partner == dead_partner && wep == ep_id
+                        )
+                });
 
-                if should_rescue {
-                    let last = ep.rq_len - 1;
-                    ep.reply_queue[pos] = ep.reply_queue[last];
-                    ep.rq_len -= 1;
+                if let Some(waiter_idx) = found {
+                    let _ = ep.reply_queue.remove(waiter_idx);
 
                     self.tasks[waiter_idx].blocked_reason = None;
-                    self.tasks[waiter_idx].last_reply = Some(IPC_ERR_DEAD_PARTNER);
+                    self.tasks[waiter_idx].last_reply = Some(IpcMessage::word(IPC_ERR_DEAD_PARTNER));
 
                     if wake_len < MAX_TASKS {
                         wake_list[wake_len] = Some(waiter_idx);
@@ -1142,7 +2430,7 @@ impl KernelState {
                     continue;
                 }
 
-                pos += 1;
+                break;
             }
         }
 
@@ -1179,7 +2467,7 @@ impl KernelState {
 
         // ---- 重要: USER フラグの mapping を “AddressSpace の全 mapping” から拾う ----
         // for_each_user_mapping_page() が将来壊れても、ここで拾えるようにする
-        let mut pages: [Option<VirtPage>; 64] = [None; 64];
+        let mut pages: [Option<(VirtPage, PhysFrame)>; 64] = [None; 64];
         let mut n: usize = 0;
 
         {
@@ -1189,7 +2477,7 @@ impl KernelState {
                     return;
                 }
                 if n < pages.len() {
-                    pages[n] = Some(m.page);
+                    pages[n] = Some((m.page, m.frame));
                     n += 1;
                 } else {
                     // 64を超えるなら、まずは “検証用途なので” ここで止める（必要なら後で拡張）
@@ -1204,6 +2492,11 @@ impl KernelState {
         {
             let aspace = &mut self.address_spaces[as_idx];
             aspace.clear_user_mappings();
+            aspace.clear_guard_pages();
+        }
+        self.swap_release_all(as_idx);
+        for (_, frame) in pages.iter().take(n).flatten() {
+            self.unref_unmapped_frame(*frame);
         }
 
         let after_clear_count = self.address_spaces[as_idx].mapping_count();
@@ -1214,22 +2507,22 @@ impl KernelState {
 
         for i in 0..n {
             let page = match pages[i] {
-                Some(p) => p,
+                Some((p, _)) => p,
                 None => continue,
             };
 
             let mem_action = MemAction::Unmap { page };
 
-            match unsafe { arch::paging::apply_mem_action_in_root(mem_action, root, &mut self.phys_mem) } {
+            match unsafe { self.apply_in_root(as_idx, root, mem_action) } {
                 Ok(()) => {
                     applied += 1;
 
                     // うるさくなりすぎないように先頭数件だけ translate を確認
                     if i < 4 {
-                        let virt_addr_u64 = arch::paging::USER_SPACE_BASE + page.start_address().0;
+                        let virt_addr_u64 = Arch::USER_SPACE_BASE + page.start_address().0;
                         logging::info("cleanup_user_mappings: debug_translate_after_unmap");
                         logging::info_u64("virt_addr", virt_addr_u64);
-                        arch::paging::debug_translate_in_root(root, virt_addr_u64);
+                        Arch::debug_translate_in_root(root, virt_addr_u64);
                     }
                 }
                 Err(_e) => {
@@ -1243,12 +2536,113 @@ impl KernelState {
 
         logging::info_u64("cleanup_user_mappings: arch_unmap_applied", applied as u64);
 
+        // ---- 参照が無くなったフレームを返す（論理・物理の両方から消えた後）----
+        for (_, frame) in pages.iter().take(n).flatten() {
+            self.release_frame_if_unreferenced(*frame);
+        }
+
         let after_unmap_count = self.address_spaces[as_idx].mapping_count();
         logging::info_u64("cleanup_user_mappings: after unmap mapping_count", after_unmap_count as u64);
 
         logging::info("cleanup_user_mappings: done");
     }
 
+    /// Dead task の root から USER slot の下のページテーブル（L3 / L2 / L1）を外してフレームを返す
+    /// - leaf の unmap（cleanup_user_mappings / shm の解放）の後に呼ぶ
+    /// - root 自体は AddressSpace に残す（slot 再利用の TaskCreate が新しい root に差し替える）
+    fn free_user_page_tables_of_address_space(&mut self, as_idx: usize) {
+        if as_idx >= self.num_tasks || self.address_spaces[as_idx].kind != AddressSpaceKind::User {
+            return;
+        }
+        let Some(root) = self.address_spaces[as_idx].root_page_frame else {
+            return;
+        };
+
+        // USER slot は PML4 の 1 entry（L3 1 枚の下に L2 / L1）。demo の user page は数枚なので十分
+        let mut tables: [Option<PhysFrame>; MAX_USER_PAGE_TABLE_FRAMES] = [None; MAX_USER_PAGE_TABLE_FRAMES];
+        let n = match unsafe { Arch::detach_user_page_tables_in_root(root, &mut tables) } {
+            Ok(n) => n,
+            Err(_e) => {
+                // leaf が残っている（unmap 漏れ）か、バッファ不足。entry は変えていないので leak だけで済む
+                logging::error("free_user_page_tables: detach failed; page tables leaked");
+                logging::info_u64("as_idx", as_idx as u64);
+                return;
+            }
+        };
+
+        // 今の CR3 の root なら、外したテーブルを paging-structure cache に残さない
+        if Arch::current_root() == Some(root) {
+            Arch::flush_tlb_all();
+        }
+
+        for frame in tables.iter().take(n).flatten() {
+            self.release_frame_if_unreferenced(*frame);
+        }
+
+        logging::info("free_user_page_tables: done");
+        logging::info_u64("as_idx", as_idx as u64);
+        logging::info_u64("table_frames_freed", n as u64);
+    }
+
+    /// AddressSpace の mapping region は重ならず、空の region は無い
+    #[spec("INV-MEM-011")]
+    fn check_region_invariants(&self) {
+        for as_idx in 0..self.num_tasks {
+            let aspace = &self.address_spaces[as_idx];
+
+            if let Some((a, b)) = aspace.overlapping_regions() {
+                self.invariant_violated(
+                    InvariantId::Regions,
+                    None,
+                    Some(as_idx as u64),
+                    "INVARIANT VIOLATION: mapping regions overlap",
+                );
+                logging::info_u64("as_idx", as_idx as u64);
+                logging::info_u64("region_a_start_page", a.start_page.number);
+                logging::info_u64("region_a_page_count", a.page_count as u64);
+                logging::info_u64("region_b_start_page", b.start_page.number);
+                logging::info_u64("region_b_page_count", b.page_count as u64);
+            }
+
+            if aspace.has_empty_region() {
+                self.invariant_violated(
+                    InvariantId::Regions,
+                    None,
+                    Some(as_idx as u64),
+                    "INVARIANT VIOLATION: empty mapping region",
+                );
+                logging::info_u64("as_idx", as_idx as u64);
+            }
+        }
+    }
+
+    /// Dead task の root は USER slot が空（ページテーブルまで返し終えている）
+    #[spec("INV-MEM-010")]
+    fn check_dead_root_invariants(&self) {
+        for idx in 0..self.num_tasks {
+            if self.tasks[idx].state != TaskState::Dead {
+                continue;
+            }
+            let as_idx = self.tasks[idx].address_space_id.0;
+            if as_idx >= self.num_tasks || self.address_spaces[as_idx].kind != AddressSpaceKind::User {
+                continue;
+            }
+            let Some(root) = self.address_spaces[as_idx].root_page_frame else {
+                continue;
+            };
+            if !Arch::user_slot_empty_in_root(root) {
+                self.invariant_violated(
+                    InvariantId::DeadRoot,
+                    Some(self.tasks[idx].id),
+                    Some(root.number),
+                    "INVARIANT VIOLATION: dead task root has non-empty USER slot",
+                );
+                logging::info_u64("task_index", idx as u64);
+                logging::info_u64("root_page_frame_index", root.number);
+            }
+        }
+    }
+
     /// demo/ テスト注入から “正規の kill 経路” を使うための入口
     pub(super) fn demo_kill_task(&mut self, idx: usize, reason: TaskKillReason) {
         self.kill_task(idx, reason);
@@ -1269,6 +2663,37 @@ impl KernelState {
                 logging::info("reason = DemoInjected");
                 logging::info_u64("demo_code", code);
             }
+            TaskKillReason::TrapFrameCorrupt { check, value } => {
+                logging::info("reason = TrapFrameCorrupt");
+                logging::info_u64("check", check);
+                logging::info_u64("value", value);
+            }
+            TaskKillReason::FaultStorm { faults, window_ticks } => {
+                logging::info("reason = FaultStorm");
+                logging::info_u64("faults", faults);
+                logging::info_u64("window_ticks", window_ticks);
+            }
+            TaskKillReason::StackOverflow { addr, rip } => {
+                logging::info("reason = StackOverflow");
+                logging::info_u64("addr", addr);
+                logging::info_u64("rip", rip);
+            }
+            TaskKillReason::UserException { vector, err, rip } => {
+                logging::info("reason = UserException");
+                logging::info(crate::arch::interrupts::exception_name(vector as u8));
+                logging::info_u64("vector", vector);
+                logging::info_u64("err", err);
+                logging::info_u64("rip", rip);
+            }
+            TaskKillReason::Shutdown { by } => {
+                logging::info("reason = Shutdown");
+                logging::info_u64("by", by.0);
+            }
+            TaskKillReason::SwapFailed { page, stage } => {
+                logging::info("reason = SwapFailed");
+                logging::info_u64("virt_page_index", page);
+                logging::info_u64("stage", stage);
+            }
         }
     }
 
@@ -1314,7 +2739,7 @@ impl KernelState {
         let kernel_root = self.address_spaces[KERNEL_ASID_INDEX]
             .root_page_frame
             .expect("kernel root_page_frame must exist");
-        arch::paging::switch_address_space_quiet(kernel_root);
+        Arch::switch_address_space_quiet(kernel_root);
         logging::set_vga_enabled(true);
 
         self.dump_events();
@@ -1324,6 +2749,12 @@ impl KernelState {
     }
 
     fn kill_task(&mut self, idx: usize, reason: TaskKillReason) {
+        // idle task は kill しない（ready が空のときに走る task が無くなる。INV-SCHED-006）
+        if idx == IDLE_TASK_INDEX {
+            logging::error("kill_task: idle task cannot be killed; ignore");
+            return;
+        }
+
         // counters を “reason” ベースで一元管理（経路差でズレないようにする）
         match reason {
             TaskKillReason::UserPageFault { .. } => {
@@ -1332,6 +2763,24 @@ impl KernelState {
             TaskKillReason::DemoInjected { .. } => {
                 self.counters.task_killed_demo_injected += 1;
             }
+            TaskKillReason::TrapFrameCorrupt { .. } => {
+                self.counters.task_killed_trap_frame += 1;
+            }
+            TaskKillReason::FaultStorm { .. } => {
+                self.counters.task_killed_fault_storm += 1;
+            }
+            TaskKillReason::StackOverflow { .. } => {
+                self.counters.task_killed_stack_overflow += 1;
+            }
+            TaskKillReason::UserException { .. } => {
+                self.counters.task_killed_user_exception += 1;
+            }
+            TaskKillReason::Shutdown { .. } => {
+                self.counters.task_killed_shutdown += 1;
+            }
+            TaskKillReason::SwapFailed { .. } => {
+                self.counters.task_killed_swap_failed += 1;
+            }
         }
 
         if idx >= self.num_tasks {
@@ -1339,28 +2788,95 @@ impl KernelState {
         }
 
         let dead_id = self.tasks[idx].id;
-        let as_idx = self.tasks[idx].address_space_id.0;
 
         // ★観測性: event_log が流れても必ず残す
         self.log_task_killed(dead_id, reason);
 
+        self.teardown_task(idx);
+
+        self.push_event(LogEvent::TaskKilled { task: dead_id, reason });
+        self.push_event(LogEvent::TaskStateChanged(dead_id, TaskState::Dead));
+
+        if idx == self.current_task {
+            self.schedule_next_task();
+        }
+
+        // ★観測性: ユーザタスク全滅なら dump + halt（1回だけ）
+        self.maybe_halt_if_no_user_tasks();
+    }
+
+    /// task を Dead にして、キュー / endpoint / mapping / 継承を片付ける（kill と TaskExit の共通部分）
+    /// - event（TaskKilled / TaskExited）と schedule は呼び出し側が出す
+    #[spec("INV-KILL-001")]
+    fn teardown_task(&mut self, idx: usize) {
+        let dead_id = self.tasks[idx].id;
+        let as_idx = self.tasks[idx].address_space_id.0;
+
         let _ = self.remove_from_ready_queue(idx);
         let _ = self.remove_from_wait_queue(idx);
         self.remove_task_from_endpoints(idx);
+        self.remove_task_from_notifications(idx);
 
         self.tasks[idx].state = TaskState::Dead;
         self.tasks[idx].blocked_reason = None;
+        self.tasks[idx].wake_at = None;
         self.tasks[idx].pending_syscall = None;
         self.tasks[idx].pending_send_msg = None;
         self.tasks[idx].last_msg = None;
+        self.tasks[idx].last_recv_ep = None;
+        self.tasks[idx].last_reply_cap = None;
         self.tasks[idx].last_reply = None;
+        self.tasks[idx].last_notify = None;
+        self.tasks[idx].last_task_info = None;
+        self.tasks[idx].last_mem_info = None;
         self.tasks[idx].last_syscall_ret = None;
         self.tasks[idx].last_syscall_ret_unread = false;
         self.tasks[idx].time_slice_used = 0;
 
         self.mem_demo_stage[idx] = 0;
         self.mem_demo_mapped[idx] = false;
-        self.mem_demo_frame[idx] = None;
+
+        self.cspaces[idx].clear();
+        // 他の task が持つ dead_id の handle も消す（TaskInfo / TaskSuspend 等で死んだ task を指させない）
+        let _ = self.revoke_object_handles(cspace::KernelObject::Task(dead_id));
+
+        // map されずにキャッシュだけ残っていたフレームを返す（map 中なら cleanup 側で返る）
+        if let Some(f) = self.mem_demo_frame[idx].take() {
+            self.release_frame_if_unreferenced(f);
+        }
+
+        self.reply_wait_since[idx] = None;
+        self.ipc_deadline[idx] = None;
+        self.ipc_handoff_since[idx] = None;
+        self.reply_cap_drop(idx);
+
+        self.pf_window_start[idx] = 0;
+        self.pf_window_count[idx] = 0;
+
+        #[cfg(feature = "timer_service")]
+        self.timer_service.cancel(idx);
+
+        #[cfg(feature = "virtio_net")]
+        self.net.cancel(idx);
+
+        self.fs.close_all(idx);
+
+        // 自分の handler 登録と fault を消し、受け取っていた fault は次の handler へ回す（fault_handler.rs）
+        self.fault_forget_task(idx);
+
+        // 渡した grant / 受け取った grant を外す（mapping の片付けより前。grant.rs）
+        self.grant_forget_task(idx);
+
+        // 自分の cap / page を親や子にもつ派生の辺を忘れる（子は付け替える。derivation.rs）
+        self.derivation_forget_task(idx);
+
+        #[cfg(feature = "kstack_switch")]
+        self.forget_kernel_stack(idx);
+
+        #[cfg(feature = "ring3_tasks")]
+        self.forget_ring3_task(idx);
+
+        self.mlfq.reset(idx);
 
         // ★ベストプラクティス: デモ用状態も kill で一貫して掃除しておく（観測の再現性）
         self.demo_early_sent_by_task0 = false;
@@ -1370,25 +2886,15 @@ impl KernelState {
         // ---------------------------------------------------------------------
         // Step2: owner が死んだ endpoint を close し、waiters を rescue する
         // - close を先に実行して “CLOSED を優先” する（DEAD_PARTNER より優先）
-        // - Rust の借用規則のため、ep_id を先に集めてから close を呼ぶ
+        // - 動的 slot は cap を revoke して未使用に戻す（endpoint_lifecycle.rs）
         // ---------------------------------------------------------------------
-        let mut to_close: [Option<EndpointId>; MAX_ENDPOINTS] = [None; MAX_ENDPOINTS];
-        let mut n: usize = 0;
+        self.close_endpoints_owned_by(dead_id);
 
-        for e in self.endpoints.iter() {
-            if e.owner == Some(dead_id) {
-                if n < MAX_ENDPOINTS {
-                    to_close[n] = Some(e.id);
-                    n += 1;
-                }
-            }
-        }
+        // owner が死んだ shared memory segment は全 AddressSpace から外して解放する（shm.rs）
+        self.destroy_shm_owned_by(dead_id);
 
-        for i in 0..n {
-            if let Some(ep_id) = to_close[i] {
-                self.close_endpoint_and_rescue_waiters(ep_id);
-            }
-        }
+        // leaf が全部外れたので、USER slot の下のページテーブルも返す
+        self.free_user_page_tables_of_address_space(as_idx);
 
         // ---------------------------------------------------------------------
         // 既存: dead partner を待つ reply_waiter を rescue
@@ -1396,15 +2902,8 @@ impl KernelState {
         // ---------------------------------------------------------------------
         self.resolve_ipc_reply_waiters_for_dead_partner(dead_id);
 
-        self.push_event(LogEvent::TaskKilled { task: dead_id, reason });
-        self.push_event(LogEvent::TaskStateChanged(dead_id, TaskState::Dead));
-
-        if idx == self.current_task {
-            self.schedule_next_task();
-        }
-
-        // ★観測性: ユーザタスク全滅なら dump + halt（1回だけ）
-        self.maybe_halt_if_no_user_tasks();
+        // 死んだ task への継承 / 死んだ task からの継承を解く
+        self.refresh_priority_inheritance();
     }
 
     fn enqueue_ready(&mut self, idx: usize) {
@@ -1422,9 +2921,13 @@ impl KernelState {
         self.rq_len += 1;
 
         self.push_event(LogEvent::ReadyQueued(self.tasks[idx].id));
+        ActivePolicy::on_ready(self, idx);
     }
 
-    fn dequeue_ready_highest_priority(&mut self) -> Option<usize> {
+    /// 次に走らせる Ready task を ready_queue から外す（どれを選ぶかは sched_policy.rs の ActivePolicy）
+    /// - 既定（FixedPriority）の priority は実効優先度（IPC 待ちからの継承込み、priority.rs）
+    #[spec("INV-SCHED-004")]
+    fn dequeue_ready_by_policy(&mut self) -> Option<usize> {
         if self.rq_len == 0 {
             return None;
         }
@@ -1450,36 +2953,33 @@ impl KernelState {
             return None;
         }
 
-        // --- 最高優先度を選ぶ ---
-        let mut best_pos: usize = 0;
-        let mut best_idx: usize = self.ready_queue[0];
-
-        if best_idx >= self.num_tasks {
-            // ここに来るのはほぼないが、念のため
-            self.rq_len = 0;
-            return None;
+        // --- policy に選ばせる（掃除済みなので queue の中は全部 num_tasks 未満の Ready）---
+        let mut best_pos = ActivePolicy::pick_next(self);
+        if best_pos >= self.rq_len {
+            logging::error("dequeue_ready: policy picked out of range; use head");
+            best_pos = 0;
         }
+        let best_idx = self.ready_queue[best_pos];
+        let best_prio = self.tasks[best_idx].priority;
 
-        let mut best_prio: u8 = self.tasks[best_idx].priority;
+        // 順序を保って取り除く（swap-remove だと同優先度の FIFO が崩れて先頭の task が飢える）
+        for pos in best_pos..(self.rq_len - 1) {
+            self.ready_queue[pos] = self.ready_queue[pos + 1];
+        }
+        self.rq_len -= 1;
 
-        for pos in 1..self.rq_len {
+        // bounded waiting の観測: 残っている同優先度 task は「同優先度の dispatch を 1 回待った」
+        // - FIFO なら各 task が待つのは自分より前に居た peer の分だけ（< num_tasks）
+        for pos in 0..self.rq_len {
             let idx = self.ready_queue[pos];
-            if idx >= self.num_tasks {
-                continue;
-            }
-
-            let prio = self.tasks[idx].priority;
-            if prio > best_prio {
-                best_prio = prio;
-                best_idx = idx;
-                best_pos = pos;
+            if idx < self.num_tasks && self.tasks[idx].priority == best_prio {
+                self.rq_same_prio_passes[idx] += 1;
+                if self.rq_same_prio_passes[idx] > self.counters.sched_rr_max_passes {
+                    self.counters.sched_rr_max_passes = self.rq_same_prio_passes[idx];
+                }
             }
         }
-
-        // swap-remove
-        let last_pos = self.rq_len - 1;
-        self.ready_queue[best_pos] = self.ready_queue[last_pos];
-        self.rq_len -= 1;
+        self.rq_same_prio_passes[best_idx] = 0;
 
         self.push_event(LogEvent::ReadyDequeued(self.tasks[best_idx].id));
         Some(best_idx)
@@ -1505,6 +3005,8 @@ impl KernelState {
         self.push_event(LogEvent::WaitQueued(self.tasks[idx].id));
     }
 
+    /// current_task を選び直す（CR3 / VGA もここで切り替える）
+    /// - kstack_switch では選んだ task の kernel stack への切替を tick の末尾で行う（kstack.rs）
     fn schedule_next_task(&mut self) {
         let prev_idx = self.current_task;
         let prev_id = self.tasks[prev_idx].id;
@@ -1525,7 +3027,7 @@ impl KernelState {
 
         // -------------------------------------------------------------
         // 1) prev が Running なら Ready に戻す
-        // - Task0(idle) は ready_queue に入れないが、state は Ready に落とす（“二重Running”防止）
+        // - idle task は ready_queue に入れないが、state は Ready に落とす（“二重Running”防止）
         // -------------------------------------------------------------
         if prev_idx < self.num_tasks && self.tasks[prev_idx].state == TaskState::Running {
             self.tasks[prev_idx].state = TaskState::Ready;
@@ -1533,85 +3035,91 @@ impl KernelState {
             self.tasks[prev_idx].time_slice_used = 0;
             self.push_event(LogEvent::TaskStateChanged(prev_id, TaskState::Ready));
 
-            if prev_idx != TASK0_INDEX {
+            if prev_idx != IDLE_TASK_INDEX {
                 self.enqueue_ready(prev_idx);
             }
         }
 
         // -------------------------------------------------------------
-        // 2) ready が無い → Sleep を 1つ起こす → それでも無いなら Idle
+        // 2) ready が無い → idle task（Sleep は期限まで起こさない。起こすのは UpdateTimer だけ）
+        // - Blocked の task には触らない（CPU は IRQ 待ちの hlt で止まり、起床は次の tick の idle_tick で拾う）
         // -------------------------------------------------------------
         if self.rq_len == 0 {
-            if self.wq_len > 0 {
-                logging::info("schedule_next_task: no ready tasks; try wake sleep waiter");
-                self.maybe_wake_one_sleep_task();
-                self.compact_ready_queue_to_ready_only();
-            }
-
-            if self.rq_len == 0 {
-                logging::info("schedule_next_task: still no ready tasks; run idle(task0) and continue");
-                let idle_idx = TASK0_INDEX;
+            logging::Subsystem::Sched.info("schedule_next_task: no ready tasks; run idle task and continue");
+            let idle_idx = IDLE_TASK_INDEX;
 
-                if self.tasks[idle_idx].state == TaskState::Dead {
-                    logging::error("schedule_next_task: idle task is DEAD; halt-safe");
-                    self.should_halt = true;
-                    return;
-                }
+            if self.tasks[idle_idx].state == TaskState::Dead {
+                logging::Subsystem::Sched.error("schedule_next_task: idle task is DEAD; halt-safe");
+                self.should_halt = true;
+                return;
+            }
 
-                // ★最重要：current_task が指すタスクは必ず Running
-                self.tasks[idle_idx].state = TaskState::Running;
-                self.tasks[idle_idx].blocked_reason = None;
-                self.tasks[idle_idx].time_slice_used = 0;
-                self.current_task = idle_idx;
+            // ★最重要：current_task が指すタスクは必ず Running
+            self.tasks[idle_idx].state = TaskState::Running;
+            self.tasks[idle_idx].blocked_reason = None;
+            self.tasks[idle_idx].time_slice_used = 0;
+            self.current_task = idle_idx;
+            if prev_idx != idle_idx {
+                self.counters.idle_entries += 1;
+            }
 
-                let kernel_root = self.address_spaces[KERNEL_ASID_INDEX]
-                    .root_page_frame
-                    .expect("kernel root_page_frame must exist");
-                arch::paging::switch_address_space_quiet(kernel_root);
-                logging::set_vga_enabled(true);
+            let kernel_root = self.address_spaces[KERNEL_ASID_INDEX]
+                .root_page_frame
+                .expect("kernel root_page_frame must exist");
+            Arch::switch_address_space_quiet(kernel_root);
+            logging::set_vga_enabled(true);
 
-                self.push_event(LogEvent::TaskSwitched(self.tasks[idle_idx].id));
-                self.push_event(LogEvent::TaskStateChanged(self.tasks[idle_idx].id, TaskState::Running));
-                return;
-            }
+            self.push_event(LogEvent::TaskSwitched(self.tasks[idle_idx].id));
+            self.push_event(LogEvent::TaskStateChanged(self.tasks[idle_idx].id, TaskState::Running));
+            return;
         }
 
         // -------------------------------------------------------------
         // 3) ready がある前提：選ぶ
         // -------------------------------------------------------------
-        logging::info("sched: dump ready_queue before dequeue");
-        logging::info_u64("rq_len", self.rq_len as u64);
+        logging::Subsystem::Sched.info("sched: dump ready_queue before dequeue");
+        logging::Subsystem::Sched.info_u64("rq_len", self.rq_len as u64);
         for pos in 0..self.rq_len {
             let idx = self.ready_queue[pos];
-            logging::info_u64("rq[pos].task_index", idx as u64);
+            logging::Subsystem::Sched.info_u64("rq[pos].task_index", idx as u64);
             if idx < self.num_tasks {
                 let t = &self.tasks[idx];
-                logging::info_u64("rq[pos].task_id", t.id.0);
+                logging::Subsystem::Sched.info_u64("rq[pos].task_id", t.id.0);
                 match t.state {
-                    TaskState::Ready => logging::info("rq[pos].state = Ready"),
-                    TaskState::Running => logging::info("rq[pos].state = Running"),
-                    TaskState::Blocked => logging::info("rq[pos].state = Blocked"),
-                    TaskState::Dead => logging::info("rq[pos].state = Dead"),
+                    TaskState::Ready => logging::Subsystem::Sched.info("rq[pos].state = Ready"),
+                    TaskState::Running => logging::Subsystem::Sched.info("rq[pos].state = Running"),
+                    TaskState::Blocked => logging::Subsystem::Sched.info("rq[pos].state = Blocked"),
+                    TaskState::Dead => logging::Subsystem::Sched.info("rq[pos].state = Dead"),
+                    TaskState::Suspended => logging::Subsystem::Sched.info("rq[pos].state = Suspended"),
                 }
-                logging::info_u64("rq[pos].prio", t.priority as u64);
+                logging::Subsystem::Sched.info_u64("rq[pos].prio", t.priority as u64);
             }
         }
 
-        let next_idx = match self.dequeue_ready_highest_priority() {
+        let next_idx = match self.dequeue_ready_by_policy() {
             Some(i) => i,
             None => {
-                logging::error("schedule_next_task: ready_queue broken; halt-safe");
+                logging::Subsystem::Sched.error("schedule_next_task: ready_queue broken; halt-safe");
                 self.should_halt = true;
                 return;
             }
         };
 
         if next_idx >= self.num_tasks {
-            logging::error("schedule_next_task: next_idx out of range; halt-safe");
+            logging::Subsystem::Sched.error("schedule_next_task: next_idx out of range; halt-safe");
             self.should_halt = true;
             return;
         }
 
+        // IPC の deliver で起こされた task なら、起こされてから走るまでの tick 数を数える（ipc_handoff.rs）
+        self.ipc_handoff_record_scheduled(next_idx);
+
+        self.dispatch_task(prev_idx, next_idx);
+    }
+
+    /// next_idx を Running にして current_task にする（CR3 / VGA の切替もここ）
+    /// - schedule_next_task と IPC の direct switch（ipc_handoff.rs）の共通部分。next_idx は ready_queue から外してあること
+    fn dispatch_task(&mut self, prev_idx: usize, next_idx: usize) {
         let next_id = self.tasks[next_idx].id;
         let as_idx = self.tasks[next_idx].address_space_id.0;
 
@@ -1627,16 +3135,17 @@ impl KernelState {
         match next_kind {
             AddressSpaceKind::User => {
                 logging::set_vga_enabled(false);
-                arch::paging::switch_address_space(root);
+                Arch::switch_address_space(root);
+                self.flush_deferred_tlb(as_idx);
             }
             AddressSpaceKind::Kernel => {
                 let kernel_root = self.address_spaces[KERNEL_ASID_INDEX]
                     .root_page_frame
                     .expect("kernel root_page_frame must exist");
-                arch::paging::switch_address_space_quiet(kernel_root);
+                Arch::switch_address_space_quiet(kernel_root);
                 logging::set_vga_enabled(true);
-                logging::info("switched to task");
-                logging::info_u64("task_id", next_id.0);
+                logging::Subsystem::Sched.info("switched to task");
+                logging::Subsystem::Sched.info_u64("task_id", next_id.0);
             }
         }
 
@@ -1678,6 +3187,94 @@ impl KernelState {
         self.push_event(LogEvent::RuntimeUpdated(id, self.tasks[ran_idx].runtime_ticks));
     }
 
+    /// idle task が走った tick
+    /// - CPU は tick の外（IRQ 待ちの hlt）で止まっている。tick は起床（UpdateTimer）と新しい Ready を拾うだけ
+    /// - ready が在れば quantum を待たずに明け渡す（idle は ready_queue に戻さない）
+    fn idle_tick(&mut self) {
+        self.update_runtime_for(IDLE_TASK_INDEX);
+        self.account_cpu_tick(IDLE_TASK_INDEX);
+
+        self.compact_ready_queue_to_ready_only();
+        if self.rq_len > 0 {
+            logging::info("idle: ready task present; reschedule");
+            self.schedule_next_task();
+        }
+    }
+
+    /// idle task は kernel AS・最低優先度で、Blocked / Dead にならず、ready_queue にも wait_queue にも居ない
+    #[spec("INV-SCHED-006")]
+    fn check_idle_invariants(&self) {
+        let idle = &self.tasks[IDLE_TASK_INDEX];
+
+        if idle.state != TaskState::Ready && idle.state != TaskState::Running {
+            self.invariant_violated(
+                InvariantId::IdleTask,
+                Some(idle.id),
+                None,
+                "INVARIANT VIOLATION: idle task is not Ready/Running",
+            );
+        }
+        if idle.address_space_id.0 != KERNEL_ASID_INDEX || idle.priority != IDLE_TASK_PRIORITY {
+            self.invariant_violated(
+                InvariantId::IdleTask,
+                Some(idle.id),
+                None,
+                "INVARIANT VIOLATION: idle task is not kernel AS / lowest priority",
+            );
+        }
+        if self.is_in_ready_queue(IDLE_TASK_INDEX) || self.wait_queue[..self.wq_len].contains(&IDLE_TASK_INDEX) {
+            self.invariant_violated(
+                InvariantId::IdleTask,
+                Some(idle.id),
+                None,
+                "INVARIANT VIOLATION: idle task is queued",
+            );
+        }
+        // ready がある間は idle を走らせない（idle_tick / schedule_next_task が明け渡す）
+        if self.current_task == IDLE_TASK_INDEX
+            && self.ready_queue[..self.rq_len].iter().any(|&i| self.tasks[i].state == TaskState::Ready)
+        {
+            self.invariant_violated(
+                InvariantId::IdleTask,
+                Some(idle.id),
+                None,
+                "INVARIANT VIOLATION: idle task runs while a task is Ready",
+            );
+        }
+    }
+
+    /// この tick を CPU 時間の user / kernel / idle のどれか 1 つに入れる（tick_body の出口ごとに 1 回）
+    fn account_cpu_tick(&mut self, ran_idx: usize) {
+        let as_idx = self.tasks[ran_idx].address_space_id.0;
+        if self.syscall_since_account {
+            self.cpu_time.kernel_ticks += 1;
+        } else if ran_idx == IDLE_TASK_INDEX {
+            self.cpu_time.idle_ticks += 1;
+        } else if self.address_spaces[as_idx].kind == AddressSpaceKind::Kernel {
+            self.cpu_time.kernel_ticks += 1;
+        } else {
+            self.cpu_time.user_ticks += 1;
+        }
+        self.syscall_since_account = false;
+    }
+
+    /// CPU 時間の内訳の合計は tick_count
+    #[spec("INV-SCHED-005")]
+    fn check_cpu_time_invariants(&self) {
+        if self.cpu_time.total() != self.tick_count {
+            self.invariant_violated(
+                InvariantId::CpuTime,
+                None,
+                Some(self.cpu_time.total()),
+                "INVARIANT VIOLATION: cpu time buckets do not sum to tick_count",
+            );
+            logging::info_u64("user_ticks", self.cpu_time.user_ticks);
+            logging::info_u64("kernel_ticks", self.cpu_time.kernel_ticks);
+            logging::info_u64("idle_ticks", self.cpu_time.idle_ticks);
+            logging::info_u64("tick_count", self.tick_count);
+        }
+    }
+
     fn block_current(&mut self, reason: BlockedReason) {
         let idx = self.current_task;
         let id = self.tasks[idx].id;
@@ -1698,11 +3295,29 @@ impl KernelState {
                     logging::info_u64("task_id", id.0);
                     logging::info_u64("ep", ep.0 as u64);
 
-                    self.tasks[idx].last_reply = Some(IPC_ERR_DEAD_PARTNER);
+                    self.tasks[idx].last_reply = Some(IpcMessage::word(IPC_ERR_DEAD_PARTNER));
                     self.tasks[idx].pending_send_msg = None;
                     return;
                 }
-                BlockedReason::Sleep => {}
+                BlockedReason::IpcRecvAny { ep_mask } => {
+                    logging::error("block_current: kernel task would block on IPC; convert to error");
+                    logging::info_u64("task_id", id.0);
+                    logging::info_u64("ep_mask", ep_mask);
+
+                    self.tasks[idx].last_reply = Some(IpcMessage::word(IPC_ERR_DEAD_PARTNER));
+                    return;
+                }
+                BlockedReason::NotifyWait { ntfn } => {
+                    logging::error("block_current: kernel task would block on notification; convert to error");
+                    logging::info_u64("task_id", id.0);
+                    logging::info_u64("ntfn", ntfn.0 as u64);
+
+                    self.tasks[idx].last_syscall_ret = Some(abi::SYSCALL_ERR_FORBIDDEN);
+                    self.tasks[idx].last_syscall_ret_unread = true;
+                    return;
+                }
+                // kernel task は handler を登録できない（syscall_fault_handler_set）ので来ない
+                BlockedReason::Sleep | BlockedReason::FaultWait { .. } => {}
             }
         }
 
@@ -1716,6 +3331,10 @@ impl KernelState {
             logging::error("block_task: idx out of range");
             return;
         }
+        if idx == IDLE_TASK_INDEX {
+            logging::error("block_task: idle task never blocks; ignore");
+            return;
+        }
 
         let id = self.tasks[idx].id;
 
@@ -1728,6 +3347,24 @@ impl KernelState {
         // Blocked に落とすなら ready_queue に居てはいけない
         let _ = self.remove_from_ready_queue(idx);
 
+        // endpoint ごとの待ち回数とキューの深さ（endpoint_stats.rs）
+        self.endpoint_stats_on_block(reason);
+
+        // reply obligation の計測開始（IpcReply 以外に変わったら計測対象外）
+        if let BlockedReason::IpcReply { .. } = reason {
+            self.reply_wait_since[idx] = Some(self.tick_count);
+            self.reply_slow_reported[idx] = false;
+        } else {
+            self.reply_wait_since[idx] = None;
+        }
+
+        // Suspended の sender が recv 側に取り出されたとき（IpcSend -> IpcReply）は止めたまま理由だけ替える
+        if self.tasks[idx].state == TaskState::Suspended {
+            self.tasks[idx].blocked_reason = Some(reason);
+            self.refresh_priority_inheritance();
+            return;
+        }
+
         // ★重要: すでに Blocked でも「理由の更新」を許可する（IpcSend -> IpcReply など）
         if self.tasks[idx].state == TaskState::Blocked {
             let prev_reason = self.tasks[idx].blocked_reason;
@@ -1743,6 +3380,7 @@ impl KernelState {
                 (_, BlockedReason::Sleep) => self.enqueue_wait(idx),
                 _ => {}
             }
+            self.refresh_priority_inheritance();
             return;
         }
 
@@ -1752,10 +3390,14 @@ impl KernelState {
         self.tasks[idx].time_slice_used = 0;
 
         self.push_event(LogEvent::TaskStateChanged(id, TaskState::Blocked));
+        ActivePolicy::on_block(self, idx);
 
         if let BlockedReason::Sleep = reason {
             self.enqueue_wait(idx);
         }
+
+        // IpcReply / IpcSend なら待ち相手へ優先度を継承する
+        self.refresh_priority_inheritance();
     }
 
     fn wake_task_to_ready(&mut self, idx: usize) {
@@ -1766,12 +3408,28 @@ impl KernelState {
             return;
         }
 
+        self.reply_wait_since[idx] = None;
+        self.ipc_deadline[idx] = None;
+        self.tasks[idx].wake_at = None;
+        // reply 以外で起きたなら払い出した reply cap はもう使えない（reply なら ipc_reply で消費済み）
+        self.reply_cap_drop(idx);
+
+        // 起こされた時点で call は終わり。reply（ipc_reply で外し済み）以外で起きたなら渡した grant をここで外す
+        self.grant_on_call_ended(idx);
+
         // 既に Ready/Running なら何もしない（重複投入を防ぐ）
         if self.tasks[idx].state == TaskState::Ready || self.tasks[idx].state == TaskState::Running {
             self.tasks[idx].blocked_reason = None;
             return;
         }
 
+        // Suspended は待ちだけ終わらせる（TaskResume まで ready_queue に入れない。suspend.rs）
+        if self.tasks[idx].state == TaskState::Suspended {
+            self.tasks[idx].blocked_reason = None;
+            self.refresh_priority_inheritance();
+            return;
+        }
+
         // Blocked から戻す
         self.tasks[idx].state = TaskState::Ready;
         self.tasks[idx].blocked_reason = None;
@@ -1786,6 +3444,9 @@ impl KernelState {
         }
 
         self.push_event(LogEvent::TaskStateChanged(self.tasks[idx].id, TaskState::Ready));
+
+        // 待ちが解けたので、この task が与えていた継承を戻す
+        self.refresh_priority_inheritance();
     }
 
     fn ready_queue_contains(&self, idx: usize) -> bool {
@@ -1834,7 +3495,7 @@ impl KernelState {
         self.tasks[ran_idx].time_slice_used += 1;
         logging::info_u64("time_slice_used", self.tasks[ran_idx].time_slice_used);
 
-        if self.tasks[ran_idx].time_slice_used >= self.quantum {
+        if ActivePolicy::on_tick(self, ran_idx) {
             logging::info("quantum expired");
             self.push_event(LogEvent::QuantumExpired(id, self.tasks[ran_idx].time_slice_used));
 
@@ -1849,19 +3510,104 @@ impl KernelState {
         }
     }
 
-    fn maybe_wake_one_sleep_task(&mut self) {
+    /// Sleep syscall: 今から ticks 後（kernel clock）まで眠る（0 なら眠らずに SYSCALL_OK）
+    /// - 眠った場合は起床時に SYSCALL_OK を入れる（None を返す）
+    fn syscall_sleep(&mut self, idx: usize, ticks: u64) -> Option<u64> {
+        let as_idx = self.tasks[idx].address_space_id.0;
+        if as_idx < self.num_tasks && self.address_spaces[as_idx].kind == AddressSpaceKind::Kernel {
+            logging::error("sleep: kernel task cannot sleep");
+            return Some(abi::SYSCALL_ERR_FORBIDDEN);
+        }
+        if ticks == 0 {
+            return Some(abi::SYSCALL_OK);
+        }
+        if self.wq_len >= MAX_TASKS {
+            logging::error("sleep: wait_queue full; reject");
+            return Some(abi::SYSCALL_ERR_CAPACITY);
+        }
+
+        let wake_at = self.deadline_after_ticks(ticks);
+        logging::info("sleep: blocked");
+        logging::info_u64("task_id", self.tasks[idx].id.0);
+        logging::info_u64("wake_at_ns", wake_at.as_ns());
+
+        self.block_task(idx, BlockedReason::Sleep);
+        self.tasks[idx].wake_at = Some(wake_at);
+
+        #[cfg(not(feature = "ring3_mailbox"))]
+        self.schedule_next_task();
+
+        None
+    }
+
+    /// 毎 tick 呼ぶ。期限（wake_at <= now）の来た Sleep task を全部起こす（来ていない task は起こさない）
+    fn wake_expired_sleepers(&mut self) {
+        let mut expired = [0usize; MAX_TASKS];
+        let mut n = 0;
         for pos in 0..self.wq_len {
             let idx = self.wait_queue[pos];
-            if idx >= self.num_tasks {
+            if idx >= self.num_tasks || self.tasks[idx].state == TaskState::Dead {
                 continue;
             }
-            if self.tasks[idx].state == TaskState::Dead {
+            if self.tasks[idx].blocked_reason != Some(BlockedReason::Sleep) {
                 continue;
             }
-            if self.tasks[idx].blocked_reason == Some(BlockedReason::Sleep) {
-                logging::info("waking 1 blocked task (Sleep only)");
-                self.wake_task_to_ready(idx);
-                return;
+            if self.tasks[idx].wake_at.is_some_and(|t| t <= self.now()) {
+                expired[n] = idx;
+                n += 1;
+            }
+        }
+
+        for &idx in expired[..n].iter() {
+            logging::info("sleep: deadline reached; wake");
+            logging::info_u64("task_id", self.tasks[idx].id.0);
+            let _ = self.remove_from_wait_queue(idx);
+            self.wake_task_to_ready(idx);
+            self.tasks[idx].last_syscall_ret = Some(abi::SYSCALL_OK);
+            self.tasks[idx].last_syscall_ret_unread = true;
+        }
+    }
+
+    /// Blocked(Sleep) は wake_at を持ち、期限 + 1 tick を過ぎて眠っていない。Sleep 以外は wake_at を持たない
+    #[spec("INV-WAIT-002")]
+    fn check_sleep_deadline_invariants(&self) {
+        for t in self.tasks.iter().take(self.num_tasks) {
+            if t.state == TaskState::Dead {
+                continue;
+            }
+            let sleeping = t.state.can_wait() && t.blocked_reason == Some(BlockedReason::Sleep);
+            match (sleeping, t.wake_at) {
+                (true, None) => {
+                    self.invariant_violated(
+                        InvariantId::SleepDeadline,
+                        Some(t.id),
+                        None,
+                        "INVARIANT VIOLATION: Sleep BLOCKED task has no wake_at",
+                    );
+                    logging::info_u64("task_id", t.id.0);
+                }
+                (true, Some(wake_at)) if self.now() > wake_at.after_ticks(1) => {
+                    self.invariant_violated(
+                        InvariantId::SleepDeadline,
+                        Some(t.id),
+                        Some(wake_at.as_ns()),
+                        "INVARIANT VIOLATION: task sleeps past its deadline",
+                    );
+                    logging::info_u64("task_id", t.id.0);
+                    logging::info_u64("wake_at_ns", wake_at.as_ns());
+                    logging::info_u64("now_ns", self.now().as_ns());
+                }
+                (false, Some(wake_at)) => {
+                    self.invariant_violated(
+                        InvariantId::SleepDeadline,
+                        Some(t.id),
+                        Some(wake_at.as_ns()),
+                        "INVARIANT VIOLATION: non-sleeping task has wake_at",
+                    );
+                    logging::info_u64("task_id", t.id.0);
+                    logging::info_u64("wake_at_ns", wake_at.as_ns());
+                }
+                _ => {}
             }
         }
     }
@@ -1887,6 +3633,143 @@ impl KernelState {
         }
     }
 
+    /// 論理 mapping の変更（AddressSpace::apply）。region が足りなければログを出して数える（panic しない）
+    /// - エラーはそのまま返す。syscall なら SYSCALL_ERR_CAPACITY などにするのは呼び出し側
+    fn aspace_apply(&mut self, as_idx: usize, action: MemAction) -> Result<(), AddressSpaceError> {
+        let res = self.address_spaces[as_idx].apply(action);
+        if let Err(AddressSpaceError::CapacityExceeded) = res {
+            logging::error("address_space: out of region slots; mapping rejected");
+            logging::info_u64("as_idx", as_idx as u64);
+            logging::info_u64("virt_page_index", action.page().number);
+            logging::info_u64("region_count", self.address_spaces[as_idx].region_count() as u64);
+            self.counters.mappings_rejected += 1;
+        }
+        res
+    }
+
+    /// aspace_apply の batch 版（AddressSpace::apply_mem_actions。all-or-nothing で、落ちた entry の index を返す）
+    fn aspace_apply_batch(&mut self, as_idx: usize, actions: &[MemAction]) -> Result<(), BatchError<AddressSpaceError>> {
+        let res = self.address_spaces[as_idx].apply_mem_actions(actions);
+        if let Err(BatchError { index, error: AddressSpaceError::CapacityExceeded }) = res {
+            logging::error("address_space: out of region slots; batch rejected");
+            logging::info_u64("as_idx", as_idx as u64);
+            logging::info_u64("batch_index", index as u64);
+            logging::info_u64("virt_page_index", actions[index].page().number);
+            logging::info_u64("region_count", self.address_spaces[as_idx].region_count() as u64);
+            self.counters.mappings_rejected += 1;
+        }
+        res
+    }
+
+    /// frame を map している mapping の数（全 AddressSpace 合計）
+    fn mapping_count_of_frame(&self, frame: PhysFrame) -> usize {
+        self.address_spaces[..self.num_tasks]
+            .iter()
+            .map(|a| a.frame_mapping_count(frame))
+            .sum()
+    }
+
+    /// 論理 mapping（AddressSpace::apply の Map）が成功したフレームの参照数を増やす
+    fn ref_mapped_frame(&mut self, frame: PhysFrame) {
+        match self.phys_mem.frame_ref(to_arch_frame(frame)) {
+            Ok(count) => {
+                if count > 1 {
+                    logging::info("frame_ref: shared frame");
+                    logging::info_u64("phys_frame_index", frame.number);
+                    logging::info_u64("frame_refcount", count as u64);
+                }
+            }
+            Err(FrameRefError::NotAllocated) => {
+                self.invariant_violated(
+                    InvariantId::FrameAllocated,
+                    None,
+                    Some(frame.number),
+                    "INVARIANT VIOLATION: mapping a frame that is not allocated",
+                );
+                logging::info_u64("phys_frame_index", frame.number);
+            }
+            Err(FrameRefError::TableFull) => {
+                self.invariant_violated(
+                    InvariantId::FrameRefcount,
+                    None,
+                    Some(frame.number),
+                    "INVARIANT VIOLATION: frame refcount table full",
+                );
+                logging::info_u64("phys_frame_index", frame.number);
+            }
+            Err(FrameRefError::NotReferenced) => {}
+        }
+    }
+
+    /// 論理 mapping（AddressSpace::apply の Unmap / clear_user_mappings）で外れたフレームの参照数を減らす
+    /// - 0 になっても返さない（arch の unmap が済んでから release_frame_if_unreferenced で返す）
+    fn unref_unmapped_frame(&mut self, frame: PhysFrame) {
+        if self.phys_mem.frame_unref(to_arch_frame(frame)).is_err() {
+            self.invariant_violated(
+                InvariantId::FrameRefcount,
+                None,
+                Some(frame.number),
+                "INVARIANT VIOLATION: frame refcount underflow",
+            );
+            logging::info_u64("phys_frame_index", frame.number);
+        }
+    }
+
+    /// Unmap / kill で外したフレームを PhysicalMemoryManager に返す
+    /// - 参照カウントが 0 で、どの AddressSpace の root でもなければ返す（共有中なら保持）
+    /// - shared memory segment のフレームは segment が解放するまで保持する（shm.rs）
+    /// - mem_demo のフレームキャッシュからも外す（解放済みフレームを再 Map しない）
+    #[spec("INV-MEM-003")]
+    fn release_frame_if_unreferenced(&mut self, frame: PhysFrame) {
+        let referenced = self.phys_mem.frame_ref_count(to_arch_frame(frame)) != 0
+            || self.address_spaces.iter().any(|a| a.root_page_frame == Some(frame))
+            || self.is_shm_frame(frame);
+        if referenced {
+            logging::info("release_frame: still referenced; keep");
+            logging::info_u64("phys_frame_index", frame.number);
+            return;
+        }
+
+        for cached in self.mem_demo_frame.iter_mut() {
+            if *cached == Some(frame) {
+                *cached = None;
+            }
+        }
+
+        match self.phys_mem.deallocate_frame(to_arch_frame(frame)) {
+            Ok(()) => {
+                self.push_event(LogEvent::FrameFreed { frame: frame.number });
+            }
+            Err(FrameDeallocError::DoubleFree) => {
+                self.invariant_violated(
+                    InvariantId::FrameRelease,
+                    None,
+                    Some(frame.number),
+                    "INVARIANT VIOLATION: frame freed twice",
+                );
+                logging::info_u64("phys_frame_index", frame.number);
+            }
+            Err(FrameDeallocError::StillReferenced) => {
+                self.invariant_violated(
+                    InvariantId::FrameRelease,
+                    None,
+                    Some(frame.number),
+                    "INVARIANT VIOLATION: freeing a frame that is still mapped",
+                );
+                logging::info_u64("phys_frame_index", frame.number);
+            }
+            Err(FrameDeallocError::NotUsable) => {
+                self.invariant_violated(
+                    InvariantId::FrameRelease,
+                    None,
+                    Some(frame.number),
+                    "INVARIANT VIOLATION: freeing a frame not owned by the allocator",
+                );
+                logging::info_u64("phys_frame_index", frame.number);
+            }
+        }
+    }
+
     fn demo_page_for_task(&self, task_idx: usize) -> VirtPage {
         let idx = match task_idx {
             TASK0_INDEX => DEMO_VIRT_PAGE_INDEX_TASK0,
@@ -1907,7 +3790,65 @@ impl KernelState {
         self.do_mem_demo_normal();
     }
 
-    fn kill_current_task_due_to_user_pf(&mut self, pf: arch::paging::PageFaultInfo) {
+    /// user #PF を 1 回数え、窓内の回数が閾値を超えたら Some(回数) を返す。
+    /// - 窓は task ごとの固定窓（最初の #PF の tick から PF_STORM_WINDOW_TICKS）
+    fn note_user_pf_and_check_storm(&mut self, idx: usize) -> Option<u64> {
+        self.counters.user_pf_total += 1;
+
+        if idx >= self.num_tasks {
+            return None;
+        }
+
+        let now = self.tick_count;
+        if self.pf_window_count[idx] == 0 || now.saturating_sub(self.pf_window_start[idx]) >= PF_STORM_WINDOW_TICKS {
+            self.pf_window_start[idx] = now;
+            self.pf_window_count[idx] = 0;
+        }
+        self.pf_window_count[idx] += 1;
+
+        if self.pf_window_count[idx] > PF_STORM_THRESHOLD {
+            Some(self.pf_window_count[idx])
+        } else {
+            None
+        }
+    }
+
+    /// fault アドレスが task の AddressSpace の guard page に当たっていればその page
+    fn guard_page_hit(&self, idx: usize, addr: u64) -> Option<VirtPage> {
+        let as_idx = self.tasks[idx].address_space_id.0;
+        if as_idx >= self.num_tasks || self.address_spaces[as_idx].kind != AddressSpaceKind::User {
+            return None;
+        }
+        let offset = addr.checked_sub(Arch::USER_SPACE_BASE)?;
+        if offset >= Arch::USER_SPACE_SIZE {
+            return None;
+        }
+        let page = VirtPage::from_index(offset / PAGE_SIZE);
+        self.address_spaces[as_idx].is_guard_page(page).then_some(page)
+    }
+
+    /// guard page は map されていない（論理 AddressSpace 上）
+    #[spec("INV-MEM-006")]
+    fn check_guard_page_invariants(&self) {
+        for as_idx in FIRST_USER_ASID_INDEX..self.num_tasks {
+            let aspace = &self.address_spaces[as_idx];
+            aspace.for_each_guard_page(|page| {
+                if aspace.lookup(page).is_some() {
+                    self.invariant_violated(
+                        InvariantId::GuardPage,
+                        None,
+                        Some(page.number),
+                        "INVARIANT VIOLATION: guard page is mapped",
+                    );
+                    logging::info_u64("as_idx", as_idx as u64);
+                    logging::info_u64("page", page.start_address().0);
+                }
+            });
+        }
+    }
+
+    #[spec("INV-PF-001")]
+    fn kill_current_task_due_to_user_pf(&mut self, pf: PageFaultInfo) {
         let idx = self.current_task;
         let task_id = self.tasks[idx].id;
 
@@ -1939,6 +3880,57 @@ impl KernelState {
         logging::info_u64("addr", pf.addr);
         logging::info_u64("err", pf.err);
         logging::info_u64("rip", pf.rip);
+        // 同じ内容を 1 行でも（アドレスは 16 進。ツール向け）
+        crate::log_record!(Info, "pf", task = dec(task_id.0), addr = hex(pf.addr), err = hex(pf.err), rip = hex(pf.rip));
+
+        // ------------------------------------------------------------
+        // SwappedOut の page: swap in して kill しない（task の誤りではない。storm にも数えない）
+        // ------------------------------------------------------------
+        match self.swap_in_on_fault(idx, pf.addr) {
+            Ok(true) => {
+                logging::info("USER PAGE FAULT: swapped-out page restored");
+                logging::info_u64("task_id", task_id.0);
+                return;
+            }
+            Ok(false) => {}
+            // swap in に失敗: swap.rs が SwapFailed で kill 済み
+            Err(_) => return,
+        }
+
+        // ------------------------------------------------------------
+        // guard page（user stack の直下）: stack overflow として区別して kill（storm / ignore より優先）
+        // ------------------------------------------------------------
+        if let Some(guard) = self.guard_page_hit(idx, pf.addr) {
+            logging::error("USER STACK OVERFLOW (guard page hit) => kill current task");
+            logging::info_u64("task_id", task_id.0);
+            logging::info_u64("guard_page", guard.start_address().0);
+            self.kill_task(idx, TaskKillReason::StackOverflow { addr: pf.addr, rip: pf.rip });
+            return;
+        }
+
+        // ------------------------------------------------------------
+        // レート制限: 窓内の #PF が多すぎる task は継続させない（ignore より優先）
+        // ------------------------------------------------------------
+        if let Some(faults) = self.note_user_pf_and_check_storm(idx) {
+            logging::error("USER PAGE FAULT STORM => kill current task");
+            logging::info_u64("task_id", task_id.0);
+            logging::info_u64("faults", faults);
+            self.kill_task(
+                idx,
+                TaskKillReason::FaultStorm {
+                    faults,
+                    window_ticks: PF_STORM_WINDOW_TICKS,
+                },
+            );
+            return;
+        }
+
+        // ------------------------------------------------------------
+        // fault handler が登録されていれば IPC で委譲する（FaultResolve まで Blocked(FaultWait)。fault_handler.rs）
+        // ------------------------------------------------------------
+        if self.delegate_user_fault(idx, pf) {
+            return;
+        }
 
         // ------------------------------------------------------------
         // 例外: デモ継続のために #PF を無視したい場合だけ “明示的に” 使う
@@ -2007,7 +3999,7 @@ impl KernelState {
                 }
             };
 
-            let virt_addr_u64 = arch::paging::USER_SPACE_BASE + page.start_address().0;
+            let virt_addr_u64 = Arch::USER_SPACE_BASE + page.start_address().0;
 
             let stage = self.mem_demo_stage[task_idx];
 
@@ -2037,7 +4029,7 @@ impl KernelState {
                         .root_page_frame
                         .expect("kernel root_page_frame must exist");
 
-                    let rw_result = arch::paging::guarded_user_rw_u64_in_root(
+                    let rw_result = Arch::guarded_user_rw_u64_in_root(
                         root,
                         kernel_root,
                         user_virt,
@@ -2067,7 +4059,7 @@ impl KernelState {
                     }
 
                     // 参考：translate（任意）
-                    arch::paging::debug_translate_in_root(root, virt_addr_u64);
+                    Arch::debug_translate_in_root(root, virt_addr_u64);
 
                     self.mem_demo_stage[task_idx] = 2;
                     return;
@@ -2118,7 +4110,7 @@ impl KernelState {
                             .root_page_frame
                             .expect("kernel root_page_frame must exist");
 
-                        let rw_result = arch::paging::guarded_user_rw_u64_in_root(
+                        let rw_result = Arch::guarded_user_rw_u64_in_root(
                             root,
                             kernel_root,
                             user_virt,
@@ -2159,6 +4151,13 @@ impl KernelState {
         // -------------------------------------------------------------------------
         // Kernel task mem demo: Map <-> Unmap を繰り返すだけ（従来通り）
         // -------------------------------------------------------------------------
+        // Unmap で外れるフレーム（arch 側の unmap が済んだら返す）
+        let unmapped_frame = if self.mem_demo_mapped[task_idx] {
+            self.address_spaces[as_idx].lookup(page).map(|m| m.frame)
+        } else {
+            None
+        };
+
         let mem_action = if !self.mem_demo_mapped[task_idx] {
             logging::info("mem_demo: issuing Map (for current task)");
 
@@ -2177,14 +4176,26 @@ impl KernelState {
             MemAction::Unmap { page }
         };
 
-        let apply_res = {
-            let aspace = &mut self.address_spaces[as_idx];
-            aspace.apply(mem_action)
-        };
+        let apply_res = self.aspace_apply(as_idx, mem_action);
 
         match apply_res {
             Ok(()) => {
                 logging::info("address_space.apply: OK");
+                match mem_action {
+                    MemAction::Map { frame, .. } => self.ref_mapped_frame(frame),
+                    MemAction::Unmap { .. } => {
+                        if let Some(f) = unmapped_frame {
+                            self.unref_unmapped_frame(f);
+                        }
+                    }
+                    // mem_demo は Protect を出さない（フレームの参照は変わらない）
+                    MemAction::Protect { .. } => {}
+                }
+            }
+            Err(AddressSpaceError::CapacityExceeded) => {
+                // 容量不足は拒否として扱う（demo frame は持ったまま、次の周期でやり直す）
+                logging::error("mem_demo: mapping rejected; skip this round");
+                return;
             }
             Err(e) => {
                 logging::error("address_space.apply: ERROR");
@@ -2192,13 +4203,15 @@ impl KernelState {
                     AddressSpaceError::AlreadyMapped => logging::info("reason = AlreadyMapped"),
                     AddressSpaceError::NotMapped => logging::info("reason = NotMapped"),
                     AddressSpaceError::CapacityExceeded => logging::info("reason = CapacityExceeded"),
+                    AddressSpaceError::GuardPage => logging::info("reason = GuardPage"),
+                    AddressSpaceError::BadFlags => logging::info("reason = BadFlags"),
                 }
                 panic!("address_space.apply failed; abort (fail-stop)");
             }
         }
 
         logging::info("mem_demo: applying arch paging (Task0 / current CR3)");
-        match unsafe { arch::paging::apply_mem_action(mem_action, &mut self.phys_mem) } {
+        match unsafe { Arch::apply_mem_action(mem_action, &mut self.phys_mem) } {
             Ok(()) => {}
             Err(_e) => {
                 logging::error("arch::paging::apply_mem_action failed; abort (fail-stop)");
@@ -2213,17 +4226,53 @@ impl KernelState {
             address_space: task.address_space_id,
             action: mem_action,
         });
+
+        if let Some(f) = unmapped_frame {
+            self.release_frame_if_unreferenced(f);
+        }
     }
 
     pub fn tick(&mut self) {
+        // debug_console: COM1 に届いたコマンドを先に処理する（console.rs）
+        #[cfg(feature = "debug_console")]
+        self.console_poll();
+
+        let ran = self.tasks[self.current_task].id;
+        self.tick_body();
+
+        // この tick の invariant 違反を InvariantViolated にする（invariant_fail_stop ならここで halt）
+        self.commit_invariant_violations();
+
+        // IPC の待ちが輪になっていないか（deadlock.rs）/ 進捗の無い tick が続いていないか（watchdog.rs）
+        self.detect_ipc_deadlocks();
+        self.watchdog_on_tick();
+
+        // この tick で kernel がした分の遷移（refinement.rs。変わっていなければ出さない）
+        self.refinement_boundary("tick", Some(ran), false);
+
+        // 抽象状態の hash（形式モデルとの突き合わせ用。state_hash.rs）
+        if !self.should_halt {
+            self.emit_state_hash();
+        }
+
+        // checkpoint / rollback で branch を試す（demo/checkpoint_explore.rs。checkpoint_demo でなければ何もしない）
+        crate::kernel::demo::on_tick_end(self);
+
+        // kstack_switch: schedule_next_task が選んだ task の stack へ、tick の末尾でだけ切り替える
+        #[cfg(feature = "kstack_switch")]
+        self.switch_kernel_stack_to_current();
+    }
+
+    fn tick_body(&mut self) {
         if self.should_halt {
             return;
         }
 
         self.tick_count += 1;
+        self.sample_clock();
 
-        logging::info("KernelState::tick()");
-        logging::info_u64("tick_count", self.tick_count);
+        logging::Subsystem::Sched.info("KernelState::tick()");
+        logging::Subsystem::Sched.info_u64("tick_count", self.tick_count);
 
         if (self.tick_count % 50) == 0 {
             logging::info("heartbeat");
@@ -2248,7 +4297,12 @@ impl KernelState {
                 self.time_ticks += 1;
                 logging::info_u64("time_ticks", self.time_ticks);
                 self.push_event(LogEvent::TimerUpdated(self.time_ticks));
-                self.maybe_wake_one_sleep_task();
+
+                #[cfg(feature = "timer_service")]
+                self.timer_service_on_tick();
+
+                #[cfg(feature = "virtio_net")]
+                self.net_poll();
             }
             KernelAction::AllocateFrame => {
                 logging::info("action = AllocateFrame");
@@ -2263,21 +4317,54 @@ impl KernelState {
             KernelAction::MemDemo => {
                 logging::info("action = MemDemo");
 
-                // ring3_mailbox_loop: IPC の loop 検証が主目的なので mem_demo は止める
-                #[cfg(feature = "ring3_mailbox_loop")]
-                {
-                    logging::info("mem_demo skipped (ring3_mailbox_loop)");
-                }
+                // idle task は何もしない（mem_demo も syscall も積まない）
+                if self.current_task == IDLE_TASK_INDEX {
+                    logging::info("mem_demo skipped (idle task)");
+                } else {
+                    // ring3_mailbox_loop: IPC の loop 検証が主目的なので mem_demo は止める
+                    #[cfg(feature = "ring3_mailbox_loop")]
+                    {
+                        logging::info("mem_demo skipped (ring3_mailbox_loop)");
+                    }
+
+                    #[cfg(all(not(feature = "ring3_mailbox_loop"), feature = "ring3_tasks"))]
+                    {
+                        // ring3_tasks: ring3 で走る task には syscall を積まない（int80 の待ちと混ざる）
+                        if self.is_ring3_task(self.current_task) {
+                            logging::info("mem_demo skipped (ring3 task)");
+                        } else {
+                            self.do_mem_demo();
+                        }
+                    }
 
-                #[cfg(not(feature = "ring3_mailbox_loop"))]
-                {
-                    self.do_mem_demo();
+                    #[cfg(all(not(feature = "ring3_mailbox_loop"), not(feature = "ring3_tasks")))]
+                    {
+                        self.do_mem_demo();
+                    }
                 }
             }
         }
 
+        // swap_demo: 周期的に current でない ring3 task の page を追い出す（次に走ったときの #PF で戻る）
+        #[cfg(feature = "swap_demo")]
+        self.swap_demo_on_tick();
+
+        // fault injection の台本（demo/fault_inject.rs。台本が空なら何もしない）
+        crate::kernel::demo::on_tick(self);
+
+        // Sleep の期限と IpcSend の timeout 切れ（どちらも kernel clock。time.rs）
+        self.wake_expired_sleepers();
+        self.expire_ipc_deadlines();
+
+        // reply obligation の超過検出（server 側の責任として記録する）
+        self.check_reply_obligations();
+
+        // endpoint ごとの待ち tick 数（endpoint_stats.rs）
+        self.endpoint_stats_on_tick();
+
         if ran_idx < self.num_tasks && self.tasks[ran_idx].state == TaskState::Dead {
             logging::info("tick: running task died in this tick; skip syscall/runtime/quantum updates");
+            self.account_cpu_tick(ran_idx);
             self.activity = next_activity;
 
             // ★保険：tick 終了時に current_task が RUNNING でなければスケジュールして整合を回復
@@ -2287,7 +4374,16 @@ impl KernelState {
                 self.schedule_next_task();
             }
 
-            self.debug_check_invariants();
+            self.run_invariant_checks();
+            return;
+        }
+
+        // idle task: syscall / quantum / sleep の判定は無い。ready が在れば明け渡す
+        if ran_idx == IDLE_TASK_INDEX {
+            self.idle_tick();
+            self.activity = next_activity;
+            self.maybe_halt_if_no_user_tasks();
+            self.run_invariant_checks();
             return;
         }
 
@@ -2304,7 +4400,15 @@ impl KernelState {
             }
         }
 
-        #[cfg(not(feature = "ring3_mailbox_loop"))]
+        // ring3_tasks: ring3 で走る task の syscall は int80 が積む（user_program は使わない）
+        #[cfg(all(not(feature = "ring3_mailbox_loop"), feature = "ring3_tasks"))]
+        {
+            if !self.is_ring3_task(ran_idx) {
+                self.user_step_issue_syscall(ran_idx);
+            }
+        }
+
+        #[cfg(all(not(feature = "ring3_mailbox_loop"), not(feature = "ring3_tasks")))]
         {
             self.user_step_issue_syscall(ran_idx);
         }
@@ -2314,6 +4418,7 @@ impl KernelState {
         }
 
         self.update_runtime_for(ran_idx);
+        self.account_cpu_tick(ran_idx);
 
         let still_running = ran_idx == self.current_task
             && self.tasks[ran_idx].state == TaskState::Running;
@@ -2334,7 +4439,7 @@ impl KernelState {
 
         self.activity = next_activity;
         self.maybe_halt_if_no_user_tasks();
-        self.debug_check_invariants();
+        self.run_invariant_checks();
     }
 
     pub fn should_halt(&self) -> bool {
@@ -2342,15 +4447,205 @@ impl KernelState {
     }
 
     pub fn dump_events(&self) {
+        self.dump_events_as(DEFAULT_DUMP_FORMAT);
+    }
+
+    /// 形式を指定して dump する（trace_wire の Wire Dump はどちらの形式でも最後に付ける）
+    pub fn dump_events_as(&self, format: DumpFormat) {
+        match format {
+            DumpFormat::Text => self.dump_text(),
+            DumpFormat::Records => self.dump_records(),
+        }
+
+        #[cfg(feature = "trace_wire")]
+        self.dump_wire();
+    }
+
+    fn dump_text(&self) {
         logging::info("=== KernelState Event Log Dump ===");
-        for i in 0..self.event_log_len {
-            let idx = (self.event_log_head + i) % EVENT_LOG_CAP;
-            if let Some(ev) = self.event_log[idx] {
-                log_event_to_vga(ev);
+        // 解析側が補正できるように、サンプリング条件を先頭に出す
+        logging::info_u64("ipc_event_sample_every", IPC_EVENT_SAMPLE_EVERY);
+        logging::info_u64("ipc_events_skipped", self.counters.ipc_events_skipped);
+        // リングで上書きした分（0 でなければ trace は先頭が欠けている）と、その前に退避した重要 event の数
+        logging::info_u64("events_overwritten", self.counters.events_overwritten);
+        logging::info_u64("retained_events", self.retained_events.len() as u64);
+        // NMI / #MC（arch::hwfault の latch）。立っていれば、この後の異常はハードウェア由来を疑う
+        dump_hw_event_text();
+        self.for_each_logged_event(|seq, ev| {
+            // 重要 event は log_dedup でもまとめない
+            if event_log::is_critical_event(ev) {
+                logging::without_dedup(|| log_event_to_vga(*ev));
+            } else {
+                log_event_to_vga(*ev);
             }
-        }
+            logging::info_u64("seq", seq);
+        });
         logging::info("=== End of Event Log ===");
 
+        #[cfg(feature = "kstack_switch")]
+        logging::info_u64("kstack_switches", self.kstacks.switches());
+
+        self.dump_task_table();
+
+        logging::info("=== AddressSpace Dump (per task) ===");
+        for i in 0..self.num_tasks {
+            let task = self.tasks[i];
+
+            logging::info("Task AddressSpace:");
+            logging::info_u64("task_index", i as u64);
+            logging::info_u64("task_id", task.id.0);
+
+            let as_idx = task.address_space_id.0;
+            let aspace = &self.address_spaces[as_idx];
+
+            match aspace.kind {
+                AddressSpaceKind::Kernel => logging::info("kind = Kernel"),
+                AddressSpaceKind::User => logging::info("kind = User"),
+            }
+
+            match aspace.root_page_frame {
+                Some(root) => logging::info_u64("root_page_frame_index", root.number),
+                None => logging::info("root_page_frame_index = None"),
+            }
+
+            logging::info_u64("address_space_id", as_idx as u64);
+
+            let count = aspace.mapping_count();
+            logging::info_u64("mapping_count", count as u64);
+            logging::info_u64("frames_in_use", aspace.frames_in_use() as u64);
+            logging::info_u64("frame_quota", aspace.frame_quota() as u64);
+            logging::info_u64("region_count", aspace.region_count() as u64);
+            logging::info_u64("region_chunks", aspace.region_chunks() as u64);
+
+            aspace.for_each_region(|r| {
+                logging::info("REGION:");
+                logging::info_u64("start_page_index", r.start_page.number);
+                logging::info_u64("page_count", r.page_count as u64);
+                logging::info_u64("first_frame_index", r.first_frame.number);
+                logging::info_u64("flags_bits", r.flags.bits());
+            });
+
+            aspace.for_each_mapping(|m| {
+                logging::info("MAPPING:");
+                logging::info_u64("virt_page_index", m.page.number);
+                logging::info_u64("phys_frame_index", m.frame.number);
+                logging::info_u64("flags_bits", m.flags.bits());
+            });
+
+            if let Some(m) = task.last_msg {
+                logging::info("IPC:");
+                logging::info_u64("last_msg", m.mr0());
+            }
+        }
+        logging::info("=== End of AddressSpace Dump ===");
+
+        self.dump_memory_usage();
+
+        logging::info("=== Endpoint Dump ===");
+        for ep in self.endpoints.iter() {
+            logging::info("ENDPOINT:");
+            logging::info_u64("ep_id", ep.id.0 as u64);
+            if !ep.allocated {
+                logging::info("endpoint slot unallocated");
+                continue;
+            }
+            if let Some(owner) = ep.owner {
+                logging::info_u64("owner_task_id", owner.0);
+            }
+
+            match ep.recv_waiter {
+                Some(tidx) => {
+                    logging::info_u64("recv_waiter_task_index", tidx as u64);
+                    if tidx < self.num_tasks {
+                        logging::info_u64("recv_waiter_task_id", self.tasks[tidx].id.0);
+                    }
+                }
+                None => logging::info("recv_waiter_task_index = None"),
+            }
+
+            // 並んだ順（pos 0 = 次に deliver される sender）と、並んだときの enqueue seq（task_queue.rs）
+            logging::info_u64("send_queue_len", ep.send_queue.len() as u64);
+            for (pos, (tidx, seq)) in ep.send_queue.entries().enumerate() {
+                logging::info_u64("send_queue_pos", pos as u64);
+                logging::info_u64("send_queue_task_index", tidx as u64);
+                if tidx < self.num_tasks {
+                    logging::info_u64("send_queue_task_id", self.tasks[tidx].id.0);
+                }
+                logging::info_u64("send_queue_enqueue_seq", seq);
+            }
+
+            logging::info_u64("reply_queue_len", ep.reply_queue.len() as u64);
+            for (pos, (tidx, seq)) in ep.reply_queue.entries().enumerate() {
+                logging::info_u64("reply_queue_pos", pos as u64);
+                logging::info_u64("reply_queue_task_index", tidx as u64);
+                if tidx < self.num_tasks {
+                    logging::info_u64("reply_queue_task_id", self.tasks[tidx].id.0);
+                }
+                logging::info_u64("reply_queue_enqueue_seq", seq);
+            }
+
+            // buffered mode（capacity = 0 は rendezvous。ipc_buffer.rs）。古い順
+            logging::info_u64("buffer_capacity", ep.buffer.capacity() as u64);
+            logging::info_u64("buffer_len", ep.buffer.len() as u64);
+            for (from, msg) in ep.buffer.iter() {
+                logging::info_u64("buffer_from_task_id", from.0);
+                logging::info_u64("buffer_msg", msg.mr0());
+            }
+        }
+        logging::info("=== End of Endpoint Dump ===");
+
+        self.dump_endpoint_stats();
+
+        logging::info("=== Notification Dump ===");
+        for n in self.notifications.iter() {
+            logging::info("NOTIFICATION:");
+            logging::info_u64("ntfn_id", n.id.0 as u64);
+            logging::info_u64("word", n.word);
+            logging::info_u64("waiters_len", n.wq_len as u64);
+            for pos in 0..n.wq_len {
+                let tidx = n.waiters[pos];
+                if tidx < self.num_tasks {
+                    logging::info_u64("waiter_task_id", self.tasks[tidx].id.0);
+                }
+            }
+        }
+        logging::info("=== End of Notification Dump ===");
+
+        logging::info("=== CPU Time Dump ===");
+        logging::info_u64("tick_count", self.tick_count);
+        logging::info_u64("user_ticks", self.cpu_time.user_ticks);
+        logging::info_u64("kernel_ticks", self.cpu_time.kernel_ticks);
+        logging::info_u64("idle_ticks", self.cpu_time.idle_ticks);
+        logging::info("=== End of CPU Time Dump ===");
+
+        self.dump_counters();
+
+        self.dump_invariant_latch();
+    }
+
+    /// Memory Usage Dump: フレームの total / used / free と、task ごとの AddressSpace の resident / swapped
+    /// - MemInfo syscall（mem_info.rs）と同じ値
+    fn dump_memory_usage(&self) {
+        logging::info("=== Memory Usage Dump ===");
+        let usage = self.phys_mem.usage();
+        logging::info_u64("frames_total", usage.total);
+        logging::info_u64("frames_used", usage.used);
+        logging::info_u64("frames_free", usage.free);
+        logging::info_u64("frames_reserved", self.phys_mem.stats().reserved);
+        for i in 0..self.num_tasks {
+            let info = self.mem_info_of(i);
+            logging::info("MEMORY:");
+            logging::info_u64("task_id", self.tasks[i].id.0);
+            logging::info_u64("address_space_id", self.tasks[i].address_space_id.0 as u64);
+            logging::info_u64("resident_pages", info.resident_pages);
+            logging::info_u64("swapped_pages", info.swapped_pages);
+            logging::info_u64("frame_quota", info.frame_quota);
+        }
+        logging::info("=== End of Memory Usage Dump ===");
+    }
+
+    /// Task Dump（dump_text と debug console の tasks）
+    fn dump_task_table(&self) {
         logging::info("=== Task Dump ===");
         for i in 0..self.num_tasks {
             let task = &self.tasks[i];
@@ -2364,6 +4659,7 @@ impl KernelState {
                 TaskState::Running => logging::info("state = Running"),
                 TaskState::Blocked => logging::info("state = Blocked"),
                 TaskState::Dead => logging::info("state = Dead"),
+                TaskState::Suspended => logging::info("state = Suspended"),
             }
 
             logging::info_u64("address_space_id", task.address_space_id.0 as u64);
@@ -2375,6 +4671,10 @@ impl KernelState {
                     logging::info("blocked_reason = IpcRecv");
                     logging::info_u64("blocked_ep", ep.0 as u64);
                 }
+                Some(BlockedReason::IpcRecvAny { ep_mask }) => {
+                    logging::info("blocked_reason = IpcRecvAny");
+                    logging::info_u64("blocked_ep_mask", ep_mask);
+                }
                 Some(BlockedReason::IpcSend { ep }) => {
                     logging::info("blocked_reason = IpcSend");
                     logging::info_u64("blocked_ep", ep.0 as u64);
@@ -2384,6 +4684,14 @@ impl KernelState {
                     logging::info_u64("blocked_ep", ep.0 as u64);
                     logging::info_u64("blocked_partner_task_id", partner.0);
                 }
+                Some(BlockedReason::NotifyWait { ntfn }) => {
+                    logging::info("blocked_reason = NotifyWait");
+                    logging::info_u64("blocked_ntfn", ntfn.0 as u64);
+                }
+                Some(BlockedReason::FaultWait { ep }) => {
+                    logging::info("blocked_reason = FaultWait");
+                    logging::info_u64("blocked_ep", ep.0 as u64);
+                }
             }
 
             match task.pending_syscall {
@@ -2392,30 +4700,43 @@ impl KernelState {
             }
 
             match task.pending_send_msg {
-                Some(v) => {
+                Some(m) => {
                     logging::info("pending_send_msg = Some");
-                    logging::info_u64("pending_send_msg_value", v);
+                    logging::info_u64("pending_send_msg_value", m.mr0());
+                    logging::info_u64("pending_send_msg_len", m.len() as u64);
                 }
                 None => logging::info("pending_send_msg = None"),
             }
 
             match task.last_msg {
-                Some(v) => {
+                Some(m) => {
                     logging::info("last_msg = Some");
-                    logging::info_u64("last_msg_value", v);
+                    logging::info_u64("last_msg_value", m.mr0());
+                    logging::info_u64("last_msg_len", m.len() as u64);
                 }
                 None => logging::info("last_msg = None"),
             }
+            if let Some(ep) = task.last_recv_ep {
+                logging::info_u64("last_recv_ep", ep.0 as u64);
+            }
 
             {
-                if let Some(v) = task.last_reply {
+                if let Some(m) = task.last_reply {
                     logging::info("last_reply = Some");
-                    logging::info_u64("last_reply_value", v);
+                    logging::info_u64("last_reply_value", m.mr0());
+                    logging::info_u64("last_reply_len", m.len() as u64);
                 } else {
                     logging::info("last_reply = None");
                 }
             }
 
+            if let Some(v) = task.last_notify {
+                logging::info("last_notify = Some");
+                logging::info_u64("last_notify_value", v);
+            } else {
+                logging::info("last_notify = None");
+            }
+
             // --- 追加: syscall（mem系など）の戻り値 ---
             {
                 if let Some(v) = task.last_syscall_ret {
@@ -2427,95 +4748,339 @@ impl KernelState {
             }
         }
         logging::info("=== End of Task Dump ===");
+    }
 
-        logging::info("=== AddressSpace Dump (per task) ===");
-        for i in 0..self.num_tasks {
-            let task = self.tasks[i];
+    /// Counters Dump（dump_text と debug console の counters）
+    fn dump_counters(&self) {
+        logging::info("=== Counters Dump ===");
+        logging::info_u64("sched_switches", self.counters.sched_switches);
+        logging::info_u64("sched_rr_max_passes", self.counters.sched_rr_max_passes);
 
-            logging::info("Task AddressSpace:");
-            logging::info_u64("task_index", i as u64);
-            logging::info_u64("task_id", task.id.0);
+        logging::info_u64("ipc_send_fast", self.counters.ipc_send_fast);
+        logging::info_u64("ipc_send_slow", self.counters.ipc_send_slow);
+        logging::info_u64("ipc_recv_fast", self.counters.ipc_recv_fast);
+        logging::info_u64("ipc_recv_slow", self.counters.ipc_recv_slow);
+        logging::info_u64("ipc_reply_delivered", self.counters.ipc_reply_delivered);
+        logging::info_u64("ipc_server_slow", self.counters.ipc_server_slow);
+        logging::info_u64("ipc_reply_timeouts", self.counters.ipc_reply_timeouts);
+        logging::info_u64("ipc_call_timeouts", self.counters.ipc_call_timeouts);
+
+        logging::info_u64("syscall_entry_int80", self.counters.syscall_entry_int80);
+        logging::info_u64("syscall_entry_syscall", self.counters.syscall_entry_syscall);
+        logging::info_u64("swap_out", self.counters.swap_out);
+        logging::info_u64("swap_in", self.counters.swap_in);
+        logging::info_u64("tlb_flush_deferred", self.counters.tlb_flush_deferred);
+        logging::info_u64("tlb_flushes", self.counters.tlb_flushes);
+        logging::info_u64("mlfq_demotions", self.counters.mlfq_demotions);
+        logging::info_u64("mlfq_promotions", self.counters.mlfq_promotions);
+        for (level, ticks) in self.counters.mlfq_level_ticks.iter().enumerate() {
+            logging::info_u64("mlfq_level", level as u64);
+            logging::info_u64("mlfq_level_ticks", *ticks);
+        }
+        logging::info_u64("idle_entries", self.counters.idle_entries);
+        logging::info_u64("watchdog_no_progress", self.counters.watchdog_no_progress);
+        logging::info_u64("ipc_deadlocks_detected", self.counters.ipc_deadlocks_detected);
+        logging::info_u64("ipc_deadlocks_broken", self.counters.ipc_deadlocks_broken);
+        logging::info_u64("events_overwritten", self.counters.events_overwritten);
+        for (key, n) in event_log::EVENT_CLASS_KEYS.iter().zip(self.counters.events_overwritten_by_class.iter()) {
+            logging::info_u64(key, *n);
+        }
+        logging::info_u64("critical_events_retained", self.counters.critical_events_retained);
+        logging::info_u64("critical_events_lost", self.counters.critical_events_lost);
+        logging::info_u64("invariant_violations", self.counters.invariant_violations);
+        logging::info_u64("invariant_checks_full", self.counters.invariant_checks_full);
+        logging::info_u64("invariant_checks_cheap", self.counters.invariant_checks_cheap);
+        logging::info_u64("input_received", self.counters.input_received);
+        logging::info_u64("input_dropped", self.counters.input_dropped);
+        logging::info_u64("input_read", self.counters.input_read);
+
+        logging::info_u64("prio_inherited", self.counters.prio_inherited);
+        logging::info_u64("prio_restored", self.counters.prio_restored);
+
+        logging::info_u64("task_created", self.counters.task_created);
+        logging::info_u64("task_exited", self.counters.task_exited);
+
+        logging::info_u64("notify_signals", self.counters.notify_signals);
+        logging::info_u64("notify_delivered", self.counters.notify_delivered);
+        logging::info_u64("notify_wait_blocked", self.counters.notify_wait_blocked);
+        logging::info_u64("cap_denied", self.counters.cap_denied);
+        logging::info_u64("cap_transfers", self.counters.cap_transfers);
+        logging::info_u64("cap_transfer_failed", self.counters.cap_transfer_failed);
 
-            let as_idx = task.address_space_id.0;
-            let aspace = &self.address_spaces[as_idx];
+        logging::info_u64("task_killed_user_pf", self.counters.task_killed_user_pf);
+        logging::info_u64("task_killed_demo_injected", self.counters.task_killed_demo_injected);
+        logging::info_u64("task_killed_trap_frame", self.counters.task_killed_trap_frame);
+        logging::info_u64("task_killed_fault_storm", self.counters.task_killed_fault_storm);
+        logging::info_u64("task_killed_stack_overflow", self.counters.task_killed_stack_overflow);
+        logging::info_u64("task_killed_user_exception", self.counters.task_killed_user_exception);
+        logging::info_u64("task_killed_shutdown", self.counters.task_killed_shutdown);
+        logging::info_u64("user_pf_total", self.counters.user_pf_total);
+        logging::info_u64("fault_delegated", self.counters.fault_delegated);
+        logging::info_u64("fault_resumed", self.counters.fault_resumed);
+        logging::info_u64("fault_orphaned", self.counters.fault_orphaned);
+        logging::info_u64("grant_mapped", self.counters.grant_mapped);
+        logging::info_u64("grant_revoked", self.counters.grant_revoked);
+        logging::info_u64("grant_failed", self.counters.grant_failed);
+        logging::info_u64("derivations_recorded", self.counters.derivations_recorded);
+        logging::info_u64("derivations_revoked", self.counters.derivations_revoked);
+        logging::info_u64("derivation_table_full", self.counters.derivation_table_full);
+        logging::info_u64("tasks_suspended", self.counters.tasks_suspended);
+        logging::info_u64("tasks_resumed", self.counters.tasks_resumed);
+        logging::info_u64("suspend_recv_deferred", self.counters.suspend_recv_deferred);
+        logging::info_u64("task_info_queries", self.counters.task_info_queries);
+        logging::info_u64("reply_caps_minted", self.counters.reply_caps_minted);
+        logging::info_u64("reply_caps_rejected", self.counters.reply_caps_rejected);
+        logging::info_u64("ipc_buffered_sends", self.counters.ipc_buffered_sends);
+        logging::info_u64("ipc_buffered_delivered", self.counters.ipc_buffered_delivered);
+        logging::info_u64("ipc_buffer_dropped", self.counters.ipc_buffer_dropped);
+        logging::info_u64("ipc_rendezvous_delivered", self.counters.ipc_rendezvous_delivered);
+        logging::info_u64("ipc_handoff_direct", self.counters.ipc_handoff_direct);
+        logging::info_u64("ipc_handoff_scheduled", self.counters.ipc_handoff_scheduled);
+        logging::info_u64("ipc_handoff_scheduled_ticks", self.counters.ipc_handoff_scheduled_ticks);
+        logging::info_u64("ipc_handoff_scheduled_max_ticks", self.counters.ipc_handoff_scheduled_max_ticks);
+        logging::info_u64("ipc_direct_switch_declined", self.counters.ipc_direct_switch_declined);
+        logging::info_u64("mappings_rejected", self.counters.mappings_rejected);
+        logging::info_u64("task_killed_swap_failed", self.counters.task_killed_swap_failed);
+
+        logging::info_u64("ipc_events_seen", self.counters.ipc_events_seen);
+        logging::info_u64("ipc_events_skipped", self.counters.ipc_events_skipped);
+
+        // 物理フレーム（PhysicalMemoryManager が数える。page table 用も含む）
+        let frames = self.phys_mem.stats();
+        logging::info_u64("frames_allocated", frames.allocated);
+        logging::info_u64("frames_freed", frames.freed);
+        logging::info_u64("frames_reused", frames.reused);
+        logging::info_u64("frames_double_free", frames.double_free);
+        logging::info_u64("frames_free", frames.free_frames);
+        logging::info_u64("frames_untracked", frames.untracked);
+        logging::info_u64("frames_shared", frames.shared);
+        logging::info_u64("frames_injected_failures", frames.injected_failures);
+        logging::info_u64("frames_reserved", frames.reserved);
+        self.phys_mem.for_each_reservation(|r| {
+            logging::info_hex("phys_reserved_start", r.start);
+            logging::info_u64("phys_reserved_len", r.len);
+        });
 
-            match aspace.kind {
-                AddressSpaceKind::Kernel => logging::info("kind = Kernel"),
-                AddressSpaceKind::User => logging::info("kind = User"),
+        // kernel heap（mm::heap）
+        let heap = crate::mm::heap::stats();
+        logging::info_u64("heap_size", heap.size as u64);
+        logging::info_u64("heap_used", heap.used as u64);
+        logging::info_u64("heap_free_blocks", heap.free_blocks as u64);
+        logging::info_u64("heap_largest_free", heap.largest_free as u64);
+        logging::info_u64("heap_allocs", heap.allocs);
+        logging::info_u64("heap_frees", heap.frees);
+        logging::info_u64("heap_failures", heap.failures);
+
+        // 外部 IRQ（arch::interrupts の dispatch が数える。タイミング依存なので wire / state hash には入れない）
+        let irq = crate::arch::interrupts::irq_stats();
+        logging::info_u64("irq_dispatched", irq.dispatched);
+        logging::info_u64("irq_spurious", irq.spurious);
+        logging::info_u64("irq_unhandled", irq.unhandled);
+
+        // time: kernel clock と hardware clock（time.rs）
+        self.dump_time();
+
+        // smp: AP と BKL（CPU 数・タイミング依存なので wire / state hash には入れない）
+        #[cfg(feature = "smp")]
+        self.dump_smp();
+
+        // virtio_console: trace の出口（device の有無で変わるので wire / state hash には入れない）
+        #[cfg(feature = "virtio_console")]
+        {
+            let v = crate::arch::drivers::virtio_console::stats();
+            logging::info_u64("virtio_trace_ready", v.ready as u64);
+            logging::info_u64("virtio_trace_bytes", v.bytes);
+            logging::info_u64("virtio_trace_flushes", v.flushes);
+        }
+        logging::info("=== End of Counters Dump ===");
+    }
+
+    /// abi.rs の word の名前で key=value の 1 行レコードを出す（serial のみ）
+    /// - event は push 時点の seq、snapshot（task / ep / counters）は出力時点
+    fn dump_records(&self) {
+        use logging::record;
+
+        logging::info("=== Record Dump ===");
+        record::begin("meta");
+        record::field("wire_version", abi::WIRE_VERSION as u64);
+        record::field("ipc_event_sample_every", IPC_EVENT_SAMPLE_EVERY);
+        record::field("events", self.event_log_len as u64);
+        record::field("events_overwritten", self.counters.events_overwritten);
+        record::field("retained_events", self.retained_events.len() as u64);
+        record::end();
+
+        if let Some(hw) = crate::arch::hwfault::latched() {
+            record::begin("hw_event");
+            record::field("latched", hw.latched);
+            record::field("nmi_count", hw.nmi_count);
+            record::field("mc_count", hw.mc_count);
+            record::field_str("last", hw.last_kind.name());
+            record::field("rip", hw.rip);
+            record::field("rsp", hw.rsp);
+            record::field("cs", hw.cs);
+            record::field("rflags", hw.rflags);
+            record::field("cr3", hw.cr3);
+            record::field("mcg_status", hw.mcg_status);
+            record::end();
+        }
+
+        // dump 時点の kernel clock（kernel_source = ticks なら trace と一緒に決まる）
+        record::begin("clock");
+        record::field("kernel_ns", self.now().as_ns());
+        record::field_str("kernel_source", time::kernel_clock_source().name());
+        record::field_str("hw_source", time::clock_source_name());
+        record::end();
+
+        self.for_each_logged_event(|seq, ev| {
+            let r = abi::encode_event(ev);
+            let Some((name, keys)) = abi::event_schema(r.sub()) else {
+                return;
+            };
+            record::begin("event");
+            record::field("seq", seq);
+            record::field_str("ev", name);
+            for (w, key) in keys.iter().enumerate() {
+                record::field(key, r.word(w));
             }
+            record::end();
+        });
 
-            match aspace.root_page_frame {
-                Some(root) => logging::info_u64("root_page_frame_index", root.number),
-                None => logging::info("root_page_frame_index = None"),
+        for i in 0..self.num_tasks {
+            let r = abi::encode_task_info(i, &self.tasks[i]);
+            record::begin("task");
+            record::field("seq", logging::next_seq());
+            record::field("index", i as u64);
+            for (w, key) in abi::TASK_INFO_KEYS.iter().enumerate() {
+                record::field(key, r.word(w));
             }
+            record::end();
+        }
 
-            logging::info_u64("address_space_id", as_idx as u64);
+        for ep in self.endpoints.iter() {
+            let r = abi::encode_endpoint_info(ep);
+            record::begin("endpoint");
+            record::field("seq", logging::next_seq());
+            for (w, key) in abi::ENDPOINT_INFO_KEYS.iter().enumerate() {
+                record::field(key, r.word(w));
+            }
+            record::end();
+        }
 
-            let count = aspace.mapping_count();
-            logging::info_u64("mapping_count", count as u64);
+        for (i, s) in self.counters.per_endpoint.iter().enumerate() {
+            if s.is_idle() {
+                continue;
+            }
+            let r = abi::encode_endpoint_stats(i, s);
+            record::begin("endpoint_stats");
+            record::field("seq", logging::next_seq());
+            for (w, key) in abi::ENDPOINT_STATS_KEYS.iter().enumerate() {
+                record::field(key, r.word(w));
+            }
+            record::end();
+        }
 
-            aspace.for_each_mapping(|m| {
-                logging::info("MAPPING:");
-                logging::info_u64("virt_page_index", m.page.number);
-                logging::info_u64("phys_frame_index", m.frame.number);
-                logging::info_u64("flags_bits", m.flags.bits());
-            });
+        // 物理フレームの使用量と task ごとの resident / swapped（Memory Usage Dump と同じ値）
+        let usage = self.phys_mem.usage();
+        record::begin("memory");
+        record::field("seq", logging::next_seq());
+        record::field("frames_total", usage.total);
+        record::field("frames_used", usage.used);
+        record::field("frames_free", usage.free);
+        record::end();
+        for i in 0..self.num_tasks {
+            let info = self.mem_info_of(i);
+            record::begin("aspace_memory");
+            record::field("seq", logging::next_seq());
+            record::field("task_id", self.tasks[i].id.0);
+            record::field("address_space_id", self.tasks[i].address_space_id.0 as u64);
+            record::field("resident_pages", info.resident_pages);
+            record::field("swapped_pages", info.swapped_pages);
+            record::end();
+        }
+
+        let frames = self.phys_mem.stats();
+        let values = abi::counter_values(&self.counters, &frames);
+        record::begin("counters");
+        record::field("seq", logging::next_seq());
+        for (key, v) in abi::COUNTER_KEYS.iter().zip(values.iter()) {
+            record::field(key, *v);
+        }
+        record::end();
+
+        logging::info("=== End of Record Dump ===");
+    }
 
-            if let Some(m) = task.last_msg {
-                logging::info("IPC:");
-                logging::info_u64("last_msg", m);
-            }
+    /// ★追加: abi.rs のワイヤ形式で同じ内容を出す（ホスト側ツール用、serial のみ）
+    /// - 1 行 = 1 レコード: "wire = <72 byte hex>"
+    #[cfg(feature = "trace_wire")]
+    fn dump_wire(&self) {
+        logging::info("=== Wire Dump ===");
+        logging::info_u64("wire_version", abi::WIRE_VERSION as u64);
+
+        self.for_each_logged_event(|seq, ev| {
+            let mut r = abi::encode_event(ev);
+            r.set_seq(seq);
+            logging::wire_hex("wire", &r.bytes);
+        });
+
+        // snapshot 系は「出した時点」の通し番号を持たせる
+        for i in 0..self.num_tasks {
+            let mut r = abi::encode_task_info(i, &self.tasks[i]);
+            r.set_seq(logging::next_seq());
+            logging::wire_hex("wire", &r.bytes);
         }
-        logging::info("=== End of AddressSpace Dump ===");
 
-        logging::info("=== Endpoint Dump ===");
         for ep in self.endpoints.iter() {
-            logging::info("ENDPOINT:");
-            logging::info_u64("ep_id", ep.id.0 as u64);
-
-            match ep.recv_waiter {
-                Some(tidx) => {
-                    logging::info_u64("recv_waiter_task_index", tidx as u64);
-                    if tidx < self.num_tasks {
-                        logging::info_u64("recv_waiter_task_id", self.tasks[tidx].id.0);
-                    }
-                }
-                None => logging::info("recv_waiter_task_index = None"),
-            }
+            let mut r = abi::encode_endpoint_info(ep);
+            r.set_seq(logging::next_seq());
+            logging::wire_hex("wire", &r.bytes);
+        }
 
-            logging::info_u64("send_queue_len", ep.sq_len as u64);
-            for pos in 0..ep.sq_len {
-                let tidx = ep.send_queue[pos];
-                logging::info_u64("send_queue_task_index", tidx as u64);
-                if tidx < self.num_tasks {
-                    logging::info_u64("send_queue_task_id", self.tasks[tidx].id.0);
-                }
+        for (i, s) in self.counters.per_endpoint.iter().enumerate() {
+            if s.is_idle() {
+                continue;
             }
+            let mut r = abi::encode_endpoint_stats(i, s);
+            r.set_seq(logging::next_seq());
+            logging::wire_hex("wire", &r.bytes);
+        }
 
-            logging::info_u64("reply_queue_len", ep.rq_len as u64);
-            for pos in 0..ep.rq_len {
-                let tidx = ep.reply_queue[pos];
-                logging::info_u64("reply_queue_task_index", tidx as u64);
-                if tidx < self.num_tasks {
-                    logging::info_u64("reply_queue_task_id", self.tasks[tidx].id.0);
-                }
-            }
+        let mut page: u16 = 0;
+        let frames = self.phys_mem.stats();
+        while let Some(mut r) = abi::encode_counters(&self.counters, &frames, page) {
+            r.set_seq(logging::next_seq());
+            logging::wire_hex("wire", &r.bytes);
+            page += 1;
         }
-        logging::info("=== End of Endpoint Dump ===");
 
-        logging::info("=== Counters Dump ===");
-        logging::info_u64("sched_switches", self.counters.sched_switches);
+        logging::info("=== End of Wire Dump ===");
+    }
+}
 
-        logging::info_u64("ipc_send_fast", self.counters.ipc_send_fast);
-        logging::info_u64("ipc_send_slow", self.counters.ipc_send_slow);
-        logging::info_u64("ipc_recv_fast", self.counters.ipc_recv_fast);
-        logging::info_u64("ipc_recv_slow", self.counters.ipc_recv_slow);
-        logging::info_u64("ipc_reply_delivered", self.counters.ipc_reply_delivered);
+/// mem::addr::PhysFrame → PhysicalMemoryManager が扱う x86_64 の PhysFrame
+fn to_arch_frame(frame: PhysFrame) -> x86_64::structures::paging::PhysFrame {
+    x86_64::structures::paging::PhysFrame::containing_address(x86_64::PhysAddr::new(frame.start_address().0))
+}
 
-        logging::info_u64("task_killed_user_pf", self.counters.task_killed_user_pf);
-        logging::info_u64("task_killed_demo_injected", self.counters.task_killed_demo_injected);
-        logging::info("=== End of Counters Dump ===");
-    }
+/// NMI / #MC の latch を Event Log Dump の先頭に出す（立っていなければ fatal_hw_event = 0 の 1 行だけ）
+fn dump_hw_event_text() {
+    let Some(hw) = crate::arch::hwfault::latched() else {
+        logging::info_u64("fatal_hw_event", 0);
+        return;
+    };
+    logging::without_dedup(|| {
+        logging::error("FATAL HARDWARE EVENT");
+        logging::info_hex("fatal_hw_event", hw.latched);
+        logging::info_u64("nmi_count", hw.nmi_count);
+        logging::info_u64("mc_count", hw.mc_count);
+        logging::info(hw.last_kind.name());
+        logging::info_hex("rip", hw.rip);
+        logging::info_hex("rsp", hw.rsp);
+        logging::info_hex("cs", hw.cs);
+        logging::info_hex("rflags", hw.rflags);
+        logging::info_hex("cr3", hw.cr3);
+        logging::info_hex("mcg_status", hw.mcg_status);
+    });
 }
 
 fn log_event_to_vga(ev: LogEvent) {
@@ -2529,6 +5094,10 @@ fn log_event_to_vga(ev: LogEvent) {
             logging::info_u64("time", n);
         }
         LogEvent::FrameAllocated => logging::info("EVENT: FrameAllocated"),
+        LogEvent::FrameFreed { frame } => {
+            logging::info("EVENT: FrameFreed");
+            logging::info_u64("frame", frame);
+        }
         LogEvent::TaskSwitched(tid) => {
             logging::info("EVENT: TaskSwitched");
             logging::info_u64("task", tid.0);
@@ -2541,6 +5110,7 @@ fn log_event_to_vga(ev: LogEvent) {
                 TaskState::Running => logging::info("to RUNNING"),
                 TaskState::Blocked => logging::info("to BLOCKED"),
                 TaskState::Dead => logging::info("to DEAD"),
+                TaskState::Suspended => logging::info("to SUSPENDED"),
             }
         }
         LogEvent::ReadyQueued(tid) => {
@@ -2585,6 +5155,11 @@ fn log_event_to_vga(ev: LogEvent) {
                     logging::info("mem_action = Unmap");
                     logging::info_u64("virt_page_index", page.number);
                 }
+                MemAction::Protect { page, new_flags } => {
+                    logging::info("mem_action = Protect");
+                    logging::info_u64("virt_page_index", page.number);
+                    logging::info_u64("flags_bits", new_flags.bits());
+                }
             }
         }
         LogEvent::SyscallIssued { task } => {
@@ -2609,7 +5184,7 @@ fn log_event_to_vga(ev: LogEvent) {
             logging::info("EVENT: IpcSendCalled");
             logging::info_u64("task", task.0);
             logging::info_u64("ep", ep.0 as u64);
-            logging::info_u64("msg", msg);
+            log_ipc_message(&msg);
         }
         LogEvent::IpcSendBlocked { task, ep } => {
             logging::info("EVENT: IpcSendBlocked");
@@ -2621,7 +5196,7 @@ fn log_event_to_vga(ev: LogEvent) {
             logging::info_u64("from", from.0);
             logging::info_u64("to", to.0);
             logging::info_u64("ep", ep.0 as u64);
-            logging::info_u64("msg", msg);
+            log_ipc_message(&msg);
         }
         LogEvent::IpcReplyCalled { task, ep, to } => {
             logging::info("EVENT: IpcReplyCalled");
@@ -2629,11 +5204,208 @@ fn log_event_to_vga(ev: LogEvent) {
             logging::info_u64("ep", ep.0 as u64);
             logging::info_u64("to", to.0);
         }
-        LogEvent::IpcReplyDelivered { from, to, ep } => {
+        LogEvent::IpcReplyDelivered { from, to, ep, msg } => {
             logging::info("EVENT: IpcReplyDelivered");
             logging::info_u64("from", from.0);
             logging::info_u64("to", to.0);
             logging::info_u64("ep", ep.0 as u64);
+            log_ipc_message(&msg);
+        }
+        LogEvent::ServerSlow { server, client, ep, held_ticks } => {
+            logging::info("EVENT: ServerSlow");
+            logging::info_u64("server", server.0);
+            logging::info_u64("client", client.0);
+            logging::info_u64("ep", ep.0 as u64);
+            logging::info_u64("held_ticks", held_ticks);
+        }
+        LogEvent::TimerFired { task, ep, missed } => {
+            logging::info("EVENT: TimerFired");
+            logging::info_u64("task", task.0);
+            logging::info_u64("ep", ep.0 as u64);
+            logging::info_u64("missed", missed);
+        }
+        LogEvent::PriorityInherited { task, from, priority } => {
+            logging::info("EVENT: PriorityInherited");
+            logging::info_u64("task", task.0);
+            logging::info_u64("from", from.0);
+            logging::info_u64("priority", priority as u64);
+        }
+        LogEvent::PriorityRestored { task, priority } => {
+            logging::info("EVENT: PriorityRestored");
+            logging::info_u64("task", task.0);
+            logging::info_u64("priority", priority as u64);
+        }
+        LogEvent::TaskCreated { task, parent, slot, priority } => {
+            logging::info("EVENT: TaskCreated");
+            logging::info_u64("task", task.0);
+            logging::info_u64("parent", parent.0);
+            logging::info_u64("slot", slot as u64);
+            logging::info_u64("priority", priority as u64);
+        }
+        LogEvent::TaskExited { task } => {
+            logging::info("EVENT: TaskExited");
+            logging::info_u64("task", task.0);
+        }
+        LogEvent::NotifySignaled { task, ntfn, bits } => {
+            logging::info("EVENT: NotifySignaled");
+            logging::info_u64("task", task.0);
+            logging::info_u64("ntfn", ntfn.0 as u64);
+            logging::info_u64("bits", bits);
+        }
+        LogEvent::NotifyWaitBlocked { task, ntfn } => {
+            logging::info("EVENT: NotifyWaitBlocked");
+            logging::info_u64("task", task.0);
+            logging::info_u64("ntfn", ntfn.0 as u64);
+        }
+        LogEvent::NotifyDelivered { to, ntfn, bits } => {
+            logging::info("EVENT: NotifyDelivered");
+            logging::info_u64("to", to.0);
+            logging::info_u64("ntfn", ntfn.0 as u64);
+            logging::info_u64("bits", bits);
+        }
+        LogEvent::CapDenied { task, cap, need } => {
+            logging::info("EVENT: CapDenied");
+            logging::info_u64("task", task.0);
+            logging::info_u64("cap", cap as u64);
+            logging::info_u64("need", need as u64);
+        }
+        LogEvent::CapGranted { from, to, cap, object } => {
+            logging::info("EVENT: CapGranted");
+            logging::info_u64("from", from.0);
+            logging::info_u64("to", to.0);
+            logging::info_u64("cap", cap as u64);
+            logging::info_u64("object", object.id());
+            logging::info_u64("kind", object.kind().code());
+        }
+        LogEvent::CapReceived { task, slot, object, rights } => {
+            logging::info("EVENT: CapReceived");
+            logging::info_u64("task", task.0);
+            logging::info_u64("slot", slot as u64);
+            logging::info_u64("object", object.id());
+            logging::info_u64("rights", rights as u64);
+            logging::info_u64("kind", object.kind().code());
+        }
+        LogEvent::EndpointCreated { task, ep, cap } => {
+            logging::info("EVENT: EndpointCreated");
+            logging::info_u64("task", task.0);
+            logging::info_u64("ep", ep.0 as u64);
+            logging::info_u64("cap", cap as u64);
+        }
+        LogEvent::EndpointDeleted { task, ep, revoked } => {
+            logging::info("EVENT: EndpointDeleted");
+            logging::info_u64("task", task.0);
+            logging::info_u64("ep", ep.0 as u64);
+            logging::info_u64("revoked", revoked as u64);
+        }
+        LogEvent::PageSwappedOut { task, page, slot } => {
+            logging::info("EVENT: PageSwappedOut");
+            logging::info_u64("task", task.0);
+            logging::info_u64("page", page);
+            logging::info_u64("slot", slot as u64);
+        }
+        LogEvent::PageSwappedIn { task, page, slot } => {
+            logging::info("EVENT: PageSwappedIn");
+            logging::info_u64("task", task.0);
+            logging::info_u64("page", page);
+            logging::info_u64("slot", slot as u64);
+        }
+        LogEvent::ShmCreated { task, shm, pages } => {
+            logging::info("EVENT: ShmCreated");
+            logging::info_u64("task", task.0);
+            logging::info_u64("shm", shm.0 as u64);
+            logging::info_u64("pages", pages as u64);
+        }
+        LogEvent::ShmMapped { task, shm, page } => {
+            logging::info("EVENT: ShmMapped");
+            logging::info_u64("task", task.0);
+            logging::info_u64("shm", shm.0 as u64);
+            logging::info_u64("page", page);
+        }
+        LogEvent::ShmDestroyed { owner, shm, unmapped } => {
+            logging::info("EVENT: ShmDestroyed");
+            logging::info_u64("owner", owner.0);
+            logging::info_u64("shm", shm.0 as u64);
+            logging::info_u64("unmapped", unmapped as u64);
+        }
+        LogEvent::TlbFlushDeferred { address_space, page } => {
+            logging::info("EVENT: TlbFlushDeferred");
+            logging::info_u64("address_space_id", address_space.0 as u64);
+            logging::info_u64("page", page);
+        }
+        LogEvent::TlbFlushed { address_space, pages, stale_ticks } => {
+            logging::info("EVENT: TlbFlushed");
+            logging::info_u64("address_space_id", address_space.0 as u64);
+            logging::info_u64("pages", pages as u64);
+            logging::info_u64("stale_ticks", stale_ticks);
+        }
+        LogEvent::LevelChanged { task, from, to } => {
+            logging::info("EVENT: LevelChanged");
+            logging::info_u64("task", task.0);
+            logging::info_u64("from", from as u64);
+            logging::info_u64("to", to as u64);
+        }
+        LogEvent::NoProgressDetected { stalled_ticks, last_progress_tick, task_states } => {
+            logging::info("EVENT: NoProgressDetected");
+            logging::info_u64("stalled_ticks", stalled_ticks);
+            logging::info_u64("last_progress_tick", last_progress_tick);
+            logging::info_u64("task_states", task_states);
+        }
+        LogEvent::DeadlockDetected { len, tasks } => {
+            logging::info("EVENT: DeadlockDetected");
+            logging::info_u64("len", len as u64);
+            for id in tasks.iter().flatten() {
+                logging::info_u64("task", id.0);
+            }
+        }
+        LogEvent::InvariantViolated { id, task, detail } => {
+            logging::info("EVENT: InvariantViolated");
+            logging::info_u64("invariant", id.code());
+            logging::info(id.name());
+            if let Some(t) = task {
+                logging::info_u64("task", t.0);
+            }
+            if let Some(d) = detail {
+                logging::info_u64("detail", d);
+            }
+        }
+        LogEvent::InputReceived { scancode, queued } => {
+            logging::info("EVENT: InputReceived");
+            logging::info_u64("scancode", scancode as u64);
+            logging::info_u64("queued", queued as u64);
+        }
+        LogEvent::FaultDelegated { task, ep, addr } => {
+            logging::info("EVENT: FaultDelegated");
+            logging::info_u64("task", task.0);
+            logging::info_u64("ep", ep.0 as u64);
+            logging::info_hex("addr", addr);
+        }
+        LogEvent::FaultResolved { task, by, action } => {
+            logging::info("EVENT: FaultResolved");
+            logging::info_u64("task", task.0);
+            logging::info_u64("by", by.0);
+            logging::info_u64("action", action);
+        }
+        LogEvent::PageGranted { from, to, page, frame } => {
+            logging::info("EVENT: PageGranted");
+            logging::info_u64("from", from.0);
+            logging::info_u64("to", to.0);
+            logging::info_u64("page", page);
+            logging::info_u64("frame", frame);
+        }
+        LogEvent::GrantRevoked { from, to, page, reason } => {
+            logging::info("EVENT: GrantRevoked");
+            logging::info_u64("from", from.0);
+            logging::info_u64("to", to.0);
+            logging::info_u64("page", page);
+            logging::info_u64("reason", reason);
+        }
+        LogEvent::DerivationRevoked { by, task, kind, object, depth } => {
+            logging::info("EVENT: DerivationRevoked");
+            logging::info_u64("by", by.0);
+            logging::info_u64("task", task.0);
+            logging::info_u64("kind", kind);
+            logging::info_u64("object", object);
+            logging::info_u64("depth", depth);
         }
         LogEvent::TaskKilled { task, reason } => {
             logging::info("EVENT: TaskKilled");
@@ -2649,11 +5421,73 @@ fn log_event_to_vga(ev: LogEvent) {
                     logging::info("reason = DemoInjected");
                     logging::info_u64("code", code);
                 }
+                TaskKillReason::TrapFrameCorrupt { check, value } => {
+                    logging::info("reason = TrapFrameCorrupt");
+                    logging::info_u64("check", check);
+                    logging::info_u64("value", value);
+                }
+                TaskKillReason::FaultStorm { faults, window_ticks } => {
+                    logging::info("reason = FaultStorm");
+                    logging::info_u64("faults", faults);
+                    logging::info_u64("window_ticks", window_ticks);
+                }
+                TaskKillReason::StackOverflow { addr, rip } => {
+                    logging::info("reason = StackOverflow");
+                    logging::info_u64("addr", addr);
+                    logging::info_u64("rip", rip);
+                }
+                TaskKillReason::UserException { vector, err, rip } => {
+                    logging::info("reason = UserException");
+                    logging::info(crate::arch::interrupts::exception_name(vector as u8));
+                    logging::info_u64("vector", vector);
+                    logging::info_u64("err", err);
+                    logging::info_u64("rip", rip);
+                }
+                TaskKillReason::Shutdown { by } => {
+                    logging::info("reason = Shutdown");
+                    logging::info_u64("by", by.0);
+                }
+                TaskKillReason::SwapFailed { page, stage } => {
+                    logging::info("reason = SwapFailed");
+                    logging::info_u64("virt_page_index", page);
+                    logging::info_u64("stage", stage);
+                }
             }
         }
     }
 }
 
+/// IPC メッセージのログ（MR0 は従来どおり "msg"、MR1.. は len > 1 のときだけ）
+fn log_ipc_message(msg: &IpcMessage) {
+    const MR_KEYS: [&str; ipc::IPC_MSG_REGS] = ["msg", "mr1", "mr2", "mr3"];
+
+    logging::info_u64("msg", msg.mr0());
+    logging::info_u64("msg_len", msg.len() as u64);
+    for (i, key) in MR_KEYS.iter().enumerate().take(msg.len()).skip(1) {
+        logging::info_u64(key, msg.mr(i));
+    }
+    if let Some(cap) = msg.cap_transfer() {
+        logging::info_u64("cap_transfer", cap.0 as u64);
+    }
+    if let Some(page) = msg.grant() {
+        logging::info_u64("grant_page", page.number);
+    }
+}
+
+/// サンプリング対象（IPC 系）の event か
+fn is_ipc_event(ev: &LogEvent) -> bool {
+    matches!(
+        ev,
+        LogEvent::IpcRecvCalled { .. }
+            | LogEvent::IpcRecvBlocked { .. }
+            | LogEvent::IpcSendCalled { .. }
+            | LogEvent::IpcSendBlocked { .. }
+            | LogEvent::IpcDelivered { .. }
+            | LogEvent::IpcReplyCalled { .. }
+            | LogEvent::IpcReplyDelivered { .. }
+    )
+}
+
 fn next_activity_and_action(current: KernelActivity) -> (KernelActivity, KernelAction) {
     match current {
         KernelActivity::Idle => (KernelActivity::UpdatingTimer, KernelAction::None),
@@ -2661,4 +5495,44 @@ fn next_activity_and_action(current: KernelActivity) -> (KernelActivity, KernelA
         KernelActivity::AllocatingFrame => (KernelActivity::MappingDemoPage, KernelAction::AllocateFrame),
         KernelActivity::MappingDemoPage => (KernelActivity::Idle, KernelAction::MemDemo),
     }
-}
\ No newline at end of file
+}
+
+/// kernel heap の起動時確認: Vec / Box を確保して解放し、使用量が元に戻ること
+fn heap_self_test() {
+    use alloc::boxed::Box;
+    use alloc::vec::Vec;
+
+    let before = crate::mm::heap::stats().used;
+    {
+        let mut v: Vec<u64> = Vec::with_capacity(32);
+        for i in 0..32u64 {
+            v.push(i);
+        }
+        let b = Box::new(v.iter().sum::<u64>());
+        if *b != 31 * 32 / 2 {
+            logging::error("kernel heap: self-test value mismatch");
+        }
+    }
+    let after = crate::mm::heap::stats().used;
+
+    if before != after {
+        logging::error("kernel heap: self-test leaked");
+        logging::info_u64("used_before", before as u64);
+        logging::info_u64("used_after", after as u64);
+        return;
+    }
+    logging::info("kernel heap: self-test OK");
+}
+
+/// kernel heap の空きリスト（mm::heap の check）
+#[spec("INV-HEAP-001")]
+fn check_heap_invariants(ks: &KernelState) {
+    if let Err(e) = crate::mm::heap::check() {
+        ks.invariant_violated(InvariantId::KernelHeap, None, None, "INVARIANT VIOLATION: kernel heap free list broken");
+        match e {
+            crate::mm::heap::HeapCheckError::FreeListOrder => logging::info("reason = FreeListOrder"),
+            crate::mm::heap::HeapCheckError::FreeBlockBounds => logging::info("reason = FreeBlockBounds"),
+            crate::mm::heap::HeapCheckError::Accounting => logging::info("reason = Accounting"),
+        }
+    }
+}
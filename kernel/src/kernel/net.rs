@@ -0,0 +1,319 @@
+// kernel/src/kernel/net.rs
+//
+// 役割（feature virtio_net）:
+// - virtio-net（arch::drivers::virtio_net）の送受信を user task に出す kernel service。
+// - 送信は NET_SERVICE_EP への IpcSend、受信は client の notify_ep への通知（IPC）+ shm の page で frame を渡す。
+//
+// プロトコル（abi.rs が正本）:
+// - BIND: MR0 = NET_OP_BIND、MR1 = notify_ep、MR2 = shm handle（client の handle table の CapIndex。MAP 権限）
+//   * 受信した frame を handle が指す segment の先頭 page に書き、notify_ep で通知する
+//   * reply = [NET_SVC_OK, MAC（下位 48bit）] / NET_SVC_ERR_*
+// - SEND: MR0 = NET_OP_SEND、MR1 = shm handle、MR2 = frame 長
+//   * segment の先頭 page の frame（Ethernet header から。FCS なし）を送る。送り終わってから reply
+// - 通知: notify_ep で IpcRecv している client に MR0 = NET_RX_TAG | 通知番号、MR1 = frame 長、
+//   MR2 = ethertype、MR3 = 捨てた frame の累計
+//   * 1 回の通知で 1 frame。次の IpcRecv までに client は page を読み終えること（次の frame で上書きされる）
+//
+// 設計方針:
+// - service は block させずに即 reply する（timer_service と同じく ipc_send の入口で処理する）
+// - 受信した frame は driver の used ring に置いたままにし、client が recv 待ちのときだけ 1 つ取り出す
+//   * 取り出しは tick（UpdateTimer）と IpcRecv の入口だけ。IRQ handler は ack と集計だけ（event trace の順序を tick にそろえる）
+//   * client が居ない間の frame は取り出して捨てる（数える）
+// - 受信側の client は 1 つ（BIND した task）。kill された task の登録は kill 側で必ず消す
+// - frame の中身は state hash に入れない（外から来る値で run ごとに変わる）
+//
+// 制限:
+// - shm handle は BIND 時に ShmId へ引いて覚える（owner が死んで segment が消えたら、その後の frame は捨てる）
+// - notify_ep は生の EndpointId のまま（kernel service の登録先。timer_service と同じ）
+// - 送信は同期（driver の TX descriptor は 1 つ）
+// - timer_service / stress_ipc（ep1 / ep2 の割り当てが違う）/ shm_demo（Task1 の取り合い）とは併用しない（compile_error）
+
+#[cfg(any(feature = "timer_service", feature = "stress_ipc"))]
+compile_error!("virtio_net uses ep1 / ep2 for the net service; it cannot be combined with timer_service / stress_ipc");
+
+#[cfg(feature = "shm_demo")]
+compile_error!("virtio_net (net_loopback demo) cannot be combined with shm_demo (both drive Task1)");
+
+use super::abi::{
+    NET_OP_BIND, NET_OP_SEND, NET_RX_TAG, NET_SVC_ERR_BAD_EP, NET_SVC_ERR_BAD_LEN, NET_SVC_ERR_BAD_OP,
+    NET_SVC_ERR_BAD_SHM, NET_SVC_ERR_BUSY, NET_SVC_ERR_NO_DEVICE, NET_SVC_ERR_TX_FAILED, NET_SVC_OK,
+};
+use super::cspace::{CapIndex, CapRights, KernelObject, ObjectKind};
+use super::{EndpointId, IpcMessage, KernelState, ShmId, TaskState, MAX_ENDPOINTS};
+use crate::arch::drivers::virtio_net::{self, NetDeviceInfo, MAX_FRAME_LEN};
+use crate::arch::ops::{Arch, ArchOps};
+use crate::mem::addr::PhysFrame;
+
+const LOG: crate::logging::Subsystem = crate::logging::Subsystem::Ipc;
+
+/// service の endpoint（ここへの send は service が直接処理する）
+pub const NET_SERVICE_EP: EndpointId = EndpointId(1);
+
+/// Ethernet header（dst / src / ethertype）
+const ETH_HEADER_LEN: usize = 14;
+
+#[derive(Clone, Copy)]
+struct NetClient {
+    idx: usize,
+    notify_ep: EndpointId,
+    shm_id: ShmId,
+}
+
+pub struct NetService {
+    device: Option<NetDeviceInfo>,
+    client: Option<NetClient>,
+
+    // 集計（report 用）
+    pub binds: u64,
+    pub tx_frames: u64,
+    pub tx_bytes: u64,
+    pub tx_failed: u64,
+    pub rx_frames: u64,
+    pub rx_delivered: u64,
+    pub rx_dropped: u64,
+}
+
+impl NetService {
+    pub const fn new() -> Self {
+        NetService {
+            device: None,
+            client: None,
+            binds: 0,
+            tx_frames: 0,
+            tx_bytes: 0,
+            tx_failed: 0,
+            rx_frames: 0,
+            rx_delivered: 0,
+            rx_dropped: 0,
+        }
+    }
+
+    /// device を見つけて使える状態か（demo の milestone 用）
+    pub fn device_present(&self) -> bool {
+        self.device.is_some()
+    }
+
+    pub fn cancel(&mut self, idx: usize) {
+        if self.client.is_some_and(|c| c.idx == idx) {
+            self.client = None;
+        }
+    }
+}
+
+fn ethertype_of(frame: &[u8]) -> u64 {
+    if frame.len() < ETH_HEADER_LEN {
+        return 0;
+    }
+    u16::from_be_bytes([frame[12], frame[13]]) as u64
+}
+
+impl KernelState {
+    /// virtio-net を探して service を使える状態にする（entry.rs から。bootstrap の後・seal の前に 1 回）
+    pub(super) fn net_init(&mut self) {
+        self.net.device = virtio_net::init(&mut self.phys_mem);
+        match self.net.device {
+            Some(_) => LOG.info("net_service: ready"),
+            None => LOG.info("net_service: no device; every request gets NET_SVC_ERR_NO_DEVICE"),
+        }
+    }
+
+    /// segment の先頭 page（使える segment でなければ None）
+    fn net_shm_frame(&self, shm_id: ShmId) -> Option<PhysFrame> {
+        let seg = self.shm.get(shm_id.0)?;
+        if !seg.allocated {
+            return None;
+        }
+        seg.frames[0]
+    }
+
+    /// client の shm handle（MR の値）を ShmId に引く（MAP 権限のある Shm handle でなければ None）
+    fn net_client_shm(&self, client_idx: usize, handle: u64) -> Option<ShmId> {
+        match self.cspaces[client_idx].lookup(CapIndex(handle as usize), ObjectKind::Shm, CapRights::MAP) {
+            Ok(KernelObject::Shm(id)) => Some(id),
+            _ => None,
+        }
+    }
+
+    /// NET_SERVICE_EP への send を処理し、reply を返す
+    pub(super) fn net_service_handle_send(&mut self, client_idx: usize, msg: IpcMessage) -> IpcMessage {
+        let Some(dev) = self.net.device else {
+            return IpcMessage::word(NET_SVC_ERR_NO_DEVICE);
+        };
+        let task_id = self.tasks[client_idx].id.0;
+
+        match msg.mr0() {
+            NET_OP_BIND => {
+                let notify_ep = EndpointId(msg.mr(1) as usize);
+                let handle = msg.mr(2);
+
+                if notify_ep.0 >= MAX_ENDPOINTS || notify_ep == NET_SERVICE_EP || self.endpoints[notify_ep.0].is_closed {
+                    LOG.error("net_service: bad notify_ep");
+                    LOG.info_u64("task_id", task_id);
+                    LOG.info_u64("ep_id", notify_ep.0 as u64);
+                    return IpcMessage::word(NET_SVC_ERR_BAD_EP);
+                }
+                let Some(shm_id) = self.net_client_shm(client_idx, handle).filter(|&s| self.net_shm_frame(s).is_some())
+                else {
+                    LOG.error("net_service: bad shm handle");
+                    LOG.info_u64("task_id", task_id);
+                    LOG.info_u64("cap_index", handle);
+                    return IpcMessage::word(NET_SVC_ERR_BAD_SHM);
+                };
+                if let Some(c) = self.net.client {
+                    if c.idx != client_idx && self.tasks[c.idx].state != TaskState::Dead {
+                        LOG.error("net_service: another task is bound");
+                        LOG.info_u64("task_id", task_id);
+                        return IpcMessage::word(NET_SVC_ERR_BUSY);
+                    }
+                }
+
+                self.net.client = Some(NetClient { idx: client_idx, notify_ep, shm_id });
+                self.net.binds += 1;
+                LOG.info("net_service: bound");
+                LOG.info_u64("task_id", task_id);
+                LOG.info_u64("ep_id", notify_ep.0 as u64);
+                LOG.info_u64("shm_id", shm_id.0 as u64);
+
+                let mac = virtio_net::mac_to_u64(dev.mac);
+                IpcMessage::from_words(&[NET_SVC_OK, mac]).unwrap_or(IpcMessage::word(NET_SVC_OK))
+            }
+            NET_OP_SEND => {
+                let handle = msg.mr(1);
+                let len = msg.mr(2) as usize;
+
+                let Some(frame) = self.net_client_shm(client_idx, handle).and_then(|s| self.net_shm_frame(s)) else {
+                    LOG.error("net_service: bad shm handle");
+                    LOG.info_u64("task_id", task_id);
+                    LOG.info_u64("cap_index", handle);
+                    return IpcMessage::word(NET_SVC_ERR_BAD_SHM);
+                };
+                if !(ETH_HEADER_LEN..=MAX_FRAME_LEN).contains(&len) {
+                    LOG.error("net_service: bad frame length");
+                    LOG.info_u64("task_id", task_id);
+                    LOG.info_u64("len", len as u64);
+                    return IpcMessage::word(NET_SVC_ERR_BAD_LEN);
+                }
+
+                let mut buf = [0u8; MAX_FRAME_LEN];
+                unsafe { Arch::read_bytes_from_frame(frame, &mut buf[..len]) };
+                if !virtio_net::transmit(&buf[..len]) {
+                    self.net.tx_failed += 1;
+                    return IpcMessage::word(NET_SVC_ERR_TX_FAILED);
+                }
+                self.net.tx_frames += 1;
+                self.net.tx_bytes += len as u64;
+                LOG.info("net_service: frame sent");
+                LOG.info_u64("task_id", task_id);
+                LOG.info_u64("len", len as u64);
+                LOG.info_hex("ethertype", ethertype_of(&buf[..len]));
+                IpcMessage::word(NET_SVC_OK)
+            }
+            op => {
+                LOG.error("net_service: bad op");
+                LOG.info_u64("task_id", task_id);
+                LOG.info_u64("op", op);
+                IpcMessage::word(NET_SVC_ERR_BAD_OP)
+            }
+        }
+    }
+
+    /// time_ticks が進んだ直後に呼ぶ（UpdateTimer）
+    /// - client が recv 待ちなら 1 frame 渡す。client が居なければ届いた frame を捨てる
+    pub(super) fn net_poll(&mut self) {
+        if self.net.device.is_none() {
+            return;
+        }
+        let Some(c) = self.net.client else {
+            while virtio_net::receive_one(|_| ()).is_some() {
+                self.net.rx_frames += 1;
+                self.net.rx_dropped += 1;
+            }
+            return;
+        };
+
+        let waiting = c.idx < self.num_tasks
+            && self.endpoints[c.notify_ep.0].recv_waiter == Some(c.idx)
+            && self.tasks[c.idx].state == TaskState::Blocked
+            && self.tasks[c.idx].blocked_reason.is_some_and(|r| r.waits_recv_on(c.notify_ep));
+        if !waiting || !virtio_net::rx_pending() {
+            return;
+        }
+
+        let Some(msg) = self.net_take_frame(c) else {
+            return;
+        };
+        self.release_recv_waiter(c.idx);
+        self.tasks[c.idx].last_msg = Some(msg);
+        self.tasks[c.idx].last_recv_ep = Some(c.notify_ep);
+        self.tasks[c.idx].last_reply_cap = None;
+        self.wake_task_to_ready(c.idx);
+    }
+
+    /// used ring から frame を 1 つ取り出して shm の page に書き、通知 msg を作る
+    /// - 書けない（segment が消えた / 短すぎる）frame は捨てて次を見る
+    fn net_take_frame(&mut self, c: NetClient) -> Option<IpcMessage> {
+        loop {
+            let page = self.net_shm_frame(c.shm_id);
+            let (len, ethertype) = virtio_net::receive_one(|frame| match page {
+                Some(p) if frame.len() >= ETH_HEADER_LEN => {
+                    unsafe { Arch::write_bytes_to_frame(p, frame) };
+                    (frame.len(), ethertype_of(frame))
+                }
+                _ => (0, 0),
+            })?;
+            self.net.rx_frames += 1;
+            if len == 0 {
+                self.net.rx_dropped += 1;
+                continue;
+            }
+
+            self.net.rx_delivered += 1;
+            let seq = self.net.rx_delivered;
+            let tag = NET_RX_TAG | (seq & 0x0000_FFFF_FFFF_FFFF);
+            return Some(
+                IpcMessage::from_words(&[tag, len as u64, ethertype, self.net.rx_dropped])
+                    .unwrap_or(IpcMessage::word(tag)),
+            );
+        }
+    }
+
+    /// ipc_recv の入口で呼ぶ。client の notify_ep に届いている frame があれば block せずに受け取る。
+    pub(super) fn net_recv_pending(&mut self, idx: usize, ep: EndpointId) -> bool {
+        let Some(c) = self.net.client else {
+            return false;
+        };
+        if c.idx != idx || c.notify_ep != ep || !virtio_net::rx_pending() {
+            return false;
+        }
+        let Some(msg) = self.net_take_frame(c) else {
+            return false;
+        };
+        self.tasks[idx].last_msg = Some(msg);
+        self.tasks[idx].last_recv_ep = Some(ep);
+        self.tasks[idx].last_reply_cap = None;
+        true
+    }
+
+    pub(super) fn net_report(&self) {
+        let s = &self.net;
+        let irq = virtio_net::irq_stats();
+        LOG.info("=== Net Report ===");
+        LOG.info_u64("net_device", s.device.is_some() as u64);
+        if let Some(d) = s.device {
+            LOG.info_hex("net_mac", virtio_net::mac_to_u64(d.mac));
+            LOG.info_u64("net_irq_line", d.irq_line.map_or(u64::MAX, |l| l as u64));
+            LOG.info_u64("net_rx_queue_size", d.rx_queue_size as u64);
+            LOG.info_u64("net_tx_queue_size", d.tx_queue_size as u64);
+        }
+        LOG.info_u64("net_binds", s.binds);
+        LOG.info_u64("net_tx_frames", s.tx_frames);
+        LOG.info_u64("net_tx_bytes", s.tx_bytes);
+        LOG.info_u64("net_tx_failed", s.tx_failed);
+        LOG.info_u64("net_rx_frames", s.rx_frames);
+        LOG.info_u64("net_rx_delivered", s.rx_delivered);
+        LOG.info_u64("net_rx_dropped", s.rx_dropped);
+        LOG.info_u64("net_irqs", irq.irqs);
+        LOG.info_u64("net_queue_irqs", irq.queue_irqs);
+        LOG.info("=== End of Net Report ===");
+    }
+}
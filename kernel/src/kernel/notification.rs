@@ -0,0 +1,273 @@
+// kernel/src/kernel/notification.rs
+//
+// 役割:
+// - Notification（非同期通知）: seL4 の notification に倣った bitmask 1 word のカーネルオブジェクト。
+// - NotifySignal / NotifyWait syscall。notification は handle（cspace.rs の handle table、Signal / Wait 権限）で指す
+//   * 初期 handle は NTFN_CAP_BASE + n → NotificationId(n)。引けなければ syscall 境界で
+//     SYSCALL_ERR_BAD_CAP / SYSCALL_ERR_WRONG_TYPE / SYSCALL_ERR_FORBIDDEN（ここには来ない）
+//
+// 意味論:
+// - NotifySignal { ntfn, bits }:
+//   * waiter が居れば先頭（FIFO）に bits をそのまま渡して起こす（word は 0 のまま）
+//   * 居なければ word |= bits（複数回の signal は OR で畳み込まれる）
+//   * signal 側は決して block しない（rendezvous IPC との違い）
+// - NotifyWait { ntfn }:
+//   * word != 0 なら即座に word を受け取り 0 に戻す（block しない）
+//   * word == 0 なら Blocked(NotifyWait{ntfn}) で waiter の末尾に並ぶ
+//
+// 受け取り:
+// - 受け取った bits は Task.last_notify、syscall 自体の結果は last_syscall_ret（abi.rs が正本）
+//   * NotifySignal: SYSCALL_OK / SYSCALL_ERR_BAD_NOTIFICATION
+//   * NotifyWait:   SYSCALL_OK（bits は last_notify）/ SYSCALL_ERR_BAD_NOTIFICATION /
+//                   SYSCALL_ERR_FORBIDDEN（kernel task は block できない）/ SYSCALL_ERR_CAPACITY
+//   * block した場合は起こされた時点で last_notify と SYSCALL_OK が入る
+//
+// 設計方針:
+// - kernel task は signal してよい（割り込み相当の通知元）が wait はできない
+// - bits == 0 の signal は何もしない（waiter を 0 で起こさない）
+// - waiter 列は FIFO（ready_queue と同じく順序を保って取り出す）
+// - kill / TaskExit では teardown_task が waiter 列から外す（通知は失われない: word は触らない）
+// - Suspended の waiter にもそのまま渡す（bits は last_notify に残り、TaskResume で Ready に戻る。suspend.rs）
+//   waiter が居る間 word を 0 に保つ（INV-NTFN-002）ため、IPC の recv のように後回しにはしない
+//
+// やらないこと:
+// - badge
+// - endpoint への bind（recv 中の task に通知を届ける seL4 の bound notification）
+// - NotifyWait からの priority inheritance（誰が signal するかは決まっていない）
+
+use super::abi::{SYSCALL_ERR_BAD_NOTIFICATION, SYSCALL_ERR_CAPACITY, SYSCALL_ERR_FORBIDDEN, SYSCALL_OK};
+use super::{AddressSpaceKind, BlockedReason, InvariantId, KernelState, LogEvent, NotificationId, MAX_TASKS};
+use spec_macros::spec;
+
+const LOG: crate::logging::Subsystem = crate::logging::Subsystem::Ipc;
+
+/// Notification（bitmask 1 word + waiter 列）
+#[derive(Clone, Copy)]
+pub struct Notification {
+    pub id: NotificationId,
+
+    /// 未受信の bits（waiter が居る間は常に 0）
+    pub word: u64,
+
+    /// NotifyWait で block している task index（FIFO）
+    pub waiters: [usize; MAX_TASKS],
+    pub wq_len: usize,
+}
+
+impl Notification {
+    pub const fn new(id: NotificationId) -> Self {
+        Notification { id, word: 0, waiters: [0; MAX_TASKS], wq_len: 0 }
+    }
+
+    fn contains(&self, idx: usize) -> bool {
+        self.waiters[..self.wq_len].contains(&idx)
+    }
+
+    fn try_enqueue(&mut self, idx: usize) -> bool {
+        if self.contains(idx) {
+            return true;
+        }
+        if self.wq_len >= MAX_TASKS {
+            return false;
+        }
+        self.waiters[self.wq_len] = idx;
+        self.wq_len += 1;
+        true
+    }
+
+    /// 先頭を取り出す（順序を保つ）
+    fn dequeue_front(&mut self) -> Option<usize> {
+        if self.wq_len == 0 {
+            return None;
+        }
+        let idx = self.waiters[0];
+        self.waiters.copy_within(1..self.wq_len, 0);
+        self.wq_len -= 1;
+        Some(idx)
+    }
+
+    fn remove(&mut self, idx: usize) -> bool {
+        let Some(pos) = self.waiters[..self.wq_len].iter().position(|&w| w == idx) else {
+            return false;
+        };
+        self.waiters.copy_within(pos + 1..self.wq_len, pos);
+        self.wq_len -= 1;
+        true
+    }
+}
+
+impl KernelState {
+    fn notification_index(&self, ntfn: NotificationId) -> Option<usize> {
+        if ntfn.0 < self.notifications.len() {
+            Some(ntfn.0)
+        } else {
+            None
+        }
+    }
+
+    /// bits を task に渡す（last_notify + last_syscall_ret）
+    fn deliver_notification(&mut self, idx: usize, ntfn: NotificationId, bits: u64) {
+        let id = self.tasks[idx].id;
+        self.tasks[idx].last_notify = Some(bits);
+        self.tasks[idx].last_syscall_ret = Some(SYSCALL_OK);
+        self.tasks[idx].last_syscall_ret_unread = true;
+
+        self.counters.notify_delivered += 1;
+        self.push_event(LogEvent::NotifyDelivered { to: id, ntfn, bits });
+    }
+
+    pub(super) fn syscall_notify_signal(&mut self, idx: usize, ntfn: NotificationId, bits: u64) -> u64 {
+        let Some(n) = self.notification_index(ntfn) else {
+            LOG.error("notify_signal: notification out of range");
+            LOG.info_u64("ntfn_id", ntfn.0 as u64);
+            return SYSCALL_ERR_BAD_NOTIFICATION;
+        };
+
+        let tid = self.tasks[idx].id;
+        self.counters.notify_signals += 1;
+        self.push_event(LogEvent::NotifySignaled { task: tid, ntfn, bits });
+
+        if bits == 0 {
+            return SYSCALL_OK;
+        }
+
+        // 壊れた waiter（Dead / 理由不一致）は捨てて次へ
+        while let Some(w) = self.notifications[n].dequeue_front() {
+            let ok = w < self.num_tasks
+                && self.tasks[w].state.can_wait()
+                && self.tasks[w].blocked_reason == Some(BlockedReason::NotifyWait { ntfn });
+            if !ok {
+                LOG.error("notify_signal: stale waiter; drop");
+                LOG.info_u64("task_index", w as u64);
+                continue;
+            }
+
+            self.deliver_notification(w, ntfn, bits);
+            self.wake_task_to_ready(w);
+            return SYSCALL_OK;
+        }
+
+        self.notifications[n].word |= bits;
+        SYSCALL_OK
+    }
+
+    pub(super) fn syscall_notify_wait(&mut self, idx: usize, ntfn: NotificationId) -> Option<u64> {
+        let Some(n) = self.notification_index(ntfn) else {
+            LOG.error("notify_wait: notification out of range");
+            LOG.info_u64("ntfn_id", ntfn.0 as u64);
+            return Some(SYSCALL_ERR_BAD_NOTIFICATION);
+        };
+
+        // 既に bits があれば block しない
+        let word = self.notifications[n].word;
+        if word != 0 {
+            self.notifications[n].word = 0;
+            self.deliver_notification(idx, ntfn, word);
+            return None;
+        }
+
+        let as_idx = self.tasks[idx].address_space_id.0;
+        if as_idx < self.num_tasks && self.address_spaces[as_idx].kind == AddressSpaceKind::Kernel {
+            LOG.error("notify_wait: kernel task cannot block on notification");
+            return Some(SYSCALL_ERR_FORBIDDEN);
+        }
+
+        if !self.notifications[n].try_enqueue(idx) {
+            LOG.error("notify_wait: waiter queue full; reject");
+            return Some(SYSCALL_ERR_CAPACITY);
+        }
+
+        let tid = self.tasks[idx].id;
+        self.tasks[idx].last_notify = None;
+        self.block_task(idx, BlockedReason::NotifyWait { ntfn });
+
+        self.counters.notify_wait_blocked += 1;
+        self.push_event(LogEvent::NotifyWaitBlocked { task: tid, ntfn });
+
+        #[cfg(not(feature = "ring3_mailbox"))]
+        self.schedule_next_task();
+
+        None
+    }
+
+    /// kill / TaskExit: waiter 列から外す（word はそのまま残す）
+    pub(super) fn remove_task_from_notifications(&mut self, idx: usize) {
+        for n in self.notifications.iter_mut() {
+            let _ = n.remove(idx);
+        }
+    }
+
+    /// waiter 列と Blocked(NotifyWait) が双方向に一致し、waiter が居る間は word が 0
+    #[spec("INV-NTFN-001", "INV-NTFN-002")]
+    pub(super) fn check_notification_invariants(&self) {
+        for n in self.notifications.iter() {
+            if n.wq_len > 0 && n.word != 0 {
+                self.invariant_violated(
+                    InvariantId::NotificationWord,
+                    None,
+                    Some(n.id.0 as u64),
+                    "INVARIANT VIOLATION: notification has waiters and pending bits",
+                );
+                LOG.info_u64("ntfn_id", n.id.0 as u64);
+                LOG.info_u64("word", n.word);
+            }
+
+            for pos in 0..n.wq_len {
+                let w = n.waiters[pos];
+                if w >= self.num_tasks {
+                    self.invariant_violated(
+                        InvariantId::NotificationWaiters,
+                        None,
+                        Some(n.id.0 as u64),
+                        "INVARIANT VIOLATION: notification waiter idx out of range",
+                    );
+                    LOG.info_u64("ntfn_id", n.id.0 as u64);
+                    continue;
+                }
+                let t = &self.tasks[w];
+                if !t.state.can_wait() || t.blocked_reason != Some(BlockedReason::NotifyWait { ntfn: n.id }) {
+                    self.invariant_violated(
+                        InvariantId::NotificationWaiters,
+                        Some(t.id),
+                        Some(n.id.0 as u64),
+                        "INVARIANT VIOLATION: notification waiter is not Blocked(NotifyWait)",
+                    );
+                    LOG.info_u64("ntfn_id", n.id.0 as u64);
+                    LOG.info_u64("task_id", t.id.0);
+                }
+                if n.waiters[..pos].contains(&w) {
+                    self.invariant_violated(
+                        InvariantId::NotificationWaiters,
+                        Some(t.id),
+                        Some(n.id.0 as u64),
+                        "INVARIANT VIOLATION: notification waiter duplicated",
+                    );
+                    LOG.info_u64("task_id", t.id.0);
+                }
+            }
+        }
+
+        for (idx, t) in self.tasks.iter().enumerate().take(self.num_tasks) {
+            if !t.state.can_wait() {
+                continue;
+            }
+            let Some(BlockedReason::NotifyWait { ntfn }) = t.blocked_reason else {
+                continue;
+            };
+            let registered = match self.notification_index(ntfn) {
+                Some(n) => self.notifications[n].contains(idx),
+                None => false,
+            };
+            if !registered {
+                self.invariant_violated(
+                    InvariantId::NotificationWaiters,
+                    Some(t.id),
+                    Some(ntfn.0 as u64),
+                    "INVARIANT VIOLATION: NotifyWait task not in notification waiters",
+                );
+                LOG.info_u64("task_id", t.id.0);
+                LOG.info_u64("ntfn_id", ntfn.0 as u64);
+            }
+        }
+    }
+}
@@ -0,0 +1,170 @@
+// kernel/src/kernel/priority.rs
+//
+// 役割:
+// - IPC の block に沿った priority inheritance（優先度継承）。
+// - 高優先度 task が低優先度の server を待っている間、server が待ち手の優先度で走れるようにする
+//   （中優先度 task に割り込まれて、高優先度 task が間接的に待たされる priority inversion を防ぐ）。
+//
+// 継承元 → 継承先（donee）:
+// - Blocked(IpcReply{partner})  → partner（deliver 済み。reply を返すまで server が責任を持つ）
+// - Blocked(IpcSend{ep})        → ep の owner（owner が居る endpoint だけ。受け手がまだ決まっていないため）
+//
+// 設計方針:
+// - Task.base_priority が本来の値、Task.priority が実効値（scheduler は priority だけを見る）
+// - 差分で boost/restore せず、block / wake / kill のたびに全 task を再計算する
+//   → 経路（rescue / close / kill）ごとの restore 漏れが起きない
+// - 実効値 = max(base, 自分を待つ task の実効値)。待ちの連鎖も MAX_TASKS 回の反復で伝播する
+// - 変化したときだけ PriorityInherited / PriorityRestored を出す
+//
+// やらないこと:
+// - Blocked(IpcRecv) / Sleep / NotifyWait からの継承（待っている相手が特定できない）
+
+use super::{BlockedReason, InvariantId, KernelState, LogEvent, TaskId, TaskState, MAX_ENDPOINTS, MAX_TASKS};
+use spec_macros::spec;
+
+const LOG: crate::logging::Subsystem = crate::logging::Subsystem::Sched;
+
+impl KernelState {
+    /// idx の task が待っている相手（継承先）の task index
+    /// - IPC の wait-for graph の辺でもある（deadlock.rs が閉路を探す）
+    pub(super) fn ipc_waits_for(&self, idx: usize) -> Option<usize> {
+        let t = &self.tasks[idx];
+        if t.state != TaskState::Blocked {
+            return None;
+        }
+
+        let donee_id = match t.blocked_reason {
+            Some(BlockedReason::IpcReply { partner, .. }) => partner,
+            Some(BlockedReason::IpcSend { ep }) if ep.0 < MAX_ENDPOINTS => self.endpoints[ep.0].owner?,
+            _ => return None,
+        };
+
+        let donee = self.task_index_of(donee_id)?;
+        if donee == idx || self.tasks[donee].state == TaskState::Dead {
+            return None;
+        }
+        Some(donee)
+    }
+
+    pub(super) fn task_index_of(&self, id: TaskId) -> Option<usize> {
+        (0..self.num_tasks).find(|&i| self.tasks[i].id == id)
+    }
+
+    /// 実効優先度を計算する（base と待ち手の最大、連鎖は反復で伝播）
+    /// 戻り値: (実効優先度, 最も高い優先度をくれた待ち手)
+    fn compute_effective_priorities(&self) -> ([u8; MAX_TASKS], [Option<TaskId>; MAX_TASKS]) {
+        let mut prio = [0u8; MAX_TASKS];
+        let mut from: [Option<TaskId>; MAX_TASKS] = [None; MAX_TASKS];
+
+        for i in 0..self.num_tasks {
+            prio[i] = self.tasks[i].base_priority;
+        }
+
+        // 連鎖の長さは高々 num_tasks - 1
+        for _ in 0..self.num_tasks {
+            let mut changed = false;
+            for waiter in 0..self.num_tasks {
+                let Some(donee) = self.ipc_waits_for(waiter) else {
+                    continue;
+                };
+                if prio[waiter] > prio[donee] {
+                    prio[donee] = prio[waiter];
+                    // 連鎖の場合も「直接待っている task」を記録する
+                    from[donee] = Some(self.tasks[waiter].id);
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        // Dead は常に base（継承を持ち越さない）
+        for i in 0..self.num_tasks {
+            if self.tasks[i].state == TaskState::Dead {
+                prio[i] = self.tasks[i].base_priority;
+                from[i] = None;
+            }
+        }
+
+        (prio, from)
+    }
+
+    /// block / wake / kill の後に呼ぶ。実効優先度を付け直し、変化を記録する。
+    pub(super) fn refresh_priority_inheritance(&mut self) {
+        let (prio, from) = self.compute_effective_priorities();
+
+        for i in 0..self.num_tasks {
+            let old = self.tasks[i].priority;
+            let new = prio[i];
+            let id = self.tasks[i].id;
+
+            if new == old && from[i] == self.tasks[i].inherited_from {
+                continue;
+            }
+
+            self.tasks[i].priority = new;
+            self.tasks[i].inherited_from = from[i];
+
+            match from[i] {
+                Some(donor) => {
+                    if new == old {
+                        // 継承元が入れ替わっただけ（値は同じ）
+                        continue;
+                    }
+                    self.counters.prio_inherited += 1;
+                    self.push_event(LogEvent::PriorityInherited { task: id, from: donor, priority: new });
+                }
+                None => {
+                    self.counters.prio_restored += 1;
+                    self.push_event(LogEvent::PriorityRestored { task: id, priority: new });
+                }
+            }
+        }
+    }
+
+    /// 実効優先度が常に「base と待ち手から再計算した値」と一致すること
+    /// - 継承元が居なくなったのに boost が残る（restore 漏れ）を検知する
+    #[spec("INV-SCHED-003")]
+    pub(super) fn check_priority_inheritance_invariants(&self) {
+        let (prio, from) = self.compute_effective_priorities();
+
+        for i in 0..self.num_tasks {
+            let t = &self.tasks[i];
+
+            if t.priority != prio[i] {
+                self.invariant_violated(
+                    InvariantId::PriorityInheritance,
+                    Some(t.id),
+                    Some(prio[i] as u64),
+                    "INVARIANT VIOLATION: effective priority differs from inheritance",
+                );
+                LOG.info_u64("task_id", t.id.0);
+                LOG.info_u64("priority", t.priority as u64);
+                LOG.info_u64("expected", prio[i] as u64);
+            }
+
+            if t.priority < t.base_priority {
+                self.invariant_violated(
+                    InvariantId::PriorityInheritance,
+                    Some(t.id),
+                    Some(t.base_priority as u64),
+                    "INVARIANT VIOLATION: effective priority below base_priority",
+                );
+                LOG.info_u64("task_id", t.id.0);
+            }
+
+            if from[i].is_none() && t.priority != t.base_priority {
+                self.invariant_violated(
+                    InvariantId::PriorityInheritance,
+                    Some(t.id),
+                    Some(t.base_priority as u64),
+                    "INVARIANT VIOLATION: inherited priority not restored",
+                );
+                LOG.info_u64("task_id", t.id.0);
+                LOG.info_u64("priority", t.priority as u64);
+                LOG.info_u64("base_priority", t.base_priority as u64);
+            }
+        }
+    }
+}
@@ -0,0 +1,437 @@
+// kernel/src/kernel/refinement.rs
+//
+// 役割:
+// - refinement trace（feature refinement_trace）: 抽象状態を変えた遷移ごとに、1 つの transition record
+//   （遷移の名前、起こした task、変わった field の pre / post）を出す。spec 側の遷移と 1 対 1 に突き合わせて
+//   refinement を確かめる（散らばった info 行を拾い集めなくてよい）。
+//
+// 抽象状態（state_hash.rs の正準な直列化と同じ範囲。slot 番号で引く）:
+// - n: num_tasks、c: current_task の slot、rq / wq: ready_queue / wait_queue（先頭から slot 番号を並べた列）
+// - t<i>.id / .s / .p / .bp / .b / .a / .q: TaskId / state / 実効 priority / base_priority / blocked の code / arg / partner
+//   * state / blocked の code は abi の STATE_* / BLOCKED_*（state_hash と同じ）
+// - e<n>.f / .o / .w / .s / .r / .k / .b: flags（bit0 = allocated、bit1 = closed）/ owner / recv_waiter の slot /
+//   send_queue / reply_queue / buffer の slot 数 / buffer の長さ
+// - a<i>.m / .g / .w: AddressSpace の mapping 数（resident）/ region 数 / swap out 中の page 数
+// - mf: 配れる空きフレームの数（PhysicalMemoryManager::usage の free。MemInfo と同じ値）
+//
+// 遷移の区切り（refinement_boundary）:
+// - syscall: handle_syscall の前後。前で「それまでに kernel がした分」を tick として出し、後で syscall の名前で出す
+//   * syscall は何も変えなくても出す（stuttering step も spec の遷移として数える）
+// - tick: tick の末尾（state_hash の前）。変わった field が無ければ出さない
+// - rollback: checkpoint へ巻き戻した直後（checkpoint.rs）
+// - init: bootstrap の最後に 1 回。基準（何も無い task 表、開いた空の endpoint）からの差として全体を出す
+//
+// 符号化（1 record = 1 行。長ければ `tr+ <seq>` の継続行に分ける）:
+// - `tr <seq> <tick> <op> <task_id> <key>=<pre>><post> ...`（init は `<key>=<post>`）
+// - 値は 0x なしの 16 進。None は `-`、slot の列は slot 番号を並べた文字列（空なら `-`）
+// - 変わった field だけを出す（全体は init から積み上げて復元する）
+//
+// やらないこと:
+// - cspace / reply object / 時刻などの写し（spec の抽象状態に入れていない。state_hash と同じ線引き）
+// - wire / record 形式での出力（serial のテキスト 1 行だけ）
+
+use super::{KernelState, TaskId};
+
+#[cfg(feature = "refinement_trace")]
+use super::state_hash::{blocked_words, state_code};
+#[cfg(feature = "refinement_trace")]
+use super::{abi::WIRE_NONE, MAX_ENDPOINTS, MAX_TASKS};
+#[cfg(feature = "refinement_trace")]
+use crate::logging;
+
+/// record の形式の版（init の record に v= で出す）
+#[cfg(feature = "refinement_trace")]
+const REFINEMENT_TRACE_VERSION: u64 = 2;
+
+/// 1 行の最大長（超えたら継続行へ）
+#[cfg(feature = "refinement_trace")]
+const LINE_MAX: usize = 160;
+
+/// slot 番号の列（queue / recv_waiter）
+#[cfg(feature = "refinement_trace")]
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Slots {
+    idx: [u8; MAX_TASKS],
+    len: usize,
+}
+
+#[cfg(feature = "refinement_trace")]
+impl Slots {
+    const EMPTY: Slots = Slots { idx: [0; MAX_TASKS], len: 0 };
+
+    fn from_iter(it: impl Iterator<Item = usize>) -> Self {
+        let mut s = Slots::EMPTY;
+        for i in it.take(MAX_TASKS) {
+            s.idx[s.len] = i as u8;
+            s.len += 1;
+        }
+        s
+    }
+}
+
+#[cfg(feature = "refinement_trace")]
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct TaskAbs {
+    id: u64,
+    state: u64,
+    prio: u64,
+    base_prio: u64,
+    blocked: u64,
+    arg: u64,
+    partner: u64,
+}
+
+#[cfg(feature = "refinement_trace")]
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct EpAbs {
+    flags: u64,
+    owner: u64,
+    waiter: Slots,
+    send: Slots,
+    reply: Slots,
+    buf_cap: u64,
+    buf_len: u64,
+}
+
+#[cfg(feature = "refinement_trace")]
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct AsAbs {
+    mappings: u64,
+    regions: u64,
+    swapped: u64,
+}
+
+/// 抽象状態の写し（前の区切りの時点）
+#[cfg(feature = "refinement_trace")]
+#[derive(Clone, Copy)]
+struct Snapshot {
+    num_tasks: u64,
+    current: u64,
+    rq: Slots,
+    wq: Slots,
+    tasks: [TaskAbs; MAX_TASKS],
+    eps: [EpAbs; MAX_ENDPOINTS],
+    aspaces: [AsAbs; MAX_TASKS],
+    free_frames: u64,
+}
+
+#[cfg(feature = "refinement_trace")]
+impl Snapshot {
+    /// init の基準: task は無く、endpoint は開いていて空
+    const BASELINE: Snapshot = Snapshot {
+        num_tasks: 0,
+        current: 0,
+        rq: Slots::EMPTY,
+        wq: Slots::EMPTY,
+        tasks: [TaskAbs { id: 0, state: 0, prio: 0, base_prio: 0, blocked: 0, arg: 0, partner: 0 }; MAX_TASKS],
+        eps: [EpAbs {
+            flags: 1,
+            owner: WIRE_NONE,
+            waiter: Slots::EMPTY,
+            send: Slots::EMPTY,
+            reply: Slots::EMPTY,
+            buf_cap: 0,
+            buf_len: 0,
+        }; MAX_ENDPOINTS],
+        aspaces: [AsAbs { mappings: 0, regions: 0, swapped: 0 }; MAX_TASKS],
+        free_frames: 0,
+    };
+}
+
+/// KernelState に持たせる trace の状態
+#[cfg(feature = "refinement_trace")]
+pub(super) struct RefinementTrace {
+    last: Option<Snapshot>,
+    seq: u64,
+}
+
+#[cfg(feature = "refinement_trace")]
+impl RefinementTrace {
+    pub(super) const fn new() -> Self {
+        RefinementTrace { last: None, seq: 0 }
+    }
+}
+
+/// 1 行分の buffer（溢れる field の前で出して継続行にする）
+#[cfg(feature = "refinement_trace")]
+struct Line {
+    buf: [u8; LINE_MAX],
+    n: usize,
+    seq: u64,
+}
+
+#[cfg(feature = "refinement_trace")]
+impl Line {
+    fn push(&mut self, s: &[u8]) {
+        let k = s.len().min(LINE_MAX - self.n);
+        self.buf[self.n..self.n + k].copy_from_slice(&s[..k]);
+        self.n += k;
+    }
+
+    fn push_dec(&mut self, v: u64) {
+        let mut b = [0u8; 21];
+        self.push(crate::logging::u64_to_decimal(v, &mut b).as_bytes());
+    }
+
+    fn flush(&mut self) {
+        // buffer には ASCII しか入れない
+        if let Ok(s) = core::str::from_utf8(&self.buf[..self.n]) {
+            logging::info(s);
+        }
+        self.n = 0;
+    }
+
+    /// field を 1 つ足す（入らなければ今の行を出して `tr+ <seq>` から続ける）
+    fn field(&mut self, tok: &Token) {
+        if self.n + 1 + tok.n > LINE_MAX {
+            self.flush();
+            self.push(b"tr+ ");
+            self.push_dec(self.seq);
+        }
+        self.push(b" ");
+        self.push(&tok.buf[..tok.n]);
+    }
+}
+
+/// `<key>=<pre>><post>` 1 つ分
+#[cfg(feature = "refinement_trace")]
+struct Token {
+    buf: [u8; 48],
+    n: usize,
+}
+
+#[cfg(feature = "refinement_trace")]
+impl Token {
+    fn key(prefix: &str, index: Option<usize>, name: &str) -> Self {
+        let mut t = Token { buf: [0; 48], n: 0 };
+        t.push(prefix.as_bytes());
+        if let Some(i) = index {
+            let mut b = [0u8; 21];
+            t.push(crate::logging::u64_to_decimal(i as u64, &mut b).as_bytes());
+            t.push(b".");
+        }
+        t.push(name.as_bytes());
+        t.push(b"=");
+        t
+    }
+
+    fn push(&mut self, s: &[u8]) {
+        let k = s.len().min(self.buf.len() - self.n);
+        self.buf[self.n..self.n + k].copy_from_slice(&s[..k]);
+        self.n += k;
+    }
+
+    fn word(&mut self, v: u64) {
+        if v == WIRE_NONE {
+            self.push(b"-");
+            return;
+        }
+        const HEX: &[u8; 16] = b"0123456789abcdef";
+        let mut b = [0u8; 16];
+        let mut i = b.len();
+        let mut x = v;
+        loop {
+            i -= 1;
+            b[i] = HEX[(x & 0xF) as usize];
+            x >>= 4;
+            if x == 0 {
+                break;
+            }
+        }
+        self.push(&b[i..]);
+    }
+
+    fn slots(&mut self, s: &Slots) {
+        if s.len == 0 {
+            self.push(b"-");
+            return;
+        }
+        for &i in &s.idx[..s.len] {
+            self.word(i as u64);
+        }
+    }
+}
+
+/// field の値（比べて違えば出す）
+#[cfg(feature = "refinement_trace")]
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Val {
+    Word(u64),
+    Slots(Slots),
+}
+
+/// 変わった field を line に足す（pre = None なら init の `<key>=<post>`）。足した数
+#[cfg(feature = "refinement_trace")]
+struct Delta<'a> {
+    line: &'a mut Line,
+    with_pre: bool,
+    changed: u64,
+}
+
+#[cfg(feature = "refinement_trace")]
+impl Delta<'_> {
+    fn put(&mut self, prefix: &str, index: Option<usize>, name: &str, pre: Val, post: Val) {
+        if pre == post {
+            return;
+        }
+        let mut tok = Token::key(prefix, index, name);
+        let val = |tok: &mut Token, v: Val| match v {
+            Val::Word(w) => tok.word(w),
+            Val::Slots(s) => tok.slots(&s),
+        };
+        if self.with_pre {
+            val(&mut tok, pre);
+            tok.push(b">");
+        }
+        val(&mut tok, post);
+        self.line.field(&tok);
+        self.changed += 1;
+    }
+
+    fn diff(&mut self, pre: &Snapshot, post: &Snapshot) {
+        use Val::{Slots as S, Word as W};
+        self.put("n", None, "", W(pre.num_tasks), W(post.num_tasks));
+        self.put("c", None, "", W(pre.current), W(post.current));
+        self.put("rq", None, "", S(pre.rq), S(post.rq));
+        self.put("wq", None, "", S(pre.wq), S(post.wq));
+
+        for (i, (a, b)) in pre.tasks.iter().zip(post.tasks.iter()).enumerate() {
+            if a == b {
+                continue;
+            }
+            self.put("t", Some(i), "id", W(a.id), W(b.id));
+            self.put("t", Some(i), "s", W(a.state), W(b.state));
+            self.put("t", Some(i), "p", W(a.prio), W(b.prio));
+            self.put("t", Some(i), "bp", W(a.base_prio), W(b.base_prio));
+            self.put("t", Some(i), "b", W(a.blocked), W(b.blocked));
+            self.put("t", Some(i), "a", W(a.arg), W(b.arg));
+            self.put("t", Some(i), "q", W(a.partner), W(b.partner));
+        }
+
+        for (n, (a, b)) in pre.eps.iter().zip(post.eps.iter()).enumerate() {
+            if a == b {
+                continue;
+            }
+            self.put("e", Some(n), "f", W(a.flags), W(b.flags));
+            self.put("e", Some(n), "o", W(a.owner), W(b.owner));
+            self.put("e", Some(n), "w", S(a.waiter), S(b.waiter));
+            self.put("e", Some(n), "s", S(a.send), S(b.send));
+            self.put("e", Some(n), "r", S(a.reply), S(b.reply));
+            self.put("e", Some(n), "k", W(a.buf_cap), W(b.buf_cap));
+            self.put("e", Some(n), "b", W(a.buf_len), W(b.buf_len));
+        }
+
+        for (i, (a, b)) in pre.aspaces.iter().zip(post.aspaces.iter()).enumerate() {
+            self.put("a", Some(i), "m", W(a.mappings), W(b.mappings));
+            self.put("a", Some(i), "g", W(a.regions), W(b.regions));
+            self.put("a", Some(i), "w", W(a.swapped), W(b.swapped));
+        }
+
+        self.put("mf", None, "", W(pre.free_frames), W(post.free_frames));
+    }
+}
+
+impl KernelState {
+    #[cfg(feature = "refinement_trace")]
+    fn refinement_snapshot(&self) -> Snapshot {
+        let mut s = Snapshot::BASELINE;
+        s.num_tasks = self.num_tasks as u64;
+        s.current = self.current_task as u64;
+        s.rq = Slots::from_iter(self.ready_queue[..self.rq_len].iter().copied());
+        s.wq = Slots::from_iter(self.wait_queue[..self.wq_len].iter().copied());
+
+        for (t, abs) in self.tasks.iter().zip(s.tasks.iter_mut()).take(self.num_tasks) {
+            let (blocked, arg, partner) = blocked_words(t.blocked_reason);
+            *abs = TaskAbs {
+                id: t.id.0,
+                state: state_code(t.state),
+                prio: t.priority as u64,
+                base_prio: t.base_priority as u64,
+                blocked,
+                arg,
+                partner,
+            };
+        }
+
+        for (e, abs) in self.endpoints.iter().zip(s.eps.iter_mut()) {
+            *abs = EpAbs {
+                flags: e.allocated as u64 | (e.is_closed as u64) << 1,
+                owner: e.owner.map_or(WIRE_NONE, |t| t.0),
+                waiter: Slots::from_iter(e.recv_waiter.into_iter()),
+                send: Slots::from_iter(e.send_queue.iter()),
+                reply: Slots::from_iter(e.reply_queue.iter()),
+                buf_cap: e.buffer.capacity() as u64,
+                buf_len: e.buffer.len() as u64,
+            };
+        }
+
+        for (a, abs) in self.address_spaces.iter().zip(s.aspaces.iter_mut()).take(self.num_tasks) {
+            *abs = AsAbs {
+                mappings: a.mapping_count() as u64,
+                regions: a.region_count() as u64,
+                swapped: a.swapped_count() as u64,
+            };
+        }
+        s.free_frames = self.phys_mem.usage().free;
+        s
+    }
+
+    /// 遷移の区切り: 前の区切りから変わった field を op の record にして出す
+    /// - always: 何も変わっていなくても出す（syscall）
+    pub(super) fn refinement_boundary(&mut self, op: &str, task: Option<TaskId>, always: bool) {
+        #[cfg(feature = "refinement_trace")]
+        {
+            let post = self.refinement_snapshot();
+            let Some(pre) = self.refinement.last else {
+                // init の前（bootstrap 中）は出さない
+                return;
+            };
+            self.refinement.last = Some(post);
+            self.refinement_emit(op, task, &pre, &post, true, always);
+        }
+        #[cfg(not(feature = "refinement_trace"))]
+        {
+            let _ = (op, task, always);
+        }
+    }
+
+    /// bootstrap の最後: 基準からの差として全体を init の record で出し、以後の差の起点にする
+    pub(super) fn refinement_init(&mut self) {
+        #[cfg(feature = "refinement_trace")]
+        {
+            let post = self.refinement_snapshot();
+            self.refinement.last = Some(post);
+            self.refinement.seq = 0;
+            self.refinement_emit("init", None, &Snapshot::BASELINE, &post, false, true);
+        }
+    }
+
+    #[cfg(feature = "refinement_trace")]
+    fn refinement_emit(&mut self, op: &str, task: Option<TaskId>, pre: &Snapshot, post: &Snapshot, with_pre: bool, always: bool) {
+        let seq = self.refinement.seq;
+        let mut line = Line { buf: [0; LINE_MAX], n: 0, seq };
+        line.push(b"tr ");
+        line.push_dec(seq);
+        line.push(b" ");
+        line.push_dec(self.tick_count);
+        line.push(b" ");
+        line.push(op.as_bytes());
+        line.push(b" ");
+        match task {
+            Some(t) => line.push_dec(t.0),
+            None => line.push(b"-"),
+        }
+        if !with_pre {
+            line.push(b" v=");
+            line.push_dec(REFINEMENT_TRACE_VERSION);
+        }
+
+        let mut d = Delta { line: &mut line, with_pre, changed: 0 };
+        d.diff(pre, post);
+        if d.changed == 0 && !always {
+            return;
+        }
+        line.flush();
+        self.refinement.seq += 1;
+    }
+}
@@ -0,0 +1,187 @@
+// kernel/src/kernel/replay.rs
+//
+// 役割:
+// - 決定的 replay: 組み込みの user_program / mem_demo の代わりに、台本（ReplayStep の const 表）で
+//   KernelState を駆動する。同じ台本は同じ trace になる（形式モデル側との突き合わせ用）。
+//
+// 台本の 1 手:
+// - Syscall: task の pending_syscall に積む（その task が次に走った tick で実行される）
+// - Reply:   task が recv で受け取った reply cap（Task.last_reply_cap）で IpcReply を積む
+//   * reply cap は実行時にしか決まらないので、台本には値ではなく「直前の recv の reply cap」と書く
+// - Tick:    tick() を n 回回す（timer IRQ ではなく同期ループ）
+// - Kill:    task を DemoInjected で kill する（fault injection。正規の kill 経路を通す）
+//
+// 方針:
+// - feature = replay のときだけ有効。台本は feature で選ぶ（replay_kill_server / replay_ipc_timeout）
+// - 実行できない手（範囲外 / Dead / Blocked / syscall が既に積まれている）は状態を変えずに飛ばし、
+//   "replay: step rejected" と件数で残す（台本と trace のずれを隠さない）
+// - event は新設しない（trace は通常の Event Log / Record Dump で見る）
+//
+// やらないこと:
+// - serial 等からの台本の読み込み（const 表のみ）
+// - trace の自動比較（外部ツールの役割）
+
+#[cfg(feature = "replay")]
+use super::{
+    IpcMessage, KernelState, Syscall, TaskKillReason, TaskState, IDLE_TASK_INDEX, IPC_DEMO_CAP0, TASK0_INDEX,
+    TASK1_INDEX, TASK2_INDEX,
+};
+#[cfg(feature = "replay")]
+use crate::logging;
+
+/// 台本の 1 手（task は task index）
+#[cfg(feature = "replay")]
+#[derive(Clone, Copy)]
+pub enum ReplayStep {
+    Syscall { task: usize, call: fn() -> Syscall },
+    Reply { task: usize, msg: u64 },
+    Tick(u64),
+    Kill { task: usize, code: u64 },
+}
+
+/// client → server の 1 往復（recv → send → reply）
+#[cfg(all(feature = "replay", not(any(feature = "replay_kill_server", feature = "replay_ipc_timeout"))))]
+const SCRIPT_IPC_ROUNDTRIP: &[ReplayStep] = &[
+    ReplayStep::Syscall { task: TASK2_INDEX, call: || Syscall::IpcRecv { cap: IPC_DEMO_CAP0 } },
+    ReplayStep::Tick(4),
+    ReplayStep::Syscall {
+        task: TASK1_INDEX,
+        call: || Syscall::IpcSend { cap: IPC_DEMO_CAP0, msg: IpcMessage::word(0x5EED_0000_0000_0001), timeout: None },
+    },
+    ReplayStep::Tick(4),
+    ReplayStep::Reply { task: TASK2_INDEX, msg: 0xABCD_0000_0000_0001 },
+    ReplayStep::Tick(8),
+];
+
+/// client が reply を待っている間に server を kill する（dead partner の rescue）
+#[cfg(feature = "replay_kill_server")]
+const SCRIPT_KILL_SERVER: &[ReplayStep] = &[
+    ReplayStep::Syscall { task: TASK2_INDEX, call: || Syscall::IpcRecv { cap: IPC_DEMO_CAP0 } },
+    ReplayStep::Tick(4),
+    ReplayStep::Syscall {
+        task: TASK1_INDEX,
+        call: || Syscall::IpcSend { cap: IPC_DEMO_CAP0, msg: IpcMessage::word(0x5EED_0000_0000_0002), timeout: None },
+    },
+    ReplayStep::Tick(4),
+    ReplayStep::Kill { task: TASK2_INDEX, code: 0x5EED_D1E0 },
+    ReplayStep::Tick(8),
+];
+
+/// server が recv しないまま client の send が timeout する（IPC_ERR_TIMEOUT で起こされる）
+#[cfg(all(feature = "replay_ipc_timeout", not(feature = "replay_kill_server")))]
+const SCRIPT_IPC_TIMEOUT: &[ReplayStep] = &[
+    ReplayStep::Syscall {
+        task: TASK1_INDEX,
+        call: || Syscall::IpcSend { cap: IPC_DEMO_CAP0, msg: IpcMessage::word(0x5EED_0000_0000_0003), timeout: Some(3) },
+    },
+    ReplayStep::Tick(8),
+];
+
+#[cfg(all(feature = "replay", not(any(feature = "replay_kill_server", feature = "replay_ipc_timeout"))))]
+const SCRIPT: (&str, &[ReplayStep]) = ("ipc_roundtrip", SCRIPT_IPC_ROUNDTRIP);
+#[cfg(feature = "replay_kill_server")]
+const SCRIPT: (&str, &[ReplayStep]) = ("kill_server", SCRIPT_KILL_SERVER);
+#[cfg(all(feature = "replay_ipc_timeout", not(feature = "replay_kill_server")))]
+const SCRIPT: (&str, &[ReplayStep]) = ("ipc_timeout", SCRIPT_IPC_TIMEOUT);
+
+/// replay 中は user_program / mem_demo を止める（demo/mod.rs の hook が見る）
+pub fn is_active() -> bool {
+    cfg!(feature = "replay")
+}
+
+/// 台本を最後まで流す（kernel が halt を要求したらそこで止める）
+#[cfg(feature = "replay")]
+pub fn run(ks: &mut KernelState) {
+    let (name, script) = SCRIPT;
+
+    logging::info("replay: start");
+    logging::info(name);
+    logging::info_u64("replay_steps", script.len() as u64);
+
+    let mut rejected: u64 = 0;
+    let mut executed: u64 = 0;
+
+    for (i, step) in script.iter().enumerate() {
+        if ks.should_halt() {
+            logging::info("replay: kernel requested halt; stop");
+            break;
+        }
+
+        logging::info_u64("replay_step", i as u64);
+        let ok = match *step {
+            ReplayStep::Tick(n) => {
+                for _ in 0..n {
+                    if ks.should_halt() {
+                        break;
+                    }
+                    ks.tick();
+                }
+                true
+            }
+            ReplayStep::Syscall { task, call } => ks.replay_inject_syscall(task, call()),
+            ReplayStep::Reply { task, msg } => ks.replay_inject_reply(task, msg),
+            ReplayStep::Kill { task, code } => ks.replay_kill(task, code),
+        };
+
+        if ok {
+            executed += 1;
+        } else {
+            rejected += 1;
+            logging::error("replay: step rejected");
+            logging::info_u64("replay_step", i as u64);
+        }
+    }
+
+    logging::info("replay: done");
+    logging::info_u64("replay_executed", executed);
+    logging::info_u64("replay_rejected", rejected);
+}
+
+#[cfg(feature = "replay")]
+impl KernelState {
+    /// user_program と同じ条件（生きていて Blocked / Suspended でなく、未処理の syscall が無い）でだけ積む
+    fn replay_inject_syscall(&mut self, task: usize, call: Syscall) -> bool {
+        if task >= self.num_tasks {
+            return false;
+        }
+        let t = &self.tasks[task];
+        if t.state == TaskState::Dead || t.state.can_wait() || t.pending_syscall.is_some() {
+            logging::info_u64("replay_task_id", t.id.0);
+            return false;
+        }
+        self.tasks[task].pending_syscall = Some(call);
+        true
+    }
+
+    /// reply cap が無ければ（recv していない / reply 待ちの無い msg）積まない
+    fn replay_inject_reply(&mut self, task: usize, msg: u64) -> bool {
+        if task >= self.num_tasks {
+            return false;
+        }
+        let Some(reply_cap) = self.tasks[task].last_reply_cap else {
+            logging::info_u64("replay_task_id", self.tasks[task].id.0);
+            return false;
+        };
+        let ok = self.replay_inject_syscall(task, Syscall::IpcReply { reply_cap, msg: IpcMessage::word(msg) });
+        if ok {
+            self.tasks[task].last_reply_cap = None;
+        }
+        ok
+    }
+
+    /// kernel task / idle task は kill しない（kill 経路の対象外）
+    fn replay_kill(&mut self, task: usize, code: u64) -> bool {
+        if task >= self.num_tasks
+            || task == TASK0_INDEX
+            || task == IDLE_TASK_INDEX
+            || self.tasks[task].state == TaskState::Dead
+        {
+            return false;
+        }
+        logging::error("replay: kill task (DemoInjected)");
+        logging::info_u64("killed_task_id", self.tasks[task].id.0);
+        logging::info_u64("demo_code", code);
+        self.kill_task(task, TaskKillReason::DemoInjected { code });
+        true
+    }
+}
@@ -0,0 +1,171 @@
+// kernel/src/kernel/reply_cap.rs
+//
+// 役割:
+// - reply cap: deliver で client の reply 待ちが始まるたびに kernel が払い出す、一度きりの reply の権限。
+//   server は IpcReply { reply_cap, msg } で返す先の client を名指しする（reply_queue を partner で探さない）。
+//   1 つの server が複数の client の call を抱えていても、どれに返すかが曖昧にならない。
+//
+// 中身:
+// - ReplyCap は不透明な u64: 下位 REPLY_CAP_CLIENT_BITS bit = client の task index、上位 = 払い出し番号（seq）
+//   * seq は kernel 全体で一意（1 から。0 は無効な reply cap）。使い終わった / 古い reply cap は seq が合わずに弾かれる
+// - kernel 側の reply object（client の task index で引く）: { server, ep, seq }
+//   * client は一度に 1 つの call しか持たないので、client ごとに高々 1 つ
+//
+// 流れ:
+// - 払い出し: ipc_send_fastpath / ipc_recv_fastpath で sender を Blocked(IpcReply) にした直後。
+//   server の Task.last_reply_cap に入る（ring3 は IpcRecv / IpcRecvAny の rdx）
+// - 消費: IpcReply。server と seq が一致する reply object だけを取り出し、client を reply_queue から外して届ける
+// - 破棄: client の call が reply 以外で終わったとき（timeout / server timeout / close / dead partner / deadlock の切断）は
+//   wake_task_to_ready、client が死んだときは teardown_task が消す
+//
+// 戻り値:
+// - reply cap を引けなければ last_syscall_ret = SYSCALL_ERR_BAD_REPLY_CAP（状態は変えない）
+//   * 成功時は last_syscall_ret を書かない（結果は client の last_reply。mem 系の戻り値と混線させない）
+//
+// やらないこと:
+// - reply cap の移譲（handle table には入れず、cap transfer もできない。受け取った server だけが使う）
+// - server が reply せずに死んだときの回収（client は dead partner の救済で起こされ、その時点で消える）
+
+use super::{BlockedReason, EndpointId, InvariantId, KernelState, MAX_TASKS};
+use spec_macros::spec;
+
+const LOG: crate::logging::Subsystem = crate::logging::Subsystem::Ipc;
+
+/// ReplyCap の下位で client の task index を持つ bit 数
+const REPLY_CAP_CLIENT_BITS: u32 = 8;
+const _: () = assert!(MAX_TASKS <= 1 << REPLY_CAP_CLIENT_BITS);
+
+/// server に渡す一度きりの reply の権限（値は不透明。ヘッダの「中身」）
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ReplyCap(pub u64);
+
+impl ReplyCap {
+    const fn new(client: usize, seq: u64) -> Self {
+        ReplyCap((seq << REPLY_CAP_CLIENT_BITS) | client as u64)
+    }
+
+    const fn client(self) -> usize {
+        (self.0 & ((1 << REPLY_CAP_CLIENT_BITS) - 1)) as usize
+    }
+
+    const fn seq(self) -> u64 {
+        self.0 >> REPLY_CAP_CLIENT_BITS
+    }
+}
+
+/// kernel 側の reply object（client の task index で引く）
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct ReplyObject {
+    /// reply する権限を持つ task（task index）
+    server: usize,
+    ep: EndpointId,
+    seq: u64,
+}
+
+impl KernelState {
+    /// client が server の reply を ep で待ち始めた: reply cap を払い出して server に渡す
+    pub(super) fn reply_cap_mint(&mut self, client: usize, server: usize, ep: EndpointId) {
+        let seq = self.next_reply_seq;
+        self.next_reply_seq += 1;
+        self.reply_objects[client] = Some(ReplyObject { server, ep, seq });
+        self.tasks[server].last_reply_cap = Some(ReplyCap::new(client, seq));
+        self.counters.reply_caps_minted += 1;
+    }
+
+    /// IpcReply の reply cap を消費する（client の task index と ep。引けなければ None で、状態は変えない）
+    pub(super) fn reply_cap_take(&mut self, server: usize, cap: ReplyCap) -> Option<(usize, EndpointId)> {
+        let client = cap.client();
+        let found = self
+            .reply_objects
+            .get(client)
+            .copied()
+            .flatten()
+            .filter(|o| o.seq == cap.seq() && o.server == server);
+
+        let Some(obj) = found else {
+            LOG.error("ipc_reply: bad reply cap");
+            LOG.info_u64("task_id", self.tasks[server].id.0);
+            LOG.info_hex("reply_cap", cap.0);
+            self.counters.reply_caps_rejected += 1;
+            return None;
+        };
+
+        self.reply_objects[client] = None;
+        Some((client, obj.ep))
+    }
+
+    /// client の call が終わった（wake_task_to_ready / teardown_task）。払い出した reply cap はもう使えない
+    pub(super) fn reply_cap_drop(&mut self, client: usize) {
+        if client < MAX_TASKS {
+            self.reply_objects[client] = None;
+        }
+    }
+
+    /// reply object ⇔ Blocked(IpcReply) の client（server と ep が一致し、seq は払い出し済みで一意）
+    #[spec("INV-IPC-010")]
+    pub(super) fn check_reply_cap_invariants(&self) {
+        for (idx, t) in self.tasks.iter().enumerate().take(self.num_tasks) {
+            let waiting = match t.blocked_reason {
+                Some(BlockedReason::IpcReply { partner, ep }) if t.state.can_wait() => Some((partner, ep)),
+                _ => None,
+            };
+
+            match (self.reply_objects[idx], waiting) {
+                (None, None) => {}
+                (Some(o), Some((partner, ep))) => {
+                    if o.server >= self.num_tasks || self.tasks[o.server].id != partner || o.ep != ep {
+                        self.invariant_violated(
+                            InvariantId::ReplyCap,
+                            Some(t.id),
+                            Some(o.seq),
+                            "INVARIANT VIOLATION: reply object does not match the client's reply wait",
+                        );
+                        LOG.info_u64("task_id", t.id.0);
+                    }
+                    if o.seq == 0 || o.seq >= self.next_reply_seq {
+                        self.invariant_violated(
+                            InvariantId::ReplyCap,
+                            Some(t.id),
+                            Some(o.seq),
+                            "INVARIANT VIOLATION: reply object seq was never issued",
+                        );
+                        LOG.info_u64("seq", o.seq);
+                    }
+                }
+                (Some(o), None) => {
+                    self.invariant_violated(
+                        InvariantId::ReplyCap,
+                        Some(t.id),
+                        Some(o.seq),
+                        "INVARIANT VIOLATION: reply object without a client waiting for reply",
+                    );
+                    LOG.info_u64("task_id", t.id.0);
+                }
+                (None, Some((_, ep))) => {
+                    self.invariant_violated(
+                        InvariantId::ReplyCap,
+                        Some(t.id),
+                        Some(ep.0 as u64),
+                        "INVARIANT VIOLATION: client waits for reply without a reply object",
+                    );
+                    LOG.info_u64("task_id", t.id.0);
+                }
+            }
+        }
+
+        for (i, a) in self.reply_objects.iter().enumerate() {
+            let Some(a) = a else {
+                continue;
+            };
+            if self.reply_objects[i + 1..].iter().flatten().any(|b| b.seq == a.seq) {
+                self.invariant_violated(
+                    InvariantId::ReplyCap,
+                    None,
+                    Some(a.seq),
+                    "INVARIANT VIOLATION: two reply objects share a seq",
+                );
+                LOG.info_u64("seq", a.seq);
+            }
+        }
+    }
+}
@@ -0,0 +1,453 @@
+// kernel/src/kernel/ring3_task.rs
+//
+// 役割:
+// - ring3_tasks: User address space の task を実際に ring3 で走らせる（ring3_demo のような一発デモではない）。
+//   * bootstrap で task ごとに code / stack page を 1 つの batch で map し（apply_mem_actions / apply_batch_in_root）、user RIP / RSP を持たせる
+//   * scheduler が初めてその task を選んだ tick の末尾で、kstack.rs が iretq で ring3 に入る
+//   * int 0x80 は syscall 境界に戻り、pending_syscall を積む（user_program の模擬 syscall の代わり）
+//
+// int 0x80 / syscall 命令の流れ（arch/syscall_abi.rs。register ABI: rax = sysno, rdi / rsi / rdx = a0..a2）:
+// - 入口ごとに counters（syscall_entry_int80 / syscall_entry_syscall）を数える
+// - ring3_syscall_enter: decode_syscall で Syscall にして current の pending_syscall に積む
+//   * SYS_DEBUG_ADD / SYS_GET_TICKS はその場で返す。decode できなければ rax = SYSCALL_ERR_BAD_SYSCALL
+// - 入口は IRQ を開けて待つ。次の tick で handle_pending_syscall_if_any が実行する
+//   * Blocked になれば、その間 timer IRQ がこの stack の上で他の task へ切り替える
+// - ring3_syscall_poll: 実行済みで Running に戻っていたら (rax, rdx) を返す
+//   * recv 系 = 受け取った MR0（エラーなら last_reply の MR0）/ reply cap（reply_cap.rs。reply 待ちの無い msg なら 0）
+//   * send = last_reply の MR0 / MR1
+//   * reply = last_syscall_ret（SYSCALL_ERR_BAD_REPLY_CAP 以外は SYSCALL_OK）
+//   * NotifyWait = last_syscall_ret / 受け取った bits
+//   * TaskInfo = last_syscall_ret / TaskInfo::pack()（task_info.rs）
+//   * MemInfo = last_syscall_ret / MemInfo::pack()（mem_info.rs）
+//   * それ以外 = last_syscall_ret
+//
+// user program（固定バイト列、register ABI）:
+// - Task1（client）: loop { IpcSend(cap0, 0x5EED) }            … syscall 命令
+// - Task2（server）: loop { rdx = IpcRecv(cap0); IpcReply(rdx, 0xABCD) } … int 0x80（reply cap は recv の rdx をそのまま a0 へ）
+// - initrd（initrd.rs）か virtio_blk の disk（fs.rs）に task1.bin / task2.bin があれば、そちらを code page に読み込む
+//   * initrd を先に見る（disk より前に、device 無しで読める）
+//   * 中身は code page（Arch::USER_SPACE_BASE + RING3_CODE_PAGE の page）に置かれる前提の生の機械語（1 page まで）
+//   * 無い / 読めなければ上の組み込みの program のまま
+//
+// やらないこと:
+// - ELF ローダ（code は 1 page の生のバイト列）
+// - TaskCreate で作った task の ring3 化（slot を再利用した task は user_program のまま）
+
+use super::abi::{
+    SYSCALL_ERR_BAD_SYSCALL, SYSCALL_OK, SYS_DEBUG_ADD, SYS_GET_TICKS, SYS_IPC_RECV, SYS_IPC_RECV_ANY,
+    SYS_IPC_REPLY, SYS_IPC_SEND, SYS_MEM_INFO, SYS_NOTIFY_WAIT, SYS_TASK_INFO,
+};
+use super::syscall::decode_syscall;
+use super::{AddressSpaceKind, KernelState, LogEvent, TaskKillReason, TaskState, MAX_TASKS, TASK1_INDEX, TASK2_INDEX};
+use crate::arch::ops::{Arch, ArchOps};
+use crate::arch::syscall_abi::{SyscallArgs, SyscallEntry};
+use crate::mem::addr::{PhysFrame, VirtPage, PAGE_SIZE};
+use crate::mem::paging::{MemAction, PageFlags};
+
+/// ring3 の #PF を kernel がどう扱ったか（arch の page fault handler が次にすること）
+pub enum UserFaultOutcome {
+    /// swap in した: そのまま戻り、fault した命令を再実行する
+    Retry,
+    /// fault handler に委譲した: ring3_fault_resumed まで IRQ を待ってから再実行する
+    Delegated,
+    /// kill した: IRQ を待つ（次の tick で他の task へ切り替わり、戻らない）
+    Killed,
+}
+
+/// code / stack の page（Arch::USER_SPACE_BASE からの page index。mem_demo / ring3_mailbox_loop とは重ねない）
+/// - stack の直下は guard page（map しない。踏んだら StackOverflow で kill）
+const RING3_CODE_PAGE: u64 = 0x130;
+const RING3_STACK_GUARD_PAGE: u64 = 0x131;
+const RING3_STACK_PAGE: u64 = 0x132;
+
+const CODE_MAX: usize = 128;
+
+/// ring3 task の code page（swap_demo が追い出す page）
+pub(super) fn ring3_code_page() -> VirtPage {
+    VirtPage::from_index(RING3_CODE_PAGE)
+}
+
+/// user RIP / RSP（初回の iretq 先）
+#[derive(Clone, Copy)]
+pub(super) struct UserEntry {
+    pub rip: u64,
+    pub rsp: u64,
+}
+
+pub(super) struct Ring3Tasks {
+    entry: [Option<UserEntry>; MAX_TASKS],
+    // int 0x80 で積んだ syscall の sysno（poll で戻り値の取り方を決める）
+    in_syscall: [Option<u64>; MAX_TASKS],
+}
+
+impl Ring3Tasks {
+    pub(super) const fn new() -> Self {
+        Self { entry: [None; MAX_TASKS], in_syscall: [None; MAX_TASKS] }
+    }
+}
+
+/// user program を組み立てる（mov r32, imm32 / int 0x80 / syscall / jmp rel8）
+struct CodeBuf {
+    buf: [u8; CODE_MAX],
+    n: usize,
+}
+
+impl CodeBuf {
+    const fn new() -> Self {
+        Self { buf: [0; CODE_MAX], n: 0 }
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            if self.n < CODE_MAX {
+                self.buf[self.n] = b;
+                self.n += 1;
+            }
+        }
+    }
+
+    /// mov r32, imm32（上位 32bit は 0 になる）。opcode = 0xB8 + register 番号
+    fn mov_r32_imm32(&mut self, opcode: u8, imm: u32) {
+        let i = imm.to_le_bytes();
+        self.push(&[opcode, i[0], i[1], i[2], i[3]]);
+    }
+
+    fn args(&mut self, sysno: u64, a0: u32, a1: u32, a2: u32) {
+        self.mov_r32_imm32(0xB8, sysno as u32); // eax
+        self.mov_r32_imm32(0xBF, a0); // edi
+        self.mov_r32_imm32(0xBE, a1); // esi
+        self.mov_r32_imm32(0xBA, a2); // edx
+    }
+
+    fn int80(&mut self, sysno: u64, a0: u32, a1: u32, a2: u32) {
+        self.args(sysno, a0, a1, a2);
+        self.push(&[0xCD, 0x80]);
+    }
+
+    /// a0 = 直前の syscall の rdx（mov rdi, rdx）で int 0x80。reply cap は 64bit なので imm32 では渡せない
+    fn int80_a0_from_rdx(&mut self, sysno: u64, a1: u32, a2: u32) {
+        self.push(&[0x48, 0x89, 0xD7]); // mov rdi, rdx
+        self.mov_r32_imm32(0xB8, sysno as u32); // eax
+        self.mov_r32_imm32(0xBE, a1); // esi
+        self.mov_r32_imm32(0xBA, a2); // edx
+        self.push(&[0xCD, 0x80]);
+    }
+
+    /// syscall 命令（rcx / r11 は壊れる）
+    fn syscall(&mut self, sysno: u64, a0: u32, a1: u32, a2: u32) {
+        self.args(sysno, a0, a1, a2);
+        self.push(&[0x0F, 0x05]);
+    }
+
+    /// 先頭へ戻る jmp rel8
+    fn jmp_to_start(&mut self) {
+        let disp = -((self.n + 2) as i32);
+        self.push(&[0xEB, disp as i8 as u8]);
+    }
+
+    fn bytes(&self) -> &[u8] {
+        &self.buf[..self.n]
+    }
+}
+
+/// initrd / disk から読む user program の名前（initrd.rs / fs.rs の file 名）
+fn program_file_for_task(idx: usize) -> Option<&'static str> {
+    match idx {
+        TASK1_INDEX => Some("task1.bin"),
+        TASK2_INDEX => Some("task2.bin"),
+        _ => None,
+    }
+}
+
+/// initrd の名前の file を frame に書く（無い / 空 / 1 page を超えるなら None）
+fn load_from_initrd(name: &str, frame: PhysFrame) -> Option<usize> {
+    let data = super::initrd::find(name)?;
+    if data.is_empty() || data.len() as u64 > PAGE_SIZE {
+        crate::logging::error("ring3_tasks: initrd program is empty or larger than one page");
+        crate::logging::info(name);
+        crate::logging::info_u64("initrd_file_size", data.len() as u64);
+        return None;
+    }
+    unsafe { Arch::write_bytes_to_frame(frame, data) };
+    Some(data.len())
+}
+
+fn program_for_task(idx: usize) -> Option<CodeBuf> {
+    let mut c = CodeBuf::new();
+    match idx {
+        TASK1_INDEX => {
+            c.syscall(SYS_IPC_SEND, 0, 0x5EED, 0);
+        }
+        TASK2_INDEX => {
+            c.int80(SYS_IPC_RECV, 0, 0, 0);
+            c.int80_a0_from_rdx(SYS_IPC_REPLY, 0xABCD, 0);
+        }
+        _ => return None,
+    }
+    c.jmp_to_start();
+    Some(c)
+}
+
+impl KernelState {
+    pub(super) fn ring3_entry_of(&self, idx: usize) -> Option<UserEntry> {
+        if idx < MAX_TASKS { self.ring3.entry[idx] } else { None }
+    }
+
+    pub(super) fn is_ring3_task(&self, idx: usize) -> bool {
+        self.ring3_entry_of(idx).is_some()
+    }
+
+    /// task が Dead になった（teardown_task から）。slot を再利用する task は ring3 にしない
+    pub(super) fn forget_ring3_task(&mut self, idx: usize) {
+        if idx < MAX_TASKS {
+            self.ring3.entry[idx] = None;
+            self.ring3.in_syscall[idx] = None;
+        }
+    }
+
+    /// page ごとにフレームを確保して as_idx の root に 1 つの batch で map する（AddressSpace の記録と refcount も取る）
+    /// - root の無い AddressSpace には何も触らない
+    /// - 論理 mapping（aspace_apply_batch）→ 実ページテーブル（apply_batch_in_root）の順。どちらも all-or-nothing
+    /// - 実ページテーブルに載らなければ論理 mapping も外し、確保したフレームを全部返す（None）
+    fn ring3_map_fresh_pages<const N: usize>(
+        &mut self,
+        as_idx: usize,
+        pages: [(VirtPage, PageFlags); N],
+    ) -> Option<[PhysFrame; N]> {
+        let root = self.address_spaces[as_idx].root_page_frame?;
+
+        let mut frames = [PhysFrame::from_index(0); N];
+        for i in 0..N {
+            let Some(raw) = self.phys_mem.allocate_frame() else {
+                for &f in frames[..i].iter() {
+                    self.release_frame_if_unreferenced(f);
+                }
+                return None;
+            };
+            frames[i] = PhysFrame::from_index(raw.start_address().as_u64() / PAGE_SIZE);
+            self.push_event(LogEvent::FrameAllocated);
+        }
+
+        let maps: [MemAction; N] = core::array::from_fn(|i| MemAction::map(pages[i].0, frames[i], pages[i].1));
+        let unmaps: [MemAction; N] = core::array::from_fn(|i| MemAction::unmap(pages[i].0));
+
+        if let Err(e) = self.aspace_apply_batch(as_idx, &maps) {
+            crate::logging::error("ring3_tasks: AddressSpace::apply_mem_actions failed");
+            crate::logging::info_u64("batch_index", e.index as u64);
+            for &f in frames.iter() {
+                self.release_frame_if_unreferenced(f);
+            }
+            return None;
+        }
+        if let Err(e) = unsafe { self.apply_batch_in_root(as_idx, root, &maps) } {
+            let _ = self.aspace_apply_batch(as_idx, &unmaps);
+            crate::logging::error("ring3_tasks: arch map failed; batch rolled back");
+            crate::logging::info_u64("batch_index", e.index as u64);
+            for &f in frames.iter() {
+                self.release_frame_if_unreferenced(f);
+            }
+            return None;
+        }
+        for &f in frames.iter() {
+            self.ref_mapped_frame(f);
+        }
+        Some(frames)
+    }
+
+    /// bootstrap の最後: User address space の task（Task1 / Task2）に code / stack を用意して ring3 task にする
+    pub(super) fn setup_ring3_tasks(&mut self) {
+        for idx in [TASK1_INDEX, TASK2_INDEX] {
+            if idx >= self.num_tasks || self.tasks[idx].state == TaskState::Dead {
+                continue;
+            }
+            let as_idx = self.tasks[idx].address_space_id.0;
+            if as_idx >= self.num_tasks || self.address_spaces[as_idx].kind != AddressSpaceKind::User {
+                continue;
+            }
+            let Some(code) = program_for_task(idx) else {
+                continue;
+            };
+
+            let code_page = ring3_code_page();
+            let stack_page = VirtPage::from_index(RING3_STACK_PAGE);
+
+            // code は RX（kernel は physmap 経由で書く。user からは書けない）、stack は RW。2 page を 1 つの batch で map する
+            let code_flags = PageFlags::PRESENT | PageFlags::USER;
+            let stack_flags = PageFlags::PRESENT | PageFlags::WRITABLE | PageFlags::USER;
+            let Some([code_frame, _stack_frame]) =
+                self.ring3_map_fresh_pages(as_idx, [(code_page, code_flags), (stack_page, stack_flags)])
+            else {
+                crate::logging::error("ring3_tasks: map user code / stack failed");
+                crate::logging::info_u64("task_id", self.tasks[idx].id.0);
+                continue;
+            };
+            let loaded = program_file_for_task(idx).and_then(|name| {
+                if let Some(n) = load_from_initrd(name, code_frame) {
+                    crate::logging::info("ring3_tasks: user program loaded from initrd");
+                    crate::logging::info(name);
+                    return Some(n);
+                }
+                let n = self.fs_load_into_frame(name, code_frame)?;
+                crate::logging::info("ring3_tasks: user program loaded from fs");
+                crate::logging::info(name);
+                Some(n)
+            });
+            let code_len = match loaded {
+                Some(n) => n,
+                None => {
+                    unsafe { Arch::write_bytes_to_frame(code_frame, code.bytes()) };
+                    code.bytes().len()
+                }
+            };
+
+            let guard_page = VirtPage::from_index(RING3_STACK_GUARD_PAGE);
+            if self.address_spaces[as_idx].reserve_guard_page(guard_page).is_err() {
+                crate::logging::error("ring3_tasks: reserve user stack guard page failed");
+                crate::logging::info_u64("task_id", self.tasks[idx].id.0);
+                continue;
+            }
+
+            let rip = Arch::USER_SPACE_BASE + code_page.start_address().0;
+            let rsp = (Arch::USER_SPACE_BASE + stack_page.start_address().0 + PAGE_SIZE) & !0xF;
+            self.ring3.entry[idx] = Some(UserEntry { rip, rsp });
+
+            crate::logging::info("ring3_tasks: user task ready");
+            crate::logging::info_u64("task_id", self.tasks[idx].id.0);
+            crate::logging::info_u64("user_rip", rip);
+            crate::logging::info_u64("user_rsp", rsp);
+            crate::logging::info_u64("stack_guard", Arch::USER_SPACE_BASE + guard_page.start_address().0);
+            crate::logging::info_u64("code_len", code_len as u64);
+        }
+    }
+
+    /// 初回の切替で ring3 に入る context を積めなかった（TrapFrame 検査違反）
+    pub(super) fn ring3_reject_entry(&mut self, idx: usize, check: u64, value: u64) {
+        crate::logging::error("ring3_tasks: initial user frame rejected");
+        crate::logging::info_u64("task_id", self.tasks[idx].id.0);
+        self.forget_ring3_task(idx);
+        self.kill_task(idx, TaskKillReason::TrapFrameCorrupt { check, value });
+    }
+
+    /// ring3 で起きた #PF（arch の page fault handler から）: SwappedOut なら戻し、handler が居れば委譲し、それ以外は current を kill する
+    /// - guard page なら StackOverflow、それ以外は通常の user #PF（kill_current_task_due_to_user_pf）
+    /// - 委譲した task は Blocked(FaultWait)。FaultResolve で起こされたら fault した命令を再実行する
+    /// - ring3 では fault 命令から再開できないので、ignore されても kill する
+    pub fn ring3_user_page_fault(&mut self, pf: crate::arch::ops::PageFaultInfo) -> UserFaultOutcome {
+        let idx = self.current_task;
+        if !self.is_ring3_task(idx) || self.tasks[idx].state == TaskState::Dead {
+            crate::logging::error("ring3_tasks: #PF from ring3 but current is not a ring3 task");
+            return UserFaultOutcome::Killed;
+        }
+        match self.swap_in_on_fault(idx, pf.addr) {
+            Ok(true) => {
+                crate::logging::info("ring3_tasks: #PF on swapped-out page => swapped in; retry");
+                crate::logging::info_u64("task_id", self.tasks[idx].id.0);
+                crate::logging::info_u64("addr", pf.addr);
+                return UserFaultOutcome::Retry;
+            }
+            Ok(false) => {}
+            Err(_) => {
+                // swap in に失敗: swap.rs が SwapFailed で kill 済み
+                self.ring3.in_syscall[idx] = None;
+                return UserFaultOutcome::Killed;
+            }
+        }
+        self.ring3.in_syscall[idx] = None;
+        self.kill_current_task_due_to_user_pf(pf);
+
+        if self.fault_pending(idx) {
+            return UserFaultOutcome::Delegated;
+        }
+        if self.tasks[idx].state != TaskState::Dead {
+            self.kill_task(idx, TaskKillReason::UserPageFault { addr: pf.addr, err: pf.err, rip: pf.rip });
+        }
+        UserFaultOutcome::Killed
+    }
+
+    /// 委譲した #PF が解決され、current として Running に戻ったか（arch の #PF handler が IRQ 待ちの間に見る）
+    pub fn ring3_fault_resumed(&mut self) -> bool {
+        let idx = self.current_task;
+        idx < self.num_tasks && self.tasks[idx].state == TaskState::Running && !self.fault_pending(idx)
+    }
+
+    /// ring3 で起きた #PF / #GP 以外の例外（arch の例外 handler から）: current を UserException で kill する
+    /// - 戻り値: true = kill した（handler は IRQ を待ち、次の tick で他の task へ切り替わる）
+    pub fn ring3_user_exception(&mut self, vector: u8, err: u64, rip: u64) -> bool {
+        let idx = self.current_task;
+        if !self.is_ring3_task(idx) || self.tasks[idx].state == TaskState::Dead {
+            crate::logging::error("ring3_tasks: exception from ring3 but current is not a ring3 task");
+            return false;
+        }
+        self.ring3.in_syscall[idx] = None;
+        self.kill_task(idx, TaskKillReason::UserException { vector: vector as u64, err, rip });
+        true
+    }
+
+    /// int 0x80: syscall を current の pending_syscall に積む
+    /// - 戻り値: その場で返す (rax, rdx)（Some）/ 実行待ち（None。ring3_syscall_poll で待つ）
+    pub fn ring3_syscall_enter(&mut self, entry: SyscallEntry, args: SyscallArgs) -> Option<(u64, u64)> {
+        match entry {
+            SyscallEntry::Int80 => self.counters.syscall_entry_int80 += 1,
+            SyscallEntry::Syscall => self.counters.syscall_entry_syscall += 1,
+        }
+        let SyscallArgs { sysno, a0, a1, a2 } = args;
+
+        let idx = self.current_task;
+        if !self.is_ring3_task(idx) || self.tasks[idx].state != TaskState::Running {
+            crate::logging::error("ring3_syscall: current task is not a running ring3 task");
+            return Some((SYSCALL_ERR_BAD_SYSCALL, 0));
+        }
+
+        match sysno {
+            SYS_DEBUG_ADD => return Some((a0.wrapping_add(a1).wrapping_add(a2), 0)),
+            SYS_GET_TICKS => return Some((self.tick_count, 0)),
+            _ => {}
+        }
+
+        let Some(sc) = decode_syscall(sysno, a0, a1, a2) else {
+            crate::logging::error("ring3_syscall: undecodable syscall");
+            crate::logging::info_u64("task_id", self.tasks[idx].id.0);
+            crate::logging::info_u64("sysno", sysno);
+            return Some((SYSCALL_ERR_BAD_SYSCALL, 0));
+        };
+
+        self.tasks[idx].pending_syscall = Some(sc);
+        self.ring3.in_syscall[idx] = Some(sysno);
+        None
+    }
+
+    /// int 0x80 の待ち: 積んだ syscall が実行され、current が Running に戻っていれば (rax, rdx)
+    pub fn ring3_syscall_poll(&mut self) -> Option<(u64, u64)> {
+        let idx = self.current_task;
+        if idx >= self.num_tasks {
+            return None;
+        }
+        let t = &self.tasks[idx];
+        if t.pending_syscall.is_some() || t.state != TaskState::Running {
+            return None;
+        }
+
+        let sysno = self.ring3.in_syscall[idx].take()?;
+        let t = &mut self.tasks[idx];
+        let msg_regs = |m: Option<super::IpcMessage>| m.map_or((0, 0), |m| (m.mr0(), m.mr(1)));
+        let ret = match sysno {
+            SYS_IPC_RECV | SYS_IPC_RECV_ANY => {
+                let rax = t.last_msg.take().or_else(|| t.last_reply.take()).map_or(0, |m| m.mr0());
+                (rax, t.last_reply_cap.take().map_or(0, |c| c.0))
+            }
+            SYS_IPC_SEND => msg_regs(t.last_reply.take()),
+            _ => {
+                t.last_syscall_ret_unread = false;
+                let rax = t.last_syscall_ret.take().unwrap_or(SYSCALL_OK);
+                let rdx = match sysno {
+                    SYS_NOTIFY_WAIT => t.last_notify.unwrap_or(0),
+                    SYS_TASK_INFO => t.last_task_info.map_or(0, |i| i.pack()),
+                    SYS_MEM_INFO => t.last_mem_info.map_or(0, |i| i.pack()),
+                    _ => 0,
+                };
+                (rax, rdx)
+            }
+        };
+        Some(ret)
+    }
+}
@@ -0,0 +1,253 @@
+// kernel/src/kernel/sched_policy.rs
+//
+// 役割:
+// - scheduler の「どの Ready task を選ぶか / いつ切り替えるか」を SchedPolicy に切り出す。
+// - 同じ syscall / tick の台本を別の policy で流し、event trace を形式的に比べられるようにする。
+//
+// 境界:
+// - ready_queue の管理（Ready のみ・重複なし、INV-SCHED-002）と dispatch は KernelState 側（mod.rs）
+// - policy が決めるのは 4 つだけ:
+//   * on_ready:  task が ready_queue に入った（enqueue_ready の後）
+//   * on_block:  Running / Ready の task が Blocked に落ちた（block_task。理由の更新だけなら呼ばない）
+//   * pick_next: ready_queue（Ready のみに掃除済み、空でない）のどの位置を次に走らせるか
+//   * on_tick:   走っている task の tick（time_slice_used 更新後）。true なら quantum 切れとして切り替える
+//
+// 実装:
+// - FixedPriority（既定）: 実効優先度（priority.rs の継承込み）の最大。同優先度は queue の先頭（FIFO）
+// - RoundRobin（feature sched_round_robin）: 優先度を見ずに queue の先頭
+//   * この 2 つは quantum = KernelState.quantum で切り替える
+// - Mlfq（feature sched_mlfq）: multi-level feedback queue
+//   * level 0 が最上位。一番上の level の先頭（同 level は FIFO）を選ぶ。priority は見ない
+//   * level ごとの quantum（MLFQ_QUANTA）を使い切ったら 1 つ下げる / block したら 1 つ上げる（LevelChanged）
+//   * level ごとの CPU tick 数を counters に積む（下の level が飢えているかを dump で見る）
+// - Chaos（feature sched_chaos）: 毎 tick、Ready の task（走っていた task を含む）から一様に 1 つ選ぶ
+//   * 乱数は seed 付きの xorshift64（ChaosState）。seed は command line の sched_seed=（無ければ CHAOS_DEFAULT_SEED）で、
+//     KernelState を作るときにログに出す。同じ seed・同じ台本なら同じ順で選ぶので、invariant 違反を再現できる
+//   * priority も FIFO も見ないので、bounded waiting（INV-SCHED-004）は検査しない（BOUNDED_WAITING = false）
+//   * 決まった順序では起きない interleaving を invariant checker で探る用（性能・公平性は見ない）
+//
+// 方針:
+// - 関数は self を取らない（arch::ops の ArchOps と同じく、ActivePolicy の型 alias で静的に選ぶ）
+// - policy 固有の状態が要るなら policy 側の struct に持ち、KernelState のフィールドに足す
+
+use super::{KernelState, LogEvent, MAX_TASKS};
+
+pub(super) trait SchedPolicy {
+    /// capabilities に出す名前
+    const NAME: &'static str;
+
+    /// 同優先度の Ready task を FIFO で選ぶ（INV-SCHED-004 を検査してよい）
+    const BOUNDED_WAITING: bool = true;
+
+    fn on_ready(ks: &mut KernelState, idx: usize);
+
+    fn on_block(ks: &mut KernelState, idx: usize);
+
+    fn pick_next(ks: &mut KernelState) -> usize;
+
+    fn on_tick(ks: &mut KernelState, idx: usize) -> bool;
+}
+
+/// 実効優先度の最大を選ぶ（同優先度は FIFO）
+pub(super) struct FixedPriority;
+
+impl SchedPolicy for FixedPriority {
+    const NAME: &'static str = "priority_preemptive";
+
+    fn on_ready(_ks: &mut KernelState, _idx: usize) {}
+
+    fn on_block(_ks: &mut KernelState, _idx: usize) {}
+
+    fn pick_next(ks: &mut KernelState) -> usize {
+        // 同じ優先度なら queue の先頭に近い方（= 先に Ready になった方）
+        // - 走った task は tail へ戻るので、同優先度の task は順番に CPU を得る（round-robin）
+        let mut best_pos = 0;
+        let mut best_prio = ks.tasks[ks.ready_queue[0]].priority;
+        for pos in 1..ks.rq_len {
+            let prio = ks.tasks[ks.ready_queue[pos]].priority;
+            if prio > best_prio {
+                best_prio = prio;
+                best_pos = pos;
+            }
+        }
+        best_pos
+    }
+
+    fn on_tick(ks: &mut KernelState, idx: usize) -> bool {
+        ks.tasks[idx].time_slice_used >= ks.quantum
+    }
+}
+
+/// 優先度を見ずに queue の先頭を選ぶ
+pub(super) struct RoundRobin;
+
+impl SchedPolicy for RoundRobin {
+    const NAME: &'static str = "round_robin";
+
+    fn on_ready(_ks: &mut KernelState, _idx: usize) {}
+
+    fn on_block(_ks: &mut KernelState, _idx: usize) {}
+
+    fn pick_next(_ks: &mut KernelState) -> usize {
+        0
+    }
+
+    fn on_tick(ks: &mut KernelState, idx: usize) -> bool {
+        ks.tasks[idx].time_slice_used >= ks.quantum
+    }
+}
+
+/// MLFQ の level 数（0 が最上位）
+pub(super) const MLFQ_LEVELS: usize = 3;
+
+// abi の COUNTER_KEYS（mlfq_level0_ticks..mlfq_level2_ticks）と揃える
+const _: () = assert!(MLFQ_LEVELS == 3);
+
+/// level ごとの quantum（tick 数。下の level ほど長く走れる）
+pub(super) const MLFQ_QUANTA: [u64; MLFQ_LEVELS] = [2, 4, 8];
+
+/// task index ごとの MLFQ level（Mlfq 以外の policy では使わない。新しい task は level 0）
+#[derive(Clone, Copy)]
+pub(super) struct MlfqState {
+    level: [u8; MAX_TASKS],
+}
+
+impl MlfqState {
+    pub(super) const fn new() -> Self {
+        Self { level: [0; MAX_TASKS] }
+    }
+
+    pub(super) fn level_of(&self, idx: usize) -> usize {
+        self.level[idx] as usize
+    }
+
+    /// slot を空けた（teardown）。次にこの slot に入る task は level 0 から始める
+    pub(super) fn reset(&mut self, idx: usize) {
+        self.level[idx] = 0;
+    }
+}
+
+/// multi-level feedback queue
+pub(super) struct Mlfq;
+
+impl Mlfq {
+    fn set_level(ks: &mut KernelState, idx: usize, to: usize) {
+        let from = ks.mlfq.level_of(idx);
+        if from == to {
+            return;
+        }
+        ks.mlfq.level[idx] = to as u8;
+        if to > from {
+            ks.counters.mlfq_demotions += 1;
+        } else {
+            ks.counters.mlfq_promotions += 1;
+        }
+        let task = ks.tasks[idx].id;
+        ks.push_event(LogEvent::LevelChanged { task, from: from as u8, to: to as u8 });
+    }
+}
+
+impl SchedPolicy for Mlfq {
+    const NAME: &'static str = "mlfq";
+
+    fn on_ready(_ks: &mut KernelState, _idx: usize) {}
+
+    /// I/O（IPC / sleep）待ちで CPU を手放した task は 1 つ上げる
+    fn on_block(ks: &mut KernelState, idx: usize) {
+        let level = ks.mlfq.level_of(idx);
+        if level > 0 {
+            Self::set_level(ks, idx, level - 1);
+        }
+    }
+
+    /// 一番上の level の先頭（同 level は FIFO）
+    fn pick_next(ks: &mut KernelState) -> usize {
+        let mut best_pos = 0;
+        let mut best_level = ks.mlfq.level_of(ks.ready_queue[0]);
+        for pos in 1..ks.rq_len {
+            let level = ks.mlfq.level_of(ks.ready_queue[pos]);
+            if level < best_level {
+                best_level = level;
+                best_pos = pos;
+            }
+        }
+        best_pos
+    }
+
+    /// level の quantum を使い切ったら 1 つ下げて切り替える
+    fn on_tick(ks: &mut KernelState, idx: usize) -> bool {
+        let level = ks.mlfq.level_of(idx);
+        ks.counters.mlfq_level_ticks[level] += 1;
+
+        if ks.tasks[idx].time_slice_used < MLFQ_QUANTA[level] {
+            return false;
+        }
+        if level + 1 < MLFQ_LEVELS {
+            Self::set_level(ks, idx, level + 1);
+        }
+        true
+    }
+}
+
+/// sched_seed= が無いときの seed
+pub(super) const CHAOS_DEFAULT_SEED: u64 = 0x5EED_C4A0_5000_0001;
+
+/// Chaos の乱数（xorshift64）
+pub(super) struct ChaosState {
+    rng: u64,
+}
+
+impl ChaosState {
+    /// seed 0 は xorshift が回らないので CHAOS_DEFAULT_SEED に置き換える
+    pub(super) const fn new(seed: u64) -> Self {
+        Self { rng: if seed == 0 { CHAOS_DEFAULT_SEED } else { seed } }
+    }
+
+    fn next(&mut self) -> u64 {
+        let mut x = self.rng;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.rng = x;
+        x
+    }
+}
+
+/// Ready の task から一様に選び、毎 tick 選び直す
+pub(super) struct Chaos;
+
+impl SchedPolicy for Chaos {
+    const NAME: &'static str = "chaos";
+    const BOUNDED_WAITING: bool = false;
+
+    fn on_ready(_ks: &mut KernelState, _idx: usize) {}
+
+    fn on_block(_ks: &mut KernelState, _idx: usize) {}
+
+    fn pick_next(ks: &mut KernelState) -> usize {
+        (ks.chaos.next() % ks.rq_len as u64) as usize
+    }
+
+    /// 毎 tick 切り替える（走っていた task も ready_queue に戻ってから選び直す）
+    fn on_tick(_ks: &mut KernelState, _idx: usize) -> bool {
+        true
+    }
+}
+
+#[cfg(not(any(feature = "sched_round_robin", feature = "sched_mlfq", feature = "sched_chaos")))]
+pub(super) type ActivePolicy = FixedPriority;
+
+#[cfg(all(feature = "sched_round_robin", not(feature = "sched_mlfq"), not(feature = "sched_chaos")))]
+pub(super) type ActivePolicy = RoundRobin;
+
+#[cfg(all(feature = "sched_mlfq", not(feature = "sched_chaos")))]
+pub(super) type ActivePolicy = Mlfq;
+
+#[cfg(feature = "sched_chaos")]
+pub(super) type ActivePolicy = Chaos;
+
+#[cfg(all(feature = "sched_round_robin", feature = "sched_mlfq"))]
+compile_error!("sched_round_robin and sched_mlfq select different policies; enable only one");
+
+#[cfg(all(feature = "sched_chaos", any(feature = "sched_round_robin", feature = "sched_mlfq")))]
+compile_error!("sched_chaos selects its own policy; it cannot be combined with sched_round_robin / sched_mlfq");
@@ -0,0 +1,354 @@
+// kernel/src/kernel/shm.rs
+//
+// 役割:
+// - ShmCreate / ShmMap syscall（shared memory segment）。
+//   2 つの user task が同じ物理フレームを map し、kernel を経由したコピー無しで大きなデータを渡す。
+//
+// 設計方針:
+// - segment table は固定長（MAX_SHM_SEGMENTS）。shm_id = table の index
+// - ShmCreate: pages 枚のフレームを確保して 0 で埋め、作った task を owner にする（まだどこにも map しない）
+//   * 作った task の handle table（cspace.rs）に Map 権限の handle を入れ、その cap index を返す
+//   * segment のフレームは refcount を取らずに segment が持つ（release_frame_if_unreferenced は返さない）
+// - ShmMap: segment の全 page を page..page + pages に RW | USER で map する
+//   * segment は handle で指す。handle を持つ user task なら map できる（渡すのは IPC の cap transfer）
+//   * 範囲は user slot に収まること（SYSCALL_ERR_BAD_PAGE_RANGE）。同じ AddressSpace に 2 回は map しない
+//   * フレーム quota に数える（segment の pages 枚を新しいフレームとして）
+//   * 全 page を 1 つの MemAction の batch にして、論理 mapping・実ページテーブルとも all-or-nothing で入れる
+//     （実ページテーブルで落ちたら論理 mapping も外す。途中の page だけ map された segment を残さない）
+// - owner が死んだら teardown_task から destroy_shm_owned_by が呼ばれる
+//   * 全 AddressSpace から segment の mapping を外し（論理 + 実ページテーブル）、フレームを返す
+//   * 全 task の table から segment の handle を消す（slot を再利用しても古い handle が新しい segment を指さない）
+//   * owner 以外の task が死んだときは、その task の mapping が cleanup_user_mappings で外れるだけ
+//
+// 戻り値（last_syscall_ret、abi.rs が正本）:
+// - ShmCreate: 成功 = SHM_CREATE_OK_TAG | handle / SYSCALL_ERR_BAD_SHM（pages）/ SYSCALL_ERR_NO_SHM_SLOT /
+//              SYSCALL_ERR_CAPACITY（handle table が満杯）/ SYSCALL_ERR_ARCH_FAILED（フレーム不足）/
+//              SYSCALL_ERR_FORBIDDEN（kernel task）
+// - ShmMap: SYSCALL_OK / SYSCALL_ERR_BAD_SHM / SYSCALL_ERR_BAD_PAGE_RANGE / SYSCALL_ERR_ALREADY_MAPPED /
+//           SYSCALL_ERR_QUOTA / SYSCALL_ERR_GUARD_PAGE / SYSCALL_ERR_CAPACITY / SYSCALL_ERR_FORBIDDEN /
+//           SYSCALL_ERR_ARCH_FAILED（実ページテーブル）
+//   * handle が引けなければ syscall 境界で SYSCALL_ERR_BAD_CAP / SYSCALL_ERR_WRONG_TYPE（cspace.rs）
+//
+// やらないこと:
+// - ShmUnmap / ShmDestroy（外すのは PageUnmap、消えるのは owner の死）
+// - 読み取り専用の map
+
+use super::abi::{
+    SHM_CREATE_OK_TAG, SYSCALL_ERR_ALREADY_MAPPED, SYSCALL_ERR_ARCH_FAILED, SYSCALL_ERR_BAD_PAGE_RANGE,
+    SYSCALL_ERR_BAD_SHM, SYSCALL_ERR_CAPACITY, SYSCALL_ERR_FORBIDDEN, SYSCALL_ERR_NO_SHM_SLOT, SYSCALL_ERR_QUOTA, SYSCALL_OK,
+};
+use super::cspace::{CapRights, CapSlot, KernelObject};
+use super::{to_arch_frame, AddressSpaceKind, InvariantId, KernelState, LogEvent, ShmId, TaskId, TaskState, MAX_TASKS};
+use crate::arch::ops::{Arch, ArchOps};
+use crate::mem::addr::{PhysFrame, VirtPage, PAGE_SIZE};
+use crate::mem::paging::{MemAction, PageFlags};
+use spec_macros::spec;
+
+const LOG: crate::logging::Subsystem = crate::logging::Subsystem::Mem;
+
+pub(super) const MAX_SHM_SEGMENTS: usize = 4;
+
+/// 1 segment の最大 page 数
+pub(super) const MAX_SHM_PAGES: usize = 4;
+
+/// 新しい segment のフレームを埋める 0
+static ZERO_PAGE: [u8; PAGE_SIZE as usize] = [0; PAGE_SIZE as usize];
+
+#[derive(Clone, Copy)]
+pub(super) struct ShmSegment {
+    pub allocated: bool,
+    pub owner: Option<TaskId>,
+    pub pages: usize,
+    pub frames: [Option<PhysFrame>; MAX_SHM_PAGES],
+}
+
+impl ShmSegment {
+    pub(super) const EMPTY: Self = Self { allocated: false, owner: None, pages: 0, frames: [None; MAX_SHM_PAGES] };
+
+    fn frames(&self) -> impl Iterator<Item = PhysFrame> + '_ {
+        self.frames.iter().take(self.pages).flatten().copied()
+    }
+}
+
+impl KernelState {
+    /// user AddressSpace の index（kernel task なら None）
+    fn shm_user_as_of(&self, idx: usize) -> Option<usize> {
+        let as_idx = self.tasks[idx].address_space_id.0;
+        (as_idx < self.num_tasks && self.address_spaces[as_idx].kind == AddressSpaceKind::User).then_some(as_idx)
+    }
+
+    /// frame が使用中の segment のフレームか（release_frame_if_unreferenced が返さない）
+    pub(super) fn is_shm_frame(&self, frame: PhysFrame) -> bool {
+        self.shm.iter().filter(|s| s.allocated).any(|s| s.frames().any(|f| f == frame))
+    }
+
+    pub(super) fn syscall_shm_create(&mut self, idx: usize, pages: usize) -> u64 {
+        let tid = self.tasks[idx].id;
+
+        if self.shm_user_as_of(idx).is_none() {
+            LOG.error("shm_create: kernel task cannot own shared memory");
+            return SYSCALL_ERR_FORBIDDEN;
+        }
+        if pages == 0 || pages > MAX_SHM_PAGES {
+            LOG.error("shm_create: bad page count");
+            LOG.info_u64("task_id", tid.0);
+            LOG.info_u64("pages", pages as u64);
+            return SYSCALL_ERR_BAD_SHM;
+        }
+        let Some(slot) = self.shm.iter().position(|s| !s.allocated) else {
+            LOG.error("shm_create: no free segment slot");
+            LOG.info_u64("task_id", tid.0);
+            return SYSCALL_ERR_NO_SHM_SLOT;
+        };
+        let shm = ShmId(slot);
+
+        // handle を先に入れる（入らなければ segment は作らない）
+        let handle = CapSlot { object: KernelObject::Shm(shm), rights: CapRights::MAP, grant: None };
+        let Some(cap) = self.cspaces[idx].insert(handle) else {
+            LOG.error("shm_create: handle table full");
+            LOG.info_u64("task_id", tid.0);
+            return SYSCALL_ERR_CAPACITY;
+        };
+
+        let mut frames = [None; MAX_SHM_PAGES];
+        for i in 0..pages {
+            let Some(raw) = self.phys_mem.allocate_frame() else {
+                LOG.error("shm_create: no frame");
+                LOG.info_u64("task_id", tid.0);
+                // 途中まで確保した分は返す（まだ segment に入れていないので保持されない）
+                for f in frames.iter().take(i).flatten().copied() {
+                    self.release_frame_if_unreferenced(f);
+                }
+                self.cspaces[idx].take(cap);
+                return SYSCALL_ERR_ARCH_FAILED;
+            };
+            let frame = PhysFrame::from_index(raw.start_address().as_u64() / PAGE_SIZE);
+            self.push_event(LogEvent::FrameAllocated);
+            unsafe { Arch::write_bytes_to_frame(frame, &ZERO_PAGE) };
+            frames[i] = Some(frame);
+        }
+
+        self.shm[slot] = ShmSegment { allocated: true, owner: Some(tid), pages, frames };
+
+        LOG.info("shm_create: created");
+        LOG.info_u64("task_id", tid.0);
+        LOG.info_u64("shm_id", slot as u64);
+        LOG.info_u64("cap_index", cap.0 as u64);
+        LOG.info_u64("pages", pages as u64);
+
+        self.push_event(LogEvent::ShmCreated { task: tid, shm, pages });
+
+        SHM_CREATE_OK_TAG | cap.0 as u64
+    }
+
+    #[spec("INV-MEM-001")]
+    pub(super) fn syscall_shm_map(&mut self, idx: usize, shm: ShmId, page: VirtPage) -> u64 {
+        let tid = self.tasks[idx].id;
+
+        let Some(as_idx) = self.shm_user_as_of(idx) else {
+            LOG.error("shm_map: kernel task cannot map shared memory");
+            return SYSCALL_ERR_FORBIDDEN;
+        };
+        if shm.0 >= MAX_SHM_SEGMENTS || !self.shm[shm.0].allocated {
+            LOG.error("shm_map: no such segment");
+            LOG.info_u64("task_id", tid.0);
+            LOG.info_u64("shm_id", shm.0 as u64);
+            return SYSCALL_ERR_BAD_SHM;
+        }
+        let seg = self.shm[shm.0];
+
+        // user slot に収まる範囲だけ（offset + pages page が USER_SPACE_SIZE を超えない）
+        let slot_pages = Arch::USER_SPACE_SIZE / PAGE_SIZE;
+        if page.number.checked_add(seg.pages as u64).is_none_or(|end| end > slot_pages) {
+            LOG.error("shm_map: range outside user slot");
+            LOG.info_u64("task_id", tid.0);
+            LOG.info_u64("page", page.number);
+            return SYSCALL_ERR_BAD_PAGE_RANGE;
+        }
+
+        let aspace = &self.address_spaces[as_idx];
+        if seg.frames().any(|f| aspace.frame_mapping_count(f) > 0) {
+            LOG.error("shm_map: segment already mapped in this address space");
+            LOG.info_u64("task_id", tid.0);
+            LOG.info_u64("shm_id", shm.0 as u64);
+            return SYSCALL_ERR_ALREADY_MAPPED;
+        }
+        if aspace.frames_in_use() + seg.pages > aspace.frame_quota() {
+            LOG.error("shm_map: frame quota exceeded");
+            LOG.info_u64("task_id", tid.0);
+            LOG.info_u64("frames_in_use", aspace.frames_in_use() as u64);
+            LOG.info_u64("frame_quota", aspace.frame_quota() as u64);
+            return SYSCALL_ERR_QUOTA;
+        }
+
+        // 全 page の Map を 1 つの batch にする（逆操作の Unmap も並べておく）
+        let flags = PageFlags::PRESENT | PageFlags::WRITABLE | PageFlags::USER;
+        let mut maps = [MemAction::unmap(page); MAX_SHM_PAGES];
+        let mut unmaps = [MemAction::unmap(page); MAX_SHM_PAGES];
+        for (i, frame) in seg.frames().enumerate() {
+            let p = VirtPage::from_index(page.number + i as u64);
+            maps[i] = MemAction::map(p, frame, flags);
+            unmaps[i] = MemAction::unmap(p);
+        }
+        let maps = &maps[..seg.pages];
+        let unmaps = &unmaps[..seg.pages];
+
+        // 論理 mapping（落ちたら AddressSpace は batch の前のまま）
+        if let Err(e) = self.aspace_apply_batch(as_idx, maps) {
+            LOG.error("shm_map: AddressSpace::apply_mem_actions failed");
+            LOG.info_u64("task_id", tid.0);
+            LOG.info_u64("batch_index", e.index as u64);
+            LOG.info_u64("page", maps[e.index].page().number);
+            return e.error.syscall_code();
+        }
+
+        // 実ページテーブル（落ちたら arch 側は戻してあるので、論理 mapping も外す）
+        let arch_res = match self.address_spaces[as_idx].root_page_frame {
+            Some(root) => unsafe { self.apply_batch_in_root(as_idx, root, maps) }.map_err(|e| e.index),
+            None => Err(0),
+        };
+        if let Err(index) = arch_res {
+            let _ = self.aspace_apply_batch(as_idx, unmaps);
+            LOG.error("shm_map: arch map failed; batch rolled back");
+            LOG.info_u64("task_id", tid.0);
+            LOG.info_u64("batch_index", index as u64);
+            return SYSCALL_ERR_ARCH_FAILED;
+        }
+        for frame in seg.frames() {
+            self.ref_mapped_frame(frame);
+        }
+
+        LOG.info("shm_map: mapped");
+        LOG.info_u64("task_id", tid.0);
+        LOG.info_u64("shm_id", shm.0 as u64);
+        LOG.info_u64("page", page.number);
+
+        self.push_event(LogEvent::ShmMapped { task: tid, shm, page: page.number });
+
+        SYSCALL_OK
+    }
+
+    /// owner が dead_id の segment を全部解放する（teardown_task から）
+    /// - 生きている task の mapping も外す（実ページテーブルごと）。外した後にフレームを返す
+    pub(super) fn destroy_shm_owned_by(&mut self, dead_id: TaskId) {
+        for slot in 0..MAX_SHM_SEGMENTS {
+            let seg = self.shm[slot];
+            if !seg.allocated || seg.owner != Some(dead_id) {
+                continue;
+            }
+
+            // 外す mapping を集めてから unmap する（借用規則のため）
+            let mut victims: [Option<(usize, VirtPage, PhysFrame)>; MAX_TASKS * MAX_SHM_PAGES] =
+                [None; MAX_TASKS * MAX_SHM_PAGES];
+            let mut n = 0;
+            for as_idx in 0..self.num_tasks {
+                if self.address_spaces[as_idx].kind != AddressSpaceKind::User {
+                    continue;
+                }
+                self.address_spaces[as_idx].for_each_mapping(|m| {
+                    if n < victims.len() && seg.frames().any(|f| f == m.frame) {
+                        victims[n] = Some((as_idx, m.page, m.frame));
+                        n += 1;
+                    }
+                });
+            }
+
+            for (as_idx, page, frame) in victims.iter().take(n).flatten().copied() {
+                let action = MemAction::Unmap { page };
+                if self.aspace_apply(as_idx, action).is_err() {
+                    continue;
+                }
+                self.unref_unmapped_frame(frame);
+                if let Some(root) = self.address_spaces[as_idx].root_page_frame {
+                    if unsafe { self.apply_in_root(as_idx, root, action) }.is_err() {
+                        LOG.error("shm: arch unmap failed; abort (fail-stop)");
+                        LOG.info_u64("as_idx", as_idx as u64);
+                        LOG.info_u64("virt_page_index", page.number);
+                        panic!("shm: arch unmap failed");
+                    }
+                }
+            }
+
+            let revoked = self.revoke_object_handles(KernelObject::Shm(ShmId(slot)));
+            self.shm[slot] = ShmSegment::EMPTY;
+            for frame in seg.frames() {
+                self.release_frame_if_unreferenced(frame);
+            }
+
+            LOG.info("shm: owner dead; segment destroyed");
+            LOG.info_u64("task_id", dead_id.0);
+            LOG.info_u64("shm_id", slot as u64);
+            LOG.info_u64("unmapped", n as u64);
+            LOG.info_u64("revoked_handles", revoked as u64);
+
+            self.push_event(LogEvent::ShmDestroyed { owner: dead_id, shm: ShmId(slot), unmapped: n });
+        }
+    }
+
+    /// 使用中の segment: owner が生きていて、pages 枚の確保中フレームを持ち、他の segment と共有しない
+    /// 未使用の segment: owner / フレームを持たない
+    #[spec("INV-MEM-008")]
+    pub(super) fn check_shm_invariants(&self) {
+        for (slot, seg) in self.shm.iter().enumerate() {
+            if !seg.allocated {
+                if seg.owner.is_some() || seg.pages != 0 || seg.frames.iter().any(|f| f.is_some()) {
+                    self.invariant_violated(
+                        InvariantId::Shm,
+                        None,
+                        Some(slot as u64),
+                        "INVARIANT VIOLATION: unallocated shm segment has state",
+                    );
+                    LOG.info_u64("shm_id", slot as u64);
+                }
+                continue;
+            }
+
+            let alive = seg.owner.is_some_and(|owner| {
+                (0..self.num_tasks).any(|i| self.tasks[i].id == owner && self.tasks[i].state != TaskState::Dead)
+            });
+            if !alive {
+                self.invariant_violated(
+                    InvariantId::Shm,
+                    seg.owner,
+                    Some(slot as u64),
+                    "INVARIANT VIOLATION: shm segment has dead owner",
+                );
+                LOG.info_u64("shm_id", slot as u64);
+            }
+
+            if seg.pages == 0 || seg.pages > MAX_SHM_PAGES || seg.frames().count() != seg.pages {
+                self.invariant_violated(
+                    InvariantId::Shm,
+                    seg.owner,
+                    Some(slot as u64),
+                    "INVARIANT VIOLATION: shm segment frame count != pages",
+                );
+                LOG.info_u64("shm_id", slot as u64);
+                LOG.info_u64("pages", seg.pages as u64);
+            }
+
+            for frame in seg.frames() {
+                if !self.phys_mem.is_frame_allocated(to_arch_frame(frame)) {
+                    self.invariant_violated(
+                        InvariantId::Shm,
+                        seg.owner,
+                        Some(frame.number),
+                        "INVARIANT VIOLATION: shm frame is not allocated",
+                    );
+                    LOG.info_u64("shm_id", slot as u64);
+                    LOG.info_u64("phys_frame_index", frame.number);
+                }
+                let owners = self.shm.iter().filter(|s| s.allocated).filter(|s| s.frames().any(|f| f == frame)).count();
+                if owners != 1 {
+                    self.invariant_violated(
+                        InvariantId::Shm,
+                        seg.owner,
+                        Some(frame.number),
+                        "INVARIANT VIOLATION: shm frame shared between segments",
+                    );
+                    LOG.info_u64("phys_frame_index", frame.number);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,74 @@
+// kernel/src/kernel/shutdown.rs
+//
+// 役割:
+// - Shutdown syscall: run を片付けてから電源を切る（halt_loop で止まり続けない）。
+//
+// 手順（syscall_shutdown。成功すれば戻らない）:
+// 1. 全 endpoint を close する（待ち手は ENDPOINT_CLOSED で救済。close_endpoint_and_rescue_waiters）
+// 2. 呼んだ task と idle 以外の生きている task を kill する（TaskKillReason::Shutdown。正規の kill 経路）
+// 3. tick ループの終わりと同じ後始末: demo の集計 → dump_events（Event Log を serial に出し切る）→ verdict
+// 4. arch::power::power_off（isa-debug-exit → ACPI S5 → halt）。code が 0 で verdict が PASS なら成功の終了値
+//
+// 方針:
+// - 呼べるのは kernel task だけ（user task は SYSCALL_ERR_FORBIDDEN。何も片付けない）
+// - kill で user task が全滅しても「全滅で dump + halt」（maybe_halt_if_no_user_tasks）は走らせない
+//   （dump は手順 3 で 1 回だけ。should_halt を立てて verdict を FAIL にしない）
+// - event は新設しない（kill は TaskKilled、close はそれぞれの経路の event のまま）
+//
+// やらないこと:
+// - 再起動、user task への shutdown の通知（先に殺す。後始末を待つ protocol は無い）
+
+use super::abi::SYSCALL_ERR_FORBIDDEN;
+use super::{
+    AddressSpaceKind, EndpointId, KernelState, TaskKillReason, TaskState, IDLE_TASK_INDEX, KERNEL_ASID_INDEX,
+    MAX_ENDPOINTS,
+};
+use crate::arch::ops::{Arch, ArchOps};
+use crate::logging;
+
+impl KernelState {
+    pub(super) fn syscall_shutdown(&mut self, idx: usize, code: u64) -> u64 {
+        let as_idx = self.tasks[idx].address_space_id.0;
+        if as_idx >= self.num_tasks || self.address_spaces[as_idx].kind != AddressSpaceKind::Kernel {
+            logging::error("shutdown: only a kernel task can shut down");
+            logging::info_u64("task_id", self.tasks[idx].id.0);
+            return SYSCALL_ERR_FORBIDDEN;
+        }
+
+        let by = self.tasks[idx].id;
+        logging::info("shutdown: requested");
+        logging::info_u64("task_id", by.0);
+        logging::info_u64("shutdown_code", code);
+
+        // 1) endpoint を全部 close（未使用の動的 slot は closed のままなので何も起きない）
+        for ep in 0..MAX_ENDPOINTS {
+            self.close_endpoint_and_rescue_waiters(EndpointId(ep));
+        }
+
+        // 2) 残りの task を kill（全滅時の dump + halt は抑える）
+        self.halt_dumped_no_user_tasks = true;
+        let mut killed: u64 = 0;
+        for i in 0..self.num_tasks {
+            if i == idx || i == IDLE_TASK_INDEX || self.tasks[i].state == TaskState::Dead {
+                continue;
+            }
+            self.kill_task(i, TaskKillReason::Shutdown { by });
+            killed += 1;
+        }
+        logging::info_u64("shutdown_tasks_killed", killed);
+
+        // 3) tick ループの終わりと同じ後始末（CR3 / VGA は kernel に寄せてから出す）
+        if let Some(kernel_root) = self.address_spaces[KERNEL_ASID_INDEX].root_page_frame {
+            Arch::switch_address_space_quiet(kernel_root);
+        }
+        logging::set_vga_enabled(true);
+
+        super::demo::on_run_finished(self);
+        self.dump_events();
+        let verdict = self.evaluate_verdict();
+
+        // 4) 電源を切る
+        logging::info("shutdown: powering off");
+        crate::arch::power::power_off(code == 0 && verdict.passed())
+    }
+}
@@ -0,0 +1,246 @@
+// kernel/src/kernel/smp.rs
+//
+// 役割（feature smp）:
+// - entry.rs から AP を起こし（arch::smp::start_aps）、結果を KernelState に残す。
+// - AP が seal の後に with_kernel_state（BKL）経由で check-in する先（note_cpu_online）。
+// - AP で task を走らせる（ap_step）。arch::smp の AP loop が BSP の tick（timer::ticks）が進むたびに 1 回呼ぶ。
+//
+// AP の step（BKL を持った 1 回分）:
+// - この CPU の task（ap_current）が無ければ ready_queue から policy で 1 つ取って dispatch する（queue は全 CPU で 1 本）
+// - current_task を一時的にその task に差し替え、BSP の tick と同じ経路（user_program の syscall 発行 → 実行、
+//   runtime、quantum）を通す。block / quantum で別の task に替わればそれを持ち続け、idle に落ちたら手放す
+// - 終わったら current_task（BSP の task）と VGA を戻し、AP の CR3 は kernel root に戻す
+//   → AP が持つ task の AddressSpace が BSP 側で壊されても、AP は古い root を掴んでいない（次の step で読み直す）
+// - 時間（tick_count / kernel clock / CPU 時間の内訳）と invariant / watchdog は BSP の tick だけが進める・見る
+//
+// 方針:
+// - KernelState の共有は BKL（state_ref。IRQ を止めて取る・再入しない）で守る。AP 同士・BSP と並んで走るのは BKL の外だけ
+// - 何 CPU が上がったか・どの task がどの CPU で走ったかは QEMU の -smp と timing 次第なので、
+//   smp では wire / state hash の再現性は無い（ap_steps は Counters Dump だけ）
+// - 終わり方: main が run の後に stop_secondary_cpus で止め（各 AP は持っていた task を Ready に戻して park）、
+//   それから BKL を取って dump する
+//
+// 制約:
+// - kstack_switch / ring3_tasks / ring3 デモ / synthetic_tick / replay / scenario_suite とは併用しない（compile_error）
+//   * main が with_kernel_state を通らずに kstate を触る区間や、BKL を持ったままの stack 切替があるため
+//
+// やらないこと:
+// - CPU ごとの run queue / affinity / 負荷分散（BKL の下では 1 本の ready_queue を取り合えば足りる）
+// - AP への IPI / LAPIC timer（AP は BSP の tick を spin で見て進む）
+// - AP の check-in を待つこと（来なくても run は進む。dump で smp_checked_in を見る）
+
+#[cfg(any(feature = "kstack_switch", feature = "ring3_tasks"))]
+compile_error!("smp cannot be combined with kstack_switch / ring3_tasks (the BKL is not held across stack switches)");
+
+#[cfg(any(feature = "synthetic_tick", feature = "replay", feature = "scenario_suite"))]
+compile_error!("smp requires the PIT-driven tick (not synthetic_tick / replay / scenario_suite)");
+
+#[cfg(any(feature = "ring3_demo", feature = "ring3_mailbox", feature = "ring3_mailbox_loop"))]
+compile_error!("smp cannot be combined with ring3 demo features");
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use super::{KernelState, LogEvent, TaskState, IDLE_TASK_INDEX, KERNEL_ASID_INDEX};
+use crate::arch::ops::{Arch, ArchOps};
+use crate::mem::address_space::AddressSpaceKind;
+use crate::{arch, logging};
+
+/// main が run の後に立てる（AP は次の step で task を返して park する）
+static STOPPING: AtomicBool = AtomicBool::new(false);
+
+/// stop_secondary_cpus が AP の park を待つ上限（ms）
+const STOP_WAIT_MS: u64 = 100;
+
+/// AP の起動結果と check-in の集計
+pub(super) struct SmpState {
+    /// start_aps で online になった CPU（bit = cpu 番号）
+    online_mask: u64,
+    cpus_in_madt: u64,
+    failed: u64,
+    ignored: u64,
+    /// seal の後に check-in した AP の bitmask（bit = cpu 番号）
+    checked_in: u64,
+    /// AP ごとに持っている task（index。BSP の分は current_task なので [0] は使わない）
+    ap_current: [Option<usize>; arch::smp::MAX_CPUS],
+    /// AP ごとに task を走らせた step の数
+    ap_steps: [u64; arch::smp::MAX_CPUS],
+}
+
+impl SmpState {
+    pub(super) const fn new() -> Self {
+        Self {
+            online_mask: 1,
+            cpus_in_madt: 1,
+            failed: 0,
+            ignored: 0,
+            checked_in: 0,
+            ap_current: [None; arch::smp::MAX_CPUS],
+            ap_steps: [0; arch::smp::MAX_CPUS],
+        }
+    }
+}
+
+impl KernelState {
+    /// AP を起こす（entry.rs から。bootstrap の後・seal の前に 1 回）
+    pub(super) fn start_secondary_cpus(&mut self) {
+        let report = arch::smp::start_aps(&mut self.phys_mem);
+        self.smp.online_mask = report.online_mask;
+        self.smp.cpus_in_madt = report.cpus_in_madt as u64;
+        self.smp.failed = report.failed as u64;
+        self.smp.ignored = report.ignored as u64;
+
+        logging::info("smp: secondary CPUs started");
+        logging::info_u64("smp_cpus_in_madt", self.smp.cpus_in_madt);
+        logging::info_hex("smp_online_mask", self.smp.online_mask);
+        logging::info_u64("smp_failed", self.smp.failed);
+        logging::info_u64("smp_ignored", self.smp.ignored);
+    }
+
+    /// AP の check-in（arch::smp の AP 入口から with_kernel_state 経由。BKL を持っている）
+    pub(crate) fn note_cpu_online(&mut self, cpu: usize) {
+        if cpu < arch::smp::MAX_CPUS {
+            self.smp.checked_in |= 1u64 << cpu;
+        }
+    }
+
+    /// AP の 1 step（arch::smp の AP loop から with_kernel_state 経由。BKL を持っている）
+    /// - 戻り値: 続けるか（halt / stop の後は false。持っていた task は Ready に戻してある）
+    pub(crate) fn ap_step(&mut self, cpu: usize) -> bool {
+        if cpu == 0 || cpu >= arch::smp::MAX_CPUS {
+            return false;
+        }
+        if self.should_halt || STOPPING.load(Ordering::SeqCst) {
+            self.release_ap_task(cpu);
+            return false;
+        }
+
+        let bsp_current = self.current_task;
+        let bsp_syscall_since_account = self.syscall_since_account;
+
+        let Some(ran) = self.ap_take_task(cpu) else {
+            return true;
+        };
+        self.smp.ap_steps[cpu] += 1;
+
+        // BSP の tick_body と同じ順（syscall の発行 → 実行 → runtime → quantum）。時間と CPU 時間の内訳は進めない
+        self.user_step_issue_syscall(ran);
+        if ran == self.current_task {
+            self.handle_pending_syscall_if_any();
+        }
+        self.update_runtime_for(ran);
+        if ran == self.current_task && self.tasks[ran].state == TaskState::Running {
+            self.update_time_slice_for_and_maybe_schedule(ran);
+        }
+
+        // block / quantum で替わった先を持ち続ける。idle に落ちたら（ready が空）手放す
+        let now = self.current_task;
+        self.smp.ap_current[cpu] =
+            if now != IDLE_TASK_INDEX && self.tasks[now].state == TaskState::Running { Some(now) } else { None };
+        // idle は BSP のもの（BSP が idle でなければ Ready に戻す。二重 Running にしない）
+        if now == IDLE_TASK_INDEX && bsp_current != IDLE_TASK_INDEX {
+            self.tasks[IDLE_TASK_INDEX].state = TaskState::Ready;
+        }
+
+        self.current_task = bsp_current;
+        self.syscall_since_account = bsp_syscall_since_account;
+        self.ap_leave_step();
+        true
+    }
+
+    /// この CPU の task を current にする（無ければ ready_queue から取って dispatch。取れなければ None）
+    fn ap_take_task(&mut self, cpu: usize) -> Option<usize> {
+        // BSP 側で止められた / 殺された task は持たない
+        if let Some(idx) = self.smp.ap_current[cpu] {
+            if idx < self.num_tasks && self.tasks[idx].state == TaskState::Running {
+                self.current_task = idx;
+                let as_idx = self.tasks[idx].address_space_id.0;
+                if self.address_spaces[as_idx].kind == AddressSpaceKind::User {
+                    Arch::switch_address_space(self.address_spaces[as_idx].root_page_frame);
+                    self.flush_deferred_tlb(as_idx);
+                }
+                return Some(idx);
+            }
+            self.smp.ap_current[cpu] = None;
+        }
+
+        self.compact_ready_queue_to_ready_only();
+        if self.rq_len == 0 {
+            return None;
+        }
+        let next = self.dequeue_ready_by_policy()?;
+        if next >= self.num_tasks {
+            return None;
+        }
+        self.ipc_handoff_record_scheduled(next);
+        // AP は task を持っていなかった（idle から来たものとして数える）
+        self.dispatch_task(IDLE_TASK_INDEX, next);
+        self.smp.ap_current[cpu] = Some(next);
+        Some(next)
+    }
+
+    /// step の後始末: AP の CR3 を kernel root に、VGA を BSP の task に合わせて戻す
+    fn ap_leave_step(&mut self) {
+        let kernel_root = self.address_spaces[KERNEL_ASID_INDEX]
+            .root_page_frame
+            .expect("kernel root_page_frame must exist");
+        Arch::switch_address_space_quiet(kernel_root);
+
+        let as_idx = self.tasks[self.current_task].address_space_id.0;
+        logging::set_vga_enabled(self.address_spaces[as_idx].kind == AddressSpaceKind::Kernel);
+    }
+
+    /// AP が持っている task を Ready に戻して ready_queue に入れる（stop / halt のとき）
+    fn release_ap_task(&mut self, cpu: usize) {
+        let Some(idx) = self.smp.ap_current[cpu].take() else {
+            return;
+        };
+        if idx < self.num_tasks && self.tasks[idx].state == TaskState::Running {
+            self.tasks[idx].state = TaskState::Ready;
+            self.tasks[idx].time_slice_used = 0;
+            self.push_event(LogEvent::TaskStateChanged(self.tasks[idx].id, TaskState::Ready));
+            self.enqueue_ready(idx);
+        }
+    }
+
+    /// 止めきれなかった AP の task も Ready に戻す（entry.rs が stop_secondary_cpus の後、BKL を持って呼ぶ）
+    pub(super) fn reclaim_ap_tasks(&mut self) {
+        for cpu in 1..arch::smp::MAX_CPUS {
+            self.release_ap_task(cpu);
+        }
+    }
+
+    /// Counters Dump の smp 部分
+    pub(super) fn dump_smp(&self) {
+        let (acquired, contended) = super::state_ref::bkl_stats();
+        logging::info_hex("smp_online_mask", self.smp.online_mask);
+        logging::info_hex("smp_checked_in", self.smp.checked_in);
+        logging::info_u64("smp_failed", self.smp.failed);
+        logging::info_u64("bkl_acquired", acquired);
+        logging::info_u64("bkl_contended", contended);
+        for cpu in 1..arch::smp::MAX_CPUS {
+            if self.smp.online_mask & (1u64 << cpu) != 0 {
+                logging::info_u64("smp_ap_cpu", cpu as u64);
+                logging::info_u64("smp_ap_steps", self.smp.ap_steps[cpu]);
+            }
+        }
+    }
+}
+
+/// stop_secondary_cpus の後か（arch::smp の AP loop が tick 待ちの間に見る）
+pub fn secondary_cpus_stopping() -> bool {
+    STOPPING.load(Ordering::SeqCst)
+}
+
+/// AP を止める（entry.rs の main が run の後、BKL を取る前に呼ぶ）
+/// - 各 AP は次の step で持っていた task を Ready に戻して park する。STOP_WAIT_MS だけ待つ
+/// - 待ちきれなかった AP の task は、BKL を取った後の reclaim_ap_tasks が戻す
+pub(super) fn stop_secondary_cpus() {
+    STOPPING.store(true, Ordering::SeqCst);
+    for _ in 0..STOP_WAIT_MS {
+        if arch::smp::all_aps_parked() {
+            return;
+        }
+        arch::timer::pit_delay_us(1_000);
+    }
+    logging::error("smp: some APs did not park in time");
+}
@@ -0,0 +1,160 @@
+// kernel/src/kernel/state_hash.rs
+//
+// 役割:
+// - 抽象状態（形式モデル側 TLA+ / Coq と対応させる部分）の正準な直列化と、その FNV-64 hash。
+// - tick ごとに `state_hash = N` を 1 行出す。モデル側で同じ直列化をすれば、tick 単位で実装と突き合わせられる。
+//
+// 正準な直列化（u64 の並び。順序も仕様の一部。変えたら CANONICAL_STATE_VERSION を上げる）:
+// - version, current_task の TaskId
+// - task index 順に: TaskId / state / 実効 priority / base_priority / blocked（code, arg, partner）
+//   * state / blocked の code は abi の STATE_* / BLOCKED_*（wire の TaskInfo と同じ）。無い値は WIRE_NONE
+// - ready_queue（長さ + 先頭から TaskId）、wait_queue（長さ + TaskId）
+// - endpoint id 順に: allocated / closed / owner / recv_waiter / send_queue（長さ + TaskId）/ reply_queue（長さ + TaskId）
+//   / buffer（slot 数 + 長さ + 古い順に送った TaskId と MR0）
+// - AddressSpace index 順に: mapping 数 / region 数
+//
+// 含めないもの（実装の都合で、モデルに対応物が無い）:
+// - tick_count / runtime / time_slice などの計測値、event_log、counters
+// - フレーム番号・root の物理アドレス（割り当て順に依存する）
+// - keyboard の入力キュー（外から非決定的に届く。input.rs）
+//
+// 出力:
+// - 毎 tick: `state_hash = N`（hash は各 word の little endian 8 byte を順に FNV-1a 64 に通したもの）
+// - dump_canonical_state(): `=== Canonical State ===` に word を 1 つずつ key 付きで出す（on demand）
+//   * feature state_dump_verbose なら毎 tick hash の後に出す
+
+use super::{abi, BlockedReason, KernelState, TaskId, TaskState};
+use crate::logging;
+
+/// 直列化の版（word の並びを変えたら上げる）
+pub(super) const CANONICAL_STATE_VERSION: u64 = 2;
+
+const FNV64_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV64_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// FNV-1a 64（checkpoint.rs の mapping の指紋も同じもので取る）
+pub(super) struct Fnv64(u64);
+
+impl Fnv64 {
+    pub(super) const fn new() -> Self {
+        Fnv64(FNV64_OFFSET)
+    }
+
+    pub(super) fn finish(&self) -> u64 {
+        self.0
+    }
+
+    pub(super) fn write_u64(&mut self, v: u64) {
+        for b in v.to_le_bytes() {
+            self.0 ^= b as u64;
+            self.0 = self.0.wrapping_mul(FNV64_PRIME);
+        }
+    }
+}
+
+pub(super) fn state_code(s: TaskState) -> u64 {
+    match s {
+        TaskState::Ready => abi::STATE_READY,
+        TaskState::Running => abi::STATE_RUNNING,
+        TaskState::Blocked => abi::STATE_BLOCKED,
+        TaskState::Dead => abi::STATE_DEAD,
+        TaskState::Suspended => abi::STATE_SUSPENDED,
+    }
+}
+
+/// (code, arg, partner)。arg は ep / ep_mask / notification id
+pub(super) fn blocked_words(r: Option<BlockedReason>) -> (u64, u64, u64) {
+    match r {
+        None => (abi::BLOCKED_NONE, abi::WIRE_NONE, abi::WIRE_NONE),
+        Some(BlockedReason::Sleep) => (abi::BLOCKED_SLEEP, abi::WIRE_NONE, abi::WIRE_NONE),
+        Some(BlockedReason::IpcRecv { ep }) => (abi::BLOCKED_IPC_RECV, ep.0 as u64, abi::WIRE_NONE),
+        Some(BlockedReason::IpcRecvAny { ep_mask }) => (abi::BLOCKED_IPC_RECV_ANY, ep_mask, abi::WIRE_NONE),
+        Some(BlockedReason::IpcSend { ep }) => (abi::BLOCKED_IPC_SEND, ep.0 as u64, abi::WIRE_NONE),
+        Some(BlockedReason::IpcReply { partner, ep }) => (abi::BLOCKED_IPC_REPLY, ep.0 as u64, partner.0),
+        Some(BlockedReason::NotifyWait { ntfn }) => (abi::BLOCKED_NOTIFY_WAIT, ntfn.0 as u64, abi::WIRE_NONE),
+        Some(BlockedReason::FaultWait { ep }) => (abi::BLOCKED_FAULT_WAIT, ep.0 as u64, abi::WIRE_NONE),
+    }
+}
+
+impl KernelState {
+    fn task_id_word(&self, idx: usize) -> u64 {
+        if idx < self.num_tasks { self.tasks[idx].id.0 } else { abi::WIRE_NONE }
+    }
+
+    /// 正準な順序で (key, word) を f に渡す（hash と verbose dump の共通部分）
+    fn visit_canonical_state(&self, f: &mut impl FnMut(&'static str, u64)) {
+        f("version", CANONICAL_STATE_VERSION);
+        f("current_task", self.task_id_word(self.current_task));
+
+        for t in self.tasks.iter().take(self.num_tasks) {
+            let (code, arg, partner) = blocked_words(t.blocked_reason);
+            f("task_id", t.id.0);
+            f("task_state", state_code(t.state));
+            f("task_priority", t.priority as u64);
+            f("task_base_priority", t.base_priority as u64);
+            f("task_blocked", code);
+            f("task_blocked_arg", arg);
+            f("task_blocked_partner", partner);
+        }
+
+        f("rq_len", self.rq_len as u64);
+        for &idx in &self.ready_queue[..self.rq_len] {
+            f("rq_task", self.task_id_word(idx));
+        }
+        f("wq_len", self.wq_len as u64);
+        for &idx in &self.wait_queue[..self.wq_len] {
+            f("wq_task", self.task_id_word(idx));
+        }
+
+        for e in self.endpoints.iter() {
+            f("ep", e.id.0 as u64);
+            f("ep_allocated", e.allocated as u64);
+            f("ep_closed", e.is_closed as u64);
+            f("ep_owner", abi::opt_word(e.owner.map(|t: TaskId| t.0)));
+            f("ep_recv_waiter", e.recv_waiter.map_or(abi::WIRE_NONE, |i| self.task_id_word(i)));
+            f("ep_sq_len", e.send_queue.len() as u64);
+            for idx in e.send_queue.iter() {
+                f("ep_sq_task", self.task_id_word(idx));
+            }
+            f("ep_rq_len", e.reply_queue.len() as u64);
+            for idx in e.reply_queue.iter() {
+                f("ep_rq_task", self.task_id_word(idx));
+            }
+            f("ep_buf_cap", e.buffer.capacity() as u64);
+            f("ep_buf_len", e.buffer.len() as u64);
+            for (from, msg) in e.buffer.iter() {
+                f("ep_buf_from", from.0);
+                f("ep_buf_mr0", msg.mr0());
+            }
+        }
+
+        for aspace in self.address_spaces.iter().take(self.num_tasks) {
+            f("as_mappings", aspace.mapping_count() as u64);
+            f("as_regions", aspace.region_count() as u64);
+        }
+    }
+
+    /// 抽象状態の FNV-64 hash
+    pub fn state_hash(&self) -> u64 {
+        let mut h = Fnv64::new();
+        self.visit_canonical_state(&mut |_, v| h.write_u64(v));
+        h.finish()
+    }
+
+    /// tick の末尾: `state_hash = N`（state_dump_verbose なら続けて全体も）
+    pub(super) fn emit_state_hash(&self) {
+        logging::info_u64("state_hash", self.state_hash());
+
+        #[cfg(feature = "state_dump_verbose")]
+        self.dump_canonical_state();
+    }
+
+    /// 正準な直列化を key 付きで全部出す（hash が食い違った tick をモデルと突き合わせる用）
+    #[cfg_attr(not(feature = "state_dump_verbose"), allow(dead_code))]
+    pub fn dump_canonical_state(&self) {
+        logging::info("=== Canonical State ===");
+        self.visit_canonical_state(&mut |k, v| logging::info_u64(k, v));
+        logging::info_u64("state_hash", self.state_hash());
+        logging::info("=== End of Canonical State ===");
+    }
+}
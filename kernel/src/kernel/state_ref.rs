@@ -7,40 +7,167 @@
 // - KernelState の raw pointer(アドレス) を登録する。
 // - 呼び出し側は with_kernel_state() 経由でのみ &mut KernelState を得る。
 //
+// ★二段階ハンドオフ（register -> seal）:
+// - register_kernel_state(): アドレスを登録するだけ（まだ割り込み側には見せない）
+// - seal_kernel_state(): bootstrap まで全 boot phase が終わった後に 1 回呼ぶ
+// - with_kernel_state() は sealed になるまで None を返す
+//   → 構築途中の KernelState を割り込み側が観測するレースを「構造的に」起こさない
+// - 外部 IRQ（timer）の unmask は arch::interrupts::allow_external_irqs() に一本化し、
+//   そこでも sealed を確認する（seal 前に unmask できない）
+//
+// ★big kernel lock（feature smp）:
+// - with_kernel_state() は KernelState を触る間 BKL を持つ（BSP の tick と AP の task step が重ならない）
+// - owner は APIC ID + 1（0 = 空き）。取る前に IF を落とし、解放で元に戻す
+//   → 持っている間にこの CPU で IRQ は入らない（timer IRQ が 2 本目の &mut KernelState を作らない）
+// - 再入しない: 同じ CPU が持ったまま with_kernel_state に来たら（例外の経路）None を返し、
+//   lock_kernel_state() に来たら bug として panic する
+// - BSP の main が with_kernel_state を通らずに触る区間（run の後の dump 等）は lock_kernel_state() で囲む
+//
 // やらないこと:
-// - 複雑な同期（単一コア前提・割り込み中の短時間利用のみ）
+// - 複雑な同期（BKL 1 本だけ。細かい lock は無い。run queue も全 CPU で 1 本）
 // - KernelState の所有権移動（所有は entry.rs 側のまま）
 
-use core::sync::atomic::{AtomicU64, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 
 use super::KernelState;
+use crate::{arch, logging};
 
 // 0 なら未登録
 static KERNEL_STATE_ADDR: AtomicU64 = AtomicU64::new(0);
 
-/// KernelState の参照を登録する（entry.rs から呼ぶ）
+// true になるまで割り込み側からは見せない
+static KERNEL_STATE_SEALED: AtomicBool = AtomicBool::new(false);
+
+/// KernelState の参照を登録する（entry.rs から呼ぶ）。
+/// - この時点では unsealed（with_kernel_state は None を返す）
 pub fn register_kernel_state(ks: &mut KernelState) {
+    KERNEL_STATE_SEALED.store(false, Ordering::SeqCst);
     let addr = ks as *mut KernelState as u64;
     KERNEL_STATE_ADDR.store(addr, Ordering::SeqCst);
 }
 
-/// KernelState の参照を解除する（必要なら）
-#[allow(dead_code)]
+/// 全 boot phase 完了後に呼ぶ。以後 with_kernel_state() が使える。
+/// - 未登録なら seal しない（ログのみ）
+/// - seal 前に IRQ が有効になっていたら仕様違反としてログに残す
+pub fn seal_kernel_state() {
+    if KERNEL_STATE_ADDR.load(Ordering::SeqCst) == 0 {
+        logging::error("seal_kernel_state: not registered; ignore");
+        return;
+    }
+
+    if arch::cpu::irqs_enabled() {
+        logging::error("seal_kernel_state: IRQs were enabled before seal");
+    }
+
+    KERNEL_STATE_SEALED.store(true, Ordering::SeqCst);
+    logging::info("kernel_state sealed (interrupt context may access)");
+}
+
+/// seal 済みか（arch 側の IRQ unmask 判定で使う）
+pub fn is_kernel_state_sealed() -> bool {
+    KERNEL_STATE_SEALED.load(Ordering::SeqCst) && KERNEL_STATE_ADDR.load(Ordering::SeqCst) != 0
+}
+
+/// KernelState の参照を解除する（scenario_suite が KernelState を作り直すとき）
+#[cfg_attr(not(feature = "scenario_suite"), allow(dead_code))]
 pub fn unregister_kernel_state() {
+    KERNEL_STATE_SEALED.store(false, Ordering::SeqCst);
     KERNEL_STATE_ADDR.store(0, Ordering::SeqCst);
 }
 
 /// KernelState を一時的に借用して処理する（arch 側はこれだけ使う）
+/// - seal 前は None（構築途中の state を見せない）
+/// - feature smp: 借用の間 BKL を持つ（この CPU が既に持っていれば None。2 本目の &mut は作らない）
 pub fn with_kernel_state<R>(f: impl FnOnce(&mut KernelState) -> R) -> Option<R> {
+    if !KERNEL_STATE_SEALED.load(Ordering::SeqCst) {
+        return None;
+    }
+
     let addr = KERNEL_STATE_ADDR.load(Ordering::SeqCst);
     if addr == 0 {
         return None;
     }
 
+    #[cfg(feature = "smp")]
+    if holds_kernel_state_lock() {
+        logging::error("with_kernel_state: BKL already held by this CPU; refuse re-entry");
+        return None;
+    }
+    #[cfg(feature = "smp")]
+    let _bkl = lock_kernel_state();
+
     let p = addr as *mut KernelState;
 
     // Safety:
     // - register_kernel_state() は KernelState の生存期間中のみ呼ばれる前提
+    // - sealed なので bootstrap まで完了済み
     // - 割り込みハンドラからの短時間利用に限定
     Some(unsafe { f(&mut *p) })
 }
+
+// -----------------------------------------------------------------------------
+// big kernel lock（feature smp）
+// -----------------------------------------------------------------------------
+
+#[cfg(feature = "smp")]
+static BKL_OWNER: AtomicU64 = AtomicU64::new(0);
+#[cfg(feature = "smp")]
+static BKL_ACQUIRED: AtomicU64 = AtomicU64::new(0);
+#[cfg(feature = "smp")]
+static BKL_CONTENDED: AtomicU64 = AtomicU64::new(0);
+
+/// BKL を持っている間の guard（drop で解放し、IF を取る前の状態に戻す）
+#[cfg(feature = "smp")]
+pub struct KernelStateLock {
+    irqs_were_enabled: bool,
+}
+
+#[cfg(feature = "smp")]
+fn bkl_owner_id() -> u64 {
+    arch::smp::current_apic_id() as u64 + 1
+}
+
+/// この CPU が BKL を持っているか
+#[cfg(feature = "smp")]
+pub fn holds_kernel_state_lock() -> bool {
+    BKL_OWNER.load(Ordering::SeqCst) == bkl_owner_id()
+}
+
+/// BKL を取る（IF を落としてから。同じ CPU が持っていれば panic）
+#[cfg(feature = "smp")]
+pub fn lock_kernel_state() -> KernelStateLock {
+    let irqs_were_enabled = arch::cpu::irqs_enabled();
+    arch::cpu::disable_irqs();
+
+    let me = bkl_owner_id();
+    if BKL_OWNER.load(Ordering::SeqCst) == me {
+        panic!("lock_kernel_state: BKL re-entered on the same CPU");
+    }
+
+    let mut contended = false;
+    while BKL_OWNER.compare_exchange(0, me, Ordering::SeqCst, Ordering::SeqCst).is_err() {
+        contended = true;
+        core::hint::spin_loop();
+    }
+    BKL_ACQUIRED.fetch_add(1, Ordering::SeqCst);
+    if contended {
+        BKL_CONTENDED.fetch_add(1, Ordering::SeqCst);
+    }
+    KernelStateLock { irqs_were_enabled }
+}
+
+#[cfg(feature = "smp")]
+impl Drop for KernelStateLock {
+    fn drop(&mut self) {
+        BKL_OWNER.store(0, Ordering::SeqCst);
+        if self.irqs_were_enabled {
+            arch::cpu::enable_irqs();
+        }
+    }
+}
+
+/// BKL の集計（取得回数, 待たされた回数）。Counters Dump 用
+#[cfg(feature = "smp")]
+pub fn bkl_stats() -> (u64, u64) {
+    (BKL_ACQUIRED.load(Ordering::SeqCst), BKL_CONTENDED.load(Ordering::SeqCst))
+}
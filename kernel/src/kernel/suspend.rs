@@ -0,0 +1,199 @@
+// kernel/src/kernel/suspend.rs
+//
+// 役割:
+// - TaskSuspend / TaskResume syscall: 自分以外の task を止め（TaskState::Suspended）、あとで戻す。
+//
+// 意味論:
+// - Suspended は Blocked とは別の state。ready_queue に居ず、scheduler に選ばれない
+// - TaskSuspend { cap }（cap は task handle。cspace.rs）:
+//   * Ready: ready_queue から外して Suspended
+//   * Blocked: 待ち（blocked_reason と endpoint / notification / wait_queue の登録）を持ったまま Suspended
+// - 止まっている間の待ち:
+//   * reply / timeout / notify / 救済（close・dead partner）で待ちが終わるのは Blocked と同じ。結果は last_* に残り、
+//     blocked_reason が消えるだけで Suspended のまま（wake_task_to_ready）
+//   * recv 待ち（IpcRecv / IpcRecvAny）には届けない: sender は send_queue で待ち（ipc_send_fastpath）、
+//     fault / timer_service / net の通知も未配送のまま残る
+// - TaskResume { cap }:
+//   * 待ちが残っていなければ Ready（ready_queue へ）
+//   * 残っていれば Blocked に戻す。recv 待ちなら止まっている間に溜まったものを ipc_recv の入口と同じ順で
+//     1 つ受け取って起こす（ipc_recv_deferred）
+//
+// 権限:
+// - Control 付きの task handle を持っていること（既定では kernel AS の task と親だけが持つ。cap transfer で渡せる）
+// - 自分 / kernel AS の task（kernel task・idle）/ 死んでいる task は handle があっても対象にならない
+//
+// 戻り値（last_syscall_ret、abi.rs が正本）:
+// - SYSCALL_OK / SYSCALL_ERR_BAD_TASK /
+//   SYSCALL_ERR_BAD_CAP・SYSCALL_ERR_FORBIDDEN・SYSCALL_ERR_WRONG_TYPE（handle の違反。CapError::syscall_code）/
+//   SYSCALL_ERR_TASK_STATE（Suspended の task への TaskSuspend、Suspended でない task への TaskResume）
+//
+// やらないこと:
+// - suspend のネスト（回数は数えない。1 回の TaskResume で戻る）
+// - Suspended の task からの priority inheritance（待ち相手に優先度を貸さない。priority.rs は Blocked だけを見る）
+// - 親が死んだときの自動 resume（kernel task が戻すか、kill で片付ける）
+
+use super::abi::{SYSCALL_ERR_BAD_TASK, SYSCALL_ERR_TASK_STATE, SYSCALL_OK};
+use super::cspace::{CapIndex, CapRights};
+use super::ipc::recv_wait_eps;
+use super::{InvariantId, KernelState, LogEvent, TaskState};
+use spec_macros::spec;
+
+const LOG: crate::logging::Subsystem = crate::logging::Subsystem::Sched;
+
+impl KernelState {
+    /// TaskSuspend / TaskResume の対象の task index（handle の Control も見る）
+    fn suspend_target(&mut self, idx: usize, cap: CapIndex, api_name: &'static str) -> Result<usize, u64> {
+        let t = self.resolve_task_cap(idx, cap, CapRights::CONTROL, api_name)?;
+        if t == idx || self.is_kernel_address_space_of(t) {
+            LOG.error("suspend: target is self or a kernel-space task");
+            LOG.info_u64("task_id", self.tasks[t].id.0);
+            return Err(SYSCALL_ERR_BAD_TASK);
+        }
+        Ok(t)
+    }
+
+    pub(super) fn syscall_task_suspend(&mut self, idx: usize, cap: CapIndex) -> u64 {
+        let t = match self.suspend_target(idx, cap, "task_suspend") {
+            Ok(t) => t,
+            Err(e) => return e,
+        };
+        let task = self.tasks[t].id;
+
+        match self.tasks[t].state {
+            TaskState::Ready => {
+                let _ = self.remove_from_ready_queue(t);
+            }
+            TaskState::Blocked => {}
+            TaskState::Suspended => {
+                LOG.error("task_suspend: already suspended");
+                LOG.info_u64("task_id", task.0);
+                return SYSCALL_ERR_TASK_STATE;
+            }
+            // 自分以外が Running / Dead なことは無い（suspend_target で落ちる）
+            TaskState::Running | TaskState::Dead => return SYSCALL_ERR_BAD_TASK,
+        }
+
+        self.tasks[t].state = TaskState::Suspended;
+        self.tasks[t].time_slice_used = 0;
+        self.counters.tasks_suspended += 1;
+
+        LOG.info("task_suspend: suspended");
+        LOG.info_u64("task_id", task.0);
+        LOG.info_u64("by_task_id", self.tasks[idx].id.0);
+        LOG.info_u64("kept_wait", self.tasks[t].blocked_reason.is_some() as u64);
+
+        self.push_event(LogEvent::TaskStateChanged(task, TaskState::Suspended));
+
+        // 止まった task は待ち相手に優先度を貸さない
+        self.refresh_priority_inheritance();
+        SYSCALL_OK
+    }
+
+    pub(super) fn syscall_task_resume(&mut self, idx: usize, cap: CapIndex) -> u64 {
+        let t = match self.suspend_target(idx, cap, "task_resume") {
+            Ok(t) => t,
+            Err(e) => return e,
+        };
+        let task = self.tasks[t].id;
+        if self.tasks[t].state != TaskState::Suspended {
+            LOG.error("task_resume: task is not suspended");
+            LOG.info_u64("task_id", task.0);
+            return SYSCALL_ERR_TASK_STATE;
+        }
+
+        self.counters.tasks_resumed += 1;
+        LOG.info("task_resume: resumed");
+        LOG.info_u64("task_id", task.0);
+        LOG.info_u64("by_task_id", self.tasks[idx].id.0);
+
+        if self.tasks[t].blocked_reason.is_none() {
+            self.tasks[t].state = TaskState::Ready;
+            self.push_event(LogEvent::TaskStateChanged(task, TaskState::Ready));
+            self.enqueue_ready(t);
+        } else {
+            // 待ちに戻す。recv 待ちなら止まっている間に溜まった sender / 通知を受け取って起こす
+            self.tasks[t].state = TaskState::Blocked;
+            self.push_event(LogEvent::TaskStateChanged(task, TaskState::Blocked));
+            if self.ipc_recv_deferred(t) {
+                LOG.info("task_resume: delivered what arrived while suspended");
+                LOG.info_u64("task_id", task.0);
+                self.wake_task_to_ready(t);
+            }
+        }
+
+        self.refresh_priority_inheritance();
+        SYSCALL_OK
+    }
+
+    /// Suspended の task は user task で、ready_queue にも current にも居ない。
+    /// recv_waiter が居る endpoint に sender が並んでいるのは、その recv_waiter が Suspended のときだけ。
+    /// Suspended の task を recv_waiter に持つ endpoint は、どれも TaskResume が受け取りに行く endpoint（recv_wait_eps）に入る
+    /// （番号が RECV_ANY_MAX_EP 以上の IpcRecv も同じ）
+    #[spec("INV-TASK-002")]
+    pub(super) fn check_suspend_invariants(&self) {
+        for (idx, t) in self.tasks.iter().enumerate().take(self.num_tasks) {
+            if t.state != TaskState::Suspended {
+                continue;
+            }
+            if self.is_kernel_address_space_of(idx) {
+                self.invariant_violated(
+                    InvariantId::Suspended,
+                    Some(t.id),
+                    None,
+                    "INVARIANT VIOLATION: kernel-space task is SUSPENDED",
+                );
+                LOG.info_u64("task_id", t.id.0);
+            }
+            if self.ready_queue_contains(idx) {
+                self.invariant_violated(
+                    InvariantId::Suspended,
+                    Some(t.id),
+                    None,
+                    "INVARIANT VIOLATION: SUSPENDED task in ready_queue",
+                );
+                LOG.info_u64("task_id", t.id.0);
+            }
+            if idx == self.current_task {
+                self.invariant_violated(
+                    InvariantId::Suspended,
+                    Some(t.id),
+                    None,
+                    "INVARIANT VIOLATION: SUSPENDED task is current_task",
+                );
+                LOG.info_u64("task_id", t.id.0);
+            }
+            for e in self.endpoints.iter().filter(|e| e.recv_waiter == Some(idx)) {
+                if !recv_wait_eps(t.blocked_reason).any(|ep| ep == e.id) {
+                    self.invariant_violated(
+                        InvariantId::Suspended,
+                        Some(t.id),
+                        Some(e.id.0 as u64),
+                        "INVARIANT VIOLATION: SUSPENDED recv waiter's endpoint is not scanned on resume",
+                    );
+                    LOG.info_u64("task_id", t.id.0);
+                    LOG.info_u64("ep_id", e.id.0 as u64);
+                }
+            }
+        }
+
+        for e in self.endpoints.iter() {
+            let Some(w) = e.recv_waiter else {
+                continue;
+            };
+            if e.send_queue.is_empty() || w >= self.num_tasks {
+                continue;
+            }
+            if self.tasks[w].state != TaskState::Suspended {
+                self.invariant_violated(
+                    InvariantId::Suspended,
+                    Some(self.tasks[w].id),
+                    Some(e.id.0 as u64),
+                    "INVARIANT VIOLATION: senders queued behind a recv_waiter that is not SUSPENDED",
+                );
+                LOG.info_u64("task_id", self.tasks[w].id.0);
+                LOG.info_u64("ep_id", e.id.0 as u64);
+                LOG.info_u64("send_queue_len", e.send_queue.len() as u64);
+            }
+        }
+    }
+}
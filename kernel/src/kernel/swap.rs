@@ -0,0 +1,372 @@
+// kernel/src/kernel/swap.rs
+//
+// 役割:
+// - user page の swap out / swap in（formal な探索用の模擬 backing store。disk は持たない）
+//   * swap 領域 = bootstrap で確保して、どこにも map しない物理フレームの列（slot）
+//   * swap out: mapping のフレームを空き slot に写し、AddressSpace を SwappedOut にして実ページテーブルから外す
+//   * swap in: #PF の page が SwappedOut なら、新しいフレームに slot の中身を写して同じ flags で map し直す
+//     （ring3 の #PF handler はそのまま戻り、fault した命令を再実行する）
+//
+// 方針:
+// - swap 領域を確保するのは swap_demo のときだけ（それ以外は slot が無く、swap out は常に失敗する）
+// - 外したフレームは参照が無くなれば返す（release_frame_if_unreferenced）。swap in は新しく確保する
+// - 共有フレーム（参照が 2 以上）は追い出さない（他の AddressSpace から見える中身が割れる）
+//   * shm segment のフレームも（1 つの task だけが map していても、segment がフレームを持っている）
+// - swap in は quota を見ない（追い出す前に quota 内で map されていた page を戻すだけ）
+//   * region が足りず Map できなければ SwappedOut のまま戻し、fault は解決しない（panic しない）
+// - 実ページテーブルの map / unmap や、region 不足以外の理由で map し直せないときも panic しない
+//   * 論理の状態（mapping / SwappedOut / refcount）を操作の前に戻し、task を SwapFailed で kill して SwapError を返す
+//   * 後始末（teardown）が戻した論理の状態から page / slot / frame を片付ける
+// - Unmap（PageUnmap syscall）された SwappedOut の page は slot を返すだけ（実ページテーブルには何も無い）
+//
+// swap_demo の周期デモ:
+// - SWAP_DEMO_PERIOD_TICKS ごとに、current でない ring3 task の code page を追い出す
+//   * current でない task を選ぶのは、CR3 の切替で TLB が捨てられるため（shootdown は持たない）
+//   * その task が次に走ると命令フェッチで #PF → swap in → 再実行
+//
+// 不変条件（INV-MEM-007）:
+// - page は mapped / guard（reserved）/ swapped のどれか 1 つだけ
+// - 使用中の slot ⇔ ちょうど 1 つの SwappedPage がその slot を指す
+// - swap 領域のフレームはどの mapping にも現れない
+//
+// やらないこと:
+// - 追い出す page の選択ポリシー（LRU など）
+// - kernel AS の page の swap
+
+use super::{
+    AddressSpaceError, AddressSpaceKind, InvariantId, KernelState, LogEvent, TaskKillReason, FIRST_USER_ASID_INDEX,
+};
+use crate::arch::ops::{Arch, ArchOps};
+use crate::mem::addr::{PhysFrame, VirtPage, PAGE_SIZE};
+use crate::mem::paging::MemAction;
+use spec_macros::spec;
+
+const LOG: crate::logging::Subsystem = crate::logging::Subsystem::Mem;
+
+/// swap 領域の slot 数（= 確保するフレーム数）
+pub(super) const SWAP_SLOTS: usize = 4;
+
+/// swap_demo: 何 tick ごとに 1 page 追い出すか
+#[cfg(feature = "swap_demo")]
+const SWAP_DEMO_PERIOD_TICKS: u64 = 40;
+
+/// swap out / swap in の失敗（どれも task を TaskKillReason::SwapFailed で kill した後に返す）
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum SwapError {
+    /// swap out: 実ページテーブルから外せなかった
+    ArchUnmap,
+    /// swap in: AddressSpace に map し直せなかった（region 不足以外）
+    Remap,
+    /// swap in: 実ページテーブルに map できなかった
+    ArchMap,
+}
+
+impl SwapError {
+    /// TaskKillReason::SwapFailed の stage（wire / log に出す番号）
+    pub(super) fn code(self) -> u64 {
+        match self {
+            SwapError::ArchUnmap => 1,
+            SwapError::Remap => 2,
+            SwapError::ArchMap => 3,
+        }
+    }
+}
+
+pub(super) struct SwapStore {
+    frames: [Option<PhysFrame>; SWAP_SLOTS],
+    used: [bool; SWAP_SLOTS],
+}
+
+impl SwapStore {
+    pub(super) const fn new() -> Self {
+        Self { frames: [None; SWAP_SLOTS], used: [false; SWAP_SLOTS] }
+    }
+
+    fn alloc_slot(&mut self) -> Option<(usize, PhysFrame)> {
+        for slot in 0..SWAP_SLOTS {
+            if let (Some(frame), false) = (self.frames[slot], self.used[slot]) {
+                self.used[slot] = true;
+                return Some((slot, frame));
+            }
+        }
+        None
+    }
+
+    fn free_slot(&mut self, slot: usize) {
+        if slot < SWAP_SLOTS {
+            self.used[slot] = false;
+        }
+    }
+
+    fn frame_of(&self, slot: usize) -> Option<PhysFrame> {
+        if slot < SWAP_SLOTS { self.frames[slot] } else { None }
+    }
+
+    fn is_swap_frame(&self, frame: PhysFrame) -> bool {
+        self.frames.iter().flatten().any(|f| *f == frame)
+    }
+}
+
+impl KernelState {
+    /// bootstrap: swap 領域のフレームを確保する（map しない。refcount も取らない）
+    #[cfg(feature = "swap_demo")]
+    pub(super) fn reserve_swap_region(&mut self) {
+        let mut reserved = 0u64;
+        for slot in 0..SWAP_SLOTS {
+            let Some(raw) = self.phys_mem.allocate_frame() else {
+                LOG.error("swap: no frame for swap region");
+                break;
+            };
+            self.push_event(LogEvent::FrameAllocated);
+            self.swap.frames[slot] = Some(PhysFrame::from_index(raw.start_address().as_u64() / PAGE_SIZE));
+            reserved += 1;
+        }
+        LOG.info("swap: region reserved");
+        LOG.info_u64("slots", reserved);
+    }
+
+    /// task idx の AddressSpace で page を swap out する（Ok(Some) なら使った slot、Ok(None) は追い出さなかった）
+    /// - 今の呼び出し元は swap_demo の周期デモだけ
+    /// - Err は task を SwapFailed で kill した後（mapping は追い出す前に戻してある）
+    #[cfg_attr(not(feature = "swap_demo"), allow(dead_code))]
+    pub(super) fn swap_out_page(&mut self, idx: usize, page: VirtPage) -> Result<Option<usize>, SwapError> {
+        let as_idx = self.tasks[idx].address_space_id.0;
+        if as_idx >= self.num_tasks || self.address_spaces[as_idx].kind != AddressSpaceKind::User {
+            return Ok(None);
+        }
+        let (Some(root), Some(m)) = (self.address_spaces[as_idx].root_page_frame, self.address_spaces[as_idx].lookup(page))
+        else {
+            return Ok(None);
+        };
+
+        if self.mapping_count_of_frame(m.frame) > 1 || self.is_shm_frame(m.frame) || self.is_granted_frame(m.frame) {
+            LOG.info("swap: shared frame; not swapped out");
+            LOG.info_u64("phys_frame_index", m.frame.number);
+            return Ok(None);
+        }
+
+        let Some((slot, slot_frame)) = self.swap.alloc_slot() else {
+            LOG.info("swap: no free swap slot");
+            return Ok(None);
+        };
+
+        if self.address_spaces[as_idx].swap_out(page, slot).is_err() {
+            LOG.error("swap: AddressSpace::swap_out failed");
+            self.swap.free_slot(slot);
+            return Ok(None);
+        }
+        unsafe { Arch::copy_frame(m.frame, slot_frame) };
+
+        if unsafe { self.apply_in_root(as_idx, root, MemAction::Unmap { page }) }.is_err() {
+            // 実ページテーブルには map されたまま: mapping を戻して slot を返す（外した region に戻すだけなので slot は足りる）
+            let _ = self.address_spaces[as_idx].take_swapped(page);
+            let _ = self.aspace_apply(as_idx, MemAction::Map { page, frame: m.frame, flags: m.flags });
+            self.swap.free_slot(slot);
+            LOG.error("swap: arch unmap failed; kill task");
+            LOG.info_u64("as_idx", as_idx as u64);
+            LOG.info_u64("virt_page_index", page.number);
+            return Err(self.kill_task_swap_failed(idx, page, SwapError::ArchUnmap));
+        }
+        self.unref_unmapped_frame(m.frame);
+        self.release_frame_if_unreferenced(m.frame);
+
+        self.counters.swap_out += 1;
+        self.push_event(LogEvent::PageSwappedOut { task: self.tasks[idx].id, page: page.number, slot });
+        Ok(Some(slot))
+    }
+
+    /// #PF の addr が current task の SwappedOut の page なら swap in する
+    /// - Ok(true) = 解決（fault した命令を再実行してよい）、Ok(false) = SwappedOut の page ではない / 解決できなかった
+    /// - Err は task を SwapFailed で kill した後（SwappedOut の記録は戻してある）
+    pub(super) fn swap_in_on_fault(&mut self, idx: usize, addr: u64) -> Result<bool, SwapError> {
+        let as_idx = self.tasks[idx].address_space_id.0;
+        if as_idx >= self.num_tasks || self.address_spaces[as_idx].kind != AddressSpaceKind::User {
+            return Ok(false);
+        }
+        let Some(offset) = addr.checked_sub(Arch::USER_SPACE_BASE) else {
+            return Ok(false);
+        };
+        if offset >= Arch::USER_SPACE_SIZE {
+            return Ok(false);
+        }
+        let page = VirtPage::from_index(offset / PAGE_SIZE);
+        let Some(swapped) = self.address_spaces[as_idx].lookup_swapped(page) else {
+            return Ok(false);
+        };
+        let (Some(root), Some(slot_frame)) =
+            (self.address_spaces[as_idx].root_page_frame, self.swap.frame_of(swapped.slot))
+        else {
+            return Ok(false);
+        };
+
+        let Some(raw) = self.phys_mem.allocate_frame() else {
+            LOG.error("swap: no frame for swap in");
+            return Ok(false);
+        };
+        let frame = PhysFrame::from_index(raw.start_address().as_u64() / PAGE_SIZE);
+        self.push_event(LogEvent::FrameAllocated);
+        unsafe { Arch::copy_frame(slot_frame, frame) };
+
+        let _ = self.address_spaces[as_idx].take_swapped(page);
+        let action = MemAction::Map { page, frame, flags: swapped.flags };
+        match self.aspace_apply(as_idx, action) {
+            Ok(()) => {}
+            Err(AddressSpaceError::CapacityExceeded) => {
+                // region が足りない: 記録を戻してフレームを返す（fault は解決しない。中身は swap slot に残る）
+                let _ = self.address_spaces[as_idx].restore_swapped(swapped);
+                self.release_frame_if_unreferenced(frame);
+                LOG.error("swap: no region slot for swap in; fault left unresolved");
+                LOG.info_u64("virt_page_index", page.number);
+                return Ok(false);
+            }
+            Err(_) => {
+                let _ = self.address_spaces[as_idx].restore_swapped(swapped);
+                self.release_frame_if_unreferenced(frame);
+                LOG.error("swap: AddressSpace::apply(Map) failed on swap in; kill task");
+                LOG.info_u64("virt_page_index", page.number);
+                return Err(self.kill_task_swap_failed(idx, page, SwapError::Remap));
+            }
+        }
+        self.ref_mapped_frame(frame);
+
+        if unsafe { self.apply_in_root(as_idx, root, action) }.is_err() {
+            // map する前の region に戻す（いま足した page を外すだけなので slot は足りる）。中身は swap slot に残っている
+            let _ = self.aspace_apply(as_idx, MemAction::Unmap { page });
+            self.unref_unmapped_frame(frame);
+            self.release_frame_if_unreferenced(frame);
+            let _ = self.address_spaces[as_idx].restore_swapped(swapped);
+            LOG.error("swap: arch map failed; kill task");
+            LOG.info_u64("as_idx", as_idx as u64);
+            LOG.info_u64("virt_page_index", page.number);
+            return Err(self.kill_task_swap_failed(idx, page, SwapError::ArchMap));
+        }
+        self.swap.free_slot(swapped.slot);
+
+        self.counters.swap_in += 1;
+        self.push_event(LogEvent::PageSwappedIn { task: self.tasks[idx].id, page: page.number, slot: swapped.slot });
+        Ok(true)
+    }
+
+    /// swap の失敗で task を kill する（論理の状態は呼び出し側が戻してある。片付けは teardown に任せる）
+    fn kill_task_swap_failed(&mut self, idx: usize, page: VirtPage, e: SwapError) -> SwapError {
+        self.kill_task(idx, TaskKillReason::SwapFailed { page: page.number, stage: e.code() });
+        e
+    }
+
+    /// PageUnmap: page が SwappedOut なら記録を消して slot を返す（true = 処理済み）
+    pub(super) fn swap_discard(&mut self, as_idx: usize, page: VirtPage) -> bool {
+        match self.address_spaces[as_idx].take_swapped(page) {
+            Some(s) => {
+                self.swap.free_slot(s.slot);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// task の後始末: AddressSpace の SwappedOut を全て消して slot を返す
+    pub(super) fn swap_release_all(&mut self, as_idx: usize) {
+        let mut slots = [None; SWAP_SLOTS];
+        let mut n = 0;
+        self.address_spaces[as_idx].for_each_swapped_page(|s| {
+            if n < SWAP_SLOTS {
+                slots[n] = Some(s.slot);
+                n += 1;
+            }
+        });
+        self.address_spaces[as_idx].clear_swapped_pages();
+        for slot in slots.iter().flatten() {
+            self.swap.free_slot(*slot);
+        }
+    }
+
+    /// swap_demo: 周期的に current でない ring3 task の code page を追い出す
+    #[cfg(feature = "swap_demo")]
+    pub(super) fn swap_demo_on_tick(&mut self) {
+        if self.tick_count % SWAP_DEMO_PERIOD_TICKS != 0 {
+            return;
+        }
+        let cur_as = self.tasks[self.current_task].address_space_id;
+        for idx in 0..self.num_tasks {
+            if idx == self.current_task
+                || !self.is_ring3_task(idx)
+                || self.tasks[idx].state == super::TaskState::Dead
+                || self.tasks[idx].address_space_id == cur_as
+            {
+                continue;
+            }
+            let page = super::ring3_task::ring3_code_page();
+            match self.swap_out_page(idx, page) {
+                Ok(Some(slot)) => {
+                    LOG.info("swap_demo: code page swapped out");
+                    LOG.info_u64("task_id", self.tasks[idx].id.0);
+                    LOG.info_u64("slot", slot as u64);
+                    return;
+                }
+                Ok(None) => {}
+                // task は kill 済み（この周期はここまで）
+                Err(_) => return,
+            }
+        }
+    }
+
+    /// page は mapped / guard / swapped のどれか 1 つ。slot の使用中 ⇔ 1 つの SwappedPage
+    #[spec("INV-MEM-007")]
+    pub(super) fn check_swap_invariants(&self) {
+        let mut refs = [0usize; SWAP_SLOTS];
+
+        for as_idx in FIRST_USER_ASID_INDEX..self.num_tasks {
+            let aspace = &self.address_spaces[as_idx];
+            aspace.for_each_swapped_page(|s| {
+                if aspace.lookup(s.page).is_some() || aspace.is_guard_page(s.page) {
+                    self.invariant_violated(
+                        InvariantId::Swap,
+                        None,
+                        Some(s.page.number),
+                        "INVARIANT VIOLATION: swapped page is also mapped or guard",
+                    );
+                    LOG.info_u64("as_idx", as_idx as u64);
+                    LOG.info_u64("page", s.page.start_address().0);
+                }
+                if s.slot < SWAP_SLOTS {
+                    refs[s.slot] += 1;
+                } else {
+                    self.invariant_violated(
+                        InvariantId::Swap,
+                        None,
+                        Some(s.slot as u64),
+                        "INVARIANT VIOLATION: swapped page has out-of-range slot",
+                    );
+                    LOG.info_u64("slot", s.slot as u64);
+                }
+            });
+            aspace.for_each_mapping(|m| {
+                if self.swap.is_swap_frame(m.frame) {
+                    self.invariant_violated(
+                        InvariantId::Swap,
+                        None,
+                        Some(m.frame.number),
+                        "INVARIANT VIOLATION: swap region frame is mapped",
+                    );
+                    LOG.info_u64("as_idx", as_idx as u64);
+                    LOG.info_u64("phys_frame_index", m.frame.number);
+                }
+            });
+        }
+
+        for slot in 0..SWAP_SLOTS {
+            let expected = if self.swap.used[slot] { 1 } else { 0 };
+            if refs[slot] != expected {
+                self.invariant_violated(
+                    InvariantId::Swap,
+                    None,
+                    Some(slot as u64),
+                    "INVARIANT VIOLATION: swap slot use != swapped page count",
+                );
+                LOG.info_u64("slot", slot as u64);
+                LOG.info_u64("used", self.swap.used[slot] as u64);
+                LOG.info_u64("swapped_pages", refs[slot] as u64);
+            }
+        }
+    }
+}
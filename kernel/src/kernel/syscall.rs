@@ -2,8 +2,36 @@
 //
 // syscall 境界（最小）
 // - IPC syscall + mem_demo 用 PageMap/PageUnmap syscall
-// - IPC reply は payload を返す（last_reply）
-// - PageMap/PageUnmap は戻り値コードを返す（last_syscall_ret）
+// - TaskCreate/TaskExit（task_lifecycle.rs）
+// - EndpointCreate/EndpointDelete（endpoint_lifecycle.rs、作った task が owner）
+// - EndpointSetBuffer { cap, slots }（ipc_buffer.rs、Recv の rights で buffered mode の slot 数を決める。0 で rendezvous）
+// - NotifySignal/NotifyWait（notification.rs、bits は last_notify。notification は handle で指す）
+// - ShmCreate/ShmMap（shm.rs、ShmCreate が返す handle を cap transfer で渡して 2 task で同じフレームを map する）
+// - ReadInput（input.rs、keyboard の scancode を 1 つ。block しない）
+// - FsOpen/FsRead（fs.rs、virtio-blk の disk の読み取り専用 fs。名前も中身も user の page 越しに渡す）
+// - Shutdown { code }（shutdown.rs、kernel task だけ。片付けて dump / verdict を出してから電源を切る。戻らない）
+// - FaultHandlerSet/FaultResolve（fault_handler.rs、user #PF を handler の endpoint へ IPC で委譲し、handler が map / 再開 / kill を選ぶ）
+// - GrantWindowSet（grant.rs、IPC の page grant を受け取る window。grant 付きの send は deliver で window に map され、reply で外れる）
+// - Revoke { cap_or_page }（derivation.rs、自分の cap / page から cap transfer / page grant で推移的に渡ったものを全 task から取り上げる）
+// - TaskSuspend/TaskResume（suspend.rs、kernel task か親だけ。Suspended の間は走らず、recv 待ちには msg を届けない）
+// - TaskInfo { task }（task_info.rs、state / priority / runtime_ticks / address_space_id を last_task_info に。誰でも引ける）
+// - MemInfo（mem_info.rs、フレームの total / used / free と自分の AddressSpace の resident / swapped を last_mem_info に）
+// - Sleep { ticks }: kernel clock で ticks 後（time.rs）まで Blocked(Sleep)。起床時に last_syscall_ret = SYSCALL_OK
+// - IPC send/reply は IpcMessage（MR 配列）を運ぶ。reply は last_reply
+// - IpcReply { reply_cap }（reply_cap.rs）: recv で受け取った一度きりの reply cap で返す先の client を名指しする。
+//   引けなければ last_syscall_ret = SYSCALL_ERR_BAD_REPLY_CAP（成功時は書かない）
+// - IpcRecvAny { cap_mask }: bit i = cap index i の endpoint のどれかに届くまで待つ（全 cap に Recv が要る）
+// - IpcSend { timeout: Some(n) }: kernel clock で n tick 後（Sleep と同じ時計。time.rs）に send / reply 待ちを打ち切り、
+//   last_reply = IPC_ERR_TIMEOUT
+// - kernel object（endpoint / notification / shm）は handle（cap index）で指す
+//   （cspace.rs で型と rights を検査してから EndpointId / NotificationId / ShmId に解決）
+// - send / reply の msg に添付した cap（cap_transfer）も入口で実在を検査する
+// - send の msg に添付した page grant は入口で送信側に map されていることを検査する（reply には付けられない）
+// - PageMap/PageUnmap/PageProtect は戻り値コードを返す（last_syscall_ret）
+// - PageProtect { page, flags }: map したまま flags だけ替える（MemAction::Protect。Unmap → Map の隙間を作らない）
+//   * 受け取った grant の window は替えられない（granter の flags を越えさせない。SYSCALL_ERR_GRANT_ACTIVE）
+//   * 成功したら MemActionApplied の event を出す
+// - PageMap は AddressSpace のフレーム quota を先に検査する（超えるなら確保せず SYSCALL_ERR_QUOTA）
 //
 // トレース（feature で切替）
 // - ipc_trace_syscall: syscall 境界の trace（kind/msg/task/ep を出す）
@@ -20,27 +48,141 @@
 // ★整理（テスト分離）:
 // - dead_partner_test 等の “テスト注入” は demo/ 側に集約し、syscall 境界から排除する。
 
-use super::{EndpointId, KernelState, LogEvent};
+use super::cspace::{CapIndex, CapRights};
+use super::ipc::RECV_ANY_MAX_EP;
+use super::fault_handler::FaultAction;
+use super::derivation::RevokeTarget;
+use super::reply_cap::ReplyCap;
+use super::{IpcMessage, KernelState, LogEvent, TaskKillReason};
 
-use crate::mem::address_space::AddressSpaceKind;
+use crate::arch::ops::{Arch, ArchOps};
+use crate::mem::address_space::{AddressSpaceError, AddressSpaceKind};
 use crate::mem::addr::VirtPage;
 use crate::mem::paging::{MemAction, PageFlags};
-
-const SYSCALL_OK: u64 = 0;
-const SYSCALL_ERR_ALREADY_MAPPED: u64 = 1;
-const SYSCALL_ERR_NOT_MAPPED: u64 = 2;
-const SYSCALL_ERR_CAPACITY: u64 = 3;
-const SYSCALL_ERR_ARCH_FAILED: u64 = 10;
-const SYSCALL_ERR_BAD_ASPACE: u64 = 11;
+use spec_macros::spec;
+
+// 戻り値コードの正本は abi.rs
+use super::abi::{
+    SYSCALL_ERR_ALREADY_MAPPED, SYSCALL_ERR_ARCH_FAILED, SYSCALL_ERR_BAD_ASPACE, SYSCALL_ERR_BAD_FLAGS, SYSCALL_ERR_CAPACITY,
+    SYSCALL_ERR_GUARD_PAGE, SYSCALL_ERR_NOT_MAPPED, SYSCALL_ERR_QUOTA, SYSCALL_OK,
+};
+// syscall 番号も abi.rs が正本
+use super::abi::{
+    SYS_DEBUG_ADD, SYS_ENDPOINT_CREATE, SYS_ENDPOINT_DELETE, SYS_GET_TICKS, SYS_IPC_RECV, SYS_IPC_RECV_ANY,
+    SYS_IPC_REPLY, SYS_IPC_SEND, SYS_NOTIFY_SIGNAL, SYS_NOTIFY_WAIT, SYS_PAGE_MAP, SYS_PAGE_UNMAP, SYS_SHM_CREATE,
+    SYS_READ_INPUT, SYS_SHM_MAP, SYS_SLEEP, SYS_TASK_CREATE, SYS_TASK_EXIT, SYS_FS_OPEN, SYS_FS_READ, SYS_SHUTDOWN,
+    SYS_FAULT_HANDLER_SET, SYS_FAULT_RESOLVE, SYS_GRANT_WINDOW_SET, SYS_IPC_SEND_GRANT, SYS_REVOKE,
+    SYS_TASK_INFO, SYS_TASK_RESUME, SYS_TASK_SUSPEND, SYS_ENDPOINT_SET_BUFFER, SYS_PAGE_PROTECT,
+    SYS_MEM_INFO,
+};
+use super::abi::{FAULT_ACTION_KILL, FAULT_ACTION_MAP_RESUME, FAULT_ACTION_RESUME, FAULT_HANDLER_NONE, GRANT_WINDOW_NONE};
+use super::abi::{SYSCALL_ERR_GRANT_ACTIVE, REVOKE_KIND_CAP, REVOKE_KIND_PAGE};
 
 #[derive(Clone, Copy)]
 pub enum Syscall {
-    IpcRecv { ep: EndpointId },
-    IpcSend { ep: EndpointId, msg: u64 },
-    IpcReply { ep: EndpointId, msg: u64 },
+    IpcRecv { cap: CapIndex },
+    IpcRecvAny { cap_mask: u64 },
+    // timeout は tick 数（Sleep と同じく kernel clock の期限にする）
+    IpcSend { cap: CapIndex, msg: IpcMessage, timeout: Option<u64> },
+    IpcReply { reply_cap: ReplyCap, msg: IpcMessage },
 
     PageMap { page: VirtPage, flags: PageFlags },
     PageUnmap { page: VirtPage },
+    // map したまま flags だけ替える
+    PageProtect { page: VirtPage, flags: PageFlags },
+
+    TaskCreate { entry_hint: u64, priority: u8 },
+    TaskExit,
+
+    EndpointCreate,
+    EndpointDelete { cap: CapIndex },
+    // slots = 0 で rendezvous に戻す
+    EndpointSetBuffer { cap: CapIndex, slots: usize },
+
+    NotifySignal { cap: CapIndex, bits: u64 },
+    NotifyWait { cap: CapIndex },
+
+    // ticks 後の kernel clock（time.rs）まで眠る
+    Sleep { ticks: u64 },
+
+    ShmCreate { pages: usize },
+    ShmMap { cap: CapIndex, page: VirtPage },
+
+    ReadInput,
+
+    FsOpen { page: VirtPage, name_len: u64 },
+    FsRead { fd: u64, page: VirtPage, offset: u64 },
+
+    Shutdown { code: u64 },
+
+    // cap = None で登録を外す
+    FaultHandlerSet { cap: Option<CapIndex> },
+    // cap は faulter の task handle（Resolve が要る。fault の msg の cap_transfer で渡る）
+    FaultResolve { cap: CapIndex, action: FaultAction },
+
+    // page = None で window を外す
+    GrantWindowSet { page: Option<VirtPage> },
+
+    // 自分の object は残し、そこから派生したものだけを消す
+    Revoke { cap_or_page: RevokeTarget },
+
+    // 自分以外の task を止める / 戻す（cap は task handle。Control が要る）
+    TaskSuspend { cap: CapIndex },
+    TaskResume { cap: CapIndex },
+    // task の観測できる状態を引く（cap は task handle。Info が要る。結果は last_task_info）
+    TaskInfo { cap: CapIndex },
+    // メモリ使用量を引く（結果は last_mem_info）
+    MemInfo,
+}
+
+impl Syscall {
+    /// 名前（caps の `cap syscall=` と同じ綴り。refinement trace の遷移名）
+    pub fn name(&self) -> &'static str {
+        match self {
+            Syscall::IpcRecv { .. } => "ipc_recv",
+            Syscall::IpcRecvAny { .. } => "ipc_recv_any",
+            Syscall::IpcSend { .. } => "ipc_send",
+            Syscall::IpcReply { .. } => "ipc_reply",
+            Syscall::PageMap { .. } => "page_map",
+            Syscall::PageUnmap { .. } => "page_unmap",
+            Syscall::PageProtect { .. } => "page_protect",
+            Syscall::TaskCreate { .. } => "task_create",
+            Syscall::TaskExit => "task_exit",
+            Syscall::EndpointCreate => "endpoint_create",
+            Syscall::EndpointDelete { .. } => "endpoint_delete",
+            Syscall::EndpointSetBuffer { .. } => "endpoint_set_buffer",
+            Syscall::NotifySignal { .. } => "notify_signal",
+            Syscall::NotifyWait { .. } => "notify_wait",
+            Syscall::Sleep { .. } => "sleep",
+            Syscall::ShmCreate { .. } => "shm_create",
+            Syscall::ShmMap { .. } => "shm_map",
+            Syscall::ReadInput => "read_input",
+            Syscall::FsOpen { .. } => "fs_open",
+            Syscall::FsRead { .. } => "fs_read",
+            Syscall::Shutdown { .. } => "shutdown",
+            Syscall::FaultHandlerSet { .. } => "fault_handler_set",
+            Syscall::FaultResolve { .. } => "fault_resolve",
+            Syscall::GrantWindowSet { .. } => "grant_window_set",
+            Syscall::Revoke { .. } => "revoke",
+            Syscall::TaskSuspend { .. } => "task_suspend",
+            Syscall::TaskResume { .. } => "task_resume",
+            Syscall::TaskInfo { .. } => "task_info",
+            Syscall::MemInfo => "mem_info",
+        }
+    }
+}
+
+impl AddressSpaceError {
+    /// last_syscall_ret で返すコード（PageMap / PageUnmap / PageProtect / ShmMap の論理側の失敗）
+    pub(super) fn syscall_code(self) -> u64 {
+        match self {
+            AddressSpaceError::AlreadyMapped => SYSCALL_ERR_ALREADY_MAPPED,
+            AddressSpaceError::NotMapped => SYSCALL_ERR_NOT_MAPPED,
+            AddressSpaceError::CapacityExceeded => SYSCALL_ERR_CAPACITY,
+            AddressSpaceError::GuardPage => SYSCALL_ERR_GUARD_PAGE,
+            AddressSpaceError::BadFlags => SYSCALL_ERR_BAD_FLAGS,
+        }
+    }
 }
 
 impl KernelState {
@@ -63,12 +205,23 @@ impl KernelState {
         }
     }
 
-    fn handle_syscall(&mut self, sc: Syscall) {
+    /// syscall 1 つ = refinement trace の遷移 1 つ（前に kernel がした分を tick として区切ってから、syscall の名前で出す）
+    pub(super) fn handle_syscall(&mut self, sc: Syscall) {
+        let tid = (self.current_task < self.num_tasks).then(|| self.tasks[self.current_task].id);
+        self.refinement_boundary("tick", tid, false);
+        self.dispatch_syscall(sc);
+        self.refinement_boundary(sc.name(), tid, true);
+    }
+
+    fn dispatch_syscall(&mut self, sc: Syscall) {
         let task_index = self.current_task;
         if task_index >= self.num_tasks {
             return;
         }
 
+        // CPU 時間: この tick は kernel（syscall 処理）に入れる
+        self.syscall_since_account = true;
+
         let tid = self.tasks[task_index].id;
 
         // kernel task の IPC syscall は禁止
@@ -78,10 +231,22 @@ impl KernelState {
 
             if is_kernel {
                 match sc {
-                    Syscall::IpcRecv { ep } | Syscall::IpcSend { ep, .. } | Syscall::IpcReply { ep, .. } => {
+                    Syscall::IpcRecv { cap } | Syscall::IpcSend { cap, .. } => {
                         crate::logging::error("syscall: kernel task IPC is forbidden (ignored at syscall boundary)");
                         crate::logging::info_u64("task_id", tid.0);
-                        crate::logging::info_u64("ep_id", ep.0 as u64);
+                        crate::logging::info_u64("cap_index", cap.0 as u64);
+                        return;
+                    }
+                    Syscall::IpcRecvAny { cap_mask } => {
+                        crate::logging::error("syscall: kernel task IPC is forbidden (ignored at syscall boundary)");
+                        crate::logging::info_u64("task_id", tid.0);
+                        crate::logging::info_u64("cap_mask", cap_mask);
+                        return;
+                    }
+                    Syscall::IpcReply { reply_cap, .. } => {
+                        crate::logging::error("syscall: kernel task IPC is forbidden (ignored at syscall boundary)");
+                        crate::logging::info_u64("task_id", tid.0);
+                        crate::logging::info_hex("reply_cap", reply_cap.0);
                         return;
                     }
                     _ => {}
@@ -92,7 +257,11 @@ impl KernelState {
         self.push_event(LogEvent::SyscallHandled { task: tid });
 
         match sc {
-            Syscall::IpcRecv { ep } => {
+            Syscall::IpcRecv { cap } => {
+                let Some(ep) = self.resolve_endpoint_cap(task_index, cap, CapRights::RECV, "ipc_recv") else {
+                    return;
+                };
+
                 #[cfg(feature = "ipc_trace_syscall")]
                 trace_ipc(TraceKind::Recv, tid, ep, None);
 
@@ -102,18 +271,73 @@ impl KernelState {
                 crate::kernel::demo::on_after_ipc_recv(self, task_index, tid, ep);
             }
 
-            Syscall::IpcSend { ep, msg } => {
+            Syscall::IpcRecvAny { cap_mask } => {
+                // 全 cap を解決してから endpoint の mask を作る（1 つでも拒否されたら何もしない）
+                let mut ep_mask: u64 = 0;
+                for i in 0..RECV_ANY_MAX_EP {
+                    if cap_mask & (1u64 << i) == 0 {
+                        continue;
+                    }
+                    let cap = CapIndex(i);
+                    let Some(ep) = self.resolve_endpoint_cap(task_index, cap, CapRights::RECV, "ipc_recv_any") else {
+                        return;
+                    };
+                    if ep.0 >= RECV_ANY_MAX_EP {
+                        crate::logging::error("syscall: ipc_recv_any endpoint id too large for ep_mask");
+                        crate::logging::info_u64("ep_id", ep.0 as u64);
+                        return;
+                    }
+                    ep_mask |= 1u64 << ep.0;
+                }
+
+                self.ipc_recv_any(ep_mask);
+            }
+
+            Syscall::IpcSend { cap, msg, timeout } => {
+                let Some(ep) = self.resolve_endpoint_cap(task_index, cap, CapRights::SEND, "ipc_send") else {
+                    return;
+                };
+                // 添付する cap も送信側の table に実在すること（種類と rights は問わない。そのまま複製される）
+                if let Some(c) = msg.cap_transfer() {
+                    if !self.resolve_transfer_cap(task_index, c, "ipc_send(cap_transfer)") {
+                        return;
+                    }
+                }
+                // grant する page は送信側に map されていること（grant.rs）
+                if let Some(page) = msg.grant() {
+                    if !self.grant_source_ok(task_index, page) {
+                        return;
+                    }
+                }
+
                 #[cfg(feature = "ipc_trace_syscall")]
                 trace_ipc(TraceKind::Send, tid, ep, Some(msg));
 
-                self.ipc_send(ep, msg);
+                self.ipc_send(ep, msg, timeout);
             }
 
-            Syscall::IpcReply { ep, msg } => {
+            Syscall::IpcReply { reply_cap, msg } => {
+                if let Some(c) = msg.cap_transfer() {
+                    if !self.resolve_transfer_cap(task_index, c, "ipc_reply(cap_transfer)") {
+                        return;
+                    }
+                }
+                // reply は grant を外す側（grant を付けた reply は届けない）
+                if msg.grant().is_some() {
+                    crate::logging::error("syscall: ipc_reply cannot carry a page grant; rejected");
+                    crate::logging::info_u64("task_id", tid.0);
+                    self.counters.grant_failed += 1;
+                    return;
+                }
+
                 #[cfg(feature = "ipc_trace_syscall")]
-                trace_ipc(TraceKind::Reply, tid, ep, Some(msg));
+                trace_ipc_reply(tid, reply_cap, msg);
 
-                self.ipc_reply(ep, msg);
+                // 成功時は last_syscall_ret を書かない（reply の結果は client の last_reply）
+                let ret = self.ipc_reply(reply_cap, msg);
+                if ret != SYSCALL_OK {
+                    self.set_last_syscall_ret_for_current(ret);
+                }
             }
 
             Syscall::PageMap { page, flags } => {
@@ -125,10 +349,144 @@ impl KernelState {
                 let ret = self.syscall_page_unmap(task_index, tid, page);
                 self.set_last_syscall_ret_for_current(ret);
             }
+
+            Syscall::PageProtect { page, flags } => {
+                let ret = self.syscall_page_protect(task_index, tid, page, flags);
+                self.set_last_syscall_ret_for_current(ret);
+            }
+
+            Syscall::TaskCreate { entry_hint, priority } => {
+                let ret = self.syscall_task_create(task_index, entry_hint, priority);
+                self.set_last_syscall_ret_for_current(ret);
+            }
+
+            Syscall::TaskExit => {
+                // 成功時は呼び出し元が Dead（current も切り替わっている）なので戻り値を書かない
+                let ret = self.syscall_task_exit(task_index);
+                if ret != SYSCALL_OK {
+                    self.set_last_syscall_ret_for_current(ret);
+                }
+            }
+
+            Syscall::NotifySignal { cap, bits } => {
+                let ret = match self.resolve_notification_cap(task_index, cap, CapRights::SIGNAL, "notify_signal") {
+                    Ok(ntfn) => self.syscall_notify_signal(task_index, ntfn, bits),
+                    Err(e) => e,
+                };
+                self.set_last_syscall_ret_for_current(ret);
+            }
+
+            Syscall::EndpointCreate => {
+                let ret = self.syscall_endpoint_create(task_index);
+                self.set_last_syscall_ret_for_current(ret);
+            }
+
+            Syscall::EndpointDelete { cap } => {
+                let ret = self.syscall_endpoint_delete(task_index, cap);
+                self.set_last_syscall_ret_for_current(ret);
+            }
+
+            Syscall::EndpointSetBuffer { cap, slots } => {
+                let ret = self.syscall_endpoint_set_buffer(task_index, cap, slots);
+                self.set_last_syscall_ret_for_current(ret);
+            }
+
+            Syscall::NotifyWait { cap } => {
+                // 受け取り / block した場合は notification.rs 側で戻り値を入れる
+                let ret = match self.resolve_notification_cap(task_index, cap, CapRights::WAIT, "notify_wait") {
+                    Ok(ntfn) => self.syscall_notify_wait(task_index, ntfn),
+                    Err(e) => Some(e),
+                };
+                if let Some(ret) = ret {
+                    self.set_last_syscall_ret_for_current(ret);
+                }
+            }
+
+            Syscall::Sleep { ticks } => {
+                // 眠った場合は起床時に戻り値を入れる
+                if let Some(ret) = self.syscall_sleep(task_index, ticks) {
+                    self.set_last_syscall_ret_for_current(ret);
+                }
+            }
+
+            Syscall::ShmCreate { pages } => {
+                let ret = self.syscall_shm_create(task_index, pages);
+                self.set_last_syscall_ret_for_current(ret);
+            }
+
+            Syscall::ShmMap { cap, page } => {
+                let ret = match self.resolve_shm_cap(task_index, cap, "shm_map") {
+                    Ok(shm) => self.syscall_shm_map(task_index, shm, page),
+                    Err(e) => e,
+                };
+                self.set_last_syscall_ret_for_current(ret);
+            }
+
+            Syscall::ReadInput => {
+                let ret = self.syscall_read_input();
+                self.set_last_syscall_ret_for_current(ret);
+            }
+
+            Syscall::FsOpen { page, name_len } => {
+                let ret = self.syscall_fs_open(task_index, page, name_len);
+                self.set_last_syscall_ret_for_current(ret);
+            }
+
+            Syscall::FsRead { fd, page, offset } => {
+                let ret = self.syscall_fs_read(task_index, fd, page, offset);
+                self.set_last_syscall_ret_for_current(ret);
+            }
+
+            // 成功すれば戻らない（電源を切る）。戻るのは拒否したときだけ
+            Syscall::Shutdown { code } => {
+                let ret = self.syscall_shutdown(task_index, code);
+                self.set_last_syscall_ret_for_current(ret);
+            }
+
+            Syscall::FaultHandlerSet { cap } => {
+                let ret = self.syscall_fault_handler_set(task_index, cap);
+                self.set_last_syscall_ret_for_current(ret);
+            }
+
+            Syscall::FaultResolve { cap, action } => {
+                let ret = self.syscall_fault_resolve(task_index, cap, action);
+                self.set_last_syscall_ret_for_current(ret);
+            }
+
+            Syscall::GrantWindowSet { page } => {
+                let ret = self.syscall_grant_window_set(task_index, page);
+                self.set_last_syscall_ret_for_current(ret);
+            }
+
+            Syscall::Revoke { cap_or_page } => {
+                let ret = self.syscall_revoke(task_index, cap_or_page);
+                self.set_last_syscall_ret_for_current(ret);
+            }
+
+            Syscall::TaskSuspend { cap } => {
+                let ret = self.syscall_task_suspend(task_index, cap);
+                self.set_last_syscall_ret_for_current(ret);
+            }
+
+            Syscall::TaskResume { cap } => {
+                let ret = self.syscall_task_resume(task_index, cap);
+                self.set_last_syscall_ret_for_current(ret);
+            }
+
+            Syscall::TaskInfo { cap } => {
+                let ret = self.syscall_task_info(task_index, cap);
+                self.set_last_syscall_ret_for_current(ret);
+            }
+
+            Syscall::MemInfo => {
+                let ret = self.syscall_mem_info(task_index);
+                self.set_last_syscall_ret_for_current(ret);
+            }
         }
     }
 
-    fn syscall_page_map(&mut self, task_index: usize, tid: super::TaskId, page: VirtPage, flags: PageFlags) -> u64 {
+    #[spec("INV-MEM-001")]
+    pub(super) fn syscall_page_map(&mut self, task_index: usize, tid: super::TaskId, page: VirtPage, flags: PageFlags) -> u64 {
         if task_index >= self.num_tasks {
             return SYSCALL_ERR_BAD_ASPACE;
         }
@@ -138,6 +496,16 @@ impl KernelState {
             return SYSCALL_ERR_BAD_ASPACE;
         }
 
+        // quota は確保の前に見る（まだ demo frame が無ければ新しいフレームとして数える）
+        let aspace = &self.address_spaces[as_idx];
+        if aspace.would_exceed_frame_quota(self.mem_demo_frame[task_index]) {
+            crate::logging::error("syscall: PageMap failed (frame quota exceeded)");
+            crate::logging::info_u64("task_id", tid.0);
+            crate::logging::info_u64("frames_in_use", aspace.frames_in_use() as u64);
+            crate::logging::info_u64("frame_quota", aspace.frame_quota() as u64);
+            return SYSCALL_ERR_QUOTA;
+        }
+
         let frame = match self.get_or_alloc_demo_frame(task_index) {
             Some(f) => f,
             None => {
@@ -149,24 +517,20 @@ impl KernelState {
 
         let mem_action = MemAction::Map { page, frame, flags };
 
-        let apply_res = {
-            let aspace = &mut self.address_spaces[as_idx];
-            aspace.apply(mem_action)
-        };
+        let apply_res = self.aspace_apply(as_idx, mem_action);
 
         let logical_ret = match apply_res {
             Ok(()) => SYSCALL_OK,
-            Err(crate::mem::address_space::AddressSpaceError::AlreadyMapped) => SYSCALL_ERR_ALREADY_MAPPED,
-            Err(crate::mem::address_space::AddressSpaceError::NotMapped) => SYSCALL_ERR_NOT_MAPPED,
-            Err(crate::mem::address_space::AddressSpaceError::CapacityExceeded) => SYSCALL_ERR_CAPACITY,
+            Err(e) => e.syscall_code(),
         };
 
         if logical_ret != SYSCALL_OK {
             return logical_ret;
         }
+        self.ref_mapped_frame(frame);
 
         match self.address_spaces[as_idx].kind {
-            AddressSpaceKind::Kernel => match unsafe { crate::arch::paging::apply_mem_action(mem_action, &mut self.phys_mem) } {
+            AddressSpaceKind::Kernel => match unsafe { Arch::apply_mem_action(mem_action, &mut self.phys_mem) } {
                 Ok(()) => SYSCALL_OK,
                 Err(_e) => SYSCALL_ERR_ARCH_FAILED,
             },
@@ -176,7 +540,7 @@ impl KernelState {
                     Some(r) => r,
                     None => return SYSCALL_ERR_BAD_ASPACE,
                 };
-                match unsafe { crate::arch::paging::apply_mem_action_in_root(mem_action, root, &mut self.phys_mem) } {
+                match unsafe { self.apply_in_root(as_idx, root, mem_action) } {
                     Ok(()) => SYSCALL_OK,
                     Err(_e) => SYSCALL_ERR_ARCH_FAILED,
                 }
@@ -184,6 +548,7 @@ impl KernelState {
         }
     }
 
+    #[spec("INV-MEM-001")]
     fn syscall_page_unmap(&mut self, task_index: usize, _tid: super::TaskId, page: VirtPage) -> u64 {
         if task_index >= self.num_tasks {
             return SYSCALL_ERR_BAD_ASPACE;
@@ -194,26 +559,39 @@ impl KernelState {
             return SYSCALL_ERR_BAD_ASPACE;
         }
 
+        // 受け取った grant の window は reply まで外せない（grant.rs）
+        if self.is_grant_window_in_use(task_index, page) {
+            crate::logging::error("page_unmap: page is an active grant window; rejected");
+            crate::logging::info_u64("page", page.number);
+            return SYSCALL_ERR_GRANT_ACTIVE;
+        }
+
+        // SwappedOut の page: 実ページテーブルには何も無い。記録を消して swap slot を返すだけ
+        if self.swap_discard(as_idx, page) {
+            return SYSCALL_OK;
+        }
+
         let mem_action = MemAction::Unmap { page };
 
-        let apply_res = {
-            let aspace = &mut self.address_spaces[as_idx];
-            aspace.apply(mem_action)
-        };
+        // 外れるフレーム（arch 側の unmap まで成功したら返す）
+        let unmapped_frame = self.address_spaces[as_idx].lookup(page).map(|m| m.frame);
+
+        let apply_res = self.aspace_apply(as_idx, mem_action);
 
         let logical_ret = match apply_res {
             Ok(()) => SYSCALL_OK,
-            Err(crate::mem::address_space::AddressSpaceError::AlreadyMapped) => SYSCALL_ERR_ALREADY_MAPPED,
-            Err(crate::mem::address_space::AddressSpaceError::NotMapped) => SYSCALL_ERR_NOT_MAPPED,
-            Err(crate::mem::address_space::AddressSpaceError::CapacityExceeded) => SYSCALL_ERR_CAPACITY,
+            Err(e) => e.syscall_code(),
         };
 
         if logical_ret != SYSCALL_OK {
             return logical_ret;
         }
+        if let Some(f) = unmapped_frame {
+            self.unref_unmapped_frame(f);
+        }
 
-        match self.address_spaces[as_idx].kind {
-            AddressSpaceKind::Kernel => match unsafe { crate::arch::paging::apply_mem_action(mem_action, &mut self.phys_mem) } {
+        let ret = match self.address_spaces[as_idx].kind {
+            AddressSpaceKind::Kernel => match unsafe { Arch::apply_mem_action(mem_action, &mut self.phys_mem) } {
                 Ok(()) => SYSCALL_OK,
                 Err(_e) => SYSCALL_ERR_ARCH_FAILED,
             },
@@ -223,12 +601,79 @@ impl KernelState {
                     Some(r) => r,
                     None => return SYSCALL_ERR_BAD_ASPACE,
                 };
-                match unsafe { crate::arch::paging::apply_mem_action_in_root(mem_action, root, &mut self.phys_mem) } {
+                match unsafe { self.apply_in_root(as_idx, root, mem_action) } {
                     Ok(()) => SYSCALL_OK,
                     Err(_e) => SYSCALL_ERR_ARCH_FAILED,
                 }
             }
+        };
+
+        if ret == SYSCALL_OK {
+            if let Some(f) = unmapped_frame {
+                self.release_frame_if_unreferenced(f);
+            }
+        }
+        ret
+    }
+
+    #[spec("INV-MEM-001")]
+    fn syscall_page_protect(&mut self, task_index: usize, tid: super::TaskId, page: VirtPage, flags: PageFlags) -> u64 {
+        if task_index >= self.num_tasks {
+            return SYSCALL_ERR_BAD_ASPACE;
+        }
+
+        let as_idx = self.tasks[task_index].address_space_id.0;
+        if as_idx >= self.num_tasks {
+            return SYSCALL_ERR_BAD_ASPACE;
+        }
+
+        // 受け取った grant の window は granter の flags のまま（reply まで替えられない）
+        if self.is_grant_window_in_use(task_index, page) {
+            crate::logging::error("page_protect: page is an active grant window; rejected");
+            crate::logging::info_u64("page", page.number);
+            return SYSCALL_ERR_GRANT_ACTIVE;
+        }
+
+        // 失敗したら戻す flags（map されていなければ apply が NotMapped を返す）
+        let old_flags = self.address_spaces[as_idx].lookup(page).map(|m| m.flags);
+        let mem_action = MemAction::Protect { page, new_flags: flags };
+
+        let logical_ret = match self.aspace_apply(as_idx, mem_action) {
+            Ok(()) => SYSCALL_OK,
+            Err(e) => e.syscall_code(),
+        };
+        if logical_ret != SYSCALL_OK {
+            return logical_ret;
+        }
+
+        let arch_ok = match self.address_spaces[as_idx].kind {
+            AddressSpaceKind::Kernel => unsafe { Arch::apply_mem_action(mem_action, &mut self.phys_mem) }.is_ok(),
+            AddressSpaceKind::User => match self.address_spaces[as_idx].root_page_frame {
+                Some(root) => unsafe { self.apply_in_root(as_idx, root, mem_action) }.is_ok(),
+                None => false,
+            },
+        };
+        if !arch_ok {
+            // 実ページテーブルは替わっていない: 論理の flags も戻す
+            if let Some(f) = old_flags {
+                let _ = self.address_spaces[as_idx].apply(MemAction::Protect { page, new_flags: f });
+            }
+            crate::logging::error("page_protect: arch update_flags failed");
+            crate::logging::info_u64("page", page.number);
+            return SYSCALL_ERR_ARCH_FAILED;
         }
+
+        crate::logging::info("page_protect: flags updated");
+        crate::logging::info_u64("task_id", tid.0);
+        crate::logging::info_u64("page", page.number);
+        crate::logging::info_u64("old_flags_bits", old_flags.map_or(0, |f| f.bits()));
+        crate::logging::info_u64("new_flags_bits", flags.bits());
+        self.push_event(LogEvent::MemActionApplied {
+            task: tid,
+            address_space: self.tasks[task_index].address_space_id,
+            action: mem_action,
+        });
+        SYSCALL_OK
     }
 }
 
@@ -237,47 +682,120 @@ impl KernelState {
 enum TraceKind {
     Recv,
     Send,
-    Reply,
 }
 
 #[cfg(feature = "ipc_trace_syscall")]
-fn trace_ipc(kind: TraceKind, tid: super::TaskId, ep: EndpointId, msg: Option<u64>) {
+fn trace_ipc(kind: TraceKind, tid: super::TaskId, ep: super::EndpointId, msg: Option<IpcMessage>) {
     match kind {
         TraceKind::Recv => crate::logging::info("ipc_trace kind=ipc_recv"),
         TraceKind::Send => crate::logging::info("ipc_trace kind=ipc_send"),
-        TraceKind::Reply => crate::logging::info("ipc_trace kind=ipc_reply"),
     }
     crate::logging::info_u64("task_id", tid.0);
     crate::logging::info_u64("ep_id", ep.0 as u64);
     if let Some(m) = msg {
-        crate::logging::info_u64("msg", m);
+        crate::logging::info_u64("msg", m.mr0());
+        crate::logging::info_u64("msg_len", m.len() as u64);
     }
 }
 
-fn mailbox_decode(sysno: u64, a0: u64, a1: u64, _a2: u64) -> Option<Syscall> {
-    let ep = EndpointId(a0 as usize);
+/// reply は endpoint ではなく reply cap で相手を指すので ep の代わりに reply_cap を出す
+#[cfg(feature = "ipc_trace_syscall")]
+fn trace_ipc_reply(tid: super::TaskId, reply_cap: ReplyCap, msg: IpcMessage) {
+    crate::logging::info("ipc_trace kind=ipc_reply");
+    crate::logging::info_u64("task_id", tid.0);
+    crate::logging::info_hex("reply_cap", reply_cap.0);
+    crate::logging::info_u64("msg", msg.mr0());
+    crate::logging::info_u64("msg_len", msg.len() as u64);
+}
+
+// ring3 mailbox はレジスタ 3 本なので MR0 だけ（len = 1）。a0 は cap index（reply は reply cap）
+// - send の a2 は timeout（tick 数、0 = timeout なし）
+fn mailbox_decode(sysno: u64, a0: u64, a1: u64, a2: u64) -> Option<Syscall> {
     match sysno {
-        10 => Some(Syscall::IpcRecv { ep }),
-        11 => Some(Syscall::IpcSend { ep, msg: a1 }),
-        12 => Some(Syscall::IpcReply { ep, msg: a1 }),
+        SYS_IPC_RECV | SYS_IPC_SEND | SYS_IPC_REPLY => decode_syscall(sysno, a0, a1, a2),
         _ => None,
     }
 }
 
+/// sysno / a0..a2（番号と引数の割り当ては abi.rs の SYS_*）を Syscall にする
+/// - 知らない sysno / 範囲外の引数（priority > u8 等）は None
+/// - SYS_DEBUG_ADD / SYS_GET_TICKS は Syscall ではない（入口でその場で返す）ので None
+pub(super) fn decode_syscall(sysno: u64, a0: u64, a1: u64, a2: u64) -> Option<Syscall> {
+    let cap = CapIndex(a0 as usize);
+    let sc = match sysno {
+        SYS_IPC_RECV => Syscall::IpcRecv { cap },
+        SYS_IPC_SEND => Syscall::IpcSend { cap, msg: IpcMessage::word(a1), timeout: (a2 != 0).then_some(a2) },
+        SYS_IPC_REPLY => Syscall::IpcReply { reply_cap: ReplyCap(a0), msg: IpcMessage::word(a1) },
+        SYS_IPC_RECV_ANY => Syscall::IpcRecvAny { cap_mask: a0 },
+        SYS_PAGE_MAP => {
+            // user が決めてよいのは WRITABLE だけ（kernel 専用 page / NX は作らせない）
+            let flags = (PageFlags::from_bits_truncate(a1) & PageFlags::WRITABLE) | PageFlags::PRESENT | PageFlags::USER;
+            Syscall::PageMap { page: VirtPage::from_index(a0), flags }
+        }
+        SYS_PAGE_UNMAP => Syscall::PageUnmap { page: VirtPage::from_index(a0) },
+        SYS_PAGE_PROTECT => {
+            let flags = (PageFlags::from_bits_truncate(a1) & PageFlags::WRITABLE) | PageFlags::PRESENT | PageFlags::USER;
+            Syscall::PageProtect { page: VirtPage::from_index(a0), flags }
+        }
+        SYS_TASK_CREATE => Syscall::TaskCreate { entry_hint: a0, priority: u8::try_from(a1).ok()? },
+        SYS_TASK_EXIT => Syscall::TaskExit,
+        SYS_ENDPOINT_CREATE => Syscall::EndpointCreate,
+        SYS_ENDPOINT_DELETE => Syscall::EndpointDelete { cap },
+        SYS_ENDPOINT_SET_BUFFER => Syscall::EndpointSetBuffer { cap, slots: usize::try_from(a1).ok()? },
+        SYS_NOTIFY_SIGNAL => Syscall::NotifySignal { cap, bits: a1 },
+        SYS_NOTIFY_WAIT => Syscall::NotifyWait { cap },
+        SYS_SLEEP => Syscall::Sleep { ticks: a0 },
+        SYS_SHM_CREATE => Syscall::ShmCreate { pages: usize::try_from(a0).ok()? },
+        SYS_SHM_MAP => Syscall::ShmMap { cap, page: VirtPage::from_index(a1) },
+        SYS_READ_INPUT => Syscall::ReadInput,
+        SYS_FS_OPEN => Syscall::FsOpen { page: VirtPage::from_index(a0), name_len: a1 },
+        SYS_FS_READ => Syscall::FsRead { fd: a0, page: VirtPage::from_index(a1), offset: a2 },
+        SYS_SHUTDOWN => Syscall::Shutdown { code: a0 },
+        SYS_FAULT_HANDLER_SET => Syscall::FaultHandlerSet { cap: (a0 != FAULT_HANDLER_NONE).then_some(cap) },
+        SYS_FAULT_RESOLVE => {
+            let action = match a1 {
+                FAULT_ACTION_RESUME => FaultAction::Resume,
+                FAULT_ACTION_MAP_RESUME => FaultAction::MapAndResume { page: VirtPage::from_index(a2) },
+                FAULT_ACTION_KILL => FaultAction::Kill,
+                _ => return None,
+            };
+            Syscall::FaultResolve { cap, action }
+        }
+        SYS_GRANT_WINDOW_SET => Syscall::GrantWindowSet { page: (a0 != GRANT_WINDOW_NONE).then(|| VirtPage::from_index(a0)) },
+        SYS_IPC_SEND_GRANT => {
+            Syscall::IpcSend { cap, msg: IpcMessage::word(a1).with_grant(VirtPage::from_index(a2)), timeout: None }
+        }
+        SYS_REVOKE => {
+            let cap_or_page = match a0 {
+                REVOKE_KIND_CAP => RevokeTarget::Cap(CapIndex(usize::try_from(a1).ok()?)),
+                REVOKE_KIND_PAGE => RevokeTarget::Page(VirtPage::from_index(a1)),
+                _ => return None,
+            };
+            Syscall::Revoke { cap_or_page }
+        }
+        SYS_TASK_SUSPEND => Syscall::TaskSuspend { cap },
+        SYS_TASK_RESUME => Syscall::TaskResume { cap },
+        SYS_TASK_INFO => Syscall::TaskInfo { cap },
+        SYS_MEM_INFO => Syscall::MemInfo,
+        _ => return None,
+    };
+    Some(sc)
+}
+
 /// ring3 mailbox dispatcher
 pub fn mailbox_dispatch(ks: &mut KernelState, sysno: u64, a0: u64, a1: u64, a2: u64) -> u64 {
     let ring3_task_index: usize = 1;
 
     match sysno {
-        1 => return a0.wrapping_add(a1).wrapping_add(a2),
-        2 => return ks.tick_count,
+        SYS_DEBUG_ADD => return a0.wrapping_add(a1).wrapping_add(a2),
+        SYS_GET_TICKS => return ks.tick_count,
         30 => {
             ks.tick();
             return ks.tick_count;
         }
         31 => {
             if ring3_task_index < ks.num_tasks {
-                let v = ks.tasks[ring3_task_index].last_reply.unwrap_or(0);
+                let v = ks.tasks[ring3_task_index].last_reply.map_or(0, |m| m.mr0());
                 ks.tasks[ring3_task_index].last_reply = None;
                 return v;
             }
@@ -286,7 +804,7 @@ pub fn mailbox_dispatch(ks: &mut KernelState, sysno: u64, a0: u64, a1: u64, a2:
         _ => {}
     }
 
-    let is_ipc_sysno = matches!(sysno, 10 | 11 | 12);
+    let is_ipc_sysno = matches!(sysno, SYS_IPC_RECV | SYS_IPC_SEND | SYS_IPC_REPLY);
 
     if is_ipc_sysno {
         if ring3_task_index < ks.num_tasks && ks.tasks[ring3_task_index].state != super::TaskState::Dead {
@@ -315,3 +833,25 @@ pub fn mailbox_dispatch(ks: &mut KernelState, sysno: u64, a0: u64, a1: u64, a2:
     ks.current_task = prev_task;
     ret
 }
+
+/// ring3 mailbox: iretq 直前の TrapFrame 検査に落ちた ring3 task を kill する。
+///
+/// - check / value は arch::trapframe::TrapFrameViolation の code とその値
+/// - ring3 へは戻れない（壊れたフレームで iretq しない）ので、観測のため dump まで行う
+/// - halt は呼び出し側（int80 handler）が行う
+pub fn mailbox_kill_bad_trap_frame(ks: &mut KernelState, check: u64, value: u64) {
+    // ring3_tasks では ring3 に居たのは current（それ以外の ring3 系デモは Task1 固定）
+    let ring3_task_index: usize = if cfg!(feature = "ring3_tasks") { ks.current_task } else { 1 };
+
+    if ring3_task_index < ks.num_tasks && ks.tasks[ring3_task_index].state != super::TaskState::Dead {
+        ks.kill_task(ring3_task_index, TaskKillReason::TrapFrameCorrupt { check, value });
+    }
+
+    let kernel_root = ks.address_spaces[super::KERNEL_ASID_INDEX]
+        .root_page_frame
+        .expect("kernel root_page_frame must exist");
+    Arch::switch_address_space_quiet(kernel_root);
+    crate::logging::set_vga_enabled(true);
+
+    ks.dump_events();
+}
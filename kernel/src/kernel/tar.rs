@@ -0,0 +1,103 @@
+// kernel/src/kernel/tar.rs
+//
+// 役割:
+// - ustar（POSIX TAR）の 512 byte header を読む小さな helper。fs.rs（virtio-blk の disk）と initrd.rs（boot 時の package）が使う。
+//
+// 方針:
+// - header 1 つを読むだけ（中身の位置は呼び出し側が数える: header の次の block から、512 byte 境界で次の header）
+// - 名前の先頭の "./" は落とす（`tar -C dir .` で作った image もそのまま読める）
+// - 読めない entry（file 以外 / prefix 付き / 名前が長すぎる）は大きさだけ返して飛ばさせる
+//
+// 制限:
+// - ustar の prefix（155 byte）/ GNU・pax の拡張 header / base-256 の大きさは読まない
+
+/// header / 中身の block の大きさ
+#[cfg_attr(not(any(feature = "virtio_blk", feature = "initrd")), allow(dead_code))]
+pub(super) const BLOCK_SIZE: usize = 512;
+/// File の名前の最大長（byte）
+pub(super) const NAME_MAX: usize = 32;
+
+// ustar header の field（offset, 長さ）
+#[cfg_attr(not(any(feature = "virtio_blk", feature = "initrd")), allow(dead_code))]
+const TAR_NAME: (usize, usize) = (0, 100);
+#[cfg_attr(not(any(feature = "virtio_blk", feature = "initrd")), allow(dead_code))]
+const TAR_SIZE: (usize, usize) = (124, 12);
+#[cfg_attr(not(any(feature = "virtio_blk", feature = "initrd")), allow(dead_code))]
+const TAR_TYPEFLAG: usize = 156;
+#[cfg_attr(not(any(feature = "virtio_blk", feature = "initrd")), allow(dead_code))]
+const TAR_MAGIC: (usize, usize) = (257, 5);
+#[cfg_attr(not(any(feature = "virtio_blk", feature = "initrd")), allow(dead_code))]
+const TAR_PREFIX: (usize, usize) = (345, 155);
+
+/// header の octal の数字列（空白 / NUL で終わる）。数字以外があれば None
+#[cfg_attr(not(any(feature = "virtio_blk", feature = "initrd")), allow(dead_code))]
+fn parse_octal(field: &[u8]) -> Option<u64> {
+    let mut v: u64 = 0;
+    let mut digits = 0;
+    for &b in field {
+        match b {
+            b'0'..=b'7' => {
+                v = v.checked_mul(8)?.checked_add((b - b'0') as u64)?;
+                digits += 1;
+            }
+            b' ' | 0 if digits == 0 => {}
+            b' ' | 0 => break,
+            _ => return None,
+        }
+    }
+    (digits > 0).then_some(v)
+}
+
+/// NUL までの部分
+#[cfg_attr(not(any(feature = "virtio_blk", feature = "initrd")), allow(dead_code))]
+fn until_nul(field: &[u8]) -> &[u8] {
+    let n = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    &field[..n]
+}
+
+#[cfg_attr(not(any(feature = "virtio_blk", feature = "initrd")), allow(dead_code))]
+pub(super) enum TarEntry {
+    /// 終端の block
+    End,
+    /// 大きさだけ分かる entry（file 以外 / 名前が使えない）。次の header を探すのに使う
+    Skip { size: u64 },
+    File { name: [u8; NAME_MAX], name_len: usize, size: u64 },
+}
+
+#[cfg_attr(not(any(feature = "virtio_blk", feature = "initrd")), allow(dead_code))]
+impl TarEntry {
+    /// この entry の header と中身が占める block 数（次の header までの距離）
+    pub(super) fn blocks(&self) -> u64 {
+        match *self {
+            TarEntry::End => 0,
+            TarEntry::Skip { size } | TarEntry::File { size, .. } => 1 + size.div_ceil(BLOCK_SIZE as u64),
+        }
+    }
+}
+
+/// 1 block の header を読む（ustar でなければ None）
+#[cfg_attr(not(any(feature = "virtio_blk", feature = "initrd")), allow(dead_code))]
+pub(super) fn parse_header(h: &[u8]) -> Option<TarEntry> {
+    if h.len() < BLOCK_SIZE {
+        return None;
+    }
+    let name = until_nul(&h[TAR_NAME.0..TAR_NAME.0 + TAR_NAME.1]);
+    if name.is_empty() {
+        return Some(TarEntry::End);
+    }
+    if &h[TAR_MAGIC.0..TAR_MAGIC.0 + TAR_MAGIC.1] != b"ustar" {
+        return None;
+    }
+    let size = parse_octal(&h[TAR_SIZE.0..TAR_SIZE.0 + TAR_SIZE.1])?;
+
+    let is_file = matches!(h[TAR_TYPEFLAG], b'0' | 0);
+    let has_prefix = h[TAR_PREFIX.0] != 0;
+    let name = name.strip_prefix(b"./").unwrap_or(name);
+    if !is_file || has_prefix || name.is_empty() || name.len() > NAME_MAX {
+        return Some(TarEntry::Skip { size });
+    }
+
+    let mut buf = [0u8; NAME_MAX];
+    buf[..name.len()].copy_from_slice(name);
+    Some(TarEntry::File { name: buf, name_len: name.len(), size })
+}
@@ -0,0 +1,92 @@
+// kernel/src/kernel/task_info.rs
+//
+// 役割:
+// - TaskInfo syscall: task の観測できる状態（state / 実効 priority / runtime_ticks / address_space_id）を返す。
+//   user 側の monitor を書けるようにする。
+//
+// 中身:
+// - KIND_TASK_INFO の record（abi.rs の encode_task_info）の word 1..4 と同じもの・同じ符号（STATE_*）
+//   * 形式モデルが「観測できる状態」とするものと、user が syscall で知れるものを揃える
+//   * blocked_reason / 待ち相手は返さない（record の word 5..7 は trace 専用）
+//
+// 返し方:
+// - last_syscall_ret = SYSCALL_OK / SYSCALL_ERR_BAD_TASK（handle の task が死んでいる）/
+//   SYSCALL_ERR_BAD_CAP・SYSCALL_ERR_FORBIDDEN・SYSCALL_ERR_WRONG_TYPE（handle の違反。CapError::syscall_code）
+// - 成功時の中身は Task.last_task_info（kernel 内の user step が読む）
+// - ring3（ring3_task.rs の ring3_syscall_poll）は rax = last_syscall_ret、rdx = TaskInfo::pack()
+//   * pack の配置は abi.rs の TASK_INFO_*_SHIFT。runtime_ticks は 32bit で飽和する
+//
+// 方針:
+// - 読むだけ。Info 付きの task handle が要る（既定では idle 以外の全 task が全 task の Info を持つ。cspace.rs）
+// - 死んだ task の handle は kill / TaskExit で消える（Dead の task は引けない。BAD_CAP）
+//
+// やらないこと:
+// - 複数 task の一括取得 / 変化の通知（monitor は poll する）
+
+use super::abi::{
+    STATE_BLOCKED, STATE_DEAD, STATE_READY, STATE_RUNNING, STATE_SUSPENDED, SYSCALL_OK, TASK_INFO_ASID_SHIFT,
+    TASK_INFO_PRIORITY_SHIFT, TASK_INFO_RUNTIME_SHIFT, TASK_INFO_STATE_SHIFT,
+};
+use super::cspace::{CapIndex, CapRights};
+use super::{KernelState, TaskState};
+
+/// TaskInfo syscall の結果（KIND_TASK_INFO の word 1..4 と同じ）
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct TaskInfo {
+    /// STATE_*（abi.rs）
+    pub state: u64,
+    /// 実効優先度（priority inheritance 込み。scheduler が見る値）
+    pub priority: u8,
+    pub runtime_ticks: u64,
+    pub address_space_id: u64,
+}
+
+impl TaskInfo {
+    /// ring3 の rdx 1 本に詰める（配置は abi.rs の TASK_INFO_*_SHIFT）
+    #[cfg_attr(not(feature = "ring3_tasks"), allow(dead_code))]
+    pub fn pack(&self) -> u64 {
+        let runtime = self.runtime_ticks.min(u32::MAX as u64);
+        ((self.state & 0xFF) << TASK_INFO_STATE_SHIFT)
+            | ((self.priority as u64) << TASK_INFO_PRIORITY_SHIFT)
+            | ((self.address_space_id & 0xFFFF) << TASK_INFO_ASID_SHIFT)
+            | (runtime << TASK_INFO_RUNTIME_SHIFT)
+    }
+}
+
+fn state_code(s: TaskState) -> u64 {
+    match s {
+        TaskState::Ready => STATE_READY,
+        TaskState::Running => STATE_RUNNING,
+        TaskState::Blocked => STATE_BLOCKED,
+        TaskState::Dead => STATE_DEAD,
+        TaskState::Suspended => STATE_SUSPENDED,
+    }
+}
+
+impl KernelState {
+    /// task index の TaskInfo（encode_task_info と同じ値）
+    pub(super) fn task_info_of(&self, t: usize) -> TaskInfo {
+        let task = &self.tasks[t];
+        TaskInfo {
+            state: state_code(task.state),
+            priority: task.priority,
+            runtime_ticks: task.runtime_ticks,
+            address_space_id: task.address_space_id.0 as u64,
+        }
+    }
+
+    pub(super) fn syscall_task_info(&mut self, idx: usize, cap: CapIndex) -> u64 {
+        self.counters.task_info_queries += 1;
+
+        let t = match self.resolve_task_cap(idx, cap, CapRights::INFO, "task_info") {
+            Ok(t) => t,
+            Err(e) => {
+                self.tasks[idx].last_task_info = None;
+                return e;
+            }
+        };
+
+        self.tasks[idx].last_task_info = Some(self.task_info_of(t));
+        SYSCALL_OK
+    }
+}
@@ -0,0 +1,200 @@
+// kernel/src/kernel/task_lifecycle.rs
+//
+// 役割:
+// - TaskCreate / TaskExit syscall（実行時の task 生成・退役）。
+//
+// 設計方針:
+// - task table は固定長（MAX_TASKS）のまま。Dead の user slot を再利用する
+//   * slot 0（kernel task）は再利用しない
+// - TaskId は再利用しない（next_task_id から払い出す）
+//   → event log 上で「前の住人」と「新しい住人」が混ざらない
+// - 再利用時は pagetable_init で新しい root(PML4) を確保し、AddressSpace も作り直す
+// - capability table も slot の既定（cspace.rs の default_for_task）で入れ直す
+//   * task の handle（TASK_CAP_BASE + slot）は全 task の table に入れ直す。呼び出し元には Control | Info（cspace.rs）
+// - TaskExit の片付けは kill と同じ teardown_task を通す（経路で掃除漏れが出ないように）
+// - priority の上限: TASK_PRIORITY_MAX 以下、かつ user task からは自分の base_priority 以下
+//   （子を作って優先度を上げる抜け道を塞ぐ。kernel task は上限のみ）
+//
+// 戻り値（last_syscall_ret、abi.rs が正本）:
+// - TaskCreate: 成功 = TASK_CREATE_OK_TAG | 子の task handle（TASK_CREATE_CAP_SHIFT から）| 新 TaskId（下位 32bit）/
+//               SYSCALL_ERR_NO_TASK_SLOT / SYSCALL_ERR_BAD_PRIORITY /
+//               SYSCALL_ERR_CAPACITY（root 用フレームが無い）
+// - TaskExit:   成功時は戻らない（task は Dead）。kernel task は SYSCALL_ERR_FORBIDDEN
+//
+// 制限:
+// - entry_hint は記録するだけ（user_program は slot 単位でプログラムを選ぶ）
+// - 退役した slot の旧 root フレームは返却しない（PhysicalMemoryManager に free が無い）
+
+use super::abi::{
+    SYSCALL_ERR_BAD_PRIORITY, SYSCALL_ERR_CAPACITY, SYSCALL_ERR_FORBIDDEN, SYSCALL_ERR_NO_TASK_SLOT, SYSCALL_OK,
+    TASK_CREATE_CAP_SHIFT, TASK_CREATE_ID_MASK, TASK_CREATE_OK_TAG, TASK_PRIORITY_MAX,
+};
+use super::cspace::TASK_CAP_BASE;
+use super::{
+    pagetable_init, AddressSpace, AddressSpaceKind, CapTable, InvariantId, KernelState, LogEvent, Task, TaskId,
+    TaskState, FIRST_USER_ASID_INDEX,
+};
+use crate::arch::ops::{Arch, ArchOps};
+use spec_macros::spec;
+
+impl KernelState {
+    pub(super) fn is_kernel_address_space_of(&self, idx: usize) -> bool {
+        let as_idx = self.tasks[idx].address_space_id.0;
+        as_idx < self.num_tasks && self.address_spaces[as_idx].kind == AddressSpaceKind::Kernel
+    }
+
+    /// 再利用できる slot（Dead の user slot）
+    fn find_free_task_slot(&self) -> Option<usize> {
+        (FIRST_USER_ASID_INDEX..self.num_tasks).find(|&i| self.tasks[i].state == TaskState::Dead)
+    }
+
+    pub(super) fn syscall_task_create(&mut self, parent_idx: usize, entry_hint: u64, priority: u8) -> u64 {
+        let parent_id = self.tasks[parent_idx].id;
+
+        let max_prio = if self.is_kernel_address_space_of(parent_idx) {
+            TASK_PRIORITY_MAX
+        } else {
+            self.tasks[parent_idx].base_priority.min(TASK_PRIORITY_MAX)
+        };
+        if priority == 0 || priority > max_prio {
+            crate::logging::error("task_create: priority out of range");
+            crate::logging::info_u64("parent_task_id", parent_id.0);
+            crate::logging::info_u64("priority", priority as u64);
+            crate::logging::info_u64("max_priority", max_prio as u64);
+            return SYSCALL_ERR_BAD_PRIORITY;
+        }
+
+        let slot = match self.find_free_task_slot() {
+            Some(i) => i,
+            None => return SYSCALL_ERR_NO_TASK_SLOT,
+        };
+
+        // TaskId が handle / tag に食い込んだら払い出しを止める（現実には起きない）
+        if self.next_task_id & !TASK_CREATE_ID_MASK != 0 {
+            return SYSCALL_ERR_NO_TASK_SLOT;
+        }
+
+        let as_idx = self.tasks[slot].address_space_id.0;
+
+        // 新しい root(PML4)。kernel 側の上位半分は現在の PML4 からコピーする
+        let root = match pagetable_init::allocate_new_l4_table(&mut self.phys_mem) {
+            Some(f) => f,
+            None => {
+                crate::logging::error("task_create: no more frames for user pml4");
+                return SYSCALL_ERR_CAPACITY;
+            }
+        };
+        Arch::init_user_pml4_from_current(root);
+
+        let mut aspace = AddressSpace::new_user();
+        aspace.root_page_frame = Some(root);
+        self.address_spaces[as_idx] = aspace;
+
+        let id = TaskId(self.next_task_id);
+        self.next_task_id += 1;
+
+        self.tasks[slot] = Task {
+            id,
+            state: TaskState::Ready,
+            priority,
+            base_priority: priority,
+            inherited_from: None,
+            entry_hint,
+            parent: Some(parent_id),
+            runtime_ticks: 0,
+            time_slice_used: 0,
+            address_space_id: self.tasks[slot].address_space_id,
+            blocked_reason: None,
+            wake_at: None,
+            last_msg: None,
+            last_recv_ep: None,
+            last_reply_cap: None,
+            last_reply: None,
+            last_notify: None,
+            last_task_info: None,
+            last_mem_info: None,
+            last_syscall_ret: None,
+            last_syscall_ret_unread: false,
+            pending_send_msg: None,
+            pending_syscall: None,
+        };
+        self.cspaces[slot] = CapTable::default_for_task(slot);
+        for t in 0..self.num_tasks {
+            self.install_task_handle(slot, t);
+            self.install_task_handle(t, slot);
+        }
+        self.reply_slow_reported[slot] = false;
+        self.ipc_deadline[slot] = None;
+
+        self.counters.task_created += 1;
+
+        crate::logging::info("task_create: created");
+        crate::logging::info_u64("task_id", id.0);
+        crate::logging::info_u64("parent_task_id", parent_id.0);
+        crate::logging::info_u64("slot", slot as u64);
+        crate::logging::info_u64("root_page_frame_index", root.number);
+
+        self.push_event(LogEvent::TaskCreated { task: id, parent: parent_id, slot, priority });
+        self.push_event(LogEvent::TaskStateChanged(id, TaskState::Ready));
+        self.enqueue_ready(slot);
+
+        TASK_CREATE_OK_TAG | (((TASK_CAP_BASE + slot) as u64) << TASK_CREATE_CAP_SHIFT) | id.0
+    }
+
+    /// 成功したら SYSCALL_OK（呼び出し元はもう Dead なので戻り値は書かない）
+    pub(super) fn syscall_task_exit(&mut self, idx: usize) -> u64 {
+        if self.is_kernel_address_space_of(idx) {
+            crate::logging::error("task_exit: kernel task cannot exit");
+            return SYSCALL_ERR_FORBIDDEN;
+        }
+
+        let id = self.tasks[idx].id;
+        crate::logging::info("task_exit: exiting");
+        crate::logging::info_u64("task_id", id.0);
+
+        self.teardown_task(idx);
+        self.counters.task_exited += 1;
+
+        self.push_event(LogEvent::TaskExited { task: id });
+        self.push_event(LogEvent::TaskStateChanged(id, TaskState::Dead));
+
+        if idx == self.current_task {
+            self.schedule_next_task();
+        }
+
+        self.maybe_halt_if_no_user_tasks();
+        SYSCALL_OK
+    }
+
+    /// TaskId は生きている task 間で一意で、払い出し済みの範囲に収まる
+    #[spec("INV-TASK-001")]
+    pub(super) fn check_task_table_invariants(&self) {
+        for i in 0..self.num_tasks {
+            let t = &self.tasks[i];
+            if t.id.0 >= self.next_task_id {
+                self.invariant_violated(
+                    InvariantId::TaskIdUnique,
+                    Some(t.id),
+                    None,
+                    "INVARIANT VIOLATION: task id beyond next_task_id",
+                );
+                crate::logging::info_u64("task_index", i as u64);
+                crate::logging::info_u64("task_id", t.id.0);
+            }
+            if t.state == TaskState::Dead {
+                continue;
+            }
+            for j in (i + 1)..self.num_tasks {
+                if self.tasks[j].state != TaskState::Dead && self.tasks[j].id == t.id {
+                    self.invariant_violated(
+                        InvariantId::TaskIdUnique,
+                        Some(t.id),
+                        None,
+                        "INVARIANT VIOLATION: duplicate live task id",
+                    );
+                    crate::logging::info_u64("task_id", t.id.0);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,128 @@
+// kernel/src/kernel/task_queue.rs
+//
+// 役割:
+// - endpoint の send_queue / reply_queue（ipc.rs）に使う、task index の FIFO リングバッファ。
+//   以前の swap-remove（順序は未規定）をやめ、並んだ順を保つ。
+//
+// 中身:
+// - slots[head..head+len]（MAX_TASKS で折り返す）に task index、seqs に並んだときの番号（enqueue seq）を持つ
+//   * enqueue seq はキューごとに 1 から増える。head から tail へ狭義単調増加（INV-IPC-011）
+//   * last_popped_seq: pop_front で最後に取り出した seq（0 = まだ無い）
+// - 公平性（send_queue）: 先に並んだ sender が必ず先に deliver される
+//   * deliver は pop_front だけ。途中の要素を外すのは待ちを諦めるとき（timeout / 救済 / kill）だけで、順序は崩さない
+//   * 残っている要素の seq は last_popped_seq より大きい（先に並んだ task を追い越して deliver していない）
+//
+// やらないこと:
+// - 優先度順の取り出し（priority inheritance は待ち相手を見るだけで、キューの順は変えない）
+// - reply_queue の取り出し順の保証（reply は reply cap で client を名指しするので途中から外れる。順は dump の観測用）
+
+use super::MAX_TASKS;
+
+#[derive(Clone, Copy)]
+pub struct TaskQueue {
+    slots: [usize; MAX_TASKS],
+    seqs: [u64; MAX_TASKS],
+    head: usize,
+    len: usize,
+    next_seq: u64,
+    last_popped_seq: u64,
+}
+
+impl TaskQueue {
+    pub const fn new() -> Self {
+        TaskQueue { slots: [0; MAX_TASKS], seqs: [0; MAX_TASKS], head: 0, len: 0, next_seq: 1, last_popped_seq: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len >= MAX_TASKS
+    }
+
+    /// 先頭から pos 番目の slot 位置
+    fn at(&self, pos: usize) -> usize {
+        (self.head + pos) % MAX_TASKS
+    }
+
+    /// 並んだ順（先頭 = 次に取り出す task）の task index
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.len).map(move |pos| self.slots[self.at(pos)])
+    }
+
+    /// 並んだ順の (task index, enqueue seq)（invariant / dump 用）
+    pub fn entries(&self) -> impl Iterator<Item = (usize, u64)> + '_ {
+        (0..self.len).map(move |pos| {
+            let at = self.at(pos);
+            (self.slots[at], self.seqs[at])
+        })
+    }
+
+    pub fn front(&self) -> Option<usize> {
+        if self.len == 0 { None } else { Some(self.slots[self.head]) }
+    }
+
+    pub fn contains(&self, idx: usize) -> bool {
+        self.iter().any(|i| i == idx)
+    }
+
+    /// 末尾に並べる（満杯なら false。既に居れば並び直さずに true）
+    pub fn push_back(&mut self, idx: usize) -> bool {
+        if self.is_full() {
+            return false;
+        }
+        if self.contains(idx) {
+            return true;
+        }
+        let at = self.at(self.len);
+        self.slots[at] = idx;
+        self.seqs[at] = self.next_seq;
+        self.next_seq += 1;
+        self.len += 1;
+        true
+    }
+
+    /// 先頭（一番先に並んだ task）を取り出す
+    pub fn pop_front(&mut self) -> Option<usize> {
+        if self.len == 0 {
+            return None;
+        }
+        let idx = self.slots[self.head];
+        self.last_popped_seq = self.seqs[self.head];
+        self.head = (self.head + 1) % MAX_TASKS;
+        self.len -= 1;
+        Some(idx)
+    }
+
+    /// idx を外す（後ろの要素を詰めて順序を保つ。居なければ false）
+    pub fn remove(&mut self, idx: usize) -> bool {
+        let Some(pos) = self.iter().position(|i| i == idx) else {
+            return false;
+        };
+        for p in pos..self.len - 1 {
+            let (to, from) = (self.at(p), self.at(p + 1));
+            self.slots[to] = self.slots[from];
+            self.seqs[to] = self.seqs[from];
+        }
+        self.len -= 1;
+        true
+    }
+
+    /// 公平性の破れ（INV-IPC-011）: 並び順で seq が増えていない / 取り出し済みの seq 以下の要素が残っている
+    /// / 払い出していない seq。最初に見つけた要素の (task index, seq)
+    pub fn order_violation(&self) -> Option<(usize, u64)> {
+        let mut prev = self.last_popped_seq;
+        for (idx, seq) in self.entries() {
+            if seq <= prev || seq >= self.next_seq {
+                return Some((idx, seq));
+            }
+            prev = seq;
+        }
+        None
+    }
+}
@@ -0,0 +1,132 @@
+// kernel/src/kernel/time.rs
+//
+// 役割:
+// - カーネルの時刻を ns の Instant 1 種類で表す（起動からの単調時刻）。
+//   * kernel clock（KernelState::now）: Sleep の期限と IPC の timeout はこれで測る
+//   * hardware clock（monotonic_ns）: ArchOps の clock_*（実機は arch::clock の HPET / calibrate した TSC）。kernel clock の元で、dump の timestamp にも使う
+//   * 壁時計（boot_wallclock）: 起動時に CMOS RTC から読んだ UTC（arch::rtc）。ログをホストの時刻と突き合わせる用
+// - syscall の引数（Sleep { ticks } / IpcSend { timeout }）は tick のまま受け、NS_PER_TICK で ns にして Instant に足す
+//
+// kernel clock の元（KernelClockSource）:
+// - Hardware: hardware clock があれば（HPET / TSC）、tick の先頭で 1 回読んだ値（sample_clock）
+// - Ticks: hardware clock が無いとき（ホストの MockArch もここ）は tick_count × NS_PER_TICK
+//   * synthetic_tick / replay / scenario_suite も Ticks（tick が実時間で進まない。台本どおりの tick で期限が来る）
+//
+// 方針:
+// - now は tick の中では変わらない（tick の先頭で読んだ値。期限の判定と invariant が同じ now を見る）
+// - now は戻らない（読んだ値が前より小さければ前の値のまま）
+// - 期限は Instant 同士でだけ比べる（raw の tick 数で期限を判定しない）
+// - Hardware のときは 1 tick が何 ns で進んだかで起きる tick が変わる（trace / state hash の再現性は Ticks のときだけ）
+//
+// やらないこと:
+// - timer_service の周期（UpdateTimer の回数 = time_ticks で数える。service の仕様）
+// - tick より細かい粒度の期限（期限は tick の先頭でだけ見る）
+// - 壁時計での期限（壁時計は起動時の 1 点だけ。期限にも trace にも使わない）
+
+use super::KernelState;
+use crate::arch;
+use crate::arch::ops::{Arch, ArchOps, ClockSource};
+
+pub use crate::arch::rtc::WallClock;
+
+/// 1 tick の長さ（ns）
+pub const NS_PER_TICK: u64 = 1_000_000_000 / arch::timer::TIMER_HZ as u64;
+
+/// kernel clock の時刻（起動からの ns）
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct Instant(u64);
+
+impl Instant {
+    pub const fn as_ns(self) -> u64 {
+        self.0
+    }
+
+    /// tick 数を kernel clock の時刻にする（tick 0 = 0ns）
+    pub const fn from_ticks(ticks: u64) -> Instant {
+        Instant(ticks.saturating_mul(NS_PER_TICK))
+    }
+
+    /// ticks 後の時刻（飽和する）
+    pub const fn after_ticks(self, ticks: u64) -> Instant {
+        Instant(self.0.saturating_add(ticks.saturating_mul(NS_PER_TICK)))
+    }
+}
+
+/// kernel clock の元
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum KernelClockSource {
+    /// hardware clock（HPET / TSC）を tick の先頭で読む
+    Hardware,
+    /// tick_count × NS_PER_TICK
+    Ticks,
+}
+
+impl KernelClockSource {
+    pub fn name(self) -> &'static str {
+        match self {
+            KernelClockSource::Hardware => "hardware",
+            KernelClockSource::Ticks => "ticks",
+        }
+    }
+}
+
+/// 今の kernel clock の元（hardware clock が無いか、tick が台本で進む build なら Ticks）
+pub fn kernel_clock_source() -> KernelClockSource {
+    if cfg!(any(feature = "synthetic_tick", feature = "replay", feature = "scenario_suite")) {
+        return KernelClockSource::Ticks;
+    }
+    match Arch::clock_source() {
+        ClockSource::None => KernelClockSource::Ticks,
+        _ => KernelClockSource::Hardware,
+    }
+}
+
+/// hardware clock の経過 ns（ArchOps::clock_init から。clock が無ければ 0）
+pub fn monotonic_ns() -> u64 {
+    Arch::clock_now_ns()
+}
+
+/// hardware clock の出どころ（"hpet" / "tsc" / "none"）
+pub fn clock_source_name() -> &'static str {
+    Arch::clock_source().name()
+}
+
+/// 起動時の壁時計（RTC が読めなかったら None）
+pub fn boot_wallclock() -> Option<WallClock> {
+    arch::rtc::boot_wallclock()
+}
+
+impl KernelState {
+    /// kernel clock の今（期限の判定はすべてこれで行う。tick の先頭で sample_clock が決めた値）
+    pub(super) fn now(&self) -> Instant {
+        self.clock_now
+    }
+
+    /// tick の先頭で kernel clock を進める（tick_body から。tick_count を進めた後）
+    pub(super) fn sample_clock(&mut self) {
+        let t = match kernel_clock_source() {
+            KernelClockSource::Hardware => Instant(monotonic_ns()),
+            KernelClockSource::Ticks => Instant::from_ticks(self.tick_count),
+        };
+        self.clock_now = core::cmp::max(self.clock_now, t);
+    }
+
+    /// 今から ticks 後の期限
+    pub(super) fn deadline_after_ticks(&self, ticks: u64) -> Instant {
+        self.now().after_ticks(ticks)
+    }
+
+    /// Counters Dump の time 部分（hardware clock はタイミング依存なので wire / state hash には入れない）
+    pub(super) fn dump_time(&self) {
+        crate::logging::info_u64("kernel_clock_ns", self.now().as_ns());
+        match kernel_clock_source() {
+            KernelClockSource::Hardware => crate::logging::info("kernel_clock_source = hardware"),
+            KernelClockSource::Ticks => crate::logging::info("kernel_clock_source = ticks"),
+        }
+        crate::logging::info_u64("hw_clock_ns", monotonic_ns());
+        crate::logging::info_u64("hw_clock_hz", Arch::clock_frequency_hz());
+        if let Some(wc) = boot_wallclock() {
+            crate::logging::info_u64("boot_unix_seconds", wc.unix_seconds());
+        }
+    }
+}
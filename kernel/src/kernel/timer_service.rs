@@ -0,0 +1,249 @@
+// kernel/src/kernel/timer_service.rs
+//
+// 役割:
+// - カーネルタイマ（time_ticks）を、client ごとの周期通知（IPC）に多重化する kernel service。
+// - 各 task が自前のタイマを持たなくても、周期ワークロードを組めるようにする。
+//
+// プロトコル（abi.rs が正本）:
+// - 登録: TIMER_SERVICE_EP に IpcSend。msg = (notify_ep << 32) | period_ticks
+//   * period_ticks = 0 は解除
+//   * service は block させずに即 reply（last_reply = TIMER_SVC_OK / TIMER_SVC_ERR_*）
+// - 通知: notify_ep で IpcRecv している client に MR0 = TIMER_TICK_TAG | 通知番号、MR1 = missed を渡す
+//   * recv していない間に来た周期は「まとめて 1 回」にする（coalesce、件数は数える）
+//   * 次に notify_ep で recv したときに block せず即受け取る
+//
+// 設計方針:
+// - 期限管理は hashed timer wheel（slot = deadline % TIMER_WHEEL_SLOTS、slot ごとに client の bitmask）
+//   * 1 tick で見るのは 1 slot だけ。周回が先の client は bit を残して次の周回へ回す
+// - client は task index で引く（1 task = 1 登録、再登録は上書き）
+// - kill された task の登録は kill 側で必ず消す（死んだ task に通知しない）
+//
+// 制限:
+// - 通知は kernel 起点の deliver（sender が居ないので reply obligation は発生しない）
+// - notify_ep は TIMER_SERVICE_EP 以外の open な endpoint に限る
+
+use super::abi::{TIMER_SVC_ERR_BAD_EP, TIMER_SVC_ERR_BAD_TASK, TIMER_SVC_OK, TIMER_TICK_TAG};
+use super::{EndpointId, IpcMessage, KernelState, LogEvent, TaskState, MAX_ENDPOINTS, MAX_TASKS};
+
+// wheel の slot は client の bitmask（u32）
+const _: () = assert!(MAX_TASKS <= 32);
+
+/// service の endpoint（ここへの send は service が直接処理する）
+pub const TIMER_SERVICE_EP: EndpointId = EndpointId(1);
+
+/// timer wheel の slot 数
+pub const TIMER_WHEEL_SLOTS: usize = 8;
+
+#[derive(Clone, Copy)]
+struct TimerClient {
+    notify_ep: EndpointId,
+    period: u64,
+    deadline: u64,
+    /// recv していなかったため未配送の周期数（coalesce 済み）
+    pending: u64,
+}
+
+pub struct TimerService {
+    clients: [Option<TimerClient>; MAX_TASKS],
+    wheel: [u32; TIMER_WHEEL_SLOTS],
+
+    // 集計（report 用）
+    pub registrations: u64,
+    pub fired: u64,
+    pub delivered: u64,
+    pub coalesced: u64,
+}
+
+impl TimerService {
+    pub const fn new() -> Self {
+        TimerService {
+            clients: [None; MAX_TASKS],
+            wheel: [0; TIMER_WHEEL_SLOTS],
+            registrations: 0,
+            fired: 0,
+            delivered: 0,
+            coalesced: 0,
+        }
+    }
+
+    fn insert(&mut self, idx: usize, deadline: u64) {
+        let slot = (deadline % TIMER_WHEEL_SLOTS as u64) as usize;
+        self.wheel[slot] |= 1u32 << idx;
+    }
+
+    fn remove_from_wheel(&mut self, idx: usize) {
+        for s in self.wheel.iter_mut() {
+            *s &= !(1u32 << idx);
+        }
+    }
+
+    pub fn cancel(&mut self, idx: usize) {
+        if idx >= MAX_TASKS {
+            return;
+        }
+        self.clients[idx] = None;
+        self.remove_from_wheel(idx);
+    }
+}
+
+/// msg = (notify_ep << 32) | period_ticks
+pub const fn encode_register(notify_ep: EndpointId, period_ticks: u64) -> u64 {
+    ((notify_ep.0 as u64) << 32) | (period_ticks & 0xFFFF_FFFF)
+}
+
+impl KernelState {
+    /// TIMER_SERVICE_EP への send を処理し、reply 値を返す
+    pub(super) fn timer_service_handle_send(&mut self, client_idx: usize, msg: u64) -> u64 {
+        if client_idx >= self.num_tasks || self.tasks[client_idx].state == TaskState::Dead {
+            return TIMER_SVC_ERR_BAD_TASK;
+        }
+
+        let notify_ep = EndpointId((msg >> 32) as usize);
+        let period = msg & 0xFFFF_FFFF;
+
+        if period == 0 {
+            self.timer_service.cancel(client_idx);
+            crate::logging::info("timer_service: unregistered");
+            crate::logging::info_u64("task_id", self.tasks[client_idx].id.0);
+            return TIMER_SVC_OK;
+        }
+
+        if notify_ep.0 >= MAX_ENDPOINTS || notify_ep == TIMER_SERVICE_EP || self.endpoints[notify_ep.0].is_closed {
+            crate::logging::error("timer_service: bad notify_ep");
+            crate::logging::info_u64("task_id", self.tasks[client_idx].id.0);
+            crate::logging::info_u64("ep_id", notify_ep.0 as u64);
+            return TIMER_SVC_ERR_BAD_EP;
+        }
+
+        let deadline = self.time_ticks + period;
+
+        let svc = &mut self.timer_service;
+        svc.remove_from_wheel(client_idx);
+        svc.clients[client_idx] = Some(TimerClient {
+            notify_ep,
+            period,
+            deadline,
+            pending: 0,
+        });
+        svc.insert(client_idx, deadline);
+        svc.registrations += 1;
+
+        crate::logging::info("timer_service: registered");
+        crate::logging::info_u64("task_id", self.tasks[client_idx].id.0);
+        crate::logging::info_u64("ep_id", notify_ep.0 as u64);
+        crate::logging::info_u64("period_ticks", period);
+
+        TIMER_SVC_OK
+    }
+
+    /// time_ticks が進んだ直後に呼ぶ（UpdateTimer）
+    pub(super) fn timer_service_on_tick(&mut self) {
+        let now = self.time_ticks;
+        let slot = (now % TIMER_WHEEL_SLOTS as u64) as usize;
+
+        let bits = self.timer_service.wheel[slot];
+        self.timer_service.wheel[slot] = 0;
+
+        for idx in 0..MAX_TASKS {
+            if bits & (1u32 << idx) == 0 {
+                continue;
+            }
+
+            let mut c = match self.timer_service.clients[idx] {
+                Some(c) => c,
+                None => continue,
+            };
+
+            // 周回が先：bit を戻して次の周回で見る
+            if c.deadline > now {
+                self.timer_service.insert(idx, c.deadline);
+                continue;
+            }
+
+            self.timer_service.fired += 1;
+            c.pending += 1;
+
+            // 取りこぼした周期は飛ばして次の期限へ（now 基準で再計算）
+            c.deadline = now + c.period;
+            self.timer_service.clients[idx] = Some(c);
+            self.timer_service.insert(idx, c.deadline);
+
+            self.timer_service_try_deliver(idx);
+        }
+    }
+
+    /// client が notify_ep で recv 待ちなら、未配送分をまとめて 1 回渡す
+    fn timer_service_try_deliver(&mut self, idx: usize) {
+        let c = match self.timer_service.clients[idx] {
+            Some(c) => c,
+            None => return,
+        };
+        if c.pending == 0 || idx >= self.num_tasks {
+            return;
+        }
+
+        let ep = c.notify_ep;
+        let waiting = self.endpoints[ep.0].recv_waiter == Some(idx)
+            && self.tasks[idx].state == TaskState::Blocked
+            && self.tasks[idx].blocked_reason.is_some_and(|r| r.waits_recv_on(ep));
+        if !waiting {
+            return;
+        }
+
+        self.release_recv_waiter(idx);
+        let msg = self.timer_service_take_pending(idx);
+        self.tasks[idx].last_msg = Some(msg);
+        self.tasks[idx].last_recv_ep = Some(ep);
+        self.tasks[idx].last_reply_cap = None;
+        self.wake_task_to_ready(idx);
+    }
+
+    /// 未配送分を消費して通知 msg を作る（coalesce 件数も数える）
+    fn timer_service_take_pending(&mut self, idx: usize) -> IpcMessage {
+        let task = self.tasks[idx].id;
+        let mut c = match self.timer_service.clients[idx] {
+            Some(c) => c,
+            None => return IpcMessage::word(TIMER_TICK_TAG),
+        };
+
+        let missed = c.pending.saturating_sub(1);
+        c.pending = 0;
+        self.timer_service.clients[idx] = Some(c);
+
+        self.timer_service.delivered += 1;
+        self.timer_service.coalesced += missed;
+
+        let seq = self.timer_service.delivered;
+        self.push_event(LogEvent::TimerFired { task, ep: c.notify_ep, missed });
+
+        let tag = TIMER_TICK_TAG | (seq & 0x0000_FFFF_FFFF_FFFF);
+        IpcMessage::from_words(&[tag, missed]).unwrap_or(IpcMessage::word(tag))
+    }
+
+    /// ipc_recv の入口で呼ぶ。未配送の周期があれば block せずに受け取る。
+    pub(super) fn timer_service_recv_pending(&mut self, idx: usize, ep: EndpointId) -> bool {
+        let c = match self.timer_service.clients.get(idx).copied().flatten() {
+            Some(c) => c,
+            None => return false,
+        };
+        if c.notify_ep != ep || c.pending == 0 {
+            return false;
+        }
+
+        let msg = self.timer_service_take_pending(idx);
+        self.tasks[idx].last_msg = Some(msg);
+        self.tasks[idx].last_recv_ep = Some(ep);
+        self.tasks[idx].last_reply_cap = None;
+        true
+    }
+
+    pub(super) fn timer_service_report(&self) {
+        let s = &self.timer_service;
+        crate::logging::info("=== Timer Service Report ===");
+        crate::logging::info_u64("registrations", s.registrations);
+        crate::logging::info_u64("fired", s.fired);
+        crate::logging::info_u64("delivered", s.delivered);
+        crate::logging::info_u64("coalesced", s.coalesced);
+        crate::logging::info("=== End of Timer Service Report ===");
+    }
+}
@@ -0,0 +1,165 @@
+// kernel/src/kernel/tlb.rs
+//
+// 役割:
+// - TLB invalidation のモデル。今の CR3 でない root を変えたときに「その root の TLB は古い」と記録し、
+//   その root を次に CR3 に載せるときに flush する。
+//
+// 背景:
+// - invlpg は今の CR3 の TLB にしか効かない。別 task の root の mapping を変えても、
+//   その root の古い entry は次にその root が走るまで残りうる（arch::paging は今の CR3 のときだけ invlpg する）
+//   * 単一CPU・PCID なしなら CR3 の書き込みで非 global entry は捨てられるが、ここでは明示的に flush する
+//     （PCID / SMP を入れたときに shootdown が要る箇所をここに集める）
+//
+// 方針:
+// - KernelState から user root への反映は apply_in_root（batch は apply_batch_in_root）を通す
+//   * root が今の CR3 なら arch 側の invlpg で済む。違えば遅延を記録して TlbFlushDeferred を出す
+// - 遅延は AddressSpace ごとに「溜まった変更数」と「最初に遅延した tick」だけ持つ（flush は全体）
+// - flush するのは kernel が root を CR3 に載せるところ（schedule_next_task / kstack の load_root_of）
+//   * flush したら TlbFlushed（stale_ticks = 古い entry が残りえた tick 数）
+// - guarded_user_* の一時切替（kernel → user → kernel）では flush しない（記録は残る）
+//
+// 不変条件（INV-MEM-009）:
+// - 遅延 flush を持つのは root のある User AddressSpace だけ
+// - 今の CR3 の root の AddressSpace は遅延 flush を持たない（古い entry が見えうるまま走らない）
+//
+// やらないこと:
+// - 他 CPU への IPI shootdown（SMP なし）
+// - page 単位の invlpg（遅延分はまとめて flush する）
+
+use super::{AddressSpaceId, AddressSpaceKind, InvariantId, KernelState, LogEvent, MAX_TASKS};
+use crate::arch::ops::{Arch, ArchOps, PagingApplyError};
+use crate::mem::addr::{PhysFrame, VirtPage};
+use crate::mem::paging::{BatchError, MemAction};
+use spec_macros::spec;
+
+const LOG: crate::logging::Subsystem = crate::logging::Subsystem::Mem;
+
+/// 1 つの AddressSpace の遅延 flush（pages = 0 なら無し）
+#[derive(Clone, Copy)]
+struct PendingFlush {
+    pages: usize,
+    since_tick: u64,
+}
+
+impl PendingFlush {
+    const NONE: Self = Self { pages: 0, since_tick: 0 };
+}
+
+/// AddressSpace index ごとの遅延 flush
+pub(super) struct TlbState {
+    pending: [PendingFlush; MAX_TASKS],
+}
+
+impl TlbState {
+    pub(super) const fn new() -> Self {
+        Self { pending: [PendingFlush::NONE; MAX_TASKS] }
+    }
+}
+
+impl KernelState {
+    /// AddressSpace as_idx の root に action を反映する（root が今の CR3 でなければ flush を遅らせて記録する）
+    pub(super) unsafe fn apply_in_root(
+        &mut self,
+        as_idx: usize,
+        root: PhysFrame,
+        action: MemAction,
+    ) -> Result<(), PagingApplyError> {
+        Arch::apply_mem_action_in_root(action, root, &mut self.phys_mem)?;
+
+        if Arch::current_root() != Some(root) {
+            self.defer_tlb_flush(as_idx, action.page());
+        }
+        Ok(())
+    }
+
+    /// apply_in_root の batch 版（all-or-nothing。全部反映できたときだけ page ごとに遅延を記録する）
+    /// - 途中で落ちた batch は arch 側が元に戻している（ページテーブルは batch の前と同じなので記録しない）
+    pub(super) unsafe fn apply_batch_in_root(
+        &mut self,
+        as_idx: usize,
+        root: PhysFrame,
+        actions: &[MemAction],
+    ) -> Result<(), BatchError<PagingApplyError>> {
+        Arch::apply_mem_actions_in_root(actions, root, &mut self.phys_mem)?;
+
+        if Arch::current_root() != Some(root) {
+            for action in actions {
+                self.defer_tlb_flush(as_idx, action.page());
+            }
+        }
+        Ok(())
+    }
+
+    /// 今の CR3 でない root の page を変えた: その AddressSpace の flush を遅らせて記録する
+    fn defer_tlb_flush(&mut self, as_idx: usize, page: VirtPage) {
+        if as_idx >= MAX_TASKS {
+            return;
+        }
+        let p = &mut self.tlb.pending[as_idx];
+        if p.pages == 0 {
+            p.since_tick = self.tick_count;
+        }
+        p.pages += 1;
+
+        self.counters.tlb_flush_deferred += 1;
+        self.push_event(LogEvent::TlbFlushDeferred { address_space: AddressSpaceId(as_idx), page: page.number });
+    }
+
+    /// root を CR3 に載せた直後: その AddressSpace に遅延 flush があれば flush する
+    pub(super) fn flush_deferred_tlb(&mut self, as_idx: usize) {
+        if as_idx >= MAX_TASKS || self.tlb.pending[as_idx].pages == 0 {
+            return;
+        }
+        let p = self.tlb.pending[as_idx];
+        self.tlb.pending[as_idx] = PendingFlush::NONE;
+
+        Arch::flush_tlb_all();
+
+        let stale_ticks = self.tick_count.saturating_sub(p.since_tick);
+        LOG.info("tlb: deferred flush done");
+        LOG.info_u64("as_idx", as_idx as u64);
+        LOG.info_u64("pages", p.pages as u64);
+        LOG.info_u64("stale_ticks", stale_ticks);
+
+        self.counters.tlb_flushes += 1;
+        self.push_event(LogEvent::TlbFlushed { address_space: AddressSpaceId(as_idx), pages: p.pages, stale_ticks });
+    }
+
+    /// 遅延 flush は root のある User AS だけ。今の CR3 の AS は遅延 flush を持たない
+    #[spec("INV-MEM-009")]
+    pub(super) fn check_tlb_invariants(&self) {
+        let current = Arch::current_root();
+
+        for as_idx in 0..MAX_TASKS {
+            let p = self.tlb.pending[as_idx];
+            if p.pages == 0 {
+                continue;
+            }
+
+            let is_user_with_root = as_idx < self.num_tasks
+                && self.address_spaces[as_idx].kind == AddressSpaceKind::User
+                && self.address_spaces[as_idx].root_page_frame.is_some();
+            if !is_user_with_root {
+                self.invariant_violated(
+                    InvariantId::Tlb,
+                    None,
+                    Some(as_idx as u64),
+                    "INVARIANT VIOLATION: deferred TLB flush on non-user address space",
+                );
+                LOG.info_u64("as_idx", as_idx as u64);
+                continue;
+            }
+
+            if current.is_some() && self.address_spaces[as_idx].root_page_frame == current {
+                self.invariant_violated(
+                    InvariantId::Tlb,
+                    None,
+                    Some(as_idx as u64),
+                    "INVARIANT VIOLATION: active root has deferred TLB flush",
+                );
+                LOG.info_u64("as_idx", as_idx as u64);
+                LOG.info_u64("pages", p.pages as u64);
+            }
+        }
+    }
+}
@@ -8,8 +8,10 @@
 // - Task1: 最初の kick send（1回だけ）
 // - Task0: 周期 kick-send
 // - Task2: IPC server (recv -> reply)
+//   * reply は recv で受け取った reply cap（Task.last_reply_cap）で返す。reply cap が無い msg には返さない
+//   * reply は MR0 = 0xABCD タグ ^ (MR0 下位 16bit)、MR1 = 受け取った msg の語数（multi-word の確認用）
 //
-// 仕様（feature = ipc_demo_single_slow）:
+// 仕様（feature = ipc_demo_single_slow。実行時の判定は demo::scenario）:
 // - 目的: “send_queue 経由の slow send” を 1 回に固定しやすくする
 // - Task1: kick send をしない（ノイズ源を除去）
 // - Task0:
@@ -26,12 +28,20 @@
 //   * IPC  : last_reply
 
 use crate::kernel::{
-    EndpointId, KernelState, Syscall, TaskState, IPC_DEMO_EP0, TASK0_INDEX, TASK1_INDEX, TASK2_INDEX,
+    CapIndex, EndpointId, IpcMessage, KernelState, Syscall, TaskState, IPC_DEMO_CAP0, IPC_DEMO_EP0, TASK0_INDEX,
+    TASK1_INDEX, TASK2_INDEX,
 };
+use crate::kernel::demo::scenario::{self, Scenario};
 
 impl KernelState {
     const IPC_KICK_PERIOD_TICKS: u64 = 8;
 
+    /// server の reply（MR0 = 0xABCD タグ、MR1 = 受け取った語数）
+    fn demo_server_reply(msg: &IpcMessage) -> IpcMessage {
+        let tag = 0xABCD_0000_0000_0000u64 ^ (msg.mr0() & 0xFFFF);
+        IpcMessage::from_words(&[tag, msg.len() as u64]).unwrap_or(IpcMessage::word(tag))
+    }
+
     pub fn user_step_issue_syscall(&mut self, task_idx: usize) {
         if task_idx >= self.num_tasks {
             return;
@@ -52,7 +62,13 @@ impl KernelState {
             return;
         }
 
+        // demo 側が syscall を積んだなら通常のデモ手順は踏まない（stress_ipc 等）
+        if crate::kernel::demo::on_user_step(self, task_idx) {
+            return;
+        }
+
         let ep: EndpointId = IPC_DEMO_EP0;
+        let cap: CapIndex = IPC_DEMO_CAP0;
 
         // ------------------------------------------------------------
         // Step3: syscall 戻り値（mem系）を観測してクリア（unread のときだけ）
@@ -69,10 +85,10 @@ impl KernelState {
         // ------------------------------------------------------------
         if task_idx == TASK0_INDEX {
             // IPC reply が残っていたら観測して消すだけ（任意）
-            if let Some(v) = self.tasks[task_idx].last_reply {
+            if let Some(m) = self.tasks[task_idx].last_reply {
                 crate::logging::info("ipc_reply_received");
                 crate::logging::info_u64("task_id", self.tasks[task_idx].id.0);
-                crate::logging::info_u64("reply", v);
+                crate::logging::info_u64("reply", m.mr0());
                 self.tasks[task_idx].last_reply = None;
             }
             return;
@@ -83,23 +99,24 @@ impl KernelState {
         // ------------------------------------------------------------
         if task_idx == TASK1_INDEX {
             // IPC reply が来てたら観測してクリア
-            if let Some(v) = self.tasks[task_idx].last_reply {
+            if let Some(m) = self.tasks[task_idx].last_reply {
                 crate::logging::info("ipc_reply_received");
                 crate::logging::info_u64("task_id", self.tasks[task_idx].id.0);
-                crate::logging::info_u64("reply", v);
+                crate::logging::info_u64("reply", m.mr0());
                 self.tasks[task_idx].last_reply = None;
             }
 
-            #[cfg(feature = "ipc_demo_single_slow")]
-            {
+            if scenario::is_active(Scenario::IpcSingleSlow) {
                 return;
             }
 
             // 通常モード：最初に 1 回だけ kick
             if !self.demo_sent_by_task1 {
                 self.demo_sent_by_task1 = true;
-                let msg: u64 = 0x1111_0000_0000_0000u64 ^ (self.tick_count & 0xFFFF);
-                self.tasks[task_idx].pending_syscall = Some(Syscall::IpcSend { ep, msg });
+                // MR0 = タグ、MR1 = 送信時の tick（2 語）
+                let tag = 0x1111_0000_0000_0000u64 ^ (self.tick_count & 0xFFFF);
+                let msg = IpcMessage::from_words(&[tag, self.tick_count]).unwrap_or(IpcMessage::word(tag));
+                self.tasks[task_idx].pending_syscall = Some(Syscall::IpcSend { cap, msg, timeout: None });
                 return;
             }
 
@@ -107,8 +124,8 @@ impl KernelState {
             if self.tick_count != 0 && (self.tick_count % Self::IPC_KICK_PERIOD_TICKS) == 0 {
                 let can_fast_send = self.endpoints[ep.0].recv_waiter.is_some();
                 if can_fast_send {
-                    let msg: u64 = 0x2222_0000_0000_0000u64 ^ (self.tick_count & 0xFFFF);
-                    self.tasks[task_idx].pending_syscall = Some(Syscall::IpcSend { ep, msg });
+                    let msg = IpcMessage::word(0x2222_0000_0000_0000u64 ^ (self.tick_count & 0xFFFF));
+                    self.tasks[task_idx].pending_syscall = Some(Syscall::IpcSend { cap, msg, timeout: None });
                     return;
                 }
             }
@@ -123,16 +140,20 @@ impl KernelState {
             if let Some(msg) = self.tasks[task_idx].last_msg {
                 crate::logging::info("ipc_msg_received");
                 crate::logging::info_u64("task_id", self.tasks[task_idx].id.0);
-                crate::logging::info_u64("msg", msg);
+                crate::logging::info_u64("msg", msg.mr0());
+                crate::logging::info_u64("msg_len", msg.len() as u64);
 
-                let reply: u64 = 0xABCD_0000_0000_0000u64 ^ (msg & 0xFFFF);
+                let reply = Self::demo_server_reply(&msg);
 
                 self.tasks[task_idx].last_msg = None;
-                self.tasks[task_idx].pending_syscall = Some(Syscall::IpcReply { ep, msg: reply });
-                return;
+                // reply 待ちの無い msg（reply cap 無し）なら返さずに次の recv へ
+                if let Some(reply_cap) = self.tasks[task_idx].last_reply_cap.take() {
+                    self.tasks[task_idx].pending_syscall = Some(Syscall::IpcReply { reply_cap, msg: reply });
+                    return;
+                }
             }
 
-            self.tasks[task_idx].pending_syscall = Some(Syscall::IpcRecv { ep });
+            self.tasks[task_idx].pending_syscall = Some(Syscall::IpcRecv { cap });
             return;
         }
 
@@ -142,15 +163,18 @@ impl KernelState {
         if let Some(msg) = self.tasks[task_idx].last_msg {
             crate::logging::info("ipc_msg_received");
             crate::logging::info_u64("task_id", self.tasks[task_idx].id.0);
-            crate::logging::info_u64("msg", msg);
+            crate::logging::info_u64("msg", msg.mr0());
+            crate::logging::info_u64("msg_len", msg.len() as u64);
 
-            let reply: u64 = 0xABCD_0000_0000_0000u64 ^ (msg & 0xFFFF);
+            let reply = Self::demo_server_reply(&msg);
 
             self.tasks[task_idx].last_msg = None;
-            self.tasks[task_idx].pending_syscall = Some(Syscall::IpcReply { ep, msg: reply });
-            return;
+            if let Some(reply_cap) = self.tasks[task_idx].last_reply_cap.take() {
+                self.tasks[task_idx].pending_syscall = Some(Syscall::IpcReply { reply_cap, msg: reply });
+                return;
+            }
         }
 
-        self.tasks[task_idx].pending_syscall = Some(Syscall::IpcRecv { ep });
+        self.tasks[task_idx].pending_syscall = Some(Syscall::IpcRecv { cap });
     }
 }
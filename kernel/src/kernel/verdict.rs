@@ -0,0 +1,85 @@
+// kernel/src/kernel/verdict.rs
+//
+// 役割:
+// - run の終わり（dump_events の後）に、その run が「通った」かを 1 つの verdict にまとめる。
+// - feature qemu_exit のときは verdict を QEMU の終了コードにして止める（arch::qemu）。
+//   Shutdown syscall（shutdown.rs）も verdict を電源断の終了値に使う。
+//
+// 判定（上から順に見て、最初に引っかかったものを理由にする）:
+// - NMI / #MC が latch されていない（arch::hwfault。後ろの失敗より先に出し、kernel の bug と取り違えない）
+// - should_halt が立っていない（idle が死んだ / ready_queue が壊れた / invariant_fail_stop で止まった等）
+// - invariant 違反が 1 つも無い（counters.invariant_violations == 0）
+// - 有効な demo の到達点に届いている（demo::missed_milestone。abitest は全ケース PASS 等）
+//
+// やらないこと:
+// - kill / deadlock / watchdog を失敗にしない（evil / demo で意図して起こすものがある）
+// - ログの文字列を見た判定（ci-check.sh の grep はそのまま残す）
+// - event にすること（dump の後なので載せても出ない）
+
+use super::KernelState;
+use crate::logging;
+
+/// run の判定
+#[derive(Clone, Copy)]
+pub enum Verdict {
+    Pass,
+    Fail(VerdictFailure),
+}
+
+/// 失敗の理由
+#[derive(Clone, Copy)]
+pub enum VerdictFailure {
+    HardwareEvent { latched: u64 },
+    Halted,
+    InvariantViolated { count: u64 },
+    MilestoneMissed { name: &'static str },
+}
+
+impl Verdict {
+    pub fn passed(self) -> bool {
+        matches!(self, Verdict::Pass)
+    }
+}
+
+impl KernelState {
+    /// run の終わりの状態から verdict を決めて、1 行で出す
+    pub fn evaluate_verdict(&self) -> Verdict {
+        let verdict = if let Some(hw) = crate::arch::hwfault::latched() {
+            Verdict::Fail(VerdictFailure::HardwareEvent { latched: hw.latched })
+        } else if self.should_halt {
+            Verdict::Fail(VerdictFailure::Halted)
+        } else if self.counters.invariant_violations != 0 {
+            Verdict::Fail(VerdictFailure::InvariantViolated { count: self.counters.invariant_violations })
+        } else if let Some(name) = super::demo::missed_milestone(self) {
+            Verdict::Fail(VerdictFailure::MilestoneMissed { name })
+        } else {
+            Verdict::Pass
+        };
+
+        match verdict {
+            Verdict::Pass => logging::info("verdict: PASS"),
+            Verdict::Fail(VerdictFailure::HardwareEvent { latched }) => {
+                logging::error("verdict: FAIL hardware_event");
+                logging::info_hex("verdict_hw_event", latched);
+            }
+            Verdict::Fail(VerdictFailure::Halted) => logging::error("verdict: FAIL halted"),
+            Verdict::Fail(VerdictFailure::InvariantViolated { count }) => {
+                logging::error("verdict: FAIL invariant_violated");
+                logging::info_u64("verdict_invariant_violations", count);
+            }
+            Verdict::Fail(VerdictFailure::MilestoneMissed { name }) => {
+                logging::error("verdict: FAIL milestone_missed");
+                logging::info(name);
+            }
+        }
+        verdict
+    }
+}
+
+/// verdict を QEMU の終了コードにして止める（qemu_exit）
+#[cfg(feature = "qemu_exit")]
+pub fn exit_with(verdict: Verdict) -> ! {
+    use crate::arch::qemu::{exit_qemu, QemuExitCode};
+
+    exit_qemu(if verdict.passed() { QemuExitCode::Success } else { QemuExitCode::Failed })
+}
@@ -0,0 +1,127 @@
+// kernel/src/kernel/watchdog.rs
+//
+// 役割:
+// - 進捗の無い tick が続いたこと（livelock / invariant に出ない deadlock）を検出する watchdog。
+//
+// 進捗とみなす event（push_event が見る。IPC のサンプリングで log から落ちる分も数える）:
+// - IpcDelivered（メッセージが届いた）
+// - TaskSwitched（別の task が CPU を得た）
+// - MemActionApplied（mapping が変わった）
+//
+// 検出:
+// - tick の末尾で「最後に進捗のあった tick」から WATCHDOG_NO_PROGRESS_TICKS を超えたら 1 回だけ
+//   NoProgressDetected を出し、全 task の状態をログに残す
+//   * event には task index ごとの状態（abi の STATE_* を 4bit ずつ）を詰める
+// - 進捗が 1 つでもあれば再び検出できる状態に戻る（同じ停滞で何度も出さない）
+//
+// やらないこと:
+// - 回復（kill / halt）。検出と記録だけ。判断は dump を見る側に任せる
+// - Sleep が長いだけの正常な停滞との区別（閾値を十分長くしておく）
+
+use super::{abi, BlockedReason, KernelState, LogEvent, TaskState, MAX_TASKS};
+use crate::logging;
+
+/// 進捗の無い tick がこれを超えたら NoProgressDetected
+pub(super) const WATCHDOG_NO_PROGRESS_TICKS: u64 = 64;
+
+// 状態を 4bit ずつ u64 に詰める
+const _: () = assert!(MAX_TASKS * 4 <= 64);
+
+pub(super) struct WatchdogState {
+    last_progress_tick: u64,
+    // 今の停滞を報告済みか（進捗があれば false に戻す）
+    reported: bool,
+}
+
+impl WatchdogState {
+    pub(super) const fn new() -> Self {
+        Self { last_progress_tick: 0, reported: false }
+    }
+}
+
+/// watchdog が進捗とみなす event か
+fn is_progress_event(ev: &LogEvent) -> bool {
+    matches!(
+        ev,
+        LogEvent::IpcDelivered { .. } | LogEvent::TaskSwitched(_) | LogEvent::MemActionApplied { .. }
+    )
+}
+
+fn state_nibble(s: TaskState) -> u64 {
+    match s {
+        TaskState::Ready => abi::STATE_READY,
+        TaskState::Running => abi::STATE_RUNNING,
+        TaskState::Blocked => abi::STATE_BLOCKED,
+        TaskState::Dead => abi::STATE_DEAD,
+        TaskState::Suspended => abi::STATE_SUSPENDED,
+    }
+}
+
+impl KernelState {
+    /// push_event から: 進捗の event なら停滞を数え直す
+    pub(super) fn watchdog_note_event(&mut self, ev: &LogEvent) {
+        if is_progress_event(ev) {
+            self.watchdog.last_progress_tick = self.tick_count;
+            self.watchdog.reported = false;
+        }
+    }
+
+    /// tick の末尾: 進捗の無い tick が閾値を超えたら 1 回だけ報告する
+    pub(super) fn watchdog_on_tick(&mut self) {
+        let stalled_ticks = self.tick_count.saturating_sub(self.watchdog.last_progress_tick);
+        if self.watchdog.reported || stalled_ticks <= WATCHDOG_NO_PROGRESS_TICKS {
+            return;
+        }
+        self.watchdog.reported = true;
+        self.counters.watchdog_no_progress += 1;
+
+        logging::error("watchdog: no progress detected");
+        logging::info_u64("stalled_ticks", stalled_ticks);
+        logging::info_u64("last_progress_tick", self.watchdog.last_progress_tick);
+        logging::info_u64("current_task_id", self.tasks[self.current_task].id.0);
+
+        let mut states = 0u64;
+        for idx in 0..self.num_tasks {
+            let t = &self.tasks[idx];
+            states |= state_nibble(t.state) << (idx * 4);
+
+            logging::info_u64("task_id", t.id.0);
+            logging::info_u64("task_state", state_nibble(t.state));
+            match t.blocked_reason {
+                None => {}
+                Some(BlockedReason::Sleep) => logging::info("blocked_reason = Sleep"),
+                Some(BlockedReason::IpcRecv { ep }) => {
+                    logging::info("blocked_reason = IpcRecv");
+                    logging::info_u64("blocked_ep", ep.0 as u64);
+                }
+                Some(BlockedReason::IpcRecvAny { ep_mask }) => {
+                    logging::info("blocked_reason = IpcRecvAny");
+                    logging::info_u64("blocked_ep_mask", ep_mask);
+                }
+                Some(BlockedReason::IpcSend { ep }) => {
+                    logging::info("blocked_reason = IpcSend");
+                    logging::info_u64("blocked_ep", ep.0 as u64);
+                }
+                Some(BlockedReason::IpcReply { partner, ep }) => {
+                    logging::info("blocked_reason = IpcReply");
+                    logging::info_u64("blocked_ep", ep.0 as u64);
+                    logging::info_u64("blocked_partner_task_id", partner.0);
+                }
+                Some(BlockedReason::NotifyWait { ntfn }) => {
+                    logging::info("blocked_reason = NotifyWait");
+                    logging::info_u64("blocked_ntfn", ntfn.0 as u64);
+                }
+                Some(BlockedReason::FaultWait { ep }) => {
+                    logging::info("blocked_reason = FaultWait");
+                    logging::info_u64("blocked_ep", ep.0 as u64);
+                }
+            }
+        }
+
+        self.push_event(LogEvent::NoProgressDetected {
+            stalled_ticks,
+            last_progress_tick: self.watchdog.last_progress_tick,
+            task_states: states,
+        });
+    }
+}
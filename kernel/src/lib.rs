@@ -0,0 +1,42 @@
+// kernel/src/lib.rs
+//
+// 役割:
+// - kernel の本体（arch / kernel / logging / mem / mm）を lib crate にまとめる。
+//   * 実機の kernel（main.rs）は entry_point と boot の順番だけを持ち、ここを呼ぶ
+//   * ホスト（target_os != "none"）の cargo test は同じ lib を MockArch / MockSink で回す（kernel/tests/）
+//     MockArch / MockSink と test 用の入口（kernel/host_probe.rs）は feature host_test のときだけ在る
+//
+// 方針:
+// - ホストでも #![no_std] のまま（test crate 側が std を持つ）
+// - #[panic_handler] と #[global_allocator] は実機だけ（ホストは std のものを使う）
+//
+// やらないこと:
+// - ホストでの割り込み / timer / ring3 の実行（ArchOps の外側。test は tick を直接呼ぶ）
+
+#![no_std]
+
+// nightly: x86-interrupt ABI
+#![feature(abi_x86_interrupt)]
+
+// ─────────────────────────────────────────────
+// formal-os: pre-formal verification kernel
+//
+// - フォーマル検証しやすい「状態機械 + 抽象イベント」中心にする
+// - unsafe は arch 側に閉じ込め、kernel 側は状態遷移を明示する
+// ─────────────────────────────────────────────
+
+// kernel heap（mm::heap）の上で Box / Vec などを使う
+extern crate alloc;
+
+// ホストの build は test 専用（Arch / Sink の実装が MockArch / MockSink しか無い）
+#[cfg(all(not(target_os = "none"), not(feature = "host_test")))]
+compile_error!("host builds are test-only: enable feature host_test (scripts/host-test.sh)");
+
+pub mod arch;
+pub mod kernel;
+pub mod logging;
+pub mod mem;
+pub mod mm;
+#[cfg(target_os = "none")]
+mod panic;
+mod types;
@@ -0,0 +1,354 @@
+// kernel/src/logging/fb.rs
+//
+// linear framebuffer への文字出力（feature fb_console。VGA テキストモード vga.rs の代わり）。
+// - init(): 文字の格子を空にする（framebuffer はまだ無い。それまでの出力は格子にだけ溜める）
+// - attach(boot_info): BootInfo から framebuffer を決めて描き始める（溜まっていた行もここで描く）
+// - write_str / write_line / write_prefixed_line: vga.rs と同じ
+// - on_scancode(sc): scrollback は持たないので何もしない
+//
+// 描き方:
+// - font.rs の 8x8 bitmap font で、1 文字を 8x8 画素の cell に描く（背景は黒で塗る）
+// - 画面の格子（cols x rows = 画素 / 8、上限 MAX_COLS x MAX_ROWS）を cell で持ち、最下行に書く
+// - 改行は framebuffer の画素を 8 行ぶん上へ copy し、最下行を空白で描き直す
+//
+// framebuffer の選び方:
+// - bootloader 0.9 の BootInfo は framebuffer を記述しない。feature fb_console は bootloader の vga_320x200
+//   を立て、bootloader が VGA mode 13h（320x200、1 画素 = 1 byte の palette index、物理 0xA0000）にしてから
+//   kernel に入る。kernel は BootInfo.physical_memory_offset の physmap 越しにそこへ書く
+// - FrameBufferInfo は base / 幅 / 高さ / stride / 画素形式の汎用の記述で、UEFI GOP の 32 bit RGB / BGR も描ける
+//   （bootloader 0.11 の BootInfo.framebuffer に移るときは from_boot_info だけを差し替える）
+//
+// 色:
+// - vga.rs と同じく行頭の tag で 1 行の色（VGA の 16 色の番号）を決める。改行で既定色に戻す
+// - Indexed8 は番号をそのまま palette index にする（mode 13h の既定 palette の 0..15 は text mode の 16 色）
+// - Rgb32 / Bgr32 は PALETTE_RGB で RGB に直す
+//
+// C対応:
+// - vga.rs と同じく、ロック取得～書き込みを interrupts::without_interrupts で囲む
+// - is_vga_enabled（例外中 / user の CR3 の間は止める）に従う
+//
+// やらないこと:
+// - UEFI での boot（bootloader 0.9 には BIOS の経路しか無い）
+// - scrollback、24 bit / 16 bit の画素形式、0x7F 以上の文字（font.rs）
+
+use core::fmt::{self, Write};
+use core::ptr;
+
+use bootloader::BootInfo;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use super::font::{self, GLYPH_HEIGHT, GLYPH_WIDTH};
+
+/// 文字の格子の上限（1024x768 の framebuffer まで全面に描ける）
+const MAX_COLS: usize = 128;
+const MAX_ROWS: usize = 96;
+
+/// bootloader の vga_320x200 が切り替える VGA mode 13h
+const MODE13H_PHYS: u64 = 0xA0000;
+const MODE13H_WIDTH: usize = 320;
+const MODE13H_HEIGHT: usize = 200;
+
+// VGA の 16 色の番号（vga.rs の Color と同じ）
+const BLACK: u8 = 0x0;
+const LIGHT_GRAY: u8 = 0x7;
+const DARK_GRAY: u8 = 0x8;
+const LIGHT_RED: u8 = 0xC;
+const YELLOW: u8 = 0xE;
+
+const DEFAULT_COLOR: u8 = LIGHT_GRAY;
+const BACKGROUND: u8 = BLACK;
+
+/// 行頭の tag と、その行の色（vga.rs と同じ）
+const SEVERITY_COLORS: [(&str, u8); 6] = [
+    ("[TRACE]", DARK_GRAY),
+    ("[DEBUG]", DARK_GRAY),
+    ("[INFO]", LIGHT_GRAY),
+    ("[WARN]", YELLOW),
+    ("[ERROR]", LIGHT_RED),
+    ("[EXC]", YELLOW),
+];
+
+/// VGA の 16 色の RGB（0xRRGGBB）
+const PALETTE_RGB: [u32; 16] = [
+    0x000000, 0x0000AA, 0x00AA00, 0x00AAAA, 0xAA0000, 0xAA00AA, 0xAA5500, 0xAAAAAA, 0x555555, 0x5555FF, 0x55FF55,
+    0x55FFFF, 0xFF5555, 0xFF55FF, 0xFFFF55, 0xFFFFFF,
+];
+
+/// 画素の並び
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// 1 byte = palette index（VGA mode 13h）
+    Indexed8,
+    /// 4 byte、byte 0 = R（GOP の PixelRedGreenBlueReserved8BitPerColor。bootloader 0.9 からは来ない）
+    #[allow(dead_code)]
+    Rgb32,
+    /// 4 byte、byte 0 = B（GOP の PixelBlueGreenRedReserved8BitPerColor。bootloader 0.9 からは来ない）
+    #[allow(dead_code)]
+    Bgr32,
+}
+
+impl PixelFormat {
+    fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelFormat::Indexed8 => 1,
+            PixelFormat::Rgb32 | PixelFormat::Bgr32 => 4,
+        }
+    }
+}
+
+/// linear framebuffer の記述（base は kernel から書ける仮想アドレス）
+#[derive(Clone, Copy)]
+pub struct FrameBufferInfo {
+    pub base: u64,
+    pub width: usize,
+    pub height: usize,
+    /// 1 行の画素数（width 以上）
+    pub stride: usize,
+    pub format: PixelFormat,
+}
+
+impl FrameBufferInfo {
+    /// BootInfo から framebuffer を決める（physmap が無ければ None）
+    pub fn from_boot_info(boot_info: &BootInfo) -> Option<Self> {
+        if boot_info.physical_memory_offset == 0 {
+            return None;
+        }
+        Some(Self {
+            base: boot_info.physical_memory_offset + MODE13H_PHYS,
+            width: MODE13H_WIDTH,
+            height: MODE13H_HEIGHT,
+            stride: MODE13H_WIDTH,
+            format: PixelFormat::Indexed8,
+        })
+    }
+
+    fn pixel_ptr(&self, x: usize, y: usize) -> *mut u8 {
+        (self.base as usize + (y * self.stride + x) * self.format.bytes_per_pixel()) as *mut u8
+    }
+
+    fn put_pixel(&self, x: usize, y: usize, color: u8) {
+        let p = self.pixel_ptr(x, y);
+        let rgb = PALETTE_RGB[(color & 0xF) as usize];
+        // SAFETY: x < width、y < height（呼び出し側が格子の範囲で呼ぶ）。base は attach で決めた framebuffer
+        unsafe {
+            match self.format {
+                PixelFormat::Indexed8 => ptr::write_volatile(p, color),
+                PixelFormat::Rgb32 => ptr::write_volatile(p as *mut u32, rgb.swap_bytes() >> 8),
+                PixelFormat::Bgr32 => ptr::write_volatile(p as *mut u32, rgb),
+            }
+        }
+    }
+
+    /// 画素 y = from.. の lines 行を y = 0.. へ copy する
+    fn copy_lines_up(&self, from: usize, lines: usize) {
+        let row_bytes = self.stride * self.format.bytes_per_pixel();
+        // SAFETY: from + lines <= height。重なる範囲なので ptr::copy（memmove）
+        unsafe {
+            ptr::copy(self.pixel_ptr(0, from), self.pixel_ptr(0, 0), lines * row_bytes);
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Cell {
+    ch: u8,
+    color: u8,
+}
+
+const BLANK: Cell = Cell { ch: b' ', color: DEFAULT_COLOR };
+
+struct Console {
+    // 画面の格子。cells[rows - 1] が今書いている行
+    cells: [[Cell; MAX_COLS]; MAX_ROWS],
+    cols: usize,
+    rows: usize,
+    col: usize,
+    // 今の行の色（行頭の tag で決まる）
+    line_color: u8,
+    // attach までは None（格子にだけ書く）
+    fb: Option<FrameBufferInfo>,
+}
+
+impl Console {
+    const fn new() -> Self {
+        Self {
+            cells: [[BLANK; MAX_COLS]; MAX_ROWS],
+            // attach 前は mode 13h の格子で溜める
+            cols: MODE13H_WIDTH / GLYPH_WIDTH,
+            rows: MODE13H_HEIGHT / GLYPH_HEIGHT,
+            col: 0,
+            line_color: DEFAULT_COLOR,
+            fb: None,
+        }
+    }
+
+    /// framebuffer を差し込み、全面を塗ってから格子を描く（格子の大きさが変わるなら溜めた行は捨てる）
+    fn attach(&mut self, info: FrameBufferInfo) {
+        let cols = (info.width / GLYPH_WIDTH).min(MAX_COLS);
+        let rows = (info.height / GLYPH_HEIGHT).min(MAX_ROWS);
+        if (cols, rows) != (self.cols, self.rows) {
+            self.cells = [[BLANK; MAX_COLS]; MAX_ROWS];
+            self.cols = cols;
+            self.rows = rows;
+            self.col = 0;
+        }
+        self.fb = Some(info);
+
+        for y in 0..info.height {
+            for x in 0..info.width {
+                info.put_pixel(x, y, BACKGROUND);
+            }
+        }
+        for row in 0..self.rows {
+            for col in 0..self.cols {
+                self.draw_cell(row, col);
+            }
+        }
+    }
+
+    fn draw_cell(&self, row: usize, col: usize) {
+        let Some(fb) = self.fb else {
+            return;
+        };
+        let cell = self.cells[row][col];
+        for (dy, bits) in font::glyph(cell.ch).iter().enumerate() {
+            for dx in 0..GLYPH_WIDTH {
+                let color = if bits & (1 << dx) != 0 { cell.color } else { BACKGROUND };
+                fb.put_pixel(col * GLYPH_WIDTH + dx, row * GLYPH_HEIGHT + dy, color);
+            }
+        }
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        if self.rows == 0 || self.cols == 0 {
+            return;
+        }
+        match byte {
+            b'\n' => self.new_line(),
+            byte => {
+                if self.col >= self.cols {
+                    // 折り返しは同じ行の続きなので色を引き継ぐ
+                    let color = self.line_color;
+                    self.new_line();
+                    self.line_color = color;
+                }
+                let (row, col) = (self.rows - 1, self.col);
+                self.cells[row][col] = Cell { ch: byte, color: self.line_color };
+                self.draw_cell(row, col);
+                self.col += 1;
+            }
+        }
+    }
+
+    /// 格子と画素を 1 行上げて最下行を空ける
+    fn new_line(&mut self) {
+        let last = self.rows - 1;
+        self.cells.copy_within(1..self.rows, 0);
+        self.cells[last] = [BLANK; MAX_COLS];
+        self.col = 0;
+        self.line_color = DEFAULT_COLOR;
+
+        if let Some(fb) = self.fb {
+            fb.copy_lines_up(GLYPH_HEIGHT, last * GLYPH_HEIGHT);
+            for col in 0..self.cols {
+                self.draw_cell(last, col);
+            }
+        }
+    }
+
+    /// 行頭なら tag を見て行の色を決める
+    fn begin_text(&mut self, s: &str) {
+        if self.col != 0 {
+            return;
+        }
+        self.line_color = SEVERITY_COLORS
+            .iter()
+            .find(|(tag, _)| s.starts_with(*tag))
+            .map_or(DEFAULT_COLOR, |&(_, color)| color);
+    }
+}
+
+impl Write for Console {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.begin_text(s);
+        for b in s.bytes() {
+            self.write_byte(b);
+        }
+        Ok(())
+    }
+}
+
+// 格子を抱えて大きいので、vga.rs と同じく static に const で置く
+static CONSOLE: Mutex<Console> = Mutex::new(Console::new());
+
+pub fn init() {
+    interrupts::without_interrupts(|| {
+        let mut c = CONSOLE.lock();
+        c.cells = [[BLANK; MAX_COLS]; MAX_ROWS];
+        c.col = 0;
+        c.line_color = DEFAULT_COLOR;
+    });
+}
+
+/// BootInfo の framebuffer に描き始める（physmap を使うので arch::init の後）
+pub fn attach(boot_info: &BootInfo) {
+    let Some(info) = FrameBufferInfo::from_boot_info(boot_info) else {
+        super::error("fb_console: no framebuffer in BootInfo; screen output stays off");
+        return;
+    };
+
+    interrupts::without_interrupts(|| {
+        CONSOLE.lock().attach(info);
+    });
+    super::info_kv3(
+        "fb_console",
+        "width",
+        info.width as u64,
+        "height",
+        info.height as u64,
+        "stride",
+        info.stride as u64,
+    );
+}
+
+/// scrollback は持たない（keyboard のキーは無視する）
+pub fn on_scancode(_scancode: u8) {}
+
+/// 文字列を出す（改行なし）
+pub fn write_str(s: &str) {
+    if !crate::logging::is_vga_enabled() {
+        return;
+    }
+
+    interrupts::without_interrupts(|| {
+        let _ = CONSOLE.lock().write_str(s);
+    });
+}
+
+/// 文字列＋改行
+pub fn write_line(s: &str) {
+    if !crate::logging::is_vga_enabled() {
+        return;
+    }
+
+    interrupts::without_interrupts(|| {
+        let mut c = CONSOLE.lock();
+        let _ = c.write_str(s);
+        let _ = c.write_str("\n");
+    });
+}
+
+/// prefix + msg を 1 回のロックで書いて改行
+pub fn write_prefixed_line(prefix: &str, msg: &str) {
+    if !crate::logging::is_vga_enabled() {
+        return;
+    }
+
+    interrupts::without_interrupts(|| {
+        let mut c = CONSOLE.lock();
+        let _ = c.write_str(prefix);
+        let _ = c.write_str(msg);
+        let _ = c.write_str("\n");
+    });
+}
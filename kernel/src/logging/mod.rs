@@ -2,28 +2,207 @@
 //
 // ログ出力基盤。
 // - VGA テキストモード + シリアル(COM1) の両方に出力する。
+//   * feature fb_console: 画面側は VGA テキストモードの代わりに framebuffer（fb.rs、8x8 の bitmap font）。
+//     どちらに描くかは select_display（BootInfo を見る）で決まる
 // - 解析・比較しやすいよう、可能な限り「1行=1レコード」に寄せる。
 // - 例外/割り込みなど「危険な場面」では VGA を止めて serial-only を許す。
 //
 // やること:
 // - info/error の共通 API
-// - u64 の key-value ログ（info_u64 / info_kv）
+// - log level（Trace / Debug / Info / Warn / Error）による絞り込み
+//   * 閾値は全体（global）と subsystem（sched / ipc / mem / arch）ごと。実行時に変えられる
+//   * subsystem の閾値が未設定なら global に従う。subsystem の無い info/error は global だけを見る
+//   * 既定は global = Trace（全部出す。従来と同じ出力）
+//   * force_errors: 閾値に関係なく Error を必ず出す（Off にしていても止めない）
+//   * 絞るのは info/error/log 系だけ。emergency_* / wire_hex / record は常に出す（機械可読・非常用）
+// - 同じ行の連続をまとめる（feature log_dedup）
+//   * 直前と同じ record（prefix + key + 値 / 文字列）が続いたら出さずに数え、別の行が来たときに
+//     "last message repeated N times" を 1 行出す（最初の 1 回はそのまま出る）
+//   * 対象は info/error/log 系と emergency_*。wire_hex / record はまとめない
+//   * without_dedup の中で出した行はまとめない（重要 event の dump 用）
+//   * IRQ から割り込まれると回数がずれることがある（atomic だけで持つ。lock は取らない）
+// - u64 の key-value ログ（info_u64 / info_kv は 10 進、info_hex は 0x 付き 16 進）
+// - 1 行に複数 field を並べる構造化ログ（info_kv2 / info_kv3 / log_record!）
+//   * 形式: "[INFO] <kind> key=value key=value ..."（value は 10 進 or 0x 付き 16 進）
+//   * log_record! は kind / key / 表記（dec / hex）をコンパイル時に検査する
 // - VGA 出力の enable/disable（例外中の安全策）
+// - VGA の scrollback 操作（vga_scancode。色分けと scrollback 自体は vga.rs の中だけ）
 // - emergency_*（serial-only）
+// - serial_read_byte（COM1 の受信を polling で 1 byte。debug console 用）
+// - wire_hex（バイナリレコードの hex 出力、serial-only）
+// - record（機械可読の 1 行 key=value レコード、serial-only。record.rs）
+//   * wire_hex / record は trace の出口（LogSink::trace_*）に書く。feature virtio_console で
+//     virtio console が見つかればそちら、無ければ COM1（人間向けの行は常に COM1）
+// - 出力先は sink.rs の LogSink 経由（実機 = VGA + COM1 / ホスト = MockSink のバッファ）
+// - 全 sink 共通の通し番号（next_seq）
+//   * event log / serial テキスト / wire レコードが同じカウンタから番号を取る
+//   * 別々に採取したログを後で 1 本の時系列にマージできるようにする
+//   * serial テキストへの付与は log_seq feature のときだけ（"[INFO] #<seq> ..."）
 //
 // やらないこと:
 // - format! のフル対応（将来拡張）
 
+#[cfg(all(target_os = "none", not(feature = "fb_console")))]
 mod vga;
+#[cfg(all(target_os = "none", feature = "fb_console"))]
+mod fb;
+#[cfg(all(target_os = "none", feature = "fb_console"))]
+mod font;
+#[cfg(target_os = "none")]
 mod serial;
+pub mod record;
+pub mod sink;
 
-use core::sync::atomic::{AtomicBool, Ordering};
+use sink::{LogSink, Sink};
+
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
 
 static VGA_ENABLED: AtomicBool = AtomicBool::new(true);
 
+// 全 sink 共通の通し番号（lock-free: 例外ハンドラからも取れる）
+static SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// ログの重要度（小さいほど細かい）
+///
+/// - Off は閾値専用（これを閾値にすると全部止まる。force_errors の Error は除く）
+/// - Trace / Debug / Warn はまだ出す側が無い（閾値としては debug console / command line の log= から選べる）
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(not(feature = "debug_console"), allow(dead_code))]
+#[repr(u8)]
+pub enum Level {
+    Trace = 0,
+    Debug = 1,
+    Info = 2,
+    Warn = 3,
+    Error = 4,
+    Off = 5,
+}
+
+impl Level {
+    pub fn from_name(name: &str) -> Option<Level> {
+        match name {
+            "trace" => Some(Level::Trace),
+            "debug" => Some(Level::Debug),
+            "info" => Some(Level::Info),
+            "warn" => Some(Level::Warn),
+            "error" => Some(Level::Error),
+            "off" => Some(Level::Off),
+            _ => None,
+        }
+    }
+
+    fn prefix(self) -> &'static str {
+        match self {
+            Level::Trace => "[TRACE] ",
+            Level::Debug => "[DEBUG] ",
+            Level::Info => "[INFO] ",
+            Level::Warn => "[WARN] ",
+            Level::Error | Level::Off => "[ERROR] ",
+        }
+    }
+}
+
+/// 閾値を個別に持てる subsystem
+#[derive(Clone, Copy)]
+pub enum Subsystem {
+    Sched = 0,
+    Ipc = 1,
+    Mem = 2,
+    Arch = 3,
+}
+
+const SUBSYSTEM_COUNT: usize = 4;
+
+// subsystem の閾値が未設定（global に従う）
+const LEVEL_INHERIT: u8 = u8::MAX;
+
+static GLOBAL_LEVEL: AtomicU8 = AtomicU8::new(Level::Trace as u8);
+static SUBSYSTEM_LEVELS: [AtomicU8; SUBSYSTEM_COUNT] = [const { AtomicU8::new(LEVEL_INHERIT) }; SUBSYSTEM_COUNT];
+static FORCE_ERRORS: AtomicBool = AtomicBool::new(false);
+
+const DEDUP_ENABLED: bool = cfg!(feature = "log_dedup");
+
+// 直前に出した record の hash（0 = 無し）と、その後にまとめた回数
+static DEDUP_LAST_HASH: AtomicU64 = AtomicU64::new(0);
+static DEDUP_REPEATS: AtomicU64 = AtomicU64::new(0);
+// without_dedup の入れ子の深さ
+static DEDUP_BYPASS: AtomicU64 = AtomicU64::new(0);
+
+impl Subsystem {
+    #[cfg_attr(not(feature = "debug_console"), allow(dead_code))]
+    pub fn from_name(name: &str) -> Option<Subsystem> {
+        match name {
+            "sched" => Some(Subsystem::Sched),
+            "ipc" => Some(Subsystem::Ipc),
+            "mem" => Some(Subsystem::Mem),
+            "arch" => Some(Subsystem::Arch),
+            _ => None,
+        }
+    }
+
+    pub fn info(self, msg: &str) {
+        log_in(Some(self), Level::Info, msg);
+    }
+
+    pub fn error(self, msg: &str) {
+        log_in(Some(self), Level::Error, msg);
+    }
+
+    pub fn info_u64(self, key: &str, value: u64) {
+        log_kv_in(Some(self), Level::Info, key, value);
+    }
+
+    pub fn info_hex(self, key: &str, value: u64) {
+        log_value_in(Some(self), Level::Info, key, FieldValue::Hex(value));
+    }
+}
+
+/// global の閾値を設定する
+pub fn set_level(level: Level) {
+    GLOBAL_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// subsystem の閾値を設定する（None = global に従う）
+#[cfg_attr(not(feature = "debug_console"), allow(dead_code))]
+pub fn set_subsystem_level(sub: Subsystem, level: Option<Level>) {
+    let word = level.map_or(LEVEL_INHERIT, |l| l as u8);
+    SUBSYSTEM_LEVELS[sub as usize].store(word, Ordering::Relaxed);
+}
+
+/// 閾値に関係なく Error を出すか
+#[cfg_attr(not(feature = "debug_console"), allow(dead_code))]
+pub fn set_force_errors(force: bool) {
+    FORCE_ERRORS.store(force, Ordering::Relaxed);
+}
+
+/// この subsystem / level のログを出すか
+pub fn enabled(sub: Option<Subsystem>, level: Level) -> bool {
+    if level >= Level::Error && FORCE_ERRORS.load(Ordering::Relaxed) {
+        return true;
+    }
+    let mut threshold = GLOBAL_LEVEL.load(Ordering::Relaxed);
+    if let Some(sub) = sub {
+        let word = SUBSYSTEM_LEVELS[sub as usize].load(Ordering::Relaxed);
+        if word != LEVEL_INHERIT {
+            threshold = word;
+        }
+    }
+    level != Level::Off && level as u8 >= threshold
+}
+
 pub fn init() {
-    vga::init();
-    serial::init();
+    Sink::init();
+}
+
+/// BootInfo から画面の出力先を決める（physmap を使うので arch::init の後に呼ぶ）
+/// - feature fb_console: bootloader が切り替えた framebuffer に描き始める（fb.rs。init からここまでの行も描く）
+/// - 既定: VGA テキストモードは init で決まっているので何もしない
+#[cfg(target_os = "none")]
+pub fn select_display(boot_info: &'static bootloader::BootInfo) {
+    #[cfg(feature = "fb_console")]
+    fb::attach(boot_info);
+    #[cfg(not(feature = "fb_console"))]
+    let _ = boot_info;
 }
 
 /// VGA 出力を有効/無効にする（serial は常に出す）
@@ -36,16 +215,152 @@ pub fn is_vga_enabled() -> bool {
     VGA_ENABLED.load(Ordering::SeqCst)
 }
 
+/// keyboard の scancode を VGA に渡す（PageUp / PageDown / Home / End で scrollback を動かす）
+pub fn vga_scancode(scancode: u8) {
+    Sink::vga_scancode(scancode);
+}
+
+/// serial から受信した 1 byte（polling。無ければ None。debug console 用）
+#[cfg_attr(not(feature = "debug_console"), allow(dead_code))]
+pub fn serial_read_byte() -> Option<u8> {
+    Sink::serial_read_byte()
+}
+
+/// 全 sink 共通の通し番号を 1 つ払い出す（1 始まり、単調増加）
+pub fn next_seq() -> u64 {
+    SEQ.fetch_add(1, Ordering::Relaxed) + 1
+}
+
+/// f の中で出す行は連続していてもまとめない（log_dedup が無効なら何もしない）
+pub fn without_dedup<R>(f: impl FnOnce() -> R) -> R {
+    DEDUP_BYPASS.fetch_add(1, Ordering::Relaxed);
+    let r = f();
+    DEDUP_BYPASS.fetch_sub(1, Ordering::Relaxed);
+    r
+}
+
+/// record の hash（FNV-1a。部分ごとに区切りを混ぜる）
+struct RecordHash(u64);
+
+impl RecordHash {
+    const fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn part(&mut self, bytes: &[u8]) {
+        for &b in bytes.iter().chain(&[0xff]) {
+            self.0 ^= b as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    fn finish(self) -> u64 {
+        // 0 は「無し」に使う
+        self.0 | 1
+    }
+}
+
+/// この record を出すか（false = 直前と同じなのでまとめた）
+fn dedup_admit(parts: &[&str], serial_only: bool) -> bool {
+    dedup_admit_with(serial_only, |h| {
+        for part in parts {
+            h.part(part.as_bytes());
+        }
+    })
+}
+
+/// hash の元を hash_parts で与える版（field を文字列にせずに済ませる）
+fn dedup_admit_with(serial_only: bool, hash_parts: impl FnOnce(&mut RecordHash)) -> bool {
+    if !DEDUP_ENABLED {
+        return true;
+    }
+    if DEDUP_BYPASS.load(Ordering::Relaxed) > 0 {
+        flush_repeats(serial_only);
+        DEDUP_LAST_HASH.store(0, Ordering::Relaxed);
+        return true;
+    }
+    let mut hasher = RecordHash::new();
+    hash_parts(&mut hasher);
+    let h = hasher.finish();
+    if DEDUP_LAST_HASH.load(Ordering::Relaxed) == h {
+        DEDUP_REPEATS.fetch_add(1, Ordering::Relaxed);
+        return false;
+    }
+    flush_repeats(serial_only);
+    DEDUP_LAST_HASH.store(h, Ordering::Relaxed);
+    true
+}
+
+/// まとめた回数があれば 1 行出す
+fn flush_repeats(serial_only: bool) {
+    let n = DEDUP_REPEATS.swap(0, Ordering::Relaxed);
+    if n == 0 {
+        return;
+    }
+    let mut buf = [0u8; 21];
+    let s = u64_to_decimal(n, &mut buf);
+
+    if !serial_only {
+        Sink::vga_str("[INFO] last message repeated ");
+        Sink::vga_str(s);
+        Sink::vga_line(" times");
+    }
+    serial_record_start("[INFO] ");
+    Sink::serial_str("last message repeated ");
+    Sink::serial_str(s);
+    Sink::serial_line(" times");
+}
+
+/// serial の行頭（prefix + 通し番号）を書く
+///
+/// - 通し番号は feature に関係なく必ず消費する（sink 間で番号の意味を揃える）
+/// - log_seq のときだけ "#<seq> " をテキストに出す
+fn serial_record_start(prefix: &str) {
+    record_start_with(prefix, Sink::serial_str);
+}
+
+/// trace の出口（wire_hex / record）の行頭。通し番号の扱いは serial_record_start と同じ
+fn trace_record_start(prefix: &str) {
+    record_start_with(prefix, Sink::trace_str);
+}
+
+fn record_start_with(prefix: &str, write: fn(&str)) {
+    let seq = next_seq();
+    write(prefix);
+
+    #[cfg(feature = "log_seq")]
+    {
+        let mut buf = [0u8; 21];
+        write("#");
+        write(u64_to_decimal(seq, &mut buf));
+        write(" ");
+    }
+    #[cfg(not(feature = "log_seq"))]
+    let _ = seq;
+}
+
 /// 情報ログ（文字列）
 pub fn info(msg: &str) {
-    vga::write_prefixed_line("[INFO] ", msg);
-    serial::write_prefixed_line("[INFO] ", msg);
+    log_in(None, Level::Info, msg);
 }
 
 /// エラーログ（文字列）
 pub fn error(msg: &str) {
-    vga::write_prefixed_line("[ERROR] ", msg);
-    serial::write_prefixed_line("[ERROR] ", msg);
+    log_in(None, Level::Error, msg);
+}
+
+/// level / subsystem を指定したログ（文字列）。閾値未満なら何も出さない（通し番号も消費しない）
+pub fn log_in(sub: Option<Subsystem>, level: Level, msg: &str) {
+    if !enabled(sub, level) {
+        return;
+    }
+    let prefix = level.prefix();
+    if !dedup_admit(&[prefix, msg], false) {
+        return;
+    }
+    Sink::vga_prefixed_line(prefix, msg);
+    serial_record_start(prefix);
+    Sink::serial_line(msg);
 }
 
 /// 情報ログ（整数）
@@ -57,53 +372,254 @@ pub fn info_u64(label: &str, value: u64) {
 
 /// key-value 形式の情報ログ（u64）
 pub fn info_kv(key: &str, value: u64) {
-    let mut buf = [0u8; 21]; // u64 は最大 20 桁
-    let s = u64_to_decimal(value, &mut buf);
+    log_kv_in(None, Level::Info, key, value);
+}
+
+/// key-value 形式の情報ログ（u64 を 0x 付き 16 進で。アドレス向け）
+#[allow(dead_code)]
+pub fn info_hex(key: &str, value: u64) {
+    log_value_in(None, Level::Info, key, FieldValue::Hex(value));
+}
+
+/// level / subsystem を指定した key-value ログ（u64）
+pub fn log_kv_in(sub: Option<Subsystem>, level: Level, key: &str, value: u64) {
+    log_value_in(sub, level, key, FieldValue::Dec(value));
+}
+
+fn log_value_in(sub: Option<Subsystem>, level: Level, key: &str, value: FieldValue) {
+    if !enabled(sub, level) {
+        return;
+    }
+    let prefix = level.prefix();
+    let mut buf = [0u8; 21]; // u64 は 10 進で最大 20 桁 / 16 進で "0x" + 16 桁
+    let s = value.render(&mut buf);
+    if !dedup_admit(&[prefix, key, s], false) {
+        return;
+    }
 
     if key.is_empty() {
-        vga::write_str("[INFO] ");
-        vga::write_line(s);
+        Sink::vga_str(prefix);
+        Sink::vga_line(s);
+
+        serial_record_start(prefix);
+        Sink::serial_line(s);
+        return;
+    }
+
+    Sink::vga_str(prefix);
+    Sink::vga_str(key);
+    Sink::vga_str(" = ");
+    Sink::vga_line(s);
+
+    serial_record_start(prefix);
+    Sink::serial_str(key);
+    Sink::serial_str(" = ");
+    Sink::serial_line(s);
+}
+
+/// 構造化ログの値の表記
+#[derive(Clone, Copy)]
+pub enum FieldValue {
+    Dec(u64),
+    Hex(u64),
+}
+
+impl FieldValue {
+    fn render(self, buf: &mut [u8; 21]) -> &str {
+        match self {
+            FieldValue::Dec(v) => u64_to_decimal(v, buf),
+            FieldValue::Hex(v) => u64_to_hex(v, buf),
+        }
+    }
+
+    fn hash_into(self, h: &mut RecordHash) {
+        let (tag, v) = match self {
+            FieldValue::Dec(v) => (b'd', v),
+            FieldValue::Hex(v) => (b'x', v),
+        };
+        h.part(&[tag]);
+        h.part(&v.to_le_bytes());
+    }
+}
+
+/// 構造化ログの 1 field（key は空白と '=' を含まない ASCII）
+#[derive(Clone, Copy)]
+pub struct Field<'a> {
+    key: &'a str,
+    value: FieldValue,
+}
 
-        serial::write_str("[INFO] ");
-        serial::write_line(s);
+impl<'a> Field<'a> {
+    pub const fn dec(key: &'a str, value: u64) -> Self {
+        Self { key, value: FieldValue::Dec(value) }
+    }
+
+    pub const fn hex(key: &'a str, value: u64) -> Self {
+        Self { key, value: FieldValue::Hex(value) }
+    }
+}
+
+/// kind / key に使える文字か（log_record! がコンパイル時に使う）
+pub const fn is_record_token(s: &str) -> bool {
+    let b = s.as_bytes();
+    if b.is_empty() {
+        return false;
+    }
+    let mut i = 0;
+    while i < b.len() {
+        if b[i] <= b' ' || b[i] == b'=' || b[i] > 0x7e {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+/// 2 field を 1 行で（"[INFO] <kind> k1=v1 k2=v2"、10 進）
+#[allow(dead_code)]
+pub fn info_kv2(kind: &str, k1: &str, v1: u64, k2: &str, v2: u64) {
+    log_fields_in(None, Level::Info, kind, &[Field::dec(k1, v1), Field::dec(k2, v2)]);
+}
+
+/// 3 field を 1 行で（"[INFO] <kind> k1=v1 k2=v2 k3=v3"、10 進）
+#[allow(dead_code)]
+pub fn info_kv3(kind: &str, k1: &str, v1: u64, k2: &str, v2: u64, k3: &str, v3: u64) {
+    log_fields_in(None, Level::Info, kind, &[Field::dec(k1, v1), Field::dec(k2, v2), Field::dec(k3, v3)]);
+}
+
+/// level / subsystem を指定した構造化ログ（"<prefix><kind> key=value ..." を 1 行で）
+pub fn log_fields_in(sub: Option<Subsystem>, level: Level, kind: &str, fields: &[Field]) {
+    if !enabled(sub, level) {
+        return;
+    }
+    let prefix = level.prefix();
+    let admitted = dedup_admit_with(false, |h| {
+        h.part(prefix.as_bytes());
+        h.part(kind.as_bytes());
+        for f in fields {
+            h.part(f.key.as_bytes());
+            f.value.hash_into(h);
+        }
+    });
+    if !admitted {
         return;
     }
 
-    vga::write_str("[INFO] ");
-    vga::write_str(key);
-    vga::write_str(" = ");
-    vga::write_line(s);
+    Sink::vga_str(prefix);
+    Sink::vga_str(kind);
+    serial_record_start(prefix);
+    Sink::serial_str(kind);
+    for f in fields {
+        let mut buf = [0u8; 21];
+        let s = f.value.render(&mut buf);
+        for part in [" ", f.key, "=", s] {
+            Sink::vga_str(part);
+            Sink::serial_str(part);
+        }
+    }
+    Sink::vga_line("");
+    Sink::serial_line("");
+}
 
-    serial::write_str("[INFO] ");
-    serial::write_str(key);
-    serial::write_str(" = ");
-    serial::write_line(s);
+/// 構造化ログを 1 行で出す（kind / key / 表記をコンパイル時に検査する）
+///
+/// ```ignore
+/// log_record!(Info, "pf", addr = hex(cr2), err = hex(code), task = dec(id));
+/// log_record!(Mem: Error, "swap_fail", slot = dec(slot));
+/// ```
+///
+/// - level は logging::Level の variant、subsystem は `Subsystem: Level` で付ける
+/// - key は識別子（そのまま key 名になる）、表記は dec / hex のどちらか
+#[macro_export]
+macro_rules! log_record {
+    ($level:ident, $kind:literal $(, $key:ident = $fmt:ident($value:expr))* $(,)?) => {
+        $crate::log_record!(@emit None, $level, $kind $(, $key = $fmt($value))*)
+    };
+    ($sub:ident : $level:ident, $kind:literal $(, $key:ident = $fmt:ident($value:expr))* $(,)?) => {
+        $crate::log_record!(@emit Some($crate::logging::Subsystem::$sub), $level, $kind $(, $key = $fmt($value))*)
+    };
+    (@emit $sub:expr, $level:ident, $kind:literal $(, $key:ident = $fmt:ident($value:expr))*) => {{
+        const _: () = assert!($crate::logging::is_record_token($kind), "log_record!: bad kind");
+        $crate::logging::log_fields_in(
+            $sub,
+            $crate::logging::Level::$level,
+            $kind,
+            &[$($crate::logging::Field::$fmt(stringify!($key), ($value) as u64)),*],
+        )
+    }};
 }
 
 /// 例外ハンドラ用: serial のみで ERROR を出す
 pub fn emergency_error(msg: &str) {
-    serial::write_prefixed_line("[ERROR] ", msg);
+    if !dedup_admit(&["[ERROR] ", msg], true) {
+        return;
+    }
+    serial_record_start("[ERROR] ");
+    Sink::serial_line(msg);
 }
 
 /// 例外ハンドラ用: serial のみで INFO(k=v) を出す
 pub fn emergency_info_kv(key: &str, value: u64) {
     let mut buf = [0u8; 21];
     let s = u64_to_decimal(value, &mut buf);
+    if !dedup_admit(&["[INFO] ", key, s], true) {
+        return;
+    }
 
     if key.is_empty() {
-        serial::write_str("[INFO] ");
-        serial::write_line(s);
+        serial_record_start("[INFO] ");
+        Sink::serial_line(s);
         return;
     }
 
-    serial::write_str("[INFO] ");
-    serial::write_str(key);
-    serial::write_str(" = ");
-    serial::write_line(s);
+    serial_record_start("[INFO] ");
+    Sink::serial_str(key);
+    Sink::serial_str(" = ");
+    Sink::serial_line(s);
+}
+
+/// バイナリレコード（kernel::abi の WireRecord 等）を hex で 1 行に出す（serial のみ）
+///
+/// - 出力: "[INFO] <key> = <hex>"（バイト順そのまま、小文字 hex）
+/// - VGA は 80 桁で折り返して読めないため出さない（ホスト側ツール向け）
+pub fn wire_hex(key: &str, bytes: &[u8]) {
+    const HEX: &[u8; 16] = b"0123456789abcdef";
+
+    trace_record_start("[INFO] ");
+    Sink::trace_str(key);
+    Sink::trace_str(" = ");
+
+    // 2 文字ずつ書く（heap なし）
+    for &b in bytes {
+        let pair = [HEX[(b >> 4) as usize], HEX[(b & 0x0f) as usize]];
+        let s = unsafe { core::str::from_utf8_unchecked(&pair) };
+        Sink::trace_str(s);
+    }
+    Sink::trace_line("");
+}
+
+/// u64 を "0x" 付きの 16 進（小文字、先頭の 0 は省く）の ASCII 文字列に変換する。
+fn u64_to_hex(mut value: u64, buf: &mut [u8; 21]) -> &str {
+    const HEX: &[u8; 16] = b"0123456789abcdef";
+
+    let mut i = buf.len();
+    loop {
+        i -= 1;
+        buf[i] = HEX[(value & 0xf) as usize];
+        value >>= 4;
+        if value == 0 {
+            break;
+        }
+    }
+    i -= 2;
+    buf[i] = b'0';
+    buf[i + 1] = b'x';
+
+    unsafe { core::str::from_utf8_unchecked(&buf[i..]) }
 }
 
 /// u64 を 10 進数の ASCII 文字列に変換する。
-fn u64_to_decimal(mut value: u64, buf: &mut [u8; 21]) -> &str {
+pub(crate) fn u64_to_decimal(mut value: u64, buf: &mut [u8; 21]) -> &str {
     if value == 0 {
         let last = buf.len() - 1;
         buf[last] = b'0';
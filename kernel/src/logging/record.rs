@@ -0,0 +1,54 @@
+// kernel/src/logging/record.rs
+//
+// 役割:
+// - 機械可読の 1 行レコード（key=value）を serial に出す。
+// - 外部ツール（モデル検査側）が、人間向けの複数行テキストを scraping せずに trace を読めるようにする。
+//
+// 形式（1 行 = 1 レコード）:
+//   [REC] <kind> key=value key=value ...
+// - kind / key は空白と '=' を含まない ASCII
+// - value は 10 進 u64。値が無い（abi::WIRE_NONE）ところは "none"
+// - 行頭の [REC] も通し番号を消費する（log_seq のときは "[REC] #<seq> <kind> ..."）
+//
+// 方針:
+// - serial のみ（VGA には出さない。wire_hex と同じ扱い）。書く先は LogSink::trace_*（virtio console があればそちら）
+// - heap なし。begin → field... → end の順に直接 serial へ書く
+// - 行の途中で他のログを挟まない（呼び出し側が 1 レコードをまとめて出す）
+
+use super::sink::{LogSink, Sink};
+use super::{trace_record_start, u64_to_decimal};
+
+/// 値が無いことを表す word（kernel::abi::WIRE_NONE と同じ値）
+const NONE_WORD: u64 = u64::MAX;
+
+/// レコードを始める（"[REC] <kind>" まで書く）
+pub fn begin(kind: &str) {
+    trace_record_start("[REC] ");
+    Sink::trace_str(kind);
+}
+
+/// " key=value"（u64）
+pub fn field(key: &str, value: u64) {
+    Sink::trace_str(" ");
+    Sink::trace_str(key);
+    Sink::trace_str("=");
+    if value == NONE_WORD {
+        Sink::trace_str("none");
+        return;
+    }
+    let mut buf = [0u8; 21];
+    Sink::trace_str(u64_to_decimal(value, &mut buf));
+}
+
+/// " key=name"（名前。空白を含まないこと）
+pub fn field_str(key: &str, value: &str) {
+    Sink::trace_str(" ");
+    Sink::trace_str(key);
+    Sink::trace_str("=");
+    Sink::trace_str(value);
+}
+
+/// レコードを閉じる（改行）
+pub fn end() {
+    Sink::trace_line("");
+}
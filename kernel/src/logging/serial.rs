@@ -5,6 +5,7 @@
 // - write_str(): 文字列を送信
 // - write_line(): 文字列＋改行を送信
 // - write_prefixed_line(prefix, msg): prefix+msg をまとめて送信＋改行
+// - try_read_byte(): 受信した 1 byte を polling で読む（無ければ None。debug console 用。IRQ4 は使わない）
 //
 // C対応（完成版）:
 // - VGA は Mutex があるため without_interrupts が必要だが、serial はロック無し。
@@ -66,6 +67,19 @@ fn write_byte(byte: u8) {
     }
 }
 
+/// 受信バッファに byte があれば 1 つ読む（LSR bit0 = data ready）
+pub fn try_read_byte() -> Option<u8> {
+    unsafe {
+        let mut line_status = Port::<u8>::new(0x3F8 + 5);
+        let mut data = Port::<u8>::new(0x3F8 + 0);
+
+        if (line_status.read() & 0x01) == 0 {
+            return None;
+        }
+        Some(data.read())
+    }
+}
+
 pub fn write_str(s: &str) {
     for b in s.bytes() {
         write_byte(b);
@@ -77,6 +91,8 @@ pub fn write_line(s: &str) {
     write_str("\r\n");
 }
 
+// logging 側は通し番号を挟むため serial_record_start + write_line を使う（API としては残す）
+#[allow(dead_code)]
 pub fn write_prefixed_line(prefix: &str, msg: &str) {
     write_str(prefix);
     write_line(msg);
@@ -0,0 +1,192 @@
+// kernel/src/logging/sink.rs
+//
+// 役割:
+// - ログの出力先（VGA / serial）の境界（LogSink）。logging/ の API はこの trait 経由でだけ書く。
+//
+// 実装:
+// - HwSink:   実機（target_os = "none"）。vga.rs（feature fb_console では fb.rs）/ serial.rs へ委譲する
+//   * vga_*（画面）は既定で VGA テキストモード、fb_console で framebuffer。API は同じ
+//   * trace_*（wire_hex / record）は feature virtio_console で virtio console が使えればそちら、無ければ serial
+// - MockSink: ホスト（target_os != "none"）の test 専用（feature host_test）。VGA は捨て、serial の出力を固定長バッファに貯める（受信は常に無し）
+//   * captured() で貯まった文字列を読む（溢れた分は捨てて overflowed を立てる）
+//
+// 方針:
+// - 関数は self を取らない（呼び出し側は `Sink` の型 alias で静的に選ぶ）
+// - 1 行の組み立て（prefix / 通し番号 / key = value）は mod.rs 側に残す（sink は文字列を書くだけ）
+
+pub trait LogSink {
+    fn init();
+    fn vga_str(s: &str);
+    fn vga_line(s: &str);
+    /// prefix + msg を 1 行で（VGA 側は 1 回のロックで）
+    fn vga_prefixed_line(prefix: &str, msg: &str);
+    /// keyboard の scancode で VGA の scrollback を動かす
+    fn vga_scancode(scancode: u8);
+    fn serial_str(s: &str);
+    fn serial_line(s: &str);
+    /// 機械可読の trace（wire_hex / record）の出口
+    fn trace_str(s: &str);
+    fn trace_line(s: &str);
+    /// serial の受信（polling。無ければ None）
+    #[cfg_attr(not(feature = "debug_console"), allow(dead_code))]
+    fn serial_read_byte() -> Option<u8>;
+}
+
+// -----------------------------------------------------------------------------
+// 実機
+// -----------------------------------------------------------------------------
+
+// 画面の出力先（vga.rs と fb.rs は同じ関数を持つ）
+#[cfg(all(target_os = "none", not(feature = "fb_console")))]
+use super::vga as screen;
+#[cfg(all(target_os = "none", feature = "fb_console"))]
+use super::fb as screen;
+
+#[cfg(target_os = "none")]
+pub struct HwSink;
+
+#[cfg(target_os = "none")]
+impl LogSink for HwSink {
+    fn init() {
+        screen::init();
+        super::serial::init();
+    }
+
+    fn vga_str(s: &str) {
+        screen::write_str(s);
+    }
+
+    fn vga_line(s: &str) {
+        screen::write_line(s);
+    }
+
+    fn vga_prefixed_line(prefix: &str, msg: &str) {
+        screen::write_prefixed_line(prefix, msg);
+    }
+
+    fn vga_scancode(scancode: u8) {
+        screen::on_scancode(scancode);
+    }
+
+    fn serial_str(s: &str) {
+        super::serial::write_str(s);
+    }
+
+    fn serial_line(s: &str) {
+        super::serial::write_line(s);
+    }
+
+    fn trace_str(s: &str) {
+        #[cfg(feature = "virtio_console")]
+        if crate::arch::drivers::virtio_console::write_str(s) {
+            return;
+        }
+        super::serial::write_str(s);
+    }
+
+    fn trace_line(s: &str) {
+        #[cfg(feature = "virtio_console")]
+        if crate::arch::drivers::virtio_console::write_line(s) {
+            return;
+        }
+        super::serial::write_line(s);
+    }
+
+    fn serial_read_byte() -> Option<u8> {
+        super::serial::try_read_byte()
+    }
+}
+
+#[cfg(target_os = "none")]
+pub type Sink = HwSink;
+
+// -----------------------------------------------------------------------------
+// ホスト（mock）
+// -----------------------------------------------------------------------------
+
+#[cfg(all(not(target_os = "none"), feature = "host_test"))]
+pub use self::mock::MockSink;
+
+#[cfg(all(not(target_os = "none"), feature = "host_test"))]
+pub type Sink = MockSink;
+
+#[cfg(all(not(target_os = "none"), feature = "host_test"))]
+mod mock {
+    use super::LogSink;
+    use spin::Mutex;
+
+    const CAPTURE_CAP: usize = 64 * 1024;
+
+    struct Capture {
+        buf: [u8; CAPTURE_CAP],
+        len: usize,
+        overflowed: bool,
+    }
+
+    static CAPTURE: Mutex<Capture> = Mutex::new(Capture { buf: [0; CAPTURE_CAP], len: 0, overflowed: false });
+
+    fn push(s: &str) {
+        let mut c = CAPTURE.lock();
+        let room = CAPTURE_CAP - c.len;
+        let n = s.len().min(room);
+        let at = c.len;
+        c.buf[at..at + n].copy_from_slice(&s.as_bytes()[..n]);
+        c.len += n;
+        if n < s.len() {
+            c.overflowed = true;
+        }
+    }
+
+    pub struct MockSink;
+
+    impl MockSink {
+        /// 貯まった serial 出力を f に渡す（第 2 引数 = 溢れたか）
+        pub fn captured<R>(f: impl FnOnce(&str, bool) -> R) -> R {
+            let c = CAPTURE.lock();
+            let s = core::str::from_utf8(&c.buf[..c.len]).unwrap_or("");
+            f(s, c.overflowed)
+        }
+
+        pub fn clear() {
+            let mut c = CAPTURE.lock();
+            c.len = 0;
+            c.overflowed = false;
+        }
+    }
+
+    impl LogSink for MockSink {
+        fn init() {}
+
+        fn vga_str(_s: &str) {}
+
+        fn vga_line(_s: &str) {}
+
+        fn vga_prefixed_line(_prefix: &str, _msg: &str) {}
+
+        fn vga_scancode(_scancode: u8) {}
+
+        fn serial_str(s: &str) {
+            push(s);
+        }
+
+        fn serial_line(s: &str) {
+            push(s);
+            push("\n");
+        }
+
+        // trace も serial と同じバッファに貯める
+        fn trace_str(s: &str) {
+            push(s);
+        }
+
+        fn trace_line(s: &str) {
+            push(s);
+            push("\n");
+        }
+
+        // ホストには受信が無い
+        fn serial_read_byte() -> Option<u8> {
+            None
+        }
+    }
+}
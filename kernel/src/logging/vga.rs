@@ -5,10 +5,25 @@
 // - write_str(): 文字列（改行なし）
 // - write_line(): 文字列＋改行
 // - write_prefixed_line(prefix, msg): prefix+msg を 1 回のロックで出して改行
+// - on_scancode(sc): keyboard の scancode で scrollback を動かす（PageUp / PageDown / Home / End）
+//
+// scrollback:
+// - 画面（25 行）とは別に、直近 SCROLLBACK_LINES 行をメモリに持つ（リング）
+// - 表示位置が最下行（view_offset == 0）のときだけ新しい出力を画面に直接書く
+// - 過去を見ている間に出力が来ても画面は動かさない（view_offset を進めて同じ行を見続ける）
+// - 操作は keyboard（set 1 の make code）: PageUp / PageDown = 半画面、Home = 最古、End = 最新に戻る
+//   * E0 prefix は見ない（テンキーの 9 / 3 / 7 / 1 でも動く）
+//
+// 色:
+// - 行頭の tag で 1 行の色を決める（[TRACE] / [DEBUG] = 暗い灰、[INFO] = 明るい灰、[WARN] / [EXC] = 黄、
+//   [ERROR] = 明るい赤）
+// - tag の無い行は既定色。改行で既定色に戻す
 //
 // C対応:
 // - spin::Mutex は割り込み再入でデッドロックしうるため、
 //   ロック取得～書き込みを interrupts::without_interrupts で囲む。
+// - Writer は scrollback を抱えて大きいので、stack 上で組み立てずに static に const で置く
+//   （init は buffer を差し込むだけ。init 前の画面の内容はそのまま残す）。
 
 use core::fmt::{self, Write};
 use spin::Mutex;
@@ -18,13 +33,37 @@ use x86_64::instructions::interrupts;
 const BUFFER_HEIGHT: usize = 25;
 const BUFFER_WIDTH: usize = 80;
 
+/// メモリに残す行数（画面の行数以上）
+const SCROLLBACK_LINES: usize = 200;
+
+const _: () = assert!(SCROLLBACK_LINES >= BUFFER_HEIGHT);
+
 #[derive(Clone, Copy)]
 #[repr(u8)]
 enum Color {
     Black = 0x0,
     LightGray = 0x7,
+    DarkGray = 0x8,
+    LightRed = 0xC,
+    Yellow = 0xE,
 }
 
+const fn color_code(fg: Color, bg: Color) -> u8 {
+    (fg as u8) | ((bg as u8) << 4)
+}
+
+const DEFAULT_COLOR: u8 = color_code(Color::LightGray, Color::Black);
+
+/// 行頭の tag と、その行の色
+const SEVERITY_COLORS: [(&str, u8); 6] = [
+    ("[TRACE]", color_code(Color::DarkGray, Color::Black)),
+    ("[DEBUG]", color_code(Color::DarkGray, Color::Black)),
+    ("[INFO]", color_code(Color::LightGray, Color::Black)),
+    ("[WARN]", color_code(Color::Yellow, Color::Black)),
+    ("[ERROR]", color_code(Color::LightRed, Color::Black)),
+    ("[EXC]", color_code(Color::Yellow, Color::Black)),
+];
+
 #[repr(C)]
 #[derive(Clone, Copy)]
 struct ScreenChar {
@@ -32,6 +71,8 @@ struct ScreenChar {
     color_code: u8,
 }
 
+const BLANK: ScreenChar = ScreenChar { ascii_character: b' ', color_code: DEFAULT_COLOR };
+
 #[repr(transparent)]
 struct Buffer {
     chars: [[Volatile<ScreenChar>; BUFFER_WIDTH]; BUFFER_HEIGHT],
@@ -39,53 +80,142 @@ struct Buffer {
 
 struct Writer {
     col: usize,
-    color_code: u8,
-    buffer: &'static mut Buffer,
+    // 今の行の色（行頭の tag で決まる）
+    line_color: u8,
+    // scrollback（リング）。history[newest] が今書いている行
+    history: [[ScreenChar; BUFFER_WIDTH]; SCROLLBACK_LINES],
+    newest: usize,
+    // history に溜まっている行数（今の行を含む）
+    filled: usize,
+    // 最下行から何行さかのぼって表示しているか（0 = 最新を表示中）
+    view_offset: usize,
+    // init までは None（何も書かない）
+    buffer: Option<&'static mut Buffer>,
 }
 
 impl Writer {
+    const fn new() -> Self {
+        Self {
+            col: 0,
+            line_color: DEFAULT_COLOR,
+            history: [[BLANK; BUFFER_WIDTH]; SCROLLBACK_LINES],
+            newest: 0,
+            filled: 1,
+            view_offset: 0,
+            buffer: None,
+        }
+    }
+
     fn write_byte(&mut self, byte: u8) {
         match byte {
             b'\n' => self.new_line(),
             byte => {
                 if self.col >= BUFFER_WIDTH {
+                    // 折り返しは同じ行の続きなので色を引き継ぐ
+                    let color = self.line_color;
                     self.new_line();
+                    self.line_color = color;
                 }
-                let row = BUFFER_HEIGHT - 1;
-                let col = self.col;
-                self.buffer.chars[row][col].write(ScreenChar {
+                let ch = ScreenChar {
                     ascii_character: byte,
-                    color_code: self.color_code,
-                });
+                    color_code: self.line_color,
+                };
+                let col = self.col;
+                self.history[self.newest][col] = ch;
+                if self.view_offset == 0 {
+                    if let Some(buffer) = self.buffer.as_mut() {
+                        buffer.chars[BUFFER_HEIGHT - 1][col].write(ch);
+                    }
+                }
                 self.col += 1;
             }
         }
     }
 
     fn new_line(&mut self) {
+        self.newest = (self.newest + 1) % SCROLLBACK_LINES;
+        self.history[self.newest] = [BLANK; BUFFER_WIDTH];
+        if self.filled < SCROLLBACK_LINES {
+            self.filled += 1;
+        }
+        self.col = 0;
+        self.line_color = DEFAULT_COLOR;
+
+        if self.view_offset == 0 {
+            self.scroll_screen_up();
+        } else if self.view_offset < self.max_view_offset() {
+            // 同じ行を見続ける（画面はそのまま）
+            self.view_offset += 1;
+        } else {
+            // 見ていた最古の行がリングから落ちた
+            self.redraw();
+        }
+    }
+
+    /// 画面を 1 行上げて最下行を空ける（最新を表示中の通常経路）
+    fn scroll_screen_up(&mut self) {
+        let Some(buffer) = self.buffer.as_mut() else {
+            return;
+        };
         for row in 1..BUFFER_HEIGHT {
             for col in 0..BUFFER_WIDTH {
-                let ch = self.buffer.chars[row][col].read();
-                self.buffer.chars[row - 1][col].write(ch);
+                let ch = buffer.chars[row][col].read();
+                buffer.chars[row - 1][col].write(ch);
             }
         }
-        self.clear_row(BUFFER_HEIGHT - 1);
-        self.col = 0;
+        for col in 0..BUFFER_WIDTH {
+            buffer.chars[BUFFER_HEIGHT - 1][col].write(BLANK);
+        }
+    }
+
+    fn max_view_offset(&self) -> usize {
+        self.filled.saturating_sub(BUFFER_HEIGHT)
     }
 
-    fn clear_row(&mut self, row: usize) {
-        let blank = ScreenChar {
-            ascii_character: b' ',
-            color_code: self.color_code,
+    /// view_offset の位置で画面全体を history から描き直す
+    fn redraw(&mut self) {
+        let Some(buffer) = self.buffer.as_mut() else {
+            return;
         };
-        for col in 0..BUFFER_WIDTH {
-            self.buffer.chars[row][col].write(blank);
+        for row in 0..BUFFER_HEIGHT {
+            // 最下行から何行前か
+            let back = (BUFFER_HEIGHT - 1 - row) + self.view_offset;
+            for col in 0..BUFFER_WIDTH {
+                let ch = if back < self.filled {
+                    self.history[(self.newest + SCROLLBACK_LINES - back) % SCROLLBACK_LINES][col]
+                } else {
+                    BLANK
+                };
+                buffer.chars[row][col].write(ch);
+            }
         }
     }
+
+    fn scroll_view(&mut self, delta: isize) {
+        let target = (self.view_offset as isize)
+            .saturating_add(delta)
+            .clamp(0, self.max_view_offset() as isize) as usize;
+        if target != self.view_offset {
+            self.view_offset = target;
+            self.redraw();
+        }
+    }
+
+    /// 行頭なら tag を見て行の色を決める
+    fn begin_text(&mut self, s: &str) {
+        if self.col != 0 {
+            return;
+        }
+        self.line_color = SEVERITY_COLORS
+            .iter()
+            .find(|(tag, _)| s.starts_with(*tag))
+            .map_or(DEFAULT_COLOR, |&(_, color)| color);
+    }
 }
 
 impl Write for Writer {
     fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.begin_text(s);
         for b in s.bytes() {
             self.write_byte(b);
         }
@@ -93,17 +223,41 @@ impl Write for Writer {
     }
 }
 
-static WRITER: Mutex<Option<Writer>> = Mutex::new(None);
+static WRITER: Mutex<Writer> = Mutex::new(Writer::new());
 
 pub fn init() {
-    let writer = Writer {
-        col: 0,
-        color_code: (Color::LightGray as u8) | ((Color::Black as u8) << 4),
-        buffer: unsafe { &mut *(0xb8000 as *mut Buffer) },
+    interrupts::without_interrupts(|| {
+        let mut w = WRITER.lock();
+        w.buffer = Some(unsafe { &mut *(0xb8000 as *mut Buffer) });
+    });
+}
+
+// scrollback を動かすキー（scancode set 1 の make code）
+const SC_PAGE_UP: u8 = 0x49;
+const SC_PAGE_DOWN: u8 = 0x51;
+const SC_HOME: u8 = 0x47;
+const SC_END: u8 = 0x4F;
+
+/// keyboard の scancode を見て scrollback を動かす（関係ないキーは無視）
+pub fn on_scancode(scancode: u8) {
+    let delta = match scancode {
+        SC_PAGE_UP => (BUFFER_HEIGHT / 2) as isize,
+        SC_PAGE_DOWN => -((BUFFER_HEIGHT / 2) as isize),
+        SC_HOME => SCROLLBACK_LINES as isize,
+        SC_END => -(SCROLLBACK_LINES as isize),
+        _ => return,
     };
+    scroll_view(delta);
+}
+
+/// scrollback の表示位置を delta 行動かす（正 = 過去へ、負 = 新しい方へ。範囲外は端で止める）
+fn scroll_view(delta: isize) {
+    if !crate::logging::is_vga_enabled() {
+        return;
+    }
 
     interrupts::without_interrupts(|| {
-        *WRITER.lock() = Some(writer);
+        WRITER.lock().scroll_view(delta);
     });
 }
 
@@ -114,9 +268,7 @@ pub fn write_str(s: &str) {
     }
 
     interrupts::without_interrupts(|| {
-        if let Some(ref mut w) = *WRITER.lock() {
-            let _ = w.write_str(s);
-        }
+        let _ = WRITER.lock().write_str(s);
     });
 }
 
@@ -127,10 +279,9 @@ pub fn write_line(s: &str) {
     }
 
     interrupts::without_interrupts(|| {
-        if let Some(ref mut w) = *WRITER.lock() {
-            let _ = w.write_str(s);
-            let _ = w.write_str("\n");
-        }
+        let mut w = WRITER.lock();
+        let _ = w.write_str(s);
+        let _ = w.write_str("\n");
     });
 }
 
@@ -141,10 +292,9 @@ pub fn write_prefixed_line(prefix: &str, msg: &str) {
     }
 
     interrupts::without_interrupts(|| {
-        if let Some(ref mut w) = *WRITER.lock() {
-            let _ = w.write_str(prefix);
-            let _ = w.write_str(msg);
-            let _ = w.write_str("\n");
-        }
+        let mut w = WRITER.lock();
+        let _ = w.write_str(prefix);
+        let _ = w.write_str(msg);
+        let _ = w.write_str("\n");
     });
 }
@@ -1,33 +1,36 @@
 // kernel/src/main.rs
-#![no_std]
-#![no_main]
-
-// nightly: x86-interrupt ABI
-#![feature(abi_x86_interrupt)]
-
-// ─────────────────────────────────────────────
-// formal-os: pre-formal verification kernel
 //
-// - フォーマル検証しやすい「状態機械 + 抽象イベント」中心にする
-// - unsafe は arch 側に閉じ込め、kernel 側は状態遷移を明示する
-// ─────────────────────────────────────────────
-
-mod arch;
-mod kernel;
-mod logging;
-mod mem;
-mod mm;
-mod panic;
-mod types;
+// 実機の kernel の入口。本体は lib（lib.rs の formal_os）にあり、ここは boot の順番だけを持つ。
+// - ホスト（target_os != "none"）では中身の無い main（cargo test は lib と kernel/tests/ だけを使う）
+#![cfg_attr(target_os = "none", no_std)]
+#![cfg_attr(target_os = "none", no_main)]
 
+#[cfg(target_os = "none")]
 use bootloader::{entry_point, BootInfo};
+#[cfg(target_os = "none")]
+use formal_os::{arch, kernel, logging};
 
+#[cfg(target_os = "none")]
 entry_point!(kernel_main);
 
+#[cfg(target_os = "none")]
 fn kernel_main(boot_info: &'static BootInfo) -> ! {
     logging::init();
+
+    // 最初の構造化レコード: このビルドの capability advertisement
+    kernel::emit_capabilities();
+
     arch::init(boot_info);
 
+    // 画面の出力先を BootInfo から決める（feature fb_console の framebuffer は physmap 越しなので arch::init の後）
+    logging::select_display(boot_info);
+
+    // boot image に付いた initrd（memory map の Package region）を探す（physmap が要るので arch::init の後）
+    kernel::initrd::init(boot_info);
+
+    // command line（埋め込み / initrd の cmdline）を BootParams に読む
+    kernel::boot_params::init();
+
     logging::info("formal-os: kernel_main start");
 
     // カーネル本体（low entry -> high-alias -> KernelState loop）
@@ -36,3 +39,6 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
     // 基本は戻らない想定だが、万一戻ってきても止める
     arch::halt_loop()
 }
+
+#[cfg(not(target_os = "none"))]
+fn main() {}
@@ -8,9 +8,54 @@
 // - unsafe は持ち込まない（arch 側に閉じ込める）。
 // - kill 後始末で「Dead task の user mapping が残らない」を保証できる API を提供する。
 // - 実ページテーブル操作は行わない（論理状態のみ）。
+//
+// フレーム quota:
+// - AddressSpace ごとに「mapping が参照する物理フレーム（重複なし）」の上限を持つ。
+// - 検査するのは PageMap syscall（syscall.rs）。kernel 側の demo は quota を通さない。
+// - kernel AS の quota は MAX_REGIONS（実質無制限）。
+//
+// guard page:
+// - user stack の直下の page を「意図的に map しない page」として記録する（GuardPage）。
+// - guard page への Map は拒否する（AddressSpaceError::GuardPage）。
+// - そこへの #PF は stack overflow として区別する（kernel 側で TaskKillReason::StackOverflow）。
+//
+// swap（SwappedOut）:
+// - 追い出した page は mapping から外し、swap slot の番号と flags を記録する（SwappedPage）。
+//   * 論理的には「まだ map されている」ので、同じ page への Map は AlreadyMapped
+//   * 中身の退避 / 復元と slot の管理は kernel 側（kernel/swap.rs）
+// - 1 つの page は mapped / guard（reserved）/ swapped のどれか 1 つだけ（INV-MEM-007）。
+//
+// mapping region（MapRegion）:
+// - mapping は page 単位ではなく「連続した page を連続した frame に同じ flags で map した範囲」で持つ（VMA 相当）。
+//   * stack や ELF segment のような連続確保は 1 region で済む（容量は page 数ではなく region 数で決まる）
+// - Map は前後の region に続けられれば伸ばす / 2 つをつなぐ。Unmap は region を縮める / 2 つに割る。
+//   * 割るときに空き slot が無ければ Unmap は CapacityExceeded（region は変えない）
+// - 外から見る API（lookup / for_each_mapping / mapping_count など）は従来どおり page 単位の Mapping。
+// - region 同士は重ならず、空の region は無い（INV-MEM-011）。
+//
+// Protect（flags の付け替え）:
+// - map 済みの page の flags だけを替える（フレームはそのまま）。region は page を外して付け直す（割れる / 前後とつながる）
+//   * region が足りず付け直せなければ CapacityExceeded で、外す前の状態に戻す（途中の状態を残さない）
+// - PRESENT を落とす / USER を変える Protect は BadFlags（外すのは Unmap、kernel / user の境は越えない）
+// - swap out 中の page は NotMapped（先に swap in する）。同じ flags なら何もしない
+//
+// batch（apply_mem_actions）:
+// - MemAction の列を先頭から apply し、どれかが落ちたら batch の前の region に戻して、落ちた index と理由を返す
+//   * 後の entry は前の entry を反映した状態で検査する（同じ page を Map してから Protect する、など）
+//   * apply は guard / swapped の記録を変えないので、戻すのは region だけ
+//   * 使うのは shm_map（kernel/shm.rs）と ring3 task の code / stack の用意（kernel/ring3_task.rs）
+//
+// region の探索範囲（chunk）:
+// - region の slot は固定長（MAX_REGIONS）の配列のまま。REGION_CHUNK 個ずつの chunk に区切り、先頭から開いていく。
+//   * 開いた chunk に空きが無いときだけ次の chunk を開く。末尾の chunk が空になれば閉じる
+//   * 探索は開いた chunk の中だけ（region の少ない AS の Map / lookup は全 slot を舐めない）
+//   * chunk は探索の範囲を決めるだけで、容量は増えない（MAX_REGIONS = 64 は以前と同じ）
+// - 全 chunk が埋まったら Map（と region を割る Unmap）は CapacityExceeded。呼び出し側は panic せずに拒否として返す
+//   （syscall なら SYSCALL_ERR_CAPACITY。kernel 側は KernelState::aspace_apply で数える）
+// - heap には置かない（AddressSpace は Copy のまま。checkpoint がそのまま写す）
 
 use crate::mem::addr::{PhysFrame, VirtPage};
-use crate::mem::paging::{MemAction, PageFlags};
+use crate::mem::paging::{BatchError, MemAction, PageFlags};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum AddressSpaceKind {
@@ -25,12 +70,93 @@ pub struct Mapping {
     pub flags: PageFlags,
 }
 
-const MAX_MAPPINGS: usize = 64;
+/// 連続した page を連続した frame に同じ flags で map している範囲
+/// - page start_page + i（0 <= i < page_count）は first_frame + i に map されている
+#[derive(Clone, Copy)]
+pub struct MapRegion {
+    pub start_page: VirtPage,
+    pub page_count: usize,
+    pub flags: PageFlags,
+    pub first_frame: PhysFrame,
+}
+
+impl MapRegion {
+    /// 範囲の終わり（この page は含まない）
+    pub fn end_page(&self) -> u64 {
+        self.start_page.number + self.page_count as u64
+    }
+
+    pub fn contains(&self, page: VirtPage) -> bool {
+        self.start_page.number <= page.number && page.number < self.end_page()
+    }
 
+    fn contains_frame(&self, frame: PhysFrame) -> bool {
+        self.first_frame.number <= frame.number && frame.number < self.first_frame.number + self.page_count as u64
+    }
+
+    fn mapping_at(&self, i: u64) -> Mapping {
+        Mapping {
+            page: VirtPage::from_index(self.start_page.number + i),
+            frame: PhysFrame::from_index(self.first_frame.number + i),
+            flags: self.flags,
+        }
+    }
+
+    /// m を末尾に続けられるか（page / frame とも連続で flags が同じ）
+    fn can_append(&self, m: &Mapping) -> bool {
+        self.flags.bits() == m.flags.bits()
+            && m.page.number == self.end_page()
+            && m.frame.number == self.first_frame.number + self.page_count as u64
+    }
+
+    /// m を先頭に足せるか
+    fn can_prepend(&self, m: &Mapping) -> bool {
+        self.flags.bits() == m.flags.bits()
+            && m.page.number + 1 == self.start_page.number
+            && m.frame.number + 1 == self.first_frame.number
+    }
+
+    fn overlaps(&self, other: &MapRegion) -> bool {
+        self.start_page.number < other.end_page() && other.start_page.number < self.end_page()
+    }
+}
+
+/// region の slot を開く単位
+const REGION_CHUNK: usize = 16;
+
+/// AddressSpace ごとに開ける chunk の数
+const MAX_REGION_CHUNKS: usize = 4;
+
+/// AddressSpace ごとに記録できる region の数（kernel AS の frame quota もこれ）
+pub const MAX_REGIONS: usize = REGION_CHUNK * MAX_REGION_CHUNKS;
+
+/// AddressSpace ごとに記録できる guard page の数
+const MAX_GUARD_PAGES: usize = 4;
+
+/// AddressSpace ごとに記録できる swap out 中の page の数
+const MAX_SWAPPED_PAGES: usize = 4;
+
+/// user AS が mapping で参照できる物理フレーム数の既定上限
+pub const DEFAULT_USER_FRAME_QUOTA: usize = 8;
+
+/// swap out 中の page（中身は swap slot にある）
+#[derive(Clone, Copy)]
+pub struct SwappedPage {
+    pub page: VirtPage,
+    pub slot: usize,
+    pub flags: PageFlags,
+}
+
+#[derive(Clone, Copy)]
 pub struct AddressSpace {
     pub kind: AddressSpaceKind,
     pub root_page_frame: Option<PhysFrame>,
-    mappings: [Option<Mapping>; MAX_MAPPINGS],
+    regions: [Option<MapRegion>; MAX_REGIONS],
+    /// 開いている chunk の数（regions[..chunks_used * REGION_CHUNK] だけを使う）
+    chunks_used: usize,
+    frame_quota: usize,
+    guard_pages: [Option<VirtPage>; MAX_GUARD_PAGES],
+    swapped: [Option<SwappedPage>; MAX_SWAPPED_PAGES],
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -38,6 +164,10 @@ pub enum AddressSpaceError {
     AlreadyMapped,
     NotMapped,
     CapacityExceeded,
+    /// guard page は map できない
+    GuardPage,
+    /// Protect で PRESENT を落とす / USER を変えようとした
+    BadFlags,
 }
 
 impl AddressSpace {
@@ -45,7 +175,11 @@ impl AddressSpace {
         AddressSpace {
             kind: AddressSpaceKind::Kernel,
             root_page_frame: None,
-            mappings: [None; MAX_MAPPINGS],
+            regions: [None; MAX_REGIONS],
+            chunks_used: 0,
+            frame_quota: MAX_REGIONS,
+            guard_pages: [None; MAX_GUARD_PAGES],
+            swapped: [None; MAX_SWAPPED_PAGES],
         }
     }
 
@@ -53,58 +187,335 @@ impl AddressSpace {
         AddressSpace {
             kind: AddressSpaceKind::User,
             root_page_frame: None,
-            mappings: [None; MAX_MAPPINGS],
+            regions: [None; MAX_REGIONS],
+            chunks_used: 0,
+            frame_quota: DEFAULT_USER_FRAME_QUOTA,
+            guard_pages: [None; MAX_GUARD_PAGES],
+            swapped: [None; MAX_SWAPPED_PAGES],
         }
     }
 
     pub fn apply(&mut self, action: MemAction) -> Result<(), AddressSpaceError> {
         match action {
             MemAction::Map { page, frame, flags } => {
-                for entry in self.mappings.iter() {
-                    if let Some(m) = entry {
-                        if m.page == page {
-                            return Err(AddressSpaceError::AlreadyMapped);
-                        }
-                    }
+                if self.is_guard_page(page) {
+                    return Err(AddressSpaceError::GuardPage);
+                }
+                if self.lookup_swapped(page).is_some() || self.lookup(page).is_some() {
+                    return Err(AddressSpaceError::AlreadyMapped);
                 }
+                self.insert_page(Mapping { page, frame, flags })
+            }
 
-                for entry in self.mappings.iter_mut() {
-                    if entry.is_none() {
-                        *entry = Some(Mapping { page, frame, flags });
-                        return Ok(());
-                    }
+            MemAction::Unmap { page } => self.remove_page(page).map(|_| ()),
+
+            MemAction::Protect { page, new_flags } => self.protect_page(page, new_flags),
+        }
+    }
+
+    /// actions を先頭から全部 apply する（all-or-nothing。失敗したら何も変えずに落ちた index を返す）
+    pub fn apply_mem_actions(&mut self, actions: &[MemAction]) -> Result<(), BatchError<AddressSpaceError>> {
+        let saved = (self.regions, self.chunks_used);
+        for (index, &action) in actions.iter().enumerate() {
+            if let Err(error) = self.apply(action) {
+                (self.regions, self.chunks_used) = saved;
+                return Err(BatchError { index, error });
+            }
+        }
+        Ok(())
+    }
+
+    /// map 済みの page の flags を替える（失敗したら何も変えない）
+    fn protect_page(&mut self, page: VirtPage, new_flags: PageFlags) -> Result<(), AddressSpaceError> {
+        let m = self.lookup(page).ok_or(AddressSpaceError::NotMapped)?;
+        if !new_flags.contains(PageFlags::PRESENT)
+            || new_flags.contains(PageFlags::USER) != m.flags.contains(PageFlags::USER)
+        {
+            return Err(AddressSpaceError::BadFlags);
+        }
+        if new_flags.bits() == m.flags.bits() {
+            return Ok(());
+        }
+
+        let saved = (self.regions, self.chunks_used);
+        self.remove_page(page)?;
+        if let Err(e) = self.insert_page(Mapping { flags: new_flags, ..m }) {
+            (self.regions, self.chunks_used) = saved;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// 前後の region に続けられれば伸ばし（両方ならつなぎ）、無理なら新しい region にする
+    fn insert_page(&mut self, m: Mapping) -> Result<(), AddressSpaceError> {
+        let before = self.active().iter().position(|r| r.is_some_and(|r| r.can_append(&m)));
+        let after = self.active().iter().position(|r| r.is_some_and(|r| r.can_prepend(&m)));
+
+        match (before, after) {
+            (Some(b), Some(a)) => {
+                let tail = self.regions[a].take().map_or(0, |r| r.page_count);
+                if let Some(r) = self.regions[b].as_mut() {
+                    r.page_count += 1 + tail;
+                }
+                self.trim_chunks();
+                Ok(())
+            }
+            (Some(b), None) => {
+                if let Some(r) = self.regions[b].as_mut() {
+                    r.page_count += 1;
+                }
+                Ok(())
+            }
+            (None, Some(a)) => {
+                if let Some(r) = self.regions[a].as_mut() {
+                    r.start_page = m.page;
+                    r.first_frame = m.frame;
+                    r.page_count += 1;
                 }
+                Ok(())
+            }
+            (None, None) => {
+                let free = self.free_region_slot()?;
+                self.regions[free] = Some(MapRegion { start_page: m.page, page_count: 1, flags: m.flags, first_frame: m.frame });
+                Ok(())
+            }
+        }
+    }
+
+    /// page を含む region から page を外す（端なら縮め、途中なら 2 つに割る）
+    fn remove_page(&mut self, page: VirtPage) -> Result<Mapping, AddressSpaceError> {
+        let idx = self
+            .active()
+            .iter()
+            .position(|r| r.is_some_and(|r| r.contains(page)))
+            .ok_or(AddressSpaceError::NotMapped)?;
+        let r = self.regions[idx].ok_or(AddressSpaceError::NotMapped)?;
+        let i = page.number - r.start_page.number;
+        let removed = r.mapping_at(i);
+        let last = r.page_count as u64 - 1;
+
+        if r.page_count == 1 {
+            self.regions[idx] = None;
+            self.trim_chunks();
+        } else if i == 0 {
+            self.regions[idx] = Some(MapRegion {
+                start_page: VirtPage::from_index(r.start_page.number + 1),
+                page_count: r.page_count - 1,
+                flags: r.flags,
+                first_frame: PhysFrame::from_index(r.first_frame.number + 1),
+            });
+        } else if i == last {
+            self.regions[idx] = Some(MapRegion { page_count: r.page_count - 1, ..r });
+        } else {
+            let free = self.free_region_slot()?;
+            self.regions[free] = Some(MapRegion {
+                start_page: VirtPage::from_index(page.number + 1),
+                page_count: (last - i) as usize,
+                flags: r.flags,
+                first_frame: PhysFrame::from_index(removed.frame.number + 1),
+            });
+            self.regions[idx] = Some(MapRegion { page_count: i as usize, ..r });
+        }
+        Ok(removed)
+    }
 
-                Err(AddressSpaceError::CapacityExceeded)
+    /// 開いている chunk の slot
+    fn active(&self) -> &[Option<MapRegion>] {
+        &self.regions[..self.chunks_used * REGION_CHUNK]
+    }
+
+    /// 空いている slot（開いた chunk に無ければ次の chunk を開く）
+    fn free_region_slot(&mut self) -> Result<usize, AddressSpaceError> {
+        if let Some(i) = self.active().iter().position(|e| e.is_none()) {
+            return Ok(i);
+        }
+        if self.chunks_used >= MAX_REGION_CHUNKS {
+            return Err(AddressSpaceError::CapacityExceeded);
+        }
+        self.chunks_used += 1;
+        Ok((self.chunks_used - 1) * REGION_CHUNK)
+    }
+
+    /// 末尾の空になった chunk を閉じる
+    fn trim_chunks(&mut self) {
+        while self.chunks_used > 0 {
+            let start = (self.chunks_used - 1) * REGION_CHUNK;
+            if self.regions[start..start + REGION_CHUNK].iter().any(|e| e.is_some()) {
+                break;
             }
+            self.chunks_used -= 1;
+        }
+    }
 
-            MemAction::Unmap { page } => {
-                for entry in self.mappings.iter_mut() {
-                    if let Some(m) = entry {
-                        if m.page == page {
-                            *entry = None;
-                            return Ok(());
-                        }
+    /// page の mapping を引く（Unmap 前にフレームを知るため）
+    pub fn lookup(&self, page: VirtPage) -> Option<Mapping> {
+        let r = self.active().iter().flatten().find(|r| r.contains(page))?;
+        Some(r.mapping_at(page.number - r.start_page.number))
+    }
+
+    /// frame を参照している mapping の数（参照カウントとの突き合わせ用）
+    pub fn frame_mapping_count(&self, frame: PhysFrame) -> usize {
+        self.active().iter().flatten().filter(|r| r.contains_frame(frame)).count()
+    }
+
+    /// mapping が参照している物理フレームの数（同じフレームは 1 つと数える）
+    /// - 1 つの region の中のフレームは全部違うので、前の region に出てきたかだけ見ればよい
+    pub fn frames_in_use(&self) -> usize {
+        let mut n = 0;
+        for (i, entry) in self.active().iter().enumerate() {
+            if let Some(r) = entry {
+                for k in 0..r.page_count as u64 {
+                    let frame = PhysFrame::from_index(r.first_frame.number + k);
+                    let seen = self.active()[..i].iter().flatten().any(|p| p.contains_frame(frame));
+                    if !seen {
+                        n += 1;
                     }
                 }
-                Err(AddressSpaceError::NotMapped)
             }
         }
+        n
+    }
+
+    pub fn frame_quota(&self) -> usize {
+        self.frame_quota
+    }
+
+    /// frame を新たに map したら quota を超えるか（既に map 済みのフレームは数え直さない）
+    pub fn would_exceed_frame_quota(&self, frame: Option<PhysFrame>) -> bool {
+        let already = frame.is_some_and(|f| self.frame_mapping_count(f) > 0);
+        !already && self.frames_in_use() >= self.frame_quota
+    }
+
+    /// page を guard page として記録する（既に記録済みなら何もしない）
+    /// - map 済みの page は guard にできない（AlreadyMapped）
+    pub fn reserve_guard_page(&mut self, page: VirtPage) -> Result<(), AddressSpaceError> {
+        if self.is_guard_page(page) {
+            return Ok(());
+        }
+        if self.lookup(page).is_some() || self.lookup_swapped(page).is_some() {
+            return Err(AddressSpaceError::AlreadyMapped);
+        }
+        for entry in self.guard_pages.iter_mut() {
+            if entry.is_none() {
+                *entry = Some(page);
+                return Ok(());
+            }
+        }
+        Err(AddressSpaceError::CapacityExceeded)
+    }
+
+    pub fn is_guard_page(&self, page: VirtPage) -> bool {
+        self.guard_pages.iter().flatten().any(|g| *g == page)
+    }
+
+    pub fn for_each_guard_page<F>(&self, mut f: F)
+    where
+        F: FnMut(VirtPage),
+    {
+        for g in self.guard_pages.iter().flatten() {
+            f(*g);
+        }
+    }
+
+    /// guard page の記録を全て消す（task の後始末で user mapping と一緒に）
+    pub fn clear_guard_pages(&mut self) {
+        self.guard_pages = [None; MAX_GUARD_PAGES];
+    }
+
+    /// page の mapping を外して swap out 中として記録する（外した mapping を返す）
+    /// - slot の確保と中身の退避は呼び出し側（kernel/swap.rs）
+    pub fn swap_out(&mut self, page: VirtPage, slot: usize) -> Result<Mapping, AddressSpaceError> {
+        self.lookup(page).ok_or(AddressSpaceError::NotMapped)?;
+        let free = self.swapped.iter().position(|e| e.is_none()).ok_or(AddressSpaceError::CapacityExceeded)?;
+
+        let m = self.remove_page(page)?;
+        self.swapped[free] = Some(SwappedPage { page, slot, flags: m.flags });
+        Ok(m)
+    }
+
+    /// swap out 中の記録を戻す（swap in の Map が拒否されたとき。take_swapped で空いた slot に入る）
+    pub fn restore_swapped(&mut self, s: SwappedPage) -> Result<(), AddressSpaceError> {
+        let entry = self.swapped.iter_mut().find(|e| e.is_none()).ok_or(AddressSpaceError::CapacityExceeded)?;
+        *entry = Some(s);
+        Ok(())
+    }
+
+    /// swap out 中の記録を外して返す（swap in / Unmap / 後始末）
+    pub fn take_swapped(&mut self, page: VirtPage) -> Option<SwappedPage> {
+        let entry = self.swapped.iter_mut().find(|e| e.is_some_and(|s| s.page == page))?;
+        entry.take()
+    }
+
+    pub fn lookup_swapped(&self, page: VirtPage) -> Option<SwappedPage> {
+        self.swapped.iter().flatten().find(|s| s.page == page).copied()
+    }
+
+    pub fn for_each_swapped_page<F>(&self, mut f: F)
+    where
+        F: FnMut(&SwappedPage),
+    {
+        for s in self.swapped.iter().flatten() {
+            f(s);
+        }
+    }
+
+    /// swap out 中の page の数（mapping_count = resident と合わせて MemInfo に出す）
+    pub fn swapped_count(&self) -> usize {
+        self.swapped.iter().flatten().count()
+    }
+
+    /// swap out 中の記録を全て消す（slot の返却は呼び出し側）
+    pub fn clear_swapped_pages(&mut self) {
+        self.swapped = [None; MAX_SWAPPED_PAGES];
     }
 
     pub fn mapping_count(&self) -> usize {
-        self.mappings.iter().filter(|m| m.is_some()).count()
+        self.active().iter().flatten().map(|r| r.page_count).sum()
     }
 
     pub fn for_each_mapping<F>(&self, mut f: F)
     where
         F: FnMut(&Mapping),
     {
-        for entry in self.mappings.iter() {
-            if let Some(ref m) = entry {
-                f(m);
+        for r in self.active().iter().flatten() {
+            for i in 0..r.page_count as u64 {
+                f(&r.mapping_at(i));
+            }
+        }
+    }
+
+    pub fn region_count(&self) -> usize {
+        self.active().iter().filter(|r| r.is_some()).count()
+    }
+
+    /// 開いている chunk の数（dump 用）
+    pub fn region_chunks(&self) -> usize {
+        self.chunks_used
+    }
+
+    pub fn for_each_region<F>(&self, mut f: F)
+    where
+        F: FnMut(&MapRegion),
+    {
+        for r in self.active().iter().flatten() {
+            f(r);
+        }
+    }
+
+    /// 重なっている region の組（最初に見つかったもの。INV-MEM-011 の検査用）
+    pub fn overlapping_regions(&self) -> Option<(MapRegion, MapRegion)> {
+        for (i, a) in self.active().iter().enumerate() {
+            let Some(a) = a else { continue };
+            if let Some(b) = self.active()[i + 1..].iter().flatten().find(|b| a.overlaps(b)) {
+                return Some((*a, *b));
             }
         }
+        None
+    }
+
+    /// page_count = 0 の region があるか（INV-MEM-011 の検査用）
+    pub fn has_empty_region(&self) -> bool {
+        self.active().iter().flatten().any(|r| r.page_count == 0)
     }
 
     // -------------------------------------------------------------------------
@@ -120,13 +531,11 @@ impl AddressSpace {
     where
         F: FnMut(VirtPage),
     {
-        for entry in self.mappings.iter() {
-            if let Some(m) = entry {
-                if m.flags.contains(PageFlags::USER) {
-                    f(m.page);
-                }
+        self.for_each_mapping(|m| {
+            if m.flags.contains(PageFlags::USER) {
+                f(m.page);
             }
-        }
+        });
     }
 
     /// user mapping（flags に USER が付いている mapping）を論理状態から全て消す。
@@ -134,13 +543,13 @@ impl AddressSpace {
     /// 注意:
     /// - これは「論理 AddressSpace の掃除」だけ。
     /// - 実ページテーブルの unmap は arch 側で別途実行すること。
+    /// - flags は region 単位で同じなので、USER の region ごと消す。
     pub fn clear_user_mappings(&mut self) {
-        for entry in self.mappings.iter_mut() {
-            if let Some(m) = entry {
-                if m.flags.contains(PageFlags::USER) {
-                    *entry = None;
-                }
+        for entry in self.regions.iter_mut() {
+            if entry.is_some_and(|r| r.flags.contains(PageFlags::USER)) {
+                *entry = None;
             }
         }
+        self.trim_chunks();
     }
 }
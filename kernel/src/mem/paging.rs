@@ -1,7 +1,10 @@
 // kernel/src/mem/paging.rs
 //
 // 役割:
-// - ページ単位の抽象操作（Map/Unmap）と属性フラグを定義する。
+// - ページ単位の抽象操作（Map/Unmap/Protect）と属性フラグを定義する。
+// - Protect は map したままフレームを変えずに flags だけ替える（Unmap → Map の間に mapping が無くなる瞬間を作らない）。
+// - 複数 page の操作（stack / ELF segment / shm）は MemAction の列（batch）で渡せる。
+//   AddressSpace / arch のどちらも all-or-nothing（途中で落ちたら反映済みの分を戻し、落ちた index を返す）。
 // - arch 依存のページテーブル操作は arch::paging 側で行う。
 // 設計方針:
 // - kernel 側は MemAction を発行するだけにして、unsafe/実処理は arch に閉じ込める。
@@ -24,6 +27,16 @@ bitflags::bitflags! {
     }
 }
 
+/// 1 つの batch に入れられる MemAction の数（arch 側は巻き戻し用の逆操作をこの数だけ stack に持つ）
+pub const MAX_MEM_BATCH: usize = 16;
+
+/// batch の失敗: index 番目の entry が error で拒否された（batch の前の状態に戻してある）
+#[derive(Clone, Copy, Debug)]
+pub struct BatchError<E> {
+    pub index: usize,
+    pub error: E,
+}
+
 /// ページ単位のメモリ操作を表現する抽象イベント。
 #[derive(Clone, Copy, Debug)]
 pub enum MemAction {
@@ -35,6 +48,10 @@ pub enum MemAction {
     Unmap {
         page: VirtPage,
     },
+    Protect {
+        page: VirtPage,
+        new_flags: PageFlags,
+    },
 }
 
 impl MemAction {
@@ -47,4 +64,16 @@ impl MemAction {
     pub const fn unmap(page: VirtPage) -> Self {
         MemAction::Unmap { page }
     }
+
+    /// Protect を作るヘルパ
+    pub const fn protect(page: VirtPage, new_flags: PageFlags) -> Self {
+        MemAction::Protect { page, new_flags }
+    }
+
+    /// 対象の page
+    pub const fn page(&self) -> VirtPage {
+        match *self {
+            MemAction::Map { page, .. } | MemAction::Unmap { page } | MemAction::Protect { page, .. } => page,
+        }
+    }
 }
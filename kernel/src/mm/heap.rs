@@ -0,0 +1,282 @@
+// src/mm/heap.rs
+//
+// 役割:
+// - kernel heap（#[global_allocator]）。alloc の Box / Vec などを kernel 内で使えるようにする。
+// - 範囲は固定（arch::virt_layout の KERNEL_HEAP_BASE から KERNEL_HEAP_SIZE）。
+//   裏のフレームは KernelState::new が user root を作る前に kernel root に map する（arch::paging::map_kernel_heap）。
+//
+// 方式（linked-list allocator）:
+// - 空きブロックをアドレス順の単方向リストで持つ。ブロックの先頭に FreeNode { size, next } を置く。
+// - 確保は first-fit。ブロックの前（align の詰め物）と後ろ（余り）は空きに戻す。
+// - 解放はアドレス順に差し込み、前後の空きと隣接していればつなぐ（断片化を溜めない）。
+// - ブロックの先頭と大きさは BLOCK_ALIGN（= FreeNode の大きさ）の倍数に揃える
+//   * 詰め物 / 余りは 0 か BLOCK_ALIGN 以上になるので、必ず FreeNode を置ける
+//   * 解放時の大きさは Layout から同じ計算で戻せる（ブロックに header を持たない）
+//
+// 不変条件（INV-HEAP-001。check() が検査する）:
+// - 空きリストはアドレス昇順で、隣り合う空きブロックは無い（つなぎ忘れが無い）
+// - 空きブロックは heap の範囲内で、先頭と大きさが BLOCK_ALIGN の倍数
+// - 空きの合計 + 使用中 = heap の大きさ
+//
+// 注意:
+// - spin::Mutex で守るだけなので、割り込みハンドラからは確保しない（再入でデッドロックする）。
+// - init 前の確保は null（alloc のエラーハンドラで panic）。
+// - #[global_allocator] にするのは実機（target_os = "none"）だけ。
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::null_mut;
+use spin::Mutex;
+
+const LOG: crate::logging::Subsystem = crate::logging::Subsystem::Mem;
+
+/// ブロックの先頭と大きさの単位（FreeNode の大きさ）
+const BLOCK_ALIGN: usize = 16;
+
+#[repr(C)]
+struct FreeNode {
+    size: usize,
+    next: *mut FreeNode,
+}
+
+const _: () = assert!(core::mem::size_of::<FreeNode>() <= BLOCK_ALIGN);
+
+#[inline]
+const fn align_up(v: usize, align: usize) -> usize {
+    (v + align - 1) & !(align - 1)
+}
+
+/// heap の統計（Counters Dump / invariant 用）
+#[derive(Clone, Copy, Debug)]
+pub struct HeapStats {
+    pub size: usize,
+    pub used: usize,
+    pub free_blocks: usize,
+    pub largest_free: usize,
+    pub allocs: u64,
+    pub frees: u64,
+    pub failures: u64,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum HeapCheckError {
+    /// 空きリストがアドレス昇順でない / 隣り合う空きブロックがつながっていない
+    FreeListOrder,
+    /// 空きブロックが heap の外か、BLOCK_ALIGN に揃っていない
+    FreeBlockBounds,
+    /// 空きの合計 + 使用中 != heap の大きさ
+    Accounting,
+}
+
+pub struct KernelHeap {
+    start: usize,
+    size: usize,
+    head: *mut FreeNode,
+    used: usize,
+    allocs: u64,
+    frees: u64,
+    failures: u64,
+}
+
+// 生ポインタは heap の中だけを指し、Mutex の内側でしか触らない
+unsafe impl Send for KernelHeap {}
+
+impl KernelHeap {
+    pub const fn empty() -> Self {
+        KernelHeap { start: 0, size: 0, head: null_mut(), used: 0, allocs: 0, frees: 0, failures: 0 }
+    }
+
+    /// [start, start + size) を 1 つの空きブロックにする
+    /// - 範囲は map 済みで、他に使われていないこと
+    pub unsafe fn init(&mut self, start: usize, size: usize) {
+        let s = align_up(start, BLOCK_ALIGN);
+        let e = (start + size) & !(BLOCK_ALIGN - 1);
+
+        self.start = s;
+        self.size = e.saturating_sub(s);
+        self.head = null_mut();
+        self.used = 0;
+        if self.size >= BLOCK_ALIGN {
+            self.insert_free(s, self.size);
+        }
+    }
+
+    pub fn is_initialized(&self) -> bool {
+        self.size != 0
+    }
+
+    /// Layout に対して確保するブロックの大きさ（解放時も同じ計算で戻す）
+    fn block_size(layout: &Layout) -> usize {
+        align_up(layout.size().max(1), BLOCK_ALIGN)
+    }
+
+    /// first-fit で確保する（足りなければ null）
+    pub unsafe fn allocate(&mut self, layout: Layout) -> *mut u8 {
+        let size = Self::block_size(&layout);
+        let align = layout.align().max(BLOCK_ALIGN);
+
+        let mut prev: *mut FreeNode = null_mut();
+        let mut cur = self.head;
+        while !cur.is_null() {
+            let b_start = cur as usize;
+            let b_end = b_start + (*cur).size;
+            let a_start = align_up(b_start, align);
+
+            if let Some(a_end) = a_start.checked_add(size) {
+                if a_end <= b_end {
+                    // ブロックをリストから外し、前の詰め物と後ろの余りを空きに戻す
+                    let next = (*cur).next;
+                    if prev.is_null() {
+                        self.head = next;
+                    } else {
+                        (*prev).next = next;
+                    }
+                    if a_start > b_start {
+                        self.insert_free(b_start, a_start - b_start);
+                    }
+                    if b_end > a_end {
+                        self.insert_free(a_end, b_end - a_end);
+                    }
+
+                    self.used += size;
+                    self.allocs += 1;
+                    return a_start as *mut u8;
+                }
+            }
+
+            prev = cur;
+            cur = (*cur).next;
+        }
+
+        self.failures += 1;
+        null_mut()
+    }
+
+    /// allocate で得たブロックを空きに戻す（layout は確保時と同じもの）
+    pub unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+        let size = Self::block_size(&layout);
+        self.insert_free(ptr as usize, size);
+        self.used -= size;
+        self.frees += 1;
+    }
+
+    /// [addr, addr + size) をアドレス順に差し込み、前後の空きと隣接していればつなぐ
+    unsafe fn insert_free(&mut self, addr: usize, size: usize) {
+        let mut prev: *mut FreeNode = null_mut();
+        let mut next = self.head;
+        while !next.is_null() && (next as usize) < addr {
+            prev = next;
+            next = (*next).next;
+        }
+
+        let node = addr as *mut FreeNode;
+        node.write(FreeNode { size, next });
+        if prev.is_null() {
+            self.head = node;
+        } else {
+            (*prev).next = node;
+        }
+
+        if !next.is_null() && addr + size == next as usize {
+            (*node).size += (*next).size;
+            (*node).next = (*next).next;
+        }
+        if !prev.is_null() && prev as usize + (*prev).size == addr {
+            (*prev).size += (*node).size;
+            (*prev).next = (*node).next;
+        }
+    }
+
+    pub fn stats(&self) -> HeapStats {
+        let mut free_blocks = 0;
+        let mut largest_free = 0;
+        let mut cur = self.head;
+        while !cur.is_null() {
+            // 空きリストは heap の中だけを指す（init / insert_free が作ったもの）
+            let size = unsafe { (*cur).size };
+            free_blocks += 1;
+            largest_free = largest_free.max(size);
+            cur = unsafe { (*cur).next };
+        }
+
+        HeapStats {
+            size: self.size,
+            used: self.used,
+            free_blocks,
+            largest_free,
+            allocs: self.allocs,
+            frees: self.frees,
+            failures: self.failures,
+        }
+    }
+
+    /// INV-HEAP-001 を検査する
+    pub fn check(&self) -> Result<(), HeapCheckError> {
+        let end = self.start + self.size;
+        let mut free_total = 0;
+        let mut prev_end: Option<usize> = None;
+
+        let mut cur = self.head;
+        while !cur.is_null() {
+            let addr = cur as usize;
+            let size = unsafe { (*cur).size };
+
+            if addr < self.start || addr + size > end || addr % BLOCK_ALIGN != 0 || size % BLOCK_ALIGN != 0 || size == 0 {
+                return Err(HeapCheckError::FreeBlockBounds);
+            }
+            // 昇順で、前のブロックの終わりとちょうど接していない（接していればつないでいるはず）
+            if prev_end.is_some_and(|p| addr <= p) {
+                return Err(HeapCheckError::FreeListOrder);
+            }
+
+            free_total += size;
+            prev_end = Some(addr + size);
+            cur = unsafe { (*cur).next };
+        }
+
+        if free_total + self.used != self.size {
+            return Err(HeapCheckError::Accounting);
+        }
+        Ok(())
+    }
+}
+
+pub struct LockedHeap(Mutex<KernelHeap>);
+
+unsafe impl GlobalAlloc for LockedHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.0.lock().allocate(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.0.lock().deallocate(ptr, layout)
+    }
+}
+
+// ホスト（MockArch）では std の allocator のまま（heap は統計と invariant のためだけに init される）
+#[cfg_attr(target_os = "none", global_allocator)]
+static KERNEL_HEAP: LockedHeap = LockedHeap(Mutex::new(KernelHeap::empty()));
+
+/// map 済みの [start, start + size) で heap を始める（2 回目以降は何もしない）
+pub unsafe fn init(start: u64, size: usize) {
+    let mut heap = KERNEL_HEAP.0.lock();
+    if heap.is_initialized() {
+        return;
+    }
+    heap.init(start as usize, size);
+
+    LOG.info("kernel heap: ready");
+    LOG.info_u64("heap_base", start);
+    LOG.info_u64("heap_size", heap.size as u64);
+}
+
+pub fn is_initialized() -> bool {
+    KERNEL_HEAP.0.lock().is_initialized()
+}
+
+pub fn stats() -> HeapStats {
+    KERNEL_HEAP.0.lock().stats()
+}
+
+pub fn check() -> Result<(), HeapCheckError> {
+    KERNEL_HEAP.0.lock().check()
+}
@@ -2,24 +2,194 @@
 //
 // 物理メモリ管理の入り口。
 // - ブートローダから渡された BootInfo::memory_map をもとに、
-//   「Usable」な物理フレームを順番に返すだけの最小アロケータ。
-// - unsafe は BootInfo を受け取ってフレーム列挙器に変換する箇所に局所化する。
+//   「Usable」な物理フレームを bitmap で管理する最小アロケータ。
+// - unsafe は BootInfo を受け取って bitmap に変換する箇所に局所化する。
 // - フォーマル検証の対象になりやすいよう、状態は構造体 + カウンタに閉じ込める。
 //
+// ★bitmap:
+// - 1 bit = 1 フレーム。usable（配ってよい）と allocated（配布中）の 2 枚を持つ。
+// - 二重確保は「allocated の bit が既に立っている」、二重解放は「bit が立っていない」で検出する。
+// - is_frame_allocated() で debug_check_invariants から mapping との突き合わせができる。
+// - 対象は物理アドレス [0, FRAME_BITMAP_FRAMES * 4KiB) のみ（範囲外の usable は使わずに数えるだけ）。
+//
 // 追加の設計意図（性能）:
-// - allocate_frame() を O(1) で動かす（毎回 nth で先頭から走査しない）
-// - 低スペック環境でも “フレーム確保回数が増えるほど遅くなる” 事態を避ける
+// - allocate_frame() は 64 フレーム単位（u64 1 語）で空きを探す
+// - 探索開始位置（hint）を持ち、通常は先頭から走査し直さない
+//
+// ★解放:
+// - deallocate_frame() で bit を落とす。hint を戻すので、解放済みフレームが先に再利用される。
+// - 「まだどこかに map されているか」は見ない（呼び出し側 = KernelState の責務）。
+//
+// ★参照カウント（共有フレーム）:
+// - 複数の AddressSpace / page が同じフレームを map できるように、mapping 数をフレームごとに数える。
+// - 表は固定長（FRAME_REF_TABLE_CAP）。count > 0 のフレームだけ行を持つ。
+// - frame_unref() は数えるだけで解放しない（0 になったら返すのは呼び出し側。arch の unmap 後に返すため）。
+//
+// ★確保失敗の注入（demo/fault_inject.rs）:
+// - inject_alloc_failures(n) の後、allocate_frame() は n 回 None を返す（bitmap は触らない）。
+// - set_fail_mode(FrameFailMode) で「n 回成功した後はずっと失敗」/「seed の xorshift64 で 1/one_in の確率で失敗」にできる。
+// - 本当に尽きたときと同じ None なので、呼び出し側の OOM 経路（halt / SYSCALL_ERR / kill）をそのまま踏む。
+// - allocate_frame_below / reserve_contiguous は対象外（AP の trampoline / DMA ring は起動時に 1 回だけ）。
+//
+// ★物理範囲の予約（MMIO / DMA）:
+// - reserve_range(start, len) で device が使う物理範囲を予約表（MAX_PHYS_RESERVATIONS 行）に載せる。
+//   * 範囲は 4KiB 境界に広げて持つ（start は切り下げ、end は切り上げ）
+//   * 既存の予約と 1 フレームでも重なれば Overlap、配布中のフレームを含めば InUse（どちらも何も変えない）
+//   * 範囲内の usable なフレームは usable の bit を落として配らない（free_frames からも引く）
+//   * usable でない範囲（LAPIC などの MMIO の穴）は表に載せるだけ
+// - reserve_contiguous(pages) は空いている usable な連続フレームを低い方から探して reserve_range する（virtio の DMA ring / buffer）。
+// - 予約は外さない（device は boot から停止まで居る前提）。
+//
+// ★使用量（usage）:
+// - total = bitmap が配ってよいフレームの数（usable のうち予約で外した分を除く）、free = 今配れる数、used = total - free。
+// - page table / kernel stack / heap 用に配ったフレームも used に入る（AddressSpace の resident とは数え方が違う）。
+// - bitmap の範囲外（untracked）は total に入れない。
+
+// kernel heap（#[global_allocator]）
+pub mod heap;
 
 use bootloader::BootInfo;
 use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
 use x86_64::structures::paging::PhysFrame;
 use x86_64::PhysAddr;
 
+/// bitmap で管理するフレーム数（4KiB * 65536 = 256MiB）
+pub const FRAME_BITMAP_FRAMES: usize = 65536;
+
+const FRAME_BITMAP_WORDS: usize = FRAME_BITMAP_FRAMES / 64;
+
+/// 参照カウント表の行数（同時に map されている異なるフレームの上限）
+pub const FRAME_REF_TABLE_CAP: usize = 256;
+
+#[derive(Clone, Copy, Debug)]
+pub enum FrameRefError {
+    /// 確保されていないフレームを参照しようとした
+    NotAllocated,
+    /// 参照カウント表が満杯
+    TableFull,
+    /// 参照が無いのに unref した
+    NotReferenced,
+}
+
+#[derive(Clone, Copy)]
+struct FrameRef {
+    frame: PhysFrame,
+    count: u32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum FrameDeallocError {
+    /// 確保されていない（二重解放）
+    DoubleFree,
+    /// 参照カウントが 0 でない（まだ map されている）
+    StillReferenced,
+    /// usable でない / bitmap の範囲外（このアロケータが配ったフレームではない）
+    NotUsable,
+}
+
+/// 予約表の行数（同時に予約できる物理範囲の数）
+pub const MAX_PHYS_RESERVATIONS: usize = 8;
+
+/// 予約した物理範囲（4KiB 境界）
+#[derive(Clone, Copy, Debug)]
+pub struct PhysReservation {
+    pub start: u64,
+    pub len: u64,
+    /// 予約で配らなくなった usable なフレームの数
+    pub usable_frames: u64,
+}
+
+impl PhysReservation {
+    fn end(&self) -> u64 {
+        self.start + self.len
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum ReserveError {
+    /// len = 0 / 物理アドレスが溢れる
+    Empty,
+    /// 既存の予約と重なる（重なった予約）
+    Overlap(PhysReservation),
+    /// 配布中のフレームを含む
+    InUse,
+    /// 予約表が満杯
+    TableFull,
+    /// 空いている連続フレームが無い（reserve_contiguous）
+    NoContiguous,
+}
+
+impl ReserveError {
+    /// ログ用の名前
+    pub fn name(&self) -> &'static str {
+        match self {
+            ReserveError::Empty => "reserve_empty",
+            ReserveError::Overlap(_) => "reserve_overlap",
+            ReserveError::InUse => "reserve_in_use",
+            ReserveError::TableFull => "reserve_table_full",
+            ReserveError::NoContiguous => "reserve_no_contiguous",
+        }
+    }
+}
+
+/// allocate_frame() を失敗させる mode（inject_alloc_failures の n 回とは別に効く）
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FrameFailMode {
+    Off,
+    /// set_fail_mode の後、成功を n 回返したら以後はずっと None
+    AfterCount { n: u64 },
+    /// 1/one_in の確率で None（seed が同じなら同じ回が失敗する）
+    Random { seed: u64, one_in: u64 },
+}
+
+/// フレーム確保/解放の集計（Counters Dump / wire 用）
+#[derive(Clone, Copy)]
+pub struct FrameStats {
+    pub allocated: u64,
+    pub freed: u64,
+    pub reused: u64,
+    pub double_free: u64,
+    pub free_frames: u64,
+    pub untracked: u64,
+    pub shared: u64,
+    pub injected_failures: u64,
+    /// 予約で配らなくなった usable なフレーム
+    pub reserved: u64,
+}
+
+/// 配れるフレームの使用量（Memory Usage Dump / MemInfo 用）
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct FrameUsage {
+    pub total: u64,
+    pub used: u64,
+    pub free: u64,
+}
+
 /// カーネル側から見える「物理メモリマネージャ」。
 /// - 外部 API はすべて safe にする。
-/// - 内部で BootInfoFrameAllocator を使ってフレームを順番に返す。
+/// - 内部で FrameBitmap を使ってフレームを配る。
+/// - 解放されたフレームは次回以降の確保で優先して再利用される。
+/// - map されているフレームは参照カウントを持つ（共有フレーム）。
 pub struct PhysicalMemoryManager {
-    inner: BootInfoFrameAllocator,
+    inner: FrameBitmap,
+    refs: [Option<FrameRef>; FRAME_REF_TABLE_CAP],
+
+    allocated: u64,
+    freed: u64,
+    reused: u64,
+    double_free: u64,
+
+    // 注入で残っている失敗の回数 / 注入で失敗させた回数
+    fail_next: u64,
+    injected_failures: u64,
+
+    // FrameFailMode と、その進行（AfterCount: 成功させた回数、Random: xorshift64 の状態）
+    fail_mode: FrameFailMode,
+    fail_passed: u64,
+    fail_rng: u64,
+
+    // MMIO / DMA の予約表
+    reservations: [Option<PhysReservation>; MAX_PHYS_RESERVATIONS],
 }
 
 impl PhysicalMemoryManager {
@@ -30,62 +200,314 @@ impl PhysicalMemoryManager {
     /// - 他のコードが BootInfo::memory_map に基づいて同じフレームを直接触らないこと
     ///   （ダブルアロケーションを防ぐため）。
     pub fn new(boot_info: &'static BootInfo) -> Self {
-        let memory_map: &'static MemoryMap = &boot_info.memory_map;
-
         // BootInfo 自体はブートローダ側の責務で正しく構築されている前提とし、
         // その「信頼境界との橋渡し」をこの unsafe に局所化する。
-        let inner = unsafe { BootInfoFrameAllocator::new(memory_map) };
+        unsafe { Self::from_memory_map(&boot_info.memory_map) }
+    }
 
-        PhysicalMemoryManager { inner }
+    /// MemoryMap から直接構築する（ホストの test は自分で組んだ MemoryMap を渡す）。
+    ///
+    /// # Safety
+    /// - FrameBitmap::new と同じ（Usable のフレームを本アロケータ以外から触らないこと）。
+    pub unsafe fn from_memory_map(memory_map: &'static MemoryMap) -> Self {
+        let inner = FrameBitmap::new(memory_map);
+
+        PhysicalMemoryManager {
+            inner,
+            refs: [None; FRAME_REF_TABLE_CAP],
+            allocated: 0,
+            freed: 0,
+            reused: 0,
+            double_free: 0,
+            fail_next: 0,
+            injected_failures: 0,
+            fail_mode: FrameFailMode::Off,
+            fail_passed: 0,
+            fail_rng: 0,
+            reservations: [None; MAX_PHYS_RESERVATIONS],
+        }
     }
 
     /// 次の利用可能な物理フレームを 1 つ確保する。
+    /// - 解放済みフレームがあればそれを先に返す
     /// - 成功: Some(PhysFrame)
-    /// - これ以上 usable なフレームが無い: None
+    /// - これ以上 usable なフレームが無い / 失敗を注入されている: None
     pub fn allocate_frame(&mut self) -> Option<PhysFrame> {
-        self.inner.allocate_frame()
+        if self.fail_next > 0 || self.fail_by_mode() {
+            self.fail_next = self.fail_next.saturating_sub(1);
+            self.injected_failures += 1;
+            return None;
+        }
+        let (frame, reused) = self.inner.allocate()?;
+        self.allocated += 1;
+        if reused {
+            self.reused += 1;
+        }
+        Some(frame)
+    }
+
+    /// この後の allocate_frame() を count 回失敗させる（残っている回数に足す）
+    pub fn inject_alloc_failures(&mut self, count: u64) {
+        self.fail_next = self.fail_next.saturating_add(count);
+    }
+
+    /// FrameFailMode を入れ替え、進行を最初からにする
+    pub fn set_fail_mode(&mut self, mode: FrameFailMode) {
+        self.fail_mode = mode;
+        self.fail_passed = 0;
+        self.fail_rng = match mode {
+            // xorshift は 0 から動かないので定数に置き換える
+            FrameFailMode::Random { seed: 0, .. } => 0x9E37_79B9_7F4A_7C15,
+            FrameFailMode::Random { seed, .. } => seed,
+            _ => 0,
+        };
+    }
+
+    /// FrameFailMode で今回の確保を失敗させるか（進行を 1 つ進める）
+    fn fail_by_mode(&mut self) -> bool {
+        match self.fail_mode {
+            FrameFailMode::Off => false,
+            FrameFailMode::AfterCount { n } => {
+                if self.fail_passed >= n {
+                    return true;
+                }
+                self.fail_passed += 1;
+                false
+            }
+            FrameFailMode::Random { one_in, .. } => {
+                let mut x = self.fail_rng;
+                x ^= x << 13;
+                x ^= x >> 7;
+                x ^= x << 17;
+                self.fail_rng = x;
+                one_in != 0 && x % one_in == 0
+            }
+        }
+    }
+
+    /// 物理アドレス limit 未満のフレームを 1 つ確保する（AP の起動 trampoline のように置き場所に制約があるもの用）
+    /// - hint は使わず低い方から探す（通常の確保の順序は変えない）
+    #[cfg_attr(not(feature = "smp"), allow(dead_code))]
+    pub fn allocate_frame_below(&mut self, limit: u64) -> Option<PhysFrame> {
+        let (frame, reused) = self.inner.allocate_below(limit)?;
+        self.allocated += 1;
+        if reused {
+            self.reused += 1;
+        }
+        Some(frame)
+    }
+
+    /// 物理範囲 [start, start + len) を予約する（4KiB 境界に広げる）。範囲内の usable なフレームは以後配らない
+    /// - 既存の予約と重なる / 配布中のフレームを含む / 表が満杯なら何も変えずに Err
+    /// - 戻り値: 表に載せた予約（広げた後の範囲）
+    pub fn reserve_range(&mut self, start: u64, len: u64) -> Result<PhysReservation, ReserveError> {
+        let end = start.checked_add(len).and_then(|e| e.checked_add(4095)).ok_or(ReserveError::Empty)?;
+        if len == 0 {
+            return Err(ReserveError::Empty);
+        }
+        let start = start & !4095;
+        let end = end & !4095;
+
+        if let Some(r) = self.reservations.iter().flatten().find(|r| start < r.end() && r.start < end) {
+            return Err(ReserveError::Overlap(*r));
+        }
+        if self.inner.any_allocated_in(start, end) {
+            return Err(ReserveError::InUse);
+        }
+        let slot = self.reservations.iter_mut().find(|r| r.is_none()).ok_or(ReserveError::TableFull)?;
+
+        let usable_frames = self.inner.withdraw_usable_in(start, end);
+        let r = PhysReservation { start, len: end - start, usable_frames };
+        *slot = Some(r);
+        Ok(r)
+    }
+
+    /// 空いている usable な連続 pages 枚を低い方から探して予約する（device の DMA ring のように連続が要るもの用）
+    /// - hint は使わない（通常の確保の順序は変えない）。見つからなければ NoContiguous
+    /// - 予約なので返さない（deallocate_frame の対象ではない）
+    #[cfg_attr(not(any(feature = "virtio_net", feature = "virtio_blk")), allow(dead_code))]
+    pub fn reserve_contiguous(&mut self, pages: usize) -> Result<PhysReservation, ReserveError> {
+        if pages == 0 {
+            return Err(ReserveError::Empty);
+        }
+        let start = self.inner.find_free_run(pages).ok_or(ReserveError::NoContiguous)?;
+        self.reserve_range(start as u64 * 4096, pages as u64 * 4096)
+    }
+
+    /// 予約を列挙する（dump 用）
+    pub fn for_each_reservation<F>(&self, mut f: F)
+    where
+        F: FnMut(PhysReservation),
+    {
+        for r in self.reservations.iter().flatten() {
+            f(*r);
+        }
+    }
+
+    /// フレームを返す（bitmap の bit を落とす）。
+    /// - 呼び出し側は「どの mapping / page table からも参照されていない」ことを保証すること。
+    pub fn deallocate_frame(&mut self, frame: PhysFrame) -> Result<(), FrameDeallocError> {
+        if self.frame_ref_count(frame) != 0 {
+            // map されたままのフレームは返さない（二重確保になる）
+            return Err(FrameDeallocError::StillReferenced);
+        }
+        match self.inner.deallocate(frame) {
+            Ok(()) => {
+                self.freed += 1;
+                Ok(())
+            }
+            Err(e) => {
+                if let FrameDeallocError::DoubleFree = e {
+                    self.double_free += 1;
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// このアロケータが配って、まだ返されていないフレームか
+    pub fn is_frame_allocated(&self, frame: PhysFrame) -> bool {
+        self.inner.is_allocated(frame)
+    }
+
+    /// mapping が 1 つ増えた（戻り値は増やした後の参照数）
+    pub fn frame_ref(&mut self, frame: PhysFrame) -> Result<u32, FrameRefError> {
+        if !self.inner.is_allocated(frame) {
+            return Err(FrameRefError::NotAllocated);
+        }
+
+        if let Some(r) = self.refs.iter_mut().flatten().find(|r| r.frame == frame) {
+            r.count += 1;
+            return Ok(r.count);
+        }
+
+        match self.refs.iter_mut().find(|r| r.is_none()) {
+            Some(slot) => {
+                *slot = Some(FrameRef { frame, count: 1 });
+                Ok(1)
+            }
+            None => Err(FrameRefError::TableFull),
+        }
+    }
+
+    /// mapping が 1 つ減った（戻り値は減らした後の参照数。0 でも解放はしない）
+    pub fn frame_unref(&mut self, frame: PhysFrame) -> Result<u32, FrameRefError> {
+        for slot in self.refs.iter_mut() {
+            if let Some(r) = slot {
+                if r.frame == frame {
+                    r.count -= 1;
+                    let left = r.count;
+                    if left == 0 {
+                        *slot = None;
+                    }
+                    return Ok(left);
+                }
+            }
+        }
+        Err(FrameRefError::NotReferenced)
+    }
+
+    pub fn frame_ref_count(&self, frame: PhysFrame) -> u32 {
+        self.refs
+            .iter()
+            .flatten()
+            .find(|r| r.frame == frame)
+            .map_or(0, |r| r.count)
+    }
+
+    /// 参照カウントを持つフレームを列挙する（invariant の突き合わせ用）
+    pub fn for_each_frame_ref<F>(&self, mut f: F)
+    where
+        F: FnMut(PhysFrame, u32),
+    {
+        for r in self.refs.iter().flatten() {
+            f(r.frame, r.count);
+        }
+    }
+
+    pub fn stats(&self) -> FrameStats {
+        FrameStats {
+            allocated: self.allocated,
+            freed: self.freed,
+            reused: self.reused,
+            double_free: self.double_free,
+            free_frames: self.inner.free_frames,
+            untracked: self.inner.untracked_frames,
+            shared: self.refs.iter().flatten().filter(|r| r.count > 1).count() as u64,
+            injected_failures: self.injected_failures,
+            reserved: self.reservations.iter().flatten().map(|r| r.usable_frames).sum(),
+        }
+    }
+
+    /// 配れるフレームの total / used / free
+    pub fn usage(&self) -> FrameUsage {
+        let total = self.inner.total_frames;
+        let free = self.inner.free_frames;
+        FrameUsage { total, used: total - free, free }
     }
 }
 
-/// BootInfo の MemoryMap から usable なフレームを順番に返すアロケータ。
+/// BootInfo の MemoryMap から作る usable / allocated の bitmap。
 ///
-/// - 状態: memory_map（不変入力）と「今どのUsable領域のどこまで配ったか」
+/// - 状態: 2 枚の bitmap と探索開始位置（hint）、high water mark のみ
 /// - これはほぼ純粋ロジックなので、フォーマル検証の対象にしやすい。
-///
-/// 重要: O(n^2) になりがちな nth(skip) を避けるため、
-/// 「次に返す物理アドレス」を保持して前進する。
-struct BootInfoFrameAllocator {
-    memory_map: &'static MemoryMap,
-
-    // 次に見る memory_map のインデックス
-    region_index: usize,
+struct FrameBitmap {
+    // bit = 1: このアロケータが配ってよいフレーム
+    usable: [u64; FRAME_BITMAP_WORDS],
+    // bit = 1: 配布中
+    allocated: [u64; FRAME_BITMAP_WORDS],
 
-    // 現在の Usable region の [cur_addr, cur_end) を保持
-    cur_addr: u64,
-    cur_end: u64,
+    // 次に空きを探し始める語のインデックス
+    hint: usize,
+    // 一度でも配ったことのある最大フレーム番号 + 1（再利用の判定用）
+    high_water: usize,
 
-    // 有効な region を指しているか
-    has_region: bool,
+    // usable の bit が立っているフレームの数（配布中も含む）
+    total_frames: u64,
+    free_frames: u64,
+    untracked_frames: u64,
 }
 
-impl BootInfoFrameAllocator {
-    /// MemoryMap からフレームアロケータを構築する。
+impl FrameBitmap {
+    /// MemoryMap から bitmap を構築する。
     ///
     /// # Safety
     /// - 渡された memory_map がブートローダによって正しく初期化されていること。
     /// - memory_map 上で `Usable` とマークされているフレームが、他で
     ///   すでに利用中でないこと（本アロケータ以外から触らないこと）。
     pub unsafe fn new(memory_map: &'static MemoryMap) -> Self {
-        let mut me = BootInfoFrameAllocator {
-            memory_map,
-            region_index: 0,
-            cur_addr: 0,
-            cur_end: 0,
-            has_region: false,
+        let mut me = FrameBitmap {
+            usable: [0; FRAME_BITMAP_WORDS],
+            allocated: [0; FRAME_BITMAP_WORDS],
+            hint: 0,
+            high_water: 0,
+            total_frames: 0,
+            free_frames: 0,
+            untracked_frames: 0,
         };
 
-        // 最初の usable region をセット
-        me.advance_to_next_usable_region();
+        for region in memory_map.iter() {
+            if region.region_type != MemoryRegionType::Usable {
+                continue;
+            }
+
+            // start は 4KiB に切り上げ、end は [start, end) のまま扱う
+            let mut addr = Self::align_up_4k(region.range.start_addr());
+            let end = region.range.end_addr();
+
+            while addr + 4096 <= end {
+                let idx = (addr / 4096) as usize;
+                if idx < FRAME_BITMAP_FRAMES {
+                    me.usable[idx / 64] |= 1u64 << (idx % 64);
+                    me.total_frames += 1;
+                    me.free_frames += 1;
+                } else {
+                    me.untracked_frames += 1;
+                }
+                addr += 4096;
+            }
+        }
+
         me
     }
 
@@ -96,52 +518,135 @@ impl BootInfoFrameAllocator {
         (x + MASK) & !MASK
     }
 
-    /// 次の usable region を探して、(cur_addr, cur_end) を更新する。
-    fn advance_to_next_usable_region(&mut self) {
-        self.has_region = false;
-
-        while self.region_index < self.memory_map.len() {
-            let region = &self.memory_map[self.region_index];
-            self.region_index += 1;
+    /// フレーム番号を (語, bit) に変換する（範囲外は None）
+    #[inline]
+    fn slot(frame: PhysFrame) -> Option<(usize, u64)> {
+        let idx = (frame.start_address().as_u64() / 4096) as usize;
+        if idx >= FRAME_BITMAP_FRAMES {
+            return None;
+        }
+        Some((idx / 64, 1u64 << (idx % 64)))
+    }
 
-            if region.region_type != MemoryRegionType::Usable {
+    /// 空きフレームを 1 つ配る（戻り値の bool は「一度返されたフレームの再利用か」）
+    ///
+    /// - hint から末尾まで語単位で探し、無ければ先頭から hint まで探す
+    fn allocate(&mut self) -> Option<(PhysFrame, bool)> {
+        for w in (self.hint..FRAME_BITMAP_WORDS).chain(0..self.hint) {
+            let avail = self.usable[w] & !self.allocated[w];
+            if avail == 0 {
                 continue;
             }
 
-            let start = region.range.start_addr();
-            let end = region.range.end_addr();
+            let bit = avail.trailing_zeros() as usize;
+            self.allocated[w] |= 1u64 << bit;
+            self.free_frames -= 1;
+            self.hint = w;
 
-            // start を 4KiB に切り上げ、end は [start, end) のまま扱う
-            let cur = Self::align_up_4k(start);
+            let idx = w * 64 + bit;
+            let reused = idx < self.high_water;
+            if !reused {
+                self.high_water = idx + 1;
+            }
 
-            if cur >= end {
+            let addr = (idx as u64) * 4096;
+            return Some((PhysFrame::containing_address(PhysAddr::new(addr)), reused));
+        }
+        None
+    }
+
+    fn allocate_below(&mut self, limit: u64) -> Option<(PhysFrame, bool)> {
+        let limit_frames = core::cmp::min((limit / 4096) as usize, FRAME_BITMAP_FRAMES);
+        for w in 0..limit_frames.div_ceil(64) {
+            let mut avail = self.usable[w] & !self.allocated[w];
+            let rest = limit_frames - w * 64;
+            if rest < 64 {
+                avail &= (1u64 << rest) - 1;
+            }
+            if avail == 0 {
                 continue;
             }
 
-            self.cur_addr = cur;
-            self.cur_end = end;
-            self.has_region = true;
-            return;
+            let bit = avail.trailing_zeros() as usize;
+            self.allocated[w] |= 1u64 << bit;
+            self.free_frames -= 1;
+
+            let idx = w * 64 + bit;
+            let reused = idx < self.high_water;
+            if !reused {
+                self.high_water = idx + 1;
+            }
+
+            let addr = (idx as u64) * 4096;
+            return Some((PhysFrame::containing_address(PhysAddr::new(addr)), reused));
         }
+        None
     }
 
-    /// 次の usable フレームを 1 つ返す。
-    ///
-    /// - 1回の呼び出しで O(1) を狙う（region を跨ぐときだけスキャンが走る）
-    fn allocate_frame(&mut self) -> Option<PhysFrame> {
-        loop {
-            if !self.has_region {
-                return None;
+    /// usable で未配布のフレームが pages 枚続く先頭の index（低い方から。bitmap は変えない）
+    fn find_free_run(&self, pages: usize) -> Option<usize> {
+        let is_free = |idx: usize| {
+            let (w, b) = (idx / 64, idx % 64);
+            (self.usable[w] & !self.allocated[w]) & (1u64 << b) != 0
+        };
+
+        let mut start = 0;
+        while start + pages <= FRAME_BITMAP_FRAMES {
+            match (start..start + pages).find(|&i| !is_free(i)) {
+                // 埋まっていた frame の次からやり直す
+                Some(busy) => start = busy + 1,
+                None => return Some(start),
             }
+        }
+        None
+    }
 
-            if self.cur_addr + 4096 <= self.cur_end {
-                let addr = self.cur_addr;
-                self.cur_addr += 4096;
-                return Some(PhysFrame::containing_address(PhysAddr::new(addr)));
+    fn deallocate(&mut self, frame: PhysFrame) -> Result<(), FrameDeallocError> {
+        let (w, mask) = Self::slot(frame).ok_or(FrameDeallocError::NotUsable)?;
+        if self.usable[w] & mask == 0 {
+            return Err(FrameDeallocError::NotUsable);
+        }
+        if self.allocated[w] & mask == 0 {
+            return Err(FrameDeallocError::DoubleFree);
+        }
+
+        self.allocated[w] &= !mask;
+        self.free_frames += 1;
+        if w < self.hint {
+            self.hint = w;
+        }
+        Ok(())
+    }
+
+    /// 物理範囲 [start, end) に配布中のフレームがあるか（bitmap の範囲外は見ない）
+    fn any_allocated_in(&self, start: u64, end: u64) -> bool {
+        let first = (start / 4096) as usize;
+        let last = core::cmp::min((end / 4096) as usize, FRAME_BITMAP_FRAMES);
+        (first..last).any(|idx| self.allocated[idx / 64] & (1u64 << (idx % 64)) != 0)
+    }
+
+    /// 物理範囲 [start, end) の usable な bit を落として配らなくする（落とした数）
+    /// - 配布中のフレームが無いこと（any_allocated_in）を呼び出し側が確かめておく
+    fn withdraw_usable_in(&mut self, start: u64, end: u64) -> u64 {
+        let first = (start / 4096) as usize;
+        let last = core::cmp::min((end / 4096) as usize, FRAME_BITMAP_FRAMES);
+        let mut n = 0;
+        for idx in first..last {
+            let (w, mask) = (idx / 64, 1u64 << (idx % 64));
+            if self.usable[w] & mask != 0 {
+                self.usable[w] &= !mask;
+                n += 1;
             }
+        }
+        self.total_frames -= n;
+        self.free_frames -= n;
+        n
+    }
 
-            // region の終端に達したので、次の usable region を探す
-            self.advance_to_next_usable_region();
+    fn is_allocated(&self, frame: PhysFrame) -> bool {
+        match Self::slot(frame) {
+            Some((w, mask)) => self.allocated[w] & mask != 0,
+            None => false,
         }
     }
 }
@@ -4,7 +4,10 @@
 // - 挙動は「緊急出力（ロック無し） → CPU 停止」に固定する。
 // - user CR3 中でも落ちないよう、VGA や logging を使わない。
 // - 二重 panic は即停止（再入で #DF になりやすい）
+// - qemu_exit のときは停止の代わりに QEMU を Failed で終了させる（自動実行が timeout を待たずに済む）
 // - Rust バージョン差に引きずられないよう、message の文字列化は行わない。
+// - location の後に frame pointer を辿った戻りアドレス（最大 BACKTRACE_MAX_FRAMES 個）を出す（arch::unwind）。
+//   読む前に current CR3 で map を確かめるので、user CR3 中でも fault しない。
 // - 重要: loc.file() は low-half 側に置かれる可能性があるため出力しない（再入防止）。
 
 use core::panic::PanicInfo;
@@ -54,6 +57,24 @@ fn emergency_write_hex_u64(v: u64) {
     }
 }
 
+/// frame pointer を辿って "[PANIC] bt #<i> <ret>" を 1 frame 1 行で出す
+#[inline(never)]
+fn emergency_write_backtrace() {
+    let mut frames = [0u64; arch::unwind::BACKTRACE_MAX_FRAMES];
+    let n = arch::unwind::walk_frames(arch::unwind::current_frame_pointer(), &mut frames);
+
+    emergency_write_str("[PANIC] backtrace frames=");
+    emergency_write_hex_u64(n as u64);
+    emergency_write_str("\n");
+    for (i, ret) in frames[..n].iter().enumerate() {
+        emergency_write_str("[PANIC] bt #");
+        emergency_write_hex_u64(i as u64);
+        emergency_write_str(" ");
+        emergency_write_hex_u64(*ret);
+        emergency_write_str("\n");
+    }
+}
+
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
     interrupts::disable();
@@ -80,5 +101,10 @@ fn panic(info: &PanicInfo) -> ! {
         emergency_write_str("[PANIC] location unknown\n");
     }
 
+    emergency_write_backtrace();
+
+    #[cfg(feature = "qemu_exit")]
+    arch::qemu::exit_qemu(arch::qemu::QemuExitCode::Failed);
+    #[cfg(not(feature = "qemu_exit"))]
     arch::halt_loop()
 }
@@ -0,0 +1,277 @@
+// kernel/tests/host_kernel_state.rs
+//
+// 役割:
+// - ホスト（target_os != "none"）で KernelState を MockArch / MockSink の上で回す（feature host_test）。
+//   * 実機と同じ bootstrap → tick の順で、既定の user_program（Task1 = client、Task2 = server）の IPC を往復させる
+//   * bootstrap の後に tick を回さず、host_probe の host_syscall で syscall を 1 つずつ発行して状態遷移を見る
+//     （scheduler の切替、IPC の send / recv / reply、reply cap、cap transfer、task handle、TaskSuspend / TaskResume）
+//   * 各段で全体の invariant 検査（host_check_invariants）を回し、違反が 0 のこと / 壊した状態で違反を拾うことを見る
+//   * ページテーブル / CR3 / stack 切替は MockArch が数えるだけ（arch/ops.rs）
+//
+// 走らせ方:
+// - scripts/host-test.sh（ルートの .cargo/config.toml の custom target / build-std を避けてホスト target で cargo test）
+//   * 既定の feature と stress_ipc（endpoint id が 64 を超える）の 2 回回す
+//
+// 方針:
+// - MockArch / MockSink / kernel heap は process 全体で 1 つなので、test は LOCK で 1 本ずつ流す
+// - KernelState は大きいので、stack を広げた thread の上で作る（実機は boot stack に置いている）
+
+use std::sync::Mutex;
+
+use bootloader::bootinfo::{FrameRange, MemoryMap, MemoryRegion, MemoryRegionType};
+use formal_os::arch::ops::MockArch;
+use formal_os::kernel::host_probe::*;
+use formal_os::kernel::{BlockedReason, EndpointId, KernelState, Syscall, TaskState};
+use formal_os::mem::addr::VirtPage;
+use formal_os::mm::PhysicalMemoryManager;
+
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// usable な物理範囲（1MiB..17MiB。MockArch の boot root の frame は含めない）
+const USABLE_START: u64 = 0x10_0000;
+const USABLE_END: u64 = 0x110_0000;
+
+const TICKS: usize = 200;
+const STACK_SIZE: usize = 64 * 1024 * 1024;
+
+fn phys_mem() -> PhysicalMemoryManager {
+    let mut map = MemoryMap::new();
+    map.add_region(MemoryRegion {
+        range: FrameRange::new(0, USABLE_START),
+        region_type: MemoryRegionType::Reserved,
+    });
+    map.add_region(MemoryRegion {
+        range: FrameRange::new(USABLE_START, USABLE_END),
+        region_type: MemoryRegionType::Usable,
+    });
+    let map: &'static MemoryMap = Box::leak(Box::new(map));
+    // ホストでは frame に触らない（MockArch）ので、usable な範囲を他と取り合うことは無い
+    unsafe { PhysicalMemoryManager::from_memory_map(map) }
+}
+
+/// bootstrap して ticks 回 tick した KernelState の上で f を呼ぶ
+fn run<R: Send + 'static>(ticks: usize, f: fn(&mut KernelState) -> R) -> R {
+    let _guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    MockArch::reset();
+    std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || {
+            let mut ks = KernelState::with_phys_mem(phys_mem());
+            ks.bootstrap();
+            for _ in 0..ticks {
+                if ks.should_halt() {
+                    break;
+                }
+                ks.tick();
+            }
+            f(&mut ks)
+        })
+        .unwrap()
+        .join()
+        .unwrap()
+}
+
+/// bootstrap しただけの KernelState（user program はまだ 1 step も走っていない）の上で f を呼ぶ
+fn with_booted(f: fn(&mut KernelState)) {
+    run(0, f)
+}
+
+fn ret(ks: &KernelState, idx: usize) -> Option<u64> {
+    ks.host_task(idx).last_syscall_ret
+}
+
+fn state(ks: &KernelState, idx: usize) -> TaskState {
+    ks.host_task(idx).state
+}
+
+/// 起こされた task（scheduler がそのまま選んでいれば Running）
+fn runnable(ks: &KernelState, idx: usize) -> bool {
+    matches!(state(ks, idx), TaskState::Ready | TaskState::Running)
+}
+
+fn send(ep_cap: usize, word: u64) -> Syscall {
+    Syscall::IpcSend { cap: CapIndex(ep_cap), msg: IpcMessage::word(word), timeout: None }
+}
+
+fn recv(ep_cap: usize) -> Syscall {
+    Syscall::IpcRecv { cap: CapIndex(ep_cap) }
+}
+
+#[test]
+fn ipc_round_trips_under_mock_arch() {
+    let (counters, passed) = run(TICKS, |ks| {
+        let c = &ks.counters;
+        (
+            (c.sched_switches, c.ipc_send_fast + c.ipc_send_slow, c.ipc_reply_delivered, c.invariant_violations),
+            ks.evaluate_verdict().passed(),
+        )
+    });
+    let (switches, sends, replies, violations) = counters;
+
+    assert!(switches > 0, "scheduler never switched");
+    assert!(sends > 0, "client never sent");
+    assert!(replies > 0, "server never replied");
+    assert_eq!(violations, 0);
+    assert!(passed);
+
+    // user root の作成 / 切替は MockArch を通っている
+    let stats = MockArch::stats();
+    assert!(stats.address_space_switches > 0);
+}
+
+#[test]
+fn same_script_gives_same_state_hash() {
+    let a = run(TICKS, |ks| ks.state_hash());
+    let b = run(TICKS, |ks| ks.state_hash());
+    assert_eq!(a, b);
+}
+
+#[test]
+fn scheduler_runs_every_user_task_with_one_running() {
+    let (runtimes, running, current, violations) = run(TICKS, |ks| {
+        let runtimes = [TASK1_INDEX, TASK2_INDEX].map(|i| ks.host_task(i).runtime_ticks);
+        let running = (0..=IDLE_TASK_INDEX).filter(|&i| state(ks, i) == TaskState::Running).count();
+        (runtimes, running, ks.host_current_task(), ks.host_check_invariants())
+    });
+
+    assert!(runtimes.iter().all(|&r| r > 0), "a user task never ran: {runtimes:?}");
+    assert_eq!(running, 1);
+    assert!(current <= IDLE_TASK_INDEX);
+    assert_eq!(violations, 0);
+}
+
+#[test]
+fn ipc_send_recv_reply_through_syscalls() {
+    with_booted(|ks| {
+        assert_eq!(ks.host_check_invariants(), 0);
+
+        // server が recv で待つ
+        assert!(ks.host_syscall(TASK2_INDEX, recv(0)));
+        assert!(state(ks, TASK2_INDEX) == TaskState::Blocked);
+        assert_eq!(ks.host_endpoint(0).recv_waiter, Some(TASK2_INDEX));
+        assert_eq!(ks.host_check_invariants(), 0);
+
+        // client の send は fastpath で届き、client は reply 待ち
+        assert!(ks.host_syscall(TASK1_INDEX, send(0, 7)));
+        assert!(runnable(ks, TASK2_INDEX));
+        let server = ks.host_task(TASK2_INDEX);
+        assert_eq!(server.last_msg.map(|m| m.mr0()), Some(7));
+        let reply_cap = server.last_reply_cap.expect("call must carry a reply cap");
+        assert!(matches!(ks.host_task(TASK1_INDEX).blocked_reason, Some(BlockedReason::IpcReply { .. })));
+        assert_eq!(ks.host_check_invariants(), 0);
+
+        // reply で client が起きる（成功時は last_syscall_ret を書かない。reply_cap.rs）
+        assert!(ks.host_syscall(TASK2_INDEX, Syscall::IpcReply { reply_cap, msg: IpcMessage::word(8) }));
+        assert_eq!(ret(ks, TASK2_INDEX), None);
+        assert!(runnable(ks, TASK1_INDEX));
+        assert_eq!(ks.host_task(TASK1_INDEX).last_reply.map(|m| m.mr0()), Some(8));
+        assert_eq!(ks.host_check_invariants(), 0);
+
+        // reply cap は一度きり
+        assert!(ks.host_syscall(TASK2_INDEX, Syscall::IpcReply { reply_cap, msg: IpcMessage::word(9) }));
+        assert_eq!(ret(ks, TASK2_INDEX), Some(SYSCALL_ERR_BAD_REPLY_CAP));
+        assert_eq!(ks.host_task(TASK1_INDEX).last_reply.map(|m| m.mr0()), Some(8));
+        assert_eq!(ks.host_check_invariants(), 0);
+    });
+}
+
+#[test]
+fn invariant_check_catches_a_ready_recv_waiter() {
+    with_booted(|ks| {
+        assert!(ks.host_syscall(TASK2_INDEX, recv(0)));
+        assert_eq!(ks.host_check_invariants(), 0);
+
+        // recv_waiter に登録されたまま Ready にする（ready_queue にも居ない）
+        ks.host_force_state(TASK2_INDEX, TaskState::Ready);
+        assert!(ks.host_check_invariants() > 0);
+    });
+}
+
+#[test]
+fn cap_transfer_carries_a_shm_handle() {
+    with_booted(|ks| {
+        assert!(ks.host_syscall(TASK1_INDEX, Syscall::ShmCreate { pages: 1 }));
+        let created = ret(ks, TASK1_INDEX).unwrap();
+        assert_eq!(created & SHM_CREATE_OK_TAG_MASK, SHM_CREATE_OK_TAG);
+        let shm_cap = CapIndex((created & !SHM_CREATE_OK_TAG_MASK) as usize);
+
+        assert!(ks.host_syscall(TASK2_INDEX, recv(0)));
+        let msg = IpcMessage::word(1).with_cap(shm_cap);
+        assert!(ks.host_syscall(TASK1_INDEX, Syscall::IpcSend { cap: CapIndex(0), msg, timeout: None }));
+
+        // endpoint 以外の handle も届き、受け取った側の slot で map できる
+        let got = ks.host_task(TASK2_INDEX).last_msg.and_then(|m| m.cap_transfer()).expect("shm handle dropped");
+        assert!(matches!(ks.host_cap(TASK2_INDEX, got).map(|s| s.object), Some(KernelObject::Shm(_))));
+        assert!(ks.host_syscall(TASK2_INDEX, Syscall::ShmMap { cap: got, page: VirtPage::from_index(0x40) }));
+        assert_eq!(ret(ks, TASK2_INDEX), Some(SYSCALL_OK));
+        assert_eq!(ks.host_check_invariants(), 0);
+    });
+}
+
+#[test]
+fn task_handles_are_type_and_rights_checked() {
+    with_booted(|ks| {
+        // endpoint の handle で TaskSuspend
+        assert!(ks.host_syscall(TASK1_INDEX, Syscall::TaskSuspend { cap: CapIndex(0) }));
+        assert_eq!(ret(ks, TASK1_INDEX), Some(SYSCALL_ERR_WRONG_TYPE));
+
+        // 親でも kernel でもない task の handle は Info だけ（Control が無い）
+        assert!(ks.host_syscall(TASK1_INDEX, Syscall::TaskSuspend { cap: task_cap(TASK2_INDEX) }));
+        assert_eq!(ret(ks, TASK1_INDEX), Some(SYSCALL_ERR_FORBIDDEN));
+        assert!(runnable(ks, TASK2_INDEX));
+
+        // kernel の task は Control を持つ
+        assert!(ks.host_syscall(TASK0_INDEX, Syscall::TaskSuspend { cap: task_cap(TASK2_INDEX) }));
+        assert_eq!(ret(ks, TASK0_INDEX), Some(SYSCALL_OK));
+        assert!(state(ks, TASK2_INDEX) == TaskState::Suspended);
+        assert_eq!(ks.host_check_invariants(), 0);
+
+        assert!(ks.host_syscall(TASK0_INDEX, Syscall::TaskResume { cap: task_cap(TASK2_INDEX) }));
+        assert_eq!(ret(ks, TASK0_INDEX), Some(SYSCALL_OK));
+        assert!(runnable(ks, TASK2_INDEX));
+        assert_eq!(ks.host_check_invariants(), 0);
+    });
+}
+
+#[test]
+fn suspended_receiver_on_the_last_static_endpoint_gets_the_msg_on_resume() {
+    with_booted(|ks| {
+        // stress_ipc では 255（ep_mask の 64 bit に収まらない番号）
+        let ep = STATIC_ENDPOINTS - 1;
+        assert!(ks.host_syscall(TASK2_INDEX, recv(ep)));
+        assert!(ks.host_syscall(TASK0_INDEX, Syscall::TaskSuspend { cap: task_cap(TASK2_INDEX) }));
+        assert!(state(ks, TASK2_INDEX) == TaskState::Suspended);
+        assert_eq!(ks.host_endpoint(ep).recv_waiter, Some(TASK2_INDEX));
+        assert_eq!(ks.host_check_invariants(), 0);
+
+        // 止まっている間の send は届けず、send_queue で待つ
+        assert!(ks.host_syscall(TASK1_INDEX, send(ep, 42)));
+        assert!(ks.host_task(TASK2_INDEX).last_msg.is_none());
+        assert_eq!(ks.host_endpoint(ep).send_queue.len(), 1);
+        assert_eq!(ks.host_check_invariants(), 0);
+
+        // resume で同じ endpoint から受け取る
+        assert!(ks.host_syscall(TASK0_INDEX, Syscall::TaskResume { cap: task_cap(TASK2_INDEX) }));
+        assert_eq!(ret(ks, TASK0_INDEX), Some(SYSCALL_OK));
+        assert!(runnable(ks, TASK2_INDEX));
+        let server = ks.host_task(TASK2_INDEX);
+        assert_eq!(server.last_msg.map(|m| m.mr0()), Some(42));
+        assert_eq!(server.last_recv_ep.map(|e| e.0), Some(ep));
+        assert_eq!(ks.host_endpoint(ep).recv_waiter, None);
+        assert_eq!(ks.host_check_invariants(), 0);
+    });
+}
+
+#[test]
+fn invariant_check_catches_an_unregistered_suspended_receiver() {
+    with_booted(|ks| {
+        let ep = STATIC_ENDPOINTS - 1;
+        assert!(ks.host_syscall(TASK2_INDEX, recv(ep)));
+        assert!(ks.host_syscall(TASK0_INDEX, Syscall::TaskSuspend { cap: task_cap(TASK2_INDEX) }));
+        assert_eq!(ks.host_check_invariants(), 0);
+
+        // 登録は last endpoint のまま、待ちだけを ep 0 に向ける（resume は ep 0 しか見ない）
+        ks.host_force_blocked_reason(TASK2_INDEX, Some(BlockedReason::IpcRecv { ep: EndpointId(0) }));
+        assert!(ks.host_check_invariants() > 0);
+    });
+}
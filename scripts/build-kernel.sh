@@ -7,6 +7,12 @@ TARGET_JSON="x86_64-formal-os-local.json"
 
 FEATURES="${FEATURES:-}"
 
+# CMDLINE="..." を kernel の command line として埋め込む（kernel/boot_params.rs）
+if [ -n "${CMDLINE:-}" ]; then
+  echo "[*] cmdline: ${CMDLINE}"
+  export FORMAL_OS_CMDLINE="${CMDLINE}"
+fi
+
 echo "[*] building kernel bootimage (target = ${TARGET_JSON})..."
 if [ -n "${FEATURES}" ]; then
   echo "[*] features: ${FEATURES}"
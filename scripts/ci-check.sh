@@ -139,6 +139,16 @@ run_qemu_assert() {
   local rc=$?
   set -e
 
+  # qemu_exit の verdict（33 = PASS / 35 = FAIL）
+  if [[ "${rc}" -eq 35 ]]; then
+    echo "[ci] ERROR: kernel verdict FAIL (or panic)"
+    grep -nE -A1 "verdict: FAIL|PANIC" "${log_file}" | head -n 20
+    exit 1
+  fi
+  if [[ "${rc}" -eq 33 ]]; then
+    rc=0
+  fi
+
   # timeout 終了は許容（124）
   if [[ "${rc}" -ne 0 && "${rc}" -ne 124 && "${rc}" -ne 137 ]]; then
     echo "[ci] ERROR: qemu returned non-zero (rc=${rc})"
@@ -172,6 +182,13 @@ run_qemu_assert() {
     exit 1
   fi
 
+  # selftest の判定（abitest が動いたビルドだけ意味がある）
+  if grep -qE "abitest: FAIL" "${log_file}"; then
+    echo "[ci] ERROR: abitest failure detected"
+    grep -nE -A1 "abitest: FAIL" "${log_file}" | head -n 80
+    exit 1
+  fi
+
   # dump は timeout で切れることがあるので WARN 扱い
   if ! grep -qE "=== KernelState Event Log Dump ===" "${log_file}"; then
     echo "[ci] WARN: event dump not found (may be cut by timeout) - OK"
@@ -189,6 +206,8 @@ build_only "endpoint_close_test" "endpoint_close_test"
 build_only "dead_partner_test" "dead_partner_test"
 build_only "evil_double_map" "evil_double_map"
 build_only "evil_unmap_not_mapped" "evil_unmap_not_mapped"
+build_only "selftest" "selftest"
+build_only "synthetic_tick" "synthetic_tick"
 
 echo "[ci] 2) runtime smoke (slow but high value)"
 if [[ "${CI_RUN}" == "1" ]]; then
@@ -199,6 +218,7 @@ if [[ "${CI_RUN}" == "1" ]]; then
   run_qemu_assert "endpoint_close_test" "run_endpoint_close_test" 12
   run_qemu_assert "evil_unmap_not_mapped" "run_evil_unmap_not_mapped" 12
   run_qemu_assert "evil_double_map" "run_evil_double_map" 12
+  run_qemu_assert "selftest" "run_selftest" 12
 else
   echo "[ci] runtime smoke skipped (CI_RUN=0)"
 fi
@@ -0,0 +1,32 @@
+#!/usr/bin/env bash
+# scripts/host-test.sh
+#
+# kernel の lib（formal_os）をホスト target で cargo test する（kernel/tests/、MockArch / MockSink）。
+# - feature host_test（MockArch / MockSink / kernel::host_probe）を立てる。ホストの build はこれが無いと通らない。
+# - 既定の feature と stress_ipc（STATIC_ENDPOINTS = 256。endpoint id が 64 を超える）の 2 回回す。
+# - ルートの .cargo/config.toml（custom target / build-std）は cwd から探されるので、repo の外から呼ぶ。
+# - kernel は nightly の feature を使うので、toolchain は rust-toolchain.toml の channel を明示する。
+#
+# 例:
+#   scripts/host-test.sh
+#   scripts/host-test.sh -- --nocapture
+set -euo pipefail
+
+ROOT="$(cd "$(dirname "$0")/.." && pwd)"
+TOOLCHAIN="$(sed -n 's/^channel = "\(.*\)"$/\1/p' "${ROOT}/rust-toolchain.toml")"
+HOST="$(rustc +"${TOOLCHAIN}" -vV | sed -n 's/^host: //p')"
+
+run() {
+  local features="$1"
+  shift
+  cargo +"${TOOLCHAIN}" test \
+    --manifest-path "${ROOT}/Cargo.toml" \
+    -p kernel \
+    --target "${HOST}" \
+    --features "${features}" \
+    "$@"
+}
+
+cd "${TMPDIR:-/tmp}"
+run host_test "$@"
+run host_test,stress_ipc "$@"
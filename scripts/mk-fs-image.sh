@@ -0,0 +1,44 @@
+#!/usr/bin/env bash
+set -euo pipefail
+
+# virtio_blk の disk image（ustar 形式の TAR）を作る（kernel/fs.rs が読む形式）
+#
+# 例:
+#   mkdir -p fsroot && echo "hello from disk" > fsroot/hello.txt
+#   ./scripts/mk-fs-image.sh fsroot logs/fs.img
+#   DISK=logs/fs.img FEATURES="fs_demo" ./scripts/run-qemu-debug.sh
+#
+# - dir 直下の通常 file だけを入れる（kernel の fs は directory を持たない。名前は 32 byte まで）
+# - ring3_tasks 用の task1.bin / task2.bin は code page に置かれる生の機械語（1 page まで）
+
+if [[ $# -ne 2 ]]; then
+    echo "usage: $0 <dir> <image>"
+    exit 1
+fi
+
+SRC_DIR="$1"
+IMAGE="$2"
+
+if [[ ! -d "${SRC_DIR}" ]]; then
+    echo "[-] not a directory: ${SRC_DIR}"
+    exit 1
+fi
+
+mapfile -t FILES < <(cd "${SRC_DIR}" && find . -maxdepth 1 -type f -printf '%f\n' | sort)
+if [[ ${#FILES[@]} -eq 0 ]]; then
+    echo "[-] no files in ${SRC_DIR}"
+    exit 1
+fi
+
+for f in "${FILES[@]}"; do
+    if [[ ${#f} -gt 32 ]]; then
+        echo "[!] name longer than 32 bytes (the kernel skips it): ${f}"
+    fi
+done
+
+mkdir -p "$(dirname "${IMAGE}")"
+# owner / mtime を固定して、同じ中身なら同じ image にする
+tar --format=ustar --owner=0 --group=0 --numeric-owner --mtime=@0 \
+    -C "${SRC_DIR}" -cf "${IMAGE}" "${FILES[@]}"
+
+echo "[*] wrote ${IMAGE} (${#FILES[@]} files, $(stat -c %s "${IMAGE}") bytes)"
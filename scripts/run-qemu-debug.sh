@@ -13,6 +13,12 @@ BOOTIMAGE="${TARGET_DIR}/bootimage-kernel.bin"
 #   FEATURES="evil_double_map evil_ipc" ./scripts/run-qemu-debug.sh
 FEATURES="${FEATURES:-}"
 
+# CMDLINE="max_ticks=300 log=info": kernel の command line として埋め込む（kernel/boot_params.rs。FORMAL_OS_CMDLINE）
+if [[ -n "${CMDLINE:-}" ]]; then
+    echo "[*] cmdline: ${CMDLINE}"
+    export FORMAL_OS_CMDLINE="${CMDLINE}"
+fi
+
 echo "[*] building kernel bootimage (target = ${TARGET_JSON})..."
 
 if [[ -n "${FEATURES}" ]]; then
@@ -36,9 +42,46 @@ LOG_FILE="${LOG_DIR}/qemu_${TS}.log"
 echo "[*] launching QEMU with ${BOOTIMAGE}..."
 echo "[*] logging output to ${LOG_FILE}"
 
+# VIRTIO_TRACE=1: legacy virtio console を足し、virtio_console feature の trace を別ファイルに出す
+EXTRA_ARGS=()
+if [[ "${VIRTIO_TRACE:-0}" == "1" ]]; then
+    TRACE_FILE="${LOG_DIR}/trace_${TS}.log"
+    echo "[*] virtio console trace to ${TRACE_FILE}"
+    EXTRA_ARGS+=(
+      -device virtio-serial-pci,disable-modern=on
+      -chardev file,id=trace,path="${TRACE_FILE}"
+      -device virtconsole,chardev=trace
+    )
+fi
+
+# NET=1: user networking（slirp）の legacy virtio-net を足す（virtio_net feature の net_loopback demo 用）
+if [[ "${NET:-0}" == "1" ]]; then
+    echo "[*] virtio-net on user networking (guest 10.0.2.15, gateway 10.0.2.2)"
+    EXTRA_ARGS+=(
+      -netdev user,id=n0
+      -device virtio-net-pci,netdev=n0,disable-modern=on
+    )
+fi
+
+# DISK=<image>: legacy virtio-blk として raw image を読み取り専用でつなぐ（virtio_blk feature の fs 用。scripts/mk-fs-image.sh で作る）
+if [[ -n "${DISK:-}" ]]; then
+    if [[ ! -f "${DISK}" ]]; then
+        echo "[-] disk image not found: ${DISK}"
+        exit 1
+    fi
+    echo "[*] virtio-blk disk: ${DISK}"
+    EXTRA_ARGS+=(
+      -drive file="${DISK}",if=none,id=d0,format=raw,readonly=on
+      -device virtio-blk-pci,drive=d0,disable-modern=on
+    )
+fi
+
 # QEMU のシリアル出力をコンソールに表示しつつ、ログファイルにも保存
+# - isa-debug-exit: qemu_exit feature の kernel が verdict で QEMU を止める（PASS = 33 / FAIL = 35 で終了）
 qemu-system-x86_64 \
   -drive format=raw,file="${BOOTIMAGE}" \
   -m 512M \
   -serial stdio \
+  -device isa-debug-exit,iobase=0xf4,iosize=0x04 \
+  ${EXTRA_ARGS[@]+"${EXTRA_ARGS[@]}"} \
   | tee "${LOG_FILE}"
@@ -0,0 +1,19 @@
+#!/usr/bin/env bash
+# scripts/specxref.sh
+#
+# kernel ELF の formal_spec セクション（#[spec] の対応表）を docs/spec/clauses.txt と突き合わせる。
+# - tools/specxref はホスト側ツールなので、traceviz と同じく stable + host target でビルドする。
+#
+# 例:
+#   scripts/specxref.sh target/x86_64-formal-os-local/debug/kernel
+#   scripts/specxref.sh --strict target/x86_64-formal-os-local/release/kernel
+set -euo pipefail
+
+ROOT="$(cd "$(dirname "$0")/.." && pwd)"
+HOST="$(rustc +stable -vV | sed -n 's/^host: //p')"
+
+cd "${ROOT}"
+cargo +stable run --quiet \
+  --manifest-path "${ROOT}/tools/specxref/Cargo.toml" \
+  --target "${HOST}" \
+  -- "$@"
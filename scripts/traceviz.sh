@@ -0,0 +1,19 @@
+#!/usr/bin/env bash
+# scripts/traceviz.sh
+#
+# Event Log Dump（serial ログ）を sequence diagram に変換する。
+# - tools/traceviz はホスト側ツールなので、ルートの custom target / build-std を避けて
+#   stable + host target でビルドする（stable では [unstable] 設定は無視される）。
+#
+# 例:
+#   scripts/traceviz.sh logs/qemu_XXXX.log > trace.mmd
+#   scripts/traceviz.sh --format plantuml logs/qemu_XXXX.log > trace.puml
+set -euo pipefail
+
+ROOT="$(cd "$(dirname "$0")/.." && pwd)"
+HOST="$(rustc +stable -vV | sed -n 's/^host: //p')"
+
+cargo +stable run --quiet \
+  --manifest-path "${ROOT}/tools/traceviz/Cargo.toml" \
+  --target "${HOST}" \
+  -- "$@"
@@ -0,0 +1,128 @@
+// spec_macros/src/lib.rs
+//
+// 役割:
+// - `#[spec("INV-IPC-007")]` 属性マクロ。
+//   遷移関数 / invariant チェックに「どの仕様条項に対応するか」を付け、
+//   その対応を ELF の専用セクション（formal_spec）に焼き込む。
+// - ホスト側の tools/specxref がセクションを読み、条項の被覆を報告する。
+//
+// レコード形式（1 行 = 1 対応、UTF-8）:
+//   "<spec_id>\t<fn_name>\t<module_path>\n"
+//
+// 設計方針:
+// - 依存なし（proc_macro の TokenStream を直接組み立てる）
+// - 関数本体の先頭に `#[used] #[link_section = "formal_spec"] static` を差し込むだけで、
+//   関数の挙動・シグネチャは変えない
+// - セクション名は C 識別子にする（リンカが __start_formal_spec / __stop_formal_spec を定義できる）
+//
+// やらないこと:
+// - 条項 ID が仕様に存在するかの検査（それは tools/specxref の役割）
+
+use proc_macro::{Delimiter, Group, TokenStream, TokenTree};
+
+const SECTION: &str = "formal_spec";
+
+fn compile_error(msg: &str) -> TokenStream {
+    format!("compile_error!({:?});", msg).parse().unwrap()
+}
+
+/// 属性引数から条項 ID を取り出す（"ID" をカンマ区切りで 1 個以上）
+fn parse_ids(attr: TokenStream) -> Result<Vec<String>, String> {
+    let mut ids = Vec::new();
+
+    for tt in attr {
+        match tt {
+            TokenTree::Literal(lit) => {
+                let s = lit.to_string();
+                let id = s
+                    .strip_prefix('"')
+                    .and_then(|x| x.strip_suffix('"'))
+                    .ok_or_else(|| format!("#[spec] expects string literals, got {s}"))?;
+                let ok = !id.is_empty()
+                    && id.bytes().all(|b| b.is_ascii_uppercase() || b.is_ascii_digit() || b == b'-' || b == b'_');
+                if !ok {
+                    return Err(format!("#[spec] id must be [A-Z0-9_-]+, got {id:?}"));
+                }
+                ids.push(id.to_string());
+            }
+            TokenTree::Punct(p) if p.as_char() == ',' => {}
+            other => return Err(format!("#[spec] unexpected token: {other}")),
+        }
+    }
+
+    if ids.is_empty() {
+        return Err("#[spec] needs at least one id, e.g. #[spec(\"INV-IPC-001\")]".to_string());
+    }
+    Ok(ids)
+}
+
+/// `fn` の直後の識別子を関数名とする
+fn fn_name(tokens: &[TokenTree]) -> Option<String> {
+    let mut it = tokens.iter();
+    while let Some(tt) = it.next() {
+        if let TokenTree::Ident(i) = tt {
+            if i.to_string() == "fn" {
+                if let Some(TokenTree::Ident(name)) = it.next() {
+                    return Some(name.to_string());
+                }
+            }
+        }
+    }
+    None
+}
+
+/// 1 条項ぶんのレコード static（ブロックで囲んで名前衝突を避ける）
+fn record_block(id: &str, name: &str) -> String {
+    format!(
+        r#"{{
+            const __FORMAL_SPEC_REC: &str = concat!({id:?}, "\t", {name:?}, "\t", module_path!(), "\n");
+            #[used]
+            #[link_section = {SECTION:?}]
+            static __FORMAL_SPEC: [u8; __FORMAL_SPEC_REC.len()] = {{
+                let b = __FORMAL_SPEC_REC.as_bytes();
+                let mut out = [0u8; __FORMAL_SPEC_REC.len()];
+                let mut i = 0;
+                while i < b.len() {{
+                    out[i] = b[i];
+                    i += 1;
+                }}
+                out
+            }};
+        }}"#
+    )
+}
+
+#[proc_macro_attribute]
+pub fn spec(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let ids = match parse_ids(attr) {
+        Ok(v) => v,
+        Err(e) => return compile_error(&e),
+    };
+
+    let mut tokens: Vec<TokenTree> = item.into_iter().collect();
+
+    let name = match fn_name(&tokens) {
+        Some(n) => n,
+        None => return compile_error("#[spec] can only be applied to fn items"),
+    };
+
+    // 関数本体（最後の {...}）の先頭にレコードを差し込む
+    let body = match tokens.pop() {
+        Some(TokenTree::Group(g)) if g.delimiter() == Delimiter::Brace => g,
+        _ => return compile_error("#[spec] requires a fn with a body"),
+    };
+
+    let mut records = String::new();
+    for id in &ids {
+        records.push_str(&record_block(id, &name));
+    }
+
+    let mut new_body: TokenStream = records.parse().unwrap();
+    new_body.extend(body.stream());
+
+    let mut g = Group::new(Delimiter::Brace, new_body);
+    g.set_span(body.span());
+    tokens.push(TokenTree::Group(g));
+
+    tokens.into_iter().collect()
+}
@@ -0,0 +1,83 @@
+// tools/specxref/src/elf.rs
+//
+// 役割:
+// - ELF64 little-endian のセクションヘッダだけを読む最小パーサ（依存なし）。
+//
+// やらないこと:
+// - ELF32 / big-endian / プログラムヘッダ / シンボル表
+
+fn u16_at(b: &[u8], off: usize) -> Result<u16, String> {
+    b.get(off..off + 2)
+        .map(|s| u16::from_le_bytes([s[0], s[1]]))
+        .ok_or_else(|| format!("truncated at {off:#x}"))
+}
+
+fn u32_at(b: &[u8], off: usize) -> Result<u32, String> {
+    b.get(off..off + 4)
+        .map(|s| u32::from_le_bytes([s[0], s[1], s[2], s[3]]))
+        .ok_or_else(|| format!("truncated at {off:#x}"))
+}
+
+fn u64_at(b: &[u8], off: usize) -> Result<u64, String> {
+    b.get(off..off + 8)
+        .map(|s| u64::from_le_bytes([s[0], s[1], s[2], s[3], s[4], s[5], s[6], s[7]]))
+        .ok_or_else(|| format!("truncated at {off:#x}"))
+}
+
+struct Section {
+    name_off: u32,
+    offset: u64,
+    size: u64,
+}
+
+fn section_at(b: &[u8], shoff: usize, shentsize: usize, i: usize) -> Result<Section, String> {
+    let base = shoff + i * shentsize;
+    Ok(Section {
+        name_off: u32_at(b, base)?,
+        offset: u64_at(b, base + 24)?,
+        size: u64_at(b, base + 32)?,
+    })
+}
+
+fn cstr_at(b: &[u8], off: usize) -> Result<&str, String> {
+    let rest = b.get(off..).ok_or_else(|| format!("bad string offset {off:#x}"))?;
+    let end = rest.iter().position(|&c| c == 0).unwrap_or(rest.len());
+    std::str::from_utf8(&rest[..end]).map_err(|_| format!("non-utf8 section name at {off:#x}"))
+}
+
+/// name のセクションの中身を返す（無ければ None）
+pub fn section_bytes<'a>(b: &'a [u8], name: &str) -> Result<Option<&'a [u8]>, String> {
+    if b.get(0..4) != Some(b"\x7fELF".as_slice()) {
+        return Err("not an ELF file".to_string());
+    }
+    // EI_CLASS = 2 (64bit), EI_DATA = 1 (little-endian)
+    if b.get(4) != Some(&2) || b.get(5) != Some(&1) {
+        return Err("only ELF64 little-endian is supported".to_string());
+    }
+
+    let shoff = u64_at(b, 0x28)? as usize;
+    let shentsize = u16_at(b, 0x3A)? as usize;
+    let shnum = u16_at(b, 0x3C)? as usize;
+    let shstrndx = u16_at(b, 0x3E)? as usize;
+
+    if shoff == 0 || shnum == 0 {
+        return Ok(None);
+    }
+
+    let strtab = section_at(b, shoff, shentsize, shstrndx)?;
+
+    for i in 0..shnum {
+        let s = section_at(b, shoff, shentsize, i)?;
+        let sname = cstr_at(b, strtab.offset as usize + s.name_off as usize)?;
+        if sname == name {
+            let start = s.offset as usize;
+            let end = start + s.size as usize;
+            return b
+                .get(start..end)
+                .map(Some)
+                .ok_or_else(|| format!("section {name} out of file bounds"));
+        }
+    }
+
+    Ok(None)
+}
@@ -0,0 +1,193 @@
+// tools/specxref/src/main.rs
+//
+// 役割:
+// - kernel ELF の formal_spec セクション（#[spec("...")] が焼き込んだ対応表）を読み、
+//   仕様条項一覧（docs/spec/clauses.txt）と突き合わせて被覆を報告するホスト側ツール。
+//
+// 使い方:
+//   scripts/specxref.sh target/x86_64-formal-os-local/debug/kernel
+//   scripts/specxref.sh --clauses docs/spec/clauses.txt --strict KERNEL_ELF
+//
+// 出力:
+// - covered:   条項 ID と、それを実装している関数（module_path::fn）
+// - uncovered: 仕様にあるがコードに対応が無い条項
+// - unknown:   コードに付いているが仕様に無い ID（typo / 仕様側の書き漏れ）
+//
+// 終了コード:
+// - unknown があれば 1（--strict なら uncovered があっても 1）
+//
+// やらないこと:
+// - #[spec] の付いていない関数の列挙（バイナリからは判別できない。unknown の逆方向のみ報告）
+
+mod elf;
+
+use std::collections::BTreeMap;
+use std::process::ExitCode;
+
+const SECTION: &str = "formal_spec";
+const DEFAULT_CLAUSES: &str = "docs/spec/clauses.txt";
+
+fn usage() -> &'static str {
+    "usage: specxref [--clauses FILE] [--strict] KERNEL_ELF"
+}
+
+struct Record {
+    id: String,
+    func: String,
+    module: String,
+}
+
+fn parse_records(bytes: &[u8]) -> Vec<Record> {
+    let text = String::from_utf8_lossy(bytes);
+    let mut out = Vec::new();
+    for line in text.lines() {
+        // リンカの padding（0 埋め）は無視する
+        let line = line.trim_matches('\0');
+        if line.is_empty() {
+            continue;
+        }
+        let mut it = line.split('\t');
+        if let (Some(id), Some(func), Some(module)) = (it.next(), it.next(), it.next()) {
+            out.push(Record {
+                id: id.to_string(),
+                func: func.to_string(),
+                module: module.to_string(),
+            });
+        }
+    }
+    out
+}
+
+/// "ID  説明" の行（# はコメント）
+fn parse_clauses(text: &str) -> BTreeMap<String, String> {
+    let mut out = BTreeMap::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (id, desc) = match line.split_once(char::is_whitespace) {
+            Some((id, desc)) => (id, desc.trim()),
+            None => (line, ""),
+        };
+        out.insert(id.to_string(), desc.to_string());
+    }
+    out
+}
+
+fn main() -> ExitCode {
+    let mut clauses_path = DEFAULT_CLAUSES.to_string();
+    let mut strict = false;
+    let mut elf_path: Option<String> = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--clauses" => match args.next() {
+                Some(p) => clauses_path = p,
+                None => {
+                    eprintln!("{}", usage());
+                    return ExitCode::from(2);
+                }
+            },
+            "--strict" => strict = true,
+            "-h" | "--help" => {
+                println!("{}", usage());
+                return ExitCode::SUCCESS;
+            }
+            _ if elf_path.is_none() && !arg.starts_with('-') => elf_path = Some(arg),
+            _ => {
+                eprintln!("{}", usage());
+                return ExitCode::from(2);
+            }
+        }
+    }
+
+    let Some(elf_path) = elf_path else {
+        eprintln!("{}", usage());
+        return ExitCode::from(2);
+    };
+
+    let image = match std::fs::read(&elf_path) {
+        Ok(b) => b,
+        Err(e) => {
+            eprintln!("specxref: cannot read {elf_path}: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let clauses_text = match std::fs::read_to_string(&clauses_path) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("specxref: cannot read {clauses_path}: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let section = match elf::section_bytes(&image, SECTION) {
+        Ok(Some(b)) => b,
+        Ok(None) => {
+            eprintln!("specxref: no {SECTION} section in {elf_path} (no #[spec] in this build?)");
+            return ExitCode::FAILURE;
+        }
+        Err(e) => {
+            eprintln!("specxref: {elf_path}: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let records = parse_records(section);
+    let clauses = parse_clauses(&clauses_text);
+
+    // ID -> 実装箇所
+    let mut by_id: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for r in &records {
+        by_id
+            .entry(r.id.as_str())
+            .or_default()
+            .push(format!("{}::{}", r.module, r.func));
+    }
+    for v in by_id.values_mut() {
+        v.sort();
+        v.dedup();
+    }
+
+    let covered: Vec<&String> = clauses.keys().filter(|id| by_id.contains_key(id.as_str())).collect();
+    let uncovered: Vec<&String> = clauses.keys().filter(|id| !by_id.contains_key(id.as_str())).collect();
+    let unknown: Vec<&str> = by_id.keys().copied().filter(|id| !clauses.contains_key(*id)).collect();
+
+    println!("== spec coverage ==");
+    println!("clauses   = {}", clauses.len());
+    println!("records   = {}", records.len());
+    println!("covered   = {}", covered.len());
+    println!("uncovered = {}", uncovered.len());
+    println!("unknown   = {}", unknown.len());
+
+    println!();
+    println!("-- covered --");
+    for id in &covered {
+        println!("{id}  {}", clauses[*id]);
+        for site in &by_id[id.as_str()] {
+            println!("    {site}");
+        }
+    }
+
+    println!();
+    println!("-- uncovered (spec clause without code) --");
+    for id in &uncovered {
+        println!("{id}  {}", clauses[*id]);
+    }
+
+    println!();
+    println!("-- unknown (code annotation without spec clause) --");
+    for id in &unknown {
+        println!("{id}");
+        for site in &by_id[id] {
+            println!("    {site}");
+        }
+    }
+
+    if !unknown.is_empty() || (strict && !uncovered.is_empty()) {
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,7 @@
+// tools/traceviz/build.rs
+//
+// kernel/src/kernel/abi.rs を #[path] で取り込むので、カーネル型に依存する encode を外す cfg を立てる。
+fn main() {
+    println!("cargo::rustc-check-cfg=cfg(wire_decode_only)");
+    println!("cargo:rustc-cfg=wire_decode_only");
+}
@@ -0,0 +1,96 @@
+// tools/traceviz/src/main.rs
+//
+// 役割:
+// - QEMU の serial ログ（Event Log Dump）から sequence diagram を生成するホスト側ツール。
+//
+// 使い方:
+//   scripts/traceviz.sh logs/qemu_XXXX.log > trace.mmd
+//   scripts/traceviz.sh --format plantuml --ticks logs/qemu_XXXX.log > trace.puml
+//   cat logs/qemu_XXXX.log | scripts/traceviz.sh
+//
+// やらないこと:
+// - 画像化（Mermaid / PlantUML 側のツールに任せる）
+
+mod parse;
+mod render;
+mod wire;
+
+use std::io::Read;
+use std::process::ExitCode;
+
+use render::{Format, Options};
+
+fn usage() -> &'static str {
+    "usage: traceviz [--format mermaid|plantuml] [--ticks] [LOG_FILE]\n\
+     reads stdin when LOG_FILE is omitted"
+}
+
+fn main() -> ExitCode {
+    let mut opts = Options {
+        format: Format::Mermaid,
+        show_ticks: false,
+    };
+    let mut path: Option<String> = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => match args.next().as_deref() {
+                Some("mermaid") => opts.format = Format::Mermaid,
+                Some("plantuml") => opts.format = Format::PlantUml,
+                _ => {
+                    eprintln!("{}", usage());
+                    return ExitCode::from(2);
+                }
+            },
+            "--ticks" => opts.show_ticks = true,
+            "-h" | "--help" => {
+                println!("{}", usage());
+                return ExitCode::SUCCESS;
+            }
+            _ if path.is_none() && !arg.starts_with('-') => path = Some(arg),
+            _ => {
+                eprintln!("{}", usage());
+                return ExitCode::from(2);
+            }
+        }
+    }
+
+    let input = match path {
+        Some(p) => match std::fs::read_to_string(&p) {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("traceviz: cannot read {p}: {e}");
+                return ExitCode::FAILURE;
+            }
+        },
+        None => {
+            let mut s = String::new();
+            if let Err(e) = std::io::stdin().read_to_string(&mut s) {
+                eprintln!("traceviz: cannot read stdin: {e}");
+                return ExitCode::FAILURE;
+            }
+            s
+        }
+    };
+
+    // trace_wire ビルドのログなら、テキストではなくワイヤ形式を優先して読む
+    let events = if wire::has_wire_dump(&input) {
+        match wire::parse_wire(&input) {
+            Ok(v) => v,
+            Err(e) => {
+                eprintln!("traceviz: wire decode failed: {e}");
+                return ExitCode::FAILURE;
+            }
+        }
+    } else {
+        parse::parse(&input)
+    };
+    if events.is_empty() {
+        eprintln!("traceviz: no events found (is the Event Log Dump in the input?)");
+        return ExitCode::FAILURE;
+    }
+
+    print!("{}", render::render(&events, opts));
+    ExitCode::SUCCESS
+}
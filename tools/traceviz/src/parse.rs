@@ -0,0 +1,177 @@
+// tools/traceviz/src/parse.rs
+//
+// 役割:
+// - serial ログ（KernelState Event Log Dump）を EventRecord 列に戻す。
+//
+// 設計方針:
+// - 入力は logging の出力そのまま（"[INFO] EVENT: X" + "[INFO] key = value" 行の並び）。
+// - "EVENT:" 行で 1 レコード開始、次の "EVENT:" までの key/value をそのレコードに属させる。
+// - Dump ヘッダ（=== KernelState Event Log Dump ===）があればその区間だけを読む。
+//   無ければ入力全体を読む（部分的に切り出したログでも使えるように）。
+//
+// やらないこと:
+// - ipc_trace 行（docs/LOG_FORMAT.md 2章）の解釈（event log だけで図は描ける）
+
+const DUMP_BEGIN: &str = "=== KernelState Event Log Dump ===";
+const DUMP_END: &str = "=== End of Event Log ===";
+
+/// 1 イベント分のレコード
+#[derive(Debug, Clone)]
+pub struct EventRecord {
+    pub name: String,
+    /// "key = <u64>" 形式のフィールド
+    pub nums: Vec<(String, u64)>,
+    /// "key = Text" 形式のフィールド（mem_action = Map, reason = UserPageFault など）
+    pub texts: Vec<(String, String)>,
+    /// TaskStateChanged の "to READY" 等
+    pub to_state: Option<String>,
+}
+
+impl EventRecord {
+    fn new(name: &str) -> Self {
+        EventRecord {
+            name: name.to_string(),
+            nums: Vec::new(),
+            texts: Vec::new(),
+            to_state: None,
+        }
+    }
+
+    pub fn num(&self, key: &str) -> Option<u64> {
+        self.nums.iter().find(|(k, _)| k == key).map(|(_, v)| *v)
+    }
+
+    pub fn text(&self, key: &str) -> Option<&str> {
+        self.texts
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// "[INFO] " / "[ERROR] " を剥がした本文を返す（それ以外の行は None）
+/// - log_seq ビルドの "#<seq> " も剥がす
+pub fn strip_prefix(line: &str) -> Option<&str> {
+    let line = line.trim_end_matches(['\r', '\n']);
+    let body = line
+        .strip_prefix("[INFO] ")
+        .or_else(|| line.strip_prefix("[ERROR] "))?;
+
+    if let Some(rest) = body.strip_prefix('#') {
+        if let Some((num, tail)) = rest.split_once(' ') {
+            if !num.is_empty() && num.bytes().all(|b| b.is_ascii_digit()) {
+                return Some(tail);
+            }
+        }
+    }
+    Some(body)
+}
+
+pub fn parse(input: &str) -> Vec<EventRecord> {
+    let has_header = input.lines().any(|l| strip_prefix(l) == Some(DUMP_BEGIN));
+    let mut in_dump = !has_header;
+
+    let mut out: Vec<EventRecord> = Vec::new();
+    let mut cur: Option<EventRecord> = None;
+
+    for raw in input.lines() {
+        let Some(body) = strip_prefix(raw) else {
+            continue;
+        };
+
+        if body == DUMP_BEGIN {
+            in_dump = true;
+            continue;
+        }
+        if body == DUMP_END {
+            if let Some(ev) = cur.take() {
+                out.push(ev);
+            }
+            in_dump = false;
+            continue;
+        }
+        if !in_dump {
+            continue;
+        }
+
+        if let Some(name) = body.strip_prefix("EVENT: ") {
+            if let Some(ev) = cur.take() {
+                out.push(ev);
+            }
+            cur = Some(EventRecord::new(name.trim()));
+            continue;
+        }
+
+        let Some(ev) = cur.as_mut() else {
+            continue;
+        };
+
+        // "to = 3"（IPC の宛先）と "to READY"（状態遷移）を取り違えないよう key/value を先に見る
+        if let Some((k, v)) = body.split_once(" = ") {
+            let k = k.trim().to_string();
+            let v = v.trim();
+            match v.parse::<u64>() {
+                Ok(n) => ev.nums.push((k, n)),
+                Err(_) => ev.texts.push((k, v.to_string())),
+            }
+            continue;
+        }
+
+        if let Some(state) = body.strip_prefix("to ") {
+            ev.to_state = Some(state.trim().to_string());
+        }
+    }
+
+    if let Some(ev) = cur.take() {
+        out.push(ev);
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_well_formed_record() {
+        let log = "\
+[INFO] === KernelState Event Log Dump ===
+[INFO] #12 EVENT: TaskStateChanged
+[INFO] #13 task = 3
+[INFO] #14 to BLOCKED
+[INFO] === End of Event Log ===
+";
+        let evs = parse(log);
+        assert_eq!(evs.len(), 1);
+        assert_eq!(evs[0].name, "TaskStateChanged");
+        assert_eq!(evs[0].num("task"), Some(3));
+        assert_eq!(evs[0].to_state.as_deref(), Some("BLOCKED"));
+    }
+
+    #[test]
+    fn skips_a_malformed_line_and_keeps_the_record() {
+        let log = "\
+[INFO] === KernelState Event Log Dump ===
+[INFO] EVENT: IpcDelivered
+[INFO] from = 1
+[INFO] to: 2
+garbage without a level tag
+[INFO] ep = zero
+[INFO] msg = 7
+[INFO] === End of Event Log ===
+";
+        let evs = parse(log);
+        assert_eq!(evs.len(), 1);
+        let ev = &evs[0];
+        assert_eq!(ev.name, "IpcDelivered");
+        assert_eq!(ev.num("from"), Some(1));
+        // "to: 2" は key = value でも "to <STATE>" でもない
+        assert_eq!(ev.num("to"), None);
+        assert_eq!(ev.to_state, None);
+        // 数でない値は text として残る
+        assert_eq!(ev.num("ep"), None);
+        assert_eq!(ev.text("ep"), Some("zero"));
+        assert_eq!(ev.num("msg"), Some(7));
+    }
+}
@@ -0,0 +1,555 @@
+// tools/traceviz/src/render.rs
+//
+// 役割:
+// - EventRecord 列を sequence diagram（Mermaid / PlantUML）に変換する。
+//
+// 設計方針:
+// - lifeline = task（TaskId ごとに 1 本）
+// - 矢印 = IPC（IpcDelivered は実線、IpcReplyDelivered は破線）
+// - Running 区間 = activation（activate/deactivate の網掛け）
+// - block / kill は note で示す（原因の追跡用）
+//
+// やらないこと:
+// - 時間軸の正確な縮尺（順序だけを保つ）
+
+use crate::parse::EventRecord;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Mermaid,
+    PlantUml,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Options {
+    pub format: Format,
+    /// TickStarted を note として出す（長いトレースでは冗長なので既定 off）
+    pub show_ticks: bool,
+}
+
+struct Out {
+    format: Format,
+    buf: String,
+    /// activate 中の task（deactivate の過不足を防ぐ）
+    active: Vec<u64>,
+}
+
+impl Out {
+    fn line(&mut self, s: &str) {
+        self.buf.push_str(s);
+        self.buf.push('\n');
+    }
+
+    fn participant(&mut self, tid: u64) {
+        let s = match self.format {
+            Format::Mermaid => format!("    participant T{tid} as task {tid}"),
+            Format::PlantUml => format!("participant \"task {tid}\" as T{tid}"),
+        };
+        self.line(&s);
+    }
+
+    fn arrow(&mut self, from: u64, to: u64, dashed: bool, label: &str) {
+        let s = match (self.format, dashed) {
+            (Format::Mermaid, false) => format!("    T{from}->>T{to}: {label}"),
+            (Format::Mermaid, true) => format!("    T{from}-->>T{to}: {label}"),
+            (Format::PlantUml, false) => format!("T{from} -> T{to} : {label}"),
+            (Format::PlantUml, true) => format!("T{from} --> T{to} : {label}"),
+        };
+        self.line(&s);
+    }
+
+    fn note(&mut self, over: &str, text: &str) {
+        let s = match self.format {
+            Format::Mermaid => format!("    Note over {over}: {text}"),
+            Format::PlantUml => format!("note over {over} : {text}"),
+        };
+        self.line(&s);
+    }
+
+    fn activate(&mut self, tid: u64) {
+        if self.active.contains(&tid) {
+            return;
+        }
+        self.active.push(tid);
+        let s = match self.format {
+            Format::Mermaid => format!("    activate T{tid}"),
+            Format::PlantUml => format!("activate T{tid}"),
+        };
+        self.line(&s);
+    }
+
+    fn deactivate(&mut self, tid: u64) {
+        let Some(pos) = self.active.iter().position(|t| *t == tid) else {
+            return;
+        };
+        self.active.remove(pos);
+        let s = match self.format {
+            Format::Mermaid => format!("    deactivate T{tid}"),
+            Format::PlantUml => format!("deactivate T{tid}"),
+        };
+        self.line(&s);
+    }
+}
+
+/// IPC メッセージの表示（len <= 1 は "msg=MR0"、それ以外は "msg=[MR0, MR1, ..]"）
+/// - msg が無い（古い kernel の reply）なら None
+fn msg_label(ev: &EventRecord) -> Option<String> {
+    let mr0 = ev.num("msg")?;
+    let len = ev.num("msg_len").unwrap_or(1);
+    if len <= 1 {
+        return Some(format!("msg={mr0}"));
+    }
+    let mut words = vec![mr0.to_string()];
+    for key in ["mr1", "mr2", "mr3"].iter().take(len as usize - 1) {
+        words.push(ev.num(key).unwrap_or(0).to_string());
+    }
+    Some(format!("msg=[{}]", words.join(", ")))
+}
+
+/// handle が指す object の表示（kind は abi.rs の OBJECT_KIND_*: 0 = ep / 1 = ntfn / 2 = shm）
+fn object_name(kind: u64, object: u64) -> String {
+    match kind {
+        0 => format!("ep{object}"),
+        1 => format!("ntfn{object}"),
+        2 => format!("shm{object}"),
+        _ => format!("obj{object}"),
+    }
+}
+
+/// イベント中に現れる task id を昇順で集める
+fn collect_tasks(events: &[EventRecord]) -> Vec<u64> {
+    let mut tasks: Vec<u64> = Vec::new();
+    for ev in events {
+        for key in ["task", "from", "to"] {
+            if let Some(t) = ev.num(key) {
+                if !tasks.contains(&t) {
+                    tasks.push(t);
+                }
+            }
+        }
+    }
+    tasks.sort_unstable();
+    tasks
+}
+
+pub fn render(events: &[EventRecord], opts: Options) -> String {
+    let tasks = collect_tasks(events);
+
+    let mut out = Out {
+        format: opts.format,
+        buf: String::new(),
+        active: Vec::new(),
+    };
+
+    match opts.format {
+        Format::Mermaid => out.line("sequenceDiagram"),
+        Format::PlantUml => out.line("@startuml"),
+    }
+
+    for &t in &tasks {
+        out.participant(t);
+    }
+
+    // note を全 lifeline にまたがらせるときの範囲
+    let span = match (tasks.first(), tasks.last()) {
+        (Some(a), Some(b)) if a != b => format!("T{a},T{b}"),
+        (Some(a), _) => format!("T{a}"),
+        _ => String::new(),
+    };
+
+    for ev in events {
+        match ev.name.as_str() {
+            "TickStarted" if opts.show_ticks && !span.is_empty() => {
+                let tick = ev.num("tick").unwrap_or(0);
+                out.note(&span, &format!("tick {tick}"));
+            }
+            "TaskStateChanged" => {
+                let (Some(t), Some(state)) = (ev.num("task"), ev.to_state.as_deref()) else {
+                    continue;
+                };
+                match state {
+                    "RUNNING" => out.activate(t),
+                    "DEAD" | "SUSPENDED" => {
+                        out.deactivate(t);
+                        out.note(&format!("T{t}"), state);
+                    }
+                    _ => out.deactivate(t),
+                }
+            }
+            "IpcDelivered" => {
+                let (Some(from), Some(to)) = (ev.num("from"), ev.num("to")) else {
+                    continue;
+                };
+                let ep = ev.num("ep").unwrap_or(0);
+                let msg = msg_label(ev).unwrap_or_else(|| "msg=0".to_string());
+                out.arrow(from, to, false, &format!("send ep={ep} {msg}"));
+            }
+            "IpcReplyDelivered" => {
+                let (Some(from), Some(to)) = (ev.num("from"), ev.num("to")) else {
+                    continue;
+                };
+                let ep = ev.num("ep").unwrap_or(0);
+                let label = match msg_label(ev) {
+                    Some(msg) => format!("reply ep={ep} {msg}"),
+                    None => format!("reply ep={ep}"),
+                };
+                out.arrow(from, to, true, &label);
+            }
+            "IpcSendBlocked" | "IpcRecvBlocked" => {
+                let Some(t) = ev.num("task") else {
+                    continue;
+                };
+                let ep = ev.num("ep").unwrap_or(0);
+                let what = if ev.name == "IpcSendBlocked" {
+                    "send"
+                } else {
+                    "recv"
+                };
+                out.note(&format!("T{t}"), &format!("{what} blocked ep={ep}"));
+            }
+            "TimerFired" => {
+                let Some(t) = ev.num("task") else {
+                    continue;
+                };
+                let ep = ev.num("ep").unwrap_or(0);
+                out.note(&format!("T{t}"), &format!("timer tick ep={ep}"));
+            }
+            "ServerSlow" => {
+                let Some(s) = ev.num("server") else {
+                    continue;
+                };
+                let held = ev.num("held_ticks").unwrap_or(0);
+                out.note(&format!("T{s}"), &format!("server slow: held reply {held} ticks"));
+            }
+            "PriorityInherited" => {
+                let Some(t) = ev.num("task") else {
+                    continue;
+                };
+                let from = ev.num("from").unwrap_or(0);
+                let prio = ev.num("priority").unwrap_or(0);
+                out.note(&format!("T{t}"), &format!("prio {prio} (inherited from T{from})"));
+            }
+            "PriorityRestored" => {
+                let Some(t) = ev.num("task") else {
+                    continue;
+                };
+                let prio = ev.num("priority").unwrap_or(0);
+                out.note(&format!("T{t}"), &format!("prio restored to {prio}"));
+            }
+            "TaskCreated" => {
+                let Some(t) = ev.num("task") else {
+                    continue;
+                };
+                let parent = ev.num("parent").unwrap_or(0);
+                out.note(&format!("T{t}"), &format!("created by T{parent}"));
+            }
+            "TaskExited" => {
+                let Some(t) = ev.num("task") else {
+                    continue;
+                };
+                out.deactivate(t);
+                out.note(&format!("T{t}"), "exited");
+            }
+            "NotifySignaled" => {
+                let Some(t) = ev.num("task") else {
+                    continue;
+                };
+                let ntfn = ev.num("ntfn").unwrap_or(0);
+                let bits = ev.num("bits").unwrap_or(0);
+                out.note(&format!("T{t}"), &format!("signal ntfn={ntfn} bits={bits:#x}"));
+            }
+            "NotifyWaitBlocked" => {
+                let Some(t) = ev.num("task") else {
+                    continue;
+                };
+                let ntfn = ev.num("ntfn").unwrap_or(0);
+                out.note(&format!("T{t}"), &format!("wait blocked ntfn={ntfn}"));
+            }
+            "NotifyDelivered" => {
+                let Some(t) = ev.num("to") else {
+                    continue;
+                };
+                let ntfn = ev.num("ntfn").unwrap_or(0);
+                let bits = ev.num("bits").unwrap_or(0);
+                out.note(&format!("T{t}"), &format!("notified ntfn={ntfn} bits={bits:#x}"));
+            }
+            "CapDenied" => {
+                let Some(t) = ev.num("task") else {
+                    continue;
+                };
+                let cap = ev.num("cap").unwrap_or(0);
+                let need = ev.num("need").unwrap_or(0);
+                out.note(&format!("T{t}"), &format!("cap denied cap={cap} need={need:#x}"));
+            }
+            "CapGranted" => {
+                let (Some(from), Some(to)) = (ev.num("from"), ev.num("to")) else {
+                    continue;
+                };
+                let obj = object_name(ev.num("kind").unwrap_or(0), ev.num("object").unwrap_or(0));
+                out.note(&format!("T{from}"), &format!("grant {obj} cap to T{to}"));
+            }
+            "CapReceived" => {
+                let Some(t) = ev.num("task") else {
+                    continue;
+                };
+                let slot = ev.num("slot").unwrap_or(0);
+                let obj = object_name(ev.num("kind").unwrap_or(0), ev.num("object").unwrap_or(0));
+                out.note(&format!("T{t}"), &format!("received {obj} cap in slot {slot}"));
+            }
+            "EndpointCreated" => {
+                let Some(t) = ev.num("task") else {
+                    continue;
+                };
+                let ep = ev.num("ep").unwrap_or(0);
+                let cap = ev.num("cap").unwrap_or(0);
+                out.note(&format!("T{t}"), &format!("created ep{ep} (cap {cap})"));
+            }
+            "EndpointDeleted" => {
+                let Some(t) = ev.num("task") else {
+                    continue;
+                };
+                let ep = ev.num("ep").unwrap_or(0);
+                let revoked = ev.num("revoked").unwrap_or(0);
+                out.note(&format!("T{t}"), &format!("deleted ep{ep} ({revoked} caps revoked)"));
+            }
+            "PageSwappedOut" | "PageSwappedIn" => {
+                let Some(t) = ev.num("task") else {
+                    continue;
+                };
+                let page = ev.num("page").unwrap_or(0);
+                let slot = ev.num("slot").unwrap_or(0);
+                let what = if ev.name == "PageSwappedOut" { "swapped out" } else { "swapped in" };
+                out.note(&format!("T{t}"), &format!("page {page:#x} {what} (slot {slot})"));
+            }
+            "ShmCreated" => {
+                let Some(t) = ev.num("task") else {
+                    continue;
+                };
+                let shm = ev.num("shm").unwrap_or(0);
+                let pages = ev.num("pages").unwrap_or(0);
+                out.note(&format!("T{t}"), &format!("created shm{shm} ({pages} pages)"));
+            }
+            "ShmMapped" => {
+                let Some(t) = ev.num("task") else {
+                    continue;
+                };
+                let shm = ev.num("shm").unwrap_or(0);
+                let page = ev.num("page").unwrap_or(0);
+                out.note(&format!("T{t}"), &format!("mapped shm{shm} at page {page:#x}"));
+            }
+            "ShmDestroyed" => {
+                let Some(t) = ev.num("owner") else {
+                    continue;
+                };
+                let shm = ev.num("shm").unwrap_or(0);
+                let unmapped = ev.num("unmapped").unwrap_or(0);
+                out.note(&format!("T{t}"), &format!("shm{shm} destroyed ({unmapped} mappings removed)"));
+            }
+            "TlbFlushDeferred" | "TlbFlushed" => {
+                // AddressSpace は task の lifeline に対応しないので全体にまたがらせる
+                if span.is_empty() {
+                    continue;
+                }
+                let asid = ev.num("address_space_id").unwrap_or(0);
+                let text = if ev.name == "TlbFlushDeferred" {
+                    let page = ev.num("page").unwrap_or(0);
+                    format!("AS{asid} TLB stale (page {page:#x}; flush deferred)")
+                } else {
+                    let pages = ev.num("pages").unwrap_or(0);
+                    let stale = ev.num("stale_ticks").unwrap_or(0);
+                    format!("AS{asid} TLB flushed ({pages} changes, stale {stale} ticks)")
+                };
+                out.note(&span, &text);
+            }
+            "LevelChanged" => {
+                let Some(t) = ev.num("task") else {
+                    continue;
+                };
+                let from = ev.num("from").unwrap_or(0);
+                let to = ev.num("to").unwrap_or(0);
+                let dir = if to > from { "down" } else { "up" };
+                out.note(&format!("T{t}"), &format!("mlfq level {from} -> {to} ({dir})"));
+            }
+            "NoProgressDetected" => {
+                // 全 task の停滞なので全体にまたがらせる
+                if span.is_empty() {
+                    continue;
+                }
+                let stalled = ev.num("stalled_ticks").unwrap_or(0);
+                let since = ev.num("last_progress_tick").unwrap_or(0);
+                out.note(&span, &format!("WATCHDOG: no progress for {stalled} ticks (since tick {since})"));
+            }
+            "DeadlockDetected" => {
+                if span.is_empty() {
+                    continue;
+                }
+                // wire は task0..task3、テキストは task を len 個並べる
+                let len = ev.num("len").unwrap_or(0) as usize;
+                let ids: Vec<u64> = ev
+                    .nums
+                    .iter()
+                    .filter(|(k, _)| k.starts_with("task"))
+                    .map(|(_, v)| *v)
+                    .take(len)
+                    .collect();
+                let Some(first) = ids.first() else {
+                    continue;
+                };
+                let cycle: Vec<String> = ids.iter().chain(Some(first)).map(|t| format!("T{t}")).collect();
+                out.note(&span, &format!("DEADLOCK: {}", cycle.join(" -> ")));
+            }
+            "InvariantViolated" => {
+                // task の無い違反（wire では WIRE_NONE）は全体にまたがらせる
+                let code = ev.num("invariant").unwrap_or(u64::MAX);
+                let name = crate::wire::invariant_name(code).unwrap_or("?");
+                let text = match ev.num("detail").filter(|&d| d != u64::MAX) {
+                    Some(d) => format!("INVARIANT: {name} (detail {d})"),
+                    None => format!("INVARIANT: {name}"),
+                };
+                match ev.num("task").filter(|&t| t != u64::MAX) {
+                    Some(t) => out.note(&format!("T{t}"), &text),
+                    None if !span.is_empty() => out.note(&span, &text),
+                    None => {}
+                }
+            }
+            "InputReceived" => {
+                // keyboard IRQ はどの task にも属さないので全体にまたがらせる
+                if span.is_empty() {
+                    continue;
+                }
+                let sc = ev.num("scancode").unwrap_or(0);
+                let queued = ev.num("queued").unwrap_or(0);
+                out.note(&span, &format!("input scancode {sc:#04x} ({queued} queued)"));
+            }
+            "FaultDelegated" => {
+                let Some(t) = ev.num("task") else {
+                    continue;
+                };
+                let ep = ev.num("ep").unwrap_or(0);
+                let addr = ev.num("addr").unwrap_or(0);
+                out.note(&format!("T{t}"), &format!("#PF {addr:#x} -> ep{ep}"));
+            }
+            "FaultResolved" => {
+                let Some(t) = ev.num("task") else {
+                    continue;
+                };
+                let by = ev.num("by").unwrap_or(0);
+                let action = ev.text("action").unwrap_or("?");
+                out.note(&format!("T{t}"), &format!("fault resolved by T{by} ({action})"));
+            }
+            "PageGranted" => {
+                let Some(t) = ev.num("to") else {
+                    continue;
+                };
+                let from = ev.num("from").unwrap_or(0);
+                let page = ev.num("page").unwrap_or(0);
+                out.note(&format!("T{t}"), &format!("granted page {page:#x} from T{from}"));
+            }
+            "GrantRevoked" => {
+                let Some(t) = ev.num("to") else {
+                    continue;
+                };
+                let page = ev.num("page").unwrap_or(0);
+                let reason = ev.text("reason").unwrap_or("?");
+                out.note(&format!("T{t}"), &format!("grant {page:#x} revoked ({reason})"));
+            }
+            "DerivationRevoked" => {
+                let Some(t) = ev.num("task") else {
+                    continue;
+                };
+                let by = ev.num("by").unwrap_or(0);
+                let depth = ev.num("depth").unwrap_or(0);
+                let object = ev.num("object").unwrap_or(0);
+                let what = match ev.text("kind").unwrap_or("?") {
+                    "page" => format!("page {object:#x}"),
+                    kind => format!("{kind} {object}"),
+                };
+                out.note(&format!("T{t}"), &format!("{what} revoked by T{by} (depth {depth})"));
+            }
+            "TaskKilled" => {
+                let Some(t) = ev.num("task") else {
+                    continue;
+                };
+                let reason = ev.text("reason").unwrap_or("?");
+                out.deactivate(t);
+                out.note(&format!("T{t}"), &format!("killed: {reason}"));
+            }
+            _ => {}
+        }
+    }
+
+    // 末尾で開きっぱなしの activation を閉じる
+    let remaining: Vec<u64> = out.active.clone();
+    for t in remaining {
+        out.deactivate(t);
+    }
+
+    if opts.format == Format::PlantUml {
+        out.line("@enduml");
+    }
+
+    out.buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::parse;
+
+    const LOG: &str = "\
+[INFO] === KernelState Event Log Dump ===
+[INFO] EVENT: TickStarted
+[INFO] tick = 1
+[INFO] EVENT: TaskStateChanged
+[INFO] task = 1
+[INFO] to RUNNING
+[INFO] EVENT: IpcDelivered
+[INFO] from = 1
+[INFO] to = 2
+[INFO] ep = 0
+[INFO] msg = 7
+[INFO] EVENT: IpcReplyDelivered
+[INFO] from = 2
+[INFO] to = 1
+[INFO] ep = 0
+[INFO] msg = 8
+[INFO] EVENT: TaskKilled
+[INFO] task = 2
+[INFO] reason = UserPageFault
+[INFO] === End of Event Log ===
+";
+
+    #[test]
+    fn renders_a_small_sequence_as_mermaid() {
+        let opts = Options { format: Format::Mermaid, show_ticks: true };
+        let expected = "\
+sequenceDiagram
+    participant T1 as task 1
+    participant T2 as task 2
+    Note over T1,T2: tick 1
+    activate T1
+    T1->>T2: send ep=0 msg=7
+    T2-->>T1: reply ep=0 msg=8
+    Note over T2: killed: UserPageFault
+    deactivate T1
+";
+        assert_eq!(render(&parse(LOG), opts), expected);
+    }
+
+    #[test]
+    fn renders_a_small_sequence_as_plantuml_without_ticks() {
+        let opts = Options { format: Format::PlantUml, show_ticks: false };
+        let expected = "\
+@startuml
+participant \"task 1\" as T1
+participant \"task 2\" as T2
+activate T1
+T1 -> T2 : send ep=0 msg=7
+T2 --> T1 : reply ep=0 msg=8
+note over T2 : killed: UserPageFault
+deactivate T1
+@enduml
+";
+        assert_eq!(render(&parse(LOG), opts), expected);
+    }
+}
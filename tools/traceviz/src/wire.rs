@@ -0,0 +1,331 @@
+// tools/traceviz/src/wire.rs
+//
+// 役割:
+// - "=== Wire Dump ===" 区間（kernel feature trace_wire）を decode して EventRecord に戻す。
+//
+// 設計方針:
+// - レイアウト定義・decoder は kernel/src/kernel/abi.rs をそのまま共有する（二重定義しない）。
+// - 変換後の name / key はテキスト版 Event Log Dump と同じにする（render 側を共通化）。
+
+#[path = "../../../kernel/src/kernel/abi.rs"]
+mod abi;
+
+use crate::parse::{strip_prefix, EventRecord};
+
+const WIRE_BEGIN: &str = "=== Wire Dump ===";
+const WIRE_END: &str = "=== End of Wire Dump ===";
+
+pub fn has_wire_dump(input: &str) -> bool {
+    input.lines().any(|l| strip_prefix(l) == Some(WIRE_BEGIN))
+}
+
+/// 2 文字ずつ byte に戻す（奇数長・hex でない文字は None。byte 単位で切るので非 ASCII でも panic しない）
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    let pairs = s.as_bytes().chunks_exact(2);
+    if !pairs.remainder().is_empty() {
+        return None;
+    }
+    pairs
+        .map(|p| std::str::from_utf8(p).ok().and_then(|h| u8::from_str_radix(h, 16).ok()))
+        .collect()
+}
+
+/// InvariantViolated の invariant（code）の名前（テキスト版は数値しか出ないので render が引く）
+pub fn invariant_name(code: u64) -> Option<&'static str> {
+    abi::INVARIANT_NAMES.get(code as usize).copied()
+}
+
+fn state_name(code: u64) -> &'static str {
+    match code {
+        abi::STATE_READY => "READY",
+        abi::STATE_RUNNING => "RUNNING",
+        abi::STATE_BLOCKED => "BLOCKED",
+        abi::STATE_DEAD => "DEAD",
+        abi::STATE_SUSPENDED => "SUSPENDED",
+        _ => "?",
+    }
+}
+
+fn event_from_wire(r: &abi::WireRecord) -> Option<EventRecord> {
+    let w = |i| r.word(i);
+    let (name, keys): (&str, &[&str]) = match r.sub() {
+        abi::EV_TICK_STARTED => ("TickStarted", &["tick"]),
+        abi::EV_TIMER_UPDATED => ("TimerUpdated", &["time"]),
+        abi::EV_FRAME_ALLOCATED => ("FrameAllocated", &[]),
+        abi::EV_FRAME_FREED => ("FrameFreed", &["frame"]),
+        abi::EV_TASK_SWITCHED => ("TaskSwitched", &["task"]),
+        abi::EV_TASK_STATE_CHANGED => ("TaskStateChanged", &["task"]),
+        abi::EV_READY_QUEUED => ("ReadyQueued", &["task"]),
+        abi::EV_READY_DEQUEUED => ("ReadyDequeued", &["task"]),
+        abi::EV_WAIT_QUEUED => ("WaitQueued", &["task"]),
+        abi::EV_WAIT_DEQUEUED => ("WaitDequeued", &["task"]),
+        abi::EV_RUNTIME_UPDATED => ("RuntimeUpdated", &["task", "runtime"]),
+        abi::EV_QUANTUM_EXPIRED => ("QuantumExpired", &["task", "used_ticks"]),
+        abi::EV_MEM_ACTION_APPLIED => ("MemActionApplied", &["task", "address_space_id"]),
+        abi::EV_SYSCALL_ISSUED => ("SyscallIssued", &["task"]),
+        abi::EV_SYSCALL_HANDLED => ("SyscallHandled", &["task"]),
+        abi::EV_IPC_RECV_CALLED => ("IpcRecvCalled", &["task", "ep"]),
+        abi::EV_IPC_RECV_BLOCKED => ("IpcRecvBlocked", &["task", "ep"]),
+        abi::EV_IPC_SEND_CALLED => ("IpcSendCalled", &["task", "ep", "msg", "msg_len", "mr1", "mr2", "mr3"]),
+        abi::EV_IPC_SEND_BLOCKED => ("IpcSendBlocked", &["task", "ep"]),
+        abi::EV_IPC_DELIVERED => ("IpcDelivered", &["from", "to", "ep", "msg", "msg_len", "mr1", "mr2", "mr3"]),
+        abi::EV_IPC_REPLY_CALLED => ("IpcReplyCalled", &["task", "ep", "to"]),
+        abi::EV_IPC_REPLY_DELIVERED => {
+            ("IpcReplyDelivered", &["from", "to", "ep", "msg", "msg_len", "mr1", "mr2", "mr3"])
+        }
+        abi::EV_TASK_KILLED => ("TaskKilled", &["task"]),
+        abi::EV_SERVER_SLOW => ("ServerSlow", &["server", "client", "ep", "held_ticks"]),
+        abi::EV_TIMER_FIRED => ("TimerFired", &["task", "ep", "missed"]),
+        abi::EV_PRIORITY_INHERITED => ("PriorityInherited", &["task", "from", "priority"]),
+        abi::EV_PRIORITY_RESTORED => ("PriorityRestored", &["task", "priority"]),
+        abi::EV_TASK_CREATED => ("TaskCreated", &["task", "parent", "slot", "priority"]),
+        abi::EV_TASK_EXITED => ("TaskExited", &["task"]),
+        abi::EV_NOTIFY_SIGNALED => ("NotifySignaled", &["task", "ntfn", "bits"]),
+        abi::EV_NOTIFY_WAIT_BLOCKED => ("NotifyWaitBlocked", &["task", "ntfn"]),
+        abi::EV_NOTIFY_DELIVERED => ("NotifyDelivered", &["to", "ntfn", "bits"]),
+        abi::EV_CAP_DENIED => ("CapDenied", &["task", "cap", "need"]),
+        abi::EV_CAP_GRANTED => ("CapGranted", &["from", "to", "cap", "object", "kind"]),
+        abi::EV_CAP_RECEIVED => ("CapReceived", &["task", "slot", "object", "rights", "kind"]),
+        abi::EV_ENDPOINT_CREATED => ("EndpointCreated", &["task", "ep", "cap"]),
+        abi::EV_ENDPOINT_DELETED => ("EndpointDeleted", &["task", "ep", "revoked"]),
+        abi::EV_PAGE_SWAPPED_OUT => ("PageSwappedOut", &["task", "page", "slot"]),
+        abi::EV_PAGE_SWAPPED_IN => ("PageSwappedIn", &["task", "page", "slot"]),
+        abi::EV_SHM_CREATED => ("ShmCreated", &["task", "shm", "pages"]),
+        abi::EV_SHM_MAPPED => ("ShmMapped", &["task", "shm", "page"]),
+        abi::EV_SHM_DESTROYED => ("ShmDestroyed", &["owner", "shm", "unmapped"]),
+        abi::EV_TLB_FLUSH_DEFERRED => ("TlbFlushDeferred", &["address_space_id", "page"]),
+        abi::EV_TLB_FLUSHED => ("TlbFlushed", &["address_space_id", "pages", "stale_ticks"]),
+        abi::EV_LEVEL_CHANGED => ("LevelChanged", &["task", "from", "to"]),
+        abi::EV_NO_PROGRESS_DETECTED => ("NoProgressDetected", &["stalled_ticks", "last_progress_tick", "task_states"]),
+        abi::EV_DEADLOCK_DETECTED => ("DeadlockDetected", &["len", "task0", "task1", "task2", "task3"]),
+        abi::EV_INVARIANT_VIOLATED => ("InvariantViolated", &["invariant", "task", "detail"]),
+        abi::EV_INPUT_RECEIVED => ("InputReceived", &["scancode", "queued"]),
+        abi::EV_FAULT_DELEGATED => ("FaultDelegated", &["task", "ep", "addr"]),
+        abi::EV_FAULT_RESOLVED => ("FaultResolved", &["task", "by", "action"]),
+        abi::EV_PAGE_GRANTED => ("PageGranted", &["from", "to", "page", "frame"]),
+        abi::EV_GRANT_REVOKED => ("GrantRevoked", &["from", "to", "page", "reason"]),
+        abi::EV_DERIVATION_REVOKED => ("DerivationRevoked", &["by", "task", "kind", "object", "depth"]),
+        _ => return None,
+    };
+
+    let mut ev = EventRecord {
+        name: name.to_string(),
+        nums: Vec::new(),
+        texts: Vec::new(),
+        to_state: None,
+    };
+    for (i, k) in keys.iter().enumerate() {
+        ev.nums.push((k.to_string(), w(i)));
+    }
+    ev.nums.push(("seq".to_string(), r.seq()));
+
+    match r.sub() {
+        abi::EV_TASK_STATE_CHANGED => ev.to_state = Some(state_name(w(1)).to_string()),
+        abi::EV_TASK_KILLED => {
+            let reason = match w(1) {
+                abi::KILL_USER_PAGE_FAULT => "UserPageFault",
+                abi::KILL_DEMO_INJECTED => "DemoInjected",
+                abi::KILL_TRAP_FRAME_CORRUPT => "TrapFrameCorrupt",
+                abi::KILL_FAULT_STORM => "FaultStorm",
+                abi::KILL_STACK_OVERFLOW => "StackOverflow",
+                abi::KILL_USER_EXCEPTION => "UserException",
+                abi::KILL_SHUTDOWN => "Shutdown",
+                abi::KILL_SWAP_FAILED => "SwapFailed",
+                _ => "?",
+            };
+            ev.texts.push(("reason".to_string(), reason.to_string()));
+        }
+        abi::EV_FAULT_RESOLVED => {
+            let action = match w(2) {
+                abi::FAULT_ACTION_RESUME => "resume",
+                abi::FAULT_ACTION_MAP_RESUME => "map+resume",
+                abi::FAULT_ACTION_KILL => "kill",
+                _ => "?",
+            };
+            ev.texts.push(("action".to_string(), action.to_string()));
+        }
+        abi::EV_GRANT_REVOKED => {
+            let reason = match w(3) {
+                abi::GRANT_REVOKE_REPLY => "reply",
+                abi::GRANT_REVOKE_CALL_ENDED => "call ended",
+                abi::GRANT_REVOKE_TASK_DEAD => "task dead",
+                abi::GRANT_REVOKE_REVOKED => "revoked",
+                _ => "?",
+            };
+            ev.texts.push(("reason".to_string(), reason.to_string()));
+        }
+        abi::EV_DERIVATION_REVOKED => {
+            let kind = match w(2) {
+                abi::REVOKE_KIND_CAP => "cap",
+                abi::REVOKE_KIND_PAGE => "page",
+                _ => "?",
+            };
+            ev.texts.push(("kind".to_string(), kind.to_string()));
+        }
+        _ => {}
+    }
+
+    Some(ev)
+}
+
+/// Wire Dump 区間の event レコードだけを取り出す
+pub fn parse_wire(input: &str) -> Result<Vec<EventRecord>, String> {
+    let mut out = Vec::new();
+    let mut in_dump = false;
+
+    for (lineno, raw) in input.lines().enumerate() {
+        let Some(body) = strip_prefix(raw) else {
+            continue;
+        };
+        if body == WIRE_BEGIN {
+            in_dump = true;
+            continue;
+        }
+        if body == WIRE_END {
+            in_dump = false;
+            continue;
+        }
+        if !in_dump {
+            continue;
+        }
+
+        let Some(hex) = body.strip_prefix("wire = ") else {
+            continue;
+        };
+        let bytes =
+            decode_hex(hex).ok_or_else(|| format!("line {}: bad hex", lineno + 1))?;
+        let rec = abi::WireRecord::from_bytes(&bytes)
+            .map_err(|e| format!("line {}: {:?}", lineno + 1, e))?;
+
+        if rec.kind() != abi::KIND_EVENT {
+            continue;
+        }
+        if let Some(ev) = event_from_wire(&rec) {
+            out.push(ev);
+        }
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// logging::wire_hex と同じ 1 行（"[INFO] wire = " + byte 順そのままの小文字 hex）
+    fn wire_hex_line(r: &abi::WireRecord) -> String {
+        let hex: String = r.bytes.iter().map(|b| format!("{b:02x}")).collect();
+        format!("[INFO] wire = {hex}")
+    }
+
+    /// dump_events が出す Wire Dump 区間
+    fn dump(records: &[abi::WireRecord]) -> String {
+        let mut s = format!("[INFO] {WIRE_BEGIN}\n[INFO] wire_version = {}\n", abi::WIRE_VERSION);
+        for r in records {
+            s.push_str(&wire_hex_line(r));
+            s.push('\n');
+        }
+        s.push_str(&format!("[INFO] {WIRE_END}\n"));
+        s
+    }
+
+    fn record(kind: u8, sub: u16, seq: u64, words: &[u64]) -> abi::WireRecord {
+        let mut r = abi::WireRecord::new(kind, sub);
+        for (i, &w) in words.iter().enumerate() {
+            r.put(i, w);
+        }
+        r.set_seq(seq);
+        r
+    }
+
+    fn ipc_delivered() -> abi::WireRecord {
+        // from = 1, to = 2, ep = 0, msg = 7, msg_len = 1
+        record(abi::KIND_EVENT, abi::EV_IPC_DELIVERED, 42, &[1, 2, 0, 7, 1])
+    }
+
+    #[test]
+    fn hex_line_matches_the_kernel_layout() {
+        let expected = concat!(
+            "[INFO] wire = ",
+            "02011300", // version 2 / kind event / sub 19（IpcDelivered、LE）
+            "00000000", // reserved
+            "2a00000000000000", // seq 42
+            "0100000000000000", // from
+            "0200000000000000", // to
+            "0000000000000000", // ep
+            "0700000000000000", // msg
+            "0100000000000000", // msg_len
+            "0000000000000000",
+            "0000000000000000",
+            "0000000000000000",
+        );
+        assert_eq!(wire_hex_line(&ipc_delivered()), expected);
+    }
+
+    #[test]
+    fn events_round_trip_through_wire_hex() {
+        let records = [
+            ipc_delivered(),
+            record(abi::KIND_EVENT, abi::EV_TASK_STATE_CHANGED, 43, &[3, abi::STATE_SUSPENDED]),
+            record(abi::KIND_EVENT, abi::EV_TASK_KILLED, 44, &[2, abi::KILL_USER_PAGE_FAULT]),
+        ];
+        let evs = parse_wire(&dump(&records)).unwrap();
+        assert_eq!(evs.len(), 3);
+
+        assert_eq!(evs[0].name, "IpcDelivered");
+        let nums: Vec<_> = ["from", "to", "ep", "msg", "msg_len", "seq"].map(|k| evs[0].num(k)).into();
+        assert_eq!(nums, [1, 2, 0, 7, 1, 42].map(Some));
+
+        assert_eq!(evs[1].name, "TaskStateChanged");
+        assert_eq!(evs[1].num("task"), Some(3));
+        assert_eq!(evs[1].to_state.as_deref(), Some("SUSPENDED"));
+
+        assert_eq!(evs[2].name, "TaskKilled");
+        assert_eq!(evs[2].text("reason"), Some("UserPageFault"));
+        assert_eq!(evs[2].num("seq"), Some(44));
+    }
+
+    #[test]
+    fn every_record_kind_decodes_at_the_current_version() {
+        let records = [
+            ipc_delivered(),
+            record(abi::KIND_COUNTERS, 0, 50, &[1, 2, 3, 4, 5, 6, 7, 8]),
+            record(abi::KIND_TASK_INFO, 1, 51, &[1, abi::STATE_BLOCKED]),
+            record(abi::KIND_ENDPOINT_INFO, 0, 52, &[0, abi::WIRE_NONE]),
+            record(abi::KIND_ENDPOINT_STATS, 0, 53, &[9, 9]),
+        ];
+        for r in &records {
+            let line = wire_hex_line(r);
+            let hex = line.strip_prefix("[INFO] wire = ").unwrap();
+            let back = abi::WireRecord::from_bytes(&decode_hex(hex).unwrap()).unwrap();
+            assert_eq!(back.bytes, r.bytes);
+            assert_eq!(back.version(), abi::WIRE_VERSION);
+        }
+
+        // snapshot の kind は読めたうえで図には使わない
+        let evs = parse_wire(&dump(&records)).unwrap();
+        assert_eq!(evs.len(), 1);
+        assert_eq!(evs[0].name, "IpcDelivered");
+    }
+
+    #[test]
+    fn unknown_version_is_rejected() {
+        for version in [1, abi::WIRE_VERSION + 1, 0xff] {
+            let mut r = ipc_delivered();
+            r.bytes[0] = version;
+            let err = parse_wire(&dump(&[r])).unwrap_err();
+            assert!(err.contains("BadVersion"), "version {version}: {err}");
+        }
+    }
+
+    #[test]
+    fn malformed_hex_is_rejected() {
+        assert_eq!(decode_hex("0a1"), None);
+        assert_eq!(decode_hex("zz"), None);
+        assert_eq!(decode_hex("é0"), None);
+        assert_eq!(decode_hex("0aff"), Some(vec![0x0a, 0xff]));
+
+        let input = format!("[INFO] {WIRE_BEGIN}\n[INFO] wire = 0a1\n[INFO] {WIRE_END}\n");
+        assert_eq!(parse_wire(&input).unwrap_err(), "line 2: bad hex");
+    }
+}